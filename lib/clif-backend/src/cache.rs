@@ -3,8 +3,11 @@ use crate::relocation::{ExternalRelocation, TrapSink};
 use hashbrown::HashMap;
 use std::sync::Arc;
 use wasmer_runtime_core::{
-    backend::{sys::Memory, CacheGen},
-    cache::{Artifact, Error},
+    backend::{
+        sys::{Memory, Protect},
+        CacheGen,
+    },
+    cache::{serialize_sorted, Artifact, Error, Target},
     module::{ModuleInfo, ModuleInner},
     structures::Map,
     types::{LocalFuncIndex, SigIndex},
@@ -35,7 +38,14 @@ impl CacheGen for CacheGenerator {
 
         // Clone the memory to a new location. This could take a long time,
         // depending on the throughput of your memcpy implementation.
-        let compiled_code = (*self.memory).clone();
+        let mut compiled_code = (*self.memory).clone();
+        unsafe {
+            compiled_code
+                .protect(.., Protect::ReadWrite)
+                .map_err(|e| Error::Unknown(e.to_string()))?;
+            self.backend_cache
+                .clear_relocations(compiled_code.as_slice_mut());
+        }
 
         Ok((
             info,
@@ -43,12 +53,23 @@ impl CacheGen for CacheGenerator {
             compiled_code,
         ))
     }
+
+    fn target(&self) -> Target {
+        // Cranelift is given the isa of the host's architecture without
+        // detecting its cpu features, so the code runs on any machine of it.
+        Target::baseline()
+    }
+
+    fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TrampolineCache {
     #[serde(with = "serde_bytes")]
     pub code: Vec<u8>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub offsets: HashMap<SigIndex, usize>,
 }
 
@@ -70,6 +91,23 @@ impl BackendCache {
         Ok((info, compiled_code, backend_cache))
     }
 
+    /// Zero the places in `code` that the external relocations patch.
+    ///
+    /// Calls between the functions of a module are relative, so the code
+    /// can be loaded at any address once the relocations are applied
+    /// again, and the addresses of the process that compiled it, which
+    /// mean nothing in another, aren't saved with it.
+    pub fn clear_relocations(&self, code: &mut [u8]) {
+        for (index, relocs) in self.external_relocs.iter() {
+            for reloc in relocs.iter() {
+                let start = self.offsets[index] + reloc.offset as usize;
+                for byte in &mut code[start..start + reloc.reloc.size()] {
+                    *byte = 0;
+                }
+            }
+        }
+    }
+
     pub fn into_backend_data(&self) -> Result<Vec<u8>, Error> {
         let mut buffer = Vec::new();
 
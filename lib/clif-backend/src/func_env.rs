@@ -1,6 +1,10 @@
-use crate::{module::Converter, module_env::ModuleEnv, relocation::call_names};
+use crate::{
+    module::Converter,
+    module_env::ModuleEnv,
+    relocation::{call_names, user_traps},
+};
 use cranelift_codegen::{
-    cursor::FuncCursor,
+    cursor::{Cursor, FuncCursor},
     ir::{self, InstBuilder},
     isa,
 };
@@ -9,18 +13,126 @@ use cranelift_wasm::{self, FuncEnvironment, ModuleEnvironment};
 use std::mem;
 use wasmer_runtime_core::{
     memory::MemoryType,
+    metering::FunctionCosts,
     structures::TypedIndex,
     types::{FuncIndex, GlobalIndex, LocalOrImport, MemoryIndex, TableIndex},
     vm,
 };
 
-pub struct FuncEnv<'env, 'module, 'isa> {
-    env: &'env ModuleEnv<'module, 'isa>,
+pub struct FuncEnv<'env, 'data, 'module, 'isa> {
+    env: &'env ModuleEnv<'data, 'module, 'isa>,
+    /// The points to charge for the regions of the function, if the
+    /// module is metered.
+    costs: Option<&'env FunctionCosts>,
 }
 
-impl<'env, 'module, 'isa> FuncEnv<'env, 'module, 'isa> {
-    pub fn new(env: &'env ModuleEnv<'module, 'isa>) -> Self {
-        Self { env }
+impl<'env, 'data, 'module, 'isa> FuncEnv<'env, 'data, 'module, 'isa> {
+    pub fn new(
+        env: &'env ModuleEnv<'data, 'module, 'isa>,
+        costs: Option<&'env FunctionCosts>,
+    ) -> Self {
+        Self { env, costs }
+    }
+
+    /// Charges the points for the function's entry region, and checks
+    /// whether the instance has been interrupted or its epoch deadline
+    /// has been reached, at the start of the translated function.
+    pub fn translate_entry(&self, func: &mut ir::Function) {
+        let entry_ebb = func.layout.entry_block().unwrap();
+        let mut pos = FuncCursor::new(func).at_first_insertion_point(entry_ebb);
+        if let Some(costs) = self.costs {
+            self.charge_points(&mut pos, costs.entry);
+        }
+        self.check_interrupted(&mut pos);
+        self.check_deadline(&mut pos);
+    }
+
+    /// Generates code that traps if the instance has been interrupted.
+    /// The runtime reports the trap as an interrupt.
+    fn check_interrupted(&self, pos: &mut FuncCursor) {
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let interrupted_ptr = pos.ins().load(
+            self.pointer_type(),
+            ir::MemFlags::trusted(),
+            vmctx,
+            vm::Ctx::offset_interrupted() as i32,
+        );
+        let interrupted = pos
+            .ins()
+            .load(ir::types::I8, ir::MemFlags::new(), interrupted_ptr, 0);
+        pos.ins()
+            .trapnz(interrupted, ir::TrapCode::User(user_traps::INTERRUPTED));
+    }
+
+    /// Generates code that traps if the epoch deadline of the instance
+    /// has been reached. The runtime reports the trap as an exceeded
+    /// deadline.
+    fn check_deadline(&self, pos: &mut FuncCursor) {
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let epoch_ptr = pos.ins().load(
+            self.pointer_type(),
+            ir::MemFlags::trusted(),
+            vmctx,
+            vm::Ctx::offset_epoch() as i32,
+        );
+        let epoch = pos
+            .ins()
+            .load(ir::types::I64, ir::MemFlags::new(), epoch_ptr, 0);
+        let epoch_deadline = pos.ins().load(
+            ir::types::I64,
+            ir::MemFlags::trusted(),
+            vmctx,
+            vm::Ctx::offset_epoch_deadline() as i32,
+        );
+        let deadline_reached = pos.ins().icmp(
+            ir::condcodes::IntCC::UnsignedGreaterThanOrEqual,
+            epoch,
+            epoch_deadline,
+        );
+        pos.ins().trapnz(
+            deadline_reached,
+            ir::TrapCode::User(user_traps::DEADLINE_EXCEEDED),
+        );
+    }
+
+    /// Generates code that takes `cost` points from the instance, and
+    /// traps if that leaves it with less than none. The runtime reports
+    /// the trap as running out of gas.
+    fn charge_points(&self, pos: &mut FuncCursor, cost: u64) {
+        if cost == 0 {
+            return;
+        }
+
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let points = pos.ins().load(
+            ir::types::I64,
+            ir::MemFlags::trusted(),
+            vmctx,
+            vm::Ctx::offset_remaining_points() as i32,
+        );
+        let points = pos.ins().iadd_imm(points, -(cost as i64));
+        pos.ins().store(
+            ir::MemFlags::trusted(),
+            points,
+            vmctx,
+            vm::Ctx::offset_remaining_points() as i32,
+        );
+        let out_of_gas = pos
+            .ins()
+            .icmp_imm(ir::condcodes::IntCC::SignedLessThan, points, 0);
+        pos.ins().trapnz(out_of_gas, ir::TrapCode::Interrupt);
     }
 
     /// Creates a signature with VMContext as the last param
@@ -42,7 +154,7 @@ impl<'env, 'module, 'isa> FuncEnv<'env, 'module, 'isa> {
     }
 }
 
-impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
+impl<'env, 'data, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'data, 'module, 'isa> {
     /// Gets configuration information needed for compiling functions
     fn target_config(&self) -> isa::TargetFrontendConfig {
         self.env.target_config()
@@ -697,4 +809,19 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
 
         Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
+
+    /// Generates checks of whether the instance has been interrupted,
+    /// or its epoch deadline has been reached, at the start of every
+    /// loop iteration, and charges for the loop if the module is
+    /// metered. The runtime reports the traps as such.
+    fn translate_loop_header(&mut self, mut pos: FuncCursor) {
+        if let Some(costs) = self.costs {
+            // The source location is the offset of the `loop` operator.
+            let offset = pos.srcloc().bits() as usize;
+            self.charge_points(&mut pos, costs.loop_cost_at(offset));
+        }
+
+        self.check_interrupted(&mut pos);
+        self.check_deadline(&mut pos);
+    }
 }
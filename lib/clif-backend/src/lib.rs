@@ -3,6 +3,7 @@ mod func_env;
 mod libcalls;
 mod module;
 mod module_env;
+mod nan_canonicalization;
 mod relocation;
 mod resolver;
 mod signal;
@@ -16,9 +17,10 @@ use target_lexicon::Triple;
 
 use wasmer_runtime_core::cache::{Artifact, Error as CacheError};
 use wasmer_runtime_core::{
-    backend::{Compiler, Token},
+    backend::{Capabilities, Compiler, CompilerConfig, Token},
     error::{CompileError, CompileResult},
-    module::ModuleInner,
+    module::{read_func_names, ModuleInner},
+    validate_with_features, Features,
 };
 
 #[macro_use]
@@ -27,7 +29,8 @@ extern crate serde_derive;
 extern crate rayon;
 extern crate serde;
 
-use wasmparser::{self, WasmDecoder};
+use hashbrown::HashMap;
+use wasmparser::{self, BinaryReaderError, ModuleReader, SectionCode, WasmDecoder};
 
 pub struct CraneliftCompiler {}
 
@@ -39,7 +42,12 @@ impl CraneliftCompiler {
 
 impl Compiler for CraneliftCompiler {
     /// Compiles wasm binary to a wasmer module.
-    fn compile(&self, wasm: &[u8], _: Token) -> CompileResult<ModuleInner> {
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> CompileResult<ModuleInner> {
         validate(wasm)?;
 
         let isa = get_isa();
@@ -47,7 +55,11 @@ impl Compiler for CraneliftCompiler {
         let mut module = module::Module::new();
         let module_env = module_env::ModuleEnv::new(&mut module, &*isa);
 
-        let func_bodies = module_env.translate(wasm)?;
+        let func_bodies = module_env.translate(wasm, compiler_config.metering)?;
+        module.info.custom_sections = read_custom_sections(wasm)?;
+        if let Some(name_section) = module.info.custom_sections.get("name") {
+            module.info.func_names = read_func_names(&name_section[0]);
+        }
 
         module.compile(&*isa, func_bodies)
     }
@@ -58,6 +70,22 @@ impl Compiler for CraneliftCompiler {
         module::Module::from_cache(cache)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            target_archs: &["x86_64"],
+            threads: false,
+            reference_types: false,
+            bulk_memory: false,
+            multi_value: false,
+            // Faults are only caught with the trap handler of
+            // runtime-core, which collects the frames, on unix.
+            backtraces: cfg!(unix),
+            jit_debug: false,
+            caching: true,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
     //
     // fn compile_to_backend_cache_data(
     //     &self,
@@ -112,13 +140,73 @@ fn validate(bytes: &[u8]) -> CompileResult<()> {
         let state = parser.read();
         match *state {
             wasmparser::ParserState::EndWasm => break Ok(()),
-            wasmparser::ParserState::Error(err) => Err(CompileError::ValidationError {
-                msg: err.message.to_string(),
-            })?,
+            wasmparser::ParserState::Error(err) => {
+                let msg = match unsupported_proposal(bytes) {
+                    Some(proposal) => format!(
+                        "the cranelift backend doesn't support the {} proposal",
+                        proposal
+                    ),
+                    None => err.message.to_string(),
+                };
+                Err(CompileError::ValidationError { msg })?
+            }
             _ => {}
         }
     }
 }
 
+/// The proposal that a module which failed to validate uses, if it's
+/// one that cranelift-wasm can't translate and would be valid with it.
+fn unsupported_proposal(bytes: &[u8]) -> Option<&'static str> {
+    let proposals = [
+        (
+            "threads",
+            Features {
+                threads: true,
+                ..Features::default()
+            },
+        ),
+        (
+            "bulk memory",
+            Features {
+                bulk_memory: true,
+                ..Features::default()
+            },
+        ),
+    ];
+    proposals
+        .iter()
+        .find(|(_, features)| validate_with_features(bytes, *features).is_ok())
+        .map(|&(proposal, _)| proposal)
+}
+
+/// Collect the custom sections, which cranelift-wasm skips over.
+fn read_custom_sections(bytes: &[u8]) -> CompileResult<HashMap<String, Vec<Vec<u8>>>> {
+    let mut custom_sections = HashMap::new();
+    let mut reader = ModuleReader::new(bytes).map_err(read_error)?;
+
+    while !reader.eof() {
+        let section = reader.read().map_err(read_error)?;
+        if let SectionCode::Custom { name, .. } = section.code {
+            let mut reader = section.get_binary_reader();
+            let len = reader.bytes_remaining();
+            let contents = reader.read_bytes(len).map_err(read_error)?;
+
+            custom_sections
+                .entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(contents.to_vec());
+        }
+    }
+
+    Ok(custom_sections)
+}
+
+fn read_error(err: BinaryReaderError) -> CompileError {
+    CompileError::ValidationError {
+        msg: err.message.to_string(),
+    }
+}
+
 /// The current version of this crate
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
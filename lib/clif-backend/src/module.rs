@@ -12,7 +12,9 @@ use wasmer_runtime_core::cache::{Artifact, Error as CacheError};
 use wasmer_runtime_core::{
     backend::Backend,
     error::CompileResult,
+    memory::memory_style,
     module::{ModuleInfo, ModuleInner, StringTable},
+    perf,
     structures::{Map, TypedIndex},
     types::{
         FuncIndex, FuncSig, GlobalIndex, LocalFuncIndex, MemoryIndex, SigIndex, TableIndex, Type,
@@ -42,14 +44,22 @@ impl Module {
                 data_initializers: Vec::new(),
                 elem_initializers: Vec::new(),
 
+                passive_data: HashMap::new(),
+                passive_elements: HashMap::new(),
+
                 start_func: None,
 
                 func_assoc: Map::new(),
                 signatures: Map::new(),
                 backend: Backend::Cranelift,
+                memory_style: memory_style(),
+                initial_points: None,
 
                 namespace_table: StringTable::new(),
                 name_table: StringTable::new(),
+
+                custom_sections: HashMap::new(),
+                func_names: HashMap::new(),
             },
         }
     }
@@ -69,6 +79,9 @@ impl Module {
             Arc::clone(&trampolines),
             handler_data.clone(),
         )?;
+        if perf::is_enabled() {
+            unsafe { perf::register(&self.info, &func_resolver.functions()) };
+        }
 
         let protected_caller = Caller::new(&self.info, handler_data, trampolines);
 
@@ -99,6 +112,9 @@ impl Module {
                 handler_data.clone(),
             )
             .map_err(|e| CacheError::Unknown(format!("{:?}", e)))?;
+        if perf::is_enabled() {
+            unsafe { perf::register(&info, &func_resolver.functions()) };
+        }
 
         let protected_caller = Caller::new(&info, handler_data, trampolines);
 
@@ -1,11 +1,15 @@
 use crate::{
     func_env::FuncEnv,
     module::{Converter, Module},
+    nan_canonicalization::canonicalize_nans,
 };
 use cranelift_codegen::{ir, isa};
 use cranelift_wasm::{self, translate_module, FuncTranslator, ModuleEnvironment};
+use rayon::prelude::*;
 use wasmer_runtime_core::{
+    deterministic,
     error::{CompileError, CompileResult},
+    metering::{self, FunctionCosts, MeteringConfig},
     module::{
         DataInitializer, ExportIndex, ImportName, NameIndex, NamespaceIndex, StringTableBuilder,
         TableInitializer,
@@ -18,41 +22,272 @@ use wasmer_runtime_core::{
     units::Pages,
 };
 
-pub struct ModuleEnv<'module, 'isa> {
+pub struct ModuleEnv<'data, 'module, 'isa> {
     pub module: &'module mut Module,
     isa: &'isa isa::TargetIsa,
     pub signatures: Map<SigIndex, ir::Signature>,
     globals: Map<GlobalIndex, cranelift_wasm::Global>,
-    func_bodies: Map<LocalFuncIndex, ir::Function>,
+    func_body_bytes: Vec<&'data [u8]>,
     namespace_table_builder: StringTableBuilder<NamespaceIndex>,
     name_table_builder: StringTableBuilder<NameIndex>,
+    /// The points to charge for the regions of each function, if the
+    /// module is metered.
+    costs: Option<Map<LocalFuncIndex, FunctionCosts>>,
 }
 
-impl<'module, 'isa> ModuleEnv<'module, 'isa> {
+impl<'data, 'module, 'isa> ModuleEnv<'data, 'module, 'isa> {
     pub fn new(module: &'module mut Module, isa: &'isa isa::TargetIsa) -> Self {
         Self {
             module,
             isa,
             signatures: Map::new(),
             globals: Map::new(),
-            func_bodies: Map::new(),
+            func_body_bytes: Vec::new(),
             namespace_table_builder: StringTableBuilder::new(),
             name_table_builder: StringTableBuilder::new(),
+            costs: None,
         }
     }
 
-    pub fn translate(mut self, wasm: &[u8]) -> CompileResult<Map<LocalFuncIndex, ir::Function>> {
+    pub fn translate(
+        mut self,
+        wasm: &'data [u8],
+        metering_config: Option<MeteringConfig>,
+    ) -> CompileResult<Map<LocalFuncIndex, ir::Function>> {
+        self.costs = metering_config
+            .map(|config| metering::function_costs(wasm, config.cost_function))
+            .transpose()?;
+
         translate_module(wasm, &mut self)
             .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+        if self
+            .module
+            .info
+            .signatures
+            .iter()
+            .any(|(_, sig)| sig.returns().len() > 1)
+        {
+            return Err(CompileError::InternalError {
+                msg: "the clif backend doesn't support functions that return more than one value"
+                    .to_string(),
+            });
+        }
+        let func_bodies = self
+            .translate_function_bodies()
+            .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
 
         self.module.info.namespace_table = self.namespace_table_builder.finish();
         self.module.info.name_table = self.name_table_builder.finish();
 
-        Ok(self.func_bodies)
+        Ok(func_bodies)
+    }
+
+    /// Translate every function body, spread across threads. The
+    /// functions come out in the order of the module, however the
+    /// work was split.
+    fn translate_function_bodies(
+        &self,
+    ) -> cranelift_wasm::WasmResult<Map<LocalFuncIndex, ir::Function>> {
+        let func_bodies: Vec<ir::Function> = self
+            .func_body_bytes
+            .par_iter()
+            .enumerate()
+            .map_init(
+                FuncTranslator::new,
+                |func_translator, (index, body_bytes)| {
+                    self.translate_function(func_translator, LocalFuncIndex::new(index), body_bytes)
+                },
+            )
+            .collect::<Result<_, _>>()?;
+
+        Ok(func_bodies.into_iter().collect())
+    }
+
+    /// Translate the body of the function `func_index` to Cranelift IR.
+    fn translate_function(
+        &self,
+        func_translator: &mut FuncTranslator,
+        func_index: LocalFuncIndex,
+        body_bytes: &[u8],
+    ) -> cranelift_wasm::WasmResult<ir::Function> {
+        let costs = self.costs.as_ref().map(|costs| &costs[func_index]);
+        let mut func_env = FuncEnv::new(self, costs);
+        let name = ir::ExternalName::user(0, func_index.index() as u32);
+
+        let sig = func_env.generate_signature(
+            self.get_func_type(Converter(func_index.convert_up(&self.module.info)).into()),
+        );
+
+        let mut func = ir::Function::with_name_signature(name, sig);
+
+        func_translator.translate(body_bytes, &mut func, &mut func_env)?;
+        func_env.translate_entry(&mut func);
+        if deterministic::is_deterministic() {
+            canonicalize_nans(&mut func);
+        }
+
+        #[cfg(feature = "debug")]
+        {
+            use cranelift_codegen::cursor::{Cursor, FuncCursor};
+            use cranelift_codegen::ir::InstBuilder;
+            let entry_ebb = func.layout.entry_block().unwrap();
+            let ebb = func.dfg.make_ebb();
+            func.layout.insert_ebb(ebb, entry_ebb);
+            let mut pos = FuncCursor::new(&mut func).at_first_insertion_point(ebb);
+            let params = pos.func.dfg.ebb_params(entry_ebb).to_vec();
+
+            let new_ebb_params: Vec<_> = params
+                .iter()
+                .map(|&param| {
+                    pos.func
+                        .dfg
+                        .append_ebb_param(ebb, pos.func.dfg.value_type(param))
+                })
+                .collect();
+
+            let start_debug = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![
+                        ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
+                        ir::AbiParam::new(ir::types::I32),
+                    ],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("strtdbug");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let end_debug = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![ir::AbiParam::special(
+                        ir::types::I64,
+                        ir::ArgumentPurpose::VMContext,
+                    )],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("enddbug");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let i32_print = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![
+                        ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
+                        ir::AbiParam::new(ir::types::I32),
+                    ],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("i32print");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let i64_print = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![
+                        ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
+                        ir::AbiParam::new(ir::types::I64),
+                    ],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("i64print");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let f32_print = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![
+                        ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
+                        ir::AbiParam::new(ir::types::F32),
+                    ],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("f32print");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let f64_print = {
+                let signature = pos.func.import_signature(ir::Signature {
+                    call_conv: self.target_config().default_call_conv,
+                    params: vec![
+                        ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
+                        ir::AbiParam::new(ir::types::F64),
+                    ],
+                    returns: vec![],
+                });
+
+                let name = ir::ExternalName::testcase("f64print");
+
+                pos.func.import_function(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: false,
+                })
+            };
+
+            let vmctx = pos
+                .func
+                .special_param(ir::ArgumentPurpose::VMContext)
+                .expect("missing vmctx parameter");
+
+            let func_index = pos.ins().iconst(ir::types::I32, func_index.index() as i64);
+
+            pos.ins().call(start_debug, &[vmctx, func_index]);
+
+            for param in new_ebb_params.iter().cloned() {
+                match pos.func.dfg.value_type(param) {
+                    ir::types::I32 => pos.ins().call(i32_print, &[vmctx, param]),
+                    ir::types::I64 => pos.ins().call(i64_print, &[vmctx, param]),
+                    ir::types::F32 => pos.ins().call(f32_print, &[vmctx, param]),
+                    ir::types::F64 => pos.ins().call(f64_print, &[vmctx, param]),
+                    _ => unimplemented!(),
+                };
+            }
+
+            pos.ins().call(end_debug, &[vmctx]);
+
+            pos.ins().jump(entry_ebb, new_ebb_params.as_slice());
+        }
+
+        Ok(func)
     }
 }
 
-impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa> {
+impl<'data, 'module, 'isa> ModuleEnvironment<'data> for ModuleEnv<'data, 'module, 'isa> {
     /// Get the information needed to produce Cranelift IR for the current target.
     fn target_config(&self) -> isa::TargetFrontendConfig {
         self.isa.frontend_config()
@@ -377,183 +612,9 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
 
     /// Provides the contents of a function body.
     fn define_function_body(&mut self, body_bytes: &'data [u8]) -> cranelift_wasm::WasmResult<()> {
-        let mut func_translator = FuncTranslator::new();
-
-        let func_body = {
-            let mut func_env = FuncEnv::new(self);
-            let func_index = self.func_bodies.next_index();
-            let name = ir::ExternalName::user(0, func_index.index() as u32);
-
-            let sig = func_env.generate_signature(
-                self.get_func_type(Converter(func_index.convert_up(&self.module.info)).into()),
-            );
-
-            let mut func = ir::Function::with_name_signature(name, sig);
-
-            func_translator.translate(body_bytes, &mut func, &mut func_env)?;
-
-            #[cfg(feature = "debug")]
-            {
-                use cranelift_codegen::cursor::{Cursor, FuncCursor};
-                use cranelift_codegen::ir::InstBuilder;
-                let entry_ebb = func.layout.entry_block().unwrap();
-                let ebb = func.dfg.make_ebb();
-                func.layout.insert_ebb(ebb, entry_ebb);
-                let mut pos = FuncCursor::new(&mut func).at_first_insertion_point(ebb);
-                let params = pos.func.dfg.ebb_params(entry_ebb).to_vec();
-
-                let new_ebb_params: Vec<_> = params
-                    .iter()
-                    .map(|&param| {
-                        pos.func
-                            .dfg
-                            .append_ebb_param(ebb, pos.func.dfg.value_type(param))
-                    })
-                    .collect();
-
-                let start_debug = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![
-                            ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
-                            ir::AbiParam::new(ir::types::I32),
-                        ],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("strtdbug");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let end_debug = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![ir::AbiParam::special(
-                            ir::types::I64,
-                            ir::ArgumentPurpose::VMContext,
-                        )],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("enddbug");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let i32_print = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![
-                            ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
-                            ir::AbiParam::new(ir::types::I32),
-                        ],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("i32print");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let i64_print = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![
-                            ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
-                            ir::AbiParam::new(ir::types::I64),
-                        ],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("i64print");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let f32_print = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![
-                            ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
-                            ir::AbiParam::new(ir::types::F32),
-                        ],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("f32print");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let f64_print = {
-                    let signature = pos.func.import_signature(ir::Signature {
-                        call_conv: self.target_config().default_call_conv,
-                        params: vec![
-                            ir::AbiParam::special(ir::types::I64, ir::ArgumentPurpose::VMContext),
-                            ir::AbiParam::new(ir::types::F64),
-                        ],
-                        returns: vec![],
-                    });
-
-                    let name = ir::ExternalName::testcase("f64print");
-
-                    pos.func.import_function(ir::ExtFuncData {
-                        name,
-                        signature,
-                        colocated: false,
-                    })
-                };
-
-                let vmctx = pos
-                    .func
-                    .special_param(ir::ArgumentPurpose::VMContext)
-                    .expect("missing vmctx parameter");
-
-                let func_index = pos.ins().iconst(ir::types::I32, func_index.index() as i64);
-
-                pos.ins().call(start_debug, &[vmctx, func_index]);
-
-                for param in new_ebb_params.iter().cloned() {
-                    match pos.func.dfg.value_type(param) {
-                        ir::types::I32 => pos.ins().call(i32_print, &[vmctx, param]),
-                        ir::types::I64 => pos.ins().call(i64_print, &[vmctx, param]),
-                        ir::types::F32 => pos.ins().call(f32_print, &[vmctx, param]),
-                        ir::types::F64 => pos.ins().call(f64_print, &[vmctx, param]),
-                        _ => unimplemented!(),
-                    };
-                }
-
-                pos.ins().call(end_debug, &[vmctx]);
-
-                pos.ins().jump(entry_ebb, new_ebb_params.as_slice());
-            }
-
-            func
-        };
-
-        // Add function body to list of function bodies.
-        self.func_bodies.push(func_body);
+        // The bodies are translated together, in parallel, once the
+        // whole module has been read.
+        self.func_body_bytes.push(body_bytes);
 
         Ok(())
     }
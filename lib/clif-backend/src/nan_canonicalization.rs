@@ -0,0 +1,68 @@
+//! Canonicalization of the NaNs that float operations produce, for
+//! deterministic mode.
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::{
+    self,
+    condcodes::FloatCC,
+    immediates::{Ieee32, Ieee64},
+    types, InstBuilder, Opcode,
+};
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// Replace the result of every float operation in `func` that can
+/// produce a NaN with the canonical NaN, if it is one.
+pub fn canonicalize_nans(func: &mut ir::Function) {
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            if produces_nan(pos.func.dfg[inst].opcode()) {
+                add_canonicalization(&mut pos, inst);
+            }
+        }
+    }
+}
+
+fn produces_nan(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Fadd
+        | Opcode::Fsub
+        | Opcode::Fmul
+        | Opcode::Fdiv
+        | Opcode::Sqrt
+        | Opcode::Fmin
+        | Opcode::Fmax
+        | Opcode::Ceil
+        | Opcode::Floor
+        | Opcode::Trunc
+        | Opcode::Nearest
+        | Opcode::Fpromote
+        | Opcode::Fdemote => true,
+        _ => false,
+    }
+}
+
+fn add_canonicalization(pos: &mut FuncCursor, inst: ir::Inst) {
+    // The instruction gets a new result, and the old one, which its
+    // uses refer to, is redefined as the canonicalized value.
+    let result = pos.func.dfg.first_result(inst);
+    let ty = pos.func.dfg.value_type(result);
+    let new_result = pos.func.dfg.replace_result(result, ty);
+
+    // Insert after `inst`, which can't be the last instruction of its
+    // EBB, since that's a terminator.
+    pos.next_inst();
+    let is_nan = pos.ins().fcmp(FloatCC::Unordered, new_result, new_result);
+    let canonical_nan = if ty == types::F32 {
+        pos.ins().f32const(Ieee32::with_bits(CANONICAL_NAN_F32))
+    } else {
+        pos.ins().f64const(Ieee64::with_bits(CANONICAL_NAN_F64))
+    };
+    pos.ins()
+        .with_result(result)
+        .select(is_nan, canonical_nan, new_result);
+
+    // Step back so that the instruction after `inst` is visited next.
+    pos.prev_inst();
+}
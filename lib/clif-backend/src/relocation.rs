@@ -6,6 +6,7 @@ use cranelift_codegen::binemit;
 use cranelift_codegen::ir::{self, ExternalName, SourceLoc};
 use wasmer_runtime_core::{
     structures::TypedIndex,
+    trap::TrapSite,
     types::{FuncIndex, SigIndex},
 };
 
@@ -22,6 +23,16 @@ pub mod call_names {
     pub const DYNAMIC_MEM_SIZE: u32 = 5;
 }
 
+/// The codes of the `User` traps in compiled code.
+pub mod user_traps {
+    /// cranelift-wasm translates `unreachable` to `trap user0`.
+    pub const UNREACHABLE: u16 = 0;
+    /// The instance has been interrupted.
+    pub const INTERRUPTED: u16 = 1;
+    /// The epoch deadline of the instance has been reached.
+    pub const DEADLINE_EXCEEDED: u16 = 2;
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Reloc {
     Abs8,
@@ -29,6 +40,16 @@ pub enum Reloc {
     X86CallPCRel4,
 }
 
+impl Reloc {
+    /// The number of bytes that the relocation patches.
+    pub fn size(self) -> usize {
+        match self {
+            Reloc::Abs8 => 8,
+            Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => 4,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub enum LibCall {
     Probestack,
@@ -227,6 +248,26 @@ pub enum TrapCode {
     User(u16),
 }
 
+impl From<TrapCode> for wasmer_runtime_core::error::TrapCode {
+    fn from(trapcode: TrapCode) -> Self {
+        use wasmer_runtime_core::error::TrapCode as RuntimeTrapCode;
+        match trapcode {
+            TrapCode::StackOverflow => RuntimeTrapCode::StackOverflow,
+            TrapCode::HeapOutOfBounds | TrapCode::OutOfBounds => RuntimeTrapCode::MemoryOutOfBounds,
+            TrapCode::TableOutOfBounds => RuntimeTrapCode::TableOutOfBounds,
+            TrapCode::IndirectCallToNull => RuntimeTrapCode::IndirectCallToNull,
+            TrapCode::BadSignature => RuntimeTrapCode::BadSignature,
+            TrapCode::IntegerOverflow => RuntimeTrapCode::IntegerOverflow,
+            TrapCode::IntegerDivisionByZero => RuntimeTrapCode::IntegerDivisionByZero,
+            TrapCode::BadConversionToInteger => RuntimeTrapCode::BadConversionToInteger,
+            TrapCode::User(user_traps::UNREACHABLE) => RuntimeTrapCode::Unreachable,
+            TrapCode::User(user_traps::INTERRUPTED) => RuntimeTrapCode::Interrupted,
+            TrapCode::User(user_traps::DEADLINE_EXCEEDED) => RuntimeTrapCode::DeadlineExceeded,
+            TrapCode::Interrupt | TrapCode::User(_) => RuntimeTrapCode::Unknown,
+        }
+    }
+}
+
 /// Implementation of a relocation sink that just saves all the information for later
 impl RelocSink {
     pub fn new() -> Self {
@@ -257,11 +298,16 @@ impl TrapSink {
         }
     }
 
-    pub fn lookup(&self, offset: usize) -> Option<TrapData> {
+    /// The traps, as the trap handler looks them up.
+    pub fn sites(&self) -> Vec<TrapSite> {
         self.trap_datas
             .iter()
-            .find(|(trap_offset, _)| *trap_offset == offset)
-            .map(|(_, trap_data)| *trap_data)
+            .map(|&(offset, trap_data)| TrapSite {
+                offset,
+                code: trap_data.trapcode.into(),
+                wasm_offset: Some(trap_data.srcloc).filter(|&srcloc| srcloc != !0),
+            })
+            .collect()
     }
 
     pub fn drain_local(&mut self, current_func_offset: usize, local: &mut LocalTrapSink) {
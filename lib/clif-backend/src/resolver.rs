@@ -15,6 +15,7 @@ use std::{
     mem,
     ptr::{write_unaligned, NonNull},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use wasmer_runtime_core::cache::Error as CacheError;
@@ -23,7 +24,7 @@ use wasmer_runtime_core::{
     backend::{
         self,
         sys::{Memory, Protect},
-        SigRegistry,
+        FuncCode, SigRegistry,
     },
     error::{CompileError, CompileResult},
     module::ModuleInfo,
@@ -54,6 +55,12 @@ fn lookup_func(
 pub struct FuncResolverBuilder {
     map: Map<LocalFuncIndex, usize>,
     memory: Memory,
+    /// The size of the code of each function, which counts the padding
+    /// after it if the module was loaded from a cache.
+    code_sizes: Map<LocalFuncIndex, usize>,
+    /// How long each function took to compile, unless the module was
+    /// loaded from a cache.
+    compile_times: Option<Map<LocalFuncIndex, Duration>>,
     local_relocs: Map<LocalFuncIndex, Box<[LocalRelocation]>>,
     external_relocs: Map<LocalFuncIndex, Box<[ExternalRelocation]>>,
     import_len: usize,
@@ -70,13 +77,34 @@ impl FuncResolverBuilder {
                 .map_err(|e| CacheError::Unknown(e.to_string()))?;
         }
 
-        let handler_data =
-            HandlerData::new(backend_cache.trap_sink, code.as_ptr() as _, code.size());
+        let handler_data = HandlerData::new(
+            backend_cache.trap_sink,
+            code.as_ptr() as _,
+            code.size(),
+            &backend_cache.offsets,
+            info,
+        );
+
+        // The functions are laid out in order.
+        let ends = backend_cache
+            .offsets
+            .iter()
+            .skip(1)
+            .map(|(_, &offset)| offset)
+            .chain(Some(code.size()));
+        let code_sizes = backend_cache
+            .offsets
+            .iter()
+            .zip(ends)
+            .map(|((_, &offset), end)| end - offset)
+            .collect();
 
         Ok((
             Self {
                 map: backend_cache.offsets,
                 memory: code,
+                code_sizes,
+                compile_times: None,
                 local_relocs: Map::new(),
                 external_relocs: backend_cache.external_relocs,
                 import_len: info.imported_functions.len(),
@@ -99,13 +127,14 @@ impl FuncResolverBuilder {
 
         let mut trap_sink = TrapSink::new();
 
-        let compiled_functions: Result<Vec<(Vec<u8>, (RelocSink, LocalTrapSink))>, CompileError> =
+        let compiled_functions: Result<Vec<(Vec<u8>, CompiledSinks)>, CompileError> =
             function_bodies
                 .into_vec()
                 .par_iter()
                 .map_init(
                     || Context::new(),
                     |ctx, func| {
+                        let start = Instant::now();
                         let mut code_buf = Vec::new();
                         ctx.func = func.to_owned();
                         let mut reloc_sink = RelocSink::new();
@@ -119,7 +148,7 @@ impl FuncResolverBuilder {
                         )
                         .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
                         ctx.clear();
-                        Ok((code_buf, (reloc_sink, local_trap_sink)))
+                        Ok((code_buf, (reloc_sink, local_trap_sink, start.elapsed())))
                     },
                 )
                 .collect();
@@ -127,9 +156,12 @@ impl FuncResolverBuilder {
         let compiled_functions = compiled_functions?;
         let mut total_size = 0;
         // We separate into two iterators, one iterable and one into iterable
-        let (code_bufs, sinks): (Vec<Vec<u8>>, Vec<(RelocSink, LocalTrapSink)>) =
+        let (code_bufs, sinks): (Vec<Vec<u8>>, Vec<CompiledSinks>) =
             compiled_functions.into_iter().unzip();
-        for (code_buf, (reloc_sink, mut local_trap_sink)) in code_bufs.iter().zip(sinks.into_iter())
+        let mut code_sizes = Map::with_capacity(num_func_bodies);
+        let mut compile_times = Map::with_capacity(num_func_bodies);
+        for (code_buf, (reloc_sink, mut local_trap_sink, compile_time)) in
+            code_bufs.iter().zip(sinks.into_iter())
         {
             // Clear the local trap sink and consolidate all trap info
             // into a single location.
@@ -138,11 +170,13 @@ impl FuncResolverBuilder {
             // Round up each function's size to pointer alignment.
             total_size += round_up(code_buf.len(), mem::size_of::<usize>());
 
+            code_sizes.push(code_buf.len());
+            compile_times.push(compile_time);
             local_relocs.push(reloc_sink.local_relocs.into_boxed_slice());
             external_relocs.push(reloc_sink.external_relocs.into_boxed_slice());
         }
 
-        let mut memory = Memory::with_size(total_size)
+        let mut memory = Memory::with_size_for_code(total_size)
             .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
         unsafe {
             memory
@@ -177,12 +211,19 @@ impl FuncResolverBuilder {
             previous_end = new_end;
         }
 
-        let handler_data =
-            HandlerData::new(Arc::new(trap_sink), memory.as_ptr() as _, memory.size());
+        let handler_data = HandlerData::new(
+            Arc::new(trap_sink),
+            memory.as_ptr() as _,
+            memory.size(),
+            &map,
+            info,
+        );
 
         let mut func_resolver_builder = Self {
             map,
             memory,
+            code_sizes,
+            compile_times: Some(compile_times),
             local_relocs,
             external_relocs,
             import_len: info.imported_functions.len(),
@@ -258,8 +299,12 @@ impl FuncResolverBuilder {
                             VmCallKind::StaticMemoryGrow => vmcalls::local_static_memory_grow as _,
                             VmCallKind::StaticMemorySize => vmcalls::local_static_memory_size as _,
 
-                            VmCallKind::SharedStaticMemoryGrow => unimplemented!(),
-                            VmCallKind::SharedStaticMemorySize => unimplemented!(),
+                            VmCallKind::SharedStaticMemoryGrow => {
+                                vmcalls::local_shared_static_memory_grow as _
+                            }
+                            VmCallKind::SharedStaticMemorySize => {
+                                vmcalls::local_shared_static_memory_size as _
+                            }
 
                             VmCallKind::DynamicMemoryGrow => {
                                 vmcalls::local_dynamic_memory_grow as _
@@ -276,8 +321,12 @@ impl FuncResolverBuilder {
                                 vmcalls::imported_static_memory_size as _
                             }
 
-                            VmCallKind::SharedStaticMemoryGrow => unimplemented!(),
-                            VmCallKind::SharedStaticMemorySize => unimplemented!(),
+                            VmCallKind::SharedStaticMemoryGrow => {
+                                vmcalls::imported_shared_static_memory_grow as _
+                            }
+                            VmCallKind::SharedStaticMemorySize => {
+                                vmcalls::imported_shared_static_memory_size as _
+                            }
 
                             VmCallKind::DynamicMemoryGrow => {
                                 vmcalls::imported_dynamic_memory_grow as _
@@ -288,9 +337,7 @@ impl FuncResolverBuilder {
                         },
                     },
                     RelocationType::Signature(sig_index) => {
-                        let signature = SigRegistry.lookup_signature_ref(&signatures[sig_index]);
-                        let sig_index = SigRegistry.lookup_sig_index(signature);
-                        sig_index.index() as _
+                        SigRegistry.lookup_sig_id(&signatures[sig_index]).0 as _
                     }
                 };
 
@@ -313,14 +360,27 @@ impl FuncResolverBuilder {
                         };
                         LittleEndian::write_u64(ptr_slice, ptr_to_write);
                     }
-                    Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => unsafe {
+                    Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
                         let reloc_address = (func_addr as usize) + reloc.offset as usize;
                         let reloc_delta = target_func_address
                             .wrapping_sub(reloc_address as isize)
                             .wrapping_add(reloc.addend as isize);
 
-                        write_unaligned(reloc_address as *mut u32, reloc_delta as u32);
-                    },
+                        // The code can be loaded anywhere, so the target
+                        // can be out of the reach of 32 bits.
+                        if reloc_delta as i32 as isize != reloc_delta {
+                            Err(CompileError::InternalError {
+                                msg: format!(
+                                    "relocation to {:?} is out of range at {:#x}",
+                                    reloc.target, reloc_address
+                                ),
+                            })?;
+                        }
+
+                        unsafe {
+                            write_unaligned(reloc_address as *mut u32, reloc_delta as u32);
+                        }
+                    }
                 }
             }
         }
@@ -342,6 +402,8 @@ impl FuncResolverBuilder {
             FuncResolver {
                 map: self.map,
                 memory: Arc::new(self.memory),
+                code_sizes: self.code_sizes,
+                compile_times: self.compile_times,
             },
             backend_cache,
         ))
@@ -355,6 +417,21 @@ unsafe impl Send for FuncResolver {}
 pub struct FuncResolver {
     map: Map<LocalFuncIndex, usize>,
     pub(crate) memory: Arc<Memory>,
+    code_sizes: Map<LocalFuncIndex, usize>,
+    compile_times: Option<Map<LocalFuncIndex, Duration>>,
+}
+
+impl FuncResolver {
+    /// Each local function, with the address and size of its code.
+    pub fn functions(&self) -> Vec<(LocalFuncIndex, *const u8, usize)> {
+        self.map
+            .iter()
+            .zip(self.code_sizes.iter())
+            .map(|((index, &offset), (_, &size))| unsafe {
+                (index, self.memory.as_ptr().add(offset) as *const u8, size)
+            })
+            .collect()
+    }
 }
 
 // Implements FuncResolver trait.
@@ -366,9 +443,33 @@ impl backend::FuncResolver for FuncResolver {
     ) -> Option<NonNull<vm::Func>> {
         lookup_func(&self.map, &self.memory, index)
     }
+
+    fn calls_through_ctx(&self) -> bool {
+        true
+    }
+
+    fn code(
+        &self,
+        _module: &wasmer_runtime_core::module::ModuleInner,
+        index: LocalFuncIndex,
+    ) -> Option<FuncCode> {
+        let offset = *self.map.get(index)?;
+        let code = unsafe { &self.memory.as_slice()[offset..offset + self.code_sizes[index]] };
+        Some(FuncCode {
+            code,
+            compile_time: self
+                .compile_times
+                .as_ref()
+                .map(|compile_times| compile_times[index]),
+        })
+    }
 }
 
 #[inline]
+/// The relocations and traps of a compiled function, and how long it
+/// took to compile.
+type CompiledSinks = (RelocSink, LocalTrapSink, Duration);
+
 fn round_up(n: usize, multiple: usize) -> usize {
     (n + multiple - 1) & !(multiple - 1)
 }
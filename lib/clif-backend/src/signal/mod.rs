@@ -1,42 +1,28 @@
-use crate::relocation::{TrapData, TrapSink};
+use crate::relocation::TrapSink;
 use crate::trampoline::Trampolines;
 use hashbrown::HashSet;
-use libc::c_void;
-use std::{any::Any, cell::Cell, sync::Arc};
+use std::sync::Arc;
 use wasmer_runtime_core::{
     backend::{ProtectedCaller, Token, UserTrapper},
     error::RuntimeResult,
     export::Context,
     module::{ExportIndex, ModuleInfo, ModuleInner},
-    types::{FuncIndex, FuncSig, LocalOrImport, SigIndex, Type, Value},
+    refs::{ExternRef, FuncRef},
+    structures::SliceMap,
+    trap::TrapTable,
+    types::{FuncIndex, FuncSig, LocalFuncIndex, LocalOrImport, SigIndex, Type, Value},
     vm::{self, ImportBacking},
 };
 
 #[cfg(unix)]
-mod unix;
+use wasmer_runtime_core::trap::{call_protected, Trapper};
 
 #[cfg(windows)]
 mod windows;
 
-#[cfg(unix)]
-pub use self::unix::*;
-
 #[cfg(windows)]
 pub use self::windows::*;
 
-thread_local! {
-    pub static TRAP_EARLY_DATA: Cell<Option<Box<dyn Any>>> = Cell::new(None);
-}
-
-pub struct Trapper;
-
-impl UserTrapper for Trapper {
-    unsafe fn do_early_trap(&self, data: Box<dyn Any>) -> ! {
-        TRAP_EARLY_DATA.with(|cell| cell.set(Some(data)));
-        trigger_trap()
-    }
-}
-
 pub struct Caller {
     func_export_set: HashSet<FuncIndex>,
     handler_data: HandlerData,
@@ -78,20 +64,16 @@ impl ProtectedCaller for Caller {
         _: Token,
     ) -> RuntimeResult<Vec<Value>> {
         let (func_ptr, ctx, signature, sig_index) =
-            get_func_from_index(&module, import_backing, func_index);
+            get_func_from_index(&module, import_backing, vmctx, func_index);
 
         let vmctx_ptr = match ctx {
             Context::External(external_vmctx) => external_vmctx,
             Context::Internal => vmctx,
+            Context::InternalWithEnv(_) => unreachable!("host closures are resolved at link time"),
         };
 
         assert!(self.func_export_set.contains(&func_index));
 
-        assert!(
-            signature.returns().len() <= 1,
-            "multi-value returns not yet supported"
-        );
-
         assert!(
             signature.check_param_value_types(params),
             "incorrect signature"
@@ -104,6 +86,8 @@ impl ProtectedCaller for Caller {
                 Value::I64(x) => *x as u64,
                 Value::F32(x) => x.to_bits() as u64,
                 Value::F64(x) => x.to_bits(),
+                Value::ExternRef(r) => r.to_bits(),
+                Value::FuncRef(r) => r.to_bits(),
             })
             .collect();
 
@@ -115,7 +99,7 @@ impl ProtectedCaller for Caller {
             .expect("that trampoline doesn't exist");
 
         #[cfg(not(target_os = "windows"))]
-        call_protected(&self.handler_data, || unsafe {
+        call_protected(Some(&self.handler_data.table), || unsafe {
             // Leap of faith.
             trampoline(
                 vmctx_ptr,
@@ -144,10 +128,48 @@ impl ProtectedCaller for Caller {
                 Type::I64 => Value::I64(x as i64),
                 Type::F32 => Value::F32(f32::from_bits(x as u32)),
                 Type::F64 => Value::F64(f64::from_bits(x as u64)),
+                Type::ExternRef => Value::ExternRef(ExternRef::from_bits(x)),
+                Type::FuncRef => Value::FuncRef(FuncRef::from_bits(x)),
             })
             .collect())
     }
 
+    fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+        #[cfg(not(target_os = "windows"))]
+        call_protected(Some(&self.handler_data.table), || f())?;
+
+        // Go through a trampoline, since that's what
+        // the windows exception handler knows how to call.
+        #[cfg(target_os = "windows")]
+        {
+            unsafe extern "C" fn call_closure(
+                _ctx: *mut vm::Ctx,
+                f: *const vm::Func,
+                _params: *const u64,
+                _returns: *mut u64,
+            ) {
+                let f = &mut *(f as *mut &mut dyn FnMut());
+                f();
+            }
+
+            let mut f = f;
+            call_protected(
+                &self.handler_data,
+                unsafe {
+                    std::mem::transmute::<_, crate::trampoline::Trampoline>(
+                        call_closure as unsafe extern "C" fn(_, _, _, _),
+                    )
+                },
+                std::ptr::null_mut(),
+                &mut f as *mut &mut dyn FnMut() as *const vm::Func,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+            )?;
+        }
+
+        Ok(())
+    }
+
     fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
         Box::new(Trapper)
     }
@@ -156,6 +178,7 @@ impl ProtectedCaller for Caller {
 fn get_func_from_index<'a>(
     module: &'a ModuleInner,
     import_backing: &ImportBacking,
+    vmctx: *mut vm::Ctx,
     func_index: FuncIndex,
 ) -> (*const vm::Func, Context, &'a FuncSig, SigIndex) {
     let sig_index = *module
@@ -165,13 +188,10 @@ fn get_func_from_index<'a>(
         .expect("broken invariant, incorrect func index");
 
     let (func_ptr, ctx) = match func_index.local_or_import(&module.info) {
+        // The code of a local function may have been replaced, so it's
+        // looked up in the instance rather than the module.
         LocalOrImport::Local(local_func_index) => (
-            module
-                .func_resolver
-                .get(&module, local_func_index)
-                .expect("broken invariant, func resolver not synced with module.exports")
-                .cast()
-                .as_ptr() as *const _,
+            unsafe { (*vmctx).local_function(local_func_index) },
             Context::Internal,
         ),
         LocalOrImport::Import(imported_func_index) => {
@@ -188,38 +208,33 @@ fn get_func_from_index<'a>(
     (func_ptr, ctx, signature, sig_index)
 }
 
-unsafe impl Send for HandlerData {}
-unsafe impl Sync for HandlerData {}
-
+/// The traps of the compiled code, in the form the cache keeps them
+/// and in the table that the trap handler looks them up in.
 #[derive(Clone)]
 pub struct HandlerData {
     pub trap_data: Arc<TrapSink>,
-    exec_buffer_ptr: *const c_void,
-    exec_buffer_size: usize,
+    pub table: Arc<TrapTable>,
 }
 
 impl HandlerData {
     pub fn new(
         trap_data: Arc<TrapSink>,
-        exec_buffer_ptr: *const c_void,
+        exec_buffer_ptr: *const u8,
         exec_buffer_size: usize,
+        func_offsets: &SliceMap<LocalFuncIndex, usize>,
+        info: &ModuleInfo,
     ) -> Self {
-        Self {
-            trap_data,
+        let table = TrapTable::new(
             exec_buffer_ptr,
             exec_buffer_size,
-        }
-    }
-
-    pub fn lookup(&self, ip: *const c_void) -> Option<TrapData> {
-        let ip = ip as usize;
-        let buffer_ptr = self.exec_buffer_ptr as usize;
+            trap_data.sites(),
+            func_offsets.iter().map(|(_, &offset)| offset).collect(),
+            info,
+        );
 
-        if buffer_ptr <= ip && ip < buffer_ptr + self.exec_buffer_size {
-            let offset = ip - buffer_ptr;
-            self.trap_data.lookup(offset)
-        } else {
-            None
+        Self {
+            trap_data,
+            table: Arc::new(table),
         }
     }
 }
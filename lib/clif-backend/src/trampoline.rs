@@ -6,6 +6,7 @@ use cranelift_codegen::{
     isa, Context,
 };
 use hashbrown::HashMap;
+use std::collections::BTreeSet;
 use std::ffi::c_void;
 use std::{iter, mem};
 use wasmer_runtime_core::{
@@ -39,7 +40,7 @@ impl Trampolines {
         //     offsets: HashMap<SigIndex, usize>,
         // }
 
-        let mut memory = Memory::with_size(cache.code.len()).unwrap();
+        let mut memory = Memory::with_size_for_code(cache.code.len()).unwrap();
         unsafe {
             memory.protect(.., Protect::ReadWrite).unwrap();
 
@@ -69,21 +70,24 @@ impl Trampolines {
     }
 
     pub fn new(isa: &isa::TargetIsa, module: &ModuleInfo) -> Self {
-        let func_index_iter = module
+        // One trampoline per signature, in the order of the signatures,
+        // so the code doesn't depend on the order of the exports map.
+        let sig_indices: BTreeSet<SigIndex> = module
             .exports
             .values()
             .filter_map(|export| match export {
                 ExportIndex::Func(func_index) => Some(func_index),
                 _ => None,
             })
-            .chain(module.start_func.iter());
+            .chain(module.start_func.iter())
+            .map(|func_index| module.func_assoc[*func_index])
+            .collect();
 
         let mut compiled_functions = Vec::new();
         let mut ctx = Context::new();
         let mut total_size = 0;
 
-        for exported_func_index in func_index_iter {
-            let sig_index = module.func_assoc[*exported_func_index];
+        for sig_index in sig_indices {
             let func_sig = &module.signatures[sig_index];
 
             let trampoline_func = generate_func(&func_sig);
@@ -105,7 +109,7 @@ impl Trampolines {
             compiled_functions.push((sig_index, code_buf));
         }
 
-        let mut memory = Memory::with_size(total_size).unwrap();
+        let mut memory = Memory::with_size_for_code(total_size).unwrap();
         unsafe {
             memory.protect(.., Protect::ReadWrite).unwrap();
         }
@@ -206,6 +210,8 @@ fn wasm_ty_to_clif(ty: Type) -> ir::types::Type {
         Type::I64 => ir::types::I64,
         Type::F32 => ir::types::F32,
         Type::F64 => ir::types::F64,
+        // References are passed as their handles.
+        Type::ExternRef | Type::FuncRef => ir::types::I64,
     }
 }
 
@@ -1,5 +1,6 @@
 use wasmer_runtime_core::{
     backend::{FuncResolver, ProtectedCaller},
+    metering::FunctionCosts,
     module::ModuleInfo,
     structures::Map,
     types::{FuncIndex, FuncSig, SigIndex},
@@ -22,6 +23,8 @@ pub trait FunctionCodeGenerator {
     fn feed_return(&mut self, ty: WpType) -> Result<(), CodegenError>;
     fn feed_param(&mut self, ty: WpType) -> Result<(), CodegenError>;
     fn feed_local(&mut self, ty: WpType, n: usize) -> Result<(), CodegenError>;
+    /// Meter the function, charging the given costs for its regions.
+    fn feed_costs(&mut self, costs: FunctionCosts) -> Result<(), CodegenError>;
     fn begin_body(&mut self) -> Result<(), CodegenError>;
     fn feed_opcode(&mut self, op: Operator, module_info: &ModuleInfo) -> Result<(), CodegenError>;
     fn finalize(&mut self) -> Result<(), CodegenError>;
@@ -4,28 +4,39 @@ use super::codegen::*;
 use super::stack::{
     ControlFrame, ControlStack, IfElseState, ScratchRegister, ValueInfo, ValueLocation, ValueStack,
 };
-use crate::protect_unix;
 use byteorder::{ByteOrder, LittleEndian};
 use dynasmrt::{
     x64::Assembler, AssemblyOffset, DynamicLabel, DynasmApi, DynasmLabelApi, ExecutableBuffer,
 };
 use std::cell::RefCell;
 use std::ptr::NonNull;
-use std::{any::Any, collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use wasmer_runtime_core::{
-    backend::{FuncResolver, ProtectedCaller, Token, UserTrapper},
-    error::{RuntimeError, RuntimeResult},
+    backend::{FuncCode, FuncResolver, ProtectedCaller, Token, UserTrapper},
+    deterministic,
+    error::{RuntimeError, RuntimeResult, TrapCode},
     memory::MemoryType,
+    metering::FunctionCosts,
     module::{ModuleInfo, ModuleInner},
+    perf,
+    refs::{ExternRef, FuncRef},
+    stackmap::{RefLocation, Safepoint, StackMap},
     structures::{Map, TypedIndex},
+    trap,
     types::{
         FuncIndex, FuncSig, ImportedMemoryIndex, LocalFuncIndex, LocalGlobalIndex,
-        LocalMemoryIndex, LocalOrImport, MemoryIndex, SigIndex, Type, Value,
+        LocalMemoryIndex, LocalOrImport, MemoryIndex, SigIndex, TableIndex, Type, Value,
     },
     units::Pages,
     vm::{self, ImportBacking, LocalGlobal, LocalMemory, LocalTable},
+    vmcalls::AtomicRmwOp,
 };
-use wasmparser::{Operator, Type as WpType};
+use wasmparser::{MemoryImmediate, Operator, Type as WpType};
 
 thread_local! {
     static CURRENT_EXECUTION_CONTEXT: RefCell<Vec<*const X64ExecutionContext>> = RefCell::new(Vec::new());
@@ -250,6 +261,16 @@ pub struct NativeTrampolines {
     memory_grow_dynamic_import: DynamicLabel,
     memory_grow_static_import: DynamicLabel,
     memory_grow_shared_import: DynamicLabel,
+    atomic_rmw: DynamicLabel,
+    atomic_wait: DynamicLabel,
+    atomic_notify: DynamicLabel,
+    memory_copy: DynamicLabel,
+    memory_fill: DynamicLabel,
+    memory_init: DynamicLabel,
+    data_drop: DynamicLabel,
+    table_init: DynamicLabel,
+    elem_drop: DynamicLabel,
+    trunc_sat: DynamicLabel,
 }
 
 pub struct X64ModuleCodeGenerator {
@@ -278,6 +299,30 @@ pub struct X64FunctionCode {
     value_stack: ValueStack,
     control_stack: Option<ControlStack>,
     unreachable_depth: usize,
+    /// The points to charge for the regions of the function, if the
+    /// module is metered.
+    costs: Option<FunctionCosts>,
+    /// The trap that charging jumps to when the points run out, which
+    /// is placed after the code of the function, if it's metered.
+    out_of_gas: Option<DynamicLabel>,
+    /// The trap that the interrupt checks jump to, which is placed
+    /// after the code of the function.
+    interrupted: Option<DynamicLabel>,
+    /// The trap that the epoch deadline checks jump to, which is placed
+    /// after the code of the function.
+    deadline_exceeded: Option<DynamicLabel>,
+    /// The number of loops fed so far, reachable or not.
+    loops: usize,
+    /// Whether the NaNs that float operations produce are replaced with
+    /// the canonical NaN, in deterministic mode.
+    canonicalize_nans: bool,
+    /// When code generation for the function began.
+    started: Instant,
+    /// How long code generation took, once the function is finalized.
+    compile_time: Duration,
+    /// The references in the frame at each call to a WebAssembly
+    /// function.
+    stack_map: StackMap,
 }
 
 enum FuncPtrInner {}
@@ -288,7 +333,7 @@ unsafe impl Send for FuncPtr {}
 unsafe impl Sync for FuncPtr {}
 
 pub struct X64ExecutionContext {
-    code: ExecutableBuffer,
+    code: Arc<ExecutableBuffer>,
     functions: Vec<X64FunctionCode>,
     signatures: Arc<Map<SigIndex, FuncSig>>,
     function_signatures: Arc<Map<FuncIndex, SigIndex>>,
@@ -300,12 +345,18 @@ pub struct X64ExecutionContext {
 pub struct X64RuntimeResolver {
     _code: ExecutableBuffer,
     local_pointers: Vec<FuncPtr>,
+    /// The code of the functions, which `function_ranges` index into.
+    function_code: Arc<ExecutableBuffer>,
+    function_ranges: Vec<Range<usize>>,
+    compile_times: Vec<Duration>,
+    stack_maps: Vec<StackMap>,
 }
 
 impl X64ExecutionContext {
     fn get_runtime_resolver(
         &self,
         module_info: &ModuleInfo,
+        function_ranges: Vec<Range<usize>>,
     ) -> Result<X64RuntimeResolver, CodegenError> {
         let mut assembler = Assembler::new().unwrap();
         let mut offsets: Vec<AssemblyOffset> = vec![];
@@ -329,6 +380,18 @@ impl X64ExecutionContext {
         Ok(X64RuntimeResolver {
             _code: code,
             local_pointers: local_pointers,
+            function_code: self.code.clone(),
+            function_ranges,
+            compile_times: self
+                .functions
+                .iter()
+                .map(|function| function.compile_time)
+                .collect(),
+            stack_maps: self
+                .functions
+                .iter()
+                .map(|function| function.stack_map.clone())
+                .collect(),
         })
     }
 }
@@ -341,6 +404,22 @@ impl FuncResolver for X64RuntimeResolver {
     ) -> Option<NonNull<vm::Func>> {
         NonNull::new(self.local_pointers[_local_func_index.index() as usize].0 as *mut vm::Func)
     }
+
+    fn code(&self, _module: &ModuleInner, local_func_index: LocalFuncIndex) -> Option<FuncCode> {
+        let range = self.function_ranges.get(local_func_index.index())?;
+        Some(FuncCode {
+            code: &self.function_code[range.clone()],
+            compile_time: Some(self.compile_times[local_func_index.index()]),
+        })
+    }
+
+    fn stack_map(
+        &self,
+        _module: &ModuleInner,
+        local_func_index: LocalFuncIndex,
+    ) -> Option<&StackMap> {
+        self.stack_maps.get(local_func_index.index())
+    }
 }
 
 impl ProtectedCaller for X64ExecutionContext {
@@ -355,11 +434,18 @@ impl ProtectedCaller for X64ExecutionContext {
     ) -> RuntimeResult<Vec<Value>> {
         let index = _func_index.index() - self.func_import_count;
         let ptr = self.code.ptr(self.functions[index].begin_offset);
-        let return_ty = self.functions[index].returns.last().cloned();
+        // References are `i64`s in the generated code, so take the
+        // return type from the signature instead.
+        let return_ty = _module.info.signatures[_module.info.func_assoc[_func_index]]
+            .returns()
+            .last()
+            .cloned();
 
         if self.functions[index].num_params != _params.len() {
             return Err(RuntimeError::Trap {
+                code: TrapCode::Unknown,
                 msg: "param count mismatch".into(),
+                backtrace: Vec::new(),
             });
         }
 
@@ -382,7 +468,9 @@ impl ProtectedCaller for X64ExecutionContext {
                     Value::F32(x) => LittleEndian::write_u32(buf, f32::to_bits(x)),
                     _ => {
                         return Err(RuntimeError::Trap {
+                            code: TrapCode::Unknown,
                             msg: "signature mismatch".into(),
+                            backtrace: Vec::new(),
                         });
                     }
                 }
@@ -390,9 +478,13 @@ impl ProtectedCaller for X64ExecutionContext {
                 match _params[i] {
                     Value::I64(x) => LittleEndian::write_u64(buf, x as u64),
                     Value::F64(x) => LittleEndian::write_u64(buf, f64::to_bits(x)),
+                    Value::ExternRef(x) => LittleEndian::write_u64(buf, x.to_bits()),
+                    Value::FuncRef(x) => LittleEndian::write_u64(buf, x.to_bits()),
                     _ => {
                         return Err(RuntimeError::Trap {
+                            code: TrapCode::Unknown,
                             msg: "signature mismatch".into(),
+                            backtrace: Vec::new(),
                         });
                     }
                 }
@@ -402,7 +494,9 @@ impl ProtectedCaller for X64ExecutionContext {
         let (memory_base, memory_size): (*mut u8, usize) = if _module.info.memories.len() > 0 {
             if _module.info.memories.len() != 1 || _module.info.imported_memories.len() != 0 {
                 return Err(RuntimeError::Trap {
+                    code: TrapCode::Unknown,
                     msg: "only one linear memory is supported".into(),
+                    backtrace: Vec::new(),
                 });
             }
             unsafe {
@@ -412,7 +506,9 @@ impl ProtectedCaller for X64ExecutionContext {
         } else if _module.info.imported_memories.len() > 0 {
             if _module.info.memories.len() != 0 || _module.info.imported_memories.len() != 1 {
                 return Err(RuntimeError::Trap {
+                    code: TrapCode::Unknown,
                     msg: "only one linear memory is supported".into(),
+                    backtrace: Vec::new(),
                 });
             }
             unsafe {
@@ -430,7 +526,7 @@ impl ProtectedCaller for X64ExecutionContext {
         CURRENT_EXECUTION_CONTEXT.with(|x| x.borrow_mut().push(self));
 
         let ret = unsafe {
-            protect_unix::call_protected(|| {
+            trap::call_protected(None, || {
                 CALL_WASM(
                     param_buf.as_ptr(),
                     param_buf.len(),
@@ -448,27 +544,27 @@ impl ProtectedCaller for X64ExecutionContext {
 
         Ok(if let Some(ty) = return_ty {
             vec![match ty {
-                WpType::I32 => Value::I32(ret as i32),
-                WpType::I64 => Value::I64(ret),
-                WpType::F32 => Value::F32(f32::from_bits(ret as i32 as u32)),
-                WpType::F64 => Value::F64(f64::from_bits(ret as u64)),
-                _ => unreachable!(),
+                Type::I32 => Value::I32(ret as i32),
+                Type::I64 => Value::I64(ret),
+                Type::F32 => Value::F32(f32::from_bits(ret as i32 as u32)),
+                Type::F64 => Value::F64(f64::from_bits(ret as u64)),
+                Type::ExternRef => Value::ExternRef(ExternRef::from_bits(ret as u64)),
+                Type::FuncRef => Value::FuncRef(FuncRef::from_bits(ret as u64)),
             }]
         } else {
             vec![]
         })
     }
 
-    fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
-        pub struct Trapper;
-
-        impl UserTrapper for Trapper {
-            unsafe fn do_early_trap(&self, _data: Box<Any>) -> ! {
-                panic!("do_early_trap");
-            }
-        }
+    fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+        CURRENT_EXECUTION_CONTEXT.with(|x| x.borrow_mut().push(self));
+        let ret = trap::call_protected(None, || f());
+        CURRENT_EXECUTION_CONTEXT.with(|x| x.borrow_mut().pop().unwrap());
+        ret
+    }
 
-        Box::new(Trapper)
+    fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
+        Box::new(trap::Trapper)
     }
 }
 
@@ -476,6 +572,8 @@ impl ProtectedCaller for X64ExecutionContext {
 struct Local {
     ty: WpType,
     stack_offset: usize,
+    /// Whether the local holds a reference, which is kept as an `i64`.
+    is_ref: bool,
 }
 
 impl X64ModuleCodeGenerator {
@@ -554,6 +652,66 @@ impl X64ModuleCodeGenerator {
                 MemoryKind::SharedImport,
                 0usize,
             ),
+            atomic_rmw: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _atomic_rmw,
+                0usize,
+                0usize,
+            ),
+            atomic_wait: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _atomic_wait,
+                0usize,
+                0usize,
+            ),
+            atomic_notify: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _atomic_notify,
+                0usize,
+                0usize,
+            ),
+            memory_copy: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::MemoryCopy,
+                0usize,
+            ),
+            memory_fill: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::MemoryFill,
+                0usize,
+            ),
+            memory_init: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::MemoryInit,
+                0usize,
+            ),
+            data_drop: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::DataDrop,
+                0usize,
+            ),
+            table_init: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::TableInit,
+                0usize,
+            ),
+            elem_drop: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _bulk_memory,
+                BulkMemoryOp::ElemDrop,
+                0usize,
+            ),
+            trunc_sat: X64FunctionCode::emit_native_call_trampoline(
+                &mut assembler,
+                _trunc_sat,
+                0usize,
+                0usize,
+            ),
         };
 
         X64ModuleCodeGenerator {
@@ -617,6 +775,15 @@ impl ModuleCodeGenerator<X64FunctionCode, X64ExecutionContext, X64RuntimeResolve
             value_stack: ValueStack::new(4), // FIXME: Use of R8 and above registers generates incorrect assembly.
             control_stack: None,
             unreachable_depth: 0,
+            costs: None,
+            out_of_gas: None,
+            interrupted: None,
+            deadline_exceeded: None,
+            loops: 0,
+            canonicalize_nans: deterministic::is_deterministic(),
+            started: Instant::now(),
+            compile_time: Duration::default(),
+            stack_map: StackMap::new(),
         };
         self.functions.push(code);
         Ok(self.functions.last_mut().unwrap())
@@ -669,8 +836,37 @@ impl ModuleCodeGenerator<X64FunctionCode, X64ExecutionContext, X64RuntimeResolve
             out_labels.push(FuncPtr(output.ptr(*offset) as _));
         }
 
+        // The functions are emitted one after the other.
+        let ends = self
+            .functions
+            .iter()
+            .skip(1)
+            .map(|function| function.begin_offset.0)
+            .chain(Some(output.len()));
+        let function_ranges: Vec<Range<usize>> = self
+            .functions
+            .iter()
+            .zip(ends)
+            .map(|(function, end)| function.begin_offset.0..end)
+            .collect();
+
+        if perf::is_enabled() {
+            let functions: Vec<_> = function_ranges
+                .iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    (
+                        LocalFuncIndex::new(i),
+                        output.ptr(AssemblyOffset(range.start)) as *const u8,
+                        range.len(),
+                    )
+                })
+                .collect();
+            unsafe { perf::register(module_info, &functions) };
+        }
+
         let ctx = X64ExecutionContext {
-            code: output,
+            code: Arc::new(output),
             functions: self.functions,
             _br_table_data: br_table_data,
             func_import_count: self.func_import_count,
@@ -692,7 +888,7 @@ impl ModuleCodeGenerator<X64FunctionCode, X64ExecutionContext, X64RuntimeResolve
                 }
             },
         };
-        let resolver = ctx.get_runtime_resolver(module_info)?;
+        let resolver = ctx.get_runtime_resolver(module_info, function_ranges)?;
 
         Ok((ctx, resolver))
     }
@@ -1159,6 +1355,101 @@ impl X64FunctionCode {
         Self::emit_push_from_reg(assembler, value_stack, ty, Register::RAX)
     }
 
+    /// Takes `cost` points from the instance, jumping to `out_of_gas`
+    /// if that leaves it with less than none. The runtime reports the
+    /// trap there as running out of gas.
+    ///
+    /// Charging is a single subtraction from memory and a branch that
+    /// isn't taken, and needs no register unless the cost doesn't fit
+    /// in 32 bits.
+    fn emit_charge_points(assembler: &mut Assembler, cost: u64, out_of_gas: DynamicLabel) {
+        if cost == 0 {
+            return;
+        }
+        if cost <= i32::max_value() as u64 {
+            dynasm!(
+                assembler
+                ; sub QWORD [r14 + vm::Ctx::offset_remaining_points() as i32], cost as i32
+                ; js =>out_of_gas
+            );
+        } else {
+            dynasm!(
+                assembler
+                ; mov rax, QWORD cost as i64
+                ; sub QWORD [r14 + vm::Ctx::offset_remaining_points() as i32], rax
+                ; js =>out_of_gas
+            );
+        }
+    }
+
+    /// Jumps to `interrupted` if the instance has been interrupted. The
+    /// runtime reports the trap there as an interrupt.
+    fn emit_check_interrupted(assembler: &mut Assembler, interrupted: DynamicLabel) {
+        dynasm!(
+            assembler
+            ; mov rax, r14 => vm::InternalCtx.interrupted
+            ; cmp BYTE [rax], 0
+            ; jne =>interrupted
+        );
+    }
+
+    /// Jumps to `deadline_exceeded` if the epoch deadline of the instance
+    /// has been reached. The runtime reports the trap there as an
+    /// exceeded deadline.
+    fn emit_check_deadline(assembler: &mut Assembler, deadline_exceeded: DynamicLabel) {
+        dynasm!(
+            assembler
+            ; mov rax, r14 => vm::InternalCtx.epoch
+            ; mov rax, [rax]
+            ; cmp rax, r14 => vm::InternalCtx.epoch_deadline
+            ; jae =>deadline_exceeded
+        );
+    }
+
+    /// Replaces the value on top of the value stack with the canonical
+    /// NaN if it's a NaN.
+    fn emit_canonicalize_nan(
+        assembler: &mut Assembler,
+        value_stack: &mut ValueStack,
+    ) -> Result<(), CodegenError> {
+        let ty = match value_stack.values.last() {
+            Some(value) => value.ty,
+            None => {
+                return Err(CodegenError {
+                    message: "canonicalize_nan: value stack is empty",
+                })
+            }
+        };
+        Self::emit_unop(
+            assembler,
+            value_stack,
+            |assembler, _value_stack, reg| match ty {
+                WpType::F32 => {
+                    dynasm!(
+                        assembler
+                        ; movd xmm1, Rd(reg as u8)
+                        ; ucomiss xmm1, xmm1
+                        ; jnp >not_nan
+                        ; mov Rd(reg as u8), 0x7fc00000
+                        ; not_nan:
+                    );
+                }
+                _ => {
+                    dynasm!(
+                        assembler
+                        ; movq xmm1, Rq(reg as u8)
+                        ; ucomisd xmm1, xmm1
+                        ; jnp >not_nan
+                        ; mov Rq(reg as u8), QWORD 0x7ff8000000000000
+                        ; not_nan:
+                    );
+                }
+            },
+            ty,
+            ty,
+        )
+    }
+
     fn emit_leave_frame(
         assembler: &mut Assembler,
         frame: &ControlFrame,
@@ -1668,7 +1959,7 @@ impl X64FunctionCode {
         target: DynamicLabel,
         params: &[WpType],
         returns: &[WpType],
-    ) -> Result<(), CodegenError> {
+    ) -> Result<AssemblyOffset, CodegenError> {
         let total_size: usize = params.len() * 8;
 
         if params.len() > value_stack.values.len() {
@@ -1753,6 +2044,10 @@ impl X64FunctionCode {
         dynasm!(
             assembler
             ; jmp =>target
+        );
+        let return_offset = assembler.offset();
+        dynasm!(
+            assembler
             ; after_call:
         );
 
@@ -1782,7 +2077,25 @@ impl X64FunctionCode {
             }
         }
 
-        Ok(())
+        Ok(return_offset)
+    }
+
+    /// Record the call whose return address is at `return_offset` as a
+    /// safepoint, where the references are the locals that hold them.
+    fn record_safepoint(
+        stack_map: &mut StackMap,
+        locals: &[Local],
+        begin_offset: AssemblyOffset,
+        return_offset: AssemblyOffset,
+    ) {
+        stack_map.push(Safepoint {
+            offset: return_offset.0 - begin_offset.0,
+            refs: locals
+                .iter()
+                .filter(|local| local.is_ref)
+                .map(|local| RefLocation::Frame(-(local.stack_offset as i32)))
+                .collect(),
+        });
     }
 
     fn emit_memory_bound_check_if_needed(
@@ -1822,6 +2135,88 @@ impl X64FunctionCode {
         }
     }
 
+    /// Calls one of the native trampolines, passing the operands
+    /// on the value stack followed by `descriptor`.
+    fn emit_descriptor_call(
+        assembler: &mut Assembler,
+        value_stack: &mut ValueStack,
+        target: DynamicLabel,
+        descriptor: u64,
+        params: &[WpType],
+        returns: &[WpType],
+    ) -> Result<(), CodegenError> {
+        match value_stack.push(WpType::I64) {
+            ValueLocation::Register(x) => {
+                let reg = Register::from_scratch_reg(x);
+                dynasm!(
+                    assembler
+                    ; mov Rq(reg as u8), QWORD descriptor as i64
+                );
+            }
+            ValueLocation::Stack => {
+                dynasm!(
+                    assembler
+                    ; mov rax, QWORD descriptor as i64
+                    ; push rax
+                );
+            }
+        }
+
+        let mut params = params.to_vec();
+        params.push(WpType::I64);
+        Self::emit_call_raw(assembler, value_stack, target, &params, returns)?;
+        Ok(())
+    }
+
+    fn emit_atomic_rmw(
+        assembler: &mut Assembler,
+        value_stack: &mut ValueStack,
+        native_trampolines: &NativeTrampolines,
+        op: AtomicRmwOp,
+        width: u32,
+        memarg: &MemoryImmediate,
+        ty: WpType,
+    ) -> Result<(), CodegenError> {
+        let (params, returns): (&[WpType], &[WpType]) = match op {
+            AtomicRmwOp::Load => (&[WpType::I32], &[ty]),
+            AtomicRmwOp::Store => (&[WpType::I32, ty], &[]),
+            AtomicRmwOp::Cmpxchg => (&[WpType::I32, ty, ty], &[ty]),
+            _ => (&[WpType::I32, ty], &[ty]),
+        };
+        Self::emit_descriptor_call(
+            assembler,
+            value_stack,
+            native_trampolines.atomic_rmw,
+            atomic_descriptor(Some(op), width, memarg),
+            params,
+            returns,
+        )
+    }
+
+    fn emit_trunc_sat(
+        assembler: &mut Assembler,
+        value_stack: &mut ValueStack,
+        native_trampolines: &NativeTrampolines,
+        op: TruncSatOp,
+    ) -> Result<(), CodegenError> {
+        use self::TruncSatOp::*;
+
+        let (in_ty, out_ty) = match op {
+            I32F32S | I32F32U => (WpType::F32, WpType::I32),
+            I32F64S | I32F64U => (WpType::F64, WpType::I32),
+            I64F32S | I64F32U => (WpType::F32, WpType::I64),
+            I64F64S | I64F64U => (WpType::F64, WpType::I64),
+        };
+        Self::emit_descriptor_call(
+            assembler,
+            value_stack,
+            native_trampolines.trunc_sat,
+            op as u64,
+            &[in_ty],
+            &[out_ty],
+        )
+    }
+
     fn emit_memory_load<F: FnOnce(&mut Assembler, Register)>(
         assembler: &mut Assembler,
         value_stack: &mut ValueStack,
@@ -2006,6 +2401,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
         self.locals.push(Local {
             ty: ty,
             stack_offset: self.current_stack_offset,
+            is_ref: is_ref_type(ty),
         });
 
         self.num_params += 1;
@@ -2014,6 +2410,8 @@ impl FunctionCodeGenerator for X64FunctionCode {
     }
 
     fn feed_local(&mut self, ty: WpType, n: usize) -> Result<(), CodegenError> {
+        let is_ref = is_ref_type(ty);
+        let ty = value_type(ty);
         let assembler = self.assembler.as_mut().unwrap();
         let size = get_size_of_type(&ty)?;
 
@@ -2024,6 +2422,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                 self.locals.push(Local {
                     ty: ty,
                     stack_offset: self.current_stack_offset,
+                    is_ref,
                 });
                 dynasm!(
                     assembler
@@ -2045,6 +2444,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                 self.locals.push(Local {
                     ty: ty,
                     stack_offset: self.current_stack_offset,
+                    is_ref,
                 });
                 dynasm!(
                     assembler
@@ -2054,16 +2454,51 @@ impl FunctionCodeGenerator for X64FunctionCode {
         }
         Ok(())
     }
+    fn feed_costs(&mut self, costs: FunctionCosts) -> Result<(), CodegenError> {
+        self.costs = Some(costs);
+        Ok(())
+    }
     fn begin_body(&mut self) -> Result<(), CodegenError> {
+        if self.returns.len() > 1 {
+            return Err(CodegenError {
+                message:
+                    "the dynasm backend doesn't support functions that return more than one value",
+            });
+        }
         self.control_stack = Some(ControlStack::new(
             self.assembler.as_mut().unwrap().new_dynamic_label(),
             self.returns.clone(),
         ));
+        let assembler = self.assembler.as_mut().unwrap();
+        if let Some(ref costs) = self.costs {
+            let out_of_gas = assembler.new_dynamic_label();
+            Self::emit_charge_points(assembler, costs.entry, out_of_gas);
+            self.out_of_gas = Some(out_of_gas);
+        }
+        let interrupted = assembler.new_dynamic_label();
+        Self::emit_check_interrupted(assembler, interrupted);
+        self.interrupted = Some(interrupted);
+        let deadline_exceeded = assembler.new_dynamic_label();
+        Self::emit_check_deadline(assembler, deadline_exceeded);
+        self.deadline_exceeded = Some(deadline_exceeded);
         Ok(())
     }
     fn feed_opcode(&mut self, op: Operator, module_info: &ModuleInfo) -> Result<(), CodegenError> {
         let was_unreachable;
 
+        // Loops are counted even when they're unreachable, since their
+        // costs are looked up by the order that they appear in.
+        let loop_cost = match op {
+            Operator::Loop { .. } => {
+                self.loops += 1;
+                self.costs
+                    .as_ref()
+                    .map(|costs| costs.loop_cost(self.loops - 1))
+            }
+            _ => None,
+        };
+        let canonicalize_result = self.canonicalize_nans && produces_nan(&op);
+
         if self.unreachable_depth > 0 {
             was_unreachable = true;
             match op {
@@ -3204,7 +3639,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                         if_else: IfElseState::None,
                         returns: match ty {
                             WpType::EmptyBlockType => vec![],
-                            _ => vec![ty],
+                            _ => vec![value_type(ty)],
                         },
                         value_stack_depth_before: self.value_stack.values.len(),
                     });
@@ -3253,39 +3688,22 @@ impl FunctionCodeGenerator for X64FunctionCode {
                     sig.params().iter().cloned().map(type_to_wp_type).collect();
                 let return_types: Vec<WpType> =
                     sig.returns().iter().cloned().map(type_to_wp_type).collect();
-                Self::emit_call_raw(
+                let return_offset = Self::emit_call_raw(
                     assembler,
                     &mut self.value_stack,
                     label,
                     &param_types,
                     &return_types,
                 )?;
+                Self::record_safepoint(
+                    &mut self.stack_map,
+                    &self.locals,
+                    self.begin_offset,
+                    return_offset,
+                );
             }
             Operator::CallIndirect { index, table_index } => {
-                if table_index != 0 {
-                    return Err(CodegenError {
-                        message: "only one table is supported",
-                    });
-                }
-                let local_or_import = if module_info.tables.len() > 0 {
-                    if module_info.tables.len() != 1 || module_info.imported_tables.len() != 0 {
-                        return Err(CodegenError {
-                            message: "only one table is supported",
-                        });
-                    }
-                    CallIndirectLocalOrImport::Local
-                } else if module_info.imported_tables.len() > 0 {
-                    if module_info.tables.len() != 0 || module_info.imported_tables.len() != 1 {
-                        return Err(CodegenError {
-                            message: "only one table is supported",
-                        });
-                    }
-                    CallIndirectLocalOrImport::Import
-                } else {
-                    return Err(CodegenError {
-                        message: "no tables",
-                    });
-                };
+                let location = TableLocation::new(module_info, table_index)?;
                 let sig_index = SigIndex::new(index as usize);
                 let sig = match self.signatures.get(sig_index) {
                     Some(x) => x,
@@ -3310,7 +3728,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                     assembler,
                     call_indirect,
                     index as usize,
-                    local_or_import,
+                    location,
                 );
 
                 dynasm!(
@@ -3318,13 +3736,19 @@ impl FunctionCodeGenerator for X64FunctionCode {
                     ; after_trampoline:
                 );
 
-                Self::emit_call_raw(
+                let return_offset = Self::emit_call_raw(
                     assembler,
                     &mut self.value_stack,
                     trampoline_label,
                     &param_types,
                     &return_types,
                 )?;
+                Self::record_safepoint(
+                    &mut self.stack_map,
+                    &self.locals,
+                    self.begin_offset,
+                    return_offset,
+                );
             }
             Operator::End => {
                 if self.control_stack.as_ref().unwrap().frames.len() == 1 {
@@ -3361,7 +3785,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                         if_else: IfElseState::None,
                         returns: match ty {
                             WpType::EmptyBlockType => vec![],
-                            _ => vec![ty],
+                            _ => vec![value_type(ty)],
                         },
                         value_stack_depth_before: self.value_stack.values.len(),
                     });
@@ -3369,6 +3793,14 @@ impl FunctionCodeGenerator for X64FunctionCode {
                     assembler
                     ; =>label
                 );
+                if let (Some(cost), Some(out_of_gas)) = (loop_cost, self.out_of_gas) {
+                    Self::emit_charge_points(assembler, cost, out_of_gas);
+                }
+                // Check whether the instance has been interrupted, or its
+                // epoch deadline has been reached, at the start of every
+                // iteration. The runtime reports the traps as such.
+                Self::emit_check_interrupted(assembler, self.interrupted.unwrap());
+                Self::emit_check_deadline(assembler, self.deadline_exceeded.unwrap());
             }
             Operator::If { ty } => {
                 let label_end = assembler.new_dynamic_label();
@@ -3386,7 +3818,7 @@ impl FunctionCodeGenerator for X64FunctionCode {
                         if_else: IfElseState::If(label_else),
                         returns: match ty {
                             WpType::EmptyBlockType => vec![],
-                            _ => vec![ty],
+                            _ => vec![value_type(ty)],
                         },
                         value_stack_depth_before: self.value_stack.values.len(),
                     });
@@ -5004,6 +5436,54 @@ impl FunctionCodeGenerator for X64FunctionCode {
                     WpType::I64,
                 )?;
             }
+            Operator::I32TruncSSatF32 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I32F32S,
+            )?,
+            Operator::I32TruncUSatF32 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I32F32U,
+            )?,
+            Operator::I32TruncSSatF64 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I32F64S,
+            )?,
+            Operator::I32TruncUSatF64 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I32F64U,
+            )?,
+            Operator::I64TruncSSatF32 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I64F32S,
+            )?,
+            Operator::I64TruncUSatF32 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I64F32U,
+            )?,
+            Operator::I64TruncSSatF64 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I64F64S,
+            )?,
+            Operator::I64TruncUSatF64 => Self::emit_trunc_sat(
+                assembler,
+                &mut self.value_stack,
+                &self.native_trampolines,
+                TruncSatOp::I64F64U,
+            )?,
             Operator::Nop => {}
             Operator::MemorySize { reserved } => {
                 let memory_index = MemoryIndex::new(reserved as usize);
@@ -5072,9 +5552,185 @@ impl FunctionCodeGenerator for X64FunctionCode {
                 )?;
                 Self::emit_update_memory_from_ctx(assembler, module_info)?;
             }
-            _ => {
-                panic!("{:?}", op);
+            Operator::Wake { ref memarg } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.atomic_notify,
+                    atomic_descriptor(None, 4, memarg),
+                    &[WpType::I32, WpType::I32],
+                    &[WpType::I32],
+                )?;
             }
+            Operator::I32Wait { ref memarg } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.atomic_wait,
+                    atomic_descriptor(None, 4, memarg),
+                    &[WpType::I32, WpType::I32, WpType::I64],
+                    &[WpType::I32],
+                )?;
+            }
+            Operator::I64Wait { ref memarg } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.atomic_wait,
+                    atomic_descriptor(None, 8, memarg),
+                    &[WpType::I32, WpType::I64, WpType::I64],
+                    &[WpType::I32],
+                )?;
+            }
+            Operator::MemoryCopy => {
+                Self::emit_call_raw(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.memory_copy,
+                    &[WpType::I32, WpType::I32, WpType::I32],
+                    &[],
+                )?;
+            }
+            Operator::MemoryFill => {
+                Self::emit_call_raw(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.memory_fill,
+                    &[WpType::I32, WpType::I32, WpType::I32],
+                    &[],
+                )?;
+            }
+            Operator::MemoryInit { segment } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.memory_init,
+                    u64::from(segment),
+                    &[WpType::I32, WpType::I32, WpType::I32],
+                    &[],
+                )?;
+            }
+            Operator::DataDrop { segment } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.data_drop,
+                    u64::from(segment),
+                    &[],
+                    &[],
+                )?;
+            }
+            Operator::TableInit { segment } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.table_init,
+                    u64::from(segment),
+                    &[WpType::I32, WpType::I32, WpType::I32],
+                    &[],
+                )?;
+            }
+            Operator::ElemDrop { segment } => {
+                Self::emit_descriptor_call(
+                    assembler,
+                    &mut self.value_stack,
+                    self.native_trampolines.elem_drop,
+                    u64::from(segment),
+                    &[],
+                    &[],
+                )?;
+            }
+            Operator::RefNull => {
+                let location = self.value_stack.push(WpType::I64);
+                match location {
+                    ValueLocation::Register(x) => {
+                        let reg = Register::from_scratch_reg(x);
+                        dynasm!(
+                            assembler
+                            ; xor Rq(reg as u8), Rq(reg as u8)
+                        );
+                    }
+                    ValueLocation::Stack => {
+                        dynasm!(
+                            assembler
+                            ; push 0
+                        );
+                    }
+                }
+            }
+            Operator::RefIsNull => {
+                Self::emit_unop(
+                    assembler,
+                    &mut self.value_stack,
+                    |assembler, _value_stack, reg| {
+                        dynasm!(
+                            assembler
+                            ; cmp Rq(reg as u8), 0
+                            ; lahf
+                            ; shr ax, 14
+                            ; and eax, 1
+                        );
+                        if reg != Register::RAX {
+                            dynasm!(
+                                assembler
+                                ; mov Rd(reg as u8), eax
+                            );
+                        }
+                    },
+                    WpType::I64,
+                    WpType::I32,
+                )?;
+            }
+            Operator::TableGet { table } | Operator::TableSet { table } => {
+                let location = TableLocation::new(module_info, table)?;
+                let (target, params, returns): (_, &[WpType], &[WpType]) = match op {
+                    Operator::TableGet { .. } => (
+                        table_get as unsafe extern "C" fn(_, _, _, _, _, _) -> _,
+                        &[WpType::I32],
+                        &[WpType::I64],
+                    ),
+                    _ => (table_set as _, &[WpType::I32, WpType::I64], &[]),
+                };
+
+                dynasm!(
+                    assembler
+                    ; jmp >after_trampoline
+                );
+
+                let trampoline_label =
+                    Self::emit_native_call_trampoline(assembler, target, table as usize, location);
+
+                dynasm!(
+                    assembler
+                    ; after_trampoline:
+                );
+
+                Self::emit_call_raw(
+                    assembler,
+                    &mut self.value_stack,
+                    trampoline_label,
+                    params,
+                    returns,
+                )?;
+            }
+            _ => match atomic_rmw_info(&op) {
+                Some((rmw_op, width, ty, memarg)) => {
+                    Self::emit_atomic_rmw(
+                        assembler,
+                        &mut self.value_stack,
+                        &self.native_trampolines,
+                        rmw_op,
+                        width,
+                        memarg,
+                        ty,
+                    )?;
+                }
+                None => panic!("{:?}", op),
+            },
+        }
+
+        if canonicalize_result {
+            Self::emit_canonicalize_nan(assembler, &mut self.value_stack)?;
         }
         Ok(())
     }
@@ -5088,6 +5744,34 @@ impl FunctionCodeGenerator for X64FunctionCode {
             ; pop rbp
             ; ret
         );
+        if let Some(out_of_gas) = self.out_of_gas {
+            dynasm!(
+                assembler
+                ; =>out_of_gas
+                ; ud2
+            );
+        }
+        if let Some(interrupted) = self.interrupted {
+            dynasm!(
+                assembler
+                ; =>interrupted
+                ; mov rax, QWORD 0xfffffffffffffff0u64 as i64
+                ; and rsp, rax
+                ; mov rax, QWORD raise_interrupted as i64
+                ; call rax
+            );
+        }
+        if let Some(deadline_exceeded) = self.deadline_exceeded {
+            dynasm!(
+                assembler
+                ; =>deadline_exceeded
+                ; mov rax, QWORD 0xfffffffffffffff0u64 as i64
+                ; and rsp, rax
+                ; mov rax, QWORD raise_deadline_exceeded as i64
+                ; call rax
+            );
+        }
+        self.compile_time = self.started.elapsed();
 
         if self.value_stack.values.len() != 0
             || self.control_stack.as_ref().unwrap().frames.len() != 0
@@ -5111,6 +5795,119 @@ fn get_size_of_type(ty: &WpType) -> Result<usize, CodegenError> {
     }
 }
 
+/// Packs what the atomic native calls need to know about an
+/// atomic operator that isn't on the value stack into a `u64`.
+fn atomic_descriptor(op: Option<AtomicRmwOp>, width: u32, memarg: &MemoryImmediate) -> u64 {
+    let op = op.map(|op| op as u64).unwrap_or(0);
+    u64::from(memarg.offset) | u64::from(width) << 32 | op << 40
+}
+
+/// Whether the result of `op` can be a NaN whose bit pattern
+/// depends on the platform.
+fn produces_nan(op: &Operator) -> bool {
+    match *op {
+        Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Sqrt
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F32DemoteF64
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Sqrt
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F64PromoteF32 => true,
+        _ => false,
+    }
+}
+
+/// The operation, width in bytes and value type
+/// of an atomic load, store or read-modify-write.
+fn atomic_rmw_info(op: &Operator) -> Option<(AtomicRmwOp, u32, WpType, &MemoryImmediate)> {
+    use self::AtomicRmwOp::*;
+    use self::WpType::{I32, I64};
+
+    Some(match *op {
+        Operator::I32AtomicLoad { ref memarg } => (Load, 4, I32, memarg),
+        Operator::I64AtomicLoad { ref memarg } => (Load, 8, I64, memarg),
+        Operator::I32AtomicLoad8U { ref memarg } => (Load, 1, I32, memarg),
+        Operator::I32AtomicLoad16U { ref memarg } => (Load, 2, I32, memarg),
+        Operator::I64AtomicLoad8U { ref memarg } => (Load, 1, I64, memarg),
+        Operator::I64AtomicLoad16U { ref memarg } => (Load, 2, I64, memarg),
+        Operator::I64AtomicLoad32U { ref memarg } => (Load, 4, I64, memarg),
+        Operator::I32AtomicStore { ref memarg } => (Store, 4, I32, memarg),
+        Operator::I64AtomicStore { ref memarg } => (Store, 8, I64, memarg),
+        Operator::I32AtomicStore8 { ref memarg } => (Store, 1, I32, memarg),
+        Operator::I32AtomicStore16 { ref memarg } => (Store, 2, I32, memarg),
+        Operator::I64AtomicStore8 { ref memarg } => (Store, 1, I64, memarg),
+        Operator::I64AtomicStore16 { ref memarg } => (Store, 2, I64, memarg),
+        Operator::I64AtomicStore32 { ref memarg } => (Store, 4, I64, memarg),
+        Operator::I32AtomicRmwAdd { ref memarg } => (Add, 4, I32, memarg),
+        Operator::I64AtomicRmwAdd { ref memarg } => (Add, 8, I64, memarg),
+        Operator::I32AtomicRmw8UAdd { ref memarg } => (Add, 1, I32, memarg),
+        Operator::I32AtomicRmw16UAdd { ref memarg } => (Add, 2, I32, memarg),
+        Operator::I64AtomicRmw8UAdd { ref memarg } => (Add, 1, I64, memarg),
+        Operator::I64AtomicRmw16UAdd { ref memarg } => (Add, 2, I64, memarg),
+        Operator::I64AtomicRmw32UAdd { ref memarg } => (Add, 4, I64, memarg),
+        Operator::I32AtomicRmwSub { ref memarg } => (Sub, 4, I32, memarg),
+        Operator::I64AtomicRmwSub { ref memarg } => (Sub, 8, I64, memarg),
+        Operator::I32AtomicRmw8USub { ref memarg } => (Sub, 1, I32, memarg),
+        Operator::I32AtomicRmw16USub { ref memarg } => (Sub, 2, I32, memarg),
+        Operator::I64AtomicRmw8USub { ref memarg } => (Sub, 1, I64, memarg),
+        Operator::I64AtomicRmw16USub { ref memarg } => (Sub, 2, I64, memarg),
+        Operator::I64AtomicRmw32USub { ref memarg } => (Sub, 4, I64, memarg),
+        Operator::I32AtomicRmwAnd { ref memarg } => (And, 4, I32, memarg),
+        Operator::I64AtomicRmwAnd { ref memarg } => (And, 8, I64, memarg),
+        Operator::I32AtomicRmw8UAnd { ref memarg } => (And, 1, I32, memarg),
+        Operator::I32AtomicRmw16UAnd { ref memarg } => (And, 2, I32, memarg),
+        Operator::I64AtomicRmw8UAnd { ref memarg } => (And, 1, I64, memarg),
+        Operator::I64AtomicRmw16UAnd { ref memarg } => (And, 2, I64, memarg),
+        Operator::I64AtomicRmw32UAnd { ref memarg } => (And, 4, I64, memarg),
+        Operator::I32AtomicRmwOr { ref memarg } => (Or, 4, I32, memarg),
+        Operator::I64AtomicRmwOr { ref memarg } => (Or, 8, I64, memarg),
+        Operator::I32AtomicRmw8UOr { ref memarg } => (Or, 1, I32, memarg),
+        Operator::I32AtomicRmw16UOr { ref memarg } => (Or, 2, I32, memarg),
+        Operator::I64AtomicRmw8UOr { ref memarg } => (Or, 1, I64, memarg),
+        Operator::I64AtomicRmw16UOr { ref memarg } => (Or, 2, I64, memarg),
+        Operator::I64AtomicRmw32UOr { ref memarg } => (Or, 4, I64, memarg),
+        Operator::I32AtomicRmwXor { ref memarg } => (Xor, 4, I32, memarg),
+        Operator::I64AtomicRmwXor { ref memarg } => (Xor, 8, I64, memarg),
+        Operator::I32AtomicRmw8UXor { ref memarg } => (Xor, 1, I32, memarg),
+        Operator::I32AtomicRmw16UXor { ref memarg } => (Xor, 2, I32, memarg),
+        Operator::I64AtomicRmw8UXor { ref memarg } => (Xor, 1, I64, memarg),
+        Operator::I64AtomicRmw16UXor { ref memarg } => (Xor, 2, I64, memarg),
+        Operator::I64AtomicRmw32UXor { ref memarg } => (Xor, 4, I64, memarg),
+        Operator::I32AtomicRmwXchg { ref memarg } => (Xchg, 4, I32, memarg),
+        Operator::I64AtomicRmwXchg { ref memarg } => (Xchg, 8, I64, memarg),
+        Operator::I32AtomicRmw8UXchg { ref memarg } => (Xchg, 1, I32, memarg),
+        Operator::I32AtomicRmw16UXchg { ref memarg } => (Xchg, 2, I32, memarg),
+        Operator::I64AtomicRmw8UXchg { ref memarg } => (Xchg, 1, I64, memarg),
+        Operator::I64AtomicRmw16UXchg { ref memarg } => (Xchg, 2, I64, memarg),
+        Operator::I64AtomicRmw32UXchg { ref memarg } => (Xchg, 4, I64, memarg),
+        Operator::I32AtomicRmwCmpxchg { ref memarg } => (Cmpxchg, 4, I32, memarg),
+        Operator::I64AtomicRmwCmpxchg { ref memarg } => (Cmpxchg, 8, I64, memarg),
+        Operator::I32AtomicRmw8UCmpxchg { ref memarg } => (Cmpxchg, 1, I32, memarg),
+        Operator::I32AtomicRmw16UCmpxchg { ref memarg } => (Cmpxchg, 2, I32, memarg),
+        Operator::I64AtomicRmw8UCmpxchg { ref memarg } => (Cmpxchg, 1, I64, memarg),
+        Operator::I64AtomicRmw16UCmpxchg { ref memarg } => (Cmpxchg, 2, I64, memarg),
+        Operator::I64AtomicRmw32UCmpxchg { ref memarg } => (Cmpxchg, 4, I64, memarg),
+        _ => return None,
+    })
+}
+
 fn is_dword(n: usize) -> bool {
     n == 4
 }
@@ -5121,6 +5918,26 @@ fn type_to_wp_type(ty: Type) -> WpType {
         Type::I64 => WpType::I64,
         Type::F32 => WpType::F32,
         Type::F64 => WpType::F64,
+        Type::ExternRef | Type::FuncRef => WpType::I64,
+    }
+}
+
+/// Whether `ty` is a reference type.
+fn is_ref_type(ty: WpType) -> bool {
+    match ty {
+        WpType::AnyRef | WpType::AnyFunc => true,
+        _ => false,
+    }
+}
+
+/// The type of a value on the value stack.
+///
+/// References are handles into the runtime's registries,
+/// so they are handled like an `i64`.
+fn value_type(ty: WpType) -> WpType {
+    match ty {
+        WpType::AnyRef | WpType::AnyFunc => WpType::I64,
+        _ => ty,
     }
 }
 
@@ -5138,17 +5955,57 @@ unsafe extern "C" fn invoke_import(
     CONSTRUCT_STACK_AND_CALL_NATIVE(stack_top, stack_base, _vmctx, import)
 }
 
-#[repr(u64)]
+/// Where a table lives in the vmctx: its index in the local or imported
+/// table array, shifted left by one, with the lowest bit set for an import.
+#[repr(transparent)]
 #[derive(Copy, Clone, Debug)]
-enum CallIndirectLocalOrImport {
-    Local,
-    Import,
+struct TableLocation(u64);
+
+impl TableLocation {
+    fn new(module_info: &ModuleInfo, table_index: u32) -> Result<Self, CodegenError> {
+        let table_index = TableIndex::new(table_index as usize);
+        if table_index.index() >= module_info.imported_tables.len() + module_info.tables.len() {
+            return Err(CodegenError {
+                message: "table index out of bounds",
+            });
+        }
+        Ok(match table_index.local_or_import(module_info) {
+            LocalOrImport::Local(local_table_index) => {
+                TableLocation((local_table_index.index() as u64) << 1)
+            }
+            LocalOrImport::Import(imported_table_index) => {
+                TableLocation((imported_table_index.index() as u64) << 1 | 1)
+            }
+        })
+    }
+
+    unsafe fn local_table<'a>(self, vmctx: *mut vm::Ctx) -> &'a LocalTable {
+        let vmctx = &*(vmctx as *mut vm::InternalCtx);
+        let tables = if self.0 & 1 == 0 {
+            vmctx.tables
+        } else {
+            vmctx.imported_tables
+        };
+        &**tables.add((self.0 >> 1) as usize)
+    }
+}
+
+/// Compiled code calls this when it sees that the instance has been
+/// interrupted.
+unsafe extern "C" fn raise_interrupted() -> ! {
+    trap::raise(RuntimeError::trap(TrapCode::Interrupted))
+}
+
+/// Compiled code calls this when it sees that the epoch deadline of the
+/// instance has been reached.
+unsafe extern "C" fn raise_deadline_exceeded() -> ! {
+    trap::raise(RuntimeError::trap(TrapCode::DeadlineExceeded))
 }
 
 #[allow(clippy::cast_ptr_alignment)]
 unsafe extern "C" fn call_indirect(
     sig_index: usize,
-    local_or_import: CallIndirectLocalOrImport,
+    location: TableLocation,
     mut stack_top: *mut u8,
     stack_base: *mut u8,
     vmctx: *mut vm::Ctx,
@@ -5158,15 +6015,10 @@ unsafe extern "C" fn call_indirect(
     stack_top = stack_top.offset(8);
     assert!(stack_top as usize <= stack_base as usize);
 
-    let table: &LocalTable = match local_or_import {
-        CallIndirectLocalOrImport::Local => &*(*(*(vmctx as *mut vm::InternalCtx)).tables),
-        CallIndirectLocalOrImport::Import => {
-            &*(*(*(vmctx as *mut vm::InternalCtx)).imported_tables)
-        }
-    };
+    let table = location.local_table(vmctx);
     if elem_index >= table.count as usize {
         eprintln!("element index out of bounds");
-        protect_unix::trigger_trap();
+        trap::raise(RuntimeError::trap(TrapCode::TableOutOfBounds));
     }
     let anyfunc = &*(table.base as *mut vm::Anyfunc).offset(elem_index as isize);
     let dynamic_sigindex = *(*(vmctx as *mut vm::InternalCtx))
@@ -5175,12 +6027,12 @@ unsafe extern "C" fn call_indirect(
 
     if anyfunc.func.is_null() {
         eprintln!("null anyfunc");
-        protect_unix::trigger_trap();
+        trap::raise(RuntimeError::trap(TrapCode::IndirectCallToNull));
     }
 
     if anyfunc.sig_id.0 != dynamic_sigindex.0 {
         eprintln!("signature mismatch");
-        protect_unix::trigger_trap();
+        trap::raise(RuntimeError::trap(TrapCode::BadSignature));
     }
 
     CONSTRUCT_STACK_AND_CALL_NATIVE(stack_top, stack_base, anyfunc.ctx, anyfunc.func)
@@ -5213,14 +6065,18 @@ unsafe extern "C" fn _memory_size(
         MemoryKind::StaticLocal => {
             vmcalls::local_static_memory_size(&*vmctx, LocalMemoryIndex::new(index))
         }
-        MemoryKind::SharedLocal => unreachable!(),
+        MemoryKind::SharedLocal => {
+            vmcalls::local_shared_static_memory_size(&*vmctx, LocalMemoryIndex::new(index))
+        }
         MemoryKind::DynamicImport => {
             vmcalls::imported_dynamic_memory_size(&*vmctx, ImportedMemoryIndex::new(index))
         }
         MemoryKind::StaticImport => {
             vmcalls::imported_static_memory_size(&*vmctx, ImportedMemoryIndex::new(index))
         }
-        MemoryKind::SharedImport => unreachable!(),
+        MemoryKind::SharedImport => {
+            vmcalls::imported_shared_static_memory_size(&*vmctx, ImportedMemoryIndex::new(index))
+        }
     };
     ret.0 as u32 as u64
 }
@@ -5244,7 +6100,11 @@ unsafe extern "C" fn _memory_grow(
         MemoryKind::StaticLocal => {
             vmcalls::local_static_memory_grow(&mut *vmctx, LocalMemoryIndex::new(index), pages)
         }
-        MemoryKind::SharedLocal => unreachable!(),
+        MemoryKind::SharedLocal => vmcalls::local_shared_static_memory_grow(
+            &mut *vmctx,
+            LocalMemoryIndex::new(index),
+            pages,
+        ),
         MemoryKind::DynamicImport => vmcalls::imported_dynamic_memory_grow(
             &mut *vmctx,
             ImportedMemoryIndex::new(index),
@@ -5255,7 +6115,286 @@ unsafe extern "C" fn _memory_grow(
             ImportedMemoryIndex::new(index),
             pages,
         ),
-        MemoryKind::SharedImport => unreachable!(),
+        MemoryKind::SharedImport => vmcalls::imported_shared_static_memory_grow(
+            &mut *vmctx,
+            ImportedMemoryIndex::new(index),
+            pages,
+        ),
     };
     ret as u32 as u64
 }
+
+/// Resolves the address of an atomic access to the linear memory,
+/// trapping if it's misaligned or out of bounds.
+unsafe fn atomic_address(
+    descriptor: u64,
+    addr: u32,
+    vmctx: *mut vm::Ctx,
+    memory_base: *mut u8,
+) -> *mut u8 {
+    let offset = descriptor as u32 as usize;
+    let width = (descriptor >> 32) as u8 as usize;
+    let effective_address = addr as usize + offset;
+
+    let memory_size = (*vmctx).memory(0).size().bytes().0;
+    if effective_address % width != 0 || effective_address + width > memory_size {
+        trap::raise(RuntimeError::trap(TrapCode::MemoryOutOfBounds));
+    }
+
+    // The upper 16 bits of the memory base hold the memory size.
+    ((memory_base as usize & 0xffff_ffff_ffff) + effective_address) as *mut u8
+}
+
+#[allow(clippy::cast_ptr_alignment)]
+unsafe extern "C" fn _atomic_rmw(
+    _unused1: usize,
+    _unused2: usize,
+    stack_top: *mut u8,
+    stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+
+    // The operands are on the stack in reverse order,
+    // starting with the descriptor.
+    let args = stack_top as *const u64;
+    let nargs = (stack_base as usize - stack_top as usize) / 8;
+    let descriptor = *args;
+    let op: AtomicRmwOp = ::std::mem::transmute((descriptor >> 40) as u32);
+
+    let addr = *args.add(nargs - 1) as u32;
+    let ptr = atomic_address(descriptor, addr, vmctx, memory_base);
+    let (operand, replacement) = match nargs {
+        2 => (0, 0),
+        3 => (*args.add(1), 0),
+        4 => (*args.add(2), *args.add(1)),
+        _ => unreachable!(),
+    };
+
+    vmcalls::atomic_rmw(
+        op,
+        (descriptor >> 32) as u8 as u32,
+        ptr,
+        operand,
+        replacement,
+    )
+}
+
+#[allow(clippy::cast_ptr_alignment)]
+unsafe extern "C" fn _atomic_wait(
+    _unused1: usize,
+    _unused2: usize,
+    stack_top: *mut u8,
+    stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+    assert_eq!(stack_base as usize - stack_top as usize, 32);
+
+    let args = stack_top as *const u64;
+    let descriptor = *args;
+    let timeout = *args.add(1) as i64;
+    let expected = *args.add(2);
+    let addr = *args.add(3) as u32;
+
+    // Waiting is only allowed on shared memories.
+    if !(*vmctx).memory(0).descriptor().shared {
+        trap::raise(RuntimeError::trap(TrapCode::Unknown));
+    }
+
+    let width = (descriptor >> 32) as u8 as u32;
+    let expected = if width == 4 {
+        u64::from(expected as u32)
+    } else {
+        expected
+    };
+    let ptr = atomic_address(descriptor, addr, vmctx, memory_base);
+
+    u64::from(vmcalls::atomic_wait(width, ptr, expected, timeout))
+}
+
+#[allow(clippy::cast_ptr_alignment)]
+unsafe extern "C" fn _atomic_notify(
+    _unused1: usize,
+    _unused2: usize,
+    stack_top: *mut u8,
+    stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+    assert_eq!(stack_base as usize - stack_top as usize, 24);
+
+    let args = stack_top as *const u64;
+    let descriptor = *args;
+    let count = *args.add(1) as u32;
+    let addr = *args.add(2) as u32;
+
+    let ptr = atomic_address(descriptor, addr, vmctx, memory_base);
+
+    u64::from(vmcalls::atomic_notify(ptr, count))
+}
+
+#[repr(u64)]
+#[derive(Copy, Clone, Debug)]
+enum BulkMemoryOp {
+    MemoryCopy,
+    MemoryFill,
+    MemoryInit,
+    DataDrop,
+    TableInit,
+    ElemDrop,
+}
+
+unsafe extern "C" fn _bulk_memory(
+    op: BulkMemoryOp,
+    _unused: usize,
+    stack_top: *mut u8,
+    stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    _memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+
+    // The operands are on the stack in reverse order, so
+    // this is the `i`th operand, counting from the first.
+    let nargs = (stack_base as usize - stack_top as usize) / 8;
+    let arg = |i: usize| *(stack_top as *const u64).add(nargs - 1 - i) as u32;
+
+    let ctx = &mut *vmctx;
+    let memory_index = MemoryIndex::new(0);
+    let table_index = TableIndex::new(0);
+    // The segment, if any, is passed after the other operands.
+    let failed = match op {
+        BulkMemoryOp::MemoryCopy => vmcalls::memory_copy(ctx, memory_index, arg(0), arg(1), arg(2)),
+        BulkMemoryOp::MemoryFill => vmcalls::memory_fill(ctx, memory_index, arg(0), arg(1), arg(2)),
+        BulkMemoryOp::MemoryInit => {
+            vmcalls::memory_init(ctx, memory_index, arg(3), arg(0), arg(1), arg(2))
+        }
+        BulkMemoryOp::DataDrop => {
+            vmcalls::data_drop(ctx, arg(0));
+            0
+        }
+        BulkMemoryOp::TableInit => {
+            vmcalls::table_init(ctx, table_index, arg(3), arg(0), arg(1), arg(2))
+        }
+        BulkMemoryOp::ElemDrop => {
+            vmcalls::elem_drop(ctx, arg(0));
+            0
+        }
+    };
+
+    if failed != 0 {
+        let code = match op {
+            BulkMemoryOp::TableInit => TrapCode::TableOutOfBounds,
+            _ => TrapCode::MemoryOutOfBounds,
+        };
+        trap::raise(RuntimeError::trap(code));
+    }
+    0
+}
+
+unsafe extern "C" fn table_get(
+    table_index: usize,
+    location: TableLocation,
+    stack_top: *mut u8,
+    _stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    _memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+
+    let index = *(stack_top as *mut u32);
+    check_table_bounds(location, vmctx, index);
+    vmcalls::table_get(&mut *vmctx, TableIndex::new(table_index), index)
+}
+
+unsafe extern "C" fn table_set(
+    table_index: usize,
+    location: TableLocation,
+    stack_top: *mut u8,
+    _stack_base: *mut u8,
+    vmctx: *mut vm::Ctx,
+    _memory_base: *mut u8,
+) -> u64 {
+    use wasmer_runtime_core::vmcalls;
+
+    let value = *(stack_top as *mut u64);
+    let index = *(stack_top.offset(8) as *mut u32);
+    check_table_bounds(location, vmctx, index);
+    vmcalls::table_set(&mut *vmctx, TableIndex::new(table_index), index, value);
+    0
+}
+
+unsafe fn check_table_bounds(location: TableLocation, vmctx: *mut vm::Ctx, index: u32) {
+    if index as usize >= location.local_table(vmctx).count {
+        trap::raise(RuntimeError::trap(TrapCode::TableOutOfBounds));
+    }
+}
+
+/// A saturating float-to-int conversion, named after the result type,
+/// the operand type and whether the result is signed.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug)]
+enum TruncSatOp {
+    I32F32S,
+    I32F32U,
+    I32F64S,
+    I32F64U,
+    I64F32S,
+    I64F32U,
+    I64F64S,
+    I64F64U,
+}
+
+/// Truncate a float toward zero, saturating at the bounds of `$int`
+/// and turning NaN into zero. The bounds of the integer types are
+/// powers of two, so `$min` and `$max` are exact: `$min` is the
+/// smallest value of `$int` and `$max` is one past the largest.
+macro_rules! trunc_sat {
+    ($value:expr, $int:ty, $min:expr, $max:expr) => {{
+        let value: f64 = $value.trunc();
+        if value.is_nan() {
+            0
+        } else if value < $min {
+            <$int>::min_value()
+        } else if value >= $max {
+            <$int>::max_value()
+        } else {
+            value as $int
+        }
+    }};
+}
+
+unsafe extern "C" fn _trunc_sat(
+    _unused1: usize,
+    _unused2: usize,
+    stack_top: *mut u8,
+    _stack_base: *mut u8,
+    _vmctx: *mut vm::Ctx,
+    _memory_base: *mut u8,
+) -> u64 {
+    use self::TruncSatOp::*;
+
+    // The descriptor is on top of the operand.
+    let args = stack_top as *const u64;
+    let op: TruncSatOp = ::std::mem::transmute(*args);
+    let bits = *args.add(1);
+
+    // Every `f32` is exactly representable as an `f64`.
+    let value = match op {
+        I32F32S | I32F32U | I64F32S | I64F32U => f64::from(f32::from_bits(bits as u32)),
+        I32F64S | I32F64U | I64F64S | I64F64U => f64::from_bits(bits),
+    };
+
+    match op {
+        I32F32S | I32F64S => trunc_sat!(value, i32, -2147483648.0, 2147483648.0) as u32 as u64,
+        I32F32U | I32F64U => trunc_sat!(value, u32, 0.0, 4294967296.0) as u64,
+        I64F32S | I64F64S => {
+            trunc_sat!(value, i64, -9223372036854775808.0, 9223372036854775808.0) as u64
+        }
+        I64F32U | I64F64U => trunc_sat!(value, u64, 0.0, 18446744073709551616.0),
+    }
+}
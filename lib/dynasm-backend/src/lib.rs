@@ -19,15 +19,15 @@ extern crate byteorder;
 mod codegen;
 mod codegen_x64;
 mod parse;
-mod protect_unix;
 mod stack;
 
 use crate::codegen::{CodegenError, ModuleCodeGenerator};
 use crate::parse::LoadError;
 use wasmer_runtime_core::{
-    backend::{sys::Memory, Backend, CacheGen, Compiler, Token},
+    backend::{sys::Memory, Backend, CacheGen, Capabilities, Compiler, CompilerConfig, Token},
     cache::{Artifact, Error as CacheError},
     error::{CompileError, CompileResult},
+    metering,
     module::{ModuleInfo, ModuleInner},
 };
 
@@ -51,9 +51,18 @@ impl SinglePassCompiler {
 }
 
 impl Compiler for SinglePassCompiler {
-    fn compile(&self, wasm: &[u8], _: Token) -> CompileResult<ModuleInner> {
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> CompileResult<ModuleInner> {
+        let costs = compiler_config
+            .metering
+            .map(|config| metering::function_costs(wasm, config.cost_function))
+            .transpose()?;
         let mut mcg = codegen_x64::X64ModuleCodeGenerator::new();
-        let info = parse::read_module(wasm, Backend::Dynasm, &mut mcg)?;
+        let info = parse::read_module(wasm, Backend::Dynasm, costs, &mut mcg)?;
         let (ec, resolver) = mcg.finalize(&info)?;
         Ok(ModuleInner {
             cache_gen: Box::new(Placeholder),
@@ -68,6 +77,20 @@ impl Compiler for SinglePassCompiler {
             "the dynasm backend doesn't support caching yet".to_string(),
         ))
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            target_archs: &["x86_64"],
+            threads: cfg!(feature = "threads"),
+            reference_types: cfg!(feature = "reference-types"),
+            bulk_memory: true,
+            multi_value: false,
+            backtraces: false,
+            jit_debug: false,
+            caching: false,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
 }
 
 impl From<CodegenError> for CompileError {
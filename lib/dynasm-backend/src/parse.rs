@@ -1,15 +1,17 @@
 use crate::codegen::{CodegenError, FunctionCodeGenerator, ModuleCodeGenerator};
 use wasmer_runtime_core::{
     backend::{Backend, FuncResolver, ProtectedCaller},
+    memory::memory_style,
+    metering::FunctionCosts,
     module::{
-        DataInitializer, ExportIndex, ImportName, ModuleInfo, StringTable, StringTableBuilder,
-        TableInitializer,
+        read_func_names, DataInitializer, ExportIndex, ImportName, ModuleInfo, StringTable,
+        StringTableBuilder, TableInitializer,
     },
     structures::{Map, TypedIndex},
     types::{
         ElementType, FuncIndex, FuncSig, GlobalDescriptor, GlobalIndex, GlobalInit,
-        ImportedGlobalIndex, Initializer, MemoryDescriptor, MemoryIndex, SigIndex, TableDescriptor,
-        TableIndex, Type, Value,
+        ImportedGlobalIndex, Initializer, LocalFuncIndex, MemoryDescriptor, MemoryIndex, SigIndex,
+        TableDescriptor, TableIndex, Type, Value,
     },
     units::Pages,
 };
@@ -42,10 +44,10 @@ fn validate(bytes: &[u8]) -> Result<(), LoadError> {
         bytes,
         Some(wasmparser::ValidatingParserConfig {
             operator_config: wasmparser::OperatorValidatorConfig {
-                enable_threads: false,
-                enable_reference_types: false,
+                enable_threads: cfg!(feature = "threads"),
+                enable_reference_types: cfg!(feature = "reference-types"),
                 enable_simd: false,
-                enable_bulk_memory: false,
+                enable_bulk_memory: true,
             },
             mutable_global_imports: false,
         }),
@@ -69,6 +71,7 @@ pub fn read_module<
 >(
     wasm: &[u8],
     backend: Backend,
+    costs: Option<Map<LocalFuncIndex, FunctionCosts>>,
     mcg: &mut MCG,
 ) -> Result<ModuleInfo, LoadError> {
     validate(wasm)?;
@@ -87,14 +90,22 @@ pub fn read_module<
         data_initializers: Vec::new(),
         elem_initializers: Vec::new(),
 
+        passive_data: Default::default(),
+        passive_elements: Default::default(),
+
         start_func: None,
 
         func_assoc: Map::new(),
         signatures: Map::new(),
         backend: backend,
+        memory_style: memory_style(),
+        initial_points: None,
 
         namespace_table: StringTable::new(),
         name_table: StringTable::new(),
+
+        custom_sections: Default::default(),
+        func_names: Default::default(),
     };
 
     let mut reader = ModuleReader::new(wasm)?;
@@ -140,9 +151,8 @@ pub fn read_module<
                             mcg.feed_import_function()?;
                         }
                         ImportSectionEntryType::Table(table_ty) => {
-                            assert_eq!(table_ty.element_type, WpType::AnyFunc);
                             let table_desc = TableDescriptor {
-                                element: ElementType::Anyfunc,
+                                element: wp_type_to_element_type(table_ty.element_type)?,
                                 minimum: table_ty.limits.initial,
                                 maximum: table_ty.limits.maximum,
                             };
@@ -189,7 +199,7 @@ pub fn read_module<
                     let table_ty = table_ty?;
 
                     let table_desc = TableDescriptor {
-                        element: ElementType::Anyfunc,
+                        element: wp_type_to_element_type(table_ty.element_type)?,
                         minimum: table_ty.limits.initial,
                         maximum: table_ty.limits.maximum,
                     };
@@ -259,7 +269,7 @@ pub fn read_module<
             SectionCode::Element => {
                 let element_reader = section.get_element_section_reader()?;
 
-                for element in element_reader {
+                for (segment_index, element) in element_reader.into_iter().enumerate() {
                     let Element { kind, items } = element?;
 
                     match kind {
@@ -285,11 +295,14 @@ pub fn read_module<
                             info.elem_initializers.push(table_init);
                         }
                         ElementKind::Passive(_ty) => {
-                            return Err(BinaryReaderError {
-                                message: "passive tables are not yet supported",
-                                offset: -1isize as usize,
-                            }
-                            .into());
+                            let items_reader = items.get_items_reader()?;
+
+                            let elements: Vec<_> = items_reader
+                                .into_iter()
+                                .map(|res| res.map(|index| FuncIndex::new(index as usize)))
+                                .collect::<Result<_, _>>()?;
+
+                            info.passive_elements.insert(segment_index as u32, elements);
                         }
                     }
                 }
@@ -326,6 +339,9 @@ pub fn read_module<
                         let (count, ty) = local?;
                         fcg.feed_local(ty, count as usize)?;
                     }
+                    if let Some(ref costs) = costs {
+                        fcg.feed_costs(costs[LocalFuncIndex::new(i as usize)].clone())?;
+                    }
                     fcg.begin_body()?;
                     for op in item.get_operators_reader()? {
                         let op = op?;
@@ -337,7 +353,7 @@ pub fn read_module<
             SectionCode::Data => {
                 let data_reader = section.get_data_section_reader()?;
 
-                for data in data_reader {
+                for (segment_index, data) in data_reader.into_iter().enumerate() {
                     let Data { kind, data } = data?;
 
                     match kind {
@@ -357,17 +373,27 @@ pub fn read_module<
                             info.data_initializers.push(data_init);
                         }
                         DataKind::Passive => {
-                            return Err(BinaryReaderError {
-                                message: "passive memories are not yet supported",
-                                offset: -1isize as usize,
-                            }
-                            .into());
+                            info.passive_data
+                                .insert(segment_index as u32, data.to_vec());
                         }
                     }
                 }
             }
             SectionCode::DataCount => {}
-            SectionCode::Custom { .. } => {}
+            SectionCode::Custom { name, .. } => {
+                let mut reader = section.get_binary_reader();
+                let len = reader.bytes_remaining();
+                let contents = reader.read_bytes(len)?;
+
+                if name == "name" {
+                    info.func_names = read_func_names(contents);
+                }
+
+                info.custom_sections
+                    .entry(name.to_string())
+                    .or_insert_with(Vec::new)
+                    .push(contents.to_vec());
+            }
         }
     }
 }
@@ -378,6 +404,8 @@ pub fn wp_type_to_type(ty: WpType) -> Result<Type, BinaryReaderError> {
         WpType::I64 => Type::I64,
         WpType::F32 => Type::F32,
         WpType::F64 => Type::F64,
+        WpType::AnyRef => Type::ExternRef,
+        WpType::AnyFunc => Type::FuncRef,
         WpType::V128 => {
             return Err(BinaryReaderError {
                 message: "the wasmer llvm backend does not yet support the simd extension",
@@ -394,9 +422,24 @@ pub fn type_to_wp_type(ty: Type) -> WpType {
         Type::I64 => WpType::I64,
         Type::F32 => WpType::F32,
         Type::F64 => WpType::F64,
+        // References are `i64` handles in the generated code.
+        Type::ExternRef | Type::FuncRef => WpType::I64,
     }
 }
 
+fn wp_type_to_element_type(ty: WpType) -> Result<ElementType, BinaryReaderError> {
+    Ok(match ty {
+        WpType::AnyFunc => ElementType::Anyfunc,
+        WpType::AnyRef => ElementType::ExternRef,
+        _ => {
+            return Err(BinaryReaderError {
+                message: "that type is not supported as a table element type",
+                offset: -1isize as usize,
+            });
+        }
+    })
+}
+
 fn func_type_to_func_sig(func_ty: FuncType) -> Result<FuncSig, BinaryReaderError> {
     assert_eq!(func_ty.form, WpType::Func);
 
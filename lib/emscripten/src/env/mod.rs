@@ -10,7 +10,9 @@ pub use self::unix::*;
 #[cfg(windows)]
 pub use self::windows::*;
 
+use crate::utils::{copy_cstr_into_wasm, read_string_from_wasm};
 use crate::{allocate_on_stack, EmscriptenData};
+use std::ffi::CString;
 use std::os::raw::c_int;
 use wasmer_runtime_core::vm::Ctx;
 
@@ -42,27 +44,145 @@ pub(crate) fn get_emscripten_data(ctx: &mut Ctx) -> &mut EmscriptenData {
     unsafe { &mut *(ctx.data as *mut EmscriptenData) }
 }
 
+/// The environment variables that the running program sees in place
+/// of those of the host process, if it has been given its own.
+fn guest_env<'a>(ctx: &Ctx) -> Option<&'a mut Vec<(String, String)>> {
+    if ctx.data.is_null() {
+        return None;
+    }
+    let data = unsafe { &mut *(ctx.data as *mut EmscriptenData) };
+    data.env.as_mut()
+}
+
+/// `getenv`, if the program has its own environment variables.
+pub(crate) fn guest_getenv(ctx: &mut Ctx, name: u32) -> Option<u32> {
+    let env = guest_env(ctx)?;
+    let name = read_string_from_wasm(ctx.memory(0), name);
+    let value = get_var(env, &name).and_then(|value| CString::new(value).ok());
+    Some(match value {
+        Some(value) => unsafe { copy_cstr_into_wasm(ctx, value.as_ptr()) },
+        None => 0,
+    })
+}
+
+/// `setenv`, if the program has its own environment variables.
+pub(crate) fn guest_setenv(ctx: &mut Ctx, name: u32, value: u32, overwrite: bool) -> Option<c_int> {
+    let env = guest_env(ctx)?;
+    let name = read_string_from_wasm(ctx.memory(0), name);
+    let value = read_string_from_wasm(ctx.memory(0), value);
+    Some(set_var(env, name, value, overwrite))
+}
+
+/// `putenv`, if the program has its own environment variables. A
+/// string without a value unsets the variable.
+pub(crate) fn guest_putenv(ctx: &mut Ctx, string: u32) -> Option<c_int> {
+    let env = guest_env(ctx)?;
+    let string = read_string_from_wasm(ctx.memory(0), string);
+    put_var(env, &string);
+    Some(0)
+}
+
+/// `unsetenv`, if the program has its own environment variables.
+pub(crate) fn guest_unsetenv(ctx: &mut Ctx, name: u32) -> Option<c_int> {
+    let env = guest_env(ctx)?;
+    let name = read_string_from_wasm(ctx.memory(0), name);
+    unset_var(env, &name);
+    Some(0)
+}
+
+/// The value of the variable `name` of `env`.
+fn get_var<'a>(env: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    env.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Set the variable `name` of `env`, as `setenv` does.
+fn set_var(env: &mut Vec<(String, String)>, name: String, value: String, overwrite: bool) -> c_int {
+    if name.is_empty() || name.contains('=') {
+        return -1;
+    }
+    match env.iter_mut().find(|(key, _)| *key == name) {
+        Some(entry) => {
+            if overwrite {
+                entry.1 = value;
+            }
+        }
+        None => env.push((name, value)),
+    }
+    0
+}
+
+/// Set or unset a variable of `env` from a `KEY=value` or `KEY`
+/// string, as `putenv` does.
+fn put_var(env: &mut Vec<(String, String)>, string: &str) {
+    let mut parts = string.splitn(2, '=');
+    let name = parts.next().unwrap_or("");
+    env.retain(|(key, _)| key != name);
+    if let Some(value) = parts.next() {
+        env.push((name.to_string(), value.to_string()));
+    }
+}
+
+/// Remove the variable `name` from `env`, as `unsetenv` does.
+fn unset_var(env: &mut Vec<(String, String)>, name: &str) {
+    env.retain(|(key, _)| key != name);
+}
+
 pub fn _getpagesize(_ctx: &mut Ctx) -> u32 {
     debug!("emscripten::_getpagesize");
     16384
 }
 
+/// The size of the pool of `KEY=value` strings that `entries` go in,
+/// and the number of pointers in the array pointing into it. Both are
+/// as large as emscripten makes them, or larger if `entries` need it.
+fn environment_size(entries: Option<&[String]>) -> (u32, u32) {
+    const MAX_ENV_VALUES: u32 = 64;
+    const TOTAL_ENV_SIZE: u32 = 1024;
+
+    match entries {
+        Some(entries) => (
+            TOTAL_ENV_SIZE.max(entries.iter().map(|entry| entry.len() as u32).sum()),
+            MAX_ENV_VALUES.max(entries.len() as u32 + 1),
+        ),
+        None => (TOTAL_ENV_SIZE, MAX_ENV_VALUES),
+    }
+}
+
 #[allow(clippy::cast_ptr_alignment)]
 pub fn ___build_environment(ctx: &mut Ctx, environ: c_int) {
     debug!("emscripten::___build_environment {}", environ);
-    const MAX_ENV_VALUES: u32 = 64;
-    const TOTAL_ENV_SIZE: u32 = 1024;
+
+    // The program's own variables, as `KEY=value` strings.
+    let entries: Option<Vec<String>> = guest_env(ctx).map(|env| {
+        env.iter()
+            .map(|(key, value)| format!("{}={}\0", key, value))
+            .collect()
+    });
+    let (pool_size, max_values) = environment_size(entries.as_ref().map(Vec::as_slice));
+
     let environment = emscripten_memory_pointer!(ctx.memory(0), environ) as *mut c_int;
     unsafe {
-        let (pool_offset, _pool_slice): (u32, &mut [u8]) =
-            allocate_on_stack(ctx, TOTAL_ENV_SIZE as u32);
-        let (env_offset, _env_slice): (u32, &mut [u8]) =
-            allocate_on_stack(ctx, (MAX_ENV_VALUES * 4) as u32);
+        let (pool_offset, _pool_slice): (u32, &mut [u8]) = allocate_on_stack(ctx, pool_size);
+        let (env_offset, _env_slice): (u32, &mut [u8]) = allocate_on_stack(ctx, max_values * 4);
         let env_ptr = emscripten_memory_pointer!(ctx.memory(0), env_offset) as *mut c_int;
         let mut _pool_ptr = emscripten_memory_pointer!(ctx.memory(0), pool_offset) as *mut c_int;
         *env_ptr = pool_offset as i32;
         *environment = env_offset as i32;
 
+        if let Some(entries) = entries {
+            let pool = emscripten_memory_pointer!(ctx.memory(0), pool_offset) as *mut u8;
+            let mut pool_len = 0;
+            for (index, entry) in entries.iter().enumerate() {
+                let entry_ptr = pool.add(pool_len as usize);
+                entry_ptr.copy_from_nonoverlapping(entry.as_ptr(), entry.len());
+                *env_ptr.add(index) = (pool_offset + pool_len) as i32;
+                pool_len += entry.len() as u32;
+            }
+            *env_ptr.add(entries.len()) = 0;
+        }
+
         // *env_ptr = 0;
     };
     // unsafe {
@@ -75,3 +195,70 @@ pub fn ___assert_fail(_ctx: &mut Ctx, _a: c_int, _b: c_int, _c: c_int, _d: c_int
     // TODO: Implement like emscripten expects regarding memory/page size
     // TODO raise an error
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_var() {
+        let vars = env(&[("HOME", "/home/wasm"), ("EMPTY", "")]);
+        assert_eq!(get_var(&vars, "HOME"), Some("/home/wasm"));
+        assert_eq!(get_var(&vars, "EMPTY"), Some(""));
+        assert_eq!(get_var(&vars, "PATH"), None);
+    }
+
+    #[test]
+    fn test_set_var() {
+        let mut vars = env(&[("HOME", "/home/wasm")]);
+        assert_eq!(set_var(&mut vars, "HOME".into(), "/".into(), false), 0);
+        assert_eq!(vars, env(&[("HOME", "/home/wasm")]));
+        assert_eq!(set_var(&mut vars, "HOME".into(), "/".into(), true), 0);
+        assert_eq!(vars, env(&[("HOME", "/")]));
+        assert_eq!(set_var(&mut vars, "LANG".into(), "C".into(), false), 0);
+        assert_eq!(vars, env(&[("HOME", "/"), ("LANG", "C")]));
+
+        assert_eq!(set_var(&mut vars, "".into(), "C".into(), true), -1);
+        assert_eq!(set_var(&mut vars, "A=B".into(), "C".into(), true), -1);
+        assert_eq!(vars, env(&[("HOME", "/"), ("LANG", "C")]));
+    }
+
+    #[test]
+    fn test_put_var() {
+        let mut vars = env(&[("HOME", "/home/wasm")]);
+        put_var(&mut vars, "LANG=C");
+        put_var(&mut vars, "HOME=/=/");
+        assert_eq!(vars, env(&[("LANG", "C"), ("HOME", "/=/")]));
+        put_var(&mut vars, "LANG");
+        assert_eq!(vars, env(&[("HOME", "/=/")]));
+    }
+
+    #[test]
+    fn test_unset_var() {
+        let mut vars = env(&[("HOME", "/home/wasm"), ("LANG", "C")]);
+        unset_var(&mut vars, "PATH");
+        unset_var(&mut vars, "HOME");
+        assert_eq!(vars, env(&[("LANG", "C")]));
+    }
+
+    #[test]
+    fn test_environment_size() {
+        // Without its own variables, the program gets emscripten's sizes.
+        assert_eq!(environment_size(None), (1024, 64));
+        assert_eq!(environment_size(Some(&["A=B\0".to_string()])), (1024, 64));
+
+        // Its own variables get as much room as they need, along with
+        // the pointer that ends the array.
+        let long = vec![format!("LONG={}\0", "x".repeat(2000))];
+        assert_eq!(environment_size(Some(&long)), (2006, 64));
+        let many: Vec<_> = (0..100).map(|i| format!("VAR{}=\0", i)).collect();
+        let pool_size = many.iter().map(|entry| entry.len() as u32).sum::<u32>();
+        assert_eq!(environment_size(Some(&many)), (pool_size.max(1024), 101));
+    }
+}
@@ -7,7 +7,7 @@ use std::ffi::CStr;
 use std::mem;
 use std::os::raw::c_char;
 
-use crate::env::call_malloc;
+use crate::env::{call_malloc, guest_getenv, guest_putenv, guest_setenv, guest_unsetenv};
 use crate::utils::{copy_cstr_into_wasm, copy_terminated_array_of_cstrs};
 use wasmer_runtime_core::vm::Ctx;
 
@@ -15,6 +15,9 @@ use wasmer_runtime_core::vm::Ctx;
 /// emscripten: _getenv // (name: *const char) -> *const c_char;
 pub fn _getenv(ctx: &mut Ctx, name: i32) -> u32 {
     debug!("emscripten::_getenv");
+    if let Some(ret) = guest_getenv(ctx, name as u32) {
+        return ret;
+    }
 
     let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
 
@@ -31,6 +34,9 @@ pub fn _getenv(ctx: &mut Ctx, name: i32) -> u32 {
 /// emscripten: _setenv // (name: *const char, name: *const value, overwrite: int);
 pub fn _setenv(ctx: &mut Ctx, name: c_int, value: c_int, overwrite: c_int) -> c_int {
     debug!("emscripten::_setenv");
+    if let Some(ret) = guest_setenv(ctx, name as u32, value as u32, overwrite != 0) {
+        return ret;
+    }
 
     let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
     let value_addr = emscripten_memory_pointer!(ctx.memory(0), value) as *const c_char;
@@ -44,6 +50,9 @@ pub fn _setenv(ctx: &mut Ctx, name: c_int, value: c_int, overwrite: c_int) -> c_
 /// emscripten: _putenv // (name: *const char);
 pub fn _putenv(ctx: &mut Ctx, name: c_int) -> c_int {
     debug!("emscripten::_putenv");
+    if let Some(ret) = guest_putenv(ctx, name as u32) {
+        return ret;
+    }
 
     let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
 
@@ -55,6 +64,9 @@ pub fn _putenv(ctx: &mut Ctx, name: c_int) -> c_int {
 /// emscripten: _unsetenv // (name: *const char);
 pub fn _unsetenv(ctx: &mut Ctx, name: c_int) -> c_int {
     debug!("emscripten::_unsetenv");
+    if let Some(ret) = guest_unsetenv(ctx, name as u32) {
+        return ret;
+    }
 
     let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
 
@@ -5,7 +5,7 @@ use std::ffi::CString;
 use std::mem;
 use std::os::raw::c_char;
 
-use crate::env::call_malloc;
+use crate::env::{call_malloc, guest_getenv, guest_putenv, guest_setenv, guest_unsetenv};
 use crate::utils::{copy_cstr_into_wasm, read_string_from_wasm};
 use wasmer_runtime_core::vm::Ctx;
 
@@ -18,6 +18,9 @@ extern "C" {
 /// emscripten: _getenv // (name: *const char) -> *const c_char;
 pub fn _getenv(ctx: &mut Ctx, name: u32) -> u32 {
     debug!("emscripten::_getenv");
+    if let Some(ret) = guest_getenv(ctx, name) {
+        return ret;
+    }
     let name_string = read_string_from_wasm(ctx.memory(0), name);
     debug!("=> name({:?})", name_string);
     let c_str = unsafe { getenv(name_string.as_ptr() as *const libc::c_char) };
@@ -28,8 +31,11 @@ pub fn _getenv(ctx: &mut Ctx, name: u32) -> u32 {
 }
 
 /// emscripten: _setenv // (name: *const char, name: *const value, overwrite: int);
-pub fn _setenv(ctx: &mut Ctx, name: u32, value: u32, _overwrite: u32) -> c_int {
+pub fn _setenv(ctx: &mut Ctx, name: u32, value: u32, overwrite: u32) -> c_int {
     debug!("emscripten::_setenv");
+    if let Some(ret) = guest_setenv(ctx, name, value, overwrite != 0) {
+        return ret;
+    }
     // setenv does not exist on windows, so we hack it with _putenv
     let name = read_string_from_wasm(ctx.memory(0), name);
     let value = read_string_from_wasm(ctx.memory(0), value);
@@ -44,6 +50,9 @@ pub fn _setenv(ctx: &mut Ctx, name: u32, value: u32, _overwrite: u32) -> c_int {
 /// emscripten: _putenv // (name: *const char);
 pub fn _putenv(ctx: &mut Ctx, name: c_int) -> c_int {
     debug!("emscripten::_putenv");
+    if let Some(ret) = guest_putenv(ctx, name as u32) {
+        return ret;
+    }
     let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
     debug!("=> name({:?})", unsafe {
         std::ffi::CStr::from_ptr(name_addr)
@@ -54,6 +63,9 @@ pub fn _putenv(ctx: &mut Ctx, name: c_int) -> c_int {
 /// emscripten: _unsetenv // (name: *const char);
 pub fn _unsetenv(ctx: &mut Ctx, name: u32) -> c_int {
     debug!("emscripten::_unsetenv");
+    if let Some(ret) = guest_unsetenv(ctx, name) {
+        return ret;
+    }
     let name = read_string_from_wasm(ctx.memory(0), name);
     // no unsetenv on windows, so use putenv with an empty value
     let unsetenv_string = format!("{}=", name);
@@ -1,7 +1,11 @@
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    termination::{self, Reason},
+    vm::Ctx,
+};
 
 // __exit
 pub fn exit(_ctx: &mut Ctx, value: i32) {
     debug!("emscripten::exit {}", value);
+    termination::report(Reason::Exit { code: value }, Vec::new());
     ::std::process::exit(value);
 }
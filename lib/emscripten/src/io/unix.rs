@@ -1,9 +1,14 @@
 use libc::{chroot as _chroot, printf as _printf};
 
+use crate::stdio;
 use wasmer_runtime_core::vm::Ctx;
 
 /// putchar
-pub fn putchar(_ctx: &mut Ctx, chr: i32) {
+pub fn putchar(ctx: &mut Ctx, chr: i32) {
+    if let Some(stdio) = stdio::redirected(ctx, 1) {
+        let _ = stdio.write(1, &[chr as u8]);
+        return;
+    }
     unsafe { libc::putchar(chr) };
 }
 
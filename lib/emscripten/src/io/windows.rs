@@ -1,3 +1,4 @@
+use crate::stdio;
 use wasmer_runtime_core::vm::Ctx;
 
 // This may be problematic for msvc which uses inline functions for the printf family
@@ -14,7 +15,11 @@ use wasmer_runtime_core::vm::Ctx;
 //}
 
 /// putchar
-pub fn putchar(_ctx: &mut Ctx, chr: i32) {
+pub fn putchar(ctx: &mut Ctx, chr: i32) {
+    if let Some(stdio) = stdio::redirected(ctx, 1) {
+        let _ = stdio.write(1, &[chr as u8]);
+        return;
+    }
     unsafe { libc::putchar(chr) };
 }
 
@@ -48,6 +48,7 @@ mod time;
 mod utils;
 mod varargs;
 
+pub use self::stdio::Stdio;
 pub use self::storage::{align_memory, static_alloc};
 pub use self::utils::{
     allocate_cstr_on_stack, allocate_on_stack, get_emscripten_memory_size,
@@ -122,6 +123,11 @@ pub struct EmscriptenData<'a> {
     pub dyn_call_vijiii: Option<Func<'a, (i32, i32, i32, i32, i32, i32, i32)>>,
     pub dyn_call_vijj: Option<Func<'a, (i32, i32, i32, i32, i32, i32)>>,
     pub dyn_call_viidii: Option<Func<'a, (i32, i32, i32, f64, i32, i32)>>,
+
+    /// Where the standard streams go, if not to the host's.
+    pub stdio: Option<Box<dyn Stdio>>,
+    /// The environment variables, if not the host's.
+    pub env: Option<Vec<(String, String)>>,
 }
 
 impl<'a> EmscriptenData<'a> {
@@ -226,17 +232,63 @@ impl<'a> EmscriptenData<'a> {
             dyn_call_vijiii,
             dyn_call_vijj,
             dyn_call_viidii,
+
+            stdio: None,
+            env: None,
         }
     }
 }
 
 pub fn run_emscripten_instance(
+    module: &Module,
+    instance: &mut Instance,
+    path: &str,
+    args: Vec<&str>,
+) -> CallResult<()> {
+    run_emscripten_instance_with(module, instance, path, args, RunOptions::default())
+}
+
+/// Like `run_emscripten_instance`, with the standard streams of the
+/// instance going to `stdio` rather than to those of the host process.
+pub fn run_emscripten_instance_with_stdio(
+    module: &Module,
+    instance: &mut Instance,
+    path: &str,
+    args: Vec<&str>,
+    stdio: Box<dyn Stdio>,
+) -> CallResult<()> {
+    let options = RunOptions {
+        stdio: Some(stdio),
+        ..RunOptions::default()
+    };
+    run_emscripten_instance_with(module, instance, path, args, options)
+}
+
+/// What an instance run with `run_emscripten_instance_with` gets in
+/// place of what the host process has.
+#[derive(Default)]
+pub struct RunOptions {
+    /// Where the standard streams of the instance go, if not to those
+    /// of the host process.
+    pub stdio: Option<Box<dyn Stdio>>,
+    /// The environment variables that the program sees, if not those
+    /// of the host process. Changes that it makes to them stay in the
+    /// instance. The program reads them as C strings, so a variable
+    /// that contains a nul byte is cut short at it.
+    pub env: Option<Vec<(String, String)>>,
+}
+
+/// Like `run_emscripten_instance`, with `options`.
+pub fn run_emscripten_instance_with(
     _module: &Module,
     instance: &mut Instance,
     path: &str,
     args: Vec<&str>,
+    options: RunOptions,
 ) -> CallResult<()> {
     let mut data = EmscriptenData::new(instance);
+    data.stdio = options.stdio;
+    data.env = options.env;
     let data_ptr = &mut data as *mut _ as *mut c_void;
     instance.context_mut().data = data_ptr;
 
@@ -4,3 +4,14 @@ macro_rules! emscripten_memory_pointer {
         (&$memory.view::<u8>()[($pointer as usize)..]).as_ptr() as *mut Cell<u8> as *mut u8
     }};
 }
+
+/// Unwrap a dereferenced `WasmPtr`, or make the syscall fail
+/// with `EFAULT` if the guest pointer was out of bounds.
+macro_rules! deref_or_efault {
+    ($deref:expr) => {
+        match $deref {
+            Some(value) => value,
+            None => return -libc::EFAULT,
+        }
+    };
+}
@@ -6,16 +6,28 @@ type PidT = libc::pid_t;
 type PidT = c_int;
 
 use std::ffi::CStr;
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    termination::{self, Reason},
+    vm::Ctx,
+};
 
-pub fn abort_with_message(ctx: &mut Ctx, message: &str) {
+pub fn abort_with_message(_ctx: &mut Ctx, message: &str) {
     debug!("emscripten::abort_with_message");
+    termination::report(
+        Reason::Abort {
+            message: Some(message.to_string()),
+        },
+        Vec::new(),
+    );
     println!("{}", message);
-    _abort(ctx);
+    unsafe {
+        abort();
+    }
 }
 
 pub fn _abort(_ctx: &mut Ctx) {
     debug!("emscripten::_abort");
+    termination::report(Reason::Abort { message: None }, Vec::new());
     unsafe {
         abort();
     }
@@ -42,6 +54,7 @@ pub fn _execve(_ctx: &mut Ctx, _one: i32, _two: i32, _three: i32) -> i32 {
 pub fn _exit(_ctx: &mut Ctx, status: c_int) {
     // -> !
     debug!("emscripten::_exit {}", status);
+    termination::report(Reason::Exit { code: status }, Vec::new());
     unsafe { exit(status) }
 }
 
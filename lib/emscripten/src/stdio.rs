@@ -1,7 +1,70 @@
 use super::file_descriptor::FileDescriptor;
+use crate::EmscriptenData;
 use libc;
-use std::io::BufReader;
-use std::io::Read;
+use std::cell::Cell;
+use std::io::{self, BufReader, Read};
+use wasmer_runtime_core::vm::Ctx;
+
+/// Where the standard streams of an instance go, in place of those of
+/// the host process. See `run_emscripten_instance_with_stdio`.
+///
+/// Only the output that goes through the `write` and `writev` syscalls
+/// and `putchar` is redirected; `printf` is the host's, so modules
+/// that import it print to the host's stdout.
+pub trait Stdio {
+    /// Writes output to stdout, when `fd` is 1, or stderr, when it's 2,
+    /// returning how many bytes were written.
+    fn write(&mut self, fd: libc::c_int, buf: &[u8]) -> io::Result<usize>;
+
+    /// Reads input from stdin into `buf`, returning how many bytes
+    /// were read, or 0 at its end.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+/// The `Stdio` that `fd` goes to in place of the host's, if it's a
+/// standard stream and the running instance has one.
+pub(crate) fn redirected<'a>(ctx: &Ctx, fd: libc::c_int) -> Option<&'a mut dyn Stdio> {
+    if ctx.data.is_null() || fd < 0 || fd > 2 {
+        return None;
+    }
+    let data = unsafe { &mut *(ctx.data as *mut EmscriptenData) };
+    match data.stdio {
+        Some(ref mut stdio) => Some(&mut **stdio),
+        None => None,
+    }
+}
+
+/// Writes guest memory to stdout or stderr through `stdio`, returning
+/// what the `write` syscall would.
+pub(crate) fn write(stdio: &mut dyn Stdio, fd: libc::c_int, buf: &[Cell<u8>]) -> isize {
+    if fd == 0 {
+        return -libc::EBADF as isize;
+    }
+    let bytes: Vec<u8> = buf.iter().map(Cell::get).collect();
+    match stdio.write(fd, &bytes) {
+        Ok(written) => written.min(bytes.len()) as isize,
+        Err(_) => -libc::EIO as isize,
+    }
+}
+
+/// Reads stdin into guest memory through `stdio`, returning what the
+/// `read` syscall would.
+pub(crate) fn read(stdio: &mut dyn Stdio, fd: libc::c_int, buf: &[Cell<u8>]) -> isize {
+    if fd != 0 {
+        return -libc::EBADF as isize;
+    }
+    let mut bytes = vec![0; buf.len()];
+    match stdio.read(&mut bytes) {
+        Ok(read) => {
+            let read = read.min(bytes.len());
+            for (cell, &byte) in buf.iter().zip(&bytes[..read]) {
+                cell.set(byte);
+            }
+            read as isize
+        }
+        Err(_) => -libc::EIO as isize,
+    }
+}
 
 // A struct to hold the references to the base stdout and the captured one
 pub struct StdioCapturer {
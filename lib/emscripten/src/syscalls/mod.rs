@@ -12,7 +12,6 @@ pub use self::windows::*;
 
 use super::utils::copy_stat_into_wasm;
 use super::varargs::VarArgs;
-use byteorder::{ByteOrder, LittleEndian};
 /// NOTE: TODO: These syscalls only support wasm_32 for now because they assume offsets are u32
 /// Syscall list: https://www.cs.utexas.edu/~bismith/test/syscalls/syscalls32.html
 use libc::{
@@ -38,14 +37,16 @@ use libc::{
     write,
     // sockaddr_in,
 };
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    memory::ptr::{Array, WasmPtr},
+    vm::Ctx,
+};
 
 use super::env;
+use super::stdio;
 use std::cell::Cell;
 #[allow(unused_imports)]
 use std::io::Error;
-use std::mem;
-use std::slice;
 
 /// exit
 pub fn ___syscall1(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) {
@@ -61,11 +62,14 @@ pub fn ___syscall3(ctx: &mut Ctx, _which: i32, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall3 (read) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let buf: u32 = varargs.get(ctx);
-    let count: i32 = varargs.get(ctx);
-    debug!("=> fd: {}, buf_offset: {}, count: {}", fd, buf, count);
-    let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *mut c_void;
-    let ret = unsafe { read(fd, buf_addr, count as _) };
+    let buf: WasmPtr<u8, Array> = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
+    debug!("=> fd: {}, buf_offset: {:?}, count: {}", fd, buf, count);
+    let buf = deref_or_efault!(buf.deref(ctx.memory(0), 0, count));
+    if let Some(stdio) = stdio::redirected(ctx, fd) {
+        return stdio::read(stdio, fd, buf) as _;
+    }
+    let ret = unsafe { read(fd, buf.as_ptr() as *mut c_void, buf.len()) };
     debug!("=> ret: {}", ret);
     ret as _
 }
@@ -74,11 +78,14 @@ pub fn ___syscall3(ctx: &mut Ctx, _which: i32, mut varargs: VarArgs) -> i32 {
 pub fn ___syscall4(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall4 (write) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let buf: u32 = varargs.get(ctx);
-    let count: i32 = varargs.get(ctx);
-    debug!("=> fd: {}, buf: {}, count: {}", fd, buf, count);
-    let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *const c_void;
-    unsafe { write(fd, buf_addr, count as _) as i32 }
+    let buf: WasmPtr<u8, Array> = varargs.get(ctx);
+    let count: u32 = varargs.get(ctx);
+    debug!("=> fd: {}, buf: {:?}, count: {}", fd, buf, count);
+    let buf = deref_or_efault!(buf.deref(ctx.memory(0), 0, count));
+    if let Some(stdio) = stdio::redirected(ctx, fd) {
+        return stdio::write(stdio, fd, buf) as _;
+    }
+    unsafe { write(fd, buf.as_ptr() as *const c_void, buf.len()) as i32 }
 }
 
 /// close
@@ -92,14 +99,11 @@ pub fn ___syscall6(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int
 // chdir
 pub fn ___syscall12(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall12 (chdir) {}", _which);
-    let path_addr: i32 = varargs.get(ctx);
-    unsafe {
-        let path_ptr = emscripten_memory_pointer!(ctx.memory(0), path_addr) as *const i8;
-        let _path = std::ffi::CStr::from_ptr(path_ptr);
-        let ret = chdir(path_ptr);
-        debug!("=> path: {:?}, ret: {}", _path, ret);
-        ret
-    }
+    let path: WasmPtr<u8, Array> = varargs.get(ctx);
+    let path = deref_or_efault!(path.get_c_str(ctx.memory(0)));
+    let ret = unsafe { chdir(path.as_ptr()) };
+    debug!("=> path: {:?}, ret: {}", path, ret);
+    ret
 }
 
 pub fn ___syscall10(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
@@ -126,9 +130,9 @@ pub fn ___syscall38(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
 // rmdir
 pub fn ___syscall40(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall40 (rmdir)");
-    let pathname: u32 = varargs.get(ctx);
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
-    unsafe { rmdir(pathname_addr) }
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
+    let pathname = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
+    unsafe { rmdir(pathname.as_ptr()) }
 }
 
 // pipe
@@ -212,21 +216,16 @@ pub fn ___syscall110(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
 pub fn ___syscall183(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall183");
     use std::env;
-    let buf_offset: c_int = varargs.get(ctx);
+    let buf: WasmPtr<u8, Array> = varargs.get(ctx);
     let _size: c_int = varargs.get(ctx);
     let path = env::current_dir();
     let path_string = path.unwrap().display().to_string();
-    let len = path_string.len();
-    unsafe {
-        let pointer_to_buffer =
-            emscripten_memory_pointer!(ctx.memory(0), buf_offset) as *mut libc::c_char;
-        let slice = slice::from_raw_parts_mut(pointer_to_buffer, len.clone());
-        for (byte, loc) in path_string.bytes().zip(slice.iter_mut()) {
-            *loc = byte as _;
-        }
-        *pointer_to_buffer.add(len.clone()) = 0;
+    let len = path_string.len() as u32;
+    let buf_cells = deref_or_efault!(buf.deref(ctx.memory(0), 0, len + 1));
+    for (byte, cell) in path_string.bytes().chain(Some(0)).zip(buf_cells) {
+        cell.set(byte);
     }
-    buf_offset
+    buf.offset() as i32
 }
 
 // mmap2
@@ -262,21 +261,16 @@ pub fn ___syscall140(ctx: &mut Ctx, _which: i32, mut varargs: VarArgs) -> i32 {
     let fd: i32 = varargs.get(ctx);
     let _ = varargs.get::<i32>(ctx); // ignore high offset
     let offset_low: i32 = varargs.get(ctx);
-    let result_ptr_value = varargs.get::<i32>(ctx);
+    let result_ptr: WasmPtr<i32> = varargs.get(ctx);
     let whence: i32 = varargs.get(ctx);
     let offset = offset_low as off_t;
     let ret = unsafe { lseek(fd, offset, whence) as i32 };
-    #[allow(clippy::cast_ptr_alignment)]
-    let result_ptr = emscripten_memory_pointer!(ctx.memory(0), result_ptr_value) as *mut i32;
-    assert_eq!(8, mem::align_of_val(&result_ptr));
-    unsafe {
-        *result_ptr = ret;
-    }
+    deref_or_efault!(result_ptr.deref(ctx.memory(0))).set(ret);
     debug!(
-        "=> fd: {}, offset: {}, result_ptr: {}, whence: {} = {}\nlast os error: {}",
+        "=> fd: {}, offset: {}, result_ptr: {:?}, whence: {} = {}\nlast os error: {}",
         fd,
         offset,
-        result_ptr_value,
+        result_ptr,
         whence,
         0,
         Error::last_os_error(),
@@ -291,34 +285,29 @@ pub fn ___syscall145(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> i32
     debug!("emscripten::___syscall145 (readv) {}", _which);
 
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
+    // Each guest iovec is an `iov_base` pointer followed by an `iov_len`.
+    let iov: WasmPtr<u32, Array> = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
 
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
-
-    debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    debug!("=> fd: {}, iov: {:?}, iovcnt = {}", fd, iov, iovcnt);
+    let memory = ctx.memory(0);
+    let guest_iovs = deref_or_efault!(iov.deref(memory, 0, iovcnt.saturating_mul(2)));
     let mut ret = 0;
-    unsafe {
-        for i in 0..iovcnt {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *mut c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = read(fd, iov_base, iov_len);
-            if curr < 0 {
-                return -1;
-            }
-            ret += curr;
+    for guest_iov in guest_iovs.chunks(2) {
+        let iov_base: WasmPtr<u8, Array> = WasmPtr::new(guest_iov[0].get());
+        let iov_base = deref_or_efault!(iov_base.deref(memory, 0, guest_iov[1].get()));
+        // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
+        let curr = match stdio::redirected(ctx, fd) {
+            Some(stdio) => stdio::read(stdio, fd, iov_base),
+            None => unsafe { read(fd, iov_base.as_ptr() as *mut c_void, iov_base.len()) },
+        };
+        if curr < 0 {
+            return -1;
         }
-        // debug!(" => ret: {}", ret);
-        ret as _
+        ret += curr;
     }
+    // debug!(" => ret: {}", ret);
+    ret as _
 }
 
 // writev
@@ -327,34 +316,29 @@ pub fn ___syscall146(ctx: &mut Ctx, _which: i32, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall146 (writev) {}", _which);
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
-
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
+    // Each guest iovec is an `iov_base` pointer followed by an `iov_len`.
+    let iov: WasmPtr<u32, Array> = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
 
-    debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    debug!("=> fd: {}, iov: {:?}, iovcnt = {}", fd, iov, iovcnt);
+    let memory = ctx.memory(0);
+    let guest_iovs = deref_or_efault!(iov.deref(memory, 0, iovcnt.saturating_mul(2)));
     let mut ret = 0;
-    unsafe {
-        for i in 0..iovcnt {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *const c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = write(fd, iov_base, iov_len);
-            if curr < 0 {
-                return -1;
-            }
-            ret += curr;
+    for guest_iov in guest_iovs.chunks(2) {
+        let iov_base: WasmPtr<u8, Array> = WasmPtr::new(guest_iov[0].get());
+        let iov_base = deref_or_efault!(iov_base.deref(memory, 0, guest_iov[1].get()));
+        // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
+        let curr = match stdio::redirected(ctx, fd) {
+            Some(stdio) => stdio::write(stdio, fd, iov_base),
+            None => unsafe { write(fd, iov_base.as_ptr() as *const c_void, iov_base.len()) },
+        };
+        if curr < 0 {
+            return -1;
         }
-        // debug!(" => ret: {}", ret);
-        ret as _
+        ret += curr;
     }
+    // debug!(" => ret: {}", ret);
+    ret as _
 }
 
 pub fn ___syscall168(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
@@ -375,19 +359,19 @@ pub fn ___syscall199(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
 // stat64
 pub fn ___syscall195(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall195 (stat64) {}", _which);
-    let pathname: u32 = varargs.get(ctx);
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
     let buf: u32 = varargs.get(ctx);
 
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
+    let path = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
 
     unsafe {
         let mut _stat: stat = std::mem::zeroed();
-        let ret = stat(pathname_addr, &mut _stat);
+        let ret = stat(path.as_ptr(), &mut _stat);
         debug!(
-            "=> pathname: {}, buf: {}, path: {} = {}\nlast os error: {}",
+            "=> pathname: {:?}, buf: {}, path: {:?} = {}\nlast os error: {}",
             pathname,
             buf,
-            std::ffi::CStr::from_ptr(pathname_addr).to_str().unwrap(),
+            path,
             ret,
             Error::last_os_error()
         );
@@ -473,17 +457,13 @@ pub fn ___syscall340(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_in
     let _pid: i32 = varargs.get(ctx);
     let _resource: i32 = varargs.get(ctx);
     let _new_limit: u32 = varargs.get(ctx);
-    let old_limit: u32 = varargs.get(ctx);
+    let old_limit: WasmPtr<i32, Array> = varargs.get(ctx);
 
-    if old_limit != 0 {
+    if old_limit.offset() != 0 {
         // just report no limits
-        let buf_ptr = emscripten_memory_pointer!(ctx.memory(0), old_limit) as *mut u8;
-        let buf = unsafe { slice::from_raw_parts_mut(buf_ptr, 16) };
-
-        LittleEndian::write_i32(&mut buf[..], -1); // RLIM_INFINITY
-        LittleEndian::write_i32(&mut buf[4..], -1); // RLIM_INFINITY
-        LittleEndian::write_i32(&mut buf[8..], -1); // RLIM_INFINITY
-        LittleEndian::write_i32(&mut buf[12..], -1); // RLIM_INFINITY
+        for limit in deref_or_efault!(old_limit.deref(ctx.memory(0), 0, 4)) {
+            limit.set(-1); // RLIM_INFINITY
+        }
     }
 
     0
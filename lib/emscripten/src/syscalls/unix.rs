@@ -71,7 +71,10 @@ use libc::{
     SO_REUSEADDR,
     TIOCGWINSZ,
 };
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    memory::ptr::{Array, WasmPtr},
+    vm::Ctx,
+};
 
 #[allow(unused_imports)]
 use std::io::Error;
@@ -101,19 +104,18 @@ const SO_NOSIGPIPE: c_int = 0;
 /// open
 pub fn ___syscall5(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall5 (open) {}", _which);
-    let pathname: u32 = varargs.get(ctx);
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
     let flags: i32 = varargs.get(ctx);
     let mode: u32 = varargs.get(ctx);
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
-    let _path_str = unsafe { std::ffi::CStr::from_ptr(pathname_addr).to_str().unwrap() };
-    let fd = unsafe { open(pathname_addr, flags, mode) };
+    let path = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
+    let fd = unsafe { open(path.as_ptr(), flags, mode) };
     debug!(
-        "=> pathname: {}, flags: {}, mode: {} = fd: {}\npath: {}\nlast os error: {}",
+        "=> pathname: {:?}, flags: {}, mode: {} = fd: {}\npath: {:?}\nlast os error: {}",
         pathname,
         flags,
         mode,
         fd,
-        _path_str,
+        path,
         Error::last_os_error(),
     );
     fd
@@ -123,16 +125,14 @@ pub fn ___syscall5(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int
 pub fn ___syscall9(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall9 (link) {}", _which);
 
-    let oldname: c_int = varargs.get(ctx);
-    let newname: c_int = varargs.get(ctx);
-    let oldname_ptr = emscripten_memory_pointer!(ctx.memory(0), oldname) as *const i8;
-    let newname_ptr = emscripten_memory_pointer!(ctx.memory(0), newname) as *const i8;
-    let result = unsafe { link(oldname_ptr, newname_ptr) };
+    let oldname: WasmPtr<u8, Array> = varargs.get(ctx);
+    let newname: WasmPtr<u8, Array> = varargs.get(ctx);
+    let oldname = deref_or_efault!(oldname.get_c_str(ctx.memory(0)));
+    let newname = deref_or_efault!(newname.get_c_str(ctx.memory(0)));
+    let result = unsafe { link(oldname.as_ptr(), newname.as_ptr()) };
     debug!(
-        "=> oldname: {}, newname: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(oldname_ptr).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(newname_ptr).to_str().unwrap() },
-        result,
+        "=> oldname: {:?}, newname: {:?}, result: {}",
+        oldname, newname, result,
     );
     result
 }
@@ -153,16 +153,14 @@ pub fn ___syscall77(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int
 pub fn ___syscall83(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall83 (symlink) {}", _which);
 
-    let path1_ptr: c_int = varargs.get(ctx);
-    let path2_ptr: c_int = varargs.get(ctx);
-    let path1 = emscripten_memory_pointer!(ctx.memory(0), path1_ptr) as *mut i8;
-    let path2 = emscripten_memory_pointer!(ctx.memory(0), path2_ptr) as *mut i8;
-    let result = unsafe { symlink(path1, path2) };
+    let path1: WasmPtr<u8, Array> = varargs.get(ctx);
+    let path2: WasmPtr<u8, Array> = varargs.get(ctx);
+    let path1 = deref_or_efault!(path1.get_c_str(ctx.memory(0)));
+    let path2 = deref_or_efault!(path2.get_c_str(ctx.memory(0)));
+    let result = unsafe { symlink(path1.as_ptr(), path2.as_ptr()) };
     debug!(
-        "=> path1: {}, path2: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(path1).to_str().unwrap() },
-        unsafe { std::ffi::CStr::from_ptr(path2).to_str().unwrap() },
-        result,
+        "=> path1: {:?}, path2: {:?}, result: {}",
+        path1, path2, result,
     );
     result
 }
@@ -183,17 +181,14 @@ pub fn ___syscall194(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_in
 /// lchown
 pub fn ___syscall198(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall198 (lchown) {}", _which);
-    let path: c_int = varargs.get(ctx);
+    let path: WasmPtr<u8, Array> = varargs.get(ctx);
     let uid: uid_t = varargs.get(ctx);
     let gid: gid_t = varargs.get(ctx);
-    let path_ptr = emscripten_memory_pointer!(ctx.memory(0), path) as *const i8;
-    let result = unsafe { lchown(path_ptr, uid, gid) };
+    let path = deref_or_efault!(path.get_c_str(ctx.memory(0)));
+    let result = unsafe { lchown(path.as_ptr(), uid, gid) };
     debug!(
-        "=> path: {}, uid: {}, gid: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(path_ptr).to_str().unwrap() },
-        uid,
-        gid,
-        result,
+        "=> path: {:?}, uid: {}, gid: {}, result: {}",
+        path, uid, gid, result,
     );
     result
 }
@@ -219,13 +214,13 @@ pub fn ___syscall205(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_in
 pub fn ___syscall212(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall212 (chown) {}", _which);
 
-    let pathname: u32 = varargs.get(ctx);
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
     let owner: u32 = varargs.get(ctx);
     let group: u32 = varargs.get(ctx);
 
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
+    let pathname = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
 
-    unsafe { chown(pathname_addr, owner, group) }
+    unsafe { chown(pathname.as_ptr(), owner, group) }
 }
 
 /// madvise
@@ -244,15 +239,11 @@ pub fn ___syscall219(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_in
 /// access
 pub fn ___syscall33(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall33 (access) {}", _which);
-    let path_ptr: c_int = varargs.get(ctx);
+    let path: WasmPtr<u8, Array> = varargs.get(ctx);
     let amode: c_int = varargs.get(ctx);
-    let path = emscripten_memory_pointer!(ctx.memory(0), path_ptr) as *const i8;
-    let result = unsafe { access(path, amode) };
-    debug!(
-        "=> path: {}, result: {}",
-        unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() },
-        result
-    );
+    let path = deref_or_efault!(path.get_c_str(ctx.memory(0)));
+    let result = unsafe { access(path.as_ptr(), amode) };
+    debug!("=> path: {:?}, result: {}", path, result);
     result
 }
 
@@ -266,10 +257,10 @@ pub fn ___syscall34(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int
 // mkdir
 pub fn ___syscall39(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall39 (mkdir) {}", _which);
-    let pathname: u32 = varargs.get(ctx);
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
     let mode: u32 = varargs.get(ctx);
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
-    unsafe { mkdir(pathname_addr, mode as _) }
+    let pathname = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
+    unsafe { mkdir(pathname.as_ptr(), mode as _) }
 }
 
 /// dup
@@ -762,17 +753,17 @@ pub fn ___syscall122(ctx: &mut Ctx, _which: c_int, mut varargs: VarArgs) -> c_in
 /// lstat64
 pub fn ___syscall196(ctx: &mut Ctx, _which: i32, mut varargs: VarArgs) -> i32 {
     debug!("emscripten::___syscall196 (lstat64) {}", _which);
-    let path_ptr: c_int = varargs.get(ctx);
+    let path_ptr: WasmPtr<u8, Array> = varargs.get(ctx);
     let buf_ptr: c_int = varargs.get(ctx);
-    let path = emscripten_memory_pointer!(ctx.memory(0), path_ptr) as *const c_char;
+    let path = deref_or_efault!(path_ptr.get_c_str(ctx.memory(0)));
     let buf = emscripten_memory_pointer!(ctx.memory(0), buf_ptr) as *mut c_void;
-    let result = unsafe { lstat64(path, buf as _) };
+    let result = unsafe { lstat64(path.as_ptr(), buf as _) };
     debug!(
-        "=> path: {}, buf: {} = fd: {}\npath: {}\nlast os error: {}",
+        "=> path: {:?}, buf: {} = fd: {}\npath: {:?}\nlast os error: {}",
         path_ptr,
         buf_ptr,
         result,
-        unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() },
+        path,
         Error::last_os_error(),
     );
     result
@@ -8,7 +8,10 @@ use std::ffi::CString;
 use std::fs::File;
 use std::io::Write;
 use std::os::raw::c_int;
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    memory::ptr::{Array, WasmPtr},
+    vm::Ctx,
+};
 
 #[allow(non_camel_case_types)]
 type pid_t = c_int;
@@ -18,13 +21,12 @@ pub fn ___syscall5(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) -> c_int {
     debug!("emscripten::___syscall5 (open) {}", which);
     #[cfg(not(feature = "debug"))]
     let _ = which;
-    let pathname: u32 = varargs.get(ctx);
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
     let flags: i32 = varargs.get(ctx);
     let mode: u32 = varargs.get(ctx);
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
-    let path_str = unsafe { std::ffi::CStr::from_ptr(pathname_addr).to_str().unwrap() };
-    match path_str {
-        "/dev/urandom" => {
+    let path = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
+    match path.to_bytes() {
+        b"/dev/urandom" => {
             // create a fake urandom file for windows, super hacky
             // put it in the temp directory so we can just forget about it
             let mut tmp_dir = env::temp_dir();
@@ -42,16 +44,16 @@ pub fn ___syscall5(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) -> c_int {
                 emscripten_memory_pointer!(ctx.memory(0), urandom_file_offset) as *const i8;
             let fd = unsafe { open(raw_pointer_to_urandom_file, flags, mode) };
             debug!(
-                "=> pathname: {}, flags: {}, mode: {} = fd: {}",
+                "=> pathname: {:?}, flags: {}, mode: {} = fd: {}",
                 pathname, flags, mode, fd
             );
             fd
         }
         _ => {
-            let fd = unsafe { open(pathname_addr, flags, mode) };
+            let fd = unsafe { open(path.as_ptr(), flags, mode) };
             debug!(
-                "=> pathname: {}, flags: {}, mode: {} = fd: {}\npath: {}",
-                pathname, flags, mode, fd, path_str
+                "=> pathname: {:?}, flags: {}, mode: {} = fd: {}\npath: {:?}",
+                pathname, flags, mode, fd, path
             );
             fd
         }
@@ -95,9 +97,9 @@ pub fn ___syscall39(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) -> c_int
     debug!("emscripten::___syscall39 (mkdir) {}", which);
     #[cfg(not(feature = "debug"))]
     let _ = which;
-    let pathname: u32 = varargs.get(ctx);
-    let pathname_addr = emscripten_memory_pointer!(ctx.memory(0), pathname) as *const i8;
-    unsafe { mkdir(pathname_addr) }
+    let pathname: WasmPtr<u8, Array> = varargs.get(ctx);
+    let pathname = deref_or_efault!(pathname.get_c_str(ctx.memory(0)));
+    unsafe { mkdir(pathname.as_ptr()) }
 }
 
 /// dup
@@ -1,7 +1,7 @@
 use super::utils::{copy_cstr_into_wasm, write_to_buf};
 use libc::{c_char, c_int};
 use std::mem;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 #[cfg(not(target_os = "windows"))]
 use libc::{clockid_t, time as libc_time};
@@ -22,7 +22,7 @@ extern "C" {
 use time;
 
 use super::env;
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{deterministic, vm::Ctx};
 
 #[cfg(target_os = "linux")]
 use libc::{CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE, CLOCK_REALTIME};
@@ -55,8 +55,13 @@ pub fn _gettimeofday(ctx: &mut Ctx, tp: c_int, tz: c_int) -> c_int {
         "the timezone argument of `_gettimeofday` must be null"
     );
     unsafe {
-        let now = SystemTime::now();
-        let since_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        // In deterministic mode, it's always the start of the epoch.
+        let since_epoch = if deterministic::is_deterministic() {
+            Duration::from_secs(0)
+        } else {
+            let now = SystemTime::now();
+            now.duration_since(SystemTime::UNIX_EPOCH).unwrap()
+        };
         let timeval_struct_ptr = emscripten_memory_pointer!(ctx.memory(0), tp) as *mut GuestTimeVal;
 
         (*timeval_struct_ptr).tv_sec = since_epoch.as_secs() as _;
@@ -78,6 +83,13 @@ pub fn _clock_gettime(ctx: &mut Ctx, clk_id: clockid_t, tp: c_int) -> c_int {
 
     #[allow(unreachable_patterns)]
     let timespec = match clk_id {
+        // In deterministic mode, every clock is stopped at zero.
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_MONOTONIC_COARSE
+            if deterministic::is_deterministic() =>
+        {
+            time::Timespec::new(0, 0)
+        }
+
         CLOCK_REALTIME => time::get_time(),
 
         CLOCK_MONOTONIC | CLOCK_MONOTONIC_COARSE => {
@@ -292,6 +304,13 @@ pub fn _time(ctx: &mut Ctx, time_p: u32) -> i32 {
 
     unsafe {
         let time_p_addr = emscripten_memory_pointer!(ctx.memory(0), time_p) as *mut i64;
+        if deterministic::is_deterministic() {
+            // It's always the start of the epoch.
+            if time_p != 0 {
+                *time_p_addr = 0;
+            }
+            return 0;
+        }
         libc_time(time_p_addr) as i32 // TODO review i64
     }
 }
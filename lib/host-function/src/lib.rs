@@ -0,0 +1,320 @@
+//! The `#[host_function]` attribute, which turns an ordinary Rust
+//! function into a host function that WebAssembly can import.
+//!
+//! The function takes the `&mut Ctx` of the calling instance first, as
+//! host functions do, followed by parameters of any of these types,
+//! which are read from the first memory of the instance:
+//!
+//! - `&str` is passed by the guest as a pointer and a length in bytes,
+//!   and must be UTF-8.
+//! - `&CStr` is passed as a pointer to a nul-terminated string.
+//! - `&[Cell<T>]` is passed as a pointer and a number of `T`s.
+//! - `&Cell<T>` is passed as a pointer to a `T`.
+//!
+//! Nothing is copied: the parameters borrow the memory for the rest of
+//! the call. If one of them is out of bounds, misaligned or not UTF-8,
+//! the call traps. Parameters of any other type are passed through, so
+//! they must be types that host functions can take, such as `i32`.
+//!
+//! The attribute replaces the function with one that has the signature
+//! that the guest sees, so it can be given to `func!` as usual. It also
+//! adds a function, named after it with an `_import` suffix, that
+//! returns the name that the function is imported as and its export,
+//! ready to be inserted into a `Namespace`. The name is the name of the
+//! function unless it's given with `#[host_function(name = "...")]`.
+//!
+//! With `#[host_function(errno)]`, the function returns a `Result`
+//! whose values are `i32`s, or convert into them, and the guest gets
+//! either the `Ok` value or the negated `Err` value, which is how
+//! syscalls report errno values.
+//!
+//! The generated code refers to `wasmer_runtime_core`, so the crate that
+//! uses the attribute must depend on it.
+//!
+//! # Usage:
+//!
+//! ```
+//! use std::cell::Cell;
+//! use wasmer_host_function::host_function;
+//! use wasmer_runtime_core::{import::Namespace, imports, vm::Ctx};
+//!
+//! /// Write `text`, returning how many bytes were written.
+//! #[host_function(name = "print_str")]
+//! fn print(_ctx: &mut Ctx, text: &str) -> u32 {
+//!     print!("{}", text);
+//!     text.len() as u32
+//! }
+//!
+//! /// Add up `values` into `total`, failing with `EINVAL` if there are none.
+//! #[host_function(errno)]
+//! fn sum(_ctx: &mut Ctx, values: &[Cell<u32>], total: &Cell<u32>) -> Result<i32, i32> {
+//!     if values.is_empty() {
+//!         return Err(22);
+//!     }
+//!     total.set(values.iter().map(Cell::get).sum());
+//!     Ok(0)
+//! }
+//!
+//! let mut env = Namespace::new();
+//! let (name, export) = print_import();
+//! env.insert(name, export);
+//! let (name, export) = sum_import();
+//! env.insert(name, export);
+//!
+//! let import_object = imports! {
+//!     "env" => env,
+//! };
+//! assert!(import_object.get_export("env", "print_str").is_some());
+//! assert!(import_object.get_export("env", "sum").is_some());
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, AttributeArgs, FnArg, GenericArgument, Ident, ItemFn, Lit,
+    Meta, NestedMeta, Pat, PathArguments, ReturnType, Type,
+};
+
+/// Turn a Rust function into a host function. See the crate
+/// documentation.
+#[proc_macro_attribute]
+pub fn host_function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as AttributeArgs);
+    let function = parse_macro_input!(item as ItemFn);
+
+    match expand(args, function) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// How a parameter of the function is passed by the guest.
+enum Param {
+    /// A pointer and a length in bytes, to a UTF-8 string.
+    Str,
+    /// A pointer to a nul-terminated string.
+    CStr,
+    /// A pointer and a number of `T`s.
+    Slice(Type),
+    /// A pointer to a `T`.
+    Cell(Type),
+    /// As it is.
+    Value(Type),
+}
+
+impl Param {
+    fn of(ty: &Type) -> Param {
+        if let Type::Reference(reference) = ty {
+            if reference.mutability.is_none() {
+                match &*reference.elem {
+                    Type::Path(path) if path.path.is_ident("str") => return Param::Str,
+                    Type::Path(path) if last_segment_is(path, "CStr") => return Param::CStr,
+                    Type::Path(path) => {
+                        if let Some(inner) = cell_type(path) {
+                            return Param::Cell(inner);
+                        }
+                    }
+                    Type::Slice(slice) => {
+                        if let Type::Path(path) = &*slice.elem {
+                            if let Some(inner) = cell_type(path) {
+                                return Param::Slice(inner);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Param::Value(ty.clone())
+    }
+}
+
+fn last_segment_is(path: &syn::TypePath, name: &str) -> bool {
+    path.path
+        .segments
+        .iter()
+        .last()
+        .map_or(false, |segment| segment.ident == name)
+}
+
+/// `T` if `path` is `Cell<T>`.
+fn cell_type(path: &syn::TypePath) -> Option<Type> {
+    let segment = path.path.segments.iter().last()?;
+    if segment.ident != "Cell" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match &arguments.args[0] {
+                GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn expand(args: AttributeArgs, function: ItemFn) -> syn::Result<TokenStream2> {
+    let mut import_name = function.ident.to_string();
+    let mut errno = false;
+    for arg in &args {
+        match arg {
+            NestedMeta::Meta(Meta::Word(word)) if word == "errno" => errno = true,
+            NestedMeta::Meta(Meta::NameValue(pair)) if pair.ident == "name" => match &pair.lit {
+                Lit::Str(name) => import_name = name.value(),
+                lit => return Err(syn::Error::new(lit.span(), "expected a string")),
+            },
+            arg => {
+                return Err(syn::Error::new(
+                    arg.span(),
+                    "expected `errno` or `name = \"...\"`",
+                ))
+            }
+        }
+    }
+
+    let decl = &function.decl;
+    if !decl.generics.params.is_empty() || decl.variadic.is_some() {
+        return Err(syn::Error::new(
+            decl.generics.span(),
+            "host functions cannot be generic or variadic",
+        ));
+    }
+
+    let mut inputs = decl.inputs.iter().map(|input| match input {
+        FnArg::Captured(captured) => match &captured.pat {
+            Pat::Ident(pat) => Ok((pat.ident.clone(), &captured.ty)),
+            pat => Err(syn::Error::new(
+                pat.span(),
+                "parameters of host functions must be plain names",
+            )),
+        },
+        input => Err(syn::Error::new(
+            input.span(),
+            "parameters of host functions must be plain names",
+        )),
+    });
+
+    let (ctx, ctx_ty) = match inputs.next() {
+        Some(input) => input?,
+        None => {
+            return Err(syn::Error::new(
+                decl.inputs.span(),
+                "host functions take a `&mut Ctx` first",
+            ))
+        }
+    };
+
+    let core = quote!(::wasmer_runtime_core);
+    let ptr_mod = quote!(#core::memory::ptr);
+    let function_name = function.ident.to_string();
+    let mut wasm_params = Vec::new();
+    let mut decoders = Vec::new();
+    let mut names = Vec::new();
+    let mut uses_memory = false;
+
+    for input in inputs {
+        let (name, ty) = input?;
+        let ptr = Ident::new(&format!("{}_ptr", name), name.span());
+        let len = Ident::new(&format!("{}_len", name), name.span());
+        let invalid = format!(
+            "the `{}` argument of `{}` is out of bounds or invalid",
+            name, function_name
+        );
+        let decoded = match Param::of(ty) {
+            Param::Value(ty) => {
+                wasm_params.push(quote!(#name: #ty));
+                names.push(name);
+                continue;
+            }
+            Param::Str => {
+                wasm_params.push(quote!(#ptr: #ptr_mod::WasmPtr<u8, #ptr_mod::Array>));
+                wasm_params.push(quote!(#len: u32));
+                quote!(#ptr_mod::WasmStr::new(#ptr, #len).get(&memory))
+            }
+            Param::CStr => {
+                wasm_params.push(quote!(#ptr: #ptr_mod::WasmPtr<u8, #ptr_mod::Array>));
+                quote!(#ptr.get_c_str(&memory))
+            }
+            Param::Slice(inner) => {
+                wasm_params.push(quote!(#ptr: #ptr_mod::WasmPtr<#inner, #ptr_mod::Array>));
+                wasm_params.push(quote!(#len: u32));
+                quote!(#ptr_mod::WasmSlice::new(#ptr, #len).get(&memory))
+            }
+            Param::Cell(inner) => {
+                wasm_params.push(quote!(#ptr: #ptr_mod::WasmPtr<#inner>));
+                quote!(#ptr.deref(&memory))
+            }
+        };
+        uses_memory = true;
+        decoders.push(quote! {
+            let #name = match #decoded {
+                Some(value) => value,
+                None => panic!(#invalid),
+            };
+        });
+        names.push(name);
+    }
+
+    let memory = if uses_memory {
+        // A handle to the memory, so the arguments can borrow from it
+        // while the function has the `Ctx`.
+        quote!(let memory = #ctx.memory(0).clone();)
+    } else {
+        quote!()
+    };
+
+    let (wasm_output, result) = if errno {
+        (
+            quote!(-> i32),
+            quote! {
+                match result {
+                    Ok(value) => ::std::convert::Into::<i32>::into(value),
+                    Err(errno) => -::std::convert::Into::<i32>::into(errno),
+                }
+            },
+        )
+    } else {
+        let output = match &decl.output {
+            ReturnType::Default => quote!(),
+            ReturnType::Type(arrow, ty) => quote!(#arrow #ty),
+        };
+        (output, quote!(result))
+    };
+
+    let ItemFn {
+        attrs,
+        vis,
+        ident,
+        block,
+        ..
+    } = &function;
+    let inner_inputs = &decl.inputs;
+    let inner_output = &decl.output;
+    let import = Ident::new(&format!("{}_import", ident), Span::call_site());
+    let import_doc = format!("The import of `{}`, as `{}`.", ident, import_name);
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #ident(#ctx: #ctx_ty, #(#wasm_params),*) #wasm_output {
+            fn inner(#inner_inputs) #inner_output #block
+
+            #memory
+            #(#decoders)*
+            let result = inner(#ctx, #(#names),*);
+            #result
+        }
+
+        #[doc = #import_doc]
+        #[allow(dead_code)]
+        #vis fn #import() -> (&'static str, #core::export::Export) {
+            (
+                #import_name,
+                #core::IsExport::to_export(&#core::Func::new(#ident)),
+            )
+        }
+    })
+}
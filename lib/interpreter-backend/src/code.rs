@@ -0,0 +1,320 @@
+use crate::{
+    interpret,
+    native::Signature,
+    ops::{BrTarget, Op},
+};
+use std::{
+    mem,
+    ptr::{self, NonNull},
+    sync::Arc,
+};
+use wasmer_runtime_core::{
+    backend::{
+        non_native_code_range, register_non_native_code, unregister_non_native_code, FuncResolver,
+        ProtectedCaller, Token, UserTrapper,
+    },
+    error::{RuntimeError, RuntimeResult, TrapCode},
+    memory::MemoryType,
+    module::{ModuleInfo, ModuleInner},
+    refs::{ExternRef, FuncRef},
+    structures::{Map, TypedIndex},
+    trap,
+    types::{
+        FuncIndex, FuncSig, LocalFuncIndex, LocalOrImport, MemoryIndex, SigIndex, TableIndex, Type,
+        Value,
+    },
+    vm::{self, ImportBacking},
+};
+
+/// A function translated for the interpreter. Pointers to it stand in
+/// for pointers to machine code.
+pub struct Function {
+    pub index: FuncIndex,
+    pub name: Option<String>,
+    pub param_count: usize,
+    /// Not counting the parameters.
+    pub local_count: usize,
+    pub return_count: usize,
+    pub ops: Vec<Op>,
+    /// The offset in the module of the operator that each instruction
+    /// was translated from.
+    pub offsets: Vec<u32>,
+    pub br_table: Vec<BrTarget>,
+    /// The code of the module that the function is in.
+    pub code: *const Code,
+}
+
+/// Where an instance keeps a memory or a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmIndex {
+    Local(usize),
+    Import(usize),
+}
+
+/// The translated functions of a module, and what running them needs
+/// to know about it.
+pub struct Code {
+    functions: Box<[Function]>,
+    pub signatures: Map<SigIndex, Signature>,
+    /// The memory, and its type, if the module has one.
+    pub memory: Option<(VmIndex, MemoryType)>,
+    pub tables: Map<TableIndex, VmIndex>,
+}
+
+// The functions are only read once they've been translated.
+unsafe impl Send for Code {}
+unsafe impl Sync for Code {}
+unsafe impl Send for Function {}
+unsafe impl Sync for Function {}
+
+impl Code {
+    pub fn new(info: &ModuleInfo, functions: Vec<Function>) -> Arc<Code> {
+        let memory = if info.memories.len() + info.imported_memories.len() > 0 {
+            let index = MemoryIndex::new(0);
+            Some(match index.local_or_import(info) {
+                LocalOrImport::Local(local) => (
+                    VmIndex::Local(local.index()),
+                    info.memories[local].memory_type(),
+                ),
+                LocalOrImport::Import(import) => (
+                    VmIndex::Import(import.index()),
+                    info.imported_memories[import].1.memory_type(),
+                ),
+            })
+        } else {
+            None
+        };
+        let mut tables = Map::new();
+        for index in 0..info.imported_tables.len() + info.tables.len() {
+            tables.push(match TableIndex::new(index).local_or_import(info) {
+                LocalOrImport::Local(local) => VmIndex::Local(local.index()),
+                LocalOrImport::Import(import) => VmIndex::Import(import.index()),
+            });
+        }
+
+        let mut code = Arc::new(Code {
+            functions: functions.into_boxed_slice(),
+            signatures: info
+                .signatures
+                .iter()
+                .map(|(_, sig)| Signature::new(sig))
+                .collect(),
+            memory,
+            tables,
+        });
+
+        let code_ptr = &*code as *const Code;
+        for function in Arc::get_mut(&mut code).unwrap().functions.iter_mut() {
+            function.code = code_ptr;
+            function.name = info.func_names.get(&function.index).cloned();
+        }
+
+        let (start, end) = code.range();
+        // Registered so that they can be told apart from native functions.
+        register_non_native_code(start, end);
+        code
+    }
+
+    fn range(&self) -> (usize, usize) {
+        let start = self.functions.as_ptr() as usize;
+        (
+            start,
+            start + self.functions.len() * mem::size_of::<Function>(),
+        )
+    }
+
+    pub fn function(&self, index: LocalFuncIndex) -> &Function {
+        &self.functions[index.index()]
+    }
+
+    /// The function that `func` points to, if it's a function of this
+    /// module.
+    fn get(&self, func: *const vm::Func) -> Option<&Function> {
+        let (start, end) = self.range();
+        let addr = func as usize;
+        if start <= addr && addr < end {
+            Some(&self.functions[(addr - start) / mem::size_of::<Function>()])
+        } else {
+            None
+        }
+    }
+
+    /// The function that `func` points to, if it's a function of a
+    /// module that the interpreter compiled, rather than a native one.
+    ///
+    /// The module has to be alive, as it is when `func` comes from an
+    /// instance.
+    pub fn lookup(&self, func: *const vm::Func) -> Option<&Function> {
+        if let Some(function) = self.get(func) {
+            return Some(function);
+        }
+
+        let (start, _) = non_native_code_range(func)?;
+        if (func as usize - start) % mem::size_of::<Function>() == 0 {
+            Some(unsafe { &*(func as *const Function) })
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Code {
+    fn drop(&mut self) {
+        let (start, _) = self.range();
+        unregister_non_native_code(start);
+    }
+}
+
+pub struct InterpreterResolver {
+    pub code: Arc<Code>,
+}
+
+impl FuncResolver for InterpreterResolver {
+    fn get(
+        &self,
+        _module: &ModuleInner,
+        local_func_index: LocalFuncIndex,
+    ) -> Option<NonNull<vm::Func>> {
+        let function = self.code.function(local_func_index);
+        NonNull::new(function as *const Function as *mut vm::Func)
+    }
+
+    fn calls_through_ctx(&self) -> bool {
+        true
+    }
+}
+
+pub struct InterpreterCaller {
+    pub code: Arc<Code>,
+}
+
+impl ProtectedCaller for InterpreterCaller {
+    fn call(
+        &self,
+        module: &ModuleInner,
+        func_index: FuncIndex,
+        params: &[Value],
+        _import_backing: &ImportBacking,
+        vmctx: *mut vm::Ctx,
+        _: Token,
+    ) -> RuntimeResult<Vec<Value>> {
+        let signature = &module.info.signatures[module.info.func_assoc[func_index]];
+        let (func, vmctx) = unsafe {
+            match func_index.local_or_import(&module.info) {
+                LocalOrImport::Local(local) => ((*vmctx).local_function(local), vmctx),
+                LocalOrImport::Import(import) => {
+                    let internal = vmctx as *mut vm::InternalCtx;
+                    let imported = &*(*internal).imported_funcs.add(import.index());
+                    (imported.func, imported.vmctx)
+                }
+            }
+        };
+
+        self.call_ptr(func, vmctx, signature, params)
+    }
+
+    fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+        trap::call_protected(None, || f())
+    }
+
+    fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
+        Box::new(trap::Trapper)
+    }
+
+    fn has_native_code(&self) -> bool {
+        false
+    }
+
+    fn call_func(
+        &self,
+        func: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        signature: &FuncSig,
+        params: &[Value],
+        _: Token,
+    ) -> RuntimeResult<Vec<Value>> {
+        self.call_ptr(func, vmctx, signature, params)
+    }
+}
+
+impl InterpreterCaller {
+    /// Call `func`, which is either a function that the interpreter
+    /// compiled or a native one.
+    fn call_ptr(
+        &self,
+        func: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        signature: &FuncSig,
+        params: &[Value],
+    ) -> RuntimeResult<Vec<Value>> {
+        if signature.params().len() != params.len()
+            || signature
+                .params()
+                .iter()
+                .zip(params)
+                .any(|(&ty, param)| param.ty() != ty)
+        {
+            return Err(RuntimeError::Trap {
+                code: TrapCode::Unknown,
+                msg: "signature mismatch".into(),
+                backtrace: Vec::new(),
+            });
+        }
+
+        let args: Vec<u64> = params.iter().map(to_slot).collect();
+        let rets = match self.code.lookup(func) {
+            Some(function) => unsafe { interpret::call(function, vmctx, &args)? },
+            None => unsafe { Signature::new(signature).call(func, vmctx, &args)? },
+        };
+
+        Ok(signature
+            .returns()
+            .iter()
+            .zip(rets)
+            .map(|(&ty, slot)| from_slot(ty, slot))
+            .collect())
+    }
+}
+
+fn to_slot(value: &Value) -> u64 {
+    match *value {
+        Value::I32(x) => u64::from(x as u32),
+        Value::I64(x) => x as u64,
+        Value::F32(x) => u64::from(x.to_bits()),
+        Value::F64(x) => x.to_bits(),
+        Value::ExternRef(x) => x.to_bits(),
+        Value::FuncRef(x) => x.to_bits(),
+    }
+}
+
+fn from_slot(ty: Type, slot: u64) -> Value {
+    match ty {
+        Type::I32 => Value::I32(slot as i32),
+        Type::I64 => Value::I64(slot as i64),
+        Type::F32 => Value::F32(f32::from_bits(slot as u32)),
+        Type::F64 => Value::F64(f64::from_bits(slot)),
+        Type::ExternRef => Value::ExternRef(ExternRef::from_bits(slot)),
+        Type::FuncRef => Value::FuncRef(FuncRef::from_bits(slot)),
+    }
+}
+
+impl Function {
+    pub fn code(&self) -> &Code {
+        unsafe { &*self.code }
+    }
+
+    /// A function that isn't in a module yet, for translation to fill.
+    pub fn new(index: FuncIndex, param_count: usize, return_count: usize) -> Self {
+        Function {
+            index,
+            name: None,
+            param_count,
+            local_count: 0,
+            return_count,
+            ops: vec![],
+            offsets: vec![],
+            br_table: vec![],
+            code: ptr::null(),
+        }
+    }
+}
@@ -0,0 +1,1129 @@
+//! Running translated functions.
+//!
+//! Calls between interpreted functions don't recurse: every call
+//! pushes a frame onto a stack of its own, so a deep recursion in
+//! WebAssembly doesn't use up the native stack. Only calls to native
+//! functions, through `native`, leave the interpreter.
+
+use crate::{
+    code::{Code, Function, VmIndex},
+    ops::{BrTarget, LoadOp, Op, StoreOp},
+};
+use std::{ptr, sync::atomic::Ordering};
+use wasmer_runtime_core::{
+    error::{RuntimeError, RuntimeResult, TrapCode, WasmFrame},
+    memory::MemoryType,
+    structures::TypedIndex,
+    types::{
+        ImportedMemoryIndex, LocalFuncIndex, LocalMemoryIndex, MemoryIndex, SigIndex, TableIndex,
+    },
+    units::Pages,
+    vm, vmcalls,
+};
+
+/// The most calls that can be in progress at once. Deeper recursion
+/// traps with a stack overflow.
+const MAX_CALL_DEPTH: usize = 65_536;
+
+/// The canonical NaNs, which deterministic execution replaces every
+/// NaN that arithmetic produces with.
+const CANONICAL_NAN_F32: u32 = 0x7FC0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7FF8_0000_0000_0000;
+
+struct Frame {
+    function: *const Function,
+    /// The index of the next instruction.
+    pc: usize,
+    /// Where the locals of the function start on the stack.
+    fp: usize,
+    vmctx: *mut vm::Ctx,
+}
+
+struct Machine {
+    stack: Vec<u64>,
+    /// The frames of the callers of the current function.
+    frames: Vec<Frame>,
+    frame: Frame,
+}
+
+/// Call `function` with `args` and return the values it returns.
+pub unsafe fn call(
+    function: &Function,
+    vmctx: *mut vm::Ctx,
+    args: &[u64],
+) -> RuntimeResult<Vec<u64>> {
+    let mut stack = Vec::with_capacity(1024);
+    stack.extend_from_slice(args);
+    stack.resize(args.len() + function.local_count, 0);
+
+    let mut machine = Machine {
+        stack,
+        frames: vec![],
+        frame: Frame {
+            function,
+            pc: 0,
+            fp: 0,
+            vmctx,
+        },
+    };
+
+    match machine.run() {
+        Ok(()) => Ok(machine.stack),
+        Err(error) => {
+            let has_backtrace = match error {
+                RuntimeError::Trap { ref backtrace, .. } => !backtrace.is_empty(),
+                _ => true,
+            };
+            if has_backtrace {
+                Err(error)
+            } else {
+                Err(error.with_backtrace(machine.backtrace()))
+            }
+        }
+    }
+}
+
+impl Machine {
+    /// The frames of the functions being run, innermost first.
+    unsafe fn backtrace(&self) -> Vec<WasmFrame> {
+        Some(&self.frame)
+            .into_iter()
+            .chain(self.frames.iter().rev())
+            .map(|frame| {
+                let function = &*frame.function;
+                WasmFrame {
+                    func_index: function.index,
+                    func_name: function.name.clone(),
+                    // The instruction being run, or the call.
+                    offset: function.offsets.get(frame.pc.wrapping_sub(1)).cloned(),
+                }
+            })
+            .collect()
+    }
+
+    /// Run until the function that was called first returns, leaving
+    /// the values that it returns on the stack.
+    unsafe fn run(&mut self) -> RuntimeResult<()> {
+        macro_rules! trap {
+            ($code:ident) => {
+                return Err(RuntimeError::trap(TrapCode::$code))
+            };
+        }
+        macro_rules! pop {
+            () => {
+                self.stack.pop().unwrap()
+            };
+        }
+        macro_rules! push {
+            ($value:expr) => {
+                self.stack.push($value)
+            };
+        }
+        macro_rules! top {
+            () => {
+                self.stack.last_mut().unwrap()
+            };
+        }
+        macro_rules! i32_unop {
+            (|$a:ident| $e:expr) => {{
+                let $a = pop!() as u32;
+                push!(u64::from($e));
+            }};
+        }
+        macro_rules! i32_binop {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop!() as u32;
+                let $a = pop!() as u32;
+                push!(u64::from($e));
+            }};
+        }
+        macro_rules! i64_unop {
+            (|$a:ident| $e:expr) => {{
+                let $a = pop!();
+                push!($e);
+            }};
+        }
+        macro_rules! i64_binop {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop!();
+                let $a = pop!();
+                push!($e);
+            }};
+        }
+        macro_rules! f32_unop {
+            (|$a:ident| $e:expr) => {{
+                let $a = f32::from_bits(pop!() as u32);
+                push!(u64::from(($e).to_bits()));
+            }};
+        }
+        macro_rules! f32_binop {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = f32::from_bits(pop!() as u32);
+                let $a = f32::from_bits(pop!() as u32);
+                push!(u64::from(($e).to_bits()));
+            }};
+        }
+        macro_rules! f64_unop {
+            (|$a:ident| $e:expr) => {{
+                let $a = f64::from_bits(pop!());
+                push!(($e).to_bits());
+            }};
+        }
+        macro_rules! f64_binop {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = f64::from_bits(pop!());
+                let $a = f64::from_bits(pop!());
+                push!(($e).to_bits());
+            }};
+        }
+        macro_rules! cmp {
+            ($from:expr, |$a:ident, $b:ident| $e:expr) => {{
+                let $b = $from(pop!());
+                let $a = $from(pop!());
+                push!($e as u64);
+            }};
+        }
+        macro_rules! convert {
+            ($from:expr, |$a:ident| $e:expr) => {{
+                let $a = $from(pop!());
+                push!($e);
+            }};
+        }
+        macro_rules! trunc {
+            ($from:expr, $to:ident) => {{
+                let x = f64::from($from(pop!()));
+                match $to(x) {
+                    Ok(value) => push!(value),
+                    Err(code) => return Err(RuntimeError::trap(code)),
+                }
+            }};
+        }
+
+        loop {
+            let function = &*self.frame.function;
+            let code = function.code();
+            let op = function.ops[self.frame.pc];
+            self.frame.pc += 1;
+
+            match op {
+                Op::Unreachable => trap!(Unreachable),
+                Op::Br(target) => self.branch(target),
+                Op::BrIf(target) => {
+                    if pop!() as u32 != 0 {
+                        self.branch(target);
+                    }
+                }
+                Op::BrUnless { pc } => {
+                    if pop!() as u32 == 0 {
+                        self.frame.pc = pc as usize;
+                    }
+                }
+                Op::BrTable { start, len } => {
+                    let index = (pop!() as u32).min(len - 1);
+                    self.branch(function.br_table[(start + index) as usize]);
+                }
+                Op::Return => {
+                    let fp = self.frame.fp;
+                    let returns = self.stack.len() - function.return_count;
+                    self.keep(fp, returns);
+                    match self.frames.pop() {
+                        Some(frame) => self.frame = frame,
+                        None => return Ok(()),
+                    }
+                }
+                Op::Call { index, sig } => {
+                    let vmctx = self.frame.vmctx;
+                    let func = (*vmctx).local_function(LocalFuncIndex::new(index as usize));
+                    self.call(code, func, vmctx, sig)?;
+                }
+                Op::CallImport { index, sig } => {
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    let imported = &*(*internal).imported_funcs.add(index as usize);
+                    self.call(code, imported.func, imported.vmctx, sig)?;
+                }
+                Op::CallIndirect { sig, table } => {
+                    let index = pop!() as u32 as usize;
+                    let table = self.table(code, table);
+                    if index >= (*table).count {
+                        trap!(TableOutOfBounds);
+                    }
+                    let anyfunc = *((*table).base as *const vm::Anyfunc).add(index);
+                    if anyfunc.func.is_null() {
+                        trap!(IndirectCallToNull);
+                    }
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    let expected = *(*internal).dynamic_sigindices.add(sig as usize);
+                    if anyfunc.sig_id.0 != expected.0 {
+                        trap!(BadSignature);
+                    }
+                    self.call(code, anyfunc.func, anyfunc.ctx, sig)?;
+                }
+                Op::Enter { cost } => {
+                    // The runtime reports this trap as running out of gas.
+                    if !self.charge(cost) {
+                        trap!(Unknown);
+                    }
+                    if self.interrupted() {
+                        trap!(Interrupted);
+                    }
+                    if self.deadline_reached() {
+                        trap!(DeadlineExceeded);
+                    }
+                }
+                Op::Loop { cost } => {
+                    // The runtime reports this trap as running out of gas.
+                    if !self.charge(cost) {
+                        trap!(Unknown);
+                    }
+                    if self.interrupted() {
+                        trap!(Interrupted);
+                    }
+                    if self.deadline_reached() {
+                        trap!(DeadlineExceeded);
+                    }
+                }
+
+                Op::Drop => {
+                    pop!();
+                }
+                Op::Select => {
+                    let condition = pop!() as u32;
+                    let b = pop!();
+                    let a = pop!();
+                    push!(if condition != 0 { a } else { b });
+                }
+
+                Op::LocalGet(index) => {
+                    let value = self.stack[self.frame.fp + index as usize];
+                    push!(value);
+                }
+                Op::LocalSet(index) => {
+                    let value = pop!();
+                    let fp = self.frame.fp;
+                    self.stack[fp + index as usize] = value;
+                }
+                Op::LocalTee(index) => {
+                    let value = *top!();
+                    let fp = self.frame.fp;
+                    self.stack[fp + index as usize] = value;
+                }
+                Op::GlobalGet(index) => {
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    push!((**(*internal).globals.add(index as usize)).data);
+                }
+                Op::GlobalSet(index) => {
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    (**(*internal).globals.add(index as usize)).data = pop!();
+                }
+                Op::ImportedGlobalGet(index) => {
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    push!((**(*internal).imported_globals.add(index as usize)).data);
+                }
+                Op::ImportedGlobalSet(index) => {
+                    let internal = self.frame.vmctx as *mut vm::InternalCtx;
+                    (**(*internal).imported_globals.add(index as usize)).data = pop!();
+                }
+
+                Op::Load { op, offset } => {
+                    let size = match op {
+                        LoadOp::I32S8 | LoadOp::I32U8 | LoadOp::I64S8 | LoadOp::I64U8 => 1,
+                        LoadOp::I32S16 | LoadOp::I32U16 | LoadOp::I64S16 | LoadOp::I64U16 => 2,
+                        LoadOp::I32 | LoadOp::I64S32 | LoadOp::I64U32 => 4,
+                        LoadOp::I64 => 8,
+                    };
+                    let addr = pop!();
+                    let ptr = match self.address(code, addr, offset, size) {
+                        Some(ptr) => ptr,
+                        None => trap!(MemoryOutOfBounds),
+                    };
+                    push!(match op {
+                        LoadOp::I32 | LoadOp::I64U32 => u64::from(read_u32(ptr)),
+                        LoadOp::I64 => read_u64(ptr),
+                        LoadOp::I32S8 => u64::from(*ptr as i8 as i32 as u32),
+                        LoadOp::I32U8 | LoadOp::I64U8 => u64::from(*ptr),
+                        LoadOp::I32S16 => u64::from(read_u16(ptr) as i16 as i32 as u32),
+                        LoadOp::I32U16 | LoadOp::I64U16 => u64::from(read_u16(ptr)),
+                        LoadOp::I64S8 => *ptr as i8 as i64 as u64,
+                        LoadOp::I64S16 => read_u16(ptr) as i16 as i64 as u64,
+                        LoadOp::I64S32 => read_u32(ptr) as i32 as i64 as u64,
+                    });
+                }
+                Op::Store { op, offset } => {
+                    let size = match op {
+                        StoreOp::B8 => 1,
+                        StoreOp::B16 => 2,
+                        StoreOp::B32 => 4,
+                        StoreOp::B64 => 8,
+                    };
+                    let value = pop!();
+                    let addr = pop!();
+                    let ptr = match self.address(code, addr, offset, size) {
+                        Some(ptr) => ptr,
+                        None => trap!(MemoryOutOfBounds),
+                    };
+                    match op {
+                        StoreOp::B8 => *ptr = value as u8,
+                        StoreOp::B16 => {
+                            ptr::write_unaligned(ptr as *mut u16, (value as u16).to_le())
+                        }
+                        StoreOp::B32 => {
+                            ptr::write_unaligned(ptr as *mut u32, (value as u32).to_le())
+                        }
+                        StoreOp::B64 => ptr::write_unaligned(ptr as *mut u64, value.to_le()),
+                    }
+                }
+                Op::MemorySize => {
+                    let size = self.memory_size(code);
+                    push!(u64::from(size));
+                }
+                Op::MemoryGrow => {
+                    let delta = pop!() as u32;
+                    let old = self.memory_grow(code, delta);
+                    push!(u64::from(old as u32));
+                }
+                Op::MemoryInit { segment } => {
+                    let len = pop!() as u32;
+                    let src = pop!() as u32;
+                    let dst = pop!() as u32;
+                    let vmctx = &mut *self.frame.vmctx;
+                    if vmcalls::memory_init(vmctx, MemoryIndex::new(0), segment, dst, src, len) != 0
+                    {
+                        trap!(MemoryOutOfBounds);
+                    }
+                }
+                Op::DataDrop { segment } => vmcalls::data_drop(&mut *self.frame.vmctx, segment),
+                Op::MemoryCopy => {
+                    let len = pop!() as u32;
+                    let src = pop!() as u32;
+                    let dst = pop!() as u32;
+                    let vmctx = &mut *self.frame.vmctx;
+                    if vmcalls::memory_copy(vmctx, MemoryIndex::new(0), dst, src, len) != 0 {
+                        trap!(MemoryOutOfBounds);
+                    }
+                }
+                Op::MemoryFill => {
+                    let len = pop!() as u32;
+                    let value = pop!() as u32;
+                    let dst = pop!() as u32;
+                    let vmctx = &mut *self.frame.vmctx;
+                    if vmcalls::memory_fill(vmctx, MemoryIndex::new(0), dst, value, len) != 0 {
+                        trap!(MemoryOutOfBounds);
+                    }
+                }
+                Op::TableInit { segment } => {
+                    let len = pop!() as u32;
+                    let src = pop!() as u32;
+                    let dst = pop!() as u32;
+                    let vmctx = &mut *self.frame.vmctx;
+                    if vmcalls::table_init(vmctx, TableIndex::new(0), segment, dst, src, len) != 0 {
+                        trap!(TableOutOfBounds);
+                    }
+                }
+                Op::ElemDrop { segment } => vmcalls::elem_drop(&mut *self.frame.vmctx, segment),
+                Op::TableGet { table } => {
+                    let index = pop!() as u32;
+                    if index as usize >= (*self.table(code, table)).count {
+                        trap!(TableOutOfBounds);
+                    }
+                    let vmctx = &mut *self.frame.vmctx;
+                    push!(vmcalls::table_get(
+                        vmctx,
+                        TableIndex::new(table as usize),
+                        index
+                    ));
+                }
+                Op::TableSet { table } => {
+                    let value = pop!();
+                    let index = pop!() as u32;
+                    if index as usize >= (*self.table(code, table)).count {
+                        trap!(TableOutOfBounds);
+                    }
+                    let vmctx = &mut *self.frame.vmctx;
+                    vmcalls::table_set(vmctx, TableIndex::new(table as usize), index, value);
+                }
+
+                Op::Const(value) => push!(value),
+
+                Op::I32Eqz => i32_unop!(|a| (a == 0) as u32),
+                Op::I32Eq => cmp!(as_u32, |a, b| a == b),
+                Op::I32Ne => cmp!(as_u32, |a, b| a != b),
+                Op::I32LtS => cmp!(as_i32, |a, b| a < b),
+                Op::I32LtU => cmp!(as_u32, |a, b| a < b),
+                Op::I32GtS => cmp!(as_i32, |a, b| a > b),
+                Op::I32GtU => cmp!(as_u32, |a, b| a > b),
+                Op::I32LeS => cmp!(as_i32, |a, b| a <= b),
+                Op::I32LeU => cmp!(as_u32, |a, b| a <= b),
+                Op::I32GeS => cmp!(as_i32, |a, b| a >= b),
+                Op::I32GeU => cmp!(as_u32, |a, b| a >= b),
+                Op::I64Eqz => i64_unop!(|a| (a == 0) as u64),
+                Op::I64Eq => cmp!(as_u64, |a, b| a == b),
+                Op::I64Ne => cmp!(as_u64, |a, b| a != b),
+                Op::I64LtS => cmp!(as_i64, |a, b| a < b),
+                Op::I64LtU => cmp!(as_u64, |a, b| a < b),
+                Op::I64GtS => cmp!(as_i64, |a, b| a > b),
+                Op::I64GtU => cmp!(as_u64, |a, b| a > b),
+                Op::I64LeS => cmp!(as_i64, |a, b| a <= b),
+                Op::I64LeU => cmp!(as_u64, |a, b| a <= b),
+                Op::I64GeS => cmp!(as_i64, |a, b| a >= b),
+                Op::I64GeU => cmp!(as_u64, |a, b| a >= b),
+                Op::F32Eq => cmp!(as_f32, |a, b| a == b),
+                Op::F32Ne => cmp!(as_f32, |a, b| a != b),
+                Op::F32Lt => cmp!(as_f32, |a, b| a < b),
+                Op::F32Gt => cmp!(as_f32, |a, b| a > b),
+                Op::F32Le => cmp!(as_f32, |a, b| a <= b),
+                Op::F32Ge => cmp!(as_f32, |a, b| a >= b),
+                Op::F64Eq => cmp!(as_f64, |a, b| a == b),
+                Op::F64Ne => cmp!(as_f64, |a, b| a != b),
+                Op::F64Lt => cmp!(as_f64, |a, b| a < b),
+                Op::F64Gt => cmp!(as_f64, |a, b| a > b),
+                Op::F64Le => cmp!(as_f64, |a, b| a <= b),
+                Op::F64Ge => cmp!(as_f64, |a, b| a >= b),
+
+                Op::I32Clz => i32_unop!(|a| a.leading_zeros()),
+                Op::I32Ctz => i32_unop!(|a| a.trailing_zeros()),
+                Op::I32Popcnt => i32_unop!(|a| a.count_ones()),
+                Op::I32Add => i32_binop!(|a, b| a.wrapping_add(b)),
+                Op::I32Sub => i32_binop!(|a, b| a.wrapping_sub(b)),
+                Op::I32Mul => i32_binop!(|a, b| a.wrapping_mul(b)),
+                Op::I32DivS => {
+                    let b = pop!() as i32;
+                    let a = pop!() as i32;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    if a == i32::min_value() && b == -1 {
+                        trap!(IntegerOverflow);
+                    }
+                    push!(u64::from((a / b) as u32));
+                }
+                Op::I32DivU => {
+                    let b = pop!() as u32;
+                    let a = pop!() as u32;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(u64::from(a / b));
+                }
+                Op::I32RemS => {
+                    let b = pop!() as i32;
+                    let a = pop!() as i32;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(u64::from(a.wrapping_rem(b) as u32));
+                }
+                Op::I32RemU => {
+                    let b = pop!() as u32;
+                    let a = pop!() as u32;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(u64::from(a % b));
+                }
+                Op::I32And => i32_binop!(|a, b| a & b),
+                Op::I32Or => i32_binop!(|a, b| a | b),
+                Op::I32Xor => i32_binop!(|a, b| a ^ b),
+                Op::I32Shl => i32_binop!(|a, b| a.wrapping_shl(b)),
+                Op::I32ShrS => i32_binop!(|a, b| (a as i32).wrapping_shr(b) as u32),
+                Op::I32ShrU => i32_binop!(|a, b| a.wrapping_shr(b)),
+                Op::I32Rotl => i32_binop!(|a, b| a.rotate_left(b % 32)),
+                Op::I32Rotr => i32_binop!(|a, b| a.rotate_right(b % 32)),
+                Op::I64Clz => i64_unop!(|a| u64::from(a.leading_zeros())),
+                Op::I64Ctz => i64_unop!(|a| u64::from(a.trailing_zeros())),
+                Op::I64Popcnt => i64_unop!(|a| u64::from(a.count_ones())),
+                Op::I64Add => i64_binop!(|a, b| a.wrapping_add(b)),
+                Op::I64Sub => i64_binop!(|a, b| a.wrapping_sub(b)),
+                Op::I64Mul => i64_binop!(|a, b| a.wrapping_mul(b)),
+                Op::I64DivS => {
+                    let b = pop!() as i64;
+                    let a = pop!() as i64;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    if a == i64::min_value() && b == -1 {
+                        trap!(IntegerOverflow);
+                    }
+                    push!((a / b) as u64);
+                }
+                Op::I64DivU => {
+                    let b = pop!();
+                    let a = pop!();
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(a / b);
+                }
+                Op::I64RemS => {
+                    let b = pop!() as i64;
+                    let a = pop!() as i64;
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(a.wrapping_rem(b) as u64);
+                }
+                Op::I64RemU => {
+                    let b = pop!();
+                    let a = pop!();
+                    if b == 0 {
+                        trap!(IntegerDivisionByZero);
+                    }
+                    push!(a % b);
+                }
+                Op::I64And => i64_binop!(|a, b| a & b),
+                Op::I64Or => i64_binop!(|a, b| a | b),
+                Op::I64Xor => i64_binop!(|a, b| a ^ b),
+                Op::I64Shl => i64_binop!(|a, b| a.wrapping_shl(b as u32)),
+                Op::I64ShrS => i64_binop!(|a, b| (a as i64).wrapping_shr(b as u32) as u64),
+                Op::I64ShrU => i64_binop!(|a, b| a.wrapping_shr(b as u32)),
+                Op::I64Rotl => i64_binop!(|a, b| a.rotate_left((b % 64) as u32)),
+                Op::I64Rotr => i64_binop!(|a, b| a.rotate_right((b % 64) as u32)),
+
+                // The sign is flipped and copied on the bits, so that
+                // the payloads of NaNs are kept as they are.
+                Op::F32Abs => f32_unop!(|a| f32::from_bits(a.to_bits() & !(1 << 31))),
+                Op::F32Neg => f32_unop!(|a| f32::from_bits(a.to_bits() ^ (1 << 31))),
+                Op::F32Ceil => f32_unop!(|a| a.ceil()),
+                Op::F32Floor => f32_unop!(|a| a.floor()),
+                Op::F32Trunc => f32_unop!(|a| a.trunc()),
+                Op::F32Nearest => f32_unop!(|a| f32_nearest(a)),
+                Op::F32Sqrt => f32_unop!(|a| a.sqrt()),
+                Op::F32Add => f32_binop!(|a, b| a + b),
+                Op::F32Sub => f32_binop!(|a, b| a - b),
+                Op::F32Mul => f32_binop!(|a, b| a * b),
+                Op::F32Div => f32_binop!(|a, b| a / b),
+                Op::F32Min => f32_binop!(|a, b| f32_min(a, b)),
+                Op::F32Max => f32_binop!(|a, b| f32_max(a, b)),
+                Op::F32Copysign => f32_binop!(|a, b| f32::from_bits(
+                    (a.to_bits() & !(1 << 31)) | (b.to_bits() & (1 << 31))
+                )),
+                Op::F64Abs => f64_unop!(|a| f64::from_bits(a.to_bits() & !(1 << 63))),
+                Op::F64Neg => f64_unop!(|a| f64::from_bits(a.to_bits() ^ (1 << 63))),
+                Op::F64Ceil => f64_unop!(|a| a.ceil()),
+                Op::F64Floor => f64_unop!(|a| a.floor()),
+                Op::F64Trunc => f64_unop!(|a| a.trunc()),
+                Op::F64Nearest => f64_unop!(|a| f64_nearest(a)),
+                Op::F64Sqrt => f64_unop!(|a| a.sqrt()),
+                Op::F64Add => f64_binop!(|a, b| a + b),
+                Op::F64Sub => f64_binop!(|a, b| a - b),
+                Op::F64Mul => f64_binop!(|a, b| a * b),
+                Op::F64Div => f64_binop!(|a, b| a / b),
+                Op::F64Min => f64_binop!(|a, b| f64_min(a, b)),
+                Op::F64Max => f64_binop!(|a, b| f64_max(a, b)),
+                Op::F64Copysign => f64_binop!(|a, b| f64::from_bits(
+                    (a.to_bits() & !(1 << 63)) | (b.to_bits() & (1 << 63))
+                )),
+
+                Op::I32WrapI64 => convert!(as_u32, |a| u64::from(a)),
+                Op::I32TruncF32S => trunc!(as_f32, trunc_i32),
+                Op::I32TruncF32U => trunc!(as_f32, trunc_u32),
+                Op::I32TruncF64S => trunc!(as_f64, trunc_i32),
+                Op::I32TruncF64U => trunc!(as_f64, trunc_u32),
+                Op::I64ExtendI32S => convert!(as_i32, |a| i64::from(a) as u64),
+                Op::I64ExtendI32U => convert!(as_u32, |a| u64::from(a)),
+                Op::I64TruncF32S => trunc!(as_f32, trunc_i64),
+                Op::I64TruncF32U => trunc!(as_f32, trunc_u64),
+                Op::I64TruncF64S => trunc!(as_f64, trunc_i64),
+                Op::I64TruncF64U => trunc!(as_f64, trunc_u64),
+                Op::F32ConvertI32S => convert!(as_i32, |a| f32_slot(a as f32)),
+                Op::F32ConvertI32U => convert!(as_u32, |a| f32_slot(a as f32)),
+                Op::F32ConvertI64S => convert!(as_i64, |a| f32_slot(a as f32)),
+                Op::F32ConvertI64U => convert!(as_u64, |a| f32_slot(a as f32)),
+                Op::F32DemoteF64 => convert!(as_f64, |a| f32_slot(a as f32)),
+                Op::F64ConvertI32S => convert!(as_i32, |a| f64::from(a).to_bits()),
+                Op::F64ConvertI32U => convert!(as_u32, |a| f64::from(a).to_bits()),
+                Op::F64ConvertI64S => convert!(as_i64, |a| (a as f64).to_bits()),
+                Op::F64ConvertI64U => convert!(as_u64, |a| (a as f64).to_bits()),
+                Op::F64PromoteF32 => convert!(as_f32, |a| f64::from(a).to_bits()),
+                Op::I32TruncSatF32S => convert!(as_f32, |a| sat_i32(f64::from(a))),
+                Op::I32TruncSatF32U => convert!(as_f32, |a| sat_u32(f64::from(a))),
+                Op::I32TruncSatF64S => convert!(as_f64, |a| sat_i32(a)),
+                Op::I32TruncSatF64U => convert!(as_f64, |a| sat_u32(a)),
+                Op::I64TruncSatF32S => convert!(as_f32, |a| sat_i64(f64::from(a))),
+                Op::I64TruncSatF32U => convert!(as_f32, |a| sat_u64(f64::from(a))),
+                Op::I64TruncSatF64S => convert!(as_f64, |a| sat_i64(a)),
+                Op::I64TruncSatF64U => convert!(as_f64, |a| sat_u64(a)),
+
+                Op::CanonicalizeF32 => {
+                    let top = top!();
+                    if as_f32(*top).is_nan() {
+                        *top = u64::from(CANONICAL_NAN_F32);
+                    }
+                }
+                Op::CanonicalizeF64 => {
+                    let top = top!();
+                    if as_f64(*top).is_nan() {
+                        *top = CANONICAL_NAN_F64;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move the `returns` values on top of the stack down to `base`,
+    /// removing what's between.
+    fn keep(&mut self, base: usize, from: usize) {
+        if base != from {
+            for i in 0..self.stack.len() - from {
+                self.stack[base + i] = self.stack[from + i];
+            }
+            let len = self.stack.len() - (from - base);
+            self.stack.truncate(len);
+        }
+    }
+
+    fn branch(&mut self, target: BrTarget) {
+        let from = self.stack.len() - target.keep as usize;
+        self.keep(from - target.drop as usize, from);
+        self.frame.pc = target.pc as usize;
+    }
+
+    /// Call `func` from a function of `code`, in the instance of
+    /// `vmctx`. Its arguments are on top of the stack, and the values
+    /// it returns are left there.
+    unsafe fn call(
+        &mut self,
+        code: &Code,
+        func: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        sig: u32,
+    ) -> RuntimeResult<()> {
+        match code.lookup(func) {
+            Some(callee) => {
+                if self.frames.len() >= MAX_CALL_DEPTH {
+                    return Err(RuntimeError::trap(TrapCode::StackOverflow));
+                }
+                let fp = self.stack.len() - callee.param_count;
+                let locals_end = self.stack.len() + callee.local_count;
+                self.stack.resize(locals_end, 0);
+                let caller = std::mem::replace(
+                    &mut self.frame,
+                    Frame {
+                        function: callee,
+                        pc: 0,
+                        fp,
+                        vmctx,
+                    },
+                );
+                self.frames.push(caller);
+            }
+            None => {
+                let signature = &code.signatures[SigIndex::new(sig as usize)];
+                let args = self.stack.len() - signature.param_count();
+                let ret = signature.call(func, vmctx, &self.stack[args..])?;
+                self.stack.truncate(args);
+                self.stack.extend(ret);
+            }
+        }
+        Ok(())
+    }
+
+    /// Take `cost` points from the instance, and return whether it
+    /// has any left.
+    unsafe fn charge(&mut self, cost: u64) -> bool {
+        if cost == 0 {
+            return true;
+        }
+        let internal = self.frame.vmctx as *mut vm::InternalCtx;
+        (*internal).remaining_points = (*internal).remaining_points.wrapping_sub(cost as i64);
+        (*internal).remaining_points >= 0
+    }
+
+    /// Whether the instance has been interrupted.
+    unsafe fn interrupted(&self) -> bool {
+        let internal = self.frame.vmctx as *mut vm::InternalCtx;
+        (*(*internal).interrupted).load(Ordering::SeqCst)
+    }
+
+    /// Whether the epoch deadline of the instance has been reached.
+    unsafe fn deadline_reached(&self) -> bool {
+        let internal = self.frame.vmctx as *mut vm::InternalCtx;
+        (*(*internal).epoch).load(Ordering::SeqCst) >= (*internal).epoch_deadline
+    }
+
+    unsafe fn memory(&self, code: &Code) -> *mut vm::LocalMemory {
+        let internal = self.frame.vmctx as *mut vm::InternalCtx;
+        match code.memory.expect("the module has no memory").0 {
+            VmIndex::Local(index) => *(*internal).memories.add(index),
+            VmIndex::Import(index) => *(*internal).imported_memories.add(index),
+        }
+    }
+
+    /// Where in memory `size` bytes at `offset` from `addr` are, if
+    /// they're all in bounds.
+    unsafe fn address(&self, code: &Code, addr: u64, offset: u32, size: u64) -> Option<*mut u8> {
+        let memory = self.memory(code);
+        let start = u64::from(addr as u32) + u64::from(offset);
+        if start + size > (*memory).bound as u64 {
+            return None;
+        }
+        Some((*memory).base.add(start as usize))
+    }
+
+    unsafe fn memory_size(&self, code: &Code) -> u32 {
+        let vmctx = &*self.frame.vmctx;
+        let (index, ty) = code.memory.expect("the module has no memory");
+        let Pages(pages) = match (index, ty) {
+            (VmIndex::Local(i), MemoryType::Dynamic) => {
+                vmcalls::local_dynamic_memory_size(vmctx, LocalMemoryIndex::new(i))
+            }
+            (VmIndex::Local(i), MemoryType::Static) => {
+                vmcalls::local_static_memory_size(vmctx, LocalMemoryIndex::new(i))
+            }
+            (VmIndex::Local(i), MemoryType::SharedStatic) => {
+                vmcalls::local_shared_static_memory_size(vmctx, LocalMemoryIndex::new(i))
+            }
+            (VmIndex::Import(i), MemoryType::Dynamic) => {
+                vmcalls::imported_dynamic_memory_size(vmctx, ImportedMemoryIndex::new(i))
+            }
+            (VmIndex::Import(i), MemoryType::Static) => {
+                vmcalls::imported_static_memory_size(vmctx, ImportedMemoryIndex::new(i))
+            }
+            (VmIndex::Import(i), MemoryType::SharedStatic) => {
+                vmcalls::imported_shared_static_memory_size(vmctx, ImportedMemoryIndex::new(i))
+            }
+        };
+        pages
+    }
+
+    unsafe fn memory_grow(&self, code: &Code, delta: u32) -> i32 {
+        let vmctx = &mut *self.frame.vmctx;
+        let delta = Pages(delta);
+        match code.memory.expect("the module has no memory") {
+            (VmIndex::Local(i), MemoryType::Dynamic) => {
+                vmcalls::local_dynamic_memory_grow(vmctx, LocalMemoryIndex::new(i), delta)
+            }
+            (VmIndex::Local(i), MemoryType::Static) => {
+                vmcalls::local_static_memory_grow(vmctx, LocalMemoryIndex::new(i), delta)
+            }
+            (VmIndex::Local(i), MemoryType::SharedStatic) => {
+                vmcalls::local_shared_static_memory_grow(vmctx, LocalMemoryIndex::new(i), delta)
+            }
+            (VmIndex::Import(i), MemoryType::Dynamic) => {
+                vmcalls::imported_dynamic_memory_grow(vmctx, ImportedMemoryIndex::new(i), delta)
+            }
+            (VmIndex::Import(i), MemoryType::Static) => {
+                vmcalls::imported_static_memory_grow(vmctx, ImportedMemoryIndex::new(i), delta)
+            }
+            (VmIndex::Import(i), MemoryType::SharedStatic) => {
+                vmcalls::imported_shared_static_memory_grow(
+                    vmctx,
+                    ImportedMemoryIndex::new(i),
+                    delta,
+                )
+            }
+        }
+    }
+
+    unsafe fn table(&self, code: &Code, table: u32) -> *mut vm::LocalTable {
+        let internal = self.frame.vmctx as *mut vm::InternalCtx;
+        match code.tables[TableIndex::new(table as usize)] {
+            VmIndex::Local(index) => *(*internal).tables.add(index),
+            VmIndex::Import(index) => *(*internal).imported_tables.add(index),
+        }
+    }
+}
+
+unsafe fn read_u16(ptr: *const u8) -> u16 {
+    u16::from_le(ptr::read_unaligned(ptr as *const u16))
+}
+
+unsafe fn read_u32(ptr: *const u8) -> u32 {
+    u32::from_le(ptr::read_unaligned(ptr as *const u32))
+}
+
+unsafe fn read_u64(ptr: *const u8) -> u64 {
+    u64::from_le(ptr::read_unaligned(ptr as *const u64))
+}
+
+fn as_u32(slot: u64) -> u32 {
+    slot as u32
+}
+
+fn as_i32(slot: u64) -> i32 {
+    slot as i32
+}
+
+fn as_u64(slot: u64) -> u64 {
+    slot
+}
+
+fn as_i64(slot: u64) -> i64 {
+    slot as i64
+}
+
+fn as_f32(slot: u64) -> f32 {
+    f32::from_bits(slot as u32)
+}
+
+fn as_f64(slot: u64) -> f64 {
+    f64::from_bits(slot)
+}
+
+fn f32_slot(x: f32) -> u64 {
+    u64::from(x.to_bits())
+}
+
+// `min` and `max` are NaN if either operand is, and tell apart the
+// zeros of either sign.
+
+fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+/// Round to the nearest integer, and to the even one on a tie.
+fn f32_nearest(x: f32) -> f32 {
+    let rounded = x.round();
+    if (rounded - x).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        rounded
+    }
+}
+
+fn f64_nearest(x: f64) -> f64 {
+    let rounded = x.round();
+    if (rounded - x).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        rounded
+    }
+}
+
+// Every `f32` is exactly an `f64`, so truncation works on `f64`s. The
+// bounds are the first values past either end of the range, which are
+// all exact.
+
+fn trunc_i32(x: f64) -> Result<u64, TrapCode> {
+    if x.is_nan() {
+        Err(TrapCode::BadConversionToInteger)
+    } else if x <= -2_147_483_649.0 || x >= 2_147_483_648.0 {
+        Err(TrapCode::IntegerOverflow)
+    } else {
+        Ok(u64::from(x as i32 as u32))
+    }
+}
+
+fn trunc_u32(x: f64) -> Result<u64, TrapCode> {
+    if x.is_nan() {
+        Err(TrapCode::BadConversionToInteger)
+    } else if x <= -1.0 || x >= 4_294_967_296.0 {
+        Err(TrapCode::IntegerOverflow)
+    } else {
+        Ok(u64::from(x as u32))
+    }
+}
+
+fn trunc_i64(x: f64) -> Result<u64, TrapCode> {
+    if x.is_nan() {
+        Err(TrapCode::BadConversionToInteger)
+    } else if x < -9_223_372_036_854_775_808.0 || x >= 9_223_372_036_854_775_808.0 {
+        Err(TrapCode::IntegerOverflow)
+    } else {
+        Ok(x as i64 as u64)
+    }
+}
+
+fn trunc_u64(x: f64) -> Result<u64, TrapCode> {
+    if x.is_nan() {
+        Err(TrapCode::BadConversionToInteger)
+    } else if x <= -1.0 || x >= 18_446_744_073_709_551_616.0 {
+        Err(TrapCode::IntegerOverflow)
+    } else {
+        Ok(x as u64)
+    }
+}
+
+// The saturating truncations give zero for NaN and the nearest bound
+// for values out of range.
+
+fn sat_i32(x: f64) -> u64 {
+    let value = if x.is_nan() {
+        0
+    } else if x <= -2_147_483_649.0 {
+        i32::min_value()
+    } else if x >= 2_147_483_648.0 {
+        i32::max_value()
+    } else {
+        x as i32
+    };
+    u64::from(value as u32)
+}
+
+fn sat_u32(x: f64) -> u64 {
+    let value = if x.is_nan() || x <= -1.0 {
+        0
+    } else if x >= 4_294_967_296.0 {
+        u32::max_value()
+    } else {
+        x as u32
+    };
+    u64::from(value)
+}
+
+fn sat_i64(x: f64) -> u64 {
+    let value = if x.is_nan() {
+        0
+    } else if x < -9_223_372_036_854_775_808.0 {
+        i64::min_value()
+    } else if x >= 9_223_372_036_854_775_808.0 {
+        i64::max_value()
+    } else {
+        x as i64
+    };
+    value as u64
+}
+
+fn sat_u64(x: f64) -> u64 {
+    if x.is_nan() || x <= -1.0 {
+        0
+    } else if x >= 18_446_744_073_709_551_616.0 {
+        u64::max_value()
+    } else {
+        x as u64
+    }
+}
+
+#[cfg(test)]
+mod interpret_tests {
+    use crate::InterpreterCompiler;
+    use wabt::wat2wasm;
+    use wasmer_runtime_core::{
+        compile_with,
+        error::{CallError, CallResult, RuntimeError, TrapCode},
+        imports,
+        types::Value,
+        Instance,
+    };
+
+    fn instantiate(wat: &str) -> Instance {
+        let wasm = wat2wasm(wat).unwrap();
+        let module = compile_with(&wasm, &InterpreterCompiler::new()).unwrap();
+        module.instantiate(&imports! {}).unwrap()
+    }
+
+    fn trap_code(result: CallResult<Vec<Value>>) -> Option<TrapCode> {
+        match result {
+            Err(CallError::Runtime(RuntimeError::Trap { code, .. })) => Some(code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_loads_out_of_bounds_trap() {
+        let instance = instantiate(
+            r#"
+            (module
+              (memory 1)
+              (func (export "load") (param i32) (result i32)
+                get_local 0
+                i32.load offset=4))
+            "#,
+        );
+
+        assert_eq!(
+            instance.call("load", &[Value::I32(65528)]),
+            Ok(vec![Value::I32(0)])
+        );
+        assert_eq!(
+            trap_code(instance.call("load", &[Value::I32(65529)])),
+            Some(TrapCode::MemoryOutOfBounds)
+        );
+        // The offset is added without wrapping around.
+        assert_eq!(
+            trap_code(instance.call("load", &[Value::I32(-1)])),
+            Some(TrapCode::MemoryOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_stores_out_of_bounds_trap() {
+        let instance = instantiate(
+            r#"
+            (module
+              (memory 1)
+              (func (export "store") (param i32)
+                get_local 0
+                i64.const 0
+                i64.store))
+            "#,
+        );
+
+        assert_eq!(instance.call("store", &[Value::I32(65528)]), Ok(vec![]));
+        assert_eq!(
+            trap_code(instance.call("store", &[Value::I32(65529)])),
+            Some(TrapCode::MemoryOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_call_indirect_checks_signature() {
+        let instance = instantiate(
+            r#"
+            (module
+              (type $returns_i32 (func (result i32)))
+              (table 3 anyfunc)
+              (elem (i32.const 0) $one $identity)
+              (func $one (result i32)
+                i32.const 1)
+              (func $identity (param i32) (result i32)
+                get_local 0)
+              (func (export "call") (param i32) (result i32)
+                get_local 0
+                call_indirect (type $returns_i32)))
+            "#,
+        );
+
+        assert_eq!(
+            instance.call("call", &[Value::I32(0)]),
+            Ok(vec![Value::I32(1)])
+        );
+        assert_eq!(
+            trap_code(instance.call("call", &[Value::I32(1)])),
+            Some(TrapCode::BadSignature)
+        );
+        assert_eq!(
+            trap_code(instance.call("call", &[Value::I32(2)])),
+            Some(TrapCode::IndirectCallToNull)
+        );
+        assert_eq!(
+            trap_code(instance.call("call", &[Value::I32(3)])),
+            Some(TrapCode::TableOutOfBounds)
+        );
+    }
+}
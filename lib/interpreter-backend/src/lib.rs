@@ -0,0 +1,105 @@
+#[cfg(not(unix))]
+compile_error!("This crate doesn't yet support compiling on operating systems other than unix");
+
+// The interpreter translates functions into instructions of its own and
+// runs them, so it works on machines that the other backends don't
+// generate code for, and where memory can't be made executable.
+//
+// Pointers to the translated functions stand in for pointers to machine
+// code in tables and in the `vm::Ctx`, so calls from interpreted code to
+// other backends and host functions work, through libffi. Native code
+// can't call interpreted functions, though, so an instance of another
+// backend can't import functions from an interpreted one.
+//
+// Traps of host functions are caught by the signal handlers of
+// runtime-core's `trap` module, so only unix is supported.
+
+mod code;
+mod interpret;
+mod native;
+mod ops;
+mod parse;
+mod translate;
+
+use crate::{
+    code::{Code, InterpreterCaller, InterpreterResolver},
+    parse::LoadError,
+};
+use wasmer_runtime_core::{
+    backend::{sys::Memory, CacheGen, Capabilities, Compiler, CompilerConfig, Token},
+    cache::{Artifact, Error as CacheError},
+    deterministic,
+    error::{CompileError, CompileResult},
+    metering,
+    module::{ModuleInfo, ModuleInner},
+};
+
+struct Placeholder;
+impl CacheGen for Placeholder {
+    fn generate_cache(
+        &self,
+        _module: &ModuleInner,
+    ) -> Result<(Box<ModuleInfo>, Box<[u8]>, Memory), CacheError> {
+        Err(CacheError::Unknown(
+            "the interpreter backend doesn't support caching yet".to_string(),
+        ))
+    }
+}
+
+pub struct InterpreterCompiler {}
+impl InterpreterCompiler {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Compiler for InterpreterCompiler {
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> CompileResult<ModuleInner> {
+        let costs = compiler_config
+            .metering
+            .map(|config| metering::function_costs(wasm, config.cost_function))
+            .transpose()?;
+        let (info, functions) = parse::read_module(wasm, costs, deterministic::is_deterministic())?;
+        let code = Code::new(&info, functions);
+        Ok(ModuleInner {
+            cache_gen: Box::new(Placeholder),
+            func_resolver: Box::new(InterpreterResolver { code: code.clone() }),
+            protected_caller: Box::new(InterpreterCaller { code }),
+            info: info,
+        })
+    }
+
+    unsafe fn from_cache(&self, _artifact: Artifact, _: Token) -> Result<ModuleInner, CacheError> {
+        Err(CacheError::Unknown(
+            "the interpreter backend doesn't support caching yet".to_string(),
+        ))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            // No code is generated, so it runs wherever it's built.
+            target_archs: &[std::env::consts::ARCH],
+            threads: false,
+            reference_types: cfg!(feature = "reference-types"),
+            bulk_memory: true,
+            multi_value: true,
+            backtraces: false,
+            jit_debug: false,
+            caching: false,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+impl From<LoadError> for CompileError {
+    fn from(other: LoadError) -> CompileError {
+        CompileError::InternalError {
+            msg: format!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Calling host functions, and the functions of other backends, by
+//! their native signature.
+//!
+//! Since the interpreter doesn't generate code, it can't make
+//! trampolines for the signatures of the module, so it calls through
+//! libffi instead.
+
+use libffi::{
+    middle::{Cif, Type as FfiType},
+    raw::{ffi_arg, ffi_call},
+};
+use std::{ffi::c_void, mem};
+use wasmer_runtime_core::{
+    error::RuntimeResult,
+    trap,
+    types::{FuncSig, Type},
+    vm,
+};
+
+/// A signature, with what libffi needs to call functions of it.
+pub struct Signature {
+    cif: Cif,
+    params: Vec<Type>,
+    returns: Vec<Type>,
+    /// Where the results are in the struct they are written to, if
+    /// there's more than one, and its size.
+    result_offsets: Option<(Vec<usize>, usize)>,
+}
+
+// The call interface is only read once it's been made.
+unsafe impl Send for Signature {}
+unsafe impl Sync for Signature {}
+
+impl Signature {
+    pub fn new(sig: &FuncSig) -> Self {
+        // Every function takes the `vm::Ctx` first, and a pointer to
+        // its results last if it returns more than one value.
+        let results_ptr = if sig.returns_through_pointer() {
+            Some(FfiType::pointer())
+        } else {
+            None
+        };
+        let params: Vec<_> = Some(FfiType::pointer())
+            .into_iter()
+            .chain(sig.params().iter().map(|&ty| ffi_type(ty)))
+            .chain(results_ptr)
+            .collect();
+        let ret = match sig.returns() {
+            [ty] => ffi_type(*ty),
+            _ => FfiType::void(),
+        };
+
+        Signature {
+            cif: Cif::new(params, ret),
+            params: sig.params().to_vec(),
+            returns: sig.returns().to_vec(),
+            result_offsets: if sig.returns_through_pointer() {
+                Some(sig.result_offsets())
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn param_count(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn return_count(&self) -> usize {
+        self.returns.len()
+    }
+
+    /// Call the native function `func` with `args`, which are stack
+    /// slots, and return the slots of the values that it returns.
+    ///
+    /// If the function traps, or is a host function that returns an
+    /// error, that error is returned.
+    pub unsafe fn call(
+        &self,
+        func: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        args: &[u64],
+    ) -> RuntimeResult<Vec<u64>> {
+        let mut vmctx = vmctx;
+        let mut values = args.to_vec();
+        for (slot, &ty) in values.iter_mut().zip(&self.params) {
+            // Values narrower than a slot are passed from its first bytes.
+            if ty == Type::I32 || ty == Type::F32 {
+                let value = *slot as u32;
+                *slot = 0;
+                *(slot as *mut u64 as *mut u32) = value;
+            }
+        }
+        let mut arg_ptrs: Vec<*mut c_void> = Some(&mut vmctx as *mut *mut vm::Ctx as *mut c_void)
+            .into_iter()
+            .chain(
+                values
+                    .iter_mut()
+                    .map(|slot| slot as *mut u64 as *mut c_void),
+            )
+            .collect();
+
+        // The results struct is kept aligned for any value.
+        let mut results = match &self.result_offsets {
+            Some((_, size)) => vec![0u64; (size + 7) / 8],
+            None => vec![],
+        };
+        let mut results_ptr = results.as_mut_ptr();
+        if self.result_offsets.is_some() {
+            arg_ptrs.push(&mut results_ptr as *mut *mut u64 as *mut c_void);
+        }
+
+        let mut ret = 0u64;
+        let ret_ptr = &mut ret as *mut u64;
+        let cif = self.cif.as_raw_ptr();
+        let func: unsafe extern "C" fn() = mem::transmute(func);
+        trap::call_protected(None, || {
+            ffi_call(
+                cif,
+                Some(func),
+                ret_ptr as *mut c_void,
+                arg_ptrs.as_mut_ptr(),
+            )
+        })?;
+
+        if let Some((offsets, _)) = &self.result_offsets {
+            let base = results.as_ptr() as *const u8;
+            return Ok(self
+                .returns
+                .iter()
+                .zip(offsets)
+                .map(|(&ty, &offset)| {
+                    let ptr = base.add(offset);
+                    match ty {
+                        Type::I32 | Type::F32 => *(ptr as *const u32) as u64,
+                        _ => *(ptr as *const u64),
+                    }
+                })
+                .collect());
+        }
+
+        Ok(self
+            .returns
+            .first()
+            .map(|&ty| match ty {
+                // libffi widens integers narrower than a register.
+                Type::I32 => *(ret_ptr as *const ffi_arg) as u32 as u64,
+                Type::F32 => *(ret_ptr as *const u32) as u64,
+                _ => ret,
+            })
+            .into_iter()
+            .collect())
+    }
+}
+
+fn ffi_type(ty: Type) -> FfiType {
+    match ty {
+        Type::I32 => FfiType::i32(),
+        Type::I64 => FfiType::i64(),
+        Type::F32 => FfiType::f32(),
+        Type::F64 => FfiType::f64(),
+        // References are passed as their handles.
+        Type::ExternRef | Type::FuncRef => FfiType::u64(),
+    }
+}
@@ -0,0 +1,268 @@
+//! The instructions that functions are translated to.
+//!
+//! Values are kept on the stack as 64-bit slots. `i32`s are
+//! zero-extended, floats are kept as their bits and references as
+//! their handles, so an instruction only needs to know the type of the
+//! values it works on, not where they came from. Branches have been
+//! resolved to the index of the instruction that they jump to.
+
+/// Where a branch goes, and how it unwinds the stack on the way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrTarget {
+    /// The index of the instruction to continue at.
+    pub pc: u32,
+    /// The number of values under the kept ones to remove.
+    pub drop: u32,
+    /// The number of values on top of the stack that the branch keeps.
+    pub keep: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadOp {
+    I32,
+    I64,
+    I32S8,
+    I32U8,
+    I32S16,
+    I32U16,
+    I64S8,
+    I64U8,
+    I64S16,
+    I64U16,
+    I64S32,
+    I64U32,
+}
+
+/// Stores take the low bytes of the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOp {
+    B8,
+    B16,
+    B32,
+    B64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Unreachable,
+    Br(BrTarget),
+    /// Pops a condition and branches if it isn't zero.
+    BrIf(BrTarget),
+    /// Pops a condition and jumps to `pc` if it's zero, for `if`.
+    BrUnless {
+        pc: u32,
+    },
+    /// Pops an index into the `len` targets at `start` in the branch
+    /// table of the function. The last one is the default.
+    BrTable {
+        start: u32,
+        len: u32,
+    },
+    Return,
+    /// Calls the local function `index` of the instance.
+    Call {
+        index: u32,
+        sig: u32,
+    },
+    /// Calls the imported function `index`.
+    CallImport {
+        index: u32,
+        sig: u32,
+    },
+    CallIndirect {
+        sig: u32,
+        table: u32,
+    },
+    /// The start of every function, which checks whether the instance
+    /// has been interrupted or is past its deadline, and takes `cost`
+    /// points from it.
+    Enter {
+        cost: u64,
+    },
+    /// The start of every iteration of a loop, which checks whether the
+    /// instance has been interrupted or is past its deadline, and takes
+    /// `cost` points from it.
+    Loop {
+        cost: u64,
+    },
+
+    Drop,
+    Select,
+
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    ImportedGlobalGet(u32),
+    ImportedGlobalSet(u32),
+
+    Load {
+        op: LoadOp,
+        offset: u32,
+    },
+    Store {
+        op: StoreOp,
+        offset: u32,
+    },
+    MemorySize,
+    MemoryGrow,
+    MemoryInit {
+        segment: u32,
+    },
+    DataDrop {
+        segment: u32,
+    },
+    MemoryCopy,
+    MemoryFill,
+    TableInit {
+        segment: u32,
+    },
+    ElemDrop {
+        segment: u32,
+    },
+    TableGet {
+        table: u32,
+    },
+    TableSet {
+        table: u32,
+    },
+
+    Const(u64),
+
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32Rotl,
+    I32Rotr,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    I64Rotl,
+    I64Rotr,
+
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+
+    I32WrapI64,
+    I32TruncF32S,
+    I32TruncF32U,
+    I32TruncF64S,
+    I32TruncF64U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI32U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64PromoteF32,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+
+    /// Replaces the `f32` on top of the stack with the canonical NaN if
+    /// it's a NaN, when execution is deterministic.
+    CanonicalizeF32,
+    CanonicalizeF64,
+}
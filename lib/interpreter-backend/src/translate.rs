@@ -0,0 +1,791 @@
+//! Translating the operators of a function body into instructions.
+//!
+//! The translator keeps track of how many values are on the stack, so
+//! that every branch knows how many to keep and how many to drop, and
+//! resolves the targets of forward branches once it reaches the end of
+//! their blocks. Code that can't be reached isn't translated.
+
+use crate::{
+    code::Function,
+    ops::{BrTarget, LoadOp, Op, StoreOp},
+};
+use wasmer_runtime_core::{
+    metering::FunctionCosts,
+    module::ModuleInfo,
+    structures::TypedIndex,
+    types::{FuncIndex, GlobalIndex, LocalOrImport, SigIndex},
+};
+use wasmparser::{Operator, Type as WpType};
+
+#[derive(Debug)]
+pub struct TranslateError {
+    pub message: String,
+}
+
+/// A target that's filled in at the end of its block.
+const UNRESOLVED: u32 = u32::max_value();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlKind {
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// A branch whose target isn't known yet.
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    Op(usize),
+    Table(usize),
+}
+
+struct Control {
+    kind: ControlKind,
+    /// The height of the stack when the block was entered.
+    height: usize,
+    /// The number of values that the block results in.
+    arity: usize,
+    /// The first instruction of a loop, which branches to it go to.
+    start: usize,
+    fixups: Vec<Fixup>,
+    /// The instruction that jumps over the `then` branch of an `if`.
+    else_fixup: Option<usize>,
+}
+
+pub struct Translator<'a> {
+    info: &'a ModuleInfo,
+    function: Function,
+    controls: Vec<Control>,
+    height: usize,
+    /// How deep in blocks that can't be reached translation is, plus
+    /// one, or zero if the code is reachable.
+    unreachable_depth: usize,
+    loops: usize,
+    costs: Option<FunctionCosts>,
+    canonicalize_nans: bool,
+    /// The offset of the operator being translated.
+    offset: u32,
+}
+
+impl<'a> Translator<'a> {
+    pub fn new(
+        info: &'a ModuleInfo,
+        index: FuncIndex,
+        costs: Option<FunctionCosts>,
+        canonicalize_nans: bool,
+    ) -> Self {
+        let sig = &info.signatures[info.func_assoc[index]];
+        let return_count = sig.returns().len();
+        Translator {
+            info,
+            function: Function::new(index, sig.params().len(), return_count),
+            controls: vec![Control {
+                kind: ControlKind::Block,
+                height: 0,
+                arity: return_count,
+                start: 0,
+                fixups: vec![],
+                else_fixup: None,
+            }],
+            height: 0,
+            unreachable_depth: 0,
+            loops: 0,
+            costs,
+            canonicalize_nans,
+            offset: 0,
+        }
+    }
+
+    pub fn feed_locals(&mut self, count: usize) {
+        self.function.local_count += count;
+    }
+
+    pub fn begin_body(&mut self) {
+        let cost = self.costs.as_ref().map_or(0, |costs| costs.entry);
+        self.emit(Op::Enter { cost });
+    }
+
+    pub fn finish(self) -> Function {
+        self.function
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.function.ops.push(op);
+        self.function.offsets.push(self.offset);
+    }
+
+    fn pc(&self) -> usize {
+        self.function.ops.len()
+    }
+
+    /// Emit `op`, which pops `pops` values and pushes `pushes`.
+    fn emit_with(&mut self, op: Op, pops: usize, pushes: usize) {
+        self.height = self.height - pops + pushes;
+        self.emit(op);
+    }
+
+    fn br_target(&mut self, relative_depth: u32) -> (BrTarget, Option<usize>) {
+        let index = self.controls.len() - 1 - relative_depth as usize;
+        let control = &self.controls[index];
+        let keep = if control.kind == ControlKind::Loop {
+            0
+        } else {
+            control.arity
+        };
+        let pc = if control.kind == ControlKind::Loop {
+            control.start as u32
+        } else {
+            UNRESOLVED
+        };
+        let target = BrTarget {
+            pc,
+            drop: (self.height - keep - control.height) as u32,
+            keep: keep as u32,
+        };
+        let needs_fixup = if pc == UNRESOLVED { Some(index) } else { None };
+        (target, needs_fixup)
+    }
+
+    fn emit_br(&mut self, relative_depth: u32, conditional: bool) {
+        let (target, needs_fixup) = self.br_target(relative_depth);
+        let pc = self.pc();
+        self.emit(if conditional {
+            Op::BrIf(target)
+        } else {
+            Op::Br(target)
+        });
+        if let Some(index) = needs_fixup {
+            self.controls[index].fixups.push(Fixup::Op(pc));
+        }
+    }
+
+    fn resolve(&mut self, fixups: Vec<Fixup>, pc: usize) {
+        for fixup in fixups {
+            match fixup {
+                Fixup::Op(at) => match self.function.ops[at] {
+                    Op::Br(ref mut target) | Op::BrIf(ref mut target) => target.pc = pc as u32,
+                    Op::BrUnless { pc: ref mut target } => *target = pc as u32,
+                    _ => unreachable!(),
+                },
+                Fixup::Table(at) => self.function.br_table[at].pc = pc as u32,
+            }
+        }
+    }
+
+    /// What follows an unconditional branch can't be reached, up to
+    /// the end of the block.
+    fn set_unreachable(&mut self) {
+        self.height = self.controls.last().unwrap().height;
+        self.unreachable_depth = 1;
+    }
+
+    pub fn feed_operator(&mut self, op: Operator, offset: usize) -> Result<(), TranslateError> {
+        self.offset = offset as u32;
+
+        if let Operator::Loop { .. } = op {
+            // Loops are counted even when they can't be reached, since
+            // their costs are looked up by the order that they appear in.
+            self.loops += 1;
+        }
+
+        let reachable = self.unreachable_depth == 0;
+        if !reachable {
+            match op {
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                    self.unreachable_depth += 1;
+                    return Ok(());
+                }
+                Operator::Else if self.unreachable_depth == 1 => {}
+                Operator::End if self.unreachable_depth == 1 => {}
+                Operator::End => {
+                    self.unreachable_depth -= 1;
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+            self.unreachable_depth = 0;
+        }
+
+        let canonicalize = if self.canonicalize_nans {
+            nan_type(&op)
+        } else {
+            None
+        };
+
+        match op {
+            Operator::Unreachable => {
+                self.emit(Op::Unreachable);
+                self.set_unreachable();
+            }
+            Operator::Nop => {}
+            Operator::Block { ty } => {
+                let control = Control {
+                    kind: ControlKind::Block,
+                    height: self.height,
+                    arity: block_arity(ty),
+                    start: 0,
+                    fixups: vec![],
+                    else_fixup: None,
+                };
+                self.controls.push(control);
+            }
+            Operator::Loop { ty } => {
+                let cost = self
+                    .costs
+                    .as_ref()
+                    .map(|costs| costs.loop_cost(self.loops - 1))
+                    .unwrap_or(0);
+                let control = Control {
+                    kind: ControlKind::Loop,
+                    height: self.height,
+                    arity: block_arity(ty),
+                    start: self.pc(),
+                    fixups: vec![],
+                    else_fixup: None,
+                };
+                self.controls.push(control);
+                self.emit(Op::Loop { cost });
+            }
+            Operator::If { ty } => {
+                self.height -= 1;
+                let else_fixup = self.pc();
+                self.emit(Op::BrUnless { pc: UNRESOLVED });
+                let control = Control {
+                    kind: ControlKind::If,
+                    height: self.height,
+                    arity: block_arity(ty),
+                    start: 0,
+                    fixups: vec![],
+                    else_fixup: Some(else_fixup),
+                };
+                self.controls.push(control);
+            }
+            Operator::Else => {
+                let (height, arity) = {
+                    let control = self.controls.last().unwrap();
+                    (control.height, control.arity)
+                };
+                // The end of the `then` branch jumps over the `else` one,
+                // unless it can't be reached.
+                if reachable {
+                    let pc = self.pc();
+                    self.emit(Op::Br(BrTarget {
+                        pc: UNRESOLVED,
+                        drop: 0,
+                        keep: arity as u32,
+                    }));
+                    self.controls.last_mut().unwrap().fixups.push(Fixup::Op(pc));
+                }
+                let pc = self.pc();
+                let control = self.controls.last_mut().unwrap();
+                control.kind = ControlKind::Else;
+                let else_fixup = control.else_fixup.take();
+                self.resolve(else_fixup.into_iter().map(Fixup::Op).collect(), pc);
+                self.height = height;
+            }
+            Operator::End => {
+                let mut control = self.controls.pop().unwrap();
+                let pc = self.pc();
+                let mut fixups = control.fixups.split_off(0);
+                fixups.extend(control.else_fixup.map(Fixup::Op));
+                self.resolve(fixups, pc);
+                self.height = control.height + control.arity;
+                if self.controls.is_empty() {
+                    self.emit(Op::Return);
+                }
+            }
+            Operator::Br { relative_depth } => {
+                self.emit_br(relative_depth, false);
+                self.set_unreachable();
+            }
+            Operator::BrIf { relative_depth } => {
+                self.height -= 1;
+                self.emit_br(relative_depth, true);
+            }
+            Operator::BrTable { table } => {
+                self.height -= 1;
+                let (targets, default) = table.read_table().map_err(|e| TranslateError {
+                    message: e.message.to_string(),
+                })?;
+                let start = self.function.br_table.len();
+                for relative_depth in targets.iter().cloned().chain(Some(default)) {
+                    let (target, needs_fixup) = self.br_target(relative_depth);
+                    if let Some(index) = needs_fixup {
+                        let at = self.function.br_table.len();
+                        self.controls[index].fixups.push(Fixup::Table(at));
+                    }
+                    self.function.br_table.push(target);
+                }
+                let len = self.function.br_table.len() - start;
+                self.emit(Op::BrTable {
+                    start: start as u32,
+                    len: len as u32,
+                });
+                self.set_unreachable();
+            }
+            Operator::Return => {
+                self.emit(Op::Return);
+                self.set_unreachable();
+            }
+            Operator::Call { function_index } => {
+                let func_index = FuncIndex::new(function_index as usize);
+                let sig_index = self.info.func_assoc[func_index];
+                let sig = &self.info.signatures[sig_index];
+                let (pops, pushes) = (sig.params().len(), sig.returns().len());
+                let sig = sig_index.index() as u32;
+                let op = match func_index.local_or_import(self.info) {
+                    LocalOrImport::Local(local) => Op::Call {
+                        index: local.index() as u32,
+                        sig,
+                    },
+                    LocalOrImport::Import(import) => Op::CallImport {
+                        index: import.index() as u32,
+                        sig,
+                    },
+                };
+                self.emit_with(op, pops, pushes);
+            }
+            Operator::CallIndirect { index, table_index } => {
+                let sig = &self.info.signatures[SigIndex::new(index as usize)];
+                let (pops, pushes) = (sig.params().len() + 1, sig.returns().len());
+                self.emit_with(
+                    Op::CallIndirect {
+                        sig: index,
+                        table: table_index,
+                    },
+                    pops,
+                    pushes,
+                );
+            }
+            Operator::Drop => self.emit_with(Op::Drop, 1, 0),
+            Operator::Select => self.emit_with(Op::Select, 3, 1),
+            Operator::GetLocal { local_index } => self.emit_with(Op::LocalGet(local_index), 0, 1),
+            Operator::SetLocal { local_index } => self.emit_with(Op::LocalSet(local_index), 1, 0),
+            Operator::TeeLocal { local_index } => self.emit_with(Op::LocalTee(local_index), 1, 1),
+            Operator::GetGlobal { global_index } => {
+                let op = match GlobalIndex::new(global_index as usize).local_or_import(self.info) {
+                    LocalOrImport::Local(local) => Op::GlobalGet(local.index() as u32),
+                    LocalOrImport::Import(import) => Op::ImportedGlobalGet(import.index() as u32),
+                };
+                self.emit_with(op, 0, 1);
+            }
+            Operator::SetGlobal { global_index } => {
+                let op = match GlobalIndex::new(global_index as usize).local_or_import(self.info) {
+                    LocalOrImport::Local(local) => Op::GlobalSet(local.index() as u32),
+                    LocalOrImport::Import(import) => Op::ImportedGlobalSet(import.index() as u32),
+                };
+                self.emit_with(op, 1, 0);
+            }
+            Operator::I32Load { ref memarg } | Operator::F32Load { ref memarg } => {
+                self.emit_load(LoadOp::I32, memarg.offset)
+            }
+            Operator::I64Load { ref memarg } | Operator::F64Load { ref memarg } => {
+                self.emit_load(LoadOp::I64, memarg.offset)
+            }
+            Operator::I32Load8S { ref memarg } => self.emit_load(LoadOp::I32S8, memarg.offset),
+            Operator::I32Load8U { ref memarg } => self.emit_load(LoadOp::I32U8, memarg.offset),
+            Operator::I32Load16S { ref memarg } => self.emit_load(LoadOp::I32S16, memarg.offset),
+            Operator::I32Load16U { ref memarg } => self.emit_load(LoadOp::I32U16, memarg.offset),
+            Operator::I64Load8S { ref memarg } => self.emit_load(LoadOp::I64S8, memarg.offset),
+            Operator::I64Load8U { ref memarg } => self.emit_load(LoadOp::I64U8, memarg.offset),
+            Operator::I64Load16S { ref memarg } => self.emit_load(LoadOp::I64S16, memarg.offset),
+            Operator::I64Load16U { ref memarg } => self.emit_load(LoadOp::I64U16, memarg.offset),
+            Operator::I64Load32S { ref memarg } => self.emit_load(LoadOp::I64S32, memarg.offset),
+            Operator::I64Load32U { ref memarg } => self.emit_load(LoadOp::I64U32, memarg.offset),
+            Operator::I32Store8 { ref memarg } | Operator::I64Store8 { ref memarg } => {
+                self.emit_store(StoreOp::B8, memarg.offset)
+            }
+            Operator::I32Store16 { ref memarg } | Operator::I64Store16 { ref memarg } => {
+                self.emit_store(StoreOp::B16, memarg.offset)
+            }
+            Operator::I32Store { ref memarg }
+            | Operator::F32Store { ref memarg }
+            | Operator::I64Store32 { ref memarg } => self.emit_store(StoreOp::B32, memarg.offset),
+            Operator::I64Store { ref memarg } | Operator::F64Store { ref memarg } => {
+                self.emit_store(StoreOp::B64, memarg.offset)
+            }
+            Operator::MemorySize { .. } => self.emit_with(Op::MemorySize, 0, 1),
+            Operator::MemoryGrow { .. } => self.emit_with(Op::MemoryGrow, 1, 1),
+            Operator::MemoryInit { segment } => self.emit_with(Op::MemoryInit { segment }, 3, 0),
+            Operator::DataDrop { segment } => self.emit_with(Op::DataDrop { segment }, 0, 0),
+            Operator::MemoryCopy => self.emit_with(Op::MemoryCopy, 3, 0),
+            Operator::MemoryFill => self.emit_with(Op::MemoryFill, 3, 0),
+            Operator::TableInit { segment } => self.emit_with(Op::TableInit { segment }, 3, 0),
+            Operator::ElemDrop { segment } => self.emit_with(Op::ElemDrop { segment }, 0, 0),
+            Operator::TableGet { table } => self.emit_with(Op::TableGet { table }, 1, 1),
+            Operator::TableSet { table } => self.emit_with(Op::TableSet { table }, 2, 0),
+            Operator::RefNull => self.emit_with(Op::Const(0), 0, 1),
+            // A reference is null if its handle is zero.
+            Operator::RefIsNull => self.emit_with(Op::I64Eqz, 1, 1),
+            Operator::I32Const { value } => {
+                self.emit_with(Op::Const(u64::from(value as u32)), 0, 1)
+            }
+            Operator::I64Const { value } => self.emit_with(Op::Const(value as u64), 0, 1),
+            Operator::F32Const { value } => {
+                self.emit_with(Op::Const(u64::from(value.bits())), 0, 1)
+            }
+            Operator::F64Const { value } => self.emit_with(Op::Const(value.bits()), 0, 1),
+            // Floats are kept as their bits, so reinterpreting them
+            // leaves the stack as it is.
+            Operator::I32ReinterpretF32
+            | Operator::I64ReinterpretF64
+            | Operator::F32ReinterpretI32
+            | Operator::F64ReinterpretI64 => {}
+            ref op => match numeric(op) {
+                Some((op, pops)) => self.emit_with(op, pops, 1),
+                None => {
+                    return Err(TranslateError {
+                        message: format!("the interpreter doesn't support {:?}", op),
+                    });
+                }
+            },
+        }
+
+        match canonicalize {
+            Some(WpType::F32) => self.emit(Op::CanonicalizeF32),
+            Some(WpType::F64) => self.emit(Op::CanonicalizeF64),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn emit_load(&mut self, op: LoadOp, offset: u32) {
+        self.emit_with(Op::Load { op, offset }, 1, 1);
+    }
+
+    fn emit_store(&mut self, op: StoreOp, offset: u32) {
+        self.emit_with(Op::Store { op, offset }, 2, 0);
+    }
+}
+
+fn block_arity(ty: WpType) -> usize {
+    match ty {
+        WpType::EmptyBlockType => 0,
+        _ => 1,
+    }
+}
+
+/// The type of the float that `op` produces, if it can be a NaN.
+fn nan_type(op: &Operator) -> Option<WpType> {
+    match *op {
+        Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Sqrt
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F32DemoteF64 => Some(WpType::F32),
+        Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Sqrt
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F64PromoteF32 => Some(WpType::F64),
+        _ => None,
+    }
+}
+
+/// The instruction for a numeric operator, and how many operands it
+/// takes. Every one of them results in a single value.
+fn numeric(op: &Operator) -> Option<(Op, usize)> {
+    Some(match *op {
+        Operator::I32Eqz => (Op::I32Eqz, 1),
+        Operator::I32Eq => (Op::I32Eq, 2),
+        Operator::I32Ne => (Op::I32Ne, 2),
+        Operator::I32LtS => (Op::I32LtS, 2),
+        Operator::I32LtU => (Op::I32LtU, 2),
+        Operator::I32GtS => (Op::I32GtS, 2),
+        Operator::I32GtU => (Op::I32GtU, 2),
+        Operator::I32LeS => (Op::I32LeS, 2),
+        Operator::I32LeU => (Op::I32LeU, 2),
+        Operator::I32GeS => (Op::I32GeS, 2),
+        Operator::I32GeU => (Op::I32GeU, 2),
+        Operator::I64Eqz => (Op::I64Eqz, 1),
+        Operator::I64Eq => (Op::I64Eq, 2),
+        Operator::I64Ne => (Op::I64Ne, 2),
+        Operator::I64LtS => (Op::I64LtS, 2),
+        Operator::I64LtU => (Op::I64LtU, 2),
+        Operator::I64GtS => (Op::I64GtS, 2),
+        Operator::I64GtU => (Op::I64GtU, 2),
+        Operator::I64LeS => (Op::I64LeS, 2),
+        Operator::I64LeU => (Op::I64LeU, 2),
+        Operator::I64GeS => (Op::I64GeS, 2),
+        Operator::I64GeU => (Op::I64GeU, 2),
+        Operator::F32Eq => (Op::F32Eq, 2),
+        Operator::F32Ne => (Op::F32Ne, 2),
+        Operator::F32Lt => (Op::F32Lt, 2),
+        Operator::F32Gt => (Op::F32Gt, 2),
+        Operator::F32Le => (Op::F32Le, 2),
+        Operator::F32Ge => (Op::F32Ge, 2),
+        Operator::F64Eq => (Op::F64Eq, 2),
+        Operator::F64Ne => (Op::F64Ne, 2),
+        Operator::F64Lt => (Op::F64Lt, 2),
+        Operator::F64Gt => (Op::F64Gt, 2),
+        Operator::F64Le => (Op::F64Le, 2),
+        Operator::F64Ge => (Op::F64Ge, 2),
+
+        Operator::I32Clz => (Op::I32Clz, 1),
+        Operator::I32Ctz => (Op::I32Ctz, 1),
+        Operator::I32Popcnt => (Op::I32Popcnt, 1),
+        Operator::I32Add => (Op::I32Add, 2),
+        Operator::I32Sub => (Op::I32Sub, 2),
+        Operator::I32Mul => (Op::I32Mul, 2),
+        Operator::I32DivS => (Op::I32DivS, 2),
+        Operator::I32DivU => (Op::I32DivU, 2),
+        Operator::I32RemS => (Op::I32RemS, 2),
+        Operator::I32RemU => (Op::I32RemU, 2),
+        Operator::I32And => (Op::I32And, 2),
+        Operator::I32Or => (Op::I32Or, 2),
+        Operator::I32Xor => (Op::I32Xor, 2),
+        Operator::I32Shl => (Op::I32Shl, 2),
+        Operator::I32ShrS => (Op::I32ShrS, 2),
+        Operator::I32ShrU => (Op::I32ShrU, 2),
+        Operator::I32Rotl => (Op::I32Rotl, 2),
+        Operator::I32Rotr => (Op::I32Rotr, 2),
+        Operator::I64Clz => (Op::I64Clz, 1),
+        Operator::I64Ctz => (Op::I64Ctz, 1),
+        Operator::I64Popcnt => (Op::I64Popcnt, 1),
+        Operator::I64Add => (Op::I64Add, 2),
+        Operator::I64Sub => (Op::I64Sub, 2),
+        Operator::I64Mul => (Op::I64Mul, 2),
+        Operator::I64DivS => (Op::I64DivS, 2),
+        Operator::I64DivU => (Op::I64DivU, 2),
+        Operator::I64RemS => (Op::I64RemS, 2),
+        Operator::I64RemU => (Op::I64RemU, 2),
+        Operator::I64And => (Op::I64And, 2),
+        Operator::I64Or => (Op::I64Or, 2),
+        Operator::I64Xor => (Op::I64Xor, 2),
+        Operator::I64Shl => (Op::I64Shl, 2),
+        Operator::I64ShrS => (Op::I64ShrS, 2),
+        Operator::I64ShrU => (Op::I64ShrU, 2),
+        Operator::I64Rotl => (Op::I64Rotl, 2),
+        Operator::I64Rotr => (Op::I64Rotr, 2),
+
+        Operator::F32Abs => (Op::F32Abs, 1),
+        Operator::F32Neg => (Op::F32Neg, 1),
+        Operator::F32Ceil => (Op::F32Ceil, 1),
+        Operator::F32Floor => (Op::F32Floor, 1),
+        Operator::F32Trunc => (Op::F32Trunc, 1),
+        Operator::F32Nearest => (Op::F32Nearest, 1),
+        Operator::F32Sqrt => (Op::F32Sqrt, 1),
+        Operator::F32Add => (Op::F32Add, 2),
+        Operator::F32Sub => (Op::F32Sub, 2),
+        Operator::F32Mul => (Op::F32Mul, 2),
+        Operator::F32Div => (Op::F32Div, 2),
+        Operator::F32Min => (Op::F32Min, 2),
+        Operator::F32Max => (Op::F32Max, 2),
+        Operator::F32Copysign => (Op::F32Copysign, 2),
+        Operator::F64Abs => (Op::F64Abs, 1),
+        Operator::F64Neg => (Op::F64Neg, 1),
+        Operator::F64Ceil => (Op::F64Ceil, 1),
+        Operator::F64Floor => (Op::F64Floor, 1),
+        Operator::F64Trunc => (Op::F64Trunc, 1),
+        Operator::F64Nearest => (Op::F64Nearest, 1),
+        Operator::F64Sqrt => (Op::F64Sqrt, 1),
+        Operator::F64Add => (Op::F64Add, 2),
+        Operator::F64Sub => (Op::F64Sub, 2),
+        Operator::F64Mul => (Op::F64Mul, 2),
+        Operator::F64Div => (Op::F64Div, 2),
+        Operator::F64Min => (Op::F64Min, 2),
+        Operator::F64Max => (Op::F64Max, 2),
+        Operator::F64Copysign => (Op::F64Copysign, 2),
+
+        Operator::I32WrapI64 => (Op::I32WrapI64, 1),
+        Operator::I32TruncSF32 => (Op::I32TruncF32S, 1),
+        Operator::I32TruncUF32 => (Op::I32TruncF32U, 1),
+        Operator::I32TruncSF64 => (Op::I32TruncF64S, 1),
+        Operator::I32TruncUF64 => (Op::I32TruncF64U, 1),
+        Operator::I64ExtendSI32 => (Op::I64ExtendI32S, 1),
+        Operator::I64ExtendUI32 => (Op::I64ExtendI32U, 1),
+        Operator::I64TruncSF32 => (Op::I64TruncF32S, 1),
+        Operator::I64TruncUF32 => (Op::I64TruncF32U, 1),
+        Operator::I64TruncSF64 => (Op::I64TruncF64S, 1),
+        Operator::I64TruncUF64 => (Op::I64TruncF64U, 1),
+        Operator::F32ConvertSI32 => (Op::F32ConvertI32S, 1),
+        Operator::F32ConvertUI32 => (Op::F32ConvertI32U, 1),
+        Operator::F32ConvertSI64 => (Op::F32ConvertI64S, 1),
+        Operator::F32ConvertUI64 => (Op::F32ConvertI64U, 1),
+        Operator::F32DemoteF64 => (Op::F32DemoteF64, 1),
+        Operator::F64ConvertSI32 => (Op::F64ConvertI32S, 1),
+        Operator::F64ConvertUI32 => (Op::F64ConvertI32U, 1),
+        Operator::F64ConvertSI64 => (Op::F64ConvertI64S, 1),
+        Operator::F64ConvertUI64 => (Op::F64ConvertI64U, 1),
+        Operator::F64PromoteF32 => (Op::F64PromoteF32, 1),
+        Operator::I32TruncSSatF32 => (Op::I32TruncSatF32S, 1),
+        Operator::I32TruncUSatF32 => (Op::I32TruncSatF32U, 1),
+        Operator::I32TruncSSatF64 => (Op::I32TruncSatF64S, 1),
+        Operator::I32TruncUSatF64 => (Op::I32TruncSatF64U, 1),
+        Operator::I64TruncSSatF32 => (Op::I64TruncSatF32S, 1),
+        Operator::I64TruncUSatF32 => (Op::I64TruncSatF32U, 1),
+        Operator::I64TruncSSatF64 => (Op::I64TruncSatF64S, 1),
+        Operator::I64TruncUSatF64 => (Op::I64TruncSatF64U, 1),
+
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod translate_tests {
+    use super::*;
+    use crate::parse::read_module;
+    use wabt::wat2wasm;
+
+    /// Translate the only function of the module in `wat`.
+    fn translate(wat: &str) -> Function {
+        let wasm = wat2wasm(wat).unwrap();
+        let (_, mut functions) = read_module(&wasm, None, false).unwrap();
+        functions.remove(0)
+    }
+
+    fn target(pc: u32, drop: u32, keep: u32) -> BrTarget {
+        BrTarget { pc, drop, keep }
+    }
+
+    #[test]
+    fn test_forward_branch_is_fixed_up() {
+        let function = translate(
+            r#"
+            (module
+              (func (param i32) (result i32)
+                block (result i32)
+                  i32.const 1
+                  get_local 0
+                  br_if 0
+                  drop
+                  i32.const 2
+                end))
+            "#,
+        );
+        assert_eq!(
+            function.ops,
+            vec![
+                Op::Enter { cost: 0 },
+                Op::Const(1),
+                Op::LocalGet(0),
+                Op::BrIf(target(6, 0, 1)),
+                Op::Drop,
+                Op::Const(2),
+                Op::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_branch_goes_back_and_drops() {
+        let function = translate(
+            r#"
+            (module
+              (func (result i32)
+                i32.const 7
+                loop
+                  i32.const 3
+                  br 0
+                end))
+            "#,
+        );
+        assert_eq!(
+            function.ops,
+            vec![
+                Op::Enter { cost: 0 },
+                Op::Const(7),
+                Op::Loop { cost: 0 },
+                Op::Const(3),
+                Op::Br(target(2, 1, 0)),
+                Op::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_if_else_jumps_are_fixed_up() {
+        let function = translate(
+            r#"
+            (module
+              (func (param i32) (result i32)
+                get_local 0
+                if (result i32)
+                  i32.const 1
+                else
+                  i32.const 2
+                end))
+            "#,
+        );
+        assert_eq!(
+            function.ops,
+            vec![
+                Op::Enter { cost: 0 },
+                Op::LocalGet(0),
+                Op::BrUnless { pc: 5 },
+                Op::Const(1),
+                Op::Br(target(6, 0, 1)),
+                Op::Const(2),
+                Op::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_br_table_targets_are_fixed_up() {
+        let function = translate(
+            r#"
+            (module
+              (func (param i32) (result i32)
+                block
+                  block
+                    get_local 0
+                    br_table 0 1
+                  end
+                  i32.const 1
+                  return
+                end
+                i32.const 2))
+            "#,
+        );
+        assert_eq!(
+            function.ops,
+            vec![
+                Op::Enter { cost: 0 },
+                Op::LocalGet(0),
+                Op::BrTable { start: 0, len: 2 },
+                Op::Const(1),
+                Op::Return,
+                Op::Const(2),
+                Op::Return,
+            ]
+        );
+        assert_eq!(function.br_table, vec![target(3, 0, 0), target(5, 0, 0)]);
+    }
+
+    #[test]
+    fn test_unreachable_code_is_skipped() {
+        let function = translate(
+            r#"
+            (module
+              (func
+                block
+                  br 0
+                  block
+                    unreachable
+                  end
+                end))
+            "#,
+        );
+        assert_eq!(
+            function.ops,
+            vec![Op::Enter { cost: 0 }, Op::Br(target(2, 0, 0)), Op::Return]
+        );
+    }
+}
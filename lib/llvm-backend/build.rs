@@ -7,9 +7,15 @@ use semver::Version;
 use std::env;
 use std::ffi::OsStr;
 use std::io::{self, ErrorKind};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::process::Command;
 
+/// The major versions of LLVM that the backend builds and runs against.
+/// The Rust side goes through the C API, which is the same across them,
+/// and `object_loader.cpp` handles the differences in the C++ API.
+const SUPPORTED_LLVM_MAJOR: RangeInclusive<u64> = 7..=9;
+
 lazy_static! {
     /// LLVM version used by this version of the crate.
     static ref CRATE_VERSION: Version = {
@@ -67,7 +73,8 @@ lazy_static! {
                     return pb;
                 } else {
                     println!("LLVM binaries specified by {} are the wrong version.
-                              (Found {}, need {}.)", binary_prefix_var, ver, *CRATE_VERSION);
+                              (Found {}, need one of {}.x to {}.x.)", binary_prefix_var, ver,
+                              SUPPORTED_LLVM_MAJOR.start(), SUPPORTED_LLVM_MAJOR.end());
                 }
             }
         }
@@ -99,8 +106,10 @@ fn locate_system_llvm_config() -> Option<&'static str> {
                 // Version mismatch. Will try further searches, but warn that
                 // we're not using the system one.
                 println!(
-                    "Found LLVM version {} on PATH, but need {}.",
-                    version, *CRATE_VERSION
+                    "Found LLVM version {} on PATH, but need one of {}.x to {}.x.",
+                    version,
+                    SUPPORTED_LLVM_MAJOR.start(),
+                    SUPPORTED_LLVM_MAJOR.end()
                 );
             }
             Err(ref e) if e.kind() == ErrorKind::NotFound => {
@@ -127,9 +136,7 @@ fn is_compatible_llvm(llvm_version: &Version) -> bool {
     if strict {
         llvm_version.major == CRATE_VERSION.major && llvm_version.minor == CRATE_VERSION.minor
     } else {
-        llvm_version.major >= CRATE_VERSION.major
-            || (llvm_version.major == CRATE_VERSION.major
-                && llvm_version.minor >= CRATE_VERSION.minor)
+        SUPPORTED_LLVM_MAJOR.contains(&llvm_version.major)
     }
 }
 
@@ -206,6 +213,7 @@ fn get_llvm_cxxflags() -> String {
 
 fn main() {
     std::env::set_var("CXXFLAGS", get_llvm_cxxflags());
+    // `object_loader.cpp` finds the version of LLVM in its headers.
     cc::Build::new()
         .cpp(true)
         .file("cpp/object_loader.cpp")
@@ -213,9 +221,7 @@ fn main() {
 
     println!("cargo:rustc-link-lib=static=llvm-backend");
     println!("cargo:rerun-if-changed=build.rs");
-
-    // Enable "nightly" cfg if the current compiler is nightly.
-    if rustc_version::version_meta().unwrap().channel == rustc_version::Channel::Nightly {
-        println!("cargo:rustc-cfg=nightly");
-    }
+    println!("cargo:rerun-if-changed=cpp/object_loader.cpp");
+    println!("cargo:rerun-if-changed=cpp/object_loader.hh");
+    println!("cargo:rerun-if-env-changed=LLVM_SYS_70_PREFIX");
 }
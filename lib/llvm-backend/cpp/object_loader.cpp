@@ -139,6 +139,23 @@ struct SymbolLookup : llvm::JITSymbolResolver {
 public:
     SymbolLookup(callbacks_t callbacks) : callbacks(callbacks) {}
 
+#if LLVM_VERSION_MAJOR >= 8
+    /* Lookups are asynchronous from LLVM 8 on, but these are answered right away. */
+    virtual void lookup(const LookupSet& symbols, OnResolvedFunction on_resolved) override {
+        LookupResult result;
+
+        for (auto symbol : symbols) {
+            result.emplace(symbol, symbol_lookup(symbol));
+        }
+
+        on_resolved(std::move(result));
+    }
+
+    /* Replaces `lookupFlags`: the runtime is asked for every symbol, as before. */
+    virtual llvm::Expected<LookupSet> getResponsibilitySet(const LookupSet& symbols) override {
+        return symbols;
+    }
+#else
     virtual llvm::Expected<LookupResult> lookup(const LookupSet& symbols) override {
         LookupResult result;
 
@@ -158,6 +175,7 @@ public:
 
         return result;
     }
+#endif
 
 private:
     llvm::JITEvaluatedSymbol symbol_lookup(llvm::StringRef name) {
@@ -184,7 +202,7 @@ WasmModule::WasmModule(
 
     runtime_dyld->setProcessAllSections(true);
 
-    runtime_dyld->loadObject(*object_file);
+    loaded_object = runtime_dyld->loadObject(*object_file);
     runtime_dyld->finalizeWithMemoryManagerLocking();
 
     if (runtime_dyld->hasError()) {
@@ -196,4 +214,17 @@ WasmModule::WasmModule(
 void* WasmModule::get_func(llvm::StringRef name) const {
     auto symbol = runtime_dyld->getSymbol(name);
     return (void*)symbol.getAddress();
+}
+
+llvm::StringRef WasmModule::get_debug_object() {
+    // A copy of the object file with its sections moved to the addresses
+    // they were loaded at, which is what debuggers expect to be given.
+    if (!debug_object.getBinary()) {
+        debug_object = loaded_object->getObjectForDebug(*object_file);
+    }
+
+    if (auto binary = debug_object.getBinary()) {
+        return binary->getData();
+    }
+    return llvm::StringRef();
 }
\ No newline at end of file
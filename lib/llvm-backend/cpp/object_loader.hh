@@ -1,5 +1,6 @@
 #include <cstddef>
 #include <cstdint>
+#include <llvm/Config/llvm-config.h>
 #include <llvm/ExecutionEngine/RuntimeDyld.h>
 #include <iostream>
 #include <sstream>
@@ -27,8 +28,6 @@ typedef uintptr_t (*lookup_vm_symbol_t)(const char* name_ptr, size_t length);
 typedef void (*fde_visitor_t)(uint8_t *fde);
 typedef result_t (*visit_fde_t)(uint8_t *fde, size_t size, fde_visitor_t visitor);
 
-typedef void (*trampoline_t)(void*, void*, void*, void*);
-
 typedef struct {
     /* Memory management. */
     alloc_memory_t alloc_memory;
@@ -63,57 +62,6 @@ private:
     std::string msg;
 };
 
-struct WasmTrap : UncatchableException {
-public:
-    enum Type {
-        Unreachable = 0,
-        IncorrectCallIndirectSignature = 1,
-        MemoryOutOfBounds = 2,
-        CallIndirectOOB = 3,
-        IllegalArithmetic = 4,
-        Unknown,
-    };
-
-    WasmTrap(Type type) : type(type) {}
-
-    virtual std::string description() const noexcept override {
-        std::ostringstream ss;
-        ss
-            << "WebAssembly trap:" << '\n'
-            << " - type: " << type << '\n';
-        
-        return ss.str();
-    }
-
-    Type type;
-
-private:
-    friend std::ostream& operator<<(std::ostream& out, const Type& ty) {
-        switch (ty) {
-            case Type::Unreachable:
-                out << "unreachable";
-                break;
-            case Type::IncorrectCallIndirectSignature:
-                out << "incorrect call_indirect signature";
-                break;
-            case Type::MemoryOutOfBounds:
-                out << "memory access out-of-bounds";
-                break;
-            case Type::CallIndirectOOB:
-                out << "call_indirect out-of-bounds";
-                break;
-            case Type::IllegalArithmetic:
-                out << "illegal arithmetic operation";
-                break;
-            case Type::Unknown:
-            default:
-                out << "unknown";
-                break;
-        }
-        return out;
-    }
-};
-
 struct CatchableException : WasmException {
 public:
     CatchableException(uint32_t type_id, uint32_t value_num) : type_id(type_id), value_num(value_num) {}
@@ -135,10 +83,13 @@ public:
     );
 
     void *get_func(llvm::StringRef name) const;
+    llvm::StringRef get_debug_object();
 private:
     std::unique_ptr<llvm::RuntimeDyld::MemoryManager> memory_manager;
     std::unique_ptr<llvm::object::ObjectFile> object_file;
     std::unique_ptr<llvm::RuntimeDyld> runtime_dyld;
+    std::unique_ptr<llvm::RuntimeDyld::LoadedObjectInfo> loaded_object;
+    llvm::object::OwningBinary<llvm::object::ObjectFile> debug_object;
 };
 
 extern "C" {
@@ -148,38 +99,17 @@ extern "C" {
         return RESULT_OK;
     }
 
-    [[noreturn]] void throw_trap(WasmTrap::Type ty) {
-        throw WasmTrap(ty);
-    }
-
     void module_delete(WasmModule* module) {
         delete module;
     }
 
-    bool invoke_trampoline(
-        trampoline_t trampoline,
-        void* ctx,
-        void* func,
-        void* params,
-        void* results,
-        WasmTrap::Type* trap_out
-    ) throw() {
-        try {
-            trampoline(ctx, func, params, results);
-            return true;
-        } catch(const WasmTrap& e) {
-            *trap_out = e.type;
-            return false;
-        } catch(const WasmException& e) {
-            *trap_out = WasmTrap::Type::Unknown;
-            return false;
-        } catch (...) {
-            *trap_out = WasmTrap::Type::Unknown;
-            return false;
-        }
-    }
-
     void* get_func_symbol(WasmModule* module, const char* name) {
         return module->get_func(llvm::StringRef(name));
     }
+
+    void get_debug_object(WasmModule* module, const uint8_t** ptr_out, size_t* size_out) {
+        auto debug_object = module->get_debug_object();
+        *ptr_out = (const uint8_t*)debug_object.data();
+        *size_out = debug_object.size();
+    }
 }
\ No newline at end of file
@@ -1,28 +1,30 @@
-use crate::intrinsics::Intrinsics;
+use crate::{config::LLVMConfig, intrinsics::Intrinsics, profile::Counters};
 use inkwell::{
     memory_buffer::MemoryBuffer,
     module::Module,
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
-    OptimizationLevel,
 };
 use libc::{
     c_char, mmap, mprotect, munmap, MAP_ANON, MAP_PRIVATE, PROT_EXEC, PROT_NONE, PROT_READ,
     PROT_WRITE,
 };
 use std::{
-    any::Any,
     ffi::CString,
     mem,
     ptr::{self, NonNull},
     slice, str,
-    sync::Once,
+    sync::Arc,
 };
 use wasmer_runtime_core::{
-    backend::{FuncResolver, ProtectedCaller, Token, UserTrapper},
-    error::{RuntimeError, RuntimeResult},
+    backend::{sys, FuncCode, FuncResolver, ProtectedCaller, Token, UserTrapper},
+    error::{RuntimeError, RuntimeResult, TrapCode},
     export::Context,
+    jit_debug::{self, JitDebugRegistration},
     module::{ModuleInfo, ModuleInner},
+    platform,
+    refs::{ExternRef, FuncRef},
     structures::TypedIndex,
+    trap,
     types::{
         FuncIndex, FuncSig, LocalFuncIndex, LocalOrImport, MemoryIndex, SigIndex, TableIndex, Type,
         Value,
@@ -64,6 +66,8 @@ enum WasmTrapType {
     MemoryOutOfBounds = 2,
     CallIndirectOOB = 3,
     IllegalArithmetic = 4,
+    Interrupted = 5,
+    DeadlineExceeded = 6,
     Unknown,
 }
 
@@ -86,17 +90,12 @@ extern "C" {
     ) -> LLVMResult;
     fn module_delete(module: *mut LLVMModule);
     fn get_func_symbol(module: *mut LLVMModule, name: *const c_char) -> *const vm::Func;
+    fn get_debug_object(module: *mut LLVMModule, ptr_out: &mut *const u8, size_out: &mut usize);
+}
 
-    fn throw_trap(ty: i32);
-
-    fn invoke_trampoline(
-        trampoline: unsafe extern "C" fn(*mut vm::Ctx, *const vm::Func, *const u64, *mut u64),
-        vmctx_ptr: *mut vm::Ctx,
-        func_ptr: *const vm::Func,
-        params: *const u64,
-        results: *mut u64,
-        trap_out: *mut WasmTrapType,
-    ) -> bool;
+/// Compiled code calls this with the type of trap when it traps.
+unsafe extern "C" fn raise_trap(ty: WasmTrapType) -> ! {
+    trap::raise(trap_to_error(ty))
 }
 
 fn get_callbacks() -> Callbacks {
@@ -104,6 +103,16 @@ fn get_callbacks() -> Callbacks {
         (size + (4096 - 1)) & !(4096 - 1)
     }
 
+    /// `MAP_JIT` if compiled code is mapped with it, which is only on
+    /// macOS.
+    fn map_jit() -> i32 {
+        if cfg!(target_os = "macos") && platform::map_jit() {
+            0x800
+        } else {
+            0
+        }
+    }
+
     extern "C" fn alloc_memory(
         size: usize,
         protect: MemProtect,
@@ -121,7 +130,7 @@ fn get_callbacks() -> Callbacks {
                     MemProtect::READ_WRITE => PROT_READ | PROT_WRITE,
                     MemProtect::READ_EXECUTE => PROT_READ | PROT_EXEC,
                 },
-                MAP_PRIVATE | MAP_ANON,
+                MAP_PRIVATE | MAP_ANON | map_jit(),
                 -1,
                 0,
             )
@@ -148,11 +157,13 @@ fn get_callbacks() -> Callbacks {
             )
         };
 
-        if res == 0 {
-            LLVMResult::OK
-        } else {
-            LLVMResult::PROTECT_FAILURE
+        if res != 0 {
+            return LLVMResult::PROTECT_FAILURE;
         }
+        if protect == MemProtect::READ_EXECUTE {
+            sys::flush_icache(ptr, size);
+        }
+        LLVMResult::OK
     }
 
     extern "C" fn dealloc_memory(ptr: *mut u8, size: usize) -> LLVMResult {
@@ -188,8 +199,39 @@ fn get_callbacks() -> Callbacks {
             fn_name!("vm.memory.size.dynamic.local") => vmcalls::local_dynamic_memory_size as _,
             fn_name!("vm.memory.grow.static.local") => vmcalls::local_static_memory_grow as _,
             fn_name!("vm.memory.size.static.local") => vmcalls::local_static_memory_size as _,
-
-            fn_name!("vm.exception.trap") => throw_trap as _,
+            fn_name!("vm.memory.grow.shared.local") => {
+                vmcalls::local_shared_static_memory_grow as _
+            }
+            fn_name!("vm.memory.size.shared.local") => {
+                vmcalls::local_shared_static_memory_size as _
+            }
+
+            fn_name!("vm.memory.grow.dynamic.import") => vmcalls::imported_dynamic_memory_grow as _,
+            fn_name!("vm.memory.size.dynamic.import") => vmcalls::imported_dynamic_memory_size as _,
+            fn_name!("vm.memory.grow.static.import") => vmcalls::imported_static_memory_grow as _,
+            fn_name!("vm.memory.size.static.import") => vmcalls::imported_static_memory_size as _,
+            fn_name!("vm.memory.grow.shared.import") => {
+                vmcalls::imported_shared_static_memory_grow as _
+            }
+            fn_name!("vm.memory.size.shared.import") => {
+                vmcalls::imported_shared_static_memory_size as _
+            }
+
+            fn_name!("vm.atomic.rmw") => vmcalls::atomic_rmw as _,
+            fn_name!("vm.atomic.wait") => vmcalls::atomic_wait as _,
+            fn_name!("vm.atomic.notify") => vmcalls::atomic_notify as _,
+
+            fn_name!("vm.memory.copy") => vmcalls::memory_copy as _,
+            fn_name!("vm.memory.fill") => vmcalls::memory_fill as _,
+            fn_name!("vm.memory.init") => vmcalls::memory_init as _,
+            fn_name!("vm.data.drop") => vmcalls::data_drop as _,
+            fn_name!("vm.table.init") => vmcalls::table_init as _,
+            fn_name!("vm.elem.drop") => vmcalls::elem_drop as _,
+
+            fn_name!("vm.table.get") => vmcalls::table_get as _,
+            fn_name!("vm.table.set") => vmcalls::table_set as _,
+
+            fn_name!("vm.exception.trap") => raise_trap as _,
 
             _ => ptr::null(),
         }
@@ -210,72 +252,158 @@ fn get_callbacks() -> Callbacks {
     }
 }
 
+/// Compile `module` to a relocatable object file for the target in
+/// `config`, or the host.
+pub fn compile_object(module: &Module, config: &LLVMConfig) -> Result<MemoryBuffer, String> {
+    let (triple, cpu, features) = match &config.target {
+        Some(target) => {
+            Target::initialize_all(&InitializationConfig::default());
+            (
+                target.triple.clone(),
+                target.cpu.clone(),
+                target.features.clone(),
+            )
+        }
+        None => {
+            Target::initialize_x86(&InitializationConfig {
+                asm_parser: true,
+                asm_printer: true,
+                base: true,
+                disassembler: true,
+                info: true,
+                machine_code: true,
+            });
+            (
+                TargetMachine::get_default_triple().to_string(),
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            )
+        }
+    };
+
+    let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+    let target_machine = target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            config.codegen_opt_level(),
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| format!("cannot compile for {} on {}", triple, cpu))?;
+
+    target_machine
+        .write_to_memory_buffer(module, FileType::Object)
+        .map_err(|e| e.to_string())
+}
+
 unsafe impl Send for LLVMBackend {}
 unsafe impl Sync for LLVMBackend {}
 
 pub struct LLVMBackend {
     module: *mut LLVMModule,
-    #[allow(dead_code)]
-    memory_buffer: MemoryBuffer,
+    // The object file that `module` was loaded from, which it refers to.
+    object: Arc<[u8]>,
+    debug_registration: Option<JitDebugRegistration>,
+    // The counters that the code increments, if it was compiled to.
+    _counters: Option<Arc<Counters>>,
 }
 
 impl LLVMBackend {
-    pub fn new(module: Module, intrinsics: Intrinsics) -> (Self, LLVMProtectedCaller) {
-        Target::initialize_x86(&InitializationConfig {
-            asm_parser: true,
-            asm_printer: true,
-            base: true,
-            disassembler: true,
-            info: true,
-            machine_code: true,
-        });
-        let triple = TargetMachine::get_default_triple().to_string();
-        let target = Target::from_triple(&triple).unwrap();
-        let target_machine = target
-            .create_target_machine(
-                &triple,
-                &TargetMachine::get_host_cpu_name().to_string(),
-                &TargetMachine::get_host_cpu_features().to_string(),
-                OptimizationLevel::Aggressive,
-                RelocMode::PIC,
-                CodeModel::Default,
-            )
-            .unwrap();
+    pub fn new(
+        module: Module,
+        intrinsics: Intrinsics,
+        counters: Option<Arc<Counters>>,
+        config: &LLVMConfig,
+    ) -> (Self, LLVMProtectedCaller) {
+        let memory_buffer = compile_object(&module, config).unwrap();
+
+        let (mut backend, protected_caller) =
+            Self::from_object(memory_buffer.as_slice().into()).expect("failed to load object");
+        backend._counters = counters;
+
+        if let Some(dump) = &config.dump {
+            dump.write("module.o", &backend.object);
+            #[cfg(feature = "disasm")]
+            backend.dump_disassembly(dump);
+        }
 
-        let memory_buffer = target_machine
-            .write_to_memory_buffer(&module, FileType::Object)
-            .unwrap();
-        let mem_buf_slice = memory_buffer.as_slice();
+        (backend, protected_caller)
+    }
 
+    /// Load an object file that `new` compiled, linking it to the
+    /// runtime.
+    pub fn from_object(object: Arc<[u8]>) -> Result<(Self, LLVMProtectedCaller), String> {
         let callbacks = get_callbacks();
         let mut module: *mut LLVMModule = ptr::null_mut();
 
-        let res = unsafe {
-            module_load(
-                mem_buf_slice.as_ptr(),
-                mem_buf_slice.len(),
-                callbacks,
-                &mut module,
-            )
-        };
-
-        static SIGNAL_HANDLER_INSTALLED: Once = Once::new();
-
-        SIGNAL_HANDLER_INSTALLED.call_once(|| unsafe {
-            crate::platform::install_signal_handler();
-        });
+        let res = unsafe { module_load(object.as_ptr(), object.len(), callbacks, &mut module) };
 
         if res != LLVMResult::OK {
-            panic!("failed to load object")
+            return Err(format!("failed to load object: {:?}", res));
         }
 
-        (
+        let debug_registration = if jit_debug::is_enabled() {
+            let mut debug_object_ptr = ptr::null();
+            let mut debug_object_size = 0;
+            unsafe { get_debug_object(module, &mut debug_object_ptr, &mut debug_object_size) };
+            if debug_object_ptr.is_null() {
+                None
+            } else {
+                let debug_object =
+                    unsafe { slice::from_raw_parts(debug_object_ptr, debug_object_size) };
+                Some(jit_debug::register(debug_object.to_vec()))
+            }
+        } else {
+            None
+        };
+
+        Ok((
             Self {
                 module,
-                memory_buffer,
+                object,
+                debug_registration,
+                _counters: None,
             },
             LLVMProtectedCaller { module },
-        )
+        ))
+    }
+
+    /// Write the disassembly of each function in the object file.
+    #[cfg(feature = "disasm")]
+    fn dump_disassembly(&self, dump: &crate::config::Dump) {
+        use goblin::elf::{sym::STT_FUNC, Elf};
+
+        // Only the symbols of ELF object files are read.
+        let elf = match Elf::parse(&self.object) {
+            Ok(elf) => elf,
+            Err(_) => return,
+        };
+        for sym in elf.syms.iter() {
+            if sym.st_type() != STT_FUNC || sym.st_size == 0 {
+                continue;
+            }
+            let name = match elf.strtab.get(sym.st_name) {
+                Some(Ok(name)) => name,
+                _ => continue,
+            };
+            let c_str = match CString::new(name) {
+                Ok(c_str) => c_str,
+                Err(_) => continue,
+            };
+            let ptr = unsafe { get_func_symbol(self.module, c_str.as_ptr()) };
+            if ptr.is_null() {
+                continue;
+            }
+            let text = unsafe { disassemble(ptr as *const u8, sym.st_size as usize) };
+            dump.write(&format!("{}.s", name), text.as_bytes());
+        }
+    }
+
+    /// The object file that the module was loaded from.
+    pub fn object(&self) -> Arc<[u8]> {
+        Arc::clone(&self.object)
     }
 
     pub fn get_func(
@@ -283,11 +411,11 @@ impl LLVMBackend {
         info: &ModuleInfo,
         local_func_index: LocalFuncIndex,
     ) -> Option<NonNull<vm::Func>> {
-        let index = info.imported_functions.len() + local_func_index.index();
+        let name = func_symbol(info, local_func_index);
         let name = if cfg!(target_os = "macos") {
-            format!("_fn{}", index)
+            format!("_{}", name)
         } else {
-            format!("fn{}", index)
+            name
         };
 
         let c_str = CString::new(name).ok()?;
@@ -295,10 +423,43 @@ impl LLVMBackend {
 
         NonNull::new(ptr as _)
     }
+
+    /// The machine code of a local function, whose size is read from
+    /// the symbol table of the object file. Only ELF object files are
+    /// read.
+    pub fn get_func_code(
+        &self,
+        info: &ModuleInfo,
+        local_func_index: LocalFuncIndex,
+    ) -> Option<&[u8]> {
+        use goblin::elf::Elf;
+
+        let ptr = self.get_func(info, local_func_index)?;
+        let name = func_symbol(info, local_func_index);
+        let elf = Elf::parse(&self.object).ok()?;
+        let sym = elf
+            .syms
+            .iter()
+            .find(|sym| match elf.strtab.get(sym.st_name) {
+                Some(Ok(sym_name)) => sym_name == name,
+                _ => false,
+            })?;
+        Some(unsafe { slice::from_raw_parts(ptr.as_ptr() as *const u8, sym.st_size as usize) })
+    }
+}
+
+/// The name of the symbol of a local function in the object file.
+fn func_symbol(info: &ModuleInfo, local_func_index: LocalFuncIndex) -> String {
+    format!(
+        "fn{}",
+        info.imported_functions.len() + local_func_index.index()
+    )
 }
 
 impl Drop for LLVMBackend {
     fn drop(&mut self) {
+        // Unregister the code from debuggers before it goes away.
+        self.debug_registration.take();
         unsafe { module_delete(self.module) }
     }
 }
@@ -311,9 +472,30 @@ impl FuncResolver for LLVMBackend {
     ) -> Option<NonNull<vm::Func>> {
         self.get_func(&module.info, local_func_index)
     }
-}
 
-struct Placeholder;
+    fn calls_through_ctx(&self) -> bool {
+        true
+    }
+
+    fn code(&self, module: &ModuleInner, local_func_index: LocalFuncIndex) -> Option<FuncCode> {
+        // The module is compiled as a whole, so there's no time per function.
+        self.get_func_code(&module.info, local_func_index)
+            .map(|code| FuncCode {
+                code,
+                compile_time: None,
+            })
+    }
+
+    #[cfg(feature = "disasm")]
+    fn disassemble(
+        &self,
+        module: &ModuleInner,
+        local_func_index: LocalFuncIndex,
+    ) -> Option<String> {
+        let code = self.get_func_code(&module.info, local_func_index)?;
+        Some(unsafe { disassemble(code.as_ptr(), code.len()) })
+    }
+}
 
 unsafe impl Send for LLVMProtectedCaller {}
 unsafe impl Sync for LLVMProtectedCaller {}
@@ -322,6 +504,19 @@ pub struct LLVMProtectedCaller {
     module: *mut LLVMModule,
 }
 
+fn trap_to_error(trap: WasmTrapType) -> RuntimeError {
+    RuntimeError::trap(match trap {
+        WasmTrapType::Unreachable => TrapCode::Unreachable,
+        WasmTrapType::IncorrectCallIndirectSignature => TrapCode::BadSignature,
+        WasmTrapType::MemoryOutOfBounds => TrapCode::MemoryOutOfBounds,
+        WasmTrapType::CallIndirectOOB => TrapCode::TableOutOfBounds,
+        WasmTrapType::IllegalArithmetic => TrapCode::IllegalArithmetic,
+        WasmTrapType::Interrupted => TrapCode::Interrupted,
+        WasmTrapType::DeadlineExceeded => TrapCode::DeadlineExceeded,
+        WasmTrapType::Unknown => TrapCode::Unknown,
+    })
+}
+
 impl ProtectedCaller for LLVMProtectedCaller {
     fn call(
         &self,
@@ -333,18 +528,14 @@ impl ProtectedCaller for LLVMProtectedCaller {
         _: Token,
     ) -> RuntimeResult<Vec<Value>> {
         let (func_ptr, ctx, signature, sig_index) =
-            get_func_from_index(&module, import_backing, func_index);
+            get_func_from_index(&module, import_backing, vmctx, func_index);
 
         let vmctx_ptr = match ctx {
             Context::External(external_vmctx) => external_vmctx,
             Context::Internal => vmctx,
+            Context::InternalWithEnv(_) => unreachable!("host closures are resolved at link time"),
         };
 
-        assert!(
-            signature.returns().len() <= 1,
-            "multi-value returns not yet supported"
-        );
-
         assert!(
             signature.check_param_value_types(params),
             "incorrect signature"
@@ -357,6 +548,8 @@ impl ProtectedCaller for LLVMProtectedCaller {
                 Value::I64(x) => *x as u64,
                 Value::F32(x) => x.to_bits() as u64,
                 Value::F64(x) => x.to_bits(),
+                Value::ExternRef(x) => x.to_bits(),
+                Value::FuncRef(x) => x.to_bits(),
             })
             .collect();
 
@@ -376,69 +569,43 @@ impl ProtectedCaller for LLVMProtectedCaller {
             mem::transmute(symbol)
         };
 
-        let mut trap_out = WasmTrapType::Unknown;
-
         // Here we go.
-        let success = unsafe {
-            invoke_trampoline(
-                trampoline,
+        trap::call_protected(None, || unsafe {
+            trampoline(
                 vmctx_ptr,
                 func_ptr,
                 param_vec.as_ptr(),
                 return_vec.as_mut_ptr(),
-                &mut trap_out,
             )
-        };
+        })?;
 
-        if success {
-            Ok(return_vec
-                .iter()
-                .zip(signature.returns().iter())
-                .map(|(&x, ty)| match ty {
-                    Type::I32 => Value::I32(x as i32),
-                    Type::I64 => Value::I64(x as i64),
-                    Type::F32 => Value::F32(f32::from_bits(x as u32)),
-                    Type::F64 => Value::F64(f64::from_bits(x as u64)),
-                })
-                .collect())
-        } else {
-            Err(match trap_out {
-                WasmTrapType::Unreachable => RuntimeError::Trap {
-                    msg: "unreachable".into(),
-                },
-                WasmTrapType::IncorrectCallIndirectSignature => RuntimeError::Trap {
-                    msg: "uncorrect call_indirect signature".into(),
-                },
-                WasmTrapType::MemoryOutOfBounds => RuntimeError::Trap {
-                    msg: "memory out-of-bounds access".into(),
-                },
-                WasmTrapType::CallIndirectOOB => RuntimeError::Trap {
-                    msg: "call_indirect out-of-bounds".into(),
-                },
-                WasmTrapType::IllegalArithmetic => RuntimeError::Trap {
-                    msg: "illegal arithmetic operation".into(),
-                },
-                WasmTrapType::Unknown => RuntimeError::Trap {
-                    msg: "unknown trap".into(),
-                },
+        Ok(return_vec
+            .iter()
+            .zip(signature.returns().iter())
+            .map(|(&x, ty)| match ty {
+                Type::I32 => Value::I32(x as i32),
+                Type::I64 => Value::I64(x as i64),
+                Type::F32 => Value::F32(f32::from_bits(x as u32)),
+                Type::F64 => Value::F64(f64::from_bits(x as u64)),
+                Type::ExternRef => Value::ExternRef(ExternRef::from_bits(x)),
+                Type::FuncRef => Value::FuncRef(FuncRef::from_bits(x)),
             })
-        }
+            .collect())
     }
 
-    fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
-        Box::new(Placeholder)
+    fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+        trap::call_protected(None, || f())
     }
-}
 
-impl UserTrapper for Placeholder {
-    unsafe fn do_early_trap(&self, _data: Box<dyn Any>) -> ! {
-        unimplemented!("do early trap")
+    fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
+        Box::new(trap::Trapper)
     }
 }
 
 fn get_func_from_index<'a>(
     module: &'a ModuleInner,
     import_backing: &ImportBacking,
+    vmctx: *mut vm::Ctx,
     func_index: FuncIndex,
 ) -> (*const vm::Func, Context, &'a FuncSig, SigIndex) {
     let sig_index = *module
@@ -448,13 +615,10 @@ fn get_func_from_index<'a>(
         .expect("broken invariant, incorrect func index");
 
     let (func_ptr, ctx) = match func_index.local_or_import(&module.info) {
+        // The code of a local function may have been replaced, so it's
+        // looked up in the instance rather than the module.
         LocalOrImport::Local(local_func_index) => (
-            module
-                .func_resolver
-                .get(&module, local_func_index)
-                .expect("broken invariant, func resolver not synced with module.exports")
-                .cast()
-                .as_ptr() as *const _,
+            unsafe { (*vmctx).local_function(local_func_index) },
             Context::Internal,
         ),
         LocalOrImport::Import(imported_func_index) => {
@@ -471,32 +635,31 @@ fn get_func_from_index<'a>(
     (func_ptr, ctx, signature, sig_index)
 }
 
+/// The x86-64 assembly of the `size` bytes of machine code at `ptr`.
 #[cfg(feature = "disasm")]
-unsafe fn disass_ptr(ptr: *const u8, size: usize, inst_count: usize) {
+unsafe fn disassemble(ptr: *const u8, size: usize) -> String {
     use capstone::arch::BuildsCapstone;
-    let mut cs = capstone::Capstone::new() // Call builder-pattern
-        .x86() // X86 architecture
-        .mode(capstone::arch::x86::ArchMode::Mode64) // 64-bit mode
-        .detail(true) // Generate extra instruction details
+    use std::fmt::Write;
+
+    let cs = capstone::Capstone::new()
+        .x86()
+        .mode(capstone::arch::x86::ArchMode::Mode64)
         .build()
         .expect("Failed to create Capstone object");
 
-    // Get disassembled instructions
-    let insns = cs
-        .disasm_count(
-            std::slice::from_raw_parts(ptr, size),
-            ptr as u64,
-            inst_count,
-        )
-        .expect("Failed to disassemble");
-
-    println!("count = {}", insns.len());
+    let mut text = String::new();
+    let insns = match cs.disasm_all(std::slice::from_raw_parts(ptr, size), ptr as u64) {
+        Ok(insns) => insns,
+        Err(e) => return format!("failed to disassemble: {:?}\n", e),
+    };
     for insn in insns.iter() {
-        println!(
+        let _ = writeln!(
+            text,
             "0x{:x}: {:6} {}",
             insn.address(),
             insn.mnemonic().unwrap_or(""),
             insn.op_str().unwrap_or("")
         );
     }
+    text
 }
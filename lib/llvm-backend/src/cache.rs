@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use wasmer_runtime_core::{
+    backend::{sys::Memory, CacheGen},
+    cache::{Error, Target},
+    module::{ModuleInfo, ModuleInner},
+};
+
+/// Caches a module as the relocatable object file that LLVM compiled
+/// it to, so loading it again only has to link the object file to the
+/// runtime, without compiling anything.
+///
+/// The object file is compiled for the features of the host's CPU,
+/// unless the compiler was given a baseline target, so it can only be
+/// loaded on machines with the features of its target.
+pub struct CacheGenerator {
+    /// `None` if the code counts for a profiler, which it refers to by
+    /// address, so it can't be loaded in another process.
+    object: Option<Arc<[u8]>>,
+    target: Target,
+}
+
+impl CacheGenerator {
+    pub fn new(object: Arc<[u8]>, target: Target) -> Self {
+        Self {
+            object: Some(object),
+            target,
+        }
+    }
+
+    pub fn instrumented() -> Self {
+        Self {
+            object: None,
+            target: Target::host(),
+        }
+    }
+}
+
+impl CacheGen for CacheGenerator {
+    fn generate_cache(
+        &self,
+        module: &ModuleInner,
+    ) -> Result<(Box<ModuleInfo>, Box<[u8]>, Memory), Error> {
+        let object = self.object.as_ref().ok_or_else(|| {
+            Error::Unknown("code that counts for a profiler cannot be cached".to_string())
+        })?;
+        let info = Box::new(module.info.clone());
+
+        // The object file is the backend data: it isn't code that can
+        // be run where it is, so nothing goes in the code memory.
+        let compiled_code = Memory::with_size(0).map_err(|e| Error::Unknown(e.to_string()))?;
+
+        Ok((info, object.to_vec().into_boxed_slice(), compiled_code))
+    }
+
+    fn target(&self) -> Target {
+        self.target.clone()
+    }
+
+    fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
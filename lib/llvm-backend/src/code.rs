@@ -1,27 +1,36 @@
 use inkwell::{
+    attributes::AttributeLoc,
     builder::Builder,
     context::Context,
     module::{Linkage, Module},
-    passes::PassManager,
-    types::{BasicType, BasicTypeEnum, FunctionType, IntType, PointerType},
-    values::{BasicValue, FloatValue, FunctionValue, IntValue, PhiValue, PointerValue},
+    types::{BasicType, BasicTypeEnum, FunctionType, IntType, PointerType, StructType},
+    values::{
+        BasicValue, BasicValueEnum, FloatValue, FunctionValue, IntValue, PhiValue, PointerValue,
+    },
     AddressSpace, FloatPredicate, IntPredicate,
 };
 use smallvec::SmallVec;
+use std::sync::{atomic::AtomicU64, Arc};
 use wasmer_runtime_core::{
+    deterministic,
     memory::MemoryType,
+    metering::FunctionCosts,
     module::{ExportIndex, ModuleInfo},
     structures::{Map, SliceMap, TypedIndex},
     types::{
         FuncIndex, FuncSig, GlobalIndex, LocalFuncIndex, LocalOrImport, MemoryIndex, SigIndex,
         TableIndex, Type,
     },
+    vmcalls::AtomicRmwOp,
 };
 use wasmparser::{
     BinaryReaderError, CodeSectionReader, LocalsReader, MemoryImmediate, Operator, OperatorsReader,
+    Type as WpType,
 };
 
+use crate::config::{Dump, LLVMConfig};
 use crate::intrinsics::{CtxType, GlobalCache, Intrinsics, MemoryCache};
+use crate::profile::{self, Counters, Profile};
 use crate::read_info::type_to_type;
 use crate::state::{ControlFrame, IfElseState, State};
 use crate::trampolines::generate_trampolines;
@@ -29,7 +38,7 @@ use crate::trampolines::generate_trampolines;
 fn func_sig_to_llvm(context: &Context, intrinsics: &Intrinsics, sig: &FuncSig) -> FunctionType {
     let user_param_types = sig.params().iter().map(|&ty| type_to_llvm(intrinsics, ty));
 
-    let param_types: Vec<_> = std::iter::once(intrinsics.ctx_ptr_ty.as_basic_type_enum())
+    let mut param_types: Vec<_> = std::iter::once(intrinsics.ctx_ptr_ty.as_basic_type_enum())
         .chain(user_param_types)
         .collect();
 
@@ -37,31 +46,48 @@ fn func_sig_to_llvm(context: &Context, intrinsics: &Intrinsics, sig: &FuncSig) -
         &[] => intrinsics.void_ty.fn_type(&param_types, false),
         &[single_value] => type_to_llvm(intrinsics, single_value).fn_type(&param_types, false),
         returns @ _ => {
-            let basic_types: Vec<_> = returns
-                .iter()
-                .map(|&ty| type_to_llvm(intrinsics, ty))
-                .collect();
-
-            context
-                .struct_type(&basic_types, false)
-                .fn_type(&param_types, false)
+            // Several results are written through a pointer, like Rust's
+            // typed functions and host functions return them.
+            let results_ptr_ty = results_struct_ty(context, intrinsics, returns)
+                .ptr_type(AddressSpace::Generic)
+                .as_basic_type_enum();
+            param_types.push(results_ptr_ty);
+            intrinsics.void_ty.fn_type(&param_types, false)
         }
     }
 }
 
+/// The struct that a function returning `returns` writes them to, when
+/// it returns more than one value. See `FuncSig::returns_through_pointer`.
+pub(crate) fn results_struct_ty(
+    context: &Context,
+    intrinsics: &Intrinsics,
+    returns: &[Type],
+) -> StructType {
+    let basic_types: Vec<_> = returns
+        .iter()
+        .map(|&ty| type_to_llvm(intrinsics, ty))
+        .collect();
+    context.struct_type(&basic_types, false)
+}
+
 fn type_to_llvm(intrinsics: &Intrinsics, ty: Type) -> BasicTypeEnum {
     match ty {
         Type::I32 => intrinsics.i32_ty.as_basic_type_enum(),
         Type::I64 => intrinsics.i64_ty.as_basic_type_enum(),
         Type::F32 => intrinsics.f32_ty.as_basic_type_enum(),
         Type::F64 => intrinsics.f64_ty.as_basic_type_enum(),
+        // References are handles into the runtime's registries.
+        Type::ExternRef | Type::FuncRef => intrinsics.i64_ty.as_basic_type_enum(),
     }
 }
 
 pub fn parse_function_bodies(
     info: &ModuleInfo,
     code_reader: CodeSectionReader,
-) -> Result<(Module, Intrinsics), BinaryReaderError> {
+    costs: Option<&Map<LocalFuncIndex, FunctionCosts>>,
+    config: &LLVMConfig,
+) -> Result<(Module, Intrinsics, Option<Arc<Counters>>), BinaryReaderError> {
     let context = Context::create();
     let module = context.create_module("module");
     let builder = context.create_builder();
@@ -90,15 +116,32 @@ pub fn parse_function_bodies(
                 Some(Linkage::External),
             );
             func.set_personality_function(personality_func);
+            if config.fast_math {
+                // Lets the code generator fuse and reassociate the float
+                // operations of the function, and ignore the sign of zero.
+                for &name in &["unsafe-fp-math", "no-signed-zeros-fp-math"] {
+                    func.add_attribute(
+                        AttributeLoc::Function,
+                        context.create_string_attribute(name, "true"),
+                    );
+                }
+            }
             func
         })
         .collect();
 
+    let mut counters = config.profiler.as_ref().map(|_| Counters::new());
+
     for (local_func_index, body) in code_reader.into_iter().enumerate() {
         let body = body?;
+        let function_counters = match counters.as_mut() {
+            Some(counters) => Some(counters.add_function(&body)?),
+            None => None,
+        };
 
         let locals_reader = body.get_locals_reader()?;
         let op_reader = body.get_operators_reader()?;
+        let local_func_index = LocalFuncIndex::new(local_func_index);
 
         parse_function(
             &context,
@@ -108,13 +151,16 @@ pub fn parse_function_bodies(
             info,
             &signatures,
             &functions,
-            LocalFuncIndex::new(local_func_index),
+            local_func_index,
             locals_reader,
             op_reader,
+            costs.map(|costs| &costs[local_func_index]),
+            function_counters,
+            config.profile.as_ref().map(|profile| &**profile),
         )
         .map_err(|e| BinaryReaderError {
             message: e.message,
-            offset: local_func_index,
+            offset: local_func_index.index(),
         })?;
     }
 
@@ -122,23 +168,139 @@ pub fn parse_function_bodies(
 
     generate_trampolines(info, &signatures, &module, &context, &builder, &intrinsics);
 
-    let pass_manager = PassManager::create_for_module();
-    // pass_manager.add_verifier_pass();
-    pass_manager.add_function_inlining_pass();
-    pass_manager.add_promote_memory_to_register_pass();
-    pass_manager.add_cfg_simplification_pass();
-    // pass_manager.add_instruction_combining_pass();
-    pass_manager.add_aggressive_inst_combiner_pass();
-    pass_manager.add_merged_load_store_motion_pass();
-    // pass_manager.add_sccp_pass();
-    // pass_manager.add_gvn_pass();
-    pass_manager.add_new_gvn_pass();
-    pass_manager.add_aggressive_dce_pass();
+    if let Some(dump) = &config.dump {
+        dump_functions(dump, &functions, "ll");
+    }
+
+    let pass_manager = config.pass_manager();
     pass_manager.run_on_module(&module);
 
+    if let Some(dump) = &config.dump {
+        dump_functions(dump, &functions, "opt.ll");
+    }
+
     // module.print_to_stderr();
 
-    Ok((module, intrinsics))
+    let counters = counters.map(Arc::new);
+    if let (Some(profiler), Some(counters)) = (&config.profiler, &counters) {
+        profiler.attach(Arc::clone(counters));
+    }
+
+    Ok((module, intrinsics, counters))
+}
+
+fn dump_functions(dump: &Dump, functions: &Map<LocalFuncIndex, FunctionValue>, extension: &str) {
+    for (_, function) in functions.iter() {
+        let name = format!("{}.{}", function.get_name().to_string_lossy(), extension);
+        dump.write(&name, function.print_to_string().to_string().as_bytes());
+    }
+}
+
+/// Takes `cost` points from the instance, trapping if that leaves it
+/// with less than none. The runtime reports the trap as running out
+/// of gas.
+/// Generates code that traps if the instance has been interrupted.
+/// The runtime reports the trap as an interrupt.
+///
+/// The flag is loaded inline, with a volatile load so that it isn't
+/// hoisted out of the loops that check it.
+fn check_interrupted(
+    context: &Context,
+    builder: &Builder,
+    function: &FunctionValue,
+    intrinsics: &Intrinsics,
+    ctx: &CtxType,
+) {
+    let interrupted = builder
+        .build_load(ctx.interrupted_ptr(), "interrupted")
+        .into_int_value();
+    interrupted
+        .as_instruction()
+        .unwrap()
+        .set_volatile(true)
+        .unwrap();
+    let interrupted = builder.build_int_compare(
+        IntPredicate::NE,
+        interrupted,
+        intrinsics.i8_ty.const_int(0, false),
+        "interrupted_bool",
+    );
+    let interrupted_block = context.append_basic_block(function, "interrupted");
+    let continue_block = context.append_basic_block(function, "not_interrupted");
+    builder.build_conditional_branch(interrupted, &interrupted_block, &continue_block);
+    builder.position_at_end(&interrupted_block);
+    builder.build_call(
+        intrinsics.throw_trap,
+        &[intrinsics.trap_interrupted],
+        "throw",
+    );
+    builder.build_unreachable();
+    builder.position_at_end(&continue_block);
+}
+
+/// Generates code that traps if the epoch deadline of the instance has
+/// been reached. The runtime reports the trap as an exceeded deadline.
+///
+/// Like the interrupt flag, the epoch is loaded inline and volatile.
+fn check_deadline(
+    context: &Context,
+    builder: &Builder,
+    function: &FunctionValue,
+    intrinsics: &Intrinsics,
+    ctx: &CtxType,
+) {
+    let epoch = builder
+        .build_load(ctx.epoch_ptr(), "epoch")
+        .into_int_value();
+    epoch.as_instruction().unwrap().set_volatile(true).unwrap();
+    let epoch_deadline = builder
+        .build_load(ctx.epoch_deadline_ptr(), "epoch_deadline")
+        .into_int_value();
+    let deadline_reached =
+        builder.build_int_compare(IntPredicate::UGE, epoch, epoch_deadline, "deadline_reached");
+    let deadline_block = context.append_basic_block(function, "deadline_reached");
+    let continue_block = context.append_basic_block(function, "before_deadline");
+    builder.build_conditional_branch(deadline_reached, &deadline_block, &continue_block);
+    builder.position_at_end(&deadline_block);
+    builder.build_call(
+        intrinsics.throw_trap,
+        &[intrinsics.trap_deadline_exceeded],
+        "throw",
+    );
+    builder.build_unreachable();
+    builder.position_at_end(&continue_block);
+}
+
+fn charge_points(
+    context: &Context,
+    builder: &Builder,
+    function: &FunctionValue,
+    intrinsics: &Intrinsics,
+    ctx: &CtxType,
+    cost: u64,
+) {
+    if cost == 0 {
+        return;
+    }
+
+    let points_ptr = ctx.remaining_points_ptr();
+    let points = builder.build_load(points_ptr, "points").into_int_value();
+    let points = builder.build_int_sub(points, intrinsics.i64_ty.const_int(cost, false), "points");
+    builder.build_store(points_ptr, points);
+
+    let out_of_gas =
+        builder.build_int_compare(IntPredicate::SLT, points, intrinsics.i64_zero, "out_of_gas");
+    let out_of_gas_block = context.append_basic_block(function, "out_of_gas");
+    let continue_block = context.append_basic_block(function, "enough_points");
+    builder.build_conditional_branch(out_of_gas, &out_of_gas_block, &continue_block);
+    builder.position_at_end(&out_of_gas_block);
+    builder.build_call(
+        intrinsics.throw_trap,
+        &[intrinsics.trap_unreachable],
+        "throw",
+    );
+    builder.build_unreachable();
+    builder.position_at_end(&continue_block);
 }
 
 fn parse_function(
@@ -152,6 +314,9 @@ fn parse_function(
     func_index: LocalFuncIndex,
     locals_reader: LocalsReader,
     op_reader: OperatorsReader,
+    costs: Option<&FunctionCosts>,
+    counters: Option<&[AtomicU64]>,
+    profile: Option<&Profile>,
 ) -> Result<(), BinaryReaderError> {
     let sig_index = info.func_assoc[func_index.convert_up(info)];
     let func_sig = &info.signatures[sig_index];
@@ -180,6 +345,7 @@ fn parse_function(
         function
             .get_param_iter()
             .skip(1)
+            .take(func_sig.params().len())
             .enumerate()
             .map(|(index, param)| {
                 let ty = param.get_type();
@@ -203,6 +369,7 @@ fn parse_function(
             Type::I64 => intrinsics.i64_zero.as_basic_value_enum(),
             Type::F32 => intrinsics.f32_zero.as_basic_value_enum(),
             Type::F64 => intrinsics.f64_zero.as_basic_value_enum(),
+            Type::ExternRef | Type::FuncRef => intrinsics.i64_zero.as_basic_value_enum(),
         };
 
         for _ in 0..count {
@@ -223,9 +390,43 @@ fn parse_function(
     cache_builder.position_before(&entry_end_inst);
     let mut ctx = intrinsics.ctx(info, builder, &function, cache_builder);
     let mut unreachable_depth = 0;
+    let mut loops = 0;
+    let mut branches = 0;
+    let canonicalize_nans = deterministic::is_deterministic();
+
+    if let Some(costs) = costs {
+        charge_points(context, builder, &function, intrinsics, &ctx, costs.entry);
+    }
+    check_interrupted(context, builder, &function, intrinsics, &ctx);
+    check_deadline(context, builder, &function, intrinsics, &ctx);
+    if let Some(counters) = counters {
+        let one = intrinsics.i64_ty.const_int(1, false);
+        profile::increment(builder, intrinsics, &counters[0], one);
+    }
 
     for op in op_reader {
         let op = op?;
+
+        // Loops are counted even when they're unreachable, since their
+        // costs are looked up by the order that they appear in.
+        let loop_cost = match op {
+            Operator::Loop { .. } => {
+                loops += 1;
+                costs.map(|costs| costs.loop_cost(loops - 1))
+            }
+            _ => None,
+        };
+        // Branches are numbered the same way, for their counters and
+        // their profile.
+        let branch = match op {
+            Operator::BrIf { .. } | Operator::If { .. } => {
+                branches += 1;
+                Some(branches - 1)
+            }
+            _ => None,
+        };
+        let canonicalize_result = canonicalize_nans && produces_nan(&op);
+
         if !state.reachable {
             match op {
                 Operator::Block { ty: _ } | Operator::Loop { ty: _ } | Operator::If { ty: _ } => {
@@ -294,6 +495,17 @@ fn parse_function(
                 };
 
                 builder.position_at_end(&loop_body);
+
+                if let Some(cost) = loop_cost {
+                    charge_points(context, builder, &function, intrinsics, &ctx, cost);
+                }
+
+                // Check whether the instance has been interrupted, or its
+                // epoch deadline has been reached, at the start of every
+                // iteration. The runtime reports the traps as such.
+                check_interrupted(context, builder, &function, intrinsics, &ctx);
+                check_deadline(context, builder, &function, intrinsics, &ctx);
+
                 state.push_loop(loop_body, loop_next, phis);
             }
             Operator::Br { relative_depth } => {
@@ -353,7 +565,16 @@ fn parse_function(
                     intrinsics.i32_zero,
                     &state.var_name(),
                 );
-                builder.build_conditional_branch(cond_value, frame.br_dest(), &else_block);
+                let branch = branch.unwrap();
+                if let Some(counters) = counters {
+                    let counters = &counters[1 + 2 * branch..][..2];
+                    profile::count_branch(builder, intrinsics, counters, cond_value);
+                }
+                let branch_inst =
+                    builder.build_conditional_branch(cond_value, frame.br_dest(), &else_block);
+                if let Some(profile) = profile {
+                    profile::set_branch_weights(context, profile, func_index, branch, branch_inst);
+                }
                 builder.position_at_end(&else_block);
             }
             Operator::BrTable { ref table } => {
@@ -435,7 +656,16 @@ fn parse_function(
                     &state.var_name(),
                 );
 
-                builder.build_conditional_branch(cond_value, &if_then_block, &if_else_block);
+                let branch = branch.unwrap();
+                if let Some(counters) = counters {
+                    let counters = &counters[1 + 2 * branch..][..2];
+                    profile::count_branch(builder, intrinsics, counters, cond_value);
+                }
+                let branch_inst =
+                    builder.build_conditional_branch(cond_value, &if_then_block, &if_else_block);
+                if let Some(profile) = profile {
+                    profile::set_branch_weights(context, profile, func_index, branch, branch_inst);
+                }
                 builder.position_at_end(&if_then_block);
                 state.push_if(if_then_block, if_else_block, end_block, end_phis);
             }
@@ -537,7 +767,7 @@ fn parse_function(
 
                 let phis = frame.phis().to_vec();
 
-                for phi in phis.iter() {
+                for phi in phis.iter().rev() {
                     let arg = state.pop1()?;
                     phi.add_incoming(&[(&arg, &current_block)]);
                 }
@@ -662,12 +892,20 @@ fn parse_function(
                 let llvm_sig = signatures[sigindex];
                 let func_sig = &info.signatures[sigindex];
 
+                let results_ptr = if func_sig.returns_through_pointer() {
+                    let results_ty = results_struct_ty(context, intrinsics, func_sig.returns());
+                    Some(builder.build_alloca(results_ty, &state.var_name()))
+                } else {
+                    None
+                };
+
                 let call_site = match func_index.local_or_import(info) {
                     LocalOrImport::Local(local_func_index) => {
                         let params: Vec<_> = [ctx.basic()]
                             .iter()
                             .chain(state.peekn(func_sig.params().len())?.iter())
                             .map(|v| *v)
+                            .chain(results_ptr.map(|ptr| ptr.as_basic_value_enum()))
                             .collect();
 
                         let func_ptr = ctx.local_func(local_func_index, llvm_sig);
@@ -680,6 +918,7 @@ fn parse_function(
                             .iter()
                             .chain(state.peekn(func_sig.params().len())?.iter())
                             .map(|v| *v)
+                            .chain(results_ptr.map(|ptr| ptr.as_basic_value_enum()))
                             .collect();
 
                         let func_ptr_ty = llvm_sig.ptr_type(AddressSpace::Generic);
@@ -696,20 +935,10 @@ fn parse_function(
 
                 state.popn(func_sig.params().len())?;
 
-                if let Some(basic_value) = call_site.try_as_basic_value().left() {
-                    match func_sig.returns().len() {
-                        1 => state.push1(basic_value),
-                        count @ _ => {
-                            // This is a multi-value return.
-                            let struct_value = basic_value.into_struct_value();
-                            for i in 0..(count as u32) {
-                                let value = builder
-                                    .build_extract_value(struct_value, i, &state.var_name())
-                                    .unwrap();
-                                state.push1(value);
-                            }
-                        }
-                    }
+                if let Some(results_ptr) = results_ptr {
+                    load_results(builder, &mut state, results_ptr, func_sig.returns().len());
+                } else if let Some(basic_value) = call_site.try_as_basic_value().left() {
+                    state.push1(basic_value);
                 }
             }
             Operator::CallIndirect { index, table_index } => {
@@ -848,8 +1077,17 @@ fn parse_function(
 
                 let pushed_args = state.popn_save(wasmer_fn_sig.params().len())?;
 
+                let results_ptr = if wasmer_fn_sig.returns_through_pointer() {
+                    let results_ty =
+                        results_struct_ty(context, intrinsics, wasmer_fn_sig.returns());
+                    Some(builder.build_alloca(results_ty, &state.var_name()))
+                } else {
+                    None
+                };
+
                 let args: Vec<_> = std::iter::once(ctx_ptr)
                     .chain(pushed_args.into_iter())
+                    .chain(results_ptr.map(|ptr| ptr.as_basic_value_enum()))
                     .collect();
 
                 let typed_func_ptr = builder.build_pointer_cast(
@@ -866,7 +1104,10 @@ fn parse_function(
                         let value = call_site.try_as_basic_value().left().unwrap();
                         state.push1(value);
                     }
-                    returns @ _ => unimplemented!("multi-value returns"),
+                    returns @ _ => {
+                        let results_ptr = results_ptr.unwrap();
+                        load_results(builder, &mut state, results_ptr, returns.len());
+                    }
                 }
             }
 
@@ -1496,8 +1737,15 @@ fn parse_function(
             }
             Operator::I32TruncSSatF32 | Operator::I32TruncSSatF64 => {
                 let v1 = state.pop1()?.into_float_value();
-                let res =
-                    builder.build_float_to_signed_int(v1, intrinsics.i32_ty, &state.var_name());
+                let res = trunc_sat(
+                    builder,
+                    intrinsics.i32_ty,
+                    true,
+                    -2147483648.0,
+                    2147483648.0,
+                    v1,
+                    &state.var_name(),
+                );
                 state.push1(res);
             }
             Operator::I64TruncSF32 => {
@@ -1532,8 +1780,15 @@ fn parse_function(
             }
             Operator::I64TruncSSatF32 | Operator::I64TruncSSatF64 => {
                 let v1 = state.pop1()?.into_float_value();
-                let res =
-                    builder.build_float_to_signed_int(v1, intrinsics.i64_ty, &state.var_name());
+                let res = trunc_sat(
+                    builder,
+                    intrinsics.i64_ty,
+                    true,
+                    -9223372036854775808.0,
+                    9223372036854775808.0,
+                    v1,
+                    &state.var_name(),
+                );
                 state.push1(res);
             }
             Operator::I32TruncUF32 => {
@@ -1568,8 +1823,15 @@ fn parse_function(
             }
             Operator::I32TruncUSatF32 | Operator::I32TruncUSatF64 => {
                 let v1 = state.pop1()?.into_float_value();
-                let res =
-                    builder.build_float_to_unsigned_int(v1, intrinsics.i32_ty, &state.var_name());
+                let res = trunc_sat(
+                    builder,
+                    intrinsics.i32_ty,
+                    false,
+                    0.0,
+                    4294967296.0,
+                    v1,
+                    &state.var_name(),
+                );
                 state.push1(res);
             }
             Operator::I64TruncUF32 => {
@@ -1604,8 +1866,15 @@ fn parse_function(
             }
             Operator::I64TruncUSatF32 | Operator::I64TruncUSatF64 => {
                 let v1 = state.pop1()?.into_float_value();
-                let res =
-                    builder.build_float_to_unsigned_int(v1, intrinsics.i64_ty, &state.var_name());
+                let res = trunc_sat(
+                    builder,
+                    intrinsics.i64_ty,
+                    false,
+                    0.0,
+                    18446744073709551616.0,
+                    v1,
+                    &state.var_name(),
+                );
                 state.push1(res);
             }
             Operator::F32DemoteF64 => {
@@ -2143,9 +2412,268 @@ fn parse_function(
                 );
                 state.push1(result.try_as_basic_value().left().unwrap());
             }
-            op @ _ => {
-                unimplemented!("{:?}", op);
+            Operator::MemoryCopy | Operator::MemoryFill => {
+                let func_value = match op {
+                    Operator::MemoryCopy => intrinsics.memory_copy,
+                    _ => intrinsics.memory_fill,
+                };
+                let (dst, src_or_value, len) = state.pop3()?;
+                let status = builder.build_call(
+                    func_value,
+                    &[
+                        ctx.basic(),
+                        intrinsics.i32_zero.as_basic_value_enum(),
+                        dst,
+                        src_or_value,
+                        len,
+                    ],
+                    &state.var_name(),
+                );
+                trap_if_failed(
+                    builder,
+                    intrinsics,
+                    context,
+                    &function,
+                    status.try_as_basic_value().left().unwrap(),
+                    intrinsics.trap_memory_oob,
+                );
+            }
+            Operator::MemoryInit { segment } | Operator::TableInit { segment } => {
+                let (func_value, trap_code) = match op {
+                    Operator::MemoryInit { .. } => {
+                        (intrinsics.memory_init, intrinsics.trap_memory_oob)
+                    }
+                    _ => (intrinsics.table_init, intrinsics.trap_call_indirect_oob),
+                };
+                let (dst, src, len) = state.pop3()?;
+                let segment = intrinsics
+                    .i32_ty
+                    .const_int(segment as u64, false)
+                    .as_basic_value_enum();
+                let status = builder.build_call(
+                    func_value,
+                    &[
+                        ctx.basic(),
+                        intrinsics.i32_zero.as_basic_value_enum(),
+                        segment,
+                        dst,
+                        src,
+                        len,
+                    ],
+                    &state.var_name(),
+                );
+                trap_if_failed(
+                    builder,
+                    intrinsics,
+                    context,
+                    &function,
+                    status.try_as_basic_value().left().unwrap(),
+                    trap_code,
+                );
             }
+            Operator::DataDrop { segment } | Operator::ElemDrop { segment } => {
+                let func_value = match op {
+                    Operator::DataDrop { .. } => intrinsics.data_drop,
+                    _ => intrinsics.elem_drop,
+                };
+                let segment = intrinsics
+                    .i32_ty
+                    .const_int(segment as u64, false)
+                    .as_basic_value_enum();
+                builder.build_call(func_value, &[ctx.basic(), segment], "");
+            }
+            Operator::RefNull => {
+                state.push1(intrinsics.i64_zero);
+            }
+            Operator::RefIsNull => {
+                let reference = state.pop1()?.into_int_value();
+                let is_null = builder.build_int_compare(
+                    IntPredicate::EQ,
+                    reference,
+                    intrinsics.i64_zero,
+                    &state.var_name(),
+                );
+                let res = builder.build_int_z_extend(is_null, intrinsics.i32_ty, &state.var_name());
+                state.push1(res);
+            }
+            Operator::TableGet { table } | Operator::TableSet { table } => {
+                let table_index = TableIndex::new(table as usize);
+                let value = match op {
+                    Operator::TableSet { .. } => Some(state.pop1()?),
+                    _ => None,
+                };
+                let index = state.pop1()?.into_int_value();
+
+                // The vmcalls expect the index to be in bounds.
+                let (_, table_bound) = ctx.table(table_index);
+                let truncated_table_bounds = builder.build_int_truncate(
+                    table_bound,
+                    intrinsics.i32_ty,
+                    "truncated_table_bounds",
+                );
+                let out_of_bounds = builder.build_int_compare(
+                    IntPredicate::UGE,
+                    index,
+                    truncated_table_bounds,
+                    "out_of_bounds",
+                );
+                let status = builder.build_int_z_extend(out_of_bounds, intrinsics.i32_ty, "status");
+                trap_if_failed(
+                    builder,
+                    intrinsics,
+                    context,
+                    &function,
+                    status.as_basic_value_enum(),
+                    intrinsics.trap_call_indirect_oob,
+                );
+
+                let table_index = intrinsics
+                    .i32_ty
+                    .const_int(table as u64, false)
+                    .as_basic_value_enum();
+                match value {
+                    Some(value) => {
+                        builder.build_call(
+                            intrinsics.table_set,
+                            &[ctx.basic(), table_index, index.as_basic_value_enum(), value],
+                            "",
+                        );
+                    }
+                    None => {
+                        let res = builder.build_call(
+                            intrinsics.table_get,
+                            &[ctx.basic(), table_index, index.as_basic_value_enum()],
+                            &state.var_name(),
+                        );
+                        state.push1(res.try_as_basic_value().left().unwrap());
+                    }
+                }
+            }
+            Operator::Wake { memarg } => {
+                let count = state.pop1()?;
+                let effective_address = resolve_atomic_ptr(
+                    builder, intrinsics, context, &function, &mut state, &mut ctx, memarg, 4,
+                )?;
+                let result = builder.build_call(
+                    intrinsics.atomic_notify,
+                    &[effective_address.as_basic_value_enum(), count],
+                    &state.var_name(),
+                );
+                state.push1(result.try_as_basic_value().left().unwrap());
+            }
+            Operator::I32Wait { memarg } | Operator::I64Wait { memarg } => {
+                let width = match op {
+                    Operator::I32Wait { .. } => 4,
+                    _ => 8,
+                };
+                let (expected, timeout) = state.pop2()?;
+                let expected = if width == 4 {
+                    builder.build_int_z_extend(
+                        expected.into_int_value(),
+                        intrinsics.i64_ty,
+                        &state.var_name(),
+                    )
+                } else {
+                    expected.into_int_value()
+                };
+
+                // Waiting is only allowed on shared memories.
+                if !memory_is_shared(info) {
+                    builder.build_call(
+                        intrinsics.throw_trap,
+                        &[intrinsics.trap_unreachable],
+                        "throw",
+                    );
+                }
+
+                let effective_address = resolve_atomic_ptr(
+                    builder, intrinsics, context, &function, &mut state, &mut ctx, memarg, width,
+                )?;
+                let result = builder.build_call(
+                    intrinsics.atomic_wait,
+                    &[
+                        intrinsics
+                            .i32_ty
+                            .const_int(width, false)
+                            .as_basic_value_enum(),
+                        effective_address.as_basic_value_enum(),
+                        expected.as_basic_value_enum(),
+                        timeout,
+                    ],
+                    &state.var_name(),
+                );
+                state.push1(result.try_as_basic_value().left().unwrap());
+            }
+            op @ _ => match atomic_rmw_info(&op) {
+                Some((rmw_op, width, ty, memarg)) => {
+                    let widen = |value: BasicValueEnum, state: &State| match ty {
+                        WpType::I32 => builder.build_int_z_extend(
+                            value.into_int_value(),
+                            intrinsics.i64_ty,
+                            &state.var_name(),
+                        ),
+                        _ => value.into_int_value(),
+                    };
+                    let (operand, replacement) = match rmw_op {
+                        AtomicRmwOp::Load => (intrinsics.i64_zero, intrinsics.i64_zero),
+                        AtomicRmwOp::Cmpxchg => {
+                            let (expected, replacement) = state.pop2()?;
+                            (widen(expected, &state), widen(replacement, &state))
+                        }
+                        _ => {
+                            let value = state.pop1()?;
+                            (widen(value, &state), intrinsics.i64_zero)
+                        }
+                    };
+
+                    let effective_address = resolve_atomic_ptr(
+                        builder, intrinsics, context, &function, &mut state, &mut ctx, memarg,
+                        width,
+                    )?;
+                    let result = builder
+                        .build_call(
+                            intrinsics.atomic_rmw,
+                            &[
+                                intrinsics
+                                    .i32_ty
+                                    .const_int(rmw_op as u64, false)
+                                    .as_basic_value_enum(),
+                                intrinsics
+                                    .i32_ty
+                                    .const_int(width, false)
+                                    .as_basic_value_enum(),
+                                effective_address.as_basic_value_enum(),
+                                operand.as_basic_value_enum(),
+                                replacement.as_basic_value_enum(),
+                            ],
+                            &state.var_name(),
+                        )
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap()
+                        .into_int_value();
+
+                    match (rmw_op, ty) {
+                        (AtomicRmwOp::Store, _) => {}
+                        (_, WpType::I32) => {
+                            let result = builder.build_int_truncate(
+                                result,
+                                intrinsics.i32_ty,
+                                &state.var_name(),
+                            );
+                            state.push1(result);
+                        }
+                        _ => state.push1(result),
+                    }
+                }
+                None => unimplemented!("{:?}", op),
+            },
+        }
+
+        if canonicalize_result {
+            let value = state.pop1()?.into_float_value();
+            let res = canonicalize_nan(builder, value, &state.var_name());
+            state.push1(res);
         }
     }
 
@@ -2159,15 +2687,126 @@ fn parse_function(
             builder.build_return(Some(one_value));
         }
         returns @ _ => {
-            // let struct_ty = llvm_sig.get_return_type().as_struct_type();
-            // let ret_struct = struct_ty.const_zero();
-            unimplemented!("multi-value returns not yet implemented")
+            // The results pointer follows the context and the parameters.
+            let results_ptr = function
+                .get_nth_param(func_sig.params().len() as u32 + 1)
+                .unwrap()
+                .into_pointer_value();
+            for (i, value) in returns.iter().enumerate() {
+                let field_ptr =
+                    unsafe { builder.build_struct_gep(results_ptr, i as u32, "result_ptr") };
+                builder.build_store(field_ptr, *value);
+            }
+            builder.build_return(None);
         }
     }
 
     Ok(())
 }
 
+/// Push the `count` results that a call wrote to `results_ptr`.
+fn load_results(builder: &Builder, state: &mut State, results_ptr: PointerValue, count: usize) {
+    for i in 0..(count as u32) {
+        let field_ptr = unsafe { builder.build_struct_gep(results_ptr, i, "result_ptr") };
+        let value = builder.build_load(field_ptr, &state.var_name());
+        state.push1(value);
+    }
+}
+
+/// Whether the result of `op` can be a NaN whose bit pattern
+/// depends on the platform.
+fn produces_nan(op: &Operator) -> bool {
+    match *op {
+        Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Sqrt
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F32DemoteF64
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Sqrt
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F64PromoteF32 => true,
+        _ => false,
+    }
+}
+
+/// Replace `value` with the canonical NaN if it's a NaN.
+fn canonicalize_nan(builder: &Builder, value: FloatValue, name: &str) -> BasicValueEnum {
+    let is_nan = builder.build_float_compare(FloatPredicate::UNO, value, value, "is_nan");
+    let canonical_nan = value.get_type().const_float(std::f64::NAN);
+    builder.build_select(is_nan, canonical_nan, value, name)
+}
+
+/// Truncate `value` toward zero, saturating at the bounds of `int_ty` and
+/// turning NaN into zero.
+///
+/// The bounds of the integer types are powers of two, so they are exact as
+/// floats: `min` is the smallest value of `int_ty` and `max` is one past the
+/// largest.
+fn trunc_sat(
+    builder: &Builder,
+    int_ty: IntType,
+    signed: bool,
+    min: f64,
+    max: f64,
+    value: FloatValue,
+    name: &str,
+) -> IntValue {
+    let float_ty = value.get_type();
+    let bits = int_ty.get_bit_width();
+    let (int_min, int_max) = if signed {
+        (
+            int_ty.const_int(1 << (bits - 1), false),
+            int_ty.const_int(!0 >> (65 - bits), false),
+        )
+    } else {
+        (int_ty.const_zero(), int_ty.const_int(!0, false))
+    };
+
+    // The conversion is only defined for values in range, but the selects
+    // below discard it for every other value.
+    let converted = if signed {
+        builder.build_float_to_signed_int(value, int_ty, "converted")
+    } else {
+        builder.build_float_to_unsigned_int(value, int_ty, "converted")
+    };
+
+    let is_nan = builder.build_float_compare(FloatPredicate::UNO, value, value, "is_nan");
+    let below_min = builder.build_float_compare(
+        FloatPredicate::OLT,
+        value,
+        float_ty.const_float(min),
+        "below_min",
+    );
+    let above_max = builder.build_float_compare(
+        FloatPredicate::OGE,
+        value,
+        float_ty.const_float(max),
+        "above_max",
+    );
+
+    let res = builder.build_select(below_min, int_min, converted, "saturated_min");
+    let res = builder.build_select(above_max, int_max, res.into_int_value(), "saturated_max");
+    builder
+        .build_select(is_nan, int_ty.const_zero(), res.into_int_value(), name)
+        .into_int_value()
+}
+
 fn trap_if_not_representatable_as_int(
     builder: &Builder,
     intrinsics: &Intrinsics,
@@ -2414,9 +3053,25 @@ fn resolve_memory_ptr(
 
             let base_as_int = builder.build_ptr_to_int(base, intrinsics.i64_ty, "base_as_int");
 
-            let base_in_bounds = builder.build_int_compare(
-                IntPredicate::ULT,
+            // The whole access is checked, so that no access relies on
+            // the guard page after the memory to fault.
+            let value_size = if ptr_ty == intrinsics.i8_ptr_ty {
+                1
+            } else if ptr_ty == intrinsics.i16_ptr_ty {
+                2
+            } else if ptr_ty == intrinsics.i32_ptr_ty || ptr_ty == intrinsics.f32_ptr_ty {
+                4
+            } else {
+                8
+            };
+            let effective_end = builder.build_int_add(
                 effective_offset,
+                intrinsics.i64_ty.const_int(value_size, false),
+                "effective_end",
+            );
+            let base_in_bounds = builder.build_int_compare(
+                IntPredicate::ULE,
+                effective_end,
                 bounds,
                 "base_in_bounds",
             );
@@ -2464,3 +3119,180 @@ fn resolve_memory_ptr(
         builder.build_int_add(mem_base_int, effective_offset, &state.var_name());
     Ok(builder.build_int_to_ptr(effective_address_int, ptr_ty, &state.var_name()))
 }
+
+/// Resolves the address of an atomic access, trapping
+/// if it isn't aligned to the `width` of the access.
+fn resolve_atomic_ptr(
+    builder: &Builder,
+    intrinsics: &Intrinsics,
+    context: &Context,
+    function: &FunctionValue,
+    state: &mut State,
+    ctx: &mut CtxType,
+    memarg: MemoryImmediate,
+    width: u64,
+) -> Result<PointerValue, BinaryReaderError> {
+    let effective_address = resolve_memory_ptr(
+        builder,
+        intrinsics,
+        context,
+        function,
+        state,
+        ctx,
+        memarg,
+        intrinsics.i8_ptr_ty,
+    )?;
+    if width == 1 {
+        return Ok(effective_address);
+    }
+
+    let address_int = builder.build_ptr_to_int(effective_address, intrinsics.i64_ty, "address_int");
+    let misaligned_bits = builder.build_and(
+        address_int,
+        intrinsics.i64_ty.const_int(width - 1, false),
+        "misaligned_bits",
+    );
+    let aligned = builder.build_int_compare(
+        IntPredicate::EQ,
+        misaligned_bits,
+        intrinsics.i64_zero,
+        "aligned",
+    );
+
+    let aligned = builder
+        .build_call(
+            intrinsics.expect_i1,
+            &[
+                aligned.as_basic_value_enum(),
+                intrinsics.i1_ty.const_int(1, false).as_basic_value_enum(),
+            ],
+            "aligned_expect",
+        )
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+        .into_int_value();
+
+    let aligned_block = context.append_basic_block(function, "aligned_block");
+    let misaligned_block = context.append_basic_block(function, "misaligned_block");
+    builder.build_conditional_branch(aligned, &aligned_block, &misaligned_block);
+    builder.position_at_end(&misaligned_block);
+    builder.build_call(
+        intrinsics.throw_trap,
+        &[intrinsics.trap_memory_oob],
+        "throw",
+    );
+    builder.build_unreachable();
+    builder.position_at_end(&aligned_block);
+
+    Ok(effective_address)
+}
+
+/// Traps with `trap_code` if one of the bulk memory
+/// intrinsics returned a nonzero `status`.
+fn trap_if_failed(
+    builder: &Builder,
+    intrinsics: &Intrinsics,
+    context: &Context,
+    function: &FunctionValue,
+    status: BasicValueEnum,
+    trap_code: BasicValueEnum,
+) {
+    let failed = builder.build_int_compare(
+        IntPredicate::NE,
+        status.into_int_value(),
+        intrinsics.i32_zero,
+        "failed",
+    );
+
+    let failed_block = context.append_basic_block(function, "failed_block");
+    let continue_block = context.append_basic_block(function, "continue_block");
+    builder.build_conditional_branch(failed, &failed_block, &continue_block);
+    builder.position_at_end(&failed_block);
+    builder.build_call(intrinsics.throw_trap, &[trap_code], "throw");
+    builder.build_unreachable();
+    builder.position_at_end(&continue_block);
+}
+
+fn memory_is_shared(info: &ModuleInfo) -> bool {
+    match MemoryIndex::new(0).local_or_import(info) {
+        LocalOrImport::Local(local_mem_index) => info.memories[local_mem_index].shared,
+        LocalOrImport::Import(import_mem_index) => {
+            info.imported_memories[import_mem_index].1.shared
+        }
+    }
+}
+
+/// The operation, width in bytes and value type
+/// of an atomic load, store or read-modify-write.
+fn atomic_rmw_info(op: &Operator) -> Option<(AtomicRmwOp, u64, WpType, MemoryImmediate)> {
+    use self::AtomicRmwOp::*;
+    use self::WpType::{I32, I64};
+
+    Some(match *op {
+        Operator::I32AtomicLoad { memarg } => (Load, 4, I32, memarg),
+        Operator::I64AtomicLoad { memarg } => (Load, 8, I64, memarg),
+        Operator::I32AtomicLoad8U { memarg } => (Load, 1, I32, memarg),
+        Operator::I32AtomicLoad16U { memarg } => (Load, 2, I32, memarg),
+        Operator::I64AtomicLoad8U { memarg } => (Load, 1, I64, memarg),
+        Operator::I64AtomicLoad16U { memarg } => (Load, 2, I64, memarg),
+        Operator::I64AtomicLoad32U { memarg } => (Load, 4, I64, memarg),
+        Operator::I32AtomicStore { memarg } => (Store, 4, I32, memarg),
+        Operator::I64AtomicStore { memarg } => (Store, 8, I64, memarg),
+        Operator::I32AtomicStore8 { memarg } => (Store, 1, I32, memarg),
+        Operator::I32AtomicStore16 { memarg } => (Store, 2, I32, memarg),
+        Operator::I64AtomicStore8 { memarg } => (Store, 1, I64, memarg),
+        Operator::I64AtomicStore16 { memarg } => (Store, 2, I64, memarg),
+        Operator::I64AtomicStore32 { memarg } => (Store, 4, I64, memarg),
+        Operator::I32AtomicRmwAdd { memarg } => (Add, 4, I32, memarg),
+        Operator::I64AtomicRmwAdd { memarg } => (Add, 8, I64, memarg),
+        Operator::I32AtomicRmw8UAdd { memarg } => (Add, 1, I32, memarg),
+        Operator::I32AtomicRmw16UAdd { memarg } => (Add, 2, I32, memarg),
+        Operator::I64AtomicRmw8UAdd { memarg } => (Add, 1, I64, memarg),
+        Operator::I64AtomicRmw16UAdd { memarg } => (Add, 2, I64, memarg),
+        Operator::I64AtomicRmw32UAdd { memarg } => (Add, 4, I64, memarg),
+        Operator::I32AtomicRmwSub { memarg } => (Sub, 4, I32, memarg),
+        Operator::I64AtomicRmwSub { memarg } => (Sub, 8, I64, memarg),
+        Operator::I32AtomicRmw8USub { memarg } => (Sub, 1, I32, memarg),
+        Operator::I32AtomicRmw16USub { memarg } => (Sub, 2, I32, memarg),
+        Operator::I64AtomicRmw8USub { memarg } => (Sub, 1, I64, memarg),
+        Operator::I64AtomicRmw16USub { memarg } => (Sub, 2, I64, memarg),
+        Operator::I64AtomicRmw32USub { memarg } => (Sub, 4, I64, memarg),
+        Operator::I32AtomicRmwAnd { memarg } => (And, 4, I32, memarg),
+        Operator::I64AtomicRmwAnd { memarg } => (And, 8, I64, memarg),
+        Operator::I32AtomicRmw8UAnd { memarg } => (And, 1, I32, memarg),
+        Operator::I32AtomicRmw16UAnd { memarg } => (And, 2, I32, memarg),
+        Operator::I64AtomicRmw8UAnd { memarg } => (And, 1, I64, memarg),
+        Operator::I64AtomicRmw16UAnd { memarg } => (And, 2, I64, memarg),
+        Operator::I64AtomicRmw32UAnd { memarg } => (And, 4, I64, memarg),
+        Operator::I32AtomicRmwOr { memarg } => (Or, 4, I32, memarg),
+        Operator::I64AtomicRmwOr { memarg } => (Or, 8, I64, memarg),
+        Operator::I32AtomicRmw8UOr { memarg } => (Or, 1, I32, memarg),
+        Operator::I32AtomicRmw16UOr { memarg } => (Or, 2, I32, memarg),
+        Operator::I64AtomicRmw8UOr { memarg } => (Or, 1, I64, memarg),
+        Operator::I64AtomicRmw16UOr { memarg } => (Or, 2, I64, memarg),
+        Operator::I64AtomicRmw32UOr { memarg } => (Or, 4, I64, memarg),
+        Operator::I32AtomicRmwXor { memarg } => (Xor, 4, I32, memarg),
+        Operator::I64AtomicRmwXor { memarg } => (Xor, 8, I64, memarg),
+        Operator::I32AtomicRmw8UXor { memarg } => (Xor, 1, I32, memarg),
+        Operator::I32AtomicRmw16UXor { memarg } => (Xor, 2, I32, memarg),
+        Operator::I64AtomicRmw8UXor { memarg } => (Xor, 1, I64, memarg),
+        Operator::I64AtomicRmw16UXor { memarg } => (Xor, 2, I64, memarg),
+        Operator::I64AtomicRmw32UXor { memarg } => (Xor, 4, I64, memarg),
+        Operator::I32AtomicRmwXchg { memarg } => (Xchg, 4, I32, memarg),
+        Operator::I64AtomicRmwXchg { memarg } => (Xchg, 8, I64, memarg),
+        Operator::I32AtomicRmw8UXchg { memarg } => (Xchg, 1, I32, memarg),
+        Operator::I32AtomicRmw16UXchg { memarg } => (Xchg, 2, I32, memarg),
+        Operator::I64AtomicRmw8UXchg { memarg } => (Xchg, 1, I64, memarg),
+        Operator::I64AtomicRmw16UXchg { memarg } => (Xchg, 2, I64, memarg),
+        Operator::I64AtomicRmw32UXchg { memarg } => (Xchg, 4, I64, memarg),
+        Operator::I32AtomicRmwCmpxchg { memarg } => (Cmpxchg, 4, I32, memarg),
+        Operator::I64AtomicRmwCmpxchg { memarg } => (Cmpxchg, 8, I64, memarg),
+        Operator::I32AtomicRmw8UCmpxchg { memarg } => (Cmpxchg, 1, I32, memarg),
+        Operator::I32AtomicRmw16UCmpxchg { memarg } => (Cmpxchg, 2, I32, memarg),
+        Operator::I64AtomicRmw8UCmpxchg { memarg } => (Cmpxchg, 1, I64, memarg),
+        Operator::I64AtomicRmw16UCmpxchg { memarg } => (Cmpxchg, 2, I64, memarg),
+        Operator::I64AtomicRmw32UCmpxchg { memarg } => (Cmpxchg, 4, I64, memarg),
+        _ => return None,
+    })
+}
@@ -0,0 +1,230 @@
+use inkwell::{
+    passes::{PassManager, PassManagerBuilder},
+    targets::TargetMachine,
+    OptimizationLevel,
+};
+use std::{fmt, fs, path::PathBuf, sync::Arc};
+use wasmer_runtime_core::{cache, deterministic};
+
+use crate::profile::{Profile, Profiler};
+
+/// How much effort LLVM puts into optimizing, which trades compile
+/// time for the speed or size of the code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations, for the fastest compiles.
+    O0,
+    O1,
+    O2,
+    /// Every optimization. The default.
+    O3,
+    /// Optimize for code size over speed.
+    Os,
+}
+
+/// The options of an [`LLVMCompiler`], so each compiler can make its
+/// own trade-off between compile time and the quality of the code.
+///
+/// [`LLVMCompiler`]: struct.LLVMCompiler.html
+#[derive(Debug, Clone)]
+pub struct LLVMConfig {
+    pub opt_level: OptLevel,
+    /// Calls to functions that cost less than this are inlined. `None`
+    /// uses the default of `opt_level`, and `Some(0)` turns inlining off.
+    pub inline_threshold: Option<u32>,
+    /// Skip global value numbering and load and store motion, which
+    /// take the most time of the passes that run from `O2` up.
+    pub skip_costly_passes: bool,
+    /// Where to write the code that's generated for each function, to
+    /// diagnose miscompilations and slow code. Nothing's written if
+    /// it's `None`, the default.
+    pub dump: Option<Dump>,
+    /// The machine to compile for, if it isn't the host. Code compiled
+    /// for a machine that the host isn't can't be loaded here, so such
+    /// modules can only be compiled to artifacts with
+    /// `compile_to_artifact`. [`TargetConfig::baseline`] runs on any
+    /// machine of the host's architecture, the host included.
+    ///
+    /// [`TargetConfig::baseline`]: struct.TargetConfig.html#method.baseline
+    pub target: Option<TargetConfig>,
+    /// Make the code count its calls and branches, for this profiler
+    /// to read. Code that counts can't be cached.
+    pub profiler: Option<Profiler>,
+    /// A profile of an earlier run of the module, which tells LLVM how
+    /// likely each branch is to be taken.
+    pub profile: Option<Arc<Profile>>,
+    /// Let LLVM fuse multiplies with adds, reassociate float operations
+    /// and ignore the sign of zero, which makes float code faster but
+    /// its results differ from those of IEEE 754, and from machine to
+    /// machine. Off by default, which keeps each operation as strict as
+    /// WebAssembly specifies it. It can't be used in deterministic mode.
+    pub fast_math: bool,
+}
+
+/// A machine other than the host to compile for.
+#[derive(Debug, Clone)]
+pub struct TargetConfig {
+    /// The target triple, such as `aarch64-unknown-linux-gnu`.
+    pub triple: String,
+    /// The cpu to use the instructions of, such as `cortex-a72`, or
+    /// `generic`.
+    pub cpu: String,
+    /// Cpu features to enable on top of those of `cpu`, as LLVM names
+    /// them, such as `+avx2,+bmi`.
+    pub features: String,
+}
+
+impl TargetConfig {
+    /// The host's architecture and operating system, without the cpu
+    /// features that only some machines of them have, for artifacts
+    /// that are shipped to a fleet of different machines. The code may
+    /// run slower than code compiled for the host.
+    pub fn baseline() -> Self {
+        let triple = TargetMachine::get_default_triple().to_string();
+        let cpu = if triple.starts_with("x86_64") {
+            "x86-64"
+        } else {
+            "generic"
+        };
+        Self {
+            triple,
+            cpu: cpu.to_string(),
+            features: String::new(),
+        }
+    }
+
+    /// The target that artifacts compiled for this machine record.
+    pub(crate) fn cache_target(&self) -> cache::Target {
+        let features: Vec<&str> = self
+            .features
+            .split(',')
+            .filter(|feature| feature.starts_with('+'))
+            .map(|feature| match &feature[1..] {
+                // The only feature that LLVM names differently.
+                "bmi" => "bmi1",
+                feature => feature,
+            })
+            .collect();
+        cache::Target::from_triple(&self.triple, &features)
+    }
+}
+
+/// Receives the code generated for each function, under the name of
+/// its symbol and an extension: `fn3.ll` has the LLVM IR of function 3
+/// before it's optimized, and `fn3.opt.ll` after. With the `disasm`
+/// feature, `fn3.s` has the disassembly of its machine code. The whole
+/// module is also given as an object file, `module.o`.
+#[derive(Clone)]
+pub enum Dump {
+    /// Write each file to this directory, which is created if need be.
+    Directory(PathBuf),
+    /// Call this with the name and contents of each file.
+    Callback(Arc<dyn Fn(&str, &[u8]) + Send + Sync>),
+}
+
+impl Dump {
+    pub(crate) fn write(&self, name: &str, contents: &[u8]) {
+        match self {
+            Dump::Directory(directory) => {
+                // Failing to dump doesn't fail the compile.
+                let _ = fs::create_dir_all(directory)
+                    .and_then(|()| fs::write(directory.join(name), contents));
+            }
+            Dump::Callback(callback) => callback(name, contents),
+        }
+    }
+}
+
+impl fmt::Debug for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Dump::Directory(directory) => f.debug_tuple("Directory").field(directory).finish(),
+            Dump::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl Default for LLVMConfig {
+    fn default() -> Self {
+        Self {
+            opt_level: OptLevel::O3,
+            inline_threshold: None,
+            skip_costly_passes: false,
+            dump: None,
+            target: None,
+            profiler: None,
+            profile: None,
+            fast_math: false,
+        }
+    }
+}
+
+impl LLVMConfig {
+    /// The target that the code is compiled for.
+    pub(crate) fn cache_target(&self) -> cache::Target {
+        match &self.target {
+            Some(target) => target.cache_target(),
+            None => cache::Target::host(),
+        }
+    }
+
+    /// Check that the options can be used together.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.fast_math && deterministic::is_deterministic() {
+            return Err("fast math cannot be used in deterministic mode".to_string());
+        }
+        Ok(())
+    }
+
+    /// The optimization level of the code generator.
+    pub(crate) fn codegen_opt_level(&self) -> OptimizationLevel {
+        match self.opt_level {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+
+    fn inline_threshold(&self) -> u32 {
+        // LLVM's own defaults for each level.
+        self.inline_threshold.unwrap_or(match self.opt_level {
+            OptLevel::O0 | OptLevel::O1 => 0,
+            OptLevel::O2 => 225,
+            OptLevel::O3 => 250,
+            OptLevel::Os => 75,
+        })
+    }
+
+    /// The passes that optimize a module after it's been translated.
+    pub(crate) fn pass_manager(&self) -> PassManager {
+        let pass_manager = PassManager::create_for_module();
+        if self.opt_level == OptLevel::O0 {
+            return pass_manager;
+        }
+
+        let threshold = self.inline_threshold();
+        if threshold > 0 {
+            // At level 0 the builder only adds the inliner.
+            let builder = PassManagerBuilder::create();
+            builder.set_inliner_with_threshold(threshold);
+            builder.populate_module_pass_manager(&pass_manager);
+        }
+
+        pass_manager.add_promote_memory_to_register_pass();
+        pass_manager.add_cfg_simplification_pass();
+        if self.opt_level == OptLevel::O1 {
+            pass_manager.add_instruction_combining_pass();
+            pass_manager.add_aggressive_dce_pass();
+            return pass_manager;
+        }
+
+        pass_manager.add_aggressive_inst_combiner_pass();
+        if !self.skip_costly_passes {
+            pass_manager.add_merged_load_store_motion_pass();
+            pass_manager.add_new_gvn_pass();
+        }
+        pass_manager.add_aggressive_dce_pass();
+        pass_manager
+    }
+}
@@ -27,6 +27,7 @@ fn type_to_llvm_ptr(intrinsics: &Intrinsics, ty: Type) -> PointerType {
         Type::I64 => intrinsics.i64_ptr_ty,
         Type::F32 => intrinsics.f32_ptr_ty,
         Type::F64 => intrinsics.f64_ptr_ty,
+        Type::ExternRef | Type::FuncRef => intrinsics.i64_ptr_ty,
     }
 }
 
@@ -99,6 +100,8 @@ pub struct Intrinsics {
     pub trap_call_indirect_oob: BasicValueEnum,
     pub trap_memory_oob: BasicValueEnum,
     pub trap_illegal_arithmetic: BasicValueEnum,
+    pub trap_interrupted: BasicValueEnum,
+    pub trap_deadline_exceeded: BasicValueEnum,
 
     // VM intrinsics.
     pub memory_grow_dynamic_local: FunctionValue,
@@ -115,6 +118,20 @@ pub struct Intrinsics {
     pub memory_size_static_import: FunctionValue,
     pub memory_size_shared_import: FunctionValue,
 
+    pub atomic_rmw: FunctionValue,
+    pub atomic_wait: FunctionValue,
+    pub atomic_notify: FunctionValue,
+
+    pub memory_copy: FunctionValue,
+    pub memory_fill: FunctionValue,
+    pub memory_init: FunctionValue,
+    pub data_drop: FunctionValue,
+    pub table_init: FunctionValue,
+    pub elem_drop: FunctionValue,
+
+    pub table_get: FunctionValue,
+    pub table_set: FunctionValue,
+
     pub throw_trap: FunctionValue,
 
     ctx_ty: StructType,
@@ -205,6 +222,10 @@ impl Intrinsics {
                 sigindex_ty
                     .ptr_type(AddressSpace::Generic)
                     .as_basic_type_enum(),
+                i8_ptr_ty_basic,
+                i64_ptr_ty.as_basic_type_enum(),
+                i64_ty_basic,
+                i64_ty_basic,
                 local_function_ty
                     .ptr_type(AddressSpace::Generic)
                     .as_basic_type_enum(),
@@ -233,6 +254,59 @@ impl Intrinsics {
 
         let ret_i1_take_i1_i1 = i1_ty.fn_type(&[i1_ty_basic, i1_ty_basic], false);
 
+        let ret_i64_take_i32_i32_i8ptr_i64_i64 = i64_ty.fn_type(
+            &[
+                i32_ty_basic,
+                i32_ty_basic,
+                i8_ptr_ty_basic,
+                i64_ty_basic,
+                i64_ty_basic,
+            ],
+            false,
+        );
+        let ret_i32_take_i32_i8ptr_i64_i64 = i32_ty.fn_type(
+            &[i32_ty_basic, i8_ptr_ty_basic, i64_ty_basic, i64_ty_basic],
+            false,
+        );
+        let ret_i32_take_i8ptr_i32 = i32_ty.fn_type(&[i8_ptr_ty_basic, i32_ty_basic], false);
+
+        let ret_i32_take_ctx_i32_i32_i32_i32 = i32_ty.fn_type(
+            &[
+                ctx_ptr_ty.as_basic_type_enum(),
+                i32_ty_basic,
+                i32_ty_basic,
+                i32_ty_basic,
+                i32_ty_basic,
+            ],
+            false,
+        );
+        let ret_i32_take_ctx_i32_i32_i32_i32_i32 = i32_ty.fn_type(
+            &[
+                ctx_ptr_ty.as_basic_type_enum(),
+                i32_ty_basic,
+                i32_ty_basic,
+                i32_ty_basic,
+                i32_ty_basic,
+                i32_ty_basic,
+            ],
+            false,
+        );
+        let ret_void_take_ctx_i32 =
+            void_ty.fn_type(&[ctx_ptr_ty.as_basic_type_enum(), i32_ty_basic], false);
+        let ret_i64_take_ctx_i32_i32 = i64_ty.fn_type(
+            &[ctx_ptr_ty.as_basic_type_enum(), i32_ty_basic, i32_ty_basic],
+            false,
+        );
+        let ret_void_take_ctx_i32_i32_i64 = void_ty.fn_type(
+            &[
+                ctx_ptr_ty.as_basic_type_enum(),
+                i32_ty_basic,
+                i32_ty_basic,
+                i64_ty_basic,
+            ],
+            false,
+        );
+
         Self {
             ctlz_i32: module.add_function("llvm.ctlz.i32", ret_i32_take_i32_i1, None),
             ctlz_i64: module.add_function("llvm.ctlz.i64", ret_i64_take_i64_i1, None),
@@ -302,6 +376,8 @@ impl Intrinsics {
             trap_call_indirect_oob: i32_ty.const_int(3, false).as_basic_value_enum(),
             trap_memory_oob: i32_ty.const_int(2, false).as_basic_value_enum(),
             trap_illegal_arithmetic: i32_ty.const_int(4, false).as_basic_value_enum(),
+            trap_interrupted: i32_ty.const_int(5, false).as_basic_value_enum(),
+            trap_deadline_exceeded: i32_ty.const_int(6, false).as_basic_value_enum(),
 
             // VM intrinsics.
             memory_grow_dynamic_local: module.add_function(
@@ -365,6 +441,44 @@ impl Intrinsics {
                 ret_i32_take_ctx_i32,
                 None,
             ),
+            atomic_rmw: module.add_function(
+                "vm.atomic.rmw",
+                ret_i64_take_i32_i32_i8ptr_i64_i64,
+                None,
+            ),
+            atomic_wait: module.add_function(
+                "vm.atomic.wait",
+                ret_i32_take_i32_i8ptr_i64_i64,
+                None,
+            ),
+            atomic_notify: module.add_function("vm.atomic.notify", ret_i32_take_i8ptr_i32, None),
+
+            memory_copy: module.add_function(
+                "vm.memory.copy",
+                ret_i32_take_ctx_i32_i32_i32_i32,
+                None,
+            ),
+            memory_fill: module.add_function(
+                "vm.memory.fill",
+                ret_i32_take_ctx_i32_i32_i32_i32,
+                None,
+            ),
+            memory_init: module.add_function(
+                "vm.memory.init",
+                ret_i32_take_ctx_i32_i32_i32_i32_i32,
+                None,
+            ),
+            data_drop: module.add_function("vm.data.drop", ret_void_take_ctx_i32, None),
+            table_init: module.add_function(
+                "vm.table.init",
+                ret_i32_take_ctx_i32_i32_i32_i32_i32,
+                None,
+            ),
+            elem_drop: module.add_function("vm.elem.drop", ret_void_take_ctx_i32, None),
+
+            table_get: module.add_function("vm.table.get", ret_i64_take_ctx_i32_i32, None),
+            table_set: module.add_function("vm.table.set", ret_void_take_ctx_i32_i32_i64, None),
+
             throw_trap: module.add_function(
                 "vm.exception.trap",
                 void_ty.fn_type(&[i32_ty_basic], false),
@@ -587,10 +701,44 @@ impl<'a> CtxType<'a> {
         )
     }
 
+    pub fn interrupted_ptr(&self) -> PointerValue {
+        let interrupted_ptr_ptr = unsafe {
+            self.builder
+                .build_struct_gep(self.ctx_ptr_value, 8, "interrupted_ptr_ptr")
+        };
+        self.builder
+            .build_load(interrupted_ptr_ptr, "interrupted_ptr")
+            .into_pointer_value()
+    }
+
+    pub fn epoch_ptr(&self) -> PointerValue {
+        let epoch_ptr_ptr = unsafe {
+            self.builder
+                .build_struct_gep(self.ctx_ptr_value, 9, "epoch_ptr_ptr")
+        };
+        self.builder
+            .build_load(epoch_ptr_ptr, "epoch_ptr")
+            .into_pointer_value()
+    }
+
+    pub fn epoch_deadline_ptr(&self) -> PointerValue {
+        unsafe {
+            self.builder
+                .build_struct_gep(self.ctx_ptr_value, 10, "epoch_deadline_ptr")
+        }
+    }
+
+    pub fn remaining_points_ptr(&self) -> PointerValue {
+        unsafe {
+            self.builder
+                .build_struct_gep(self.ctx_ptr_value, 11, "remaining_points_ptr")
+        }
+    }
+
     pub fn local_func(&mut self, index: LocalFuncIndex, fn_ty: FunctionType) -> PointerValue {
         let local_func_array_ptr_ptr = unsafe {
             self.builder
-                .build_struct_gep(self.ctx_ptr_value, 8, "local_func_array_ptr_ptr")
+                .build_struct_gep(self.ctx_ptr_value, 12, "local_func_array_ptr_ptr")
         };
         let local_func_array_ptr = self
             .builder
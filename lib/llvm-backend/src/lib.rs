@@ -1,67 +1,132 @@
-#![cfg_attr(nightly, feature(unwind_attributes))]
-
 use inkwell::{
     execution_engine::JitFunction,
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
     OptimizationLevel,
 };
 use wasmer_runtime_core::{
-    backend::{Compiler, Token},
+    backend::{sys::Memory, Capabilities, Compiler, CompilerConfig, Token},
     cache::{Artifact, Error as CacheError},
     error::CompileError,
+    metering,
     module::ModuleInner,
 };
 use wasmparser::{self, WasmDecoder};
 
 mod backend;
+mod cache;
 mod code;
+mod config;
 mod intrinsics;
 mod platform;
+mod profile;
 mod read_info;
 mod state;
 mod trampolines;
 
+pub use crate::config::{Dump, LLVMConfig, OptLevel, TargetConfig};
+pub use crate::profile::{BranchProfile, FunctionProfile, Profile, Profiler};
+
 pub struct LLVMCompiler {
-    _private: (),
+    config: LLVMConfig,
 }
 
 impl LLVMCompiler {
     pub fn new() -> Self {
-        Self { _private: () }
+        Self::with_config(LLVMConfig::default())
+    }
+
+    /// A compiler that compiles with `config`.
+    pub fn with_config(config: LLVMConfig) -> Self {
+        Self { config }
+    }
+
+    /// Compile `wasm` to an artifact, which records the target in
+    /// `config` that it was compiled for, or the host. Artifacts for
+    /// other targets can be saved and loaded from a cache on machines
+    /// of that target.
+    pub fn compile_to_artifact(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+    ) -> Result<Artifact, CompileError> {
+        if self.config.profiler.is_some() {
+            return Err(CompileError::InternalError {
+                msg: "code that counts for a profiler cannot be saved".to_string(),
+            });
+        }
+        self.config
+            .validate()
+            .map_err(|msg| CompileError::InternalError { msg })?;
+        let wasm = wasmer_runtime_core::prepare_wasm(wasm)?;
+        validate(&wasm)?;
+
+        let costs = compiler_config
+            .metering
+            .map(|config| metering::function_costs(&wasm, config.cost_function))
+            .transpose()?;
+
+        let (mut info, code_reader) = read_info::read_module(&wasm).unwrap();
+        info.initial_points = compiler_config
+            .metering
+            .map(|metering| metering.initial_points);
+        let (module, _, _) =
+            code::parse_function_bodies(&info, code_reader, costs.as_ref(), &self.config).unwrap();
+
+        let object = backend::compile_object(&module, &self.config)
+            .map_err(|msg| CompileError::InternalError { msg })?;
+        let target = self.config.cache_target();
+        let compiled_code =
+            Memory::with_size(0).map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+
+        Ok(Artifact::from_parts(
+            Box::new(info),
+            object.as_slice().into(),
+            compiled_code,
+            target,
+            env!("CARGO_PKG_VERSION"),
+        ))
     }
 }
 
 impl Compiler for LLVMCompiler {
-    fn compile(&self, wasm: &[u8], _: Token) -> Result<ModuleInner, CompileError> {
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> Result<ModuleInner, CompileError> {
+        let target = self.config.cache_target();
+        if !target.runs_on_host() {
+            return Err(CompileError::InternalError {
+                msg: format!(
+                    "code compiled for {} cannot run here, use `compile_to_artifact`",
+                    target
+                ),
+            });
+        }
+        self.config
+            .validate()
+            .map_err(|msg| CompileError::InternalError { msg })?;
         validate(wasm)?;
 
+        let costs = compiler_config
+            .metering
+            .map(|config| metering::function_costs(wasm, config.cost_function))
+            .transpose()?;
+
         let (info, code_reader) = read_info::read_module(wasm).unwrap();
-        let (module, intrinsics) = code::parse_function_bodies(&info, code_reader).unwrap();
-
-        let (backend, protected_caller) = backend::LLVMBackend::new(module, intrinsics);
-
-        // Create placeholder values here.
-        let cache_gen = {
-            use wasmer_runtime_core::backend::{
-                sys::Memory, CacheGen, ProtectedCaller, UserTrapper,
-            };
-            use wasmer_runtime_core::cache::Error as CacheError;
-            use wasmer_runtime_core::error::RuntimeResult;
-            use wasmer_runtime_core::module::ModuleInfo;
-            use wasmer_runtime_core::types::{FuncIndex, Value};
-            use wasmer_runtime_core::vm;
-            struct Placeholder;
-            impl CacheGen for Placeholder {
-                fn generate_cache(
-                    &self,
-                    module: &ModuleInner,
-                ) -> Result<(Box<ModuleInfo>, Box<[u8]>, Memory), CacheError> {
-                    unimplemented!()
-                }
-            }
-
-            Box::new(Placeholder)
-        };
+        let (module, intrinsics, counters) =
+            code::parse_function_bodies(&info, code_reader, costs.as_ref(), &self.config).unwrap();
+        let instrumented = counters.is_some();
+
+        let (backend, protected_caller) =
+            backend::LLVMBackend::new(module, intrinsics, counters, &self.config);
+
+        let cache_gen = Box::new(if instrumented {
+            cache::CacheGenerator::instrumented()
+        } else {
+            cache::CacheGenerator::new(backend.object(), target)
+        });
 
         Ok(ModuleInner {
             func_resolver: Box::new(backend),
@@ -72,8 +137,35 @@ impl Compiler for LLVMCompiler {
         })
     }
 
-    unsafe fn from_cache(&self, _artifact: Artifact, _: Token) -> Result<ModuleInner, CacheError> {
-        unimplemented!("the llvm backend doesn't support caching yet")
+    unsafe fn from_cache(&self, artifact: Artifact, _: Token) -> Result<ModuleInner, CacheError> {
+        let target = artifact.target().clone();
+        let (info, object, _) = artifact.consume();
+
+        let (backend, protected_caller) =
+            backend::LLVMBackend::from_object(object.into()).map_err(CacheError::Unknown)?;
+        let cache_gen = Box::new(cache::CacheGenerator::new(backend.object(), target));
+
+        Ok(ModuleInner {
+            func_resolver: Box::new(backend),
+            protected_caller: Box::new(protected_caller),
+            cache_gen,
+
+            info,
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            target_archs: &["x86_64"],
+            threads: cfg!(feature = "threads"),
+            reference_types: cfg!(feature = "reference-types"),
+            bulk_memory: true,
+            multi_value: true,
+            backtraces: false,
+            jit_debug: true,
+            caching: true,
+            version: env!("CARGO_PKG_VERSION"),
+        }
     }
 }
 
@@ -82,10 +174,10 @@ fn validate(bytes: &[u8]) -> Result<(), CompileError> {
         bytes,
         Some(wasmparser::ValidatingParserConfig {
             operator_config: wasmparser::OperatorValidatorConfig {
-                enable_threads: false,
-                enable_reference_types: false,
+                enable_threads: cfg!(feature = "threads"),
+                enable_reference_types: cfg!(feature = "reference-types"),
                 enable_simd: false,
-                enable_bulk_memory: false,
+                enable_bulk_memory: true,
             },
             mutable_global_imports: false,
         }),
@@ -123,9 +215,11 @@ fn test_read_module() {
 
     let (info, code_reader) = read_info::read_module(&wasm).unwrap();
 
-    let (module, intrinsics) = code::parse_function_bodies(&info, code_reader).unwrap();
+    let config = LLVMConfig::default();
+    let (module, intrinsics, counters) =
+        code::parse_function_bodies(&info, code_reader, None, &config).unwrap();
 
-    let (backend, _caller) = backend::LLVMBackend::new(module, intrinsics);
+    let (backend, _caller) = backend::LLVMBackend::new(module, intrinsics, counters, &config);
 
     let func_ptr = backend.get_func(&info, LocalFuncIndex::new(0)).unwrap();
 
@@ -1,8 +1,3 @@
-use libc::{c_void, siginfo_t};
-use nix::sys::signal::{
-    sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal, SIGBUS, SIGFPE, SIGILL, SIGSEGV,
-};
-
 /// `__register_frame` and `__deregister_frame` on macos take a single fde as an
 /// argument, so we need to parse the fde table here.
 ///
@@ -39,36 +34,3 @@ pub unsafe fn visit_fde(addr: *mut u8, size: usize, visitor: extern "C" fn(*mut
 pub unsafe fn visit_fde(addr: *mut u8, size: usize, visitor: extern "C" fn(*mut u8)) {
     visitor(addr);
 }
-
-extern "C" {
-    #[cfg_attr(nightly, unwind(allowed))]
-    fn throw_trap(ty: i32) -> !;
-}
-
-pub unsafe fn install_signal_handler() {
-    let sa = SigAction::new(
-        SigHandler::SigAction(signal_trap_handler),
-        SaFlags::SA_ONSTACK | SaFlags::SA_SIGINFO,
-        SigSet::empty(),
-    );
-    // sigaction(SIGFPE, &sa).unwrap();
-    // sigaction(SIGILL, &sa).unwrap();
-    sigaction(SIGSEGV, &sa).unwrap();
-    sigaction(SIGBUS, &sa).unwrap();
-}
-
-#[cfg_attr(nightly, unwind(allowed))]
-extern "C" fn signal_trap_handler(
-    signum: ::nix::libc::c_int,
-    siginfo: *mut siginfo_t,
-    ucontext: *mut c_void,
-) {
-    unsafe {
-        /// Apparently, we can unwind from arbitary instructions, as long
-        /// as we don't need to catch the exception inside the function that
-        /// was interrupted.
-        ///
-        /// This works on macos, not sure about linux.
-        throw_trap(2);
-    }
-}
@@ -0,0 +1,212 @@
+//! Profile-guided optimization.
+//!
+//! A module is compiled once with a [`Profiler`] in its config, which
+//! makes its code count how often each function is called and which
+//! way each of its branches goes. Once it has run a representative
+//! workload, the [`Profile`] that the profiler reads out of the counts
+//! is put in the config of the compiler that compiles the module again,
+//! which tells LLVM which way each branch is likely to go, so it lays
+//! the code out and inlines for the paths that are actually taken.
+//!
+//! Branches are the `br_if` and `if` operators of each function, in the
+//! order that they appear in, so a profile only applies to the module
+//! that it was collected from.
+//!
+//! [`Profiler`]: struct.Profiler.html
+//! [`Profile`]: struct.Profile.html
+
+use inkwell::{
+    builder::Builder,
+    context::Context,
+    values::{BasicValueEnum, InstructionValue, IntValue},
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use wasmer_runtime_core::{structures::TypedIndex, types::LocalFuncIndex};
+use wasmparser::{BinaryReaderError, FunctionBody, Operator};
+
+use crate::intrinsics::Intrinsics;
+
+/// How often a function was called and its branches taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    pub branches: Vec<BranchProfile>,
+}
+
+/// How often a branch went each way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchProfile {
+    /// How often its condition was true, which takes a `br_if` and
+    /// enters the `then` arm of an `if`.
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// The profile of each local function of a module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    fn branch(&self, func_index: LocalFuncIndex, branch: usize) -> Option<BranchProfile> {
+        self.functions
+            .get(func_index.index())?
+            .branches
+            .get(branch)
+            .cloned()
+    }
+}
+
+/// The counters that the code of an instrumented module increments.
+/// For each function, the first counts its calls, then each branch has
+/// one that counts how often it's reached and one that counts how often
+/// its condition is true.
+pub struct Counters {
+    functions: Vec<Box<[AtomicU64]>>,
+}
+
+impl Counters {
+    pub(crate) fn new() -> Self {
+        Self {
+            functions: Vec::new(),
+        }
+    }
+
+    /// Add the counters of the next function, which has `body`.
+    pub(crate) fn add_function(
+        &mut self,
+        body: &FunctionBody,
+    ) -> Result<&[AtomicU64], BinaryReaderError> {
+        let mut branches = 0;
+        for op in body.get_operators_reader()? {
+            match op? {
+                Operator::BrIf { .. } | Operator::If { .. } => branches += 1,
+                _ => {}
+            }
+        }
+
+        let counters: Vec<AtomicU64> = (0..1 + 2 * branches).map(|_| AtomicU64::new(0)).collect();
+        self.functions.push(counters.into_boxed_slice());
+        Ok(self.functions.last().unwrap())
+    }
+
+    fn profile(&self) -> Profile {
+        let functions = self
+            .functions
+            .iter()
+            .map(|counters| {
+                let counts: Vec<u64> = counters
+                    .iter()
+                    .map(|counter| counter.load(Ordering::Relaxed))
+                    .collect();
+                FunctionProfile {
+                    calls: counts[0],
+                    branches: counts[1..]
+                        .chunks(2)
+                        .map(|counts| BranchProfile {
+                            taken: counts[1],
+                            not_taken: counts[0].saturating_sub(counts[1]),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        Profile { functions }
+    }
+}
+
+/// Collects the profile of the module that's compiled with it, which
+/// can be read while the module runs and after.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    counters: Arc<Mutex<Option<Arc<Counters>>>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The profile of the module so far, if one has been compiled with
+    /// this profiler. It's the last one compiled if there were several.
+    pub fn profile(&self) -> Option<Profile> {
+        let counters = self.counters.lock().unwrap();
+        counters.as_ref().map(|counters| counters.profile())
+    }
+
+    pub(crate) fn attach(&self, counters: Arc<Counters>) {
+        *self.counters.lock().unwrap() = Some(counters);
+    }
+}
+
+impl std::fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Profiler")
+    }
+}
+
+/// Add `amount` to `counter`. The counts of racing threads can be lost,
+/// which doesn't matter much for a profile.
+pub(crate) fn increment(
+    builder: &Builder,
+    intrinsics: &Intrinsics,
+    counter: &AtomicU64,
+    amount: IntValue,
+) {
+    let address = intrinsics
+        .i64_ty
+        .const_int(counter as *const AtomicU64 as u64, false);
+    let ptr = builder.build_int_to_ptr(address, intrinsics.i64_ptr_ty, "counter");
+    let count = builder.build_load(ptr, "count").into_int_value();
+    let count = builder.build_int_add(count, amount, "count");
+    builder.build_store(ptr, count);
+}
+
+/// Count a branch on `cond` with `counters`, the pair of the branch.
+pub(crate) fn count_branch(
+    builder: &Builder,
+    intrinsics: &Intrinsics,
+    counters: &[AtomicU64],
+    cond: IntValue,
+) {
+    increment(
+        builder,
+        intrinsics,
+        &counters[0],
+        intrinsics.i64_ty.const_int(1, false),
+    );
+    let taken = builder.build_int_z_extend(cond, intrinsics.i64_ty, "taken");
+    increment(builder, intrinsics, &counters[1], taken);
+}
+
+/// Tell LLVM how often `branch`, the `index`th branch of the function
+/// `func_index`, went each way, if `profile` knows.
+pub(crate) fn set_branch_weights(
+    context: &Context,
+    profile: &Profile,
+    func_index: LocalFuncIndex,
+    index: usize,
+    branch: InstructionValue,
+) {
+    let counts = match profile.branch(func_index, index) {
+        Some(counts) if counts.taken + counts.not_taken > 0 => counts,
+        _ => return,
+    };
+
+    // The weights are 32 bits, so large counts are scaled down.
+    let scale = (counts.taken.max(counts.not_taken) >> 32) + 1;
+    let weight = |count: u64| -> BasicValueEnum {
+        context.i32_type().const_int(count / scale, false).into()
+    };
+
+    let weights = context.metadata_node(&[
+        context.metadata_string("branch_weights").into(),
+        weight(counts.taken),
+        weight(counts.not_taken),
+    ]);
+    branch.set_metadata(weights, context.get_kind_id("prof"));
+}
@@ -1,8 +1,9 @@
 use wasmer_runtime_core::{
     backend::Backend,
+    memory::memory_style,
     module::{
-        DataInitializer, ExportIndex, ImportName, ModuleInfo, StringTable, StringTableBuilder,
-        TableInitializer,
+        read_func_names, DataInitializer, ExportIndex, ImportName, ModuleInfo, StringTable,
+        StringTableBuilder, TableInitializer,
     },
     structures::{Map, TypedIndex},
     types::{
@@ -34,14 +35,22 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
         data_initializers: Vec::new(),
         elem_initializers: Vec::new(),
 
+        passive_data: Default::default(),
+        passive_elements: Default::default(),
+
         start_func: None,
 
         func_assoc: Map::new(),
         signatures: Map::new(),
         backend: Backend::LLVM,
+        memory_style: memory_style(),
+        initial_points: None,
 
         namespace_table: StringTable::new(),
         name_table: StringTable::new(),
+
+        custom_sections: Default::default(),
+        func_names: Default::default(),
     };
 
     let mut reader = ModuleReader::new(wasm)?;
@@ -85,9 +94,8 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
                             info.func_assoc.push(sigindex);
                         }
                         ImportSectionEntryType::Table(table_ty) => {
-                            assert_eq!(table_ty.element_type, WpType::AnyFunc);
                             let table_desc = TableDescriptor {
-                                element: ElementType::Anyfunc,
+                                element: type_to_element_type(table_ty.element_type)?,
                                 minimum: table_ty.limits.initial,
                                 maximum: table_ty.limits.maximum,
                             };
@@ -132,7 +140,7 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
                     let table_ty = table_ty?;
 
                     let table_desc = TableDescriptor {
-                        element: ElementType::Anyfunc,
+                        element: type_to_element_type(table_ty.element_type)?,
                         minimum: table_ty.limits.initial,
                         maximum: table_ty.limits.maximum,
                     };
@@ -202,7 +210,7 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
             SectionCode::Element => {
                 let element_reader = section.get_element_section_reader()?;
 
-                for element in element_reader {
+                for (segment_index, element) in element_reader.into_iter().enumerate() {
                     let Element { kind, items } = element?;
 
                     match kind {
@@ -228,10 +236,14 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
                             info.elem_initializers.push(table_init);
                         }
                         ElementKind::Passive(_ty) => {
-                            return Err(BinaryReaderError {
-                                message: "passive tables are not yet supported",
-                                offset: -1isize as usize,
-                            });
+                            let items_reader = items.get_items_reader()?;
+
+                            let elements: Vec<_> = items_reader
+                                .into_iter()
+                                .map(|res| res.map(|index| FuncIndex::new(index as usize)))
+                                .collect::<Result<_, _>>()?;
+
+                            info.passive_elements.insert(segment_index as u32, elements);
                         }
                     }
                 }
@@ -242,7 +254,7 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
             SectionCode::Data => {
                 let data_reader = section.get_data_section_reader()?;
 
-                for data in data_reader {
+                for (segment_index, data) in data_reader.into_iter().enumerate() {
                     let Data { kind, data } = data?;
 
                     match kind {
@@ -262,16 +274,27 @@ pub fn read_module(wasm: &[u8]) -> Result<(ModuleInfo, CodeSectionReader), Binar
                             info.data_initializers.push(data_init);
                         }
                         DataKind::Passive => {
-                            return Err(BinaryReaderError {
-                                message: "passive memories are not yet supported",
-                                offset: -1isize as usize,
-                            });
+                            info.passive_data
+                                .insert(segment_index as u32, data.to_vec());
                         }
                     }
                 }
             }
             SectionCode::DataCount => {}
-            SectionCode::Custom { .. } => {}
+            SectionCode::Custom { name, .. } => {
+                let mut reader = section.get_binary_reader();
+                let len = reader.bytes_remaining();
+                let contents = reader.read_bytes(len)?;
+
+                if name == "name" {
+                    info.func_names = read_func_names(contents);
+                }
+
+                info.custom_sections
+                    .entry(name.to_string())
+                    .or_insert_with(Vec::new)
+                    .push(contents.to_vec());
+            }
         }
     }
 }
@@ -282,6 +305,8 @@ pub fn type_to_type(ty: WpType) -> Result<Type, BinaryReaderError> {
         WpType::I64 => Type::I64,
         WpType::F32 => Type::F32,
         WpType::F64 => Type::F64,
+        WpType::AnyRef => Type::ExternRef,
+        WpType::AnyFunc => Type::FuncRef,
         WpType::V128 => {
             return Err(BinaryReaderError {
                 message: "the wasmer llvm backend does not yet support the simd extension",
@@ -297,6 +322,19 @@ pub fn type_to_type(ty: WpType) -> Result<Type, BinaryReaderError> {
     })
 }
 
+fn type_to_element_type(ty: WpType) -> Result<ElementType, BinaryReaderError> {
+    Ok(match ty {
+        WpType::AnyFunc => ElementType::Anyfunc,
+        WpType::AnyRef => ElementType::ExternRef,
+        _ => {
+            return Err(BinaryReaderError {
+                message: "that type is not supported as a table element type",
+                offset: -1isize as usize,
+            });
+        }
+    })
+}
+
 fn func_type_to_func_sig(func_ty: FuncType) -> Result<FuncSig, BinaryReaderError> {
     assert_eq!(func_ty.form, WpType::Func);
 
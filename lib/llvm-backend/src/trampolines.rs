@@ -1,4 +1,4 @@
-use crate::intrinsics::Intrinsics;
+use crate::{code::results_struct_ty, intrinsics::Intrinsics};
 use inkwell::{
     builder::Builder,
     context::Context,
@@ -81,6 +81,7 @@ fn generate_trampoline(
         Type::I64 => intrinsics.i64_ptr_ty,
         Type::F32 => intrinsics.f32_ptr_ty,
         Type::F64 => intrinsics.f64_ptr_ty,
+        Type::ExternRef | Type::FuncRef => intrinsics.i64_ptr_ty,
     };
 
     let mut args_vec = Vec::with_capacity(func_sig.params().len() + 1);
@@ -99,6 +100,15 @@ fn generate_trampoline(
         args_vec.push(arg);
     }
 
+    let results_ptr = if func_sig.returns_through_pointer() {
+        let results_ty = results_struct_ty(context, intrinsics, func_sig.returns());
+        let results_ptr = builder.build_alloca(results_ty, "results");
+        args_vec.push(results_ptr.as_basic_value_enum());
+        Some(results_ptr)
+    } else {
+        None
+    };
+
     let call_site = builder.build_call(func_ptr, &args_vec, "call");
 
     match func_sig.returns() {
@@ -113,7 +123,26 @@ fn generate_trampoline(
                 call_site.try_as_basic_value().left().unwrap(),
             );
         }
-        _ => unimplemented!("multi-value returns"),
+        returns @ _ => {
+            let results_ptr = results_ptr.unwrap();
+
+            for (i, ret_ty) in returns.iter().enumerate() {
+                let index = intrinsics.i32_ty.const_int(i as _, false);
+                let item_pointer =
+                    unsafe { builder.build_in_bounds_gep(returns_ptr, &[index], "ret_ptr") };
+
+                let typed_item_pointer = builder.build_pointer_cast(
+                    item_pointer,
+                    cast_ptr_ty(*ret_ty),
+                    "typed_ret_pointer",
+                );
+
+                let result_ptr =
+                    unsafe { builder.build_struct_gep(results_ptr, i as u32, "result_ptr") };
+                let ret = builder.build_load(result_ptr, "ret");
+                builder.build_store(typed_item_pointer, ret);
+            }
+        }
     }
 
     builder.build_return(None);
@@ -1,7 +1,7 @@
 extern crate cbindgen;
 
-use cbindgen::{Builder, Language};
-use std::{env, fs, path::PathBuf};
+use cbindgen::{Builder, Config, Language};
+use std::{env, fs, path::Path, path::PathBuf};
 
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -12,9 +12,27 @@ fn main() {
     let mut out_wasmer_header_file = PathBuf::from(&out_dir);
     out_wasmer_header_file.push("wasmer");
 
+    // The standard C API is declared by `wasm.h`, so leave it out.
+    let mut config = Config::default();
+    config.export.exclude = wasm_c_api_items(&Path::new(&crate_dir).join("src/wasm_c_api.rs"));
+
+    // Let the code that includes the header know which version it was
+    // generated for, to compare with `wasmer_version`.
+    config.after_includes = Some(format!(
+        "\n#define WASMER_VERSION \"{}\"\
+         \n#define WASMER_VERSION_MAJOR {}\
+         \n#define WASMER_VERSION_MINOR {}\
+         \n#define WASMER_VERSION_PATCH {}",
+        env::var("CARGO_PKG_VERSION").unwrap(),
+        env::var("CARGO_PKG_VERSION_MAJOR").unwrap(),
+        env::var("CARGO_PKG_VERSION_MINOR").unwrap(),
+        env::var("CARGO_PKG_VERSION_PATCH").unwrap(),
+    ));
+
     // Generate the C bindings in the `OUT_DIR`.
     out_wasmer_header_file.set_extension("h");
     Builder::new()
+        .with_config(config.clone())
         .with_crate(crate_dir.clone())
         .with_language(Language::C)
         .with_include_guard("WASMER_H")
@@ -25,6 +43,7 @@ fn main() {
     // Generate the C++ bindings in the `OUT_DIR`.
     out_wasmer_header_file.set_extension("hh");
     Builder::new()
+        .with_config(config)
         .with_crate(crate_dir)
         .with_language(Language::Cxx)
         .with_include_guard("WASMER_H")
@@ -50,3 +69,24 @@ fn main() {
     fs::copy(out_wasmer_header_file, crate_wasmer_header_file)
         .expect("Unable to copy the generated C++ bindings");
 }
+
+/// The names of the items that `path` declares outside of macros.
+fn wasm_c_api_items(path: &Path) -> Vec<String> {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let source = fs::read_to_string(path).expect("Unable to read the standard C API");
+    let mut items = vec![];
+    for line in source.lines() {
+        let mut words = line.split_whitespace().skip_while(|&word| word != "pub");
+        let keyword = words.find(|word| ["fn", "struct", "union", "type", "const"].contains(word));
+        if let (Some(_), Some(name)) = (keyword, words.next()) {
+            let name: String = name
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            if !name.is_empty() {
+                items.push(name);
+            }
+        }
+    }
+    items
+}
@@ -0,0 +1,409 @@
+//! Compiling with a chosen backend and configuration.
+//!
+//! `wasmer_compile` and `wasmer_instantiate` compile with the default
+//! backend and its defaults. A `wasmer_config_t` chooses the backend,
+//! how much it optimizes, the WebAssembly proposals that modules may
+//! use and whether and how their code is metered, for
+//! `wasmer_compile_with_config` and `wasmer_instantiate_with_config`.
+
+use crate::{
+    extend_import_object, update_last_error, wasmer_import_t, wasmer_instance_t, wasmer_module_t,
+    wasmer_result_t, CApiError,
+};
+use libc::{c_int, uint32_t, uint64_t, uint8_t};
+use std::slice;
+use wasmer_runtime::{ImportObject, Instance, Module};
+use wasmer_runtime_core::backend::{Backend, Compiler, CompilerConfig};
+use wasmer_runtime_core::error::{CompileError, CompileResult};
+use wasmer_runtime_core::metering::{self, CostFunction, MeteringConfig};
+use wasmer_runtime_core::{validate_with_features, Features};
+
+/// Opaque pointer to a compiler configuration.
+#[repr(C)]
+pub struct wasmer_config_t;
+
+/// The backends that modules can be compiled with.
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum wasmer_backend_t {
+    WASMER_BACKEND_CRANELIFT,
+    WASMER_BACKEND_SINGLEPASS,
+    WASMER_BACKEND_LLVM,
+}
+
+impl From<wasmer_backend_t> for Backend {
+    fn from(backend: wasmer_backend_t) -> Self {
+        match backend {
+            wasmer_backend_t::WASMER_BACKEND_CRANELIFT => Backend::Cranelift,
+            wasmer_backend_t::WASMER_BACKEND_SINGLEPASS => Backend::Dynasm,
+            wasmer_backend_t::WASMER_BACKEND_LLVM => Backend::LLVM,
+        }
+    }
+}
+
+/// How much a backend optimizes the code that it generates. Only the
+/// LLVM backend can be told; the others always optimize the same.
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum wasmer_opt_level_t {
+    /// Don't optimize, for the fastest compiles.
+    WASMER_OPT_LEVEL_NONE,
+    /// Optimize for speed. The default.
+    WASMER_OPT_LEVEL_SPEED,
+    /// Optimize for the size of the code over its speed.
+    WASMER_OPT_LEVEL_SIZE,
+}
+
+/// The WebAssembly proposals, beyond the MVP, that modules may use.
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum wasmer_feature_t {
+    WASMER_FEATURE_THREADS,
+    WASMER_FEATURE_REFERENCE_TYPES,
+    WASMER_FEATURE_SIMD,
+    WASMER_FEATURE_BULK_MEMORY,
+    WASMER_FEATURE_MUTABLE_GLOBAL,
+}
+
+/// How many points the operators of metered code cost.
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum wasmer_cost_table_t {
+    /// A point for every operator. The default.
+    WASMER_COST_TABLE_UNIT,
+    /// A point for most operators, and more for multiplications,
+    /// divisions, memory accesses, calls and growing memories.
+    WASMER_COST_TABLE_WEIGHTED,
+}
+
+struct Config {
+    backend: Backend,
+    opt_level: wasmer_opt_level_t,
+    features: Features,
+    /// The points that instances start out with, if code is metered.
+    metering_points: Option<u64>,
+    cost_function: CostFunction,
+}
+
+impl Config {
+    fn compile(&self, wasm: &[u8]) -> CompileResult<Module> {
+        self.check_backend_features()?;
+        validate_with_features(wasm, self.features)?;
+
+        let compiler_config = CompilerConfig {
+            metering: self.metering_points.map(|initial_points| MeteringConfig {
+                initial_points,
+                cost_function: self.cost_function,
+            }),
+        };
+        self.compile_with_backend(wasm, compiler_config)
+    }
+
+    /// Fail if the backend can't compile a proposal that modules are
+    /// allowed to use, rather than when a module uses it.
+    fn check_backend_features(&self) -> CompileResult<()> {
+        let capabilities = wasmer_runtime::compiler_for(self.backend)
+            .map(|compiler| compiler.capabilities())
+            .unwrap_or_default();
+        let unsupported = [
+            ("threads", self.features.threads && !capabilities.threads),
+            (
+                "bulk memory",
+                self.features.bulk_memory && !capabilities.bulk_memory,
+            ),
+        ];
+        match unsupported.iter().find(|(_, unsupported)| *unsupported) {
+            Some((proposal, _)) => Err(CompileError::ValidationError {
+                msg: format!(
+                    "the {} backend doesn't support the {} proposal",
+                    self.backend.name(),
+                    proposal
+                ),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    fn compile_with_backend(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+    ) -> CompileResult<Module> {
+        use wasmer_llvm_backend::{LLVMCompiler, LLVMConfig, OptLevel};
+
+        if self.backend != Backend::LLVM {
+            return wasmer_runtime_core::compile_with_config(
+                wasm,
+                self.compiler()?,
+                compiler_config,
+            );
+        }
+        let opt_level = match self.opt_level {
+            wasmer_opt_level_t::WASMER_OPT_LEVEL_NONE => OptLevel::O0,
+            wasmer_opt_level_t::WASMER_OPT_LEVEL_SPEED => OptLevel::O3,
+            wasmer_opt_level_t::WASMER_OPT_LEVEL_SIZE => OptLevel::Os,
+        };
+        let compiler = LLVMCompiler::with_config(LLVMConfig {
+            opt_level,
+            ..LLVMConfig::default()
+        });
+        wasmer_runtime_core::compile_with_config(wasm, &compiler, compiler_config)
+    }
+
+    #[cfg(not(feature = "llvm"))]
+    fn compile_with_backend(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+    ) -> CompileResult<Module> {
+        wasmer_runtime_core::compile_with_config(wasm, self.compiler()?, compiler_config)
+    }
+
+    /// The compiler of the configured backend.
+    fn compiler(&self) -> CompileResult<&'static dyn Compiler> {
+        wasmer_runtime::compiler_for(self.backend).ok_or(CompileError::UnavailableBackend {
+            backend: self.backend,
+        })
+    }
+}
+
+/// Creates a new compiler configuration, for the default backend with
+/// its defaults, the MVP alone, and no metering.
+///
+/// The caller owns the object and should call `wasmer_config_destroy` to free it.
+#[no_mangle]
+pub extern "C" fn wasmer_config_new() -> *mut wasmer_config_t {
+    let config = Config {
+        backend: wasmer_runtime::default_backend(),
+        opt_level: wasmer_opt_level_t::WASMER_OPT_LEVEL_SPEED,
+        features: Features::default(),
+        metering_points: None,
+        cost_function: metering::unit_cost,
+    };
+    Box::into_raw(Box::new(config)) as *mut wasmer_config_t
+}
+
+/// Frees memory for the given compiler configuration.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_config_destroy(config: *mut wasmer_config_t) {
+    if !config.is_null() {
+        unsafe { Box::from_raw(config as *mut Config) };
+    }
+}
+
+/// Sets the backend that modules are compiled with.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` if this library wasn't built
+/// with the backend. Use `wasmer_last_error_length` and
+/// `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_config_set_backend(
+    config: *mut wasmer_config_t,
+    backend: wasmer_backend_t,
+) -> wasmer_result_t {
+    let config = &mut *(config as *mut Config);
+    let backend = Backend::from(backend);
+    if wasmer_runtime::compiler_for(backend).is_none() {
+        update_last_error(CApiError {
+            msg: format!("the {} backend isn't available", backend.name()),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    config.backend = backend;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Sets how much the backend optimizes the code that it generates.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_config_set_opt_level(
+    config: *mut wasmer_config_t,
+    opt_level: wasmer_opt_level_t,
+) {
+    let config = &mut *(config as *mut Config);
+    config.opt_level = opt_level;
+}
+
+/// Allows or disallows modules to use a WebAssembly proposal. Modules
+/// that use proposals that aren't allowed fail to compile, and so does
+/// every module if the backend can't compile a proposal that's allowed.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_config_set_feature(
+    config: *mut wasmer_config_t,
+    feature: wasmer_feature_t,
+    enabled: bool,
+) {
+    let features = &mut (*(config as *mut Config)).features;
+    let flag = match feature {
+        wasmer_feature_t::WASMER_FEATURE_THREADS => &mut features.threads,
+        wasmer_feature_t::WASMER_FEATURE_REFERENCE_TYPES => &mut features.reference_types,
+        wasmer_feature_t::WASMER_FEATURE_SIMD => &mut features.simd,
+        wasmer_feature_t::WASMER_FEATURE_BULK_MEMORY => &mut features.bulk_memory,
+        wasmer_feature_t::WASMER_FEATURE_MUTABLE_GLOBAL => &mut features.mutable_global,
+    };
+    *flag = enabled;
+}
+
+/// Meters the code of modules, charging points for the operators that
+/// it runs as `wasmer_config_set_cost_table` says. Calls trap with
+/// `WASMER_TRAP_OUT_OF_GAS` once an instance runs out of points.
+///
+/// The instances of modules compiled with the configuration start out
+/// with `initial_points`, including while their start function runs. Use
+/// `wasmer_instance_set_remaining_points` to give them more points.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_config_set_metering(
+    config: *mut wasmer_config_t,
+    initial_points: uint64_t,
+) {
+    let config = &mut *(config as *mut Config);
+    config.metering_points = Some(initial_points);
+}
+
+/// Sets how many points the operators of metered code cost.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_config_set_cost_table(
+    config: *mut wasmer_config_t,
+    cost_table: wasmer_cost_table_t,
+) {
+    let config = &mut *(config as *mut Config);
+    config.cost_function = match cost_table {
+        wasmer_cost_table_t::WASMER_COST_TABLE_UNIT => metering::unit_cost,
+        wasmer_cost_table_t::WASMER_COST_TABLE_WEIGHTED => metering::weighted_cost,
+    };
+}
+
+/// Creates a new Module from the given wasm bytes, compiled as the
+/// given configuration says.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_compile_with_config(
+    module: *mut *mut wasmer_module_t,
+    wasm_bytes: *mut uint8_t,
+    wasm_bytes_len: uint32_t,
+    config: *const wasmer_config_t,
+) -> wasmer_result_t {
+    if wasm_bytes.is_null() || config.is_null() {
+        update_last_error(CApiError {
+            msg: "wasm bytes or config ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let config = &*(config as *const Config);
+    let bytes: &[u8] = slice::from_raw_parts(wasm_bytes, wasm_bytes_len as usize);
+    let new_module = match config.compile(bytes) {
+        Ok(module) => module,
+        Err(error) => {
+            update_last_error(error);
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    *module = Box::into_raw(Box::new(new_module)) as *mut wasmer_module_t;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Creates a new Instance from the given wasm bytes and imports, compiled
+/// as the given configuration says.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instantiate_with_config(
+    instance: *mut *mut wasmer_instance_t,
+    wasm_bytes: *mut uint8_t,
+    wasm_bytes_len: uint32_t,
+    imports: *mut wasmer_import_t,
+    imports_len: c_int,
+    config: *const wasmer_config_t,
+) -> wasmer_result_t {
+    if wasm_bytes.is_null() || config.is_null() || (imports.is_null() && imports_len > 0) {
+        update_last_error(CApiError {
+            msg: "wasm bytes, imports or config ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let config = &*(config as *const Config);
+    let imports: &[wasmer_import_t] = if imports_len > 0 {
+        slice::from_raw_parts(imports, imports_len as usize)
+    } else {
+        &[]
+    };
+    let mut import_object = ImportObject::new();
+    if let Err(error) = extend_import_object(&mut import_object, imports) {
+        update_last_error(error);
+        return wasmer_result_t::WASMER_ERROR;
+    }
+
+    let bytes: &[u8] = slice::from_raw_parts(wasm_bytes, wasm_bytes_len as usize);
+    let module = match config.compile(bytes) {
+        Ok(module) => module,
+        Err(error) => {
+            update_last_error(error);
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    let new_instance = match module.instantiate(&import_object) {
+        Ok(instance) => instance,
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("error instantiating: {:?}", error),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Gets the points that an instance has left to run metered code with.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instance_get_remaining_points(
+    instance: *const wasmer_instance_t,
+) -> uint64_t {
+    let instance = &*(instance as *const Instance);
+    instance.get_remaining_points()
+}
+
+/// Gets the points that an instance has used up since it was created,
+/// or since `wasmer_instance_set_remaining_points` was last called on it.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instance_get_points_used(
+    instance: *const wasmer_instance_t,
+) -> uint64_t {
+    let instance = &*(instance as *const Instance);
+    instance.get_points_used()
+}
+
+/// Sets the points that an instance has left to run metered code with,
+/// and starts counting the points that it uses up from there.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instance_set_remaining_points(
+    instance: *mut wasmer_instance_t,
+    points: uint64_t,
+) {
+    let instance = &mut *(instance as *mut Instance);
+    instance.set_remaining_points(points);
+}
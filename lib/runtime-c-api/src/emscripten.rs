@@ -0,0 +1,290 @@
+//! Running emscripten modules.
+//!
+//! Emscripten modules import their environment (`env.memory`,
+//! `env.STACKTOP`, the syscalls…) from the host, and expect it to call
+//! their `_main` function with the program arguments laid out on the
+//! stack. These functions do that setup, so that embedders don't have
+//! to. The environment variables and the file system of a module are
+//! those of the host process, and so are its standard streams, unless
+//! it's run with `wasmer_emscripten_call_main_with_stdio`.
+
+use crate::{
+    update_last_call_error, update_last_error, wasmer_import_object_t, wasmer_instance_t,
+    wasmer_module_t, wasmer_result_t, CApiError,
+};
+use libc::{c_char, c_int, c_void, uint32_t, uint8_t};
+use std::ffi::CStr;
+use std::{io, slice};
+use wasmer_emscripten::{
+    generate_emscripten_env, is_emscripten_module, run_emscripten_instance,
+    run_emscripten_instance_with_stdio, EmscriptenGlobals, Stdio,
+};
+use wasmer_runtime::{Instance, Module};
+
+/// Opaque pointer to the emscripten globals of a module: its memory,
+/// table and stack layout.
+#[repr(C)]
+pub struct wasmer_emscripten_globals_t;
+
+/// Returns true if the given module imports the emscripten environment.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_is_emscripten_module(module: *const wasmer_module_t) -> bool {
+    if module.is_null() {
+        return false;
+    }
+    is_emscripten_module(&*(module as *const Module))
+}
+
+/// Creates the emscripten globals of the given module.
+///
+/// The caller owns the object and should call `wasmer_emscripten_globals_destroy` to free it.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_emscripten_globals_new(
+    module: *const wasmer_module_t,
+) -> *mut wasmer_emscripten_globals_t {
+    let module = &*(module as *const Module);
+    let globals = EmscriptenGlobals::new(module);
+    Box::into_raw(Box::new(globals)) as *mut wasmer_emscripten_globals_t
+}
+
+/// Frees memory for the given emscripten globals.
+///
+/// Instances created with them keep their memory and table alive.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_emscripten_globals_destroy(globals: *mut wasmer_emscripten_globals_t) {
+    if !globals.is_null() {
+        unsafe { Box::from_raw(globals as *mut EmscriptenGlobals) };
+    }
+}
+
+/// Creates an import object holding the emscripten environment built
+/// from the given globals, for modules that need more imports than it
+/// provides. See `wasmer_import_object_merge`.
+///
+/// The caller owns the object and should call `wasmer_import_object_destroy` to free it.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_emscripten_generate_import_object(
+    globals: *mut wasmer_emscripten_globals_t,
+) -> *mut wasmer_import_object_t {
+    let globals = &mut *(globals as *mut EmscriptenGlobals);
+    let import_object = generate_emscripten_env(globals);
+    Box::into_raw(Box::new(import_object)) as *mut wasmer_import_object_t
+}
+
+/// Receives the bytes that an emscripten instance writes to stdout, when
+/// `fd` is 1, or stderr, when it's 2. Returns how many of them it took,
+/// or -1 upon failure.
+#[allow(non_camel_case_types)]
+pub type wasmer_emscripten_write_t = extern "C" fn(
+    data: *mut c_void,
+    fd: c_int,
+    bytes: *const uint8_t,
+    bytes_len: uint32_t,
+) -> c_int;
+
+/// Fills `buffer` with the next bytes that an emscripten instance reads
+/// from stdin. Returns how many it filled it with, 0 at the end of the
+/// input, or -1 upon failure.
+#[allow(non_camel_case_types)]
+pub type wasmer_emscripten_read_t =
+    extern "C" fn(data: *mut c_void, buffer: *mut uint8_t, buffer_len: uint32_t) -> c_int;
+
+/// The standard streams of an instance, as callbacks. Those that are
+/// `None` are the host's.
+struct CallbackStdio {
+    write: Option<wasmer_emscripten_write_t>,
+    read: Option<wasmer_emscripten_read_t>,
+    data: *mut c_void,
+}
+
+impl CallbackStdio {
+    fn result(ret: c_int) -> io::Result<usize> {
+        if ret < 0 {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "stdio callback failed",
+            ))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
+impl Stdio for CallbackStdio {
+    fn write(&mut self, fd: c_int, buf: &[u8]) -> io::Result<usize> {
+        let ret = match self.write {
+            Some(write) => write(self.data, fd, buf.as_ptr(), buf.len() as uint32_t),
+            None => unsafe { libc::write(fd, buf.as_ptr() as *const c_void, buf.len()) as c_int },
+        };
+        Self::result(ret)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let ret = match self.read {
+            Some(read) => read(self.data, buf.as_mut_ptr(), buf.len() as uint32_t),
+            None => unsafe { libc::read(0, buf.as_mut_ptr() as *mut c_void, buf.len()) as c_int },
+        };
+        Self::result(ret)
+    }
+}
+
+/// Creates a new Instance of an emscripten module, importing the
+/// emscripten environment built from the given globals.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_emscripten_module_instantiate(
+    module: *const wasmer_module_t,
+    globals: *mut wasmer_emscripten_globals_t,
+    instance: *mut *mut wasmer_instance_t,
+) -> wasmer_result_t {
+    if module.is_null() || globals.is_null() {
+        update_last_error(CApiError {
+            msg: "module or globals ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let module = &*(module as *const Module);
+    let globals = &mut *(globals as *mut EmscriptenGlobals);
+    let import_object = generate_emscripten_env(globals);
+    let new_instance = match module.instantiate(&import_object) {
+        Ok(instance) => instance,
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("error instantiating emscripten module: {:?}", error),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Runs the `_main` function of an emscripten instance, as the program
+/// at `path` called with the `args_len` arguments in `args`.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message, and `wasmer_last_trap_code`
+/// to get the trap, if `_main` trapped.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_emscripten_call_main(
+    instance: *mut wasmer_instance_t,
+    path: *const c_char,
+    args: *const *const c_char,
+    args_len: c_int,
+) -> wasmer_result_t {
+    call_main(instance, path, args, args_len, None)
+}
+
+/// Runs the `_main` function of an emscripten instance, like
+/// `wasmer_emscripten_call_main`, with its output going to `write` and
+/// its input coming from `read` rather than from the standard streams
+/// of the host process. `data` is passed to them. Either may be null,
+/// to leave those streams to the host.
+///
+/// Only the output that goes through the `write` and `writev` syscalls
+/// and `putchar` is redirected; modules that import `printf` print with
+/// the host's.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message, and `wasmer_last_trap_code`
+/// to get the trap, if `_main` trapped.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_emscripten_call_main_with_stdio(
+    instance: *mut wasmer_instance_t,
+    path: *const c_char,
+    args: *const *const c_char,
+    args_len: c_int,
+    write: Option<wasmer_emscripten_write_t>,
+    read: Option<wasmer_emscripten_read_t>,
+    data: *mut c_void,
+) -> wasmer_result_t {
+    let stdio = CallbackStdio { write, read, data };
+    call_main(instance, path, args, args_len, Some(Box::new(stdio)))
+}
+
+unsafe fn call_main(
+    instance: *mut wasmer_instance_t,
+    path: *const c_char,
+    args: *const *const c_char,
+    args_len: c_int,
+    stdio: Option<Box<dyn Stdio>>,
+) -> wasmer_result_t {
+    if instance.is_null() || path.is_null() || (args.is_null() && args_len > 0) {
+        update_last_error(CApiError {
+            msg: "instance, path or args ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let instance = &mut *(instance as *mut Instance);
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => {
+            update_last_error(CApiError {
+                msg: "error converting path to string".to_string(),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    let args: &[*const c_char] = if args_len > 0 {
+        slice::from_raw_parts(args, args_len as usize)
+    } else {
+        &[]
+    };
+    let mut arg_strs = Vec::with_capacity(args.len());
+    for &arg in args {
+        match CStr::from_ptr(arg).to_str() {
+            Ok(arg) => arg_strs.push(arg),
+            Err(_) => {
+                update_last_error(CApiError {
+                    msg: "error converting argument to string".to_string(),
+                });
+                return wasmer_result_t::WASMER_ERROR;
+            }
+        }
+    }
+
+    // `run_emscripten_instance` panics on any other `_main`.
+    match instance.dyn_func("_main") {
+        Ok(main_func) => match main_func.signature().params().len() {
+            0 | 2 => {}
+            params_len => {
+                update_last_error(CApiError {
+                    msg: format!("_main takes {} params, not 0 or 2", params_len),
+                });
+                return wasmer_result_t::WASMER_ERROR;
+            }
+        },
+        Err(error) => {
+            update_last_error(error);
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    }
+
+    let module = instance.module();
+    let result = match stdio {
+        Some(stdio) => run_emscripten_instance_with_stdio(&module, instance, path, arg_strs, stdio),
+        None => run_emscripten_instance(&module, instance, path, arg_strs),
+    };
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_call_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
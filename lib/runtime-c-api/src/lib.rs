@@ -1,38 +1,66 @@
 extern crate wasmer_runtime;
 extern crate wasmer_runtime_core;
 
-use libc::{c_char, c_int, int32_t, int64_t, uint32_t, uint8_t};
+use libc::{c_char, c_int, int32_t, int64_t, uint32_t, uint64_t, uint8_t};
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::error::Error;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::fmt;
+use std::rc::Rc;
 use std::slice;
 use std::sync::Arc;
 use std::{ffi::c_void, ptr};
 use wasmer_runtime::{
     default_compiler, Ctx, Global, ImportObject, Instance, Memory, Module, Table, Value,
 };
-use wasmer_runtime_core::cache::Artifact;
-use wasmer_runtime_core::export::{Context, Export, FuncPointer};
+use wasmer_runtime_core::cache::{Artifact, Error as CacheError};
+use wasmer_runtime_core::epoch;
+use wasmer_runtime_core::error::{CallError, ErrorCode, RuntimeError, WasmFrame};
+use wasmer_runtime_core::export::{Context, Export, FuncEnv, FuncPointer};
 use wasmer_runtime_core::import::Namespace;
+use wasmer_runtime_core::instance::InterruptHandle;
 use wasmer_runtime_core::load_cache_with;
-use wasmer_runtime_core::module::{ExportIndex, ImportName};
-use wasmer_runtime_core::types::{ElementType, FuncSig, MemoryDescriptor, TableDescriptor, Type};
+use wasmer_runtime_core::module::{ExportIndex, ImportName, ModuleInfo};
+use wasmer_runtime_core::refs::FuncRef;
+use wasmer_runtime_core::structures::TypedIndex;
+use wasmer_runtime_core::table::{Anyfunc, Element};
+use wasmer_runtime_core::types::{
+    ElementType, FuncSig, ImportedFuncIndex, MemoryDescriptor, TableDescriptor, Type,
+};
 use wasmer_runtime_core::units::{Bytes, Pages};
+use wasmer_runtime_core::vm::FuncCtx;
+
+pub mod config;
+pub mod emscripten;
+pub mod wasm_c_api;
 
+/// Opaque pointer to a compiled module. Modules can be shared by threads,
+/// which may each instantiate them and read them at the same time.
 #[repr(C)]
 pub struct wasmer_module_t;
 
 #[repr(C)]
 pub struct wasmer_serialized_module_t;
 
+/// Opaque pointer to an instance. An instance, and the memories, tables,
+/// globals, functions and import objects that it was created with or
+/// exports, belong to the thread that created them, and must only be
+/// used by it. Use `wasmer_instance_interrupt_handle` to stop an instance
+/// from another thread.
 #[repr(C)]
 pub struct wasmer_instance_t;
 
 #[repr(C)]
 pub struct wasmer_instance_context_t;
 
+#[repr(C)]
+pub struct wasmer_func_env_t;
+
+/// Opaque pointer to an interrupt handle, which any thread may use.
+#[repr(C)]
+pub struct wasmer_interrupt_handle_t;
+
 #[allow(non_camel_case_types)]
 #[repr(C)]
 pub enum wasmer_result_t {
@@ -93,6 +121,10 @@ pub struct wasmer_export_func_t;
 #[derive(Clone)]
 pub struct wasmer_global_t;
 
+/// A reference to a function in a table, or 0 for none.
+#[allow(non_camel_case_types)]
+pub type wasmer_funcref_t = u64;
+
 #[repr(C)]
 pub struct wasmer_limits_t {
     pub min: uint32_t,
@@ -121,6 +153,9 @@ pub struct wasmer_import_descriptor_t;
 #[derive(Clone)]
 pub struct wasmer_import_descriptors_t;
 
+#[repr(C)]
+pub struct wasmer_import_object_t;
+
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasmer_export_t;
@@ -162,6 +197,22 @@ pub struct wasmer_byte_array {
     bytes_len: uint32_t,
 }
 
+/// The bytes of a Memory, which can be read and written in place to
+/// exchange data with an instance without copying it.
+///
+/// A view stays valid until the memory grows, which may move its bytes.
+#[repr(C)]
+pub struct wasmer_memory_view_t {
+    pub data: *mut uint8_t,
+    pub data_len: uint64_t,
+}
+
+/// Called with `data` and the old and the new size in pages of a
+/// memory when it grows.
+#[allow(non_camel_case_types)]
+pub type wasmer_memory_grow_callback_t =
+    extern "C" fn(data: *mut c_void, old_pages: uint32_t, new_pages: uint32_t);
+
 /// Returns true for valid wasm bytes and false for invalid bytes
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
@@ -177,6 +228,59 @@ pub unsafe extern "C" fn wasmer_validate(
     wasmer_runtime_core::validate(bytes)
 }
 
+/// Converts the WebAssembly text format in `wat` to the binary format,
+/// and sets `wasm` to the bytes.
+///
+/// The caller owns the bytes and should call `wasmer_byte_array_destroy` to free them.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_wat2wasm(
+    wat: *const uint8_t,
+    wat_len: uint32_t,
+    wasm: *mut wasmer_byte_array,
+) -> wasmer_result_t {
+    if wat.is_null() {
+        update_last_error(CApiError {
+            msg: "wat ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let wat: &[u8] = slice::from_raw_parts(wat, wat_len as usize);
+    match wabt::wat2wasm(wat) {
+        Ok(bytes) => {
+            let bytes = bytes.into_boxed_slice();
+            let bytes_len = bytes.len() as uint32_t;
+            *wasm = wasmer_byte_array {
+                bytes: Box::into_raw(bytes) as *const uint8_t,
+                bytes_len,
+            };
+            wasmer_result_t::WASMER_OK
+        }
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("error converting wat to wasm: {:?}", error),
+            });
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Frees the bytes of a byte array that the caller owns, such as those
+/// returned by `wasmer_wat2wasm`.
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_byte_array_destroy(bytes: wasmer_byte_array) {
+    if !bytes.bytes.is_null() {
+        let bytes =
+            slice::from_raw_parts_mut(bytes.bytes as *mut uint8_t, bytes.bytes_len as usize);
+        Box::from_raw(bytes);
+    }
+}
+
 /// Creates a new Memory for the given descriptor and initializes the given
 /// pointer to pointer to a pointer to the new memory.
 ///
@@ -245,6 +349,30 @@ pub extern "C" fn wasmer_memory_length(memory: *const wasmer_memory_t) -> uint32
     len
 }
 
+/// Returns the size in bytes of a WebAssembly page, the unit that
+/// memories are sized and grown in.
+#[no_mangle]
+pub extern "C" fn wasmer_memory_page_size() -> uint32_t {
+    let Bytes(size) = Pages(1).bytes();
+    size as uint32_t
+}
+
+/// Returns the limits in pages of the given memory: the number of pages
+/// it was created with, and the most it can grow to, if it has a maximum.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_memory_limits(memory: *const wasmer_memory_t) -> wasmer_limits_t {
+    let memory = unsafe { &*(memory as *const Memory) };
+    let descriptor = memory.descriptor();
+    wasmer_limits_t {
+        min: descriptor.minimum.0,
+        max: wasmer_limit_option_t {
+            has_some: descriptor.maximum.is_some(),
+            some: descriptor.maximum.map_or(0, |Pages(max)| max),
+        },
+    }
+}
+
 /// Creates a new Table for the given descriptor and initializes the given
 /// pointer to pointer to a pointer to the new Table.
 ///
@@ -312,6 +440,121 @@ pub extern "C" fn wasmer_table_length(table: *mut wasmer_table_t) -> uint32_t {
     table.size()
 }
 
+/// Gets the element at the given index of a Table, as a reference to the
+/// function in it, or 0 if the element is empty. The reference can be stored
+/// into any table with `wasmer_table_set`.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_table_get(
+    table: *const wasmer_table_t,
+    index: uint32_t,
+    element: *mut wasmer_funcref_t,
+) -> wasmer_result_t {
+    let table = &*(table as *const Table);
+    match table.get(index) {
+        Some(Value::FuncRef(funcref)) => {
+            *element = funcref.to_bits();
+            wasmer_result_t::WASMER_OK
+        }
+        Some(_) => {
+            update_last_error(CApiError {
+                msg: "the table does not hold functions".to_string(),
+            });
+            wasmer_result_t::WASMER_ERROR
+        }
+        None => {
+            update_last_error(CApiError {
+                msg: format!("index {} is out of the bounds of the table", index),
+            });
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Sets the element at the given index of a Table to a reference returned by
+/// `wasmer_table_get`, or empties it with 0.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_table_set(
+    table: *mut wasmer_table_t,
+    index: uint32_t,
+    element: wasmer_funcref_t,
+) -> wasmer_result_t {
+    let table = &*(table as *const Table);
+    let element = Element::FuncRef(FuncRef::from_bits(element));
+    table_set(table, index, element)
+}
+
+/// Sets the element at the given index of a Table to a host function, so
+/// that webassembly can call it indirectly.
+///
+/// The function is passed the given instance context when it's called
+/// through the table, usually the context of the instance that uses the
+/// table, which can be had from `wasmer_instance_context_get`. It may be
+/// null if the function doesn't use its context.
+///
+/// The table doesn't own the function, which can be destroyed once it's set.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_table_set_func(
+    table: *mut wasmer_table_t,
+    index: uint32_t,
+    func: *const wasmer_import_func_t,
+    ctx: *const wasmer_instance_context_t,
+) -> wasmer_result_t {
+    let table = &*(table as *const Table);
+    let element = match &*(func as *const Export) {
+        Export::Function {
+            func,
+            ctx: func_ctx,
+            signature,
+        } => {
+            let env = match func_ctx {
+                Context::InternalWithEnv(env) => Some(Rc::clone(env)),
+                _ => None,
+            };
+            Anyfunc::with_ctx(func.inner(), ctx as *mut Ctx, env, Arc::clone(signature))
+        }
+        _ => {
+            update_last_error(CApiError {
+                msg: "the import is not a function".to_string(),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    table_set(table, index, Element::Anyfunc(element))
+}
+
+fn table_set(table: &Table, index: uint32_t, element: Element) -> wasmer_result_t {
+    match table.set(index, element) {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(()) => {
+            update_last_error(CApiError {
+                msg: format!(
+                    "index {} is out of the bounds of the table, or the table does not hold functions",
+                    index
+                ),
+            });
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
 /// Frees memory for the given Table
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
@@ -346,11 +589,39 @@ pub extern "C" fn wasmer_global_get(global: *mut wasmer_global_t) -> wasmer_valu
 }
 
 /// Sets the value stored by the given Global
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` if the Global is immutable, or the
+/// value is of another type. Use `wasmer_last_error_length` and
+/// `wasmer_last_error_message` to get an error message.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub extern "C" fn wasmer_global_set(global: *mut wasmer_global_t, value: wasmer_value_t) {
+pub extern "C" fn wasmer_global_set(
+    global: *mut wasmer_global_t,
+    value: wasmer_value_t,
+) -> wasmer_result_t {
     let global = unsafe { &*(global as *mut Global) };
-    global.set(value.into());
+    let descriptor = global.descriptor();
+    let value: Value = value.into();
+    if !descriptor.mutable {
+        update_last_error(CApiError {
+            msg: "cannot set an immutable global".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    if value.ty() != descriptor.ty {
+        update_last_error(CApiError {
+            msg: format!(
+                "cannot set a global of type {:?} to a value of type {:?}",
+                descriptor.ty,
+                value.ty()
+            ),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    global.set(value);
+    wasmer_result_t::WASMER_OK
 }
 
 /// Returns a descriptor (type, mutability) of the given Global
@@ -427,34 +698,44 @@ pub unsafe extern "C" fn wasmer_module_instantiate(
 ) -> wasmer_result_t {
     let imports: &[wasmer_import_t] = slice::from_raw_parts(imports, imports_len as usize);
     let mut import_object = ImportObject::new();
-    let mut namespaces = HashMap::new();
-    for import in imports {
-        let module_name = slice::from_raw_parts(
-            import.module_name.bytes,
-            import.module_name.bytes_len as usize,
-        );
-        let module_name = if let Ok(s) = std::str::from_utf8(module_name) {
-            s
-        } else {
-            update_last_error(CApiError {
-                msg: "error converting module name to string".to_string(),
-            });
-            return wasmer_result_t::WASMER_ERROR;
-        };
-        let import_name = slice::from_raw_parts(
-            import.import_name.bytes,
-            import.import_name.bytes_len as usize,
-        );
-        let import_name = if let Ok(s) = std::str::from_utf8(import_name) {
-            s
-        } else {
-            update_last_error(CApiError {
-                msg: "error converting import_name to string".to_string(),
-            });
-            return wasmer_result_t::WASMER_ERROR;
-        };
+    if let Err(error) = extend_import_object(&mut import_object, imports) {
+        update_last_error(error);
+        return wasmer_result_t::WASMER_ERROR;
+    }
+
+    let module = &*(module as *const Module);
+    let new_instance = if let Ok(res) = module.instantiate(&import_object) {
+        res
+    } else {
+        update_last_error(CApiError {
+            msg: "error instantiating from module".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    };
+    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
+    wasmer_result_t::WASMER_OK
+}
 
-        let namespace = namespaces.entry(module_name).or_insert_with(Namespace::new);
+/// Converts a name given by C to a string.
+unsafe fn byte_array_to_str<'a>(
+    array: &wasmer_byte_array,
+    what: &str,
+) -> Result<&'a str, CApiError> {
+    let bytes = slice::from_raw_parts(array.bytes, array.bytes_len as usize);
+    std::str::from_utf8(bytes).map_err(|_| CApiError {
+        msg: format!("error converting {} to string", what),
+    })
+}
+
+/// Adds the given imports to `import_object`, in their namespaces.
+unsafe fn extend_import_object(
+    import_object: &mut ImportObject,
+    imports: &[wasmer_import_t],
+) -> Result<(), CApiError> {
+    let mut exports = Vec::with_capacity(imports.len());
+    for import in imports {
+        let module_name = byte_array_to_str(&import.module_name, "module name")?;
+        let import_name = byte_array_to_str(&import.import_name, "import_name")?;
 
         let export = match import.tag {
             wasmer_import_export_kind::WASM_MEMORY => {
@@ -474,77 +755,401 @@ pub unsafe extern "C" fn wasmer_module_instantiate(
                 Export::Table((&*table).clone())
             }
         };
-        namespace.insert(import_name, export);
+        exports.push((module_name.to_string(), import_name.to_string(), export));
     }
-    for (module_name, namespace) in namespaces.into_iter() {
-        import_object.register(module_name, namespace);
+    import_object.extend(exports);
+    Ok(())
+}
+
+/// Creates a new, empty import object.
+///
+/// Import objects hold imports by namespace, and can be built up from
+/// single imports, from the exports of instances and from other import
+/// objects, before instantiating modules with `wasmer_module_import_instantiate`.
+///
+/// The caller owns the object and should call `wasmer_import_object_destroy` to free it.
+#[no_mangle]
+pub extern "C" fn wasmer_import_object_new() -> *mut wasmer_import_object_t {
+    Box::into_raw(Box::new(ImportObject::new())) as *mut wasmer_import_object_t
+}
+
+/// Frees memory for the given import object.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_import_object_destroy(import_object: *mut wasmer_import_object_t) {
+    if !import_object.is_null() {
+        unsafe { Box::from_raw(import_object as *mut ImportObject) };
     }
+}
 
-    let module = &*(module as *const Module);
-    let new_instance = if let Ok(res) = module.instantiate(&import_object) {
-        res
-    } else {
+/// Adds the given imports to an import object, replacing those with
+/// the same module and import names.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_import_object_extend(
+    import_object: *mut wasmer_import_object_t,
+    imports: *const wasmer_import_t,
+    imports_len: c_int,
+) -> wasmer_result_t {
+    if import_object.is_null() || (imports.is_null() && imports_len > 0) {
         update_last_error(CApiError {
-            msg: "error instantiating from module".to_string(),
+            msg: "import object or imports ptr is null".to_string(),
         });
         return wasmer_result_t::WASMER_ERROR;
+    }
+    let import_object = &mut *(import_object as *mut ImportObject);
+    let imports: &[wasmer_import_t] = if imports_len > 0 {
+        slice::from_raw_parts(imports, imports_len as usize)
+    } else {
+        &[]
     };
-    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
-    wasmer_result_t::WASMER_OK
+    match extend_import_object(import_object, imports) {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
 }
 
-/// Gets export descriptors for the given module
+/// Registers the exports of an instance as the namespace `namespace_name`
+/// of an import object, replacing any namespace of that name.
 ///
-/// The caller owns the object and should call `wasmer_export_descriptors_destroy` to free it.
+/// The instance must outlive the import object and the instances
+/// created with it.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub unsafe extern "C" fn wasmer_export_descriptors(
-    module: *const wasmer_module_t,
-    export_descriptors: *mut *mut wasmer_export_descriptors_t,
-) {
-    let module = &*(module as *const Module);
+pub unsafe extern "C" fn wasmer_import_object_register_instance(
+    import_object: *mut wasmer_import_object_t,
+    namespace_name: wasmer_byte_array,
+    instance: *mut wasmer_instance_t,
+) -> wasmer_result_t {
+    if import_object.is_null() || instance.is_null() {
+        update_last_error(CApiError {
+            msg: "import object or instance ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let namespace_name = match byte_array_to_str(&namespace_name, "namespace name") {
+        Ok(namespace_name) => namespace_name,
+        Err(error) => {
+            update_last_error(error);
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    let import_object = &mut *(import_object as *mut ImportObject);
+    let instance = &mut *(instance as *mut Instance);
 
-    let named_export_descriptors: Box<NamedExportDescriptors> = Box::new(NamedExportDescriptors(
-        module.info().exports.iter().map(|e| e.into()).collect(),
-    ));
-    *export_descriptors =
-        Box::into_raw(named_export_descriptors) as *mut wasmer_export_descriptors_t;
+    let mut namespace = Namespace::new();
+    for (name, export) in instance.exports() {
+        namespace.insert(name, export);
+    }
+    import_object.register(namespace_name, namespace);
+    wasmer_result_t::WASMER_OK
 }
 
-pub struct NamedExportDescriptors(Vec<NamedExportDescriptor>);
-
-/// Frees the memory for the given export descriptors
+/// Merges `other` into an import object. Namespaces that both have are
+/// combined, with the imports of `other` taking precedence.
+///
+/// This takes ownership of `other`, which must not be used or destroyed
+/// afterwards.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub extern "C" fn wasmer_export_descriptors_destroy(
-    export_descriptors: *mut wasmer_export_descriptors_t,
+pub unsafe extern "C" fn wasmer_import_object_merge(
+    import_object: *mut wasmer_import_object_t,
+    other: *mut wasmer_import_object_t,
 ) {
-    if !export_descriptors.is_null() {
-        unsafe { Box::from_raw(export_descriptors as *mut NamedExportDescriptors) };
+    if import_object.is_null() || other.is_null() {
+        return;
     }
+    let import_object = &mut *(import_object as *mut ImportObject);
+    let other = Box::from_raw(other as *mut ImportObject);
+    import_object.merge(*other);
 }
 
-/// Gets the length of the export descriptors
+/// Returns true if an import object provides the import `import_name`
+/// of the namespace `module_name`.
+///
+/// Together with `wasmer_import_descriptors`, this tells which imports
+/// of a module an import object is missing.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub unsafe extern "C" fn wasmer_export_descriptors_len(
-    exports: *mut wasmer_export_descriptors_t,
-) -> c_int {
-    if exports.is_null() {
-        return 0;
+pub unsafe extern "C" fn wasmer_import_object_contains(
+    import_object: *const wasmer_import_object_t,
+    module_name: wasmer_byte_array,
+    import_name: wasmer_byte_array,
+) -> bool {
+    if import_object.is_null() {
+        return false;
+    }
+    let import_object = &*(import_object as *const ImportObject);
+    match (
+        byte_array_to_str(&module_name, "module name"),
+        byte_array_to_str(&import_name, "import_name"),
+    ) {
+        (Ok(module_name), Ok(import_name)) => {
+            import_object.get_export(module_name, import_name).is_some()
+        }
+        _ => false,
     }
-    (*(exports as *mut NamedExportDescriptors)).0.len() as c_int
 }
 
-/// Gets export descriptor by index
+/// Creates a new Instance from the given module and import object.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub unsafe extern "C" fn wasmer_export_descriptors_get(
-    export_descriptors: *mut wasmer_export_descriptors_t,
-    idx: c_int,
-) -> *mut wasmer_export_descriptor_t {
-    if export_descriptors.is_null() {
-        return ptr::null_mut();
+pub unsafe extern "C" fn wasmer_module_import_instantiate(
+    module: *const wasmer_module_t,
+    instance: *mut *mut wasmer_instance_t,
+    import_object: *const wasmer_import_object_t,
+) -> wasmer_result_t {
+    if module.is_null() || import_object.is_null() {
+        update_last_error(CApiError {
+            msg: "module or import object ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let module = &*(module as *const Module);
+    let import_object = &*(import_object as *const ImportObject);
+    let new_instance = match module.instantiate(import_object) {
+        Ok(instance) => instance,
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("error instantiating from module: {:?}", error),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Resolves a function import that an import object doesn't provide,
+/// for `wasmer_module_import_instantiate_with_resolver`. It's called with
+/// `data` and the descriptor of the import, and sets `func` to a func
+/// with the signature of the import.
+///
+/// Returns `wasmer_result_t::WASMER_OK` if it set `func`, and
+/// `wasmer_result_t::WASMER_ERROR` if it can't resolve the import.
+#[allow(non_camel_case_types)]
+pub type wasmer_import_resolver_t = extern "C" fn(
+    data: *mut c_void,
+    import_descriptor: *const wasmer_import_descriptor_t,
+    func: *mut *mut wasmer_import_func_t,
+) -> wasmer_result_t;
+
+/// Creates a new Instance from the given module and import object, calling
+/// `resolver` with `data` for each function that the module imports and
+/// the import object doesn't provide, at the time that it's instantiated.
+/// This lets bindings provide host functions as modules ask for them.
+///
+/// The instance takes ownership of the funcs that the resolver returns.
+/// Imports of other kinds must be in the import object.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure, or if the resolver
+/// can't resolve an import. Use `wasmer_last_error_length` and
+/// `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_module_import_instantiate_with_resolver(
+    module: *const wasmer_module_t,
+    instance: *mut *mut wasmer_instance_t,
+    import_object: *const wasmer_import_object_t,
+    resolver: wasmer_import_resolver_t,
+    data: *mut c_void,
+) -> wasmer_result_t {
+    if module.is_null() || import_object.is_null() {
+        update_last_error(CApiError {
+            msg: "module or import object ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let module = &*(module as *const Module);
+    let import_object = &*(import_object as *const ImportObject);
+
+    let mut resolved: HashMap<String, Namespace> = HashMap::new();
+    for (index, import_name) in &module.info().imported_functions {
+        let descriptor = NamedImportDescriptor::func(module.info(), index, import_name);
+        if import_object
+            .get_export(&descriptor.module, &descriptor.name)
+            .is_some()
+        {
+            continue;
+        }
+        let mut func: *mut wasmer_import_func_t = ptr::null_mut();
+        let result = resolver(
+            data,
+            &descriptor as *const NamedImportDescriptor as *const wasmer_import_descriptor_t,
+            &mut func,
+        );
+        let resolved_func = match result {
+            wasmer_result_t::WASMER_OK => !func.is_null(),
+            wasmer_result_t::WASMER_ERROR => false,
+        };
+        if !resolved_func {
+            update_last_error(CApiError {
+                msg: format!(
+                    "the resolver couldn't resolve the import {}.{}",
+                    descriptor.module, descriptor.name
+                ),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+        let export = *Box::from_raw(func as *mut Export);
+        resolved
+            .entry(descriptor.module)
+            .or_insert_with(Namespace::new)
+            .insert(descriptor.name, export);
+    }
+
+    // The resolved funcs, over the imports of the import object.
+    let base = import_object.clone_ref();
+    let mut layered = ImportObject::new();
+    layered.set_fallback(move |namespace, name| base.get_export(namespace, name));
+    for (namespace_name, namespace) in resolved {
+        layered.register(namespace_name, namespace);
+    }
+
+    let new_instance = match module.instantiate(&layered) {
+        Ok(instance) => instance,
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("error instantiating from module: {:?}", error),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    *instance = Box::into_raw(Box::new(new_instance)) as *mut wasmer_instance_t;
+    wasmer_result_t::WASMER_OK
+}
+
+/// Sets `bytes` and `bytes_len` to the contents of the first custom
+/// section of the given module named `name`.
+///
+/// The bytes belong to the module, and are valid as long as it is.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure, or if the module
+/// has no such section. Use `wasmer_last_error_length` and
+/// `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_module_custom_section(
+    module: *const wasmer_module_t,
+    name: *const c_char,
+    bytes: *mut *const uint8_t,
+    bytes_len: *mut uint32_t,
+) -> wasmer_result_t {
+    if module.is_null() || name.is_null() {
+        update_last_error(CApiError {
+            msg: "module or name ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
+    }
+    let module = &*(module as *const Module);
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => {
+            update_last_error(CApiError {
+                msg: "error converting name to string".to_string(),
+            });
+            return wasmer_result_t::WASMER_ERROR;
+        }
+    };
+    match module
+        .custom_sections(name)
+        .and_then(|sections| sections.first())
+    {
+        Some(section) => {
+            *bytes = section.as_ptr();
+            *bytes_len = section.len() as uint32_t;
+            wasmer_result_t::WASMER_OK
+        }
+        None => {
+            update_last_error(CApiError {
+                msg: format!("module has no custom section named {}", name),
+            });
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Gets export descriptors for the given module
+///
+/// The caller owns the object and should call `wasmer_export_descriptors_destroy` to free it.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_export_descriptors(
+    module: *const wasmer_module_t,
+    export_descriptors: *mut *mut wasmer_export_descriptors_t,
+) {
+    let module = &*(module as *const Module);
+
+    let named_export_descriptors: Box<NamedExportDescriptors> = Box::new(NamedExportDescriptors(
+        module
+            .info()
+            .exports
+            .iter()
+            .map(|(name, export_index)| {
+                NamedExportDescriptor::new(module.info(), name, export_index)
+            })
+            .collect(),
+    ));
+    *export_descriptors =
+        Box::into_raw(named_export_descriptors) as *mut wasmer_export_descriptors_t;
+}
+
+pub struct NamedExportDescriptors(Vec<NamedExportDescriptor>);
+
+/// Frees the memory for the given export descriptors
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_export_descriptors_destroy(
+    export_descriptors: *mut wasmer_export_descriptors_t,
+) {
+    if !export_descriptors.is_null() {
+        unsafe { Box::from_raw(export_descriptors as *mut NamedExportDescriptors) };
+    }
+}
+
+/// Gets the length of the export descriptors
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_export_descriptors_len(
+    exports: *mut wasmer_export_descriptors_t,
+) -> c_int {
+    if exports.is_null() {
+        return 0;
+    }
+    (*(exports as *mut NamedExportDescriptors)).0.len() as c_int
+}
+
+/// Gets export descriptor by index
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_export_descriptors_get(
+    export_descriptors: *mut wasmer_export_descriptors_t,
+    idx: c_int,
+) -> *mut wasmer_export_descriptor_t {
+    if export_descriptors.is_null() {
+        return ptr::null_mut();
     }
     let named_export_descriptors = &mut *(export_descriptors as *mut NamedExportDescriptors);
     &mut (*named_export_descriptors).0[idx as usize] as *mut NamedExportDescriptor
@@ -574,7 +1179,124 @@ pub unsafe extern "C" fn wasmer_export_descriptor_kind(
     named_export_descriptor.kind.clone()
 }
 
-/// Serialize the given Module.
+/// Sets the result parameter to the arity of the parameters of the function
+/// that the export descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_descriptor_func_params_arity(
+    export_descriptor: *const wasmer_export_descriptor_t,
+    result: *mut uint32_t,
+) -> wasmer_result_t {
+    let named_export_descriptor = &*(export_descriptor as *const NamedExportDescriptor);
+    if let Some(ref signature) = named_export_descriptor.signature {
+        *result = signature.params().len() as uint32_t;
+        wasmer_result_t::WASMER_OK
+    } else {
+        update_last_error(CApiError {
+            msg:
+                "export descriptor is not a function in wasmer_export_descriptor_func_params_arity"
+                    .to_string(),
+        });
+        wasmer_result_t::WASMER_ERROR
+    }
+}
+
+/// Sets the params buffer to the parameters types of the function that the
+/// export descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_descriptor_func_params(
+    export_descriptor: *const wasmer_export_descriptor_t,
+    params: *mut wasmer_value_tag,
+    params_len: c_int,
+) -> wasmer_result_t {
+    let named_export_descriptor = &*(export_descriptor as *const NamedExportDescriptor);
+    let result = match named_export_descriptor.signature {
+        Some(ref signature) => copy_value_tags(signature.params(), params, params_len),
+        None => Err(CApiError {
+            msg: "export descriptor is not a function in wasmer_export_descriptor_func_params"
+                .to_string(),
+        }),
+    };
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Sets the result parameter to the arity of the results of the function
+/// that the export descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_descriptor_func_returns_arity(
+    export_descriptor: *const wasmer_export_descriptor_t,
+    result: *mut uint32_t,
+) -> wasmer_result_t {
+    let named_export_descriptor = &*(export_descriptor as *const NamedExportDescriptor);
+    if let Some(ref signature) = named_export_descriptor.signature {
+        *result = signature.returns().len() as uint32_t;
+        wasmer_result_t::WASMER_OK
+    } else {
+        update_last_error(CApiError {
+            msg:
+                "export descriptor is not a function in wasmer_export_descriptor_func_returns_arity"
+                    .to_string(),
+        });
+        wasmer_result_t::WASMER_ERROR
+    }
+}
+
+/// Sets the returns buffer to the result types of the function that the
+/// export descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_descriptor_func_returns(
+    export_descriptor: *const wasmer_export_descriptor_t,
+    returns: *mut wasmer_value_tag,
+    returns_len: c_int,
+) -> wasmer_result_t {
+    let named_export_descriptor = &*(export_descriptor as *const NamedExportDescriptor);
+    let result = match named_export_descriptor.signature {
+        Some(ref signature) => copy_value_tags(signature.returns(), returns, returns_len),
+        None => Err(CApiError {
+            msg: "export descriptor is not a function in wasmer_export_descriptor_func_returns"
+                .to_string(),
+        }),
+    };
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Serialize the given Module, to be deserialized with `wasmer_module_deserialize`
+/// instead of compiling it again.
 ///
 /// The caller owns the object and should call `wasmer_serialized_module_destroy` to free it.
 ///
@@ -597,16 +1319,16 @@ pub unsafe extern "C" fn wasmer_module_serialize(
 
                 wasmer_result_t::WASMER_OK
             }
-            Err(_) => {
+            Err(error) => {
                 update_last_error(CApiError {
-                    msg: "Failed to serialize the module artifact".to_string(),
+                    msg: format!("Failed to serialize the module artifact: {:?}", error),
                 });
                 wasmer_result_t::WASMER_ERROR
             }
         },
-        Err(_) => {
+        Err(error) => {
             update_last_error(CApiError {
-                msg: "Failed to serialize the module".to_string(),
+                msg: format!("Failed to serialize the module: {:?}", error),
             });
             wasmer_result_t::WASMER_ERROR
         }
@@ -619,7 +1341,7 @@ pub unsafe extern "C" fn wasmer_module_serialize(
 pub unsafe extern "C" fn wasmer_serialized_module_bytes(
     serialized_module: *const wasmer_serialized_module_t,
 ) -> wasmer_byte_array {
-    let serialized_module = &*(serialized_module as *const &[u8]);
+    let serialized_module = &*(serialized_module as *const Vec<u8>);
 
     wasmer_byte_array {
         bytes: serialized_module.as_ptr(),
@@ -627,7 +1349,8 @@ pub unsafe extern "C" fn wasmer_serialized_module_bytes(
     }
 }
 
-/// Transform a sequence of bytes into a serialized module.
+/// Transform a sequence of bytes into a serialized module. The bytes are
+/// copied, so they can be freed afterwards.
 ///
 /// The caller owns the object and should call `wasmer_serialized_module_destroy` to free it.
 ///
@@ -649,10 +1372,11 @@ pub unsafe extern "C" fn wasmer_serialized_module_from_bytes(
         return wasmer_result_t::WASMER_ERROR;
     }
 
-    let serialized_module_bytes: &[u8] = slice::from_raw_parts(
+    let serialized_module_bytes: Vec<u8> = slice::from_raw_parts(
         serialized_module_bytes,
         serialized_module_bytes_length as usize,
-    );
+    )
+    .to_vec();
 
     *serialized_module = Box::into_raw(Box::new(serialized_module_bytes)) as _;
     wasmer_result_t::WASMER_OK
@@ -677,7 +1401,7 @@ pub unsafe extern "C" fn wasmer_module_deserialize(
         return wasmer_result_t::WASMER_ERROR;
     }
 
-    let serialized_module: &[u8] = &*(serialized_module as *const &[u8]);
+    let serialized_module = &*(serialized_module as *const Vec<u8>);
 
     match Artifact::deserialize(serialized_module) {
         Ok(artifact) => match load_cache_with(artifact, default_compiler()) {
@@ -685,22 +1409,63 @@ pub unsafe extern "C" fn wasmer_module_deserialize(
                 *module = Box::into_raw(Box::new(deserialized_module)) as _;
                 wasmer_result_t::WASMER_OK
             }
-            Err(_) => {
+            Err(error) => {
                 update_last_error(CApiError {
-                    msg: "Failed to compile the serialized module".to_string(),
+                    msg: format!("Failed to load the serialized module: {:?}", error),
                 });
                 wasmer_result_t::WASMER_ERROR
             }
         },
-        Err(_) => {
+        Err(error) => {
             update_last_error(CApiError {
-                msg: "Failed to deserialize the module".to_string(),
+                msg: format!("Failed to deserialize the module: {:?}", error),
             });
             wasmer_result_t::WASMER_ERROR
         }
     }
 }
 
+/// Checks whether the given serialized module can be deserialized by this
+/// library on this machine: that it's intact, and was serialized by the same
+/// versions of Wasmer and of its backend, for a machine that can run it. A
+/// cached module that fails the check should be compiled again.
+///
+/// Returns false if it can't, and sets the last error to the reason. Use
+/// `wasmer_last_error_length` and `wasmer_last_error_message` to get it.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_serialized_module_validate(
+    serialized_module: *const wasmer_serialized_module_t,
+) -> bool {
+    if serialized_module.is_null() {
+        update_last_error(CApiError {
+            msg: "`serialized_module` pointer is null".to_string(),
+        });
+        return false;
+    }
+
+    let serialized_module = &*(serialized_module as *const Vec<u8>);
+    let error = match Artifact::deserialize(serialized_module) {
+        Ok(ref artifact) if !artifact.target().runs_on_host() => {
+            CacheError::IncompatibleTarget(artifact.target().clone())
+        }
+        Ok(ref artifact)
+            if artifact.backend_version() != default_compiler().capabilities().version =>
+        {
+            CacheError::IncompatibleVersion {
+                wasmer: wasmer_runtime_core::VERSION.to_string(),
+                backend: artifact.backend_version().to_string(),
+            }
+        }
+        Ok(_) => return true,
+        Err(error) => error,
+    };
+    update_last_error(CApiError {
+        msg: format!("The serialized module can't be deserialized: {:?}", error),
+    });
+    false
+}
+
 /// Frees memory for the given serialized Module.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
@@ -708,7 +1473,7 @@ pub extern "C" fn wasmer_serialized_module_destroy(
     serialized_module: *mut wasmer_serialized_module_t,
 ) {
     if !serialized_module.is_null() {
-        unsafe { Box::from_raw(serialized_module as *mut &[u8]) };
+        unsafe { Box::from_raw(serialized_module as *mut Vec<u8>) };
     }
 }
 
@@ -737,21 +1502,12 @@ pub unsafe extern "C" fn wasmer_import_descriptors(
         + module.info().imported_memories.len();
     let mut descriptors: Vec<NamedImportDescriptor> = Vec::with_capacity(total_imports);
 
-    for (
-        _index,
-        ImportName {
-            namespace_index,
-            name_index,
-        },
-    ) in &module.info().imported_functions
-    {
-        let namespace = module.info().namespace_table.get(*namespace_index);
-        let name = module.info().name_table.get(*name_index);
-        descriptors.push(NamedImportDescriptor {
-            module: namespace.to_string(),
-            name: name.to_string(),
-            kind: wasmer_import_export_kind::WASM_FUNCTION,
-        });
+    for (index, import_name) in &module.info().imported_functions {
+        descriptors.push(NamedImportDescriptor::func(
+            module.info(),
+            index,
+            import_name,
+        ));
     }
 
     for (
@@ -771,6 +1527,7 @@ pub unsafe extern "C" fn wasmer_import_descriptors(
             module: namespace.to_string(),
             name: name.to_string(),
             kind: wasmer_import_export_kind::WASM_TABLE,
+            signature: None,
         });
     }
 
@@ -791,6 +1548,7 @@ pub unsafe extern "C" fn wasmer_import_descriptors(
             module: namespace.to_string(),
             name: name.to_string(),
             kind: wasmer_import_export_kind::WASM_GLOBAL,
+            signature: None,
         });
     }
 
@@ -811,6 +1569,7 @@ pub unsafe extern "C" fn wasmer_import_descriptors(
             module: namespace.to_string(),
             name: name.to_string(),
             kind: wasmer_import_export_kind::WASM_MEMORY,
+            signature: None,
         });
     }
 
@@ -864,9 +1623,9 @@ pub unsafe extern "C" fn wasmer_import_descriptors_get(
 #[no_mangle]
 #[allow(clippy::cast_ptr_alignment)]
 pub unsafe extern "C" fn wasmer_import_descriptor_name(
-    import_descriptor: *mut wasmer_import_descriptor_t,
+    import_descriptor: *const wasmer_import_descriptor_t,
 ) -> wasmer_byte_array {
-    let named_import_descriptor = &*(import_descriptor as *mut NamedImportDescriptor);
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
     wasmer_byte_array {
         bytes: named_import_descriptor.name.as_ptr(),
         bytes_len: named_import_descriptor.name.len() as u32,
@@ -877,9 +1636,9 @@ pub unsafe extern "C" fn wasmer_import_descriptor_name(
 #[no_mangle]
 #[allow(clippy::cast_ptr_alignment)]
 pub unsafe extern "C" fn wasmer_import_descriptor_module_name(
-    import_descriptor: *mut wasmer_import_descriptor_t,
+    import_descriptor: *const wasmer_import_descriptor_t,
 ) -> wasmer_byte_array {
-    let named_import_descriptor = &*(import_descriptor as *mut NamedImportDescriptor);
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
     wasmer_byte_array {
         bytes: named_import_descriptor.module.as_ptr(),
         bytes_len: named_import_descriptor.module.len() as u32,
@@ -890,86 +1649,154 @@ pub unsafe extern "C" fn wasmer_import_descriptor_module_name(
 #[no_mangle]
 #[allow(clippy::cast_ptr_alignment)]
 pub unsafe extern "C" fn wasmer_import_descriptor_kind(
-    export: *mut wasmer_import_descriptor_t,
+    export: *const wasmer_import_descriptor_t,
 ) -> wasmer_import_export_kind {
-    let named_import_descriptor = &*(export as *mut NamedImportDescriptor);
+    let named_import_descriptor = &*(export as *const NamedImportDescriptor);
     named_import_descriptor.kind.clone()
 }
 
-/// Creates a new Instance from the given wasm bytes and imports.
+/// Sets the result parameter to the arity of the parameters of the function
+/// that the import descriptor describes.
 ///
 /// Returns `wasmer_result_t::WASMER_OK` upon success.
 ///
 /// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
 /// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
 #[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_import_descriptor_func_params_arity(
+    import_descriptor: *const wasmer_import_descriptor_t,
+    result: *mut uint32_t,
+) -> wasmer_result_t {
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
+    if let Some(ref signature) = named_import_descriptor.signature {
+        *result = signature.params().len() as uint32_t;
+        wasmer_result_t::WASMER_OK
+    } else {
+        update_last_error(CApiError {
+            msg:
+                "import descriptor is not a function in wasmer_import_descriptor_func_params_arity"
+                    .to_string(),
+        });
+        wasmer_result_t::WASMER_ERROR
+    }
+}
+
+/// Sets the params buffer to the parameters types of the function that the
+/// import descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
 #[no_mangle]
-pub unsafe extern "C" fn wasmer_instantiate(
-    instance: *mut *mut wasmer_instance_t,
-    wasm_bytes: *mut uint8_t,
-    wasm_bytes_len: uint32_t,
-    imports: *mut wasmer_import_t,
-    imports_len: c_int,
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_import_descriptor_func_params(
+    import_descriptor: *const wasmer_import_descriptor_t,
+    params: *mut wasmer_value_tag,
+    params_len: c_int,
 ) -> wasmer_result_t {
-    if wasm_bytes.is_null() {
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
+    let result = match named_import_descriptor.signature {
+        Some(ref signature) => copy_value_tags(signature.params(), params, params_len),
+        None => Err(CApiError {
+            msg: "import descriptor is not a function in wasmer_import_descriptor_func_params"
+                .to_string(),
+        }),
+    };
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Sets the result parameter to the arity of the results of the function
+/// that the import descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_import_descriptor_func_returns_arity(
+    import_descriptor: *const wasmer_import_descriptor_t,
+    result: *mut uint32_t,
+) -> wasmer_result_t {
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
+    if let Some(ref signature) = named_import_descriptor.signature {
+        *result = signature.returns().len() as uint32_t;
+        wasmer_result_t::WASMER_OK
+    } else {
         update_last_error(CApiError {
-            msg: "wasm bytes ptr is null".to_string(),
+            msg:
+                "import descriptor is not a function in wasmer_import_descriptor_func_returns_arity"
+                    .to_string(),
         });
-        return wasmer_result_t::WASMER_ERROR;
+        wasmer_result_t::WASMER_ERROR
     }
-    let imports: &[wasmer_import_t] = slice::from_raw_parts(imports, imports_len as usize);
-    let mut import_object = ImportObject::new();
-    let mut namespaces = HashMap::new();
-    for import in imports {
-        let module_name = slice::from_raw_parts(
-            import.module_name.bytes,
-            import.module_name.bytes_len as usize,
-        );
-        let module_name = if let Ok(s) = std::str::from_utf8(module_name) {
-            s
-        } else {
-            update_last_error(CApiError {
-                msg: "error converting module name to string".to_string(),
-            });
-            return wasmer_result_t::WASMER_ERROR;
-        };
-        let import_name = slice::from_raw_parts(
-            import.import_name.bytes,
-            import.import_name.bytes_len as usize,
-        );
-        let import_name = if let Ok(s) = std::str::from_utf8(import_name) {
-            s
-        } else {
-            update_last_error(CApiError {
-                msg: "error converting import_name to string".to_string(),
-            });
-            return wasmer_result_t::WASMER_ERROR;
-        };
+}
 
-        let namespace = namespaces.entry(module_name).or_insert_with(Namespace::new);
+/// Sets the returns buffer to the result types of the function that the
+/// import descriptor describes.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_import_descriptor_func_returns(
+    import_descriptor: *const wasmer_import_descriptor_t,
+    returns: *mut wasmer_value_tag,
+    returns_len: c_int,
+) -> wasmer_result_t {
+    let named_import_descriptor = &*(import_descriptor as *const NamedImportDescriptor);
+    let result = match named_import_descriptor.signature {
+        Some(ref signature) => copy_value_tags(signature.returns(), returns, returns_len),
+        None => Err(CApiError {
+            msg: "import descriptor is not a function in wasmer_import_descriptor_func_returns"
+                .to_string(),
+        }),
+    };
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
 
-        let export = match import.tag {
-            wasmer_import_export_kind::WASM_MEMORY => {
-                let mem = import.value.memory as *mut Memory;
-                Export::Memory((&*mem).clone())
-            }
-            wasmer_import_export_kind::WASM_FUNCTION => {
-                let func_export = import.value.func as *mut Export;
-                (&*func_export).clone()
-            }
-            wasmer_import_export_kind::WASM_GLOBAL => {
-                let global = import.value.global as *mut Global;
-                Export::Global((&*global).clone())
-            }
-            wasmer_import_export_kind::WASM_TABLE => {
-                let table = import.value.table as *mut Table;
-                Export::Table((&*table).clone())
-            }
-        };
-        namespace.insert(import_name, export);
+/// Creates a new Instance from the given wasm bytes and imports.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instantiate(
+    instance: *mut *mut wasmer_instance_t,
+    wasm_bytes: *mut uint8_t,
+    wasm_bytes_len: uint32_t,
+    imports: *mut wasmer_import_t,
+    imports_len: c_int,
+) -> wasmer_result_t {
+    if wasm_bytes.is_null() {
+        update_last_error(CApiError {
+            msg: "wasm bytes ptr is null".to_string(),
+        });
+        return wasmer_result_t::WASMER_ERROR;
     }
-    for (module_name, namespace) in namespaces.into_iter() {
-        import_object.register(module_name, namespace);
+    let imports: &[wasmer_import_t] = slice::from_raw_parts(imports, imports_len as usize);
+    let mut import_object = ImportObject::new();
+    if let Err(error) = extend_import_object(&mut import_object, imports) {
+        update_last_error(error);
+        return wasmer_result_t::WASMER_ERROR;
     }
 
     let bytes: &[u8] = slice::from_raw_parts_mut(wasm_bytes, wasm_bytes_len as usize);
@@ -1036,8 +1863,8 @@ pub unsafe extern "C" fn wasmer_instance_call(
 
     match result {
         Ok(results_vec) => {
-            if !results_vec.is_empty() {
-                let ret = match results_vec[0] {
+            for (result, value) in results.iter_mut().zip(results_vec) {
+                *result = match value {
                     Value::I32(x) => wasmer_value_t {
                         tag: wasmer_value_tag::WASM_I32,
                         value: wasmer_value { I32: x },
@@ -1054,13 +1881,13 @@ pub unsafe extern "C" fn wasmer_instance_call(
                         tag: wasmer_value_tag::WASM_F64,
                         value: wasmer_value { F64: x },
                     },
+                    _ => panic!("not implemented"),
                 };
-                results[0] = ret;
             }
             wasmer_result_t::WASMER_OK
         }
         Err(err) => {
-            update_last_error(err);
+            update_last_call_error(err);
             wasmer_result_t::WASMER_ERROR
         }
     }
@@ -1100,6 +1927,16 @@ pub extern "C" fn wasmer_instance_context_data_set(
     instance_ref.context_mut().data = data_ptr;
 }
 
+/// Gets the context of the instance, as it's passed to imported functions.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_instance_context_get(
+    instance: *mut wasmer_instance_t,
+) -> *const wasmer_instance_context_t {
+    let instance_ref = unsafe { &*(instance as *const Instance) };
+    instance_ref.context() as *const Ctx as *const wasmer_instance_context_t
+}
+
 pub struct NamedExports(Vec<NamedExport>);
 
 /// Frees the memory for the given exports
@@ -1191,12 +2028,13 @@ pub unsafe extern "C" fn wasmer_export_func_params(
     let named_export = &*(func as *const NamedExport);
     let export = &named_export.export;
     if let Export::Function { ref signature, .. } = *export {
-        let params: &mut [wasmer_value_tag] =
-            slice::from_raw_parts_mut(params, params_len as usize);
-        for (i, item) in signature.params().iter().enumerate() {
-            params[i] = item.into();
+        match copy_value_tags(signature.params(), params, params_len) {
+            Ok(()) => wasmer_result_t::WASMER_OK,
+            Err(error) => {
+                update_last_error(error);
+                wasmer_result_t::WASMER_ERROR
+            }
         }
-        wasmer_result_t::WASMER_OK
     } else {
         update_last_error(CApiError {
             msg: "func ptr error in wasmer_export_func_params".to_string(),
@@ -1221,12 +2059,13 @@ pub unsafe extern "C" fn wasmer_export_func_returns(
     let named_export = &*(func as *const NamedExport);
     let export = &named_export.export;
     if let Export::Function { ref signature, .. } = *export {
-        let returns: &mut [wasmer_value_tag] =
-            slice::from_raw_parts_mut(returns, returns_len as usize);
-        for (i, item) in signature.returns().iter().enumerate() {
-            returns[i] = item.into();
+        match copy_value_tags(signature.returns(), returns, returns_len) {
+            Ok(()) => wasmer_result_t::WASMER_OK,
+            Err(error) => {
+                update_last_error(error);
+                wasmer_result_t::WASMER_ERROR
+            }
         }
-        wasmer_result_t::WASMER_OK
     } else {
         update_last_error(CApiError {
             msg: "func ptr error in wasmer_export_func_returns".to_string(),
@@ -1309,6 +2148,64 @@ pub unsafe extern "C" fn wasmer_import_func_new(
     Box::into_raw(export) as *mut wasmer_import_func_t
 }
 
+/// Creates new func with an environment, for host functions that keep state.
+///
+/// The function is called with a `wasmer_func_env_t` in place of the instance
+/// context, from which `wasmer_func_env_context` and `wasmer_func_env_data`
+/// get the instance context and `env`.
+///
+/// `env` is passed to `finalizer`, unless it's null, once the func and every
+/// instance and table that it's been imported into or stored in are freed.
+///
+/// The caller owns the object and should call `wasmer_import_func_destroy` to free it.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_import_func_new_with_env(
+    func: extern "C" fn(data: *mut c_void),
+    params: *const wasmer_value_tag,
+    params_len: c_int,
+    returns: *const wasmer_value_tag,
+    returns_len: c_int,
+    env: *mut c_void,
+    finalizer: Option<extern "C" fn(env: *mut c_void)>,
+) -> *mut wasmer_import_func_t {
+    unsafe extern "C" fn no_finalizer(_env: *mut c_void) {}
+
+    let params: &[wasmer_value_tag] = slice::from_raw_parts(params, params_len as usize);
+    let params: Vec<Type> = params.iter().cloned().map(|x| x.into()).collect();
+    let returns: &[wasmer_value_tag] = slice::from_raw_parts(returns, returns_len as usize);
+    let returns: Vec<Type> = returns.iter().cloned().map(|x| x.into()).collect();
+
+    let finalizer: unsafe extern "C" fn(*mut c_void) = match finalizer {
+        Some(finalizer) => finalizer,
+        None => no_finalizer,
+    };
+    let export = Box::new(Export::Function {
+        func: FuncPointer::new(func as _),
+        ctx: Context::InternalWithEnv(Rc::new(FuncEnv::from_raw(env, finalizer))),
+        signature: Arc::new(FuncSig::new(params, returns)),
+    });
+    Box::into_raw(export) as *mut wasmer_import_func_t
+}
+
+/// Gets the context of the instance that called a func with an environment.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_func_env_context(
+    func_env: *const wasmer_func_env_t,
+) -> *const wasmer_instance_context_t {
+    let func_ctx = unsafe { &*(func_env as *const FuncCtx) };
+    func_ctx.vmctx as *const wasmer_instance_context_t
+}
+
+/// Gets the `env` that a func with an environment was created with.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_func_env_data(func_env: *const wasmer_func_env_t) -> *mut c_void {
+    let func_ctx = unsafe { &*(func_env as *const FuncCtx) };
+    func_ctx.env
+}
+
 /// Sets the params buffer to the parameter types of the given wasmer_import_func_t
 ///
 /// Returns `wasmer_result_t::WASMER_OK` upon success.
@@ -1409,6 +2306,63 @@ pub unsafe extern "C" fn wasmer_export_to_func(
     export as *const wasmer_export_func_t
 }
 
+/// Gets a global pointer from an export pointer, to read or set the global
+/// that an instance exports.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_to_global(
+    export: *const wasmer_export_t,
+    global: *mut *mut wasmer_global_t,
+) -> wasmer_result_t {
+    let named_export = &*(export as *const NamedExport);
+    let export = &named_export.export;
+
+    if let Export::Global(exported_global) = export {
+        *global = exported_global as *const Global as *mut wasmer_global_t;
+        wasmer_result_t::WASMER_OK
+    } else {
+        update_last_error(CApiError {
+            msg: "cannot cast the `wasmer_export_t` pointer to a  `wasmer_global_t` \
+                  pointer because it does not represent a global export."
+                .to_string(),
+        });
+        wasmer_result_t::WASMER_ERROR
+    }
+}
+
+/// Gets a table pointer from an export pointer.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` upon failure. Use `wasmer_last_error_length`
+/// and `wasmer_last_error_message` to get an error message.
+#[no_mangle]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn wasmer_export_to_table(
+    export: *const wasmer_export_t,
+    table: *mut *mut wasmer_table_t,
+) -> wasmer_result_t {
+    let named_export = &*(export as *const NamedExport);
+    let export = &named_export.export;
+
+    if let Export::Table(exported_table) = export {
+        *table = exported_table as *const Table as *mut wasmer_table_t;
+        wasmer_result_t::WASMER_OK
+    } else {
+        update_last_error(CApiError {
+            msg: "cannot cast the `wasmer_export_t` pointer to a  `wasmer_table_t` \
+                  pointer because it does not represent a table export."
+                .to_string(),
+        });
+        wasmer_result_t::WASMER_ERROR
+    }
+}
+
 /// Gets a memory pointer from an export pointer.
 ///
 /// Returns `wasmer_result_t::WASMER_OK` upon success.
@@ -1488,8 +2442,8 @@ pub unsafe extern "C" fn wasmer_export_func_call(
     let result = instance.call(&named_export.name, &params[..]);
     match result {
         Ok(results_vec) => {
-            if !results_vec.is_empty() {
-                let ret = match results_vec[0] {
+            for (result, value) in results.iter_mut().zip(results_vec) {
+                *result = match value {
                     Value::I32(x) => wasmer_value_t {
                         tag: wasmer_value_tag::WASM_I32,
                         value: wasmer_value { I32: x },
@@ -1506,13 +2460,13 @@ pub unsafe extern "C" fn wasmer_export_func_call(
                         tag: wasmer_value_tag::WASM_F64,
                         value: wasmer_value { F64: x },
                     },
+                    _ => panic!("not implemented"),
                 };
-                results[0] = ret;
             }
             wasmer_result_t::WASMER_OK
         }
         Err(err) => {
-            update_last_error(err);
+            update_last_call_error(err);
             wasmer_result_t::WASMER_ERROR
         }
     }
@@ -1542,6 +2496,10 @@ pub extern "C" fn wasmer_instance_context_data_get(
 }
 
 /// Gets the start pointer to the bytes within a Memory
+///
+/// The bytes can be read and written directly, to exchange data with the
+/// instance, up to `wasmer_memory_data_length`. Growing the memory may move
+/// them, so the pointer must be read again after the memory has grown.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
 pub extern "C" fn wasmer_memory_data(mem: *const wasmer_memory_t) -> *mut uint8_t {
@@ -1550,12 +2508,135 @@ pub extern "C" fn wasmer_memory_data(mem: *const wasmer_memory_t) -> *mut uint8_
 }
 
 /// Gets the size in bytes of a Memory
+///
+/// A memory of the most pages that 32-bit WebAssembly allows has 4 GiB,
+/// which doesn't fit, so its length saturates at `UINT32_MAX`.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_memory_data_length(mem: *const wasmer_memory_t) -> uint32_t {
+    let memory = unsafe { &*(mem as *const Memory) };
+    let Bytes(len) = memory.size().bytes();
+    len.min(uint32_t::max_value() as usize) as uint32_t
+}
+
+/// Gets a view of the bytes of a Memory, to read and write them in place.
+///
+/// Unlike `wasmer_memory_data_length`, the length of the view doesn't
+/// saturate for memories of 4 GiB or more. The view stays valid until
+/// the memory grows, which `wasmer_memory_set_grow_callback` tells of.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_memory_view(mem: *const wasmer_memory_t) -> wasmer_memory_view_t {
+    let memory = unsafe { &*(mem as *const Memory) };
+    let view = memory.view::<u8>();
+    wasmer_memory_view_t {
+        data: view[..].as_ptr() as *mut Cell<u8> as *mut u8,
+        data_len: view.len() as uint64_t,
+    }
+}
+
+/// Sets a callback that is called with `data` whenever a Memory grows,
+/// either because of `wasmer_memory_grow` or because an instance grew
+/// it, so that views of it can be got again. It replaces the callback
+/// that was set before, if any.
+///
+/// The callback may be called from WebAssembly, in the middle of a call
+/// to an instance, and must not call it back. `data` must stay valid as
+/// long as the memory does, or until the callback is cleared.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` for shared memories, which don't
+/// support grow callbacks yet. Use `wasmer_last_error_length` and
+/// `wasmer_last_error_message` to get an error message.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_memory_set_grow_callback(
+    mem: *const wasmer_memory_t,
+    callback: wasmer_memory_grow_callback_t,
+    data: *mut c_void,
+) -> wasmer_result_t {
+    let memory = unsafe { &*(mem as *const Memory) };
+    let result = memory.set_grow_callback(move |Pages(old_pages), Pages(new_pages)| {
+        callback(data, old_pages, new_pages)
+    });
+    match result {
+        Ok(()) => wasmer_result_t::WASMER_OK,
+        Err(error) => {
+            update_last_error(error);
+            wasmer_result_t::WASMER_ERROR
+        }
+    }
+}
+
+/// Clears the callback set with `wasmer_memory_set_grow_callback`.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_memory_clear_grow_callback(mem: *const wasmer_memory_t) {
+    let memory = unsafe { &*(mem as *const Memory) };
+    memory.clear_grow_callback();
+}
+
+/// Gets a handle that interrupts the code running in the given instance.
+/// Unlike the instance, it can be used from any thread, such as a
+/// watchdog that stops calls that run for too long.
+///
+/// The caller owns the object and should call `wasmer_interrupt_handle_destroy` to free it.
 #[allow(clippy::cast_ptr_alignment)]
 #[no_mangle]
-pub extern "C" fn wasmer_memory_data_length(mem: *mut wasmer_memory_t) -> uint32_t {
-    let memory = mem as *mut Memory;
-    let Bytes(len) = unsafe { (*memory).size().bytes() };
-    len as uint32_t
+pub unsafe extern "C" fn wasmer_instance_interrupt_handle(
+    instance: *const wasmer_instance_t,
+) -> *mut wasmer_interrupt_handle_t {
+    let instance = &*(instance as *const Instance);
+    Box::into_raw(Box::new(instance.interrupt_handle())) as *mut wasmer_interrupt_handle_t
+}
+
+/// Makes the code running in the instance of the given handle trap at the
+/// start of its next function call or loop iteration. The call into the
+/// instance fails, and `wasmer_last_trap_code` returns
+/// `WASMER_TRAP_INTERRUPTED`.
+///
+/// The interrupt is cleared once the call into the instance returns or
+/// fails. If no code is running, it's the next call that is interrupted.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_interrupt(handle: *const wasmer_interrupt_handle_t) {
+    let handle = &*(handle as *const InterruptHandle);
+    handle.interrupt();
+}
+
+/// Frees memory for the given interrupt handle
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub extern "C" fn wasmer_interrupt_handle_destroy(handle: *mut wasmer_interrupt_handle_t) {
+    if !handle.is_null() {
+        unsafe { Box::from_raw(handle as *mut InterruptHandle) };
+    }
+}
+
+/// Makes the code running in the given instance trap once the epoch has
+/// been incremented `ticks` times from now, with `wasmer_epoch_increment`.
+/// The call into the instance fails, and `wasmer_last_trap_code` returns
+/// `WASMER_TRAP_DEADLINE_EXCEEDED`.
+///
+/// The deadline stays in place until it's set again, so calls made after
+/// it has been reached trap as soon as they start.
+#[allow(clippy::cast_ptr_alignment)]
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_instance_set_epoch_deadline(
+    instance: *mut wasmer_instance_t,
+    ticks: uint64_t,
+) {
+    let instance = &mut *(instance as *mut Instance);
+    instance.set_epoch_deadline(ticks);
+}
+
+/// Advances the epoch, which is shared by all instances, by one. It's
+/// typically called from a timer thread, to bound the time that calls
+/// take with `wasmer_instance_set_epoch_deadline`.
+#[no_mangle]
+pub extern "C" fn wasmer_epoch_increment() {
+    epoch::increment();
 }
 
 /// Frees memory for the given Instance
@@ -1612,6 +2693,7 @@ impl From<Value> for wasmer_value_t {
                 tag: wasmer_value_tag::WASM_F64,
                 value: wasmer_value { F64: x },
             },
+            _ => panic!("not implemented"),
         }
     }
 }
@@ -1647,25 +2729,116 @@ impl From<&wasmer_runtime::wasm::Type> for wasmer_value_tag {
             Type::I64 => wasmer_value_tag::WASM_I64,
             Type::F32 => wasmer_value_tag::WASM_F32,
             Type::F64 => wasmer_value_tag::WASM_F64,
+            _ => panic!("not implemented"),
         }
     }
 }
 
-impl From<(&std::string::String, &ExportIndex)> for NamedExportDescriptor {
-    fn from((name, export_index): (&String, &ExportIndex)) -> Self {
+impl NamedExportDescriptor {
+    fn new(info: &ModuleInfo, name: &str, export_index: &ExportIndex) -> Self {
         let kind = match *export_index {
             ExportIndex::Memory(_) => wasmer_import_export_kind::WASM_MEMORY,
             ExportIndex::Global(_) => wasmer_import_export_kind::WASM_GLOBAL,
             ExportIndex::Table(_) => wasmer_import_export_kind::WASM_TABLE,
             ExportIndex::Func(_) => wasmer_import_export_kind::WASM_FUNCTION,
         };
+        let signature = match *export_index {
+            ExportIndex::Func(func_index) => {
+                Some(info.signatures[info.func_assoc[func_index]].clone())
+            }
+            _ => None,
+        };
         NamedExportDescriptor {
-            name: name.clone(),
+            name: name.to_string(),
             kind,
+            signature,
+        }
+    }
+}
+
+impl NamedImportDescriptor {
+    fn func(info: &ModuleInfo, index: ImportedFuncIndex, import_name: &ImportName) -> Self {
+        let sig_index = info.func_assoc[index.convert_up(info)];
+        NamedImportDescriptor {
+            module: info
+                .namespace_table
+                .get(import_name.namespace_index)
+                .to_string(),
+            name: info.name_table.get(import_name.name_index).to_string(),
+            kind: wasmer_import_export_kind::WASM_FUNCTION,
+            signature: Some(info.signatures[sig_index].clone()),
         }
     }
 }
 
+/// Copies `types` into the C buffer `buffer` of `buffer_len` value tags.
+unsafe fn copy_value_tags(
+    types: &[Type],
+    buffer: *mut wasmer_value_tag,
+    buffer_len: c_int,
+) -> Result<(), CApiError> {
+    if buffer_len < 0 || (buffer_len as usize) < types.len() {
+        return Err(CApiError {
+            msg: format!(
+                "buffer of length {} is too small for {} types",
+                buffer_len,
+                types.len()
+            ),
+        });
+    }
+    if !types.is_empty() {
+        let buffer: &mut [wasmer_value_tag] = slice::from_raw_parts_mut(buffer, types.len());
+        for (slot, ty) in buffer.iter_mut().zip(types) {
+            *slot = ty.into();
+        }
+    }
+    Ok(())
+}
+
+// Versions
+
+/// The version of the ABI of this library: the signatures of its
+/// functions and the layouts of its types. It changes whenever they
+/// change in a way that breaks code compiled against an older header,
+/// but not when functions or types are added.
+///
+/// Code that loads the library dynamically should check that
+/// `wasmer_abi_version()` returns the `WASMER_ABI_VERSION` of the header
+/// that it was compiled with before using it.
+pub const WASMER_ABI_VERSION: uint32_t = 1;
+
+/// Gets the version of the ABI of this library, to compare with the
+/// `WASMER_ABI_VERSION` of a header.
+#[no_mangle]
+pub extern "C" fn wasmer_abi_version() -> uint32_t {
+    WASMER_ABI_VERSION
+}
+
+/// Gets the version of this library, such as `0.2.1`, as a
+/// NUL-terminated string that lives as long as the library is loaded.
+#[no_mangle]
+pub extern "C" fn wasmer_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Gets the major version of this library.
+#[no_mangle]
+pub extern "C" fn wasmer_version_major() -> uint8_t {
+    env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0)
+}
+
+/// Gets the minor version of this library.
+#[no_mangle]
+pub extern "C" fn wasmer_version_minor() -> uint8_t {
+    env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0)
+}
+
+/// Gets the patch version of this library.
+#[no_mangle]
+pub extern "C" fn wasmer_version_patch() -> uint8_t {
+    env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0)
+}
+
 // Error reporting
 
 thread_local! {
@@ -1678,6 +2851,214 @@ fn update_last_error<E: Error + 'static>(err: E) {
     });
 }
 
+/// The trap of the most recent call that failed, if it trapped.
+struct LastTrap {
+    code: wasmer_trap_code_t,
+    frames: Vec<WasmFrame>,
+    /// The names of the frames, as C strings.
+    func_names: Vec<Option<CString>>,
+}
+
+thread_local! {
+    static LAST_TRAP: RefCell<Option<LastTrap>> = RefCell::new(None);
+}
+
+/// Report the error of a call, keeping the details of its trap, if it
+/// trapped.
+fn update_last_call_error(err: CallError) {
+    let trap = match err {
+        CallError::Runtime(ref err) => Some(LastTrap {
+            code: err.code().into(),
+            frames: match err {
+                RuntimeError::Trap { backtrace, .. } => backtrace.clone(),
+                _ => vec![],
+            },
+            func_names: vec![],
+        }),
+        CallError::Resolve(_) => None,
+    };
+    let trap = trap.map(|mut trap| {
+        trap.func_names = trap
+            .frames
+            .iter()
+            .map(|frame| {
+                frame
+                    .func_name
+                    .as_ref()
+                    .and_then(|name| CString::new(name.as_str()).ok())
+            })
+            .collect();
+        trap
+    });
+    LAST_TRAP.with(|last_trap| *last_trap.borrow_mut() = trap);
+    update_last_error(err);
+}
+
+/// The reason that the most recent call trapped. The values match the
+/// error codes of Wasmer.
+#[allow(non_camel_case_types)]
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum wasmer_trap_code_t {
+    /// The call didn't trap, or failed without running.
+    WASMER_TRAP_NONE = 0,
+    WASMER_TRAP_UNREACHABLE = 400,
+    WASMER_TRAP_MEMORY_OUT_OF_BOUNDS = 401,
+    WASMER_TRAP_TABLE_OUT_OF_BOUNDS = 402,
+    WASMER_TRAP_INDIRECT_CALL_TO_NULL = 403,
+    WASMER_TRAP_BAD_SIGNATURE = 404,
+    WASMER_TRAP_INTEGER_OVERFLOW = 405,
+    WASMER_TRAP_INTEGER_DIVISION_BY_ZERO = 406,
+    WASMER_TRAP_BAD_CONVERSION_TO_INTEGER = 407,
+    WASMER_TRAP_ILLEGAL_ARITHMETIC = 408,
+    WASMER_TRAP_STACK_OVERFLOW = 409,
+    WASMER_TRAP_UNKNOWN = 410,
+    /// The module threw an exception that wasn't caught.
+    WASMER_TRAP_EXCEPTION = 420,
+    /// A host function trapped.
+    WASMER_TRAP_HOST = 421,
+    WASMER_TRAP_INTERRUPTED = 422,
+    WASMER_TRAP_DEADLINE_EXCEEDED = 423,
+    WASMER_TRAP_OUT_OF_GAS = 424,
+}
+
+impl From<ErrorCode> for wasmer_trap_code_t {
+    fn from(code: ErrorCode) -> Self {
+        use wasmer_trap_code_t::*;
+        match code {
+            ErrorCode::TrapUnreachable => WASMER_TRAP_UNREACHABLE,
+            ErrorCode::TrapMemoryOutOfBounds => WASMER_TRAP_MEMORY_OUT_OF_BOUNDS,
+            ErrorCode::TrapTableOutOfBounds => WASMER_TRAP_TABLE_OUT_OF_BOUNDS,
+            ErrorCode::TrapIndirectCallToNull => WASMER_TRAP_INDIRECT_CALL_TO_NULL,
+            ErrorCode::TrapBadSignature => WASMER_TRAP_BAD_SIGNATURE,
+            ErrorCode::TrapIntegerOverflow => WASMER_TRAP_INTEGER_OVERFLOW,
+            ErrorCode::TrapIntegerDivisionByZero => WASMER_TRAP_INTEGER_DIVISION_BY_ZERO,
+            ErrorCode::TrapBadConversionToInteger => WASMER_TRAP_BAD_CONVERSION_TO_INTEGER,
+            ErrorCode::TrapIllegalArithmetic => WASMER_TRAP_ILLEGAL_ARITHMETIC,
+            ErrorCode::TrapStackOverflow => WASMER_TRAP_STACK_OVERFLOW,
+            ErrorCode::Exception => WASMER_TRAP_EXCEPTION,
+            ErrorCode::Panic => WASMER_TRAP_HOST,
+            ErrorCode::Interrupted => WASMER_TRAP_INTERRUPTED,
+            ErrorCode::DeadlineExceeded => WASMER_TRAP_DEADLINE_EXCEEDED,
+            ErrorCode::OutOfGas => WASMER_TRAP_OUT_OF_GAS,
+            _ => WASMER_TRAP_UNKNOWN,
+        }
+    }
+}
+
+/// A WebAssembly function that was on the stack when a call trapped.
+#[repr(C)]
+pub struct wasmer_trap_frame_t {
+    pub func_index: uint32_t,
+    /// The name of the function from the `name` section, or null. It lives
+    /// until the next call on this thread fails.
+    pub func_name: *const c_char,
+    /// The offset of the instruction being run, in bytes from the start of
+    /// the module, if the backend knows it.
+    pub offset: wasmer_limit_option_t,
+}
+
+/// Gets the reason that the most recent call that failed on this thread,
+/// with `wasmer_instance_call` or `wasmer_export_func_call`, trapped, or
+/// `WASMER_TRAP_NONE` if it failed without trapping.
+#[no_mangle]
+pub extern "C" fn wasmer_last_trap_code() -> wasmer_trap_code_t {
+    LAST_TRAP.with(|last_trap| match *last_trap.borrow() {
+        Some(ref trap) => trap.code,
+        None => wasmer_trap_code_t::WASMER_TRAP_NONE,
+    })
+}
+
+/// Gets the number of WebAssembly functions that were on the stack when the
+/// most recent call that failed trapped. It's 0 if the backend can't find
+/// them.
+#[no_mangle]
+pub extern "C" fn wasmer_last_trap_frames_length() -> c_int {
+    LAST_TRAP.with(|last_trap| match *last_trap.borrow() {
+        Some(ref trap) => trap.frames.len() as c_int,
+        None => 0,
+    })
+}
+
+/// Gets a WebAssembly function that was on the stack when the most recent
+/// call that failed trapped, innermost first.
+///
+/// Returns `wasmer_result_t::WASMER_OK` upon success.
+///
+/// Returns `wasmer_result_t::WASMER_ERROR` if there's no such frame.
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_last_trap_frame(
+    index: c_int,
+    frame: *mut wasmer_trap_frame_t,
+) -> wasmer_result_t {
+    LAST_TRAP.with(|last_trap| {
+        let last_trap = last_trap.borrow();
+        let trap = match *last_trap {
+            Some(ref trap) if index >= 0 && (index as usize) < trap.frames.len() => trap,
+            _ => return wasmer_result_t::WASMER_ERROR,
+        };
+        let index = index as usize;
+        let trap_frame = &trap.frames[index];
+        *frame = wasmer_trap_frame_t {
+            func_index: trap_frame.func_index.index() as uint32_t,
+            func_name: trap.func_names[index]
+                .as_ref()
+                .map_or(ptr::null(), |name| name.as_ptr()),
+            offset: wasmer_limit_option_t {
+                has_some: trap_frame.offset.is_some(),
+                some: trap_frame.offset.unwrap_or(0),
+            },
+        };
+        wasmer_result_t::WASMER_OK
+    })
+}
+
+/// Gets the backtrace of the most recent call that failed, formatted with one
+/// function per line, innermost first.
+fn last_trap_backtrace() -> String {
+    LAST_TRAP.with(|last_trap| match *last_trap.borrow() {
+        Some(ref trap) => trap
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| format!("{}: {}\n", i, frame))
+            .collect(),
+        None => String::new(),
+    })
+}
+
+/// Gets the length in bytes of the formatted backtrace of the most recent call
+/// that failed, including the trailing null, to allocate a buffer for
+/// `wasmer_last_trap_backtrace`.
+#[no_mangle]
+pub extern "C" fn wasmer_last_trap_backtrace_length() -> c_int {
+    last_trap_backtrace().len() as c_int + 1
+}
+
+/// Stores the formatted backtrace of the most recent call that failed into
+/// the provided buffer up to the given `length`, with one function per line,
+/// innermost first.
+///
+/// Returns the length of the string in bytes.
+/// Returns `-1` if an error occurs.
+#[no_mangle]
+pub unsafe extern "C" fn wasmer_last_trap_backtrace(buffer: *mut c_char, length: c_int) -> c_int {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let backtrace = last_trap_backtrace();
+    let buffer = slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
+    if backtrace.len() >= buffer.len() {
+        return -1;
+    }
+
+    ptr::copy_nonoverlapping(backtrace.as_ptr(), buffer.as_mut_ptr(), backtrace.len());
+    buffer[backtrace.len()] = 0;
+
+    backtrace.len() as c_int
+}
+
 /// Retrieve the most recent error, clearing it in the process.
 fn take_last_error() -> Option<Box<Error>> {
     LAST_ERROR.with(|prev| prev.borrow_mut().take())
@@ -1766,6 +3147,8 @@ struct NamedImportDescriptor {
     module: String,
     name: String,
     kind: wasmer_import_export_kind,
+    /// The signature, if the import is a function.
+    signature: Option<FuncSig>,
 }
 
 struct NamedExport {
@@ -1777,4 +3160,6 @@ struct NamedExport {
 struct NamedExportDescriptor {
     name: String,
     kind: wasmer_import_export_kind,
+    /// The signature, if the export is a function.
+    signature: Option<FuncSig>,
 }
@@ -0,0 +1,1857 @@
+//! The standard WebAssembly C API, as declared by `wasm.h`, so that
+//! code written against it, and bindings built on it, work with
+//! Wasmer.
+//!
+//! Every object is owned by whoever it's returned to, and is freed with
+//! its `_delete` function; objects that are passed by `const` pointer
+//! are only borrowed. Stores and engines don't own the objects made in
+//! them here, so an object may outlive its store.
+//!
+//! A few things differ from other implementations:
+//!
+//! - The imports of a module are listed, and passed to
+//!   `wasm_instance_new`, as Wasmer orders them: the functions, then
+//!   the tables, memories and globals, each in the order they're
+//!   imported in. The exports are sorted by name.
+//! - Functions can't take or return references, or return more than
+//!   one value.
+//! - References, foreign objects, frames and host info aren't
+//!   supported, and tables can't be created from C.
+//!
+//! The items of this module are declared in `wasm.h` rather than in
+//! `wasmer.h`. The two headers can't be included together, since both
+//! declare `WASM_I32` and the like.
+
+use crate::{update_last_error, CApiError};
+use libffi::{
+    low::ffi_cif,
+    middle::{Cif, Closure, Type as FfiType},
+};
+use std::{collections::HashMap, ffi::c_void, iter, mem, ptr, rc::Rc, slice, sync::Arc};
+use wasmer_runtime::{
+    default_compiler, Global, ImportObject, Instance, Memory, Module, Table, Value,
+};
+use wasmer_runtime_core::{
+    cache::Artifact,
+    error::{CallError, Error, RuntimeError},
+    export::{Context, Export, FuncPointer},
+    import::{LikeNamespace, Namespace},
+    load_cache_with,
+    module::ExternDescriptor,
+    trap,
+    types::{ElementType, FuncSig, GlobalDescriptor, MemoryDescriptor, Type},
+    units::{Bytes, Pages},
+    vm,
+};
+
+#[allow(non_camel_case_types)]
+pub type byte_t = u8;
+#[allow(non_camel_case_types)]
+pub type float32_t = f32;
+#[allow(non_camel_case_types)]
+pub type float64_t = f64;
+
+/// Declare a vector of `$elem`s, with the functions that make, copy and
+/// delete it. Vectors own their elements.
+macro_rules! wasm_vec {
+    ($vec:ident, $elem:ty, $new_empty:ident, $new_uninitialized:ident, $new:ident, $copy:ident, $delete:ident) => {
+        #[allow(non_camel_case_types)]
+        #[repr(C)]
+        pub struct $vec {
+            pub size: usize,
+            pub data: *mut $elem,
+        }
+
+        impl $vec {
+            fn from_vec(elems: Vec<$elem>) -> Self {
+                if elems.is_empty() {
+                    return Self {
+                        size: 0,
+                        data: ptr::null_mut(),
+                    };
+                }
+                let mut elems = elems.into_boxed_slice();
+                let vec = Self {
+                    size: elems.len(),
+                    data: elems.as_mut_ptr(),
+                };
+                mem::forget(elems);
+                vec
+            }
+
+            #[allow(dead_code)]
+            fn as_slice(&self) -> &[$elem] {
+                if self.size == 0 {
+                    &[]
+                } else {
+                    unsafe { slice::from_raw_parts(self.data, self.size) }
+                }
+            }
+
+            /// Take the elements, leaving the vector empty.
+            fn take(&mut self) -> Vec<$elem> {
+                let elems = if self.size == 0 {
+                    Vec::new()
+                } else {
+                    unsafe { Box::from_raw(slice::from_raw_parts_mut(self.data, self.size)) }
+                        .into_vec()
+                };
+                self.size = 0;
+                self.data = ptr::null_mut();
+                elems
+            }
+        }
+
+        impl Clone for $vec {
+            fn clone(&self) -> Self {
+                Self::from_vec(self.as_slice().to_vec())
+            }
+        }
+
+        impl Drop for $vec {
+            fn drop(&mut self) {
+                self.take();
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $new_empty(out: *mut $vec) {
+            ptr::write(out, $vec::from_vec(Vec::new()));
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $new_uninitialized(out: *mut $vec, size: usize) {
+            let elems = iter::repeat_with(Default::default).take(size).collect();
+            ptr::write(out, $vec::from_vec(elems));
+        }
+
+        /// Takes the `size` elements at `data`.
+        #[no_mangle]
+        pub unsafe extern "C" fn $new(out: *mut $vec, size: usize, data: *const $elem) {
+            let elems = (0..size).map(|index| ptr::read(data.add(index))).collect();
+            ptr::write(out, $vec::from_vec(elems));
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $copy(out: *mut $vec, src: *const $vec) {
+            ptr::write(out, (*src).clone());
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $delete(vec: *mut $vec) {
+            (*vec).take();
+        }
+    };
+}
+
+wasm_vec!(
+    wasm_byte_vec_t,
+    byte_t,
+    wasm_byte_vec_new_empty,
+    wasm_byte_vec_new_uninitialized,
+    wasm_byte_vec_new,
+    wasm_byte_vec_copy,
+    wasm_byte_vec_delete
+);
+
+#[allow(non_camel_case_types)]
+pub type wasm_name_t = wasm_byte_vec_t;
+#[allow(non_camel_case_types)]
+pub type wasm_message_t = wasm_name_t;
+
+impl wasm_byte_vec_t {
+    fn from_str(s: &str) -> Self {
+        Self::from_vec(s.as_bytes().to_vec())
+    }
+
+    fn to_string_lossy(&self) -> String {
+        let bytes = self.as_slice();
+        // Messages end with a nul.
+        let bytes = bytes.split(|&byte| byte == 0).next().unwrap_or(bytes);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+// Configuration
+
+/// The configuration of an engine. There's nothing to configure yet.
+#[allow(non_camel_case_types)]
+pub struct wasm_config_t {}
+
+#[no_mangle]
+pub extern "C" fn wasm_config_new() -> *mut wasm_config_t {
+    Box::into_raw(Box::new(wasm_config_t {}))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_config_delete(config: *mut wasm_config_t) {
+    if !config.is_null() {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// Modules are compiled with the default compiler of `wasmer-runtime`.
+#[allow(non_camel_case_types)]
+pub struct wasm_engine_t {}
+
+#[no_mangle]
+pub extern "C" fn wasm_engine_new() -> *mut wasm_engine_t {
+    Box::into_raw(Box::new(wasm_engine_t {}))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_engine_new_with_config(
+    config: *mut wasm_config_t,
+) -> *mut wasm_engine_t {
+    wasm_config_delete(config);
+    wasm_engine_new()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_engine_delete(engine: *mut wasm_engine_t) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub struct wasm_store_t {}
+
+#[no_mangle]
+pub extern "C" fn wasm_store_new(_engine: *mut wasm_engine_t) -> *mut wasm_store_t {
+    Box::into_raw(Box::new(wasm_store_t {}))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_store_delete(store: *mut wasm_store_t) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+// Types
+
+#[allow(non_camel_case_types)]
+pub type wasm_mutability_t = u8;
+pub const WASM_CONST: wasm_mutability_t = 0;
+pub const WASM_VAR: wasm_mutability_t = 1;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct wasm_limits_t {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// The `max` of limits that have no maximum.
+pub const wasm_limits_max_default: u32 = 0xffff_ffff;
+
+impl wasm_limits_t {
+    fn new(min: u32, max: Option<u32>) -> Self {
+        Self {
+            min,
+            max: max.unwrap_or(wasm_limits_max_default),
+        }
+    }
+
+    fn max(&self) -> Option<u32> {
+        if self.max == wasm_limits_max_default {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_valkind_t = u8;
+pub const WASM_I32: wasm_valkind_t = 0;
+pub const WASM_I64: wasm_valkind_t = 1;
+pub const WASM_F32: wasm_valkind_t = 2;
+pub const WASM_F64: wasm_valkind_t = 3;
+pub const WASM_ANYREF: wasm_valkind_t = 128;
+pub const WASM_FUNCREF: wasm_valkind_t = 129;
+
+fn valkind(ty: Type) -> wasm_valkind_t {
+    match ty {
+        Type::I32 => WASM_I32,
+        Type::I64 => WASM_I64,
+        Type::F32 => WASM_F32,
+        Type::F64 => WASM_F64,
+        Type::ExternRef => WASM_ANYREF,
+        Type::FuncRef => WASM_FUNCREF,
+    }
+}
+
+/// The type of values of `kind`, if it's a number type, which are the
+/// only values that can be passed through this API.
+fn number_type(kind: wasm_valkind_t) -> Option<Type> {
+    match kind {
+        WASM_I32 => Some(Type::I32),
+        WASM_I64 => Some(Type::I64),
+        WASM_F32 => Some(Type::F32),
+        WASM_F64 => Some(Type::F64),
+        _ => None,
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub struct wasm_valtype_t {
+    kind: wasm_valkind_t,
+}
+
+impl wasm_valtype_t {
+    fn boxed(kind: wasm_valkind_t) -> Option<Box<Self>> {
+        Some(Box::new(Self { kind }))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasm_valtype_new(kind: wasm_valkind_t) -> *mut wasm_valtype_t {
+    Box::into_raw(Box::new(wasm_valtype_t { kind }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_valtype_kind(valtype: *const wasm_valtype_t) -> wasm_valkind_t {
+    (*valtype).kind
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_externkind_t = u8;
+pub const WASM_EXTERN_FUNC: wasm_externkind_t = 0;
+pub const WASM_EXTERN_GLOBAL: wasm_externkind_t = 1;
+pub const WASM_EXTERN_TABLE: wasm_externkind_t = 2;
+pub const WASM_EXTERN_MEMORY: wasm_externkind_t = 3;
+
+/// The type of an external: a function, global, table or memory type.
+/// A `wasm_functype_t` and the others are an external type of their
+/// kind, so they're converted to and from `wasm_externtype_t` in place.
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct wasm_externtype_t {
+    ty: ExternType,
+}
+
+#[derive(Clone)]
+enum ExternType {
+    Func {
+        params: wasm_valtype_vec_t,
+        results: wasm_valtype_vec_t,
+    },
+    Global {
+        content: Box<wasm_valtype_t>,
+        mutability: wasm_mutability_t,
+    },
+    Table {
+        element: Box<wasm_valtype_t>,
+        limits: wasm_limits_t,
+    },
+    Memory {
+        limits: wasm_limits_t,
+    },
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_functype_t = wasm_externtype_t;
+#[allow(non_camel_case_types)]
+pub type wasm_globaltype_t = wasm_externtype_t;
+#[allow(non_camel_case_types)]
+pub type wasm_tabletype_t = wasm_externtype_t;
+#[allow(non_camel_case_types)]
+pub type wasm_memorytype_t = wasm_externtype_t;
+
+impl wasm_externtype_t {
+    fn boxed(ty: ExternType) -> *mut Self {
+        Box::into_raw(Box::new(Self { ty }))
+    }
+
+    fn from_descriptor(descriptor: &ExternDescriptor) -> Self {
+        let ty = match descriptor {
+            ExternDescriptor::Function(signature) => func_type(signature),
+            ExternDescriptor::Global(desc) => ExternType::Global {
+                content: Box::new(wasm_valtype_t {
+                    kind: valkind(desc.ty),
+                }),
+                mutability: if desc.mutable { WASM_VAR } else { WASM_CONST },
+            },
+            ExternDescriptor::Table(desc) => ExternType::Table {
+                element: Box::new(wasm_valtype_t {
+                    kind: match desc.element {
+                        ElementType::Anyfunc => WASM_FUNCREF,
+                        ElementType::ExternRef => WASM_ANYREF,
+                    },
+                }),
+                limits: wasm_limits_t::new(desc.minimum, desc.maximum),
+            },
+            ExternDescriptor::Memory(desc) => ExternType::Memory {
+                limits: wasm_limits_t::new(desc.minimum.0, desc.maximum.map(|Pages(max)| max)),
+            },
+        };
+        Self { ty }
+    }
+
+    /// The signature of a function type, if it only has number types
+    /// and at most one result.
+    fn signature(&self) -> Option<FuncSig> {
+        let (params, results) = match &self.ty {
+            ExternType::Func { params, results } => (params, results),
+            _ => return None,
+        };
+        let types = |types: &wasm_valtype_vec_t| {
+            types
+                .as_slice()
+                .iter()
+                .map(|valtype| {
+                    valtype
+                        .as_ref()
+                        .and_then(|valtype| number_type(valtype.kind))
+                })
+                .collect::<Option<Vec<_>>>()
+        };
+        let (params, results) = (types(params)?, types(results)?);
+        if results.len() > 1 {
+            return None;
+        }
+        Some(FuncSig::new(params, results))
+    }
+}
+
+fn func_type(signature: &FuncSig) -> ExternType {
+    let types = |types: &[Type]| {
+        wasm_valtype_vec_t::from_vec(
+            types
+                .iter()
+                .map(|&ty| wasm_valtype_t::boxed(valkind(ty)))
+                .collect(),
+        )
+    };
+    ExternType::Func {
+        params: types(signature.params()),
+        results: types(signature.returns()),
+    }
+}
+
+/// Takes `params` and `results`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_functype_new(
+    params: *mut wasm_valtype_vec_t,
+    results: *mut wasm_valtype_vec_t,
+) -> *mut wasm_functype_t {
+    wasm_externtype_t::boxed(ExternType::Func {
+        params: wasm_valtype_vec_t::from_vec((*params).take()),
+        results: wasm_valtype_vec_t::from_vec((*results).take()),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_functype_params(
+    functype: *const wasm_functype_t,
+) -> *const wasm_valtype_vec_t {
+    match &(*functype).ty {
+        ExternType::Func { params, .. } => params,
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_functype_results(
+    functype: *const wasm_functype_t,
+) -> *const wasm_valtype_vec_t {
+    match &(*functype).ty {
+        ExternType::Func { results, .. } => results,
+        _ => ptr::null(),
+    }
+}
+
+/// Takes `content`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_new(
+    content: *mut wasm_valtype_t,
+    mutability: wasm_mutability_t,
+) -> *mut wasm_globaltype_t {
+    wasm_externtype_t::boxed(ExternType::Global {
+        content: Box::from_raw(content),
+        mutability,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_content(
+    globaltype: *const wasm_globaltype_t,
+) -> *const wasm_valtype_t {
+    match &(*globaltype).ty {
+        ExternType::Global { content, .. } => &**content,
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_mutability(
+    globaltype: *const wasm_globaltype_t,
+) -> wasm_mutability_t {
+    match &(*globaltype).ty {
+        ExternType::Global { mutability, .. } => *mutability,
+        _ => WASM_CONST,
+    }
+}
+
+/// Takes `element`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_new(
+    element: *mut wasm_valtype_t,
+    limits: *const wasm_limits_t,
+) -> *mut wasm_tabletype_t {
+    wasm_externtype_t::boxed(ExternType::Table {
+        element: Box::from_raw(element),
+        limits: *limits,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_element(
+    tabletype: *const wasm_tabletype_t,
+) -> *const wasm_valtype_t {
+    match &(*tabletype).ty {
+        ExternType::Table { element, .. } => &**element,
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_limits(
+    tabletype: *const wasm_tabletype_t,
+) -> *const wasm_limits_t {
+    match &(*tabletype).ty {
+        ExternType::Table { limits, .. } => limits,
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memorytype_new(
+    limits: *const wasm_limits_t,
+) -> *mut wasm_memorytype_t {
+    wasm_externtype_t::boxed(ExternType::Memory { limits: *limits })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memorytype_limits(
+    memorytype: *const wasm_memorytype_t,
+) -> *const wasm_limits_t {
+    match &(*memorytype).ty {
+        ExternType::Memory { limits } => limits,
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_kind(
+    externtype: *const wasm_externtype_t,
+) -> wasm_externkind_t {
+    match (*externtype).ty {
+        ExternType::Func { .. } => WASM_EXTERN_FUNC,
+        ExternType::Global { .. } => WASM_EXTERN_GLOBAL,
+        ExternType::Table { .. } => WASM_EXTERN_TABLE,
+        ExternType::Memory { .. } => WASM_EXTERN_MEMORY,
+    }
+}
+
+/// Declare the conversions between an external type and the type of
+/// one kind, which return null if it's of another kind.
+macro_rules! externtype_conversions {
+    ($kind:expr, $as_externtype:ident, $as_externtype_const:ident, $from_externtype:ident, $from_externtype_const:ident) => {
+        #[no_mangle]
+        pub extern "C" fn $as_externtype(ty: *mut wasm_externtype_t) -> *mut wasm_externtype_t {
+            ty
+        }
+
+        #[no_mangle]
+        pub extern "C" fn $as_externtype_const(
+            ty: *const wasm_externtype_t,
+        ) -> *const wasm_externtype_t {
+            ty
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_externtype(
+            ty: *mut wasm_externtype_t,
+        ) -> *mut wasm_externtype_t {
+            if wasm_externtype_kind(ty) == $kind {
+                ty
+            } else {
+                ptr::null_mut()
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_externtype_const(
+            ty: *const wasm_externtype_t,
+        ) -> *const wasm_externtype_t {
+            if wasm_externtype_kind(ty) == $kind {
+                ty
+            } else {
+                ptr::null()
+            }
+        }
+    };
+}
+
+externtype_conversions!(
+    WASM_EXTERN_FUNC,
+    wasm_functype_as_externtype,
+    wasm_functype_as_externtype_const,
+    wasm_externtype_as_functype,
+    wasm_externtype_as_functype_const
+);
+externtype_conversions!(
+    WASM_EXTERN_GLOBAL,
+    wasm_globaltype_as_externtype,
+    wasm_globaltype_as_externtype_const,
+    wasm_externtype_as_globaltype,
+    wasm_externtype_as_globaltype_const
+);
+externtype_conversions!(
+    WASM_EXTERN_TABLE,
+    wasm_tabletype_as_externtype,
+    wasm_tabletype_as_externtype_const,
+    wasm_externtype_as_tabletype,
+    wasm_externtype_as_tabletype_const
+);
+externtype_conversions!(
+    WASM_EXTERN_MEMORY,
+    wasm_memorytype_as_externtype,
+    wasm_memorytype_as_externtype_const,
+    wasm_externtype_as_memorytype,
+    wasm_externtype_as_memorytype_const
+);
+
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct wasm_importtype_t {
+    module: wasm_name_t,
+    name: wasm_name_t,
+    ty: Box<wasm_externtype_t>,
+}
+
+/// Takes `module`, `name` and `ty`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_new(
+    module: *mut wasm_name_t,
+    name: *mut wasm_name_t,
+    ty: *mut wasm_externtype_t,
+) -> *mut wasm_importtype_t {
+    Box::into_raw(Box::new(wasm_importtype_t {
+        module: wasm_name_t::from_vec((*module).take()),
+        name: wasm_name_t::from_vec((*name).take()),
+        ty: Box::from_raw(ty),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_module(
+    importtype: *const wasm_importtype_t,
+) -> *const wasm_name_t {
+    &(*importtype).module
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_name(
+    importtype: *const wasm_importtype_t,
+) -> *const wasm_name_t {
+    &(*importtype).name
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_type(
+    importtype: *const wasm_importtype_t,
+) -> *const wasm_externtype_t {
+    &*(*importtype).ty
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct wasm_exporttype_t {
+    name: wasm_name_t,
+    ty: Box<wasm_externtype_t>,
+}
+
+/// Takes `name` and `ty`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_new(
+    name: *mut wasm_name_t,
+    ty: *mut wasm_externtype_t,
+) -> *mut wasm_exporttype_t {
+    Box::into_raw(Box::new(wasm_exporttype_t {
+        name: wasm_name_t::from_vec((*name).take()),
+        ty: Box::from_raw(ty),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_name(
+    exporttype: *const wasm_exporttype_t,
+) -> *const wasm_name_t {
+    &(*exporttype).name
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_type(
+    exporttype: *const wasm_exporttype_t,
+) -> *const wasm_externtype_t {
+    &*(*exporttype).ty
+}
+
+/// Declare the functions that copy and delete `$ty`, and vectors of
+/// it.
+macro_rules! wasm_type {
+    ($ty:ty, $delete:ident, $copy:ident, $vec:ident, $new_empty:ident, $new_uninitialized:ident, $new:ident, $vec_copy:ident, $vec_delete:ident) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn $delete(ty: *mut $ty) {
+            if !ty.is_null() {
+                drop(Box::from_raw(ty));
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $copy(ty: *const $ty) -> *mut $ty {
+            Box::into_raw(Box::new((*ty).clone()))
+        }
+
+        wasm_vec!(
+            $vec,
+            Option<Box<$ty>>,
+            $new_empty,
+            $new_uninitialized,
+            $new,
+            $vec_copy,
+            $vec_delete
+        );
+    };
+}
+
+wasm_type!(
+    wasm_valtype_t,
+    wasm_valtype_delete,
+    wasm_valtype_copy,
+    wasm_valtype_vec_t,
+    wasm_valtype_vec_new_empty,
+    wasm_valtype_vec_new_uninitialized,
+    wasm_valtype_vec_new,
+    wasm_valtype_vec_copy,
+    wasm_valtype_vec_delete
+);
+wasm_type!(
+    wasm_functype_t,
+    wasm_functype_delete,
+    wasm_functype_copy,
+    wasm_functype_vec_t,
+    wasm_functype_vec_new_empty,
+    wasm_functype_vec_new_uninitialized,
+    wasm_functype_vec_new,
+    wasm_functype_vec_copy,
+    wasm_functype_vec_delete
+);
+wasm_type!(
+    wasm_globaltype_t,
+    wasm_globaltype_delete,
+    wasm_globaltype_copy,
+    wasm_globaltype_vec_t,
+    wasm_globaltype_vec_new_empty,
+    wasm_globaltype_vec_new_uninitialized,
+    wasm_globaltype_vec_new,
+    wasm_globaltype_vec_copy,
+    wasm_globaltype_vec_delete
+);
+wasm_type!(
+    wasm_tabletype_t,
+    wasm_tabletype_delete,
+    wasm_tabletype_copy,
+    wasm_tabletype_vec_t,
+    wasm_tabletype_vec_new_empty,
+    wasm_tabletype_vec_new_uninitialized,
+    wasm_tabletype_vec_new,
+    wasm_tabletype_vec_copy,
+    wasm_tabletype_vec_delete
+);
+wasm_type!(
+    wasm_memorytype_t,
+    wasm_memorytype_delete,
+    wasm_memorytype_copy,
+    wasm_memorytype_vec_t,
+    wasm_memorytype_vec_new_empty,
+    wasm_memorytype_vec_new_uninitialized,
+    wasm_memorytype_vec_new,
+    wasm_memorytype_vec_copy,
+    wasm_memorytype_vec_delete
+);
+wasm_type!(
+    wasm_externtype_t,
+    wasm_externtype_delete,
+    wasm_externtype_copy,
+    wasm_externtype_vec_t,
+    wasm_externtype_vec_new_empty,
+    wasm_externtype_vec_new_uninitialized,
+    wasm_externtype_vec_new,
+    wasm_externtype_vec_copy,
+    wasm_externtype_vec_delete
+);
+wasm_type!(
+    wasm_importtype_t,
+    wasm_importtype_delete,
+    wasm_importtype_copy,
+    wasm_importtype_vec_t,
+    wasm_importtype_vec_new_empty,
+    wasm_importtype_vec_new_uninitialized,
+    wasm_importtype_vec_new,
+    wasm_importtype_vec_copy,
+    wasm_importtype_vec_delete
+);
+wasm_type!(
+    wasm_exporttype_t,
+    wasm_exporttype_delete,
+    wasm_exporttype_copy,
+    wasm_exporttype_vec_t,
+    wasm_exporttype_vec_new_empty,
+    wasm_exporttype_vec_new_uninitialized,
+    wasm_exporttype_vec_new,
+    wasm_exporttype_vec_copy,
+    wasm_exporttype_vec_delete
+);
+
+// Values
+
+/// References aren't supported, so this is never made.
+#[allow(non_camel_case_types)]
+pub struct wasm_ref_t {}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct wasm_val_t {
+    pub kind: wasm_valkind_t,
+    pub of: wasm_val_inner,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union wasm_val_inner {
+    pub i32: i32,
+    pub i64: i64,
+    pub f32: float32_t,
+    pub f64: float64_t,
+    pub ref_: *mut wasm_ref_t,
+}
+
+impl Default for wasm_val_t {
+    fn default() -> Self {
+        Self {
+            kind: WASM_I32,
+            of: wasm_val_inner { i64: 0 },
+        }
+    }
+}
+
+impl wasm_val_t {
+    /// Zero, of type `ty`.
+    fn zero(ty: Type) -> Self {
+        Self {
+            kind: valkind(ty),
+            of: wasm_val_inner { i64: 0 },
+        }
+    }
+
+    fn from_value(value: Value) -> Self {
+        let of = match value {
+            Value::I32(x) => wasm_val_inner { i32: x },
+            Value::I64(x) => wasm_val_inner { i64: x },
+            Value::F32(x) => wasm_val_inner { f32: x },
+            Value::F64(x) => wasm_val_inner { f64: x },
+            Value::ExternRef(_) | Value::FuncRef(_) => wasm_val_inner {
+                ref_: ptr::null_mut(),
+            },
+        };
+        Self {
+            kind: valkind(value.ty()),
+            of,
+        }
+    }
+
+    /// The value, if it's a number.
+    fn to_value(&self) -> Option<Value> {
+        unsafe {
+            match self.kind {
+                WASM_I32 => Some(Value::I32(self.of.i32)),
+                WASM_I64 => Some(Value::I64(self.of.i64)),
+                WASM_F32 => Some(Value::F32(self.of.f32)),
+                WASM_F64 => Some(Value::F64(self.of.f64)),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_val_copy(out: *mut wasm_val_t, src: *const wasm_val_t) {
+    ptr::write(out, *src);
+}
+
+/// Values only hold numbers, so there's nothing to free.
+#[no_mangle]
+pub extern "C" fn wasm_val_delete(_val: *mut wasm_val_t) {}
+
+wasm_vec!(
+    wasm_val_vec_t,
+    wasm_val_t,
+    wasm_val_vec_new_empty,
+    wasm_val_vec_new_uninitialized,
+    wasm_val_vec_new,
+    wasm_val_vec_copy,
+    wasm_val_vec_delete
+);
+
+// Traps
+
+#[allow(non_camel_case_types)]
+pub struct wasm_trap_t {
+    message: wasm_message_t,
+}
+
+impl wasm_trap_t {
+    fn boxed(message: &str) -> *mut Self {
+        // Messages end with a nul, as C strings do.
+        let mut bytes = message.as_bytes().to_vec();
+        bytes.push(0);
+        Box::into_raw(Box::new(Self {
+            message: wasm_message_t::from_vec(bytes),
+        }))
+    }
+
+    fn from_runtime_error(error: &RuntimeError) -> *mut Self {
+        match error {
+            // A trap returned by a host function.
+            RuntimeError::Panic { data } => match data.downcast_ref::<String>() {
+                Some(message) => Self::boxed(message),
+                None => Self::boxed(&error.to_string()),
+            },
+            _ => Self::boxed(&error.to_string()),
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_trap_new(
+    _store: *mut wasm_store_t,
+    message: *const wasm_message_t,
+) -> *mut wasm_trap_t {
+    Box::into_raw(Box::new(wasm_trap_t {
+        message: (*message).clone(),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_trap_message(trap: *const wasm_trap_t, out: *mut wasm_message_t) {
+    ptr::write(out, (*trap).message.clone());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_trap_delete(trap: *mut wasm_trap_t) {
+    if !trap.is_null() {
+        drop(Box::from_raw(trap));
+    }
+}
+
+// Modules
+
+#[allow(non_camel_case_types)]
+pub struct wasm_module_t {
+    module: Module,
+}
+
+/// Returns null if `binary` can't be compiled.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_new(
+    _store: *mut wasm_store_t,
+    binary: *const wasm_byte_vec_t,
+) -> *mut wasm_module_t {
+    match wasmer_runtime::compile((*binary).as_slice()) {
+        Ok(module) => Box::into_raw(Box::new(wasm_module_t { module })),
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: error.to_string(),
+            });
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_validate(
+    _store: *mut wasm_store_t,
+    binary: *const wasm_byte_vec_t,
+) -> bool {
+    wasmer_runtime_core::validate((*binary).as_slice())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_delete(module: *mut wasm_module_t) {
+    if !module.is_null() {
+        drop(Box::from_raw(module));
+    }
+}
+
+/// The imports of `module`, in the order that `wasm_instance_new`
+/// takes them.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_imports(
+    module: *const wasm_module_t,
+    out: *mut wasm_importtype_vec_t,
+) {
+    let imports = (*module)
+        .module
+        .imports()
+        .iter()
+        .map(|import| {
+            Some(Box::new(wasm_importtype_t {
+                module: wasm_name_t::from_str(&import.namespace),
+                name: wasm_name_t::from_str(&import.name),
+                ty: Box::new(wasm_externtype_t::from_descriptor(&import.descriptor)),
+            }))
+        })
+        .collect();
+    ptr::write(out, wasm_importtype_vec_t::from_vec(imports));
+}
+
+/// The exports of `module`, sorted by name, in the order that
+/// `wasm_instance_exports` gives them.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_exports(
+    module: *const wasm_module_t,
+    out: *mut wasm_exporttype_vec_t,
+) {
+    let exports = (*module)
+        .module
+        .exports()
+        .iter()
+        .map(|export| {
+            Some(Box::new(wasm_exporttype_t {
+                name: wasm_name_t::from_str(&export.name),
+                ty: Box::new(wasm_externtype_t::from_descriptor(&export.descriptor)),
+            }))
+        })
+        .collect();
+    ptr::write(out, wasm_exporttype_vec_t::from_vec(exports));
+}
+
+/// Serialize `module` into `out`, which is left empty if the backend
+/// can't cache modules.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_serialize(
+    module: *const wasm_module_t,
+    out: *mut wasm_byte_vec_t,
+) {
+    let bytes = match (*module)
+        .module
+        .cache()
+        .and_then(|artifact| artifact.serialize())
+    {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("failed to serialize the module: {:?}", error),
+            });
+            Vec::new()
+        }
+    };
+    ptr::write(out, wasm_byte_vec_t::from_vec(bytes));
+}
+
+/// Returns null if `binary` isn't a module that was serialized by this
+/// version of Wasmer, on this machine.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_deserialize(
+    _store: *mut wasm_store_t,
+    binary: *const wasm_byte_vec_t,
+) -> *mut wasm_module_t {
+    let module = Artifact::deserialize((*binary).as_slice())
+        .and_then(|artifact| load_cache_with(artifact, default_compiler()));
+    match module {
+        Ok(module) => Box::into_raw(Box::new(wasm_module_t { module })),
+        Err(error) => {
+            update_last_error(CApiError {
+                msg: format!("failed to deserialize the module: {:?}", error),
+            });
+            ptr::null_mut()
+        }
+    }
+}
+
+// Externals
+
+/// A function, global, table or memory. A `wasm_func_t` and the others
+/// are an external of their kind, so they're converted to and from
+/// `wasm_extern_t` in place.
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct wasm_extern_t {
+    inner: Extern,
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_func_t = wasm_extern_t;
+#[allow(non_camel_case_types)]
+pub type wasm_global_t = wasm_extern_t;
+#[allow(non_camel_case_types)]
+pub type wasm_table_t = wasm_extern_t;
+#[allow(non_camel_case_types)]
+pub type wasm_memory_t = wasm_extern_t;
+
+#[derive(Clone)]
+enum Extern {
+    Func(Func),
+    Global(Global),
+    Table(Table),
+    Memory(Memory),
+}
+
+#[derive(Clone)]
+enum Func {
+    Host(Rc<HostFunc>),
+    /// A function exported by an instance, which is called by name.
+    Export {
+        instance: Rc<Instance>,
+        name: String,
+        signature: Arc<FuncSig>,
+    },
+}
+
+impl wasm_extern_t {
+    fn boxed(inner: Extern) -> *mut Self {
+        Box::into_raw(Box::new(Self { inner }))
+    }
+
+    /// `None` if it's a function that its instance doesn't export
+    /// anymore.
+    fn to_export(&self) -> Option<Export> {
+        Some(match &self.inner {
+            Extern::Func(Func::Host(func)) => Export::Function {
+                func: unsafe { FuncPointer::new(*func.closure.code_ptr() as *const vm::Func) },
+                ctx: Context::Internal,
+                signature: Arc::clone(&func.callback.signature),
+            },
+            Extern::Func(Func::Export { instance, name, .. }) => instance.get_export(name)?,
+            Extern::Global(global) => Export::Global(global.clone()),
+            Extern::Table(table) => Export::Table(table.clone()),
+            Extern::Memory(memory) => Export::Memory(memory.clone()),
+        })
+    }
+
+    fn func_signature(&self) -> Option<&Arc<FuncSig>> {
+        match &self.inner {
+            Extern::Func(Func::Host(func)) => Some(&func.callback.signature),
+            Extern::Func(Func::Export { signature, .. }) => Some(signature),
+            _ => None,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_func_callback_t =
+    unsafe extern "C" fn(args: *const wasm_val_t, results: *mut wasm_val_t) -> *mut wasm_trap_t;
+
+#[allow(non_camel_case_types)]
+pub type wasm_func_callback_with_env_t = unsafe extern "C" fn(
+    env: *mut c_void,
+    args: *const wasm_val_t,
+    results: *mut wasm_val_t,
+) -> *mut wasm_trap_t;
+
+/// A function defined in C, with the native code that WebAssembly calls
+/// it through.
+struct HostFunc {
+    // Dropped before the callback, which it refers to.
+    closure: Closure<'static>,
+    callback: Box<HostCallback>,
+}
+
+struct HostCallback {
+    signature: Arc<FuncSig>,
+    kind: CallbackKind,
+}
+
+enum CallbackKind {
+    Plain(wasm_func_callback_t),
+    WithEnv {
+        callback: wasm_func_callback_with_env_t,
+        env: *mut c_void,
+        finalizer: Option<unsafe extern "C" fn(*mut c_void)>,
+    },
+}
+
+impl HostCallback {
+    unsafe fn call(&self, args: &[wasm_val_t], results: &mut [wasm_val_t]) -> *mut wasm_trap_t {
+        match self.kind {
+            CallbackKind::Plain(callback) => callback(args.as_ptr(), results.as_mut_ptr()),
+            CallbackKind::WithEnv { callback, env, .. } => {
+                callback(env, args.as_ptr(), results.as_mut_ptr())
+            }
+        }
+    }
+}
+
+impl Drop for HostCallback {
+    fn drop(&mut self) {
+        if let CallbackKind::WithEnv {
+            env,
+            finalizer: Some(finalizer),
+            ..
+        } = self.kind
+        {
+            unsafe { finalizer(env) }
+        }
+    }
+}
+
+impl HostFunc {
+    /// A function of `ty` that calls `kind`, or `None` if the type isn't
+    /// supported.
+    fn new(ty: &wasm_functype_t, kind: CallbackKind) -> Option<Rc<Self>> {
+        let signature = Arc::new(ty.signature()?);
+        let ffi_type = |ty: &Type| match ty {
+            Type::I32 => FfiType::i32(),
+            Type::I64 => FfiType::i64(),
+            Type::F32 => FfiType::f32(),
+            Type::F64 => FfiType::f64(),
+            Type::ExternRef | Type::FuncRef => unreachable!("only number types are supported"),
+        };
+        // Every function takes the `vm::Ctx` first.
+        let params: Vec<_> = iter::once(FfiType::pointer())
+            .chain(signature.params().iter().map(ffi_type))
+            .collect();
+        let result = signature
+            .returns()
+            .first()
+            .map_or_else(FfiType::void, ffi_type);
+
+        let callback = Box::new(HostCallback { signature, kind });
+        // The callback is boxed, so it stays where it is for as long as
+        // the closure, which is dropped first.
+        let userdata = unsafe { &*(&*callback as *const HostCallback) };
+        let closure = Closure::new(Cif::new(params, result), call_host_func, userdata);
+        Some(Rc::new(HostFunc { closure, callback }))
+    }
+}
+
+/// Called by WebAssembly through a closure with the native signature
+/// of a host function: converts the arguments, calls the function, and
+/// traps if it returns a trap.
+unsafe extern "C" fn call_host_func(
+    _cif: &ffi_cif,
+    result: &mut u64,
+    args: *const *const c_void,
+    callback: &HostCallback,
+) {
+    let signature = &callback.signature;
+    // The first argument is the `vm::Ctx` of the caller.
+    let params: Vec<_> = signature
+        .params()
+        .iter()
+        .enumerate()
+        .map(|(index, &ty)| {
+            let arg = *args.add(index + 1);
+            let of = match ty {
+                Type::I32 => wasm_val_inner {
+                    i32: *(arg as *const i32),
+                },
+                Type::I64 => wasm_val_inner {
+                    i64: *(arg as *const i64),
+                },
+                Type::F32 => wasm_val_inner {
+                    f32: *(arg as *const f32),
+                },
+                Type::F64 => wasm_val_inner {
+                    f64: *(arg as *const f64),
+                },
+                Type::ExternRef | Type::FuncRef => unreachable!("only number types are supported"),
+            };
+            wasm_val_t {
+                kind: valkind(ty),
+                of,
+            }
+        })
+        .collect();
+    let mut results: Vec<_> = signature
+        .returns()
+        .iter()
+        .map(|&ty| wasm_val_t::zero(ty))
+        .collect();
+
+    let trap = callback.call(&params, &mut results);
+    if !trap.is_null() {
+        let trap = Box::from_raw(trap);
+        let error = RuntimeError::Panic {
+            data: Box::new(trap.message.to_string_lossy()),
+        };
+        drop((params, results, trap));
+        trap::raise(error);
+    }
+
+    // libffi returns results narrower than a register as a whole one.
+    if let Some(value) = results.first() {
+        match value.kind {
+            WASM_I32 => *result = value.of.i32 as i64 as u64,
+            WASM_I64 => *result = value.of.i64 as u64,
+            WASM_F32 => *(result as *mut u64 as *mut f32) = value.of.f32,
+            _ => *result = value.of.f64.to_bits(),
+        }
+    }
+}
+
+/// Returns null if the type of the function has references, or more
+/// than one result.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_new(
+    _store: *mut wasm_store_t,
+    ty: *const wasm_functype_t,
+    callback: wasm_func_callback_t,
+) -> *mut wasm_func_t {
+    match HostFunc::new(&*ty, CallbackKind::Plain(callback)) {
+        Some(func) => wasm_extern_t::boxed(Extern::Func(Func::Host(func))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Like `wasm_func_new`, but `callback` is also passed `env`, which is
+/// passed to `finalizer`, if it isn't null, once the function is freed.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_new_with_env(
+    _store: *mut wasm_store_t,
+    ty: *const wasm_functype_t,
+    callback: wasm_func_callback_with_env_t,
+    env: *mut c_void,
+    finalizer: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> *mut wasm_func_t {
+    let kind = CallbackKind::WithEnv {
+        callback,
+        env,
+        finalizer,
+    };
+    match HostFunc::new(&*ty, kind) {
+        Some(func) => wasm_extern_t::boxed(Extern::Func(Func::Host(func))),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_type(func: *const wasm_func_t) -> *mut wasm_functype_t {
+    match (*func).func_signature() {
+        Some(signature) => wasm_externtype_t::boxed(func_type(signature)),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_param_arity(func: *const wasm_func_t) -> usize {
+    (*func)
+        .func_signature()
+        .map_or(0, |signature| signature.params().len())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_result_arity(func: *const wasm_func_t) -> usize {
+    (*func)
+        .func_signature()
+        .map_or(0, |signature| signature.returns().len())
+}
+
+/// Call `func` with as many `args` as it has parameters, writing as many
+/// `results` as it has results. Returns the trap, if it traps, or null.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_call(
+    func: *const wasm_func_t,
+    args: *const wasm_val_t,
+    results: *mut wasm_val_t,
+) -> *mut wasm_trap_t {
+    let signature = match (*func).func_signature() {
+        Some(signature) => Arc::clone(signature),
+        None => return wasm_trap_t::boxed("the external isn't a function"),
+    };
+    let args = if signature.params().is_empty() {
+        &[]
+    } else {
+        slice::from_raw_parts(args, signature.params().len())
+    };
+    let results = if signature.returns().is_empty() {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(results, signature.returns().len())
+    };
+
+    match &(*func).inner {
+        Extern::Func(Func::Host(func)) => func.callback.call(args, results),
+        Extern::Func(Func::Export { instance, name, .. }) => {
+            let values: Option<Vec<_>> = args.iter().map(wasm_val_t::to_value).collect();
+            let values = match values {
+                Some(values) => values,
+                None => return wasm_trap_t::boxed("functions can only be passed numbers"),
+            };
+            match instance
+                .dyn_func(name)
+                .map_err(CallError::from)
+                .and_then(|func| func.call(&values))
+            {
+                Ok(values) => {
+                    for (result, value) in results.iter_mut().zip(values) {
+                        *result = wasm_val_t::from_value(value);
+                    }
+                    ptr::null_mut()
+                }
+                Err(CallError::Runtime(error)) => wasm_trap_t::from_runtime_error(&error),
+                Err(error) => wasm_trap_t::boxed(&error.to_string()),
+            }
+        }
+        _ => unreachable!("only functions have signatures"),
+    }
+}
+
+/// Returns null if `val` isn't of the type of the global.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_new(
+    _store: *mut wasm_store_t,
+    ty: *const wasm_globaltype_t,
+    val: *const wasm_val_t,
+) -> *mut wasm_global_t {
+    let (content, mutability) = match &(*ty).ty {
+        ExternType::Global {
+            content,
+            mutability,
+        } => (content.kind, *mutability),
+        _ => return ptr::null_mut(),
+    };
+    let value = match (*val).to_value() {
+        Some(value) if content == (*val).kind => value,
+        _ => return ptr::null_mut(),
+    };
+    let global = if mutability == WASM_VAR {
+        Global::new_mutable(value)
+    } else {
+        Global::new(value)
+    };
+    wasm_extern_t::boxed(Extern::Global(global))
+}
+
+/// `None`, after reporting an error, if `global` is an external of
+/// another kind.
+unsafe fn as_global<'a>(global: *const wasm_global_t) -> Option<&'a Global> {
+    match &(*global).inner {
+        Extern::Global(global) => Some(global),
+        _ => {
+            update_last_error(CApiError {
+                msg: "the external isn't a global".to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Returns null if `global` isn't a global.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_type(global: *const wasm_global_t) -> *mut wasm_globaltype_t {
+    let GlobalDescriptor { mutable, ty } = match as_global(global) {
+        Some(global) => global.descriptor(),
+        None => return ptr::null_mut(),
+    };
+    wasm_externtype_t::boxed(ExternType::Global {
+        content: Box::new(wasm_valtype_t { kind: valkind(ty) }),
+        mutability: if mutable { WASM_VAR } else { WASM_CONST },
+    })
+}
+
+/// Leaves `out` as it is if `global` isn't a global.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_get(global: *const wasm_global_t, out: *mut wasm_val_t) {
+    if let Some(global) = as_global(global) {
+        ptr::write(out, wasm_val_t::from_value(global.get()));
+    }
+}
+
+/// Does nothing if `global` isn't a global, the global is immutable,
+/// or `val` is of another type.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_set(global: *mut wasm_global_t, val: *const wasm_val_t) {
+    let global = match as_global(global) {
+        Some(global) => global,
+        None => return,
+    };
+    let GlobalDescriptor { mutable, ty } = global.descriptor();
+    match (*val).to_value() {
+        Some(value) if mutable && value.ty() == ty => global.set(value),
+        _ => update_last_error(CApiError {
+            msg: "the global is immutable, or of another type".to_string(),
+        }),
+    }
+}
+
+/// `None`, after reporting an error, if `table` is an external of
+/// another kind.
+unsafe fn as_table<'a>(table: *const wasm_table_t) -> Option<&'a Table> {
+    match &(*table).inner {
+        Extern::Table(table) => Some(table),
+        _ => {
+            update_last_error(CApiError {
+                msg: "the external isn't a table".to_string(),
+            });
+            None
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_table_size_t = u32;
+
+/// Returns null if `table` isn't a table.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_type(table: *const wasm_table_t) -> *mut wasm_tabletype_t {
+    let descriptor = match as_table(table) {
+        Some(table) => table.descriptor(),
+        None => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(wasm_externtype_t::from_descriptor(
+        &ExternDescriptor::Table(descriptor),
+    )))
+}
+
+/// Returns 0 if `table` isn't a table.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_size(table: *const wasm_table_t) -> wasm_table_size_t {
+    as_table(table).map_or(0, Table::size)
+}
+
+/// `None`, after reporting an error, if `memory` is an external of
+/// another kind.
+unsafe fn as_memory<'a>(memory: *const wasm_memory_t) -> Option<&'a Memory> {
+    match &(*memory).inner {
+        Extern::Memory(memory) => Some(memory),
+        _ => {
+            update_last_error(CApiError {
+                msg: "the external isn't a memory".to_string(),
+            });
+            None
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub type wasm_memory_pages_t = u32;
+
+/// Returns null if the memory can't be allocated.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_new(
+    _store: *mut wasm_store_t,
+    ty: *const wasm_memorytype_t,
+) -> *mut wasm_memory_t {
+    let limits = match &(*ty).ty {
+        ExternType::Memory { limits } => limits,
+        _ => return ptr::null_mut(),
+    };
+    let desc = MemoryDescriptor {
+        minimum: Pages(limits.min),
+        maximum: limits.max().map(Pages),
+        shared: false,
+    };
+    match Memory::new(desc) {
+        Ok(memory) => wasm_extern_t::boxed(Extern::Memory(memory)),
+        Err(error) => {
+            update_last_error(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns null if `memory` isn't a memory.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_type(memory: *const wasm_memory_t) -> *mut wasm_memorytype_t {
+    let descriptor = match as_memory(memory) {
+        Some(memory) => memory.descriptor(),
+        None => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(wasm_externtype_t::from_descriptor(
+        &ExternDescriptor::Memory(descriptor),
+    )))
+}
+
+/// Returns null if `memory` isn't a memory.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_data(memory: *mut wasm_memory_t) -> *mut byte_t {
+    match as_memory(memory) {
+        Some(memory) => memory.view::<u8>()[..].as_ptr() as *mut byte_t,
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns 0 if `memory` isn't a memory.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_data_size(memory: *const wasm_memory_t) -> usize {
+    as_memory(memory).map_or(0, |memory| {
+        let Bytes(size) = memory.size().bytes();
+        size
+    })
+}
+
+/// Returns 0 if `memory` isn't a memory.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_size(memory: *const wasm_memory_t) -> wasm_memory_pages_t {
+    as_memory(memory).map_or(0, |memory| {
+        let Pages(size) = memory.size();
+        size
+    })
+}
+
+/// Returns false if `memory` isn't a memory, or can't grow by `delta`
+/// pages.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_grow(
+    memory: *mut wasm_memory_t,
+    delta: wasm_memory_pages_t,
+) -> bool {
+    as_memory(memory).map_or(false, |memory| memory.grow(Pages(delta)).is_ok())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_kind(external: *const wasm_extern_t) -> wasm_externkind_t {
+    match (*external).inner {
+        Extern::Func(_) => WASM_EXTERN_FUNC,
+        Extern::Global(_) => WASM_EXTERN_GLOBAL,
+        Extern::Table(_) => WASM_EXTERN_TABLE,
+        Extern::Memory(_) => WASM_EXTERN_MEMORY,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_type(
+    external: *const wasm_extern_t,
+) -> *mut wasm_externtype_t {
+    match wasm_extern_kind(external) {
+        WASM_EXTERN_FUNC => wasm_func_type(external),
+        WASM_EXTERN_GLOBAL => wasm_global_type(external),
+        WASM_EXTERN_TABLE => wasm_table_type(external),
+        _ => wasm_memory_type(external),
+    }
+}
+
+/// Declare the functions that delete and copy an external of one kind,
+/// and convert between it and an external, which return null if it's
+/// of another kind.
+macro_rules! extern_kind {
+    ($kind:expr, $delete:ident, $copy:ident, $as_extern:ident, $as_extern_const:ident, $from_extern:ident, $from_extern_const:ident) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn $delete(external: *mut wasm_extern_t) {
+            if !external.is_null() {
+                drop(Box::from_raw(external));
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $copy(external: *const wasm_extern_t) -> *mut wasm_extern_t {
+            Box::into_raw(Box::new((*external).clone()))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn $as_extern(external: *mut wasm_extern_t) -> *mut wasm_extern_t {
+            external
+        }
+
+        #[no_mangle]
+        pub extern "C" fn $as_extern_const(external: *const wasm_extern_t) -> *const wasm_extern_t {
+            external
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_extern(external: *mut wasm_extern_t) -> *mut wasm_extern_t {
+            if wasm_extern_kind(external) == $kind {
+                external
+            } else {
+                ptr::null_mut()
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_extern_const(
+            external: *const wasm_extern_t,
+        ) -> *const wasm_extern_t {
+            if wasm_extern_kind(external) == $kind {
+                external
+            } else {
+                ptr::null()
+            }
+        }
+    };
+}
+
+extern_kind!(
+    WASM_EXTERN_FUNC,
+    wasm_func_delete,
+    wasm_func_copy,
+    wasm_func_as_extern,
+    wasm_func_as_extern_const,
+    wasm_extern_as_func,
+    wasm_extern_as_func_const
+);
+extern_kind!(
+    WASM_EXTERN_GLOBAL,
+    wasm_global_delete,
+    wasm_global_copy,
+    wasm_global_as_extern,
+    wasm_global_as_extern_const,
+    wasm_extern_as_global,
+    wasm_extern_as_global_const
+);
+extern_kind!(
+    WASM_EXTERN_TABLE,
+    wasm_table_delete,
+    wasm_table_copy,
+    wasm_table_as_extern,
+    wasm_table_as_extern_const,
+    wasm_extern_as_table,
+    wasm_extern_as_table_const
+);
+extern_kind!(
+    WASM_EXTERN_MEMORY,
+    wasm_memory_delete,
+    wasm_memory_copy,
+    wasm_memory_as_extern,
+    wasm_memory_as_extern_const,
+    wasm_extern_as_memory,
+    wasm_extern_as_memory_const
+);
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_delete(external: *mut wasm_extern_t) {
+    if !external.is_null() {
+        drop(Box::from_raw(external));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_copy(external: *const wasm_extern_t) -> *mut wasm_extern_t {
+    Box::into_raw(Box::new((*external).clone()))
+}
+
+wasm_vec!(
+    wasm_extern_vec_t,
+    Option<Box<wasm_extern_t>>,
+    wasm_extern_vec_new_empty,
+    wasm_extern_vec_new_uninitialized,
+    wasm_extern_vec_new,
+    wasm_extern_vec_copy,
+    wasm_extern_vec_delete
+);
+
+// Instances
+
+#[allow(non_camel_case_types)]
+pub struct wasm_instance_t {
+    instance: Rc<Instance>,
+    /// The externals that the instance imports, which it refers to.
+    _imports: Vec<Extern>,
+}
+
+/// Instantiate `module` with `imports`, one for each of its imports in
+/// the order of `wasm_module_imports`. Returns null if it can't be
+/// instantiated, and if `trap` isn't null and the start function
+/// trapped, stores the trap in it.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_instance_new(
+    _store: *mut wasm_store_t,
+    module: *const wasm_module_t,
+    imports: *const *const wasm_extern_t,
+    trap: *mut *mut wasm_trap_t,
+) -> *mut wasm_instance_t {
+    let module = &(*module).module;
+    let descriptors = module.imports();
+
+    let mut namespaces = HashMap::new();
+    let mut externals = Vec::with_capacity(descriptors.len());
+    for (index, descriptor) in descriptors.iter().enumerate() {
+        let external = &**imports.add(index);
+        let export = match external.to_export() {
+            Some(export) => export,
+            None => {
+                update_last_error(CApiError {
+                    msg: "an instance lost one of its exports".to_string(),
+                });
+                return ptr::null_mut();
+            }
+        };
+        namespaces
+            .entry(descriptor.namespace.clone())
+            .or_insert_with(Namespace::new)
+            .insert(descriptor.name.clone(), export);
+        externals.push(external.inner.clone());
+    }
+    let mut import_object = ImportObject::new();
+    for (name, namespace) in namespaces {
+        import_object.register(name, namespace);
+    }
+
+    match module.instantiate(&import_object) {
+        Ok(instance) => Box::into_raw(Box::new(wasm_instance_t {
+            instance: Rc::new(instance),
+            _imports: externals,
+        })),
+        Err(error) => {
+            let runtime_error = match &error {
+                Error::RuntimeError(error) | Error::CallError(CallError::Runtime(error)) => {
+                    Some(error)
+                }
+                _ => None,
+            };
+            if let (Some(error), false) = (runtime_error, trap.is_null()) {
+                *trap = wasm_trap_t::from_runtime_error(error);
+            }
+            update_last_error(CApiError {
+                msg: error.to_string(),
+            });
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_instance_delete(instance: *mut wasm_instance_t) {
+    if !instance.is_null() {
+        drop(Box::from_raw(instance));
+    }
+}
+
+/// The exports of `instance`, in the order of `wasm_module_exports`.
+/// Its functions keep it alive.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_instance_exports(
+    instance: *const wasm_instance_t,
+    out: *mut wasm_extern_vec_t,
+) {
+    let instance = &(*instance).instance;
+    let exports = instance
+        .module()
+        .exports()
+        .into_iter()
+        .map(|descriptor| {
+            let inner = match instance.get_export(&descriptor.name) {
+                Some(Export::Function { signature, .. }) => Extern::Func(Func::Export {
+                    instance: Rc::clone(instance),
+                    name: descriptor.name,
+                    signature,
+                }),
+                Some(Export::Global(global)) => Extern::Global(global),
+                Some(Export::Table(table)) => Extern::Table(table),
+                Some(Export::Memory(memory)) => Extern::Memory(memory),
+                None => unreachable!("an instance lost one of its exports"),
+            };
+            Some(Box::new(wasm_extern_t { inner }))
+        })
+        .collect();
+    ptr::write(out, wasm_extern_vec_t::from_vec(exports));
+}
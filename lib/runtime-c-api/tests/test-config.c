@@ -0,0 +1,65 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+int main()
+{
+    // Read the wasm file bytes
+    FILE *file = fopen("assets/sum.wasm", "r");
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    uint8_t *bytes = malloc(len);
+    fseek(file, 0, SEEK_SET);
+    fread(bytes, 1, len, file);
+    fclose(file);
+
+    wasmer_config_t *config = wasmer_config_new();
+    assert(wasmer_config_set_backend(config, WASMER_BACKEND_CRANELIFT) == WASMER_OK);
+    wasmer_config_set_opt_level(config, WASMER_OPT_LEVEL_NONE);
+    wasmer_config_set_feature(config, WASMER_FEATURE_BULK_MEMORY, true);
+
+    wasmer_module_t *module = NULL;
+    wasmer_result_t compile_result = wasmer_compile_with_config(&module, bytes, len, config);
+    printf("Compile result: %d\n", compile_result);
+    assert(compile_result == WASMER_OK);
+    wasmer_module_destroy(module);
+
+    // Metered instances trap once they run out of points.
+    wasmer_config_set_metering(config, 1000);
+    wasmer_config_set_cost_table(config, WASMER_COST_TABLE_WEIGHTED);
+    wasmer_import_t imports[] = {};
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t instantiate_result = wasmer_instantiate_with_config(&instance, bytes, len, imports, 0, config);
+    printf("Instantiate result: %d\n", instantiate_result);
+    assert(instantiate_result == WASMER_OK);
+    assert(wasmer_instance_get_remaining_points(instance) == 1000);
+    assert(wasmer_instance_get_points_used(instance) == 0);
+
+    wasmer_value_t param_one;
+    param_one.tag = WASM_I32;
+    param_one.value.I32 = 7;
+    wasmer_value_t param_two;
+    param_two.tag = WASM_I32;
+    param_two.value.I32 = 8;
+    wasmer_value_t params[] = {param_one, param_two};
+    wasmer_value_t results[1];
+    wasmer_result_t call_result = wasmer_instance_call(instance, "sum", params, 2, results, 1);
+    assert(call_result == WASMER_OK);
+    assert(results[0].value.I32 == 15);
+    uint64_t points_used = wasmer_instance_get_points_used(instance);
+    assert(points_used > 0);
+    assert(wasmer_instance_get_remaining_points(instance) == 1000 - points_used);
+
+    wasmer_instance_set_remaining_points(instance, 0);
+    call_result = wasmer_instance_call(instance, "sum", params, 2, results, 1);
+    assert(call_result == WASMER_ERROR);
+    assert(wasmer_last_trap_code() == WASMER_TRAP_OUT_OF_GAS);
+    assert(wasmer_instance_get_points_used(instance) == 0);
+
+    wasmer_instance_destroy(instance);
+    wasmer_config_destroy(config);
+    free(bytes);
+    return 0;
+}
@@ -0,0 +1,79 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+struct output
+{
+    char bytes[64];
+    uint32_t len;
+};
+
+int write_output(void *data, int fd, const uint8_t *bytes, uint32_t bytes_len)
+{
+    struct output *output = data;
+    assert(fd == 1);
+    assert(output->len + bytes_len < sizeof(output->bytes));
+    memcpy(output->bytes + output->len, bytes, bytes_len);
+    output->len += bytes_len;
+    return bytes_len;
+}
+
+wasmer_module_t *compile_file(const char *path)
+{
+    FILE *file = fopen(path, "r");
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    uint8_t *bytes = malloc(len);
+    fseek(file, 0, SEEK_SET);
+    fread(bytes, 1, len, file);
+    fclose(file);
+
+    wasmer_module_t *module = NULL;
+    wasmer_result_t compile_result = wasmer_compile(&module, bytes, len);
+    assert(compile_result == WASMER_OK);
+    free(bytes);
+    return module;
+}
+
+int main()
+{
+    wasmer_module_t *sum_module = compile_file("assets/sum.wasm");
+    assert(!wasmer_is_emscripten_module(sum_module));
+    wasmer_module_destroy(sum_module);
+
+    wasmer_module_t *module = compile_file("assets/emscripten_puts.wasm");
+    assert(wasmer_is_emscripten_module(module));
+
+    wasmer_emscripten_globals_t *globals = wasmer_emscripten_globals_new(module);
+    assert(globals);
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t instantiate_result = wasmer_emscripten_module_instantiate(module, globals, &instance);
+    printf("Instantiate result: %d\n", instantiate_result);
+    assert(instantiate_result == WASMER_OK);
+
+    const char *args[] = {"first", "second"};
+    wasmer_result_t call_result = wasmer_emscripten_call_main(instance, "puts.wasm", args, 2);
+    printf("Call result: %d\n", call_result);
+    assert(call_result == WASMER_OK);
+
+    wasmer_instance_destroy(instance);
+    wasmer_emscripten_globals_destroy(globals);
+
+    // The output of an instance can go to the embedder instead.
+    globals = wasmer_emscripten_globals_new(module);
+    instantiate_result = wasmer_emscripten_module_instantiate(module, globals, &instance);
+    assert(instantiate_result == WASMER_OK);
+    struct output output = {{0}, 0};
+    call_result = wasmer_emscripten_call_main_with_stdio(instance, "puts.wasm", args, 2, write_output, NULL, &output);
+    assert(call_result == WASMER_OK);
+    output.bytes[output.len] = '\0';
+    assert(strcmp(output.bytes, "Hello, World!\n") == 0);
+
+    wasmer_instance_destroy(instance);
+    wasmer_emscripten_globals_destroy(globals);
+    wasmer_module_destroy(module);
+    return 0;
+}
@@ -16,7 +16,8 @@ int main()
     wasmer_value_t val2;
     val2.tag = WASM_I32;
     val2.value.I32 = 14;
-    wasmer_global_set(global, val2);
+    wasmer_result_t set_result = wasmer_global_set(global, val2);
+    assert(set_result == WASMER_OK);
 
     wasmer_value_t new_get_val = wasmer_global_get(global);
     assert( new_get_val.value.I32 == 14);
@@ -25,6 +26,27 @@ int main()
     assert(desc.mutable_);
     assert(desc.kind == WASM_I32);
 
+    // Setting a value of another type fails.
+    wasmer_value_t val3;
+    val3.tag = WASM_F64;
+    val3.value.F64 = 1.5;
+    set_result = wasmer_global_set(global, val3);
+    assert(set_result == WASMER_ERROR);
+    assert(wasmer_global_get(global).value.I32 == 14);
+
+    // Setting an immutable global fails.
+    wasmer_value_t config;
+    config.tag = WASM_I64;
+    config.value.I64 = 1024;
+    wasmer_global_t *immutable_global = wasmer_global_new(config, false);
+    wasmer_global_descriptor_t immutable_desc = wasmer_global_get_descriptor(immutable_global);
+    assert(!immutable_desc.mutable_);
+    assert(immutable_desc.kind == WASM_I64);
+    set_result = wasmer_global_set(immutable_global, config);
+    assert(set_result == WASMER_ERROR);
+    assert(wasmer_global_get(immutable_global).value.I64 == 1024);
+
+    wasmer_global_destroy(immutable_global);
     wasmer_global_destroy(global);
     return 0;
 }
@@ -0,0 +1,89 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    int calls;
+    char str[14];
+    bool finalized;
+} printer_t;
+
+void print_str(wasmer_func_env_t *func_env, int32_t ptr, int32_t len)
+{
+    printer_t *printer = (printer_t *) wasmer_func_env_data(func_env);
+    const wasmer_instance_context_t *ctx = wasmer_func_env_context(func_env);
+    const wasmer_memory_t *memory = wasmer_instance_context_memory(ctx, 0);
+    uint8_t *mem_bytes = wasmer_memory_data(memory);
+    for (int32_t idx = 0; idx < len && idx < 13; idx++)
+    {
+        printer->str[idx] = mem_bytes[ptr + idx];
+    }
+    printer->str[13] = '\0';
+    printer->calls++;
+}
+
+void finalize_printer(void *env)
+{
+    ((printer_t *) env)->finalized = true;
+}
+
+int main()
+{
+    printer_t printer = {0};
+
+    wasmer_value_tag params_sig[] = {WASM_I32, WASM_I32};
+    wasmer_value_tag returns_sig[] = {};
+    wasmer_import_func_t *func = wasmer_import_func_new_with_env(
+        (void (*)(void *)) print_str, params_sig, 2, returns_sig, 0, &printer, finalize_printer);
+
+    char *module_name = "env";
+    wasmer_byte_array module_name_bytes;
+    module_name_bytes.bytes = (const uint8_t *) module_name;
+    module_name_bytes.bytes_len = strlen(module_name);
+    char *import_name = "print_str";
+    wasmer_byte_array import_name_bytes;
+    import_name_bytes.bytes = (const uint8_t *) import_name;
+    import_name_bytes.bytes_len = strlen(import_name);
+
+    wasmer_import_t import;
+    import.module_name = module_name_bytes;
+    import.import_name = import_name_bytes;
+    import.tag = WASM_FUNCTION;
+    import.value.func = func;
+    wasmer_import_t imports[] = {import};
+
+    // Read the wasm file bytes
+    FILE *file = fopen("assets/wasm_sample_app.wasm", "r");
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    uint8_t *bytes = malloc(len);
+    fseek(file, 0, SEEK_SET);
+    fread(bytes, 1, len, file);
+    fclose(file);
+
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t compile_result = wasmer_instantiate(&instance, bytes, len, imports, 1);
+    assert(compile_result == WASMER_OK);
+
+    // The instance keeps the environment alive.
+    wasmer_import_func_destroy(func);
+    assert(!printer.finalized);
+
+    wasmer_value_t params[] = {};
+    wasmer_value_t results[] = {};
+    wasmer_result_t call_result = wasmer_instance_call(instance, "hello_wasm", params, 0, results, 0);
+    assert(call_result == WASMER_OK);
+    call_result = wasmer_instance_call(instance, "hello_wasm", params, 0, results, 0);
+    assert(call_result == WASMER_OK);
+
+    assert(printer.calls == 2);
+    assert(0 == strcmp(printer.str, "Hello, World!"));
+
+    wasmer_instance_destroy(instance);
+    assert(printer.finalized);
+    free(bytes);
+    return 0;
+}
@@ -0,0 +1,195 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+bool static print_str_called = false;
+
+void print_str(const wasmer_instance_context_t *ctx, int32_t ptr, int32_t len)
+{
+    print_str_called = true;
+}
+
+wasmer_byte_array byte_array(const char *string)
+{
+    wasmer_byte_array bytes;
+    bytes.bytes = (const uint8_t *) string;
+    bytes.bytes_len = strlen(string);
+    return bytes;
+}
+
+wasmer_module_t *compile_file(const char *path)
+{
+    FILE *file = fopen(path, "r");
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    uint8_t *bytes = malloc(len);
+    fseek(file, 0, SEEK_SET);
+    fread(bytes, 1, len, file);
+    fclose(file);
+
+    wasmer_module_t *module = NULL;
+    wasmer_result_t compile_result = wasmer_compile(&module, bytes, len);
+    assert(compile_result == WASMER_OK);
+    free(bytes);
+    return module;
+}
+
+bool equals(wasmer_byte_array bytes, const char *string)
+{
+    return bytes.bytes_len == strlen(string) && memcmp(bytes.bytes, string, bytes.bytes_len) == 0;
+}
+
+// Resolves `env._print_str` to `print_str`, and nothing else.
+wasmer_result_t resolve_print_str(void *data, const wasmer_import_descriptor_t *descriptor, wasmer_import_func_t **func)
+{
+    int *resolved = data;
+    if (!equals(wasmer_import_descriptor_module_name(descriptor), "env") ||
+        !equals(wasmer_import_descriptor_name(descriptor), "_print_str"))
+    {
+        return WASMER_ERROR;
+    }
+    uint32_t params_arity;
+    assert(wasmer_import_descriptor_func_params_arity(descriptor, &params_arity) == WASMER_OK);
+    assert(params_arity == 2);
+    wasmer_value_tag params[2];
+    assert(wasmer_import_descriptor_func_params(descriptor, params, 2) == WASMER_OK);
+    assert(params[0] == WASM_I32 && params[1] == WASM_I32);
+    uint32_t returns_arity;
+    assert(wasmer_import_descriptor_func_returns_arity(descriptor, &returns_arity) == WASMER_OK);
+    assert(returns_arity == 0);
+
+    wasmer_value_tag returns[] = {};
+    *func = wasmer_import_func_new((void (*)(void *)) print_str, params, 2, returns, 0);
+    ++*resolved;
+    return WASMER_OK;
+}
+
+wasmer_result_t resolve_nothing(void *data, const wasmer_import_descriptor_t *descriptor, wasmer_import_func_t **func)
+{
+    return WASMER_ERROR;
+}
+
+// Whether `import_object` provides all the imports of `module`.
+bool provides_imports(wasmer_import_object_t *import_object, wasmer_module_t *module)
+{
+    wasmer_import_descriptors_t *import_descriptors;
+    wasmer_import_descriptors(module, &import_descriptors);
+    bool provides = true;
+    for (int i = 0; i < wasmer_import_descriptors_len(import_descriptors); ++i)
+    {
+        wasmer_import_descriptor_t *descriptor = wasmer_import_descriptors_get(import_descriptors, i);
+        provides = provides && wasmer_import_object_contains(
+                                   import_object,
+                                   wasmer_import_descriptor_module_name(descriptor),
+                                   wasmer_import_descriptor_name(descriptor));
+    }
+    wasmer_import_descriptors_destroy(import_descriptors);
+    return provides;
+}
+
+int main()
+{
+    // The host functions, in their own import object.
+    wasmer_value_tag params_sig[] = {WASM_I32, WASM_I32};
+    wasmer_value_tag returns_sig[] = {};
+    wasmer_import_func_t *func = wasmer_import_func_new((void (*)(void *)) print_str, params_sig, 2, returns_sig, 0);
+    wasmer_import_t func_import;
+    func_import.module_name = byte_array("env");
+    func_import.import_name = byte_array("_print_str");
+    func_import.tag = WASM_FUNCTION;
+    func_import.value.func = func;
+    wasmer_import_object_t *functions = wasmer_import_object_new();
+    assert(wasmer_import_object_extend(functions, &func_import, 1) == WASMER_OK);
+
+    // The memory, table and global, in another one.
+    wasmer_memory_t *memory = NULL;
+    wasmer_limits_t descriptor;
+    descriptor.min = 256;
+    descriptor.max.has_some = true;
+    descriptor.max.some = 256;
+    assert(wasmer_memory_new(&memory, descriptor) == WASMER_OK);
+    wasmer_import_t memory_import;
+    memory_import.module_name = byte_array("env");
+    memory_import.import_name = byte_array("memory");
+    memory_import.tag = WASM_MEMORY;
+    memory_import.value.memory = memory;
+
+    wasmer_table_t *table = NULL;
+    assert(wasmer_table_new(&table, descriptor) == WASMER_OK);
+    wasmer_import_t table_import;
+    table_import.module_name = byte_array("env");
+    table_import.import_name = byte_array("table");
+    table_import.tag = WASM_TABLE;
+    table_import.value.table = table;
+
+    wasmer_value_t val;
+    val.tag = WASM_I32;
+    val.value.I32 = 1024;
+    wasmer_global_t *global = wasmer_global_new(val, false);
+    wasmer_import_t global_import;
+    global_import.module_name = byte_array("env");
+    global_import.import_name = byte_array("__memory_base");
+    global_import.tag = WASM_GLOBAL;
+    global_import.value.global = global;
+
+    wasmer_import_t imports[] = {memory_import, table_import, global_import};
+    wasmer_import_object_t *import_object = wasmer_import_object_new();
+    assert(wasmer_import_object_extend(import_object, imports, 3) == WASMER_OK);
+
+    wasmer_module_t *module = compile_file("assets/hello_wasm.wasm");
+    assert(!provides_imports(import_object, module));
+
+    // A resolver can provide the missing functions instead.
+    int resolved = 0;
+    wasmer_instance_t *resolved_instance = NULL;
+    wasmer_result_t resolved_result = wasmer_module_import_instantiate_with_resolver(module, &resolved_instance, import_object, resolve_print_str, &resolved);
+    assert(resolved_result == WASMER_OK);
+    assert(resolved == 1);
+    wasmer_value_t no_params[] = {};
+    wasmer_value_t no_results[1];
+    assert(wasmer_instance_call(resolved_instance, "_hello_wasm", no_params, 0, no_results, 1) == WASMER_OK);
+    assert(print_str_called);
+    print_str_called = false;
+    wasmer_instance_destroy(resolved_instance);
+
+    resolved_result = wasmer_module_import_instantiate_with_resolver(module, &resolved_instance, import_object, resolve_nothing, NULL);
+    assert(resolved_result == WASMER_ERROR);
+
+    // Both namespaces are `env`, so they are combined.
+    wasmer_import_object_merge(import_object, functions);
+    assert(provides_imports(import_object, module));
+
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t instantiate_result = wasmer_module_import_instantiate(module, &instance, import_object);
+    printf("Instantiate result: %d\n", instantiate_result);
+    assert(instantiate_result == WASMER_OK);
+
+    wasmer_value_t params[] = {};
+    wasmer_value_t results[1];
+    wasmer_result_t call_result = wasmer_instance_call(instance, "_hello_wasm", params, 0, results, 1);
+    assert(call_result == WASMER_OK);
+    assert(print_str_called);
+
+    // The exports of an instance make a namespace.
+    wasmer_module_t *sum_module = compile_file("assets/sum.wasm");
+    wasmer_instance_t *sum_instance = NULL;
+    wasmer_import_t no_imports[] = {};
+    assert(wasmer_module_instantiate(sum_module, &sum_instance, no_imports, 0) == WASMER_OK);
+    assert(!wasmer_import_object_contains(import_object, byte_array("math"), byte_array("sum")));
+    assert(wasmer_import_object_register_instance(import_object, byte_array("math"), sum_instance) == WASMER_OK);
+    assert(wasmer_import_object_contains(import_object, byte_array("math"), byte_array("sum")));
+
+    wasmer_import_object_destroy(import_object);
+    wasmer_instance_destroy(sum_instance);
+    wasmer_module_destroy(sum_module);
+    wasmer_instance_destroy(instance);
+    wasmer_module_destroy(module);
+    wasmer_import_func_destroy(func);
+    wasmer_global_destroy(global);
+    wasmer_memory_destroy(memory);
+    wasmer_table_destroy(table);
+    return 0;
+}
@@ -0,0 +1,51 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// (module (func (export "spin") (loop (br 0))))
+static const uint8_t SPIN_WASM[] = {
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+    // Type section: () -> ()
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+    // Function section
+    0x03, 0x02, 0x01, 0x00,
+    // Export section: "spin"
+    0x07, 0x08, 0x01, 0x04, 's', 'p', 'i', 'n', 0x00, 0x00,
+    // Code section: loop forever
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+};
+
+int main()
+{
+    wasmer_import_t imports[] = {};
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t compile_result = wasmer_instantiate(&instance, (uint8_t *) SPIN_WASM, sizeof(SPIN_WASM), imports, 0);
+    assert(compile_result == WASMER_OK);
+
+    wasmer_value_t params[] = {};
+    wasmer_value_t results[] = {};
+
+    // Interrupting an instance that isn't running anything interrupts
+    // its next call, so this doesn't need a second thread.
+    wasmer_interrupt_handle_t *handle = wasmer_instance_interrupt_handle(instance);
+    wasmer_interrupt(handle);
+    wasmer_result_t call_result = wasmer_instance_call(instance, "spin", params, 0, results, 0);
+    assert(call_result == WASMER_ERROR);
+    printf("Trap code: %d\n", wasmer_last_trap_code());
+    assert(wasmer_last_trap_code() == WASMER_TRAP_INTERRUPTED);
+    wasmer_interrupt_handle_destroy(handle);
+
+    // The interrupt was cleared when that call failed, so this one runs
+    // until its deadline. A deadline of the current epoch has already
+    // been reached.
+    wasmer_instance_set_epoch_deadline(instance, 0);
+    call_result = wasmer_instance_call(instance, "spin", params, 0, results, 0);
+    assert(call_result == WASMER_ERROR);
+    printf("Trap code: %d\n", wasmer_last_trap_code());
+    assert(wasmer_last_trap_code() == WASMER_TRAP_DEADLINE_EXCEEDED);
+
+    wasmer_instance_destroy(instance);
+    return 0;
+}
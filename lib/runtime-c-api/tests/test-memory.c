@@ -4,6 +4,16 @@
 #include <stdint.h>
 #include <string.h>
 
+static uint32_t grown_from = 0;
+static uint32_t grown_to = 0;
+
+void on_grow(void *data, uint32_t old_pages, uint32_t new_pages)
+{
+    assert(*(int *) data == 42);
+    grown_from = old_pages;
+    grown_to = new_pages;
+}
+
 int main()
 {
     wasmer_memory_t *memory = NULL;
@@ -32,6 +42,15 @@ int main()
     printf("Memory bytes length:  %d\n", bytes_len);
     assert(bytes_len == 12 * 65536);
 
+    uint32_t page_size = wasmer_memory_page_size();
+    printf("Page size:  %d\n", page_size);
+    assert(page_size == 65536);
+
+    wasmer_limits_t limits = wasmer_memory_limits(memory);
+    assert(limits.min == 10);
+    assert(limits.max.has_some);
+    assert(limits.max.some == 15);
+
     // Err, grow beyond max
     wasmer_result_t grow_result2 = wasmer_memory_grow(memory, 10);
     assert(grow_result2 == WASMER_ERROR);
@@ -42,6 +61,35 @@ int main()
     assert(0 == strcmp(error_str, "Failed to add pages because would exceed maximum number of pages for the memory. Left: 22, Added: 15"));
     free(error_str);
 
+    // The bytes can be written directly, and are still there once the
+    // memory has grown.
+    uint8_t *data = wasmer_memory_data(memory);
+    data[0] = 42;
+    data[bytes_len - 1] = 43;
+    assert(wasmer_memory_grow(memory, 1) == WASMER_OK);
+    data = wasmer_memory_data(memory);
+    assert(data[0] == 42);
+    assert(data[bytes_len - 1] == 43);
+    assert(data[bytes_len] == 0);
+    assert(wasmer_memory_data_length(memory) == 13 * page_size);
+
+    // A view stays valid until the memory grows, which the grow
+    // callback tells of.
+    int grow_data = 42;
+    assert(wasmer_memory_set_grow_callback(memory, on_grow, &grow_data) == WASMER_OK);
+    wasmer_memory_view_t view = wasmer_memory_view(memory);
+    assert(view.data == data);
+    assert(view.data_len == 13 * page_size);
+    assert(wasmer_memory_grow(memory, 1) == WASMER_OK);
+    assert(grown_from == 13);
+    assert(grown_to == 14);
+    view = wasmer_memory_view(memory);
+    assert(view.data[0] == 42);
+    assert(view.data_len == 14 * page_size);
+    wasmer_memory_clear_grow_callback(memory);
+    assert(wasmer_memory_grow(memory, 1) == WASMER_OK);
+    assert(grown_to == 14);
+
     wasmer_memory_t *bad_memory = NULL;
     wasmer_limits_t bad_descriptor;
     bad_descriptor.min = 15;
@@ -45,6 +45,22 @@ int main()
         assert(name_bytes.bytes[idx] == expected[idx]);
     }
 
+    uint32_t params_arity;
+    assert(wasmer_export_descriptor_func_params_arity(export, &params_arity) == WASMER_OK);
+    assert(params_arity == 2);
+    wasmer_value_tag params[2];
+    assert(wasmer_export_descriptor_func_params(export, params, 1) == WASMER_ERROR);
+    assert(wasmer_export_descriptor_func_params(export, params, 2) == WASMER_OK);
+    assert(params[0] == WASM_I32);
+    assert(params[1] == WASM_I32);
+
+    uint32_t returns_arity;
+    assert(wasmer_export_descriptor_func_returns_arity(export, &returns_arity) == WASMER_OK);
+    assert(returns_arity == 1);
+    wasmer_value_tag returns[1];
+    assert(wasmer_export_descriptor_func_returns(export, returns, 1) == WASMER_OK);
+    assert(returns[0] == WASM_I32);
+
     printf("Destroy module\n");
     wasmer_module_destroy(module);
     printf("Destroy exports\n");
@@ -2,6 +2,8 @@
 #include "../wasmer.h"
 #include <assert.h>
 #include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
 
 int main()
 {
@@ -36,6 +38,8 @@ int main()
     assert(serialized_module_bytes.bytes[4] == 'E');
     assert(serialized_module_bytes.bytes[5] == 'R');
 
+    assert(wasmer_serialized_module_validate(serialized_module));
+
     wasmer_module_t *module_two = NULL;
     wasmer_result_t unserialize_result = wasmer_module_deserialize(&module_two, serialized_module);
     assert(unserialize_result == WASMER_OK);
@@ -71,6 +75,20 @@ int main()
     );
     assert(serialized_module_from_bytes_result == WASMER_OK);
 
+    assert(wasmer_serialized_module_validate(serialized_module_two));
+
+    // A corrupted module doesn't validate, nor deserialize.
+    uint8_t *corrupted_bytes = malloc(serialized_module_bytes.bytes_len);
+    memcpy(corrupted_bytes, serialized_module_bytes.bytes, serialized_module_bytes.bytes_len);
+    corrupted_bytes[serialized_module_bytes.bytes_len - 1] ^= 0xff;
+    wasmer_serialized_module_t *corrupted_module = NULL;
+    wasmer_serialized_module_from_bytes(&corrupted_module, corrupted_bytes, serialized_module_bytes.bytes_len);
+    free(corrupted_bytes);
+    assert(!wasmer_serialized_module_validate(corrupted_module));
+    wasmer_module_t *corrupted_deserialized_module = NULL;
+    assert(wasmer_module_deserialize(&corrupted_deserialized_module, corrupted_module) == WASMER_ERROR);
+    wasmer_serialized_module_destroy(corrupted_module);
+
     wasmer_module_t *module_three = NULL;
     wasmer_result_t unserialized_result_two = wasmer_module_deserialize(&module_three, serialized_module_two);
     assert(unserialized_result_two == WASMER_OK);
@@ -19,6 +19,16 @@ int main()
     printf("Compile result:  %d\n", compile_result);
     assert(compile_result == WASMER_OK);
 
+    // `sum.wasm` has a `name` section, and no other custom section.
+    const uint8_t *section_bytes = NULL;
+    uint32_t section_len = 0;
+    wasmer_result_t section_result = wasmer_module_custom_section(module, "name", &section_bytes, &section_len);
+    assert(section_result == WASMER_OK);
+    assert(section_bytes != NULL);
+    assert(section_len == 23);
+    section_result = wasmer_module_custom_section(module, "producers", &section_bytes, &section_len);
+    assert(section_result == WASMER_ERROR);
+
     wasmer_import_t imports[] = {};
     wasmer_instance_t *instance = NULL;
     wasmer_result_t instantiate_result = wasmer_module_instantiate(module, &instance, imports, 0);
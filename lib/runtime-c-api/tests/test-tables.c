@@ -3,6 +3,11 @@
 #include <assert.h>
 #include <stdint.h>
 
+int32_t double_it(wasmer_instance_context_t *ctx, int32_t x)
+{
+    return x * 2;
+}
+
 int main()
 {
     wasmer_table_t *table = NULL;
@@ -34,6 +39,42 @@ int main()
     printf("Table length:  %d\n", len_grow2);
     assert(len_grow2 == 15);
 
+    // Slots start out empty.
+    wasmer_funcref_t element = 1;
+    wasmer_result_t get_result = wasmer_table_get(table, 3, &element);
+    assert(get_result == WASMER_OK);
+    assert(element == 0);
+
+    // Write a host function into a slot, and copy it to another one.
+    wasmer_value_tag params_sig[] = {WASM_I32};
+    wasmer_value_tag returns_sig[] = {WASM_I32};
+    wasmer_import_func_t *func = wasmer_import_func_new((void (*)(void *)) double_it, params_sig, 1, returns_sig, 1);
+    wasmer_result_t set_func_result = wasmer_table_set_func(table, 3, func, NULL);
+    assert(set_func_result == WASMER_OK);
+    wasmer_import_func_destroy(func);
+
+    get_result = wasmer_table_get(table, 3, &element);
+    assert(get_result == WASMER_OK);
+    assert(element != 0);
+
+    wasmer_result_t set_result = wasmer_table_set(table, 14, element);
+    assert(set_result == WASMER_OK);
+    wasmer_funcref_t copied_element = 0;
+    wasmer_table_get(table, 14, &copied_element);
+    assert(copied_element == element);
+
+    // Empty a slot.
+    set_result = wasmer_table_set(table, 3, 0);
+    assert(set_result == WASMER_OK);
+    wasmer_table_get(table, 3, &element);
+    assert(element == 0);
+
+    // Out of bounds.
+    get_result = wasmer_table_get(table, 15, &element);
+    assert(get_result == WASMER_ERROR);
+    set_result = wasmer_table_set(table, 15, 0);
+    assert(set_result == WASMER_ERROR);
+
     wasmer_table_t *table_bad = NULL;
     wasmer_limits_t bad_descriptor;
     bad_descriptor.min = 15;
@@ -0,0 +1,91 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <pthread.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define THREADS 8
+
+static uint8_t *bytes = NULL;
+static long bytes_len = 0;
+
+// A module that all threads instantiate.
+static wasmer_module_t *shared_module = NULL;
+
+void call_sum(wasmer_instance_t *instance, int32_t a, int32_t b)
+{
+    wasmer_value_t param_one;
+    param_one.tag = WASM_I32;
+    param_one.value.I32 = a;
+    wasmer_value_t param_two;
+    param_two.tag = WASM_I32;
+    param_two.value.I32 = b;
+    wasmer_value_t params[] = {param_one, param_two};
+    wasmer_value_t results[1];
+    wasmer_result_t call_result = wasmer_instance_call(instance, "sum", params, 2, results, 1);
+    assert(call_result == WASMER_OK);
+    assert(results[0].value.I32 == a + b);
+}
+
+void *run(void *arg)
+{
+    int32_t id = (int32_t) (intptr_t) arg;
+    wasmer_import_t imports[] = {};
+
+    // Compile a module of its own, while other threads compile theirs.
+    wasmer_module_t *module = NULL;
+    assert(wasmer_compile(&module, bytes, bytes_len) == WASMER_OK);
+    wasmer_instance_t *instance = NULL;
+    assert(wasmer_module_instantiate(module, &instance, imports, 0) == WASMER_OK);
+    call_sum(instance, id, 1);
+    wasmer_instance_destroy(instance);
+    wasmer_module_destroy(module);
+
+    // Instantiate the shared module, each thread with its own instance.
+    assert(wasmer_module_instantiate(shared_module, &instance, imports, 0) == WASMER_OK);
+    call_sum(instance, id, 2);
+
+    // Errors belong to the thread that caused them.
+    if (id % 2 == 0)
+    {
+        wasmer_value_t params[] = {};
+        wasmer_value_t results[1];
+        assert(wasmer_instance_call(instance, "missing", params, 0, results, 1) == WASMER_ERROR);
+        assert(wasmer_last_error_length() > 0);
+    }
+    else
+    {
+        assert(wasmer_last_error_length() == 0);
+    }
+    wasmer_instance_destroy(instance);
+    return NULL;
+}
+
+int main()
+{
+    // Read the wasm file bytes
+    FILE *file = fopen("assets/sum.wasm", "r");
+    fseek(file, 0, SEEK_END);
+    bytes_len = ftell(file);
+    bytes = malloc(bytes_len);
+    fseek(file, 0, SEEK_SET);
+    fread(bytes, 1, bytes_len, file);
+    fclose(file);
+
+    assert(wasmer_compile(&shared_module, bytes, bytes_len) == WASMER_OK);
+
+    pthread_t threads[THREADS];
+    for (intptr_t i = 0; i < THREADS; ++i)
+    {
+        assert(pthread_create(&threads[i], NULL, run, (void *) i) == 0);
+    }
+    for (int i = 0; i < THREADS; ++i)
+    {
+        assert(pthread_join(threads[i], NULL) == 0);
+    }
+
+    wasmer_module_destroy(shared_module);
+    free(bytes);
+    return 0;
+}
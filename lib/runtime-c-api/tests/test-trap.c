@@ -0,0 +1,77 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+// (module (func $trap (export "trap") unreachable)), with a name section.
+static const uint8_t TRAP_WASM[] = {
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+    // Type section: () -> ()
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+    // Function section
+    0x03, 0x02, 0x01, 0x00,
+    // Export section: "trap"
+    0x07, 0x08, 0x01, 0x04, 't', 'r', 'a', 'p', 0x00, 0x00,
+    // Code section: unreachable
+    0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b,
+    // Name section: function 0 is "trap"
+    0x00, 0x0e, 0x04, 'n', 'a', 'm', 'e', 0x01, 0x07, 0x01, 0x00, 0x04, 't', 'r', 'a', 'p',
+};
+
+int main()
+{
+    wasmer_import_t imports[] = {};
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t compile_result = wasmer_instantiate(&instance, (uint8_t *) TRAP_WASM, sizeof(TRAP_WASM), imports, 0);
+    assert(compile_result == WASMER_OK);
+
+    wasmer_value_t params[] = {};
+    wasmer_value_t results[] = {};
+    wasmer_result_t call_result = wasmer_instance_call(instance, "trap", params, 0, results, 0);
+    assert(call_result == WASMER_ERROR);
+
+    wasmer_trap_code_t code = wasmer_last_trap_code();
+    printf("Trap code: %d\n", code);
+    assert(code == WASMER_TRAP_UNREACHABLE);
+
+    // Backends that find the frames report the function that trapped first.
+    int frames_len = wasmer_last_trap_frames_length();
+    printf("Frames: %d\n", frames_len);
+    if (frames_len > 0)
+    {
+        wasmer_trap_frame_t frame;
+        assert(wasmer_last_trap_frame(0, &frame) == WASMER_OK);
+        assert(frame.func_index == 0);
+        assert(frame.func_name != NULL);
+        assert(0 == strcmp(frame.func_name, "trap"));
+    }
+    wasmer_trap_frame_t missing_frame;
+    assert(wasmer_last_trap_frame(frames_len, &missing_frame) == WASMER_ERROR);
+
+    int backtrace_len = wasmer_last_trap_backtrace_length();
+    char *backtrace = malloc(backtrace_len);
+    int written = wasmer_last_trap_backtrace(backtrace, backtrace_len);
+    printf("Backtrace:\n%s", backtrace);
+    assert(written == backtrace_len - 1);
+    assert(frames_len == 0 || strstr(backtrace, "0: trap") == backtrace);
+    free(backtrace);
+
+    // The message is still there.
+    int error_len = wasmer_last_error_length();
+    char *error_str = malloc(error_len);
+    wasmer_last_error_message(error_str, error_len);
+    printf("Error str: `%s`\n", error_str);
+    assert(strstr(error_str, "unreachable") != NULL);
+    free(error_str);
+
+    // A call that fails without running isn't a trap.
+    call_result = wasmer_instance_call(instance, "missing", params, 0, results, 0);
+    assert(call_result == WASMER_ERROR);
+    assert(wasmer_last_trap_code() == WASMER_TRAP_NONE);
+    assert(wasmer_last_trap_frames_length() == 0);
+
+    wasmer_instance_destroy(instance);
+    return 0;
+}
@@ -0,0 +1,16 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <string.h>
+
+int main()
+{
+    printf("Wasmer version: %s\n", wasmer_version());
+    assert(strcmp(wasmer_version(), WASMER_VERSION) == 0);
+    assert(wasmer_version_major() == WASMER_VERSION_MAJOR);
+    assert(wasmer_version_minor() == WASMER_VERSION_MINOR);
+    assert(wasmer_version_patch() == WASMER_VERSION_PATCH);
+    assert(wasmer_abi_version() == WASMER_ABI_VERSION);
+    return 0;
+}
@@ -0,0 +1,208 @@
+#include <stdio.h>
+#include "../wasm.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int32_t print_str_ptr = -1;
+static int32_t print_str_len = -1;
+static int finalized_env = 0;
+
+wasm_trap_t *print_str(void *env, const wasm_val_t args[], wasm_val_t results[])
+{
+    assert(*(int *) env == 42);
+    print_str_ptr = args[0].of.i32;
+    print_str_len = args[1].of.i32;
+    return NULL;
+}
+
+void finalize(void *env)
+{
+    finalized_env = *(int *) env;
+}
+
+wasm_trap_t *fail(const wasm_val_t args[], wasm_val_t results[])
+{
+    wasm_message_t message;
+    wasm_name_new_from_string(&message, "failed");
+    wasm_trap_t *trap = wasm_trap_new(NULL, &message);
+    wasm_byte_vec_delete(&message);
+    return trap;
+}
+
+void read_file(const char *path, wasm_byte_vec_t *out)
+{
+    FILE *file = fopen(path, "r");
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    wasm_byte_vec_new_uninitialized(out, len);
+    fread(out->data, 1, len, file);
+    fclose(file);
+}
+
+// The export of `instance` that `module` names `name`.
+wasm_extern_t *find_export(wasm_module_t *module, wasm_extern_vec_t *exports, const char *name)
+{
+    wasm_exporttype_vec_t export_types;
+    wasm_module_exports(module, &export_types);
+    assert(export_types.size == exports->size);
+    wasm_extern_t *found = NULL;
+    for (size_t i = 0; i < export_types.size; ++i)
+    {
+        const wasm_name_t *export_name = wasm_exporttype_name(export_types.data[i]);
+        if (export_name->size == strlen(name) && memcmp(export_name->data, name, export_name->size) == 0)
+        {
+            found = exports->data[i];
+        }
+    }
+    wasm_exporttype_vec_delete(&export_types);
+    return found;
+}
+
+void test_sum(wasm_store_t *store)
+{
+    wasm_byte_vec_t binary;
+    read_file("assets/sum.wasm", &binary);
+    assert(wasm_module_validate(store, &binary));
+    wasm_module_t *module = wasm_module_new(store, &binary);
+    wasm_byte_vec_delete(&binary);
+    assert(module);
+
+    wasm_instance_t *instance = wasm_instance_new(store, module, NULL, NULL);
+    assert(instance);
+    wasm_extern_vec_t exports;
+    wasm_instance_exports(instance, &exports);
+    wasm_func_t *sum = wasm_extern_as_func(find_export(module, &exports, "sum"));
+    assert(sum);
+    assert(wasm_func_param_arity(sum) == 2);
+    assert(wasm_func_result_arity(sum) == 1);
+
+    wasm_val_t args[] = {WASM_I32_VAL(7), WASM_I32_VAL(8)};
+    wasm_val_t results[1];
+    wasm_trap_t *trap = wasm_func_call(sum, args, results);
+    assert(trap == NULL);
+    assert(results[0].kind == WASM_I32);
+    assert(results[0].of.i32 == 15);
+
+    // Functions of an instance keep it alive.
+    wasm_func_t *sum_copy = wasm_func_copy(sum);
+    wasm_extern_vec_delete(&exports);
+    wasm_instance_delete(instance);
+    trap = wasm_func_call(sum_copy, args, results);
+    assert(trap == NULL);
+    assert(results[0].of.i32 == 15);
+    wasm_func_delete(sum_copy);
+
+    wasm_module_delete(module);
+}
+
+void test_imports(wasm_store_t *store)
+{
+    wasm_byte_vec_t binary;
+    read_file("assets/wasm_sample_app.wasm", &binary);
+    wasm_module_t *module = wasm_module_new(store, &binary);
+    wasm_byte_vec_delete(&binary);
+    assert(module);
+
+    wasm_importtype_vec_t import_types;
+    wasm_module_imports(module, &import_types);
+    assert(import_types.size == 1);
+    const wasm_externtype_t *import_type = wasm_importtype_type(import_types.data[0]);
+    assert(wasm_externtype_kind(import_type) == WASM_EXTERN_FUNC);
+    const wasm_functype_t *print_str_type = wasm_externtype_as_functype_const(import_type);
+
+    int *env = malloc(sizeof(int));
+    *env = 42;
+    wasm_func_t *print_str_func = wasm_func_new_with_env(store, print_str_type, print_str, env, finalize);
+    assert(print_str_func);
+    wasm_importtype_vec_delete(&import_types);
+
+    const wasm_extern_t *imports[] = {wasm_func_as_extern(print_str_func)};
+    wasm_instance_t *instance = wasm_instance_new(store, module, imports, NULL);
+    assert(instance);
+    wasm_extern_vec_t exports;
+    wasm_instance_exports(instance, &exports);
+
+    wasm_func_t *hello_wasm = wasm_extern_as_func(find_export(module, &exports, "hello_wasm"));
+    assert(hello_wasm);
+    wasm_trap_t *trap = wasm_func_call(hello_wasm, NULL, NULL);
+    assert(trap == NULL);
+    assert(print_str_len == 13);
+
+    wasm_memory_t *memory = wasm_extern_as_memory(find_export(module, &exports, "memory"));
+    assert(memory);
+    assert(wasm_memory_data_size(memory) == wasm_memory_size(memory) * MEMORY_PAGE_SIZE);
+    assert(memcmp(wasm_memory_data(memory) + print_str_ptr, "Hello, World!", 13) == 0);
+
+    wasm_extern_vec_delete(&exports);
+    wasm_instance_delete(instance);
+    wasm_func_delete(print_str_func);
+    assert(finalized_env == 42);
+    free(env);
+    wasm_module_delete(module);
+}
+
+void test_traps(wasm_store_t *store)
+{
+    wasm_valtype_vec_t params, results;
+    wasm_valtype_vec_new_empty(&params);
+    wasm_valtype_vec_new_empty(&results);
+    wasm_functype_t *type = wasm_functype_new(&params, &results);
+    wasm_func_t *func = wasm_func_new(store, type, fail);
+    wasm_functype_delete(type);
+
+    wasm_trap_t *trap = wasm_func_call(func, NULL, NULL);
+    assert(trap);
+    wasm_message_t message;
+    wasm_trap_message(trap, &message);
+    assert(strcmp(message.data, "failed") == 0);
+    wasm_byte_vec_delete(&message);
+    wasm_trap_delete(trap);
+    wasm_func_delete(func);
+}
+
+void test_globals_and_memories(wasm_store_t *store)
+{
+    wasm_globaltype_t *global_type = wasm_globaltype_new(wasm_valtype_new(WASM_I64), WASM_VAR);
+    wasm_val_t value = WASM_I64_VAL(1);
+    wasm_global_t *global = wasm_global_new(store, global_type, &value);
+    wasm_globaltype_delete(global_type);
+    assert(global);
+    wasm_val_t new_value = WASM_I64_VAL(2);
+    wasm_global_set(global, &new_value);
+    wasm_global_get(global, &value);
+    assert(value.kind == WASM_I64);
+    assert(value.of.i64 == 2);
+    wasm_global_delete(global);
+
+    wasm_limits_t limits = {1, 3};
+    wasm_memorytype_t *memory_type = wasm_memorytype_new(&limits);
+    wasm_memory_t *memory = wasm_memory_new(store, memory_type);
+    wasm_memorytype_delete(memory_type);
+    assert(memory);
+    assert(wasm_memory_size(memory) == 1);
+    assert(wasm_memory_grow(memory, 2));
+    assert(wasm_memory_size(memory) == 3);
+    assert(!wasm_memory_grow(memory, 1));
+    // Externals of another kind are rejected rather than misread.
+    assert(wasm_global_type((const wasm_global_t *) memory) == NULL);
+    assert(wasm_table_size((const wasm_table_t *) memory) == 0);
+    wasm_memory_delete(memory);
+}
+
+int main()
+{
+    wasm_engine_t *engine = wasm_engine_new();
+    wasm_store_t *store = wasm_store_new(engine);
+
+    test_sum(store);
+    test_imports(store);
+    test_traps(store);
+    test_globals_and_memories(store);
+
+    wasm_store_delete(store);
+    wasm_engine_delete(engine);
+    return 0;
+}
@@ -0,0 +1,47 @@
+#include <stdio.h>
+#include "../wasmer.h"
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+static const char *SUM_WAT =
+    "(module\n"
+    "  (func (export \"sum\") (param i32 i32) (result i32)\n"
+    "    local.get 0\n"
+    "    local.get 1\n"
+    "    i32.add))\n";
+
+int main()
+{
+    wasmer_byte_array wasm;
+    wasmer_result_t convert_result = wasmer_wat2wasm((const uint8_t *) SUM_WAT, strlen(SUM_WAT), &wasm);
+    printf("Convert result: %d\n", convert_result);
+    assert(convert_result == WASMER_OK);
+    assert(wasmer_validate(wasm.bytes, wasm.bytes_len));
+
+    wasmer_import_t imports[] = {};
+    wasmer_instance_t *instance = NULL;
+    wasmer_result_t instantiate_result = wasmer_instantiate(&instance, (uint8_t *) wasm.bytes, wasm.bytes_len, imports, 0);
+    assert(instantiate_result == WASMER_OK);
+    wasmer_byte_array_destroy(wasm);
+
+    wasmer_value_t param_one;
+    param_one.tag = WASM_I32;
+    param_one.value.I32 = 7;
+    wasmer_value_t param_two;
+    param_two.tag = WASM_I32;
+    param_two.value.I32 = 8;
+    wasmer_value_t params[] = {param_one, param_two};
+    wasmer_value_t results[1];
+    wasmer_result_t call_result = wasmer_instance_call(instance, "sum", params, 2, results, 1);
+    assert(call_result == WASMER_OK);
+    assert(results[0].value.I32 == 15);
+    wasmer_instance_destroy(instance);
+
+    const char *invalid = "(module (func";
+    convert_result = wasmer_wat2wasm((const uint8_t *) invalid, strlen(invalid), &wasm);
+    assert(convert_result == WASMER_ERROR);
+    assert(wasmer_last_error_length() > 0);
+    return 0;
+}
@@ -0,0 +1,200 @@
+//! Calls into WebAssembly that wait on host futures without blocking
+//! the thread.
+//!
+//! A host function can return a future by wrapping it with [`wait`].
+//! When the function is called from a call made with
+//! [`Instance::call_async`], which runs on a fiber of its own, the
+//! call is suspended while the future isn't ready, and the
+//! [`CallFuture`] that the call returned is not ready either. The
+//! executor polling the `CallFuture`, such as a tokio runtime, is then
+//! free to do other work until the host future is ready. When the host
+//! function is called from a synchronous call, `wait` blocks the
+//! thread until the future is ready.
+//!
+//! A `CallFuture` must be polled on the thread that created it, since
+//! the backends keep the state of the call in thread-locals. Calls can
+//! only be suspended on x86_64 Linux, so `Instance::call_async` and
+//! `CallFuture` aren't available elsewhere, and `wait` always blocks.
+//!
+//! # Usage:
+//!
+//! ```
+//! use futures::future::{self, Future};
+//! use wasmer_runtime_core::{async_call::wait, func, imports, vm::Ctx};
+//!
+//! /// Looks `key` up in a database that's only reachable asynchronously.
+//! fn lookup(_ctx: &mut Ctx, key: i32) -> impl Future<Item = i32, Error = String> {
+//!     future::ok(key * 2)
+//! }
+//!
+//! let import_object = imports! {
+//!     "env" => {
+//!         "lookup" => func!(|ctx: &mut Ctx, key: i32| wait(lookup(ctx, key))),
+//!     },
+//! };
+//! ```
+//!
+//! [`wait`]: fn.wait.html
+//! [`Instance::call_async`]: ../struct.Instance.html#method.call_async
+//! [`CallFuture`]: struct.CallFuture.html
+
+use crate::typed_func::{TrapEarly, WasmTypeList};
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use crate::{
+    error::CallResult,
+    stack::{self, Fiber},
+    types::Value,
+};
+use futures::Future;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use futures::{Async, Poll};
+use std::any::Any;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use std::{cell::RefCell, fmt, rc::Rc};
+
+/// The result of a host function that waits on a future, as returned
+/// by [`wait`].
+///
+/// [`wait`]: fn.wait.html
+pub struct Wait<F>(F);
+
+/// Have a host function return what `future` resolves to, or trap
+/// with its error.
+pub fn wait<F: Future>(future: F) -> Wait<F> {
+    Wait(future)
+}
+
+impl<Rets, F> TrapEarly<Rets> for Wait<F>
+where
+    Rets: WasmTypeList,
+    F: Future<Item = Rets>,
+    F::Error: Any,
+{
+    fn report(self) -> Result<Rets, Box<dyn Any>> {
+        let Wait(future) = self;
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        {
+            if stack::in_fiber() {
+                return poll_in_fiber(future);
+            }
+        }
+        // Outside of an asynchronous call, there's nothing to suspend,
+        // so block on the future instead.
+        future.wait().map_err(|e| Box::new(e) as Box<dyn Any>)
+    }
+}
+
+/// Poll `future` until it's ready, suspending the fiber that's running
+/// while it isn't.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn poll_in_fiber<F>(mut future: F) -> Result<F::Item, Box<dyn Any>>
+where
+    F: Future,
+    F::Error: Any,
+{
+    loop {
+        match future.poll() {
+            Ok(Async::Ready(item)) => return Ok(item),
+            Ok(Async::NotReady) => stack::suspend(),
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+}
+
+/// A call into WebAssembly that is ready once the call has returned,
+/// as returned by [`Instance::call_async`].
+///
+/// The call runs while the future is polled, until it returns or waits
+/// on a host future that isn't ready. Dropping the future before the
+/// call has returned abandons the call without unwinding it, leaking
+/// what the frames of the call own.
+///
+/// [`Instance::call_async`]: ../struct.Instance.html#method.call_async
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub struct CallFuture<'a> {
+    fiber: Fiber<'a>,
+    result: Rc<RefCell<Option<CallResult<Vec<Value>>>>>,
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl<'a> CallFuture<'a> {
+    pub(crate) fn new(call: impl FnOnce() -> CallResult<Vec<Value>> + 'a) -> Self {
+        let result = Rc::new(RefCell::new(None));
+        let slot = Rc::clone(&result);
+        CallFuture {
+            fiber: Fiber::new(Box::new(move || *slot.borrow_mut() = Some(call()))),
+            result,
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl<'a> Future for CallFuture<'a> {
+    type Item = Vec<Value>;
+    type Error = crate::error::CallError;
+
+    fn poll(&mut self) -> Poll<Vec<Value>, Self::Error> {
+        if !self.fiber.resume() {
+            return Ok(Async::NotReady);
+        }
+        match self.result.borrow_mut().take() {
+            Some(result) => result.map(Async::Ready),
+            None => panic!("`CallFuture` polled after it was ready"),
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl<'a> fmt::Debug for CallFuture<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallFuture").finish()
+    }
+}
+
+#[cfg(test)]
+mod async_call_tests {
+    use super::*;
+    use futures::{future, task, Async, Poll};
+    use std::cell::Cell;
+
+    /// Not ready the first time that it's polled.
+    struct Yield(bool);
+
+    impl Future for Yield {
+        type Item = i32;
+        type Error = ();
+
+        fn poll(&mut self) -> Poll<i32, ()> {
+            if self.0 {
+                Ok(Async::Ready(42))
+            } else {
+                self.0 = true;
+                task::current().notify();
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn test_call_suspends_on_pending_future() {
+        let polls = Cell::new(0);
+        let mut call = CallFuture::new(|| {
+            let value = wait(Yield(false)).report().unwrap();
+            Ok(vec![Value::I32(value)])
+        });
+
+        let result = future::poll_fn(|| {
+            polls.set(polls.get() + 1);
+            call.poll()
+        })
+        .wait();
+        assert_eq!(result, Ok(vec![Value::I32(42)]));
+        assert_eq!(polls.get(), 2);
+    }
+
+    #[test]
+    fn test_wait_blocks_outside_of_calls() {
+        assert_eq!(wait(Yield(false)).report().ok(), Some(42));
+    }
+}
@@ -3,27 +3,164 @@ use crate::{
     error::CompileResult,
     error::RuntimeResult,
     module::ModuleInner,
-    types::{FuncIndex, LocalFuncIndex, Value},
+    stackmap::StackMap,
+    types::{FuncIndex, FuncSig, LocalFuncIndex, Value},
     vm,
 };
 
 use crate::{
-    cache::{Artifact, Error as CacheError},
+    cache::{Artifact, Error as CacheError, Target},
+    metering::MeteringConfig,
     module::ModuleInfo,
     sys::Memory,
 };
-use std::{any::Any, ptr::NonNull};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use std::{any::Any, collections::BTreeMap, ptr::NonNull, time::Duration};
 
 pub mod sys {
     pub use crate::sys::*;
 }
 pub use crate::sig_registry::SigRegistry;
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Backend {
     Cranelift,
     Dynasm,
     LLVM,
+    Interpreter,
+}
+
+impl Backend {
+    /// A short, stable name for this backend.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Cranelift => "cranelift",
+            Backend::Dynasm => "dynasm",
+            Backend::LLVM => "llvm",
+            Backend::Interpreter => "interpreter",
+        }
+    }
+
+    /// The backend called `name`, as returned by `name`. The dynasm
+    /// backend can also be called "singlepass".
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "cranelift" => Some(Backend::Cranelift),
+            "dynasm" | "singlepass" => Some(Backend::Dynasm),
+            "llvm" => Some(Backend::LLVM),
+            "interpreter" => Some(Backend::Interpreter),
+            _ => None,
+        }
+    }
+}
+
+/// What a backend can compile, and for which machines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The architectures that it generates code for, named as in
+    /// `cfg(target_arch)`.
+    pub target_archs: &'static [&'static str],
+    /// Whether it compiles shared memories and atomics.
+    pub threads: bool,
+    /// Whether it compiles `externref` and multiple tables.
+    pub reference_types: bool,
+    /// Whether it compiles the bulk memory operators and passive
+    /// segments.
+    pub bulk_memory: bool,
+    /// Whether it compiles functions that return more than one value.
+    pub multi_value: bool,
+    /// Whether the traps of the code that it compiles have a
+    /// backtrace.
+    pub backtraces: bool,
+    /// Whether it registers the code that it compiles with debuggers
+    /// once `jit_debug::set_enabled` has been called.
+    pub jit_debug: bool,
+    /// Whether the modules that it compiles can be cached.
+    pub caching: bool,
+    /// The version of the backend. Artifacts that it caches are only
+    /// loaded by the same version.
+    pub version: &'static str,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            target_archs: &[],
+            threads: false,
+            reference_types: false,
+            bulk_memory: false,
+            multi_value: false,
+            backtraces: false,
+            jit_debug: false,
+            caching: false,
+            version: "",
+        }
+    }
+}
+
+lazy_static! {
+    static ref BACKENDS: Mutex<HashMap<Backend, &'static (dyn Compiler + Sync)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Make `compiler` the compiler of `backend`, replacing the one that
+/// was registered for it, if there was one.
+///
+/// The runtime crate registers each backend that it's built with.
+pub fn register_backend(backend: Backend, compiler: &'static (dyn Compiler + Sync)) {
+    BACKENDS.lock().insert(backend, compiler);
+}
+
+/// The compiler registered for `backend`, if there is one.
+pub fn compiler_for(backend: Backend) -> Option<&'static (dyn Compiler + Sync)> {
+    BACKENDS.lock().get(&backend).cloned()
+}
+
+/// The backends that have a compiler registered.
+pub fn registered_backends() -> Vec<Backend> {
+    BACKENDS.lock().keys().cloned().collect()
+}
+
+lazy_static! {
+    /// The addresses that function pointers of backends without native
+    /// code point to, from the start of each module's functions to the
+    /// end.
+    static ref NON_NATIVE_CODE: RwLock<BTreeMap<usize, usize>> = RwLock::new(BTreeMap::new());
+}
+
+/// Record that the function pointers from `start` up to `end` don't
+/// point to machine code, so that instances of backends with native
+/// code refuse to import them, since they would call them directly.
+///
+/// Backends without native code register the functions of each module
+/// they compile, and unregister them when the module is dropped.
+pub fn register_non_native_code(start: usize, end: usize) {
+    NON_NATIVE_CODE.write().insert(start, end);
+}
+
+/// Forget the functions registered from `start` with
+/// [`register_non_native_code`].
+///
+/// [`register_non_native_code`]: fn.register_non_native_code.html
+pub fn unregister_non_native_code(start: usize) {
+    NON_NATIVE_CODE.write().remove(&start);
+}
+
+/// The functions registered with [`register_non_native_code`] that
+/// `func` is one of, as their start and end, if it's one.
+///
+/// [`register_non_native_code`]: fn.register_non_native_code.html
+pub fn non_native_code_range(func: *const vm::Func) -> Option<(usize, usize)> {
+    let addr = func as usize;
+    let code = NON_NATIVE_CODE.read();
+    let (&start, &end) = code.range(..=addr).next_back()?;
+    if addr < end {
+        Some((start, end))
+    } else {
+        None
+    }
 }
 
 /// This type cannot be constructed from
@@ -38,13 +175,34 @@ impl Token {
     }
 }
 
+/// How a module is compiled, whichever backend compiles it.
+#[derive(Clone, Copy, Default)]
+pub struct CompilerConfig {
+    /// How the code of the module is metered, if it is. See the
+    /// [`metering`] module.
+    ///
+    /// [`metering`]: ../metering/index.html
+    pub metering: Option<MeteringConfig>,
+}
+
 pub trait Compiler {
-    /// Compiles a `Module` from WebAssembly binary format.
+    /// Compiles a `Module` from WebAssembly binary format, as
+    /// `compiler_config` says.
     /// The `CompileToken` parameter ensures that this can only
     /// be called from inside the runtime.
-    fn compile(&self, wasm: &[u8], _: Token) -> CompileResult<ModuleInner>;
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> CompileResult<ModuleInner>;
 
     unsafe fn from_cache(&self, cache: Artifact, _: Token) -> Result<ModuleInner, CacheError>;
+
+    /// What this compiler can compile.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
 }
 
 /// The functionality exposed by this trait is expected to be used
@@ -76,7 +234,40 @@ pub trait ProtectedCaller: Send + Sync {
         _: Token,
     ) -> RuntimeResult<Vec<Value>>;
 
+    /// Runs `f` with the trap handling of this backend in place, so that
+    /// a trap in compiled code that `f` calls directly is returned as an
+    /// error instead of bringing down the process.
+    ///
+    /// Typed [`Func`] calls go through this, since they call functions
+    /// by their native signature instead of through `call`.
+    ///
+    /// [`Func`]: ../struct.Func.html
+    fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()>;
+
     fn get_early_trapper(&self) -> Box<dyn UserTrapper>;
+
+    /// Whether the function pointers of this backend point to machine
+    /// code. If not, typed [`Func`] calls go through `call_func`
+    /// instead of calling them directly.
+    ///
+    /// [`Func`]: ../struct.Func.html
+    fn has_native_code(&self) -> bool {
+        true
+    }
+
+    /// Calls the function that `func` points to, with the signature
+    /// `signature`. Only backends without native code need to
+    /// implement this; `func` may also be a host function.
+    fn call_func(
+        &self,
+        _func: *const vm::Func,
+        _vmctx: *mut vm::Ctx,
+        _signature: &FuncSig,
+        _params: &[Value],
+        _: Token,
+    ) -> RuntimeResult<Vec<Value>> {
+        unimplemented!("the backend only calls its functions natively")
+    }
 }
 
 pub trait UserTrapper {
@@ -91,6 +282,56 @@ pub trait FuncResolver: Send + Sync {
         module: &ModuleInner,
         local_func_index: LocalFuncIndex,
     ) -> Option<NonNull<vm::Func>>;
+
+    /// The machine code that a local function was compiled to, for
+    /// tooling that reports on the compiled module. `None` if the
+    /// backend doesn't keep track of it.
+    fn code(&self, _module: &ModuleInner, _local_func_index: LocalFuncIndex) -> Option<FuncCode> {
+        None
+    }
+
+    /// The disassembly of the machine code of a local function, one
+    /// instruction per line. `None` if the backend can't disassemble.
+    fn disassemble(
+        &self,
+        _module: &ModuleInner,
+        _local_func_index: LocalFuncIndex,
+    ) -> Option<String> {
+        None
+    }
+
+    /// The stack map of a local function, or `None` if the backend
+    /// doesn't make stack maps.
+    fn stack_map(
+        &self,
+        _module: &ModuleInner,
+        _local_func_index: LocalFuncIndex,
+    ) -> Option<&StackMap> {
+        None
+    }
+
+    /// Whether the code calls local functions through the table of
+    /// them in `vm::Ctx`, rather than directly, so that the functions
+    /// of an instance can be replaced.
+    ///
+    /// See the [`replace`] module.
+    ///
+    /// [`replace`]: ../replace/index.html
+    fn calls_through_ctx(&self) -> bool {
+        false
+    }
+}
+
+/// The machine code of a compiled function.
+#[derive(Debug, Copy, Clone)]
+pub struct FuncCode<'a> {
+    /// The code, where it's loaded. Backends that align functions may
+    /// count the padding after it.
+    pub code: &'a [u8],
+    /// How long the backend took to compile the function. `None` for
+    /// modules loaded from a cache, and for backends that compile the
+    /// whole module at once.
+    pub compile_time: Option<Duration>,
 }
 
 pub trait CacheGen: Send + Sync {
@@ -98,4 +339,78 @@ pub trait CacheGen: Send + Sync {
         &self,
         module: &ModuleInner,
     ) -> Result<(Box<ModuleInfo>, Box<[u8]>, Memory), CacheError>;
+
+    /// The machine that the code was compiled for, which the artifact
+    /// records so that it isn't loaded on a machine that lacks the cpu
+    /// features the code uses. The host by default.
+    fn target(&self) -> Target {
+        Target::host()
+    }
+
+    /// The version of the backend, which the artifact records so that
+    /// only the same version loads it. Backends that can't cache
+    /// needn't say.
+    fn version(&self) -> &'static str {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod backend_tests {
+    use super::*;
+    use crate::error::CompileError;
+
+    struct Unsupported;
+
+    impl Compiler for Unsupported {
+        fn compile(&self, _wasm: &[u8], _: CompilerConfig, _: Token) -> CompileResult<ModuleInner> {
+            Err(CompileError::InternalError {
+                msg: "unsupported".to_string(),
+            })
+        }
+
+        unsafe fn from_cache(&self, _: Artifact, _: Token) -> Result<ModuleInner, CacheError> {
+            Err(CacheError::Unknown("unsupported".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_backend_registry() {
+        for &backend in &[
+            Backend::Cranelift,
+            Backend::Dynasm,
+            Backend::LLVM,
+            Backend::Interpreter,
+        ] {
+            assert_eq!(Backend::from_name(backend.name()), Some(backend));
+        }
+        assert_eq!(Backend::from_name("singlepass"), Some(Backend::Dynasm));
+        assert_eq!(Backend::from_name("v8"), None);
+
+        static UNSUPPORTED: Unsupported = Unsupported;
+        register_backend(Backend::LLVM, &UNSUPPORTED);
+        assert!(registered_backends().contains(&Backend::LLVM));
+
+        let compiler = compiler_for(Backend::LLVM).unwrap();
+        assert_eq!(compiler.capabilities(), Capabilities::default());
+        assert!(compiler
+            .compile(&[], CompilerConfig::default(), Token::generate())
+            .is_err());
+    }
+
+    #[test]
+    fn test_non_native_code_registry() {
+        let functions = [0u64; 4];
+        let start = functions.as_ptr() as usize;
+        let end = start + std::mem::size_of_val(&functions);
+        let func = |addr: usize| addr as *const vm::Func;
+
+        assert_eq!(non_native_code_range(func(start)), None);
+        register_non_native_code(start, end);
+        assert_eq!(non_native_code_range(func(start)), Some((start, end)));
+        assert_eq!(non_native_code_range(func(end - 1)), Some((start, end)));
+        assert_eq!(non_native_code_range(func(end)), None);
+        unregister_non_native_code(start);
+        assert_eq!(non_native_code_range(func(start)), None);
+    }
 }
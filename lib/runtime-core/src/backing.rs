@@ -1,21 +1,28 @@
 use crate::{
-    error::{LinkError, LinkResult},
+    backend::non_native_code_range,
+    debugger::{self, DebugState},
+    error::{CreationError, LinkError, LinkResult},
     export::{Context, Export},
     global::Global,
     import::ImportObject,
+    limits::{InstanceLimiter, ResourceLimiter},
     memory::Memory,
-    module::{ImportName, ModuleInfo, ModuleInner},
+    module::{DataInitializer, ImportName, ModuleInfo, ModuleInner, TableInitializer},
+    platform,
     sig_registry::SigRegistry,
     structures::{BoxedMap, Map, SliceMap, TypedIndex},
+    sys,
     table::Table,
+    typed_func::FuncEnv,
     types::{
-        ImportedFuncIndex, ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex,
-        Initializer, LocalFuncIndex, LocalGlobalIndex, LocalMemoryIndex, LocalOrImport,
-        LocalTableIndex, SigIndex, Value,
+        ElementType, FuncIndex, ImportedFuncIndex, ImportedGlobalIndex, ImportedMemoryIndex,
+        ImportedTableIndex, Initializer, LocalFuncIndex, LocalGlobalIndex, LocalMemoryIndex,
+        LocalOrImport, LocalTableIndex, SigIndex, Value,
     },
     vm,
 };
-use std::slice;
+use hashbrown::HashSet;
+use std::{rc::Rc, slice};
 
 #[derive(Debug)]
 pub struct LocalBacking {
@@ -29,6 +36,13 @@ pub struct LocalBacking {
 
     pub(crate) dynamic_sigindices: BoxedMap<SigIndex, vm::SigId>,
     pub(crate) local_functions: BoxedMap<LocalFuncIndex, *const vm::Func>,
+
+    /// The passive segments that `data.drop` and `elem.drop` have dropped.
+    pub(crate) dropped_data: HashSet<u32>,
+    pub(crate) dropped_elements: HashSet<u32>,
+
+    pub(crate) debug: DebugState,
+    pub(crate) limiter: InstanceLimiter,
 }
 
 // impl LocalBacking {
@@ -42,19 +56,67 @@ pub struct LocalBacking {
 // }
 
 impl LocalBacking {
-    pub(crate) fn new(module: &ModuleInner, imports: &ImportBacking, vmctx: *mut vm::Ctx) -> Self {
-        let mut memories = Self::generate_memories(module);
+    /// Create the memories, tables and globals of an instance. They
+    /// are only filled in by the data and element segments if
+    /// `run_initializers` is set. `limiter` must allow the memories
+    /// and tables to be created.
+    pub(crate) fn new(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        vmctx: *mut vm::Ctx,
+        run_initializers: bool,
+        images: &[Option<sys::MemoryImage>],
+        limiter: Option<Box<dyn ResourceLimiter>>,
+    ) -> Result<Self, CreationError> {
+        let mut limiter = InstanceLimiter::new(limiter);
+        if !limiter.allows_instance(&module.info) {
+            return Err(CreationError::ResourceLimitExceeded);
+        }
+
+        let mut memories = Self::generate_memories(module)?;
         let mut tables = Self::generate_tables(module);
         let mut globals = Self::generate_globals(module, imports);
 
-        let vm_memories = Self::finalize_memories(module, imports, &mut memories);
-        let vm_tables = Self::finalize_tables(module, imports, &mut tables, vmctx);
-        let vm_globals = Self::finalize_globals(&mut globals);
+        let (data_initializers, elem_initializers): (&[_], &[_]) = if run_initializers {
+            (
+                &module.info.data_initializers,
+                &module.info.elem_initializers,
+            )
+        } else {
+            (&[], &[])
+        };
+
+        // The data segments of a memory that has been mapped from an
+        // image have already been applied.
+        let mapped: Vec<bool> = if run_initializers {
+            memories
+                .iter()
+                .map(|(index, memory)| match images.get(index.index()) {
+                    Some(Some(image)) => memory.map_image(image).unwrap_or(false),
+                    _ => false,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         let dynamic_sigindices = Self::generate_sigindices(&module.info);
         let local_functions = Self::generate_local_functions(module);
 
-        Self {
+        let vm_memories =
+            Self::finalize_memories(module, imports, &mut memories, data_initializers, &mapped);
+        let vm_tables = Self::finalize_tables(
+            module,
+            imports,
+            &dynamic_sigindices,
+            &local_functions,
+            &mut tables,
+            vmctx,
+            elem_initializers,
+        );
+        let vm_globals = Self::finalize_globals(&mut globals);
+
+        Ok(Self {
             memories,
             tables,
             globals,
@@ -65,7 +127,13 @@ impl LocalBacking {
 
             dynamic_sigindices,
             local_functions,
-        }
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            debug: DebugState::new(),
+            limiter,
+        })
     }
 
     fn generate_local_functions(module: &ModuleInner) -> BoxedMap<LocalFuncIndex, *const vm::Func> {
@@ -84,36 +152,31 @@ impl LocalBacking {
     fn generate_sigindices(info: &ModuleInfo) -> BoxedMap<SigIndex, vm::SigId> {
         info.signatures
             .iter()
-            .map(|(_, signature)| {
-                let signature = SigRegistry.lookup_signature_ref(signature);
-                let sig_index = SigRegistry.lookup_sig_index(signature);
-                vm::SigId(sig_index.index() as u32)
-            })
+            .map(|(_, signature)| SigRegistry.lookup_sig_id(signature))
             .collect::<Map<_, _>>()
             .into_boxed_map()
     }
 
-    fn generate_memories(module: &ModuleInner) -> BoxedMap<LocalMemoryIndex, Memory> {
+    fn generate_memories(
+        module: &ModuleInner,
+    ) -> Result<BoxedMap<LocalMemoryIndex, Memory>, CreationError> {
         let mut memories = Map::with_capacity(module.info.memories.len());
         for (_, &desc) in &module.info.memories {
-            memories.push(Memory::new(desc).expect("unable to create memory"));
+            memories.push(Memory::with_style(desc, module.info.memory_style)?);
         }
 
-        memories.into_boxed_map()
+        Ok(memories.into_boxed_map())
     }
 
     fn finalize_memories(
         module: &ModuleInner,
         imports: &ImportBacking,
         memories: &mut SliceMap<LocalMemoryIndex, Memory>,
+        data_initializers: &[DataInitializer],
+        mapped: &[bool],
     ) -> BoxedMap<LocalMemoryIndex, *mut vm::LocalMemory> {
         // For each init that has some data...
-        for init in module
-            .info
-            .data_initializers
-            .iter()
-            .filter(|init| init.data.len() > 0)
-        {
+        for init in data_initializers.iter().filter(|init| init.data.len() > 0) {
             let init_base = match init.base {
                 Initializer::Const(Value::I32(offset)) => offset as u32,
                 Initializer::Const(_) => panic!("a const initializer must be the i32 type"),
@@ -127,6 +190,8 @@ impl LocalBacking {
             } as usize;
 
             match init.memory_index.local_or_import(&module.info) {
+                LocalOrImport::Local(local_memory_index)
+                    if mapped.get(local_memory_index.index()) == Some(&true) => {}
                 LocalOrImport::Local(local_memory_index) => {
                     let memory_desc = module.info.memories[local_memory_index];
                     let data_top = init_base + init.data.len();
@@ -163,6 +228,63 @@ impl LocalBacking {
             .into_boxed_map()
     }
 
+    /// The contents that each local memory starts out with, up to the
+    /// end of the last page that a data segment writes to, if they
+    /// don't depend on the imports. They can be mapped copy-on-write
+    /// into new instances instead of copying the data segments.
+    pub(crate) fn memory_images(info: &ModuleInfo) -> Vec<Option<sys::MemoryImage>> {
+        // Images are mapped with the operating system, not with the
+        // page allocator that memories come from.
+        if platform::page_allocator().is_some() {
+            return info.memories.iter().map(|_| None).collect();
+        }
+
+        info.memories
+            .iter()
+            .map(|(local_memory_index, desc)| {
+                if desc.shared {
+                    return None;
+                }
+
+                let mut contents = Vec::new();
+                for init in info
+                    .data_initializers
+                    .iter()
+                    .filter(|init| init.data.len() > 0)
+                {
+                    match init.memory_index.local_or_import(info) {
+                        LocalOrImport::Local(index) if index == local_memory_index => {}
+                        _ => continue,
+                    }
+
+                    let init_base = match init.base {
+                        Initializer::Const(Value::I32(offset)) => offset as u32 as usize,
+                        _ => return None,
+                    };
+                    // Leave the out of bounds case to `finalize_memories`.
+                    let data_top = init_base + init.data.len();
+                    if data_top > desc.minimum.bytes().0 {
+                        return None;
+                    }
+
+                    if contents.len() < data_top {
+                        contents.resize(data_top, 0);
+                    }
+                    contents[init_base..data_top].copy_from_slice(&init.data);
+                }
+
+                if contents.is_empty() {
+                    return None;
+                }
+                let page_size = page_size::get();
+                let len = (contents.len() + page_size - 1) / page_size * page_size;
+                contents.resize(len, 0);
+
+                sys::MemoryImage::new(&contents).ok()
+            })
+            .collect()
+    }
+
     fn generate_tables(module: &ModuleInner) -> BoxedMap<LocalTableIndex, Table> {
         let mut tables = Map::with_capacity(module.info.tables.len());
 
@@ -178,10 +300,13 @@ impl LocalBacking {
     fn finalize_tables(
         module: &ModuleInner,
         imports: &ImportBacking,
+        sig_ids: &BoxedMap<SigIndex, vm::SigId>,
+        local_functions: &BoxedMap<LocalFuncIndex, *const vm::Func>,
         tables: &mut SliceMap<LocalTableIndex, Table>,
         vmctx: *mut vm::Ctx,
+        elem_initializers: &[TableInitializer],
     ) -> BoxedMap<LocalTableIndex, *mut vm::LocalTable> {
-        for init in &module.info.elem_initializers {
+        for init in elem_initializers {
             let init_base = match init.base {
                 Initializer::Const(Value::I32(offset)) => offset as u32,
                 Initializer::Const(_) => panic!("a const initializer must be the i32 type"),
@@ -206,31 +331,14 @@ impl LocalBacking {
 
                     table.anyfunc_direct_access_mut(|elements| {
                         for (i, &func_index) in init.elements.iter().enumerate() {
-                            let sig_index = module.info.func_assoc[func_index];
-                            // let signature = &module.info.signatures[sig_index];
-                            let signature = SigRegistry
-                                .lookup_signature_ref(&module.info.signatures[sig_index]);
-                            let sig_id =
-                                vm::SigId(SigRegistry.lookup_sig_index(signature).index() as u32);
-
-                            let (func, ctx) = match func_index.local_or_import(&module.info) {
-                                LocalOrImport::Local(local_func_index) => (
-                                    module
-                                        .func_resolver
-                                        .get(module, local_func_index)
-                                        .unwrap()
-                                        .as_ptr()
-                                        as *const vm::Func,
-                                    vmctx,
-                                ),
-                                LocalOrImport::Import(imported_func_index) => {
-                                    let vm::ImportedFunc { func, vmctx } =
-                                        imports.vm_functions[imported_func_index];
-                                    (func, vmctx)
-                                }
-                            };
-
-                            elements[init_base + i] = vm::Anyfunc { func, ctx, sig_id };
+                            elements[init_base + i] = Self::anyfunc(
+                                module,
+                                imports,
+                                sig_ids,
+                                local_functions,
+                                vmctx,
+                                func_index,
+                            );
                         }
                     });
                 }
@@ -245,31 +353,14 @@ impl LocalBacking {
 
                     table.anyfunc_direct_access_mut(|elements| {
                         for (i, &func_index) in init.elements.iter().enumerate() {
-                            let sig_index = module.info.func_assoc[func_index];
-                            let signature = SigRegistry
-                                .lookup_signature_ref(&module.info.signatures[sig_index]);
-                            // let signature = &module.info.signatures[sig_index];
-                            let sig_id =
-                                vm::SigId(SigRegistry.lookup_sig_index(signature).index() as u32);
-
-                            let (func, ctx) = match func_index.local_or_import(&module.info) {
-                                LocalOrImport::Local(local_func_index) => (
-                                    module
-                                        .func_resolver
-                                        .get(module, local_func_index)
-                                        .unwrap()
-                                        .as_ptr()
-                                        as *const vm::Func,
-                                    vmctx,
-                                ),
-                                LocalOrImport::Import(imported_func_index) => {
-                                    let vm::ImportedFunc { func, vmctx } =
-                                        imports.vm_functions[imported_func_index];
-                                    (func, vmctx)
-                                }
-                            };
-
-                            elements[init_base + i] = vm::Anyfunc { func, ctx, sig_id };
+                            elements[init_base + i] = Self::anyfunc(
+                                module,
+                                imports,
+                                sig_ids,
+                                local_functions,
+                                vmctx,
+                                func_index,
+                            );
                         }
                     });
                 }
@@ -283,6 +374,31 @@ impl LocalBacking {
             .into_boxed_map()
     }
 
+    /// The caller-checked anyfunc that a table holds for `func_index`.
+    /// `sig_ids` are the ids of the module's signatures, which
+    /// `dynamic_sigindices` holds once the instance has been created,
+    /// and `local_functions` is the code of its own functions.
+    pub(crate) fn anyfunc(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        sig_ids: &BoxedMap<SigIndex, vm::SigId>,
+        local_functions: &BoxedMap<LocalFuncIndex, *const vm::Func>,
+        vmctx: *mut vm::Ctx,
+        func_index: FuncIndex,
+    ) -> vm::Anyfunc {
+        let sig_id = sig_ids[module.info.func_assoc[func_index]];
+
+        let (func, ctx) = match func_index.local_or_import(&module.info) {
+            LocalOrImport::Local(local_func_index) => (local_functions[local_func_index], vmctx),
+            LocalOrImport::Import(imported_func_index) => {
+                let vm::ImportedFunc { func, vmctx } = imports.vm_functions[imported_func_index];
+                (func, vmctx)
+            }
+        };
+
+        vm::Anyfunc { func, ctx, sig_id }
+    }
+
     fn generate_globals(
         module: &ModuleInner,
         imports: &ImportBacking,
@@ -327,6 +443,9 @@ pub struct ImportBacking {
     pub(crate) globals: BoxedMap<ImportedGlobalIndex, Global>,
 
     pub(crate) vm_functions: BoxedMap<ImportedFuncIndex, vm::ImportedFunc>,
+    /// Keeps the contexts that imported host closures are called
+    /// with, and the environments they point to, alive.
+    pub(crate) func_ctxs: Vec<(Box<vm::FuncCtx>, Rc<FuncEnv>)>,
     pub(crate) vm_memories: BoxedMap<ImportedMemoryIndex, *mut vm::LocalMemory>,
     pub(crate) vm_tables: BoxedMap<ImportedTableIndex, *mut vm::LocalTable>,
     pub(crate) vm_globals: BoxedMap<ImportedGlobalIndex, *mut vm::LocalGlobal>,
@@ -341,11 +460,12 @@ impl ImportBacking {
         let mut failed = false;
         let mut link_errors = vec![];
 
-        let vm_functions = import_functions(module, imports, vmctx).unwrap_or_else(|le| {
-            failed = true;
-            link_errors.extend(le);
-            Map::new().into_boxed_map()
-        });
+        let (func_ctxs, vm_functions) =
+            import_functions(module, imports, vmctx).unwrap_or_else(|le| {
+                failed = true;
+                link_errors.extend(le);
+                (vec![], Map::new().into_boxed_map())
+            });
 
         let (memories, vm_memories) = import_memories(module, imports).unwrap_or_else(|le| {
             failed = true;
@@ -374,6 +494,7 @@ impl ImportBacking {
                 globals,
 
                 vm_functions,
+                func_ctxs,
                 vm_memories,
                 vm_tables,
                 vm_globals,
@@ -390,8 +511,12 @@ fn import_functions(
     module: &ModuleInner,
     imports: &ImportObject,
     vmctx: *mut vm::Ctx,
-) -> LinkResult<BoxedMap<ImportedFuncIndex, vm::ImportedFunc>> {
+) -> LinkResult<(
+    Vec<(Box<vm::FuncCtx>, Rc<FuncEnv>)>,
+    BoxedMap<ImportedFuncIndex, vm::ImportedFunc>,
+)> {
     let mut link_errors = vec![];
+    let mut func_ctxs = vec![];
     let mut functions = Map::with_capacity(module.info.imported_functions.len());
     for (
         index,
@@ -407,21 +532,40 @@ fn import_functions(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let import = imports
-            .get_namespace(namespace)
-            .and_then(|namespace| namespace.get_export(name));
+        // The hooks of the debugger are provided by the runtime.
+        let import = if namespace == debugger::NAMESPACE {
+            debugger::hook(name)
+        } else {
+            imports.get_export(namespace, name)
+        };
         match import {
             Some(Export::Function {
                 func,
                 ctx,
                 signature,
             }) => {
-                if *expected_sig == *signature {
+                if module.protected_caller.has_native_code()
+                    && non_native_code_range(func.inner()).is_some()
+                {
+                    link_errors.push(LinkError::NonNativeImport {
+                        namespace: namespace.to_string(),
+                        name: name.to_string(),
+                    });
+                } else if *expected_sig == *signature {
                     functions.push(vm::ImportedFunc {
                         func: func.inner(),
                         vmctx: match ctx {
                             Context::External(ctx) => ctx,
                             Context::Internal => vmctx,
+                            Context::InternalWithEnv(env) => {
+                                let mut func_ctx = Box::new(vm::FuncCtx {
+                                    vmctx,
+                                    env: env.as_ptr(),
+                                });
+                                let func_ctx_ptr = &mut *func_ctx as *mut vm::FuncCtx;
+                                func_ctxs.push((func_ctx, env));
+                                func_ctx_ptr as *mut vm::Ctx
+                            }
                         },
                     });
                 } else {
@@ -460,7 +604,7 @@ fn import_functions(
     if link_errors.len() > 0 {
         Err(link_errors)
     } else {
-        Ok(functions.into_boxed_map())
+        Ok((func_ctxs, functions.into_boxed_map()))
     }
 }
 
@@ -488,21 +632,30 @@ fn import_memories(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let memory_import = imports
-            .get_namespace(&namespace)
-            .and_then(|namespace| namespace.get_export(&name));
+        let memory_import = imports.get_export(&namespace, &name);
         match memory_import {
             Some(Export::Memory(memory)) => {
-                if expected_memory_desc.fits_in_imported(memory.descriptor()) {
-                    memories.push(memory.clone());
-                    vm_memories.push(memory.vm_local_memory());
-                } else {
+                // The compiled code only works with the type of memory
+                // that it was generated for.
+                let expected_type =
+                    expected_memory_desc.memory_type_with_style(module.info.memory_style);
+                if !expected_memory_desc.fits_in_imported(memory.descriptor()) {
                     link_errors.push(LinkError::IncorrectMemoryDescriptor {
                         namespace: namespace.to_string(),
                         name: name.to_string(),
                         expected: *expected_memory_desc,
                         found: memory.descriptor(),
                     });
+                } else if memory.memory_type() != expected_type {
+                    link_errors.push(LinkError::IncorrectImportType {
+                        namespace: namespace.to_string(),
+                        name: name.to_string(),
+                        expected: format!("{:?} memory", expected_type),
+                        found: format!("{:?} memory", memory.memory_type()),
+                    });
+                } else {
+                    memories.push(memory.clone());
+                    vm_memories.push(memory.vm_local_memory());
                 }
             }
             Some(export_type) => {
@@ -560,12 +713,15 @@ fn import_tables(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let table_import = imports
-            .get_namespace(&namespace)
-            .and_then(|namespace| namespace.get_export(&name));
+        let table_import = imports.get_export(&namespace, &name);
         match table_import {
             Some(Export::Table(mut table)) => {
-                if expected_table_desc.fits_in_imported(table.descriptor()) {
+                if module.protected_caller.has_native_code() && holds_non_native_code(&table) {
+                    link_errors.push(LinkError::NonNativeImport {
+                        namespace: namespace.to_string(),
+                        name: name.to_string(),
+                    });
+                } else if expected_table_desc.fits_in_imported(table.descriptor()) {
                     vm_tables.push(table.vm_local_table());
                     tables.push(table);
                 } else {
@@ -608,6 +764,18 @@ fn import_tables(
     }
 }
 
+/// Whether `table` holds a function of a backend without native code.
+fn holds_non_native_code(table: &Table) -> bool {
+    match table.descriptor().element {
+        ElementType::Anyfunc => table.anyfunc_direct_access_mut(|anyfuncs| {
+            anyfuncs
+                .iter()
+                .any(|anyfunc| non_native_code_range(anyfunc.func).is_some())
+        }),
+        ElementType::ExternRef => false,
+    }
+}
+
 fn import_globals(
     module: &ModuleInner,
     imports: &ImportObject,
@@ -631,9 +799,7 @@ fn import_globals(
     {
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
-        let import = imports
-            .get_namespace(namespace)
-            .and_then(|namespace| namespace.get_export(name));
+        let import = imports.get_export(namespace, name);
         match import {
             Some(Export::Global(mut global)) => {
                 if global.descriptor() == *imported_global_desc {
@@ -1,14 +1,25 @@
 use crate::{
+    backend::Backend,
     module::{Module, ModuleInfo},
     sys::Memory,
 };
-use blake2b_simd::blake2bp;
-use std::{fmt, io, mem, slice};
+use blake2b_simd::{blake2bp, Params};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{Serialize, Serializer};
+use std::{collections::BTreeMap, fmt, hash::Hash, io, mem, slice, str, sync::Arc};
 
 #[derive(Debug)]
 pub enum InvalidFileType {
     InvalidSize,
     InvalidMagic,
+    /// The body doesn't match the checksum in the header, so it was
+    /// corrupted or changed since it was written.
+    InvalidChecksum,
+    /// A signer is set, and the artifact isn't signed, or its
+    /// signature doesn't verify.
+    InvalidSignature,
 }
 
 #[derive(Debug)]
@@ -19,6 +30,15 @@ pub enum Error {
     Unknown(String),
     InvalidFile(InvalidFileType),
     InvalidatedCache,
+    /// The artifact was compiled for a machine that can't run it.
+    IncompatibleTarget(Target),
+    /// The artifact was written by another version of the runtime, or
+    /// of the backend that loads it. These are the versions that wrote
+    /// it.
+    IncompatibleVersion {
+        wasmer: String,
+        backend: String,
+    },
 }
 
 impl From<io::Error> for Error {
@@ -42,12 +62,33 @@ impl WasmHash {
     /// This does no verification that the supplied data
     /// is, in fact, a wasm module.
     pub fn generate(wasm: &[u8]) -> Self {
-        let mut first_part = [0u8; 32];
-        let mut second_part = [0u8; 32];
-
         let mut state = blake2bp::State::new();
         state.update(wasm);
 
+        Self::from_state(state)
+    }
+
+    /// Derive a hash that additionally covers the compiler backend
+    /// and the cpu features of the host.
+    ///
+    /// Compiled artifacts are only valid for the backend and the
+    /// kind of machine that produced them, so a [`Cache`] should
+    /// key its entries with this.
+    ///
+    /// [`Cache`]: trait.Cache.html
+    pub fn for_target(self, backend: Backend) -> Self {
+        let mut state = blake2bp::State::new();
+        state.update(&self.into_array());
+        state.update(backend.name().as_bytes());
+        state.update(Target::host().to_string().as_bytes());
+
+        Self::from_state(state)
+    }
+
+    fn from_state(state: blake2bp::State) -> Self {
+        let mut first_part = [0u8; 32];
+        let mut second_part = [0u8; 32];
+
         let hasher = state.finalize();
         let generic_array = hasher.as_bytes();
 
@@ -70,55 +111,234 @@ impl WasmHash {
     }
 }
 
-const CURRENT_CACHE_VERSION: u64 = 0;
+/// Defines `DETECTED_FEATURES` and `detect_features` from the same
+/// list, since `is_x86_feature_detected!` only takes literals.
+macro_rules! detected_features {
+    ($($feature:tt),*) => {
+        /// The cpu features that the host is checked for, named as in
+        /// `cfg(target_feature)`.
+        const DETECTED_FEATURES: &[&str] = &[$($feature),*];
+
+        /// The features of `DETECTED_FEATURES` that the host supports.
+        fn detect_features() -> Vec<String> {
+            #[allow(unused_mut)]
+            let mut features = Vec::new();
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                $(
+                    if is_x86_feature_detected!($feature) {
+                        features.push($feature.to_string());
+                    }
+                )*
+            }
+            features
+        }
+    };
+}
+
+detected_features!(
+    "sse2", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "avx", "avx2", "bmi1", "bmi2", "lzcnt"
+);
+
+/// The machine that the code of an artifact was compiled for, since
+/// code generated for one machine may use instructions that another
+/// one doesn't support.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Target {
+    /// The architecture, named as in `std::env::consts::ARCH`.
+    pub arch: String,
+    /// The operating system, named as in `std::env::consts::OS`.
+    pub os: String,
+    /// The cpu features that the code uses, of those that the host is
+    /// checked for.
+    pub features: Vec<String>,
+}
+
+impl Target {
+    /// The host, with every feature that it supports.
+    pub fn host() -> Self {
+        Self {
+            arch: std::env::consts::ARCH.to_string(),
+            os: std::env::consts::OS.to_string(),
+            features: detect_features(),
+        }
+    }
+
+    /// The host's architecture and operating system with only the cpu
+    /// features that every machine of the architecture has, for code
+    /// that is shipped to machines other than the one that compiled it.
+    pub fn baseline() -> Self {
+        let features: &[&str] = if cfg!(target_arch = "x86_64") {
+            &["sse2"]
+        } else {
+            &[]
+        };
+        Self {
+            arch: std::env::consts::ARCH.to_string(),
+            os: std::env::consts::OS.to_string(),
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+
+    /// The machine that a target triple, such as
+    /// `aarch64-unknown-linux-gnu`, describes, for code that uses
+    /// `features`.
+    pub fn from_triple(triple: &str, features: &[&str]) -> Self {
+        let mut parts = triple.split('-');
+        // Triples name some architectures differently than
+        // `std::env::consts::ARCH` does, and some by their variants.
+        let arch = match parts.next().unwrap_or("") {
+            "i386" | "i486" | "i586" | "i686" => "x86",
+            "amd64" => "x86_64",
+            "arm64" => "aarch64",
+            "powerpc64le" => "powerpc64",
+            "mipsel" => "mips",
+            "mips64el" => "mips64",
+            arch if arch.starts_with("aarch64") => "aarch64",
+            arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+            arch if arch.starts_with("riscv32") => "riscv32",
+            arch if arch.starts_with("riscv64") => "riscv64",
+            arch => arch,
+        }
+        .to_string();
+        // Darwin triples may end with a version, as in `x86_64-apple-darwin18`.
+        let os = parts.find_map(|part| {
+            ["linux", "darwin", "macos", "windows", "freebsd"]
+                .iter()
+                .find(|os| part.starts_with(*os))
+        });
+        let os = match os {
+            Some(&"darwin") => "macos",
+            Some(os) => os,
+            None => "unknown",
+        };
+
+        Self {
+            arch,
+            os: os.to_string(),
+            features: features
+                .iter()
+                .filter(|feature| DETECTED_FEATURES.contains(feature))
+                .map(|feature| feature.to_string())
+                .collect(),
+        }
+    }
+
+    /// Whether code compiled for this machine can run on the host.
+    pub fn runs_on_host(&self) -> bool {
+        let host = Self::host();
+        self.arch == host.arch
+            && self.os == host.os
+            && self
+                .features
+                .iter()
+                .all(|feature| host.features.contains(feature))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}:{}", self.arch, self.os, self.features.join(","))
+    }
+}
+
+const CURRENT_CACHE_VERSION: u64 = 2;
 static WASMER_CACHE_MAGIC: [u8; 8] = *b"WASMER\0\0";
 
 /// The header of a cache file.
+///
+/// The body follows the header, and then the signature, if the
+/// artifact is signed.
 #[repr(C, packed)]
+#[derive(Clone, Copy)]
 struct ArtifactHeader {
     magic: [u8; 8], // [W, A, S, M, E, R, \0, \0]
     version: u64,
     data_len: u64,
+    /// The version of this crate that wrote the artifact, padded with
+    /// zeros.
+    wasmer_version: [u8; 16],
+    /// The version of the backend that compiled the code, padded with
+    /// zeros.
+    backend_version: [u8; 16],
+    /// The hash of the body.
+    checksum: [u8; 64],
+    signature_len: u64,
 }
 
 impl ArtifactHeader {
-    pub fn read_from_slice(buffer: &[u8]) -> Result<(&Self, &[u8]), Error> {
-        if buffer.len() >= mem::size_of::<ArtifactHeader>() {
-            if &buffer[..8] == &WASMER_CACHE_MAGIC {
-                let (header_slice, body_slice) = buffer.split_at(mem::size_of::<ArtifactHeader>());
-                let header = unsafe { &*(header_slice.as_ptr() as *const ArtifactHeader) };
-
-                if header.version == CURRENT_CACHE_VERSION {
-                    Ok((header, body_slice))
-                } else {
-                    Err(Error::InvalidatedCache)
-                }
-            } else {
-                Err(Error::InvalidFile(InvalidFileType::InvalidMagic))
-            }
-        } else {
-            Err(Error::InvalidFile(InvalidFileType::InvalidSize))
+    /// Frame `body` with a header, and a signature by `signer`.
+    fn write(body: &[u8], backend_version: &str, signer: Option<&dyn ArtifactSigner>) -> Vec<u8> {
+        let mut header = ArtifactHeader {
+            magic: WASMER_CACHE_MAGIC,
+            version: CURRENT_CACHE_VERSION,
+            data_len: body.len() as u64,
+            wasmer_version: pad_version(crate::VERSION),
+            backend_version: pad_version(backend_version),
+            checksum: WasmHash::generate(body).into_array(),
+            signature_len: 0,
+        };
+        let signature = signer.map(|signer| signer.sign(header.as_slice()));
+        if let Some(signature) = &signature {
+            header.signature_len = signature.len() as u64;
+        }
+
+        let mut buffer = header.as_slice().to_vec();
+        buffer.extend_from_slice(body);
+        if let Some(signature) = &signature {
+            buffer.extend_from_slice(signature);
         }
+        buffer
     }
 
-    pub fn read_from_slice_mut(buffer: &mut [u8]) -> Result<(&mut Self, &mut [u8]), Error> {
-        if buffer.len() >= mem::size_of::<ArtifactHeader>() {
-            if &buffer[..8] == &WASMER_CACHE_MAGIC {
-                let (header_slice, body_slice) =
-                    buffer.split_at_mut(mem::size_of::<ArtifactHeader>());
-                let header = unsafe { &mut *(header_slice.as_ptr() as *mut ArtifactHeader) };
+    /// Check the header of `buffer`, and that the body matches its
+    /// checksum and, if there's a `signer`, its signature.
+    pub fn read_from_slice<'a>(
+        buffer: &'a [u8],
+        signer: Option<&dyn ArtifactSigner>,
+    ) -> Result<(&'a Self, &'a [u8]), Error> {
+        if buffer.len() < mem::size_of::<ArtifactHeader>() {
+            return Err(Error::InvalidFile(InvalidFileType::InvalidSize));
+        }
+        if &buffer[..8] != &WASMER_CACHE_MAGIC {
+            return Err(Error::InvalidFile(InvalidFileType::InvalidMagic));
+        }
+
+        let (header_slice, rest) = buffer.split_at(mem::size_of::<ArtifactHeader>());
+        let header = unsafe { &*(header_slice.as_ptr() as *const ArtifactHeader) };
+
+        if header.version != CURRENT_CACHE_VERSION {
+            return Err(Error::InvalidatedCache);
+        }
+        if header.wasmer_version != pad_version(crate::VERSION) {
+            return Err(Error::IncompatibleVersion {
+                wasmer: unpad_version(&header.wasmer_version).to_string(),
+                backend: unpad_version(&header.backend_version).to_string(),
+            });
+        }
+        if header.data_len.checked_add(header.signature_len) != Some(rest.len() as u64) {
+            // The file was truncated or has trailing garbage,
+            // most likely from an interrupted write.
+            return Err(Error::InvalidFile(InvalidFileType::InvalidSize));
+        }
 
-                if header.version == CURRENT_CACHE_VERSION {
-                    Ok((header, body_slice))
-                } else {
-                    Err(Error::InvalidatedCache)
-                }
-            } else {
-                Err(Error::InvalidFile(InvalidFileType::InvalidMagic))
+        let (body_slice, signature) = rest.split_at(header.data_len as usize);
+        if WasmHash::generate(body_slice).into_array()[..] != header.checksum[..] {
+            return Err(Error::InvalidFile(InvalidFileType::InvalidChecksum));
+        }
+        if let Some(signer) = signer {
+            // The signature is of the header as it was before the
+            // length of the signature was filled in.
+            let unsigned = ArtifactHeader {
+                signature_len: 0,
+                ..*header
+            };
+            if signature.is_empty() || !signer.verify(unsigned.as_slice(), signature) {
+                return Err(Error::InvalidFile(InvalidFileType::InvalidSignature));
             }
-        } else {
-            Err(Error::InvalidFile(InvalidFileType::InvalidSize))
         }
+
+        Ok((header, body_slice))
     }
 
     pub fn as_slice(&self) -> &[u8] {
@@ -127,46 +347,150 @@ impl ArtifactHeader {
     }
 }
 
+fn pad_version(version: &str) -> [u8; 16] {
+    let mut padded = [0; 16];
+    let len = version.len().min(padded.len());
+    padded[..len].copy_from_slice(&version.as_bytes()[..len]);
+    padded
+}
+
+fn unpad_version(padded: &[u8; 16]) -> &str {
+    let len = padded.iter().position(|&byte| byte == 0).unwrap_or(16);
+    str::from_utf8(&padded[..len]).unwrap_or("unknown")
+}
+
+/// Signs artifacts when they're serialized, and verifies their
+/// signatures when they're deserialized, so that artifacts from
+/// anyone without the key are refused.
+///
+/// The signature covers the header of the artifact, which holds a hash
+/// of the rest of it.
+pub trait ArtifactSigner: Send + Sync {
+    /// The signature of `data`.
+    fn sign(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Whether `signature` is a signature of `data`.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Signs artifacts with a BLAKE2b MAC keyed with a secret key.
+pub struct KeyedHash {
+    key: Vec<u8>,
+}
+
+impl KeyedHash {
+    /// A signer with `key`, which is at most 64 bytes long.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(key.len() <= 64, "a BLAKE2b key is at most 64 bytes long");
+        Self { key: key.to_vec() }
+    }
+}
+
+impl ArtifactSigner for KeyedHash {
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        Params::new().key(&self.key).hash(data).as_bytes().to_vec()
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        let expected = self.sign(data);
+        // Compare every byte, so the time taken doesn't tell how much
+        // of a forged signature is right.
+        expected.len() == signature.len()
+            && expected
+                .iter()
+                .zip(signature)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+lazy_static! {
+    static ref SIGNER: Mutex<Option<Arc<dyn ArtifactSigner>>> = Mutex::new(None);
+}
+
+/// Sign artifacts that are serialized from now on with `signer`, and
+/// refuse to deserialize artifacts that it didn't sign, or sign and
+/// verify none with `None`.
+pub fn set_artifact_signer(signer: Option<Box<dyn ArtifactSigner>>) {
+    *SIGNER.lock() = signer.map(Arc::from);
+}
+
+fn artifact_signer() -> Option<Arc<dyn ArtifactSigner>> {
+    SIGNER.lock().clone()
+}
+
 #[derive(Serialize, Deserialize)]
 struct ArtifactInner {
     info: Box<ModuleInfo>,
     #[serde(with = "serde_bytes")]
     backend_metadata: Box<[u8]>,
     compiled_code: Memory,
+    target: Target,
 }
 
+/// A compiled module, as it's cached.
+///
+/// Compiling the same wasm with the same backend and configuration,
+/// for the same machine, gives an artifact that serializes to the same
+/// bytes, so artifacts can be checked against a known hash.
 pub struct Artifact {
     inner: ArtifactInner,
+    backend_version: String,
 }
 
 impl Artifact {
-    pub(crate) fn from_parts(
+    #[doc(hidden)]
+    pub fn from_parts(
         info: Box<ModuleInfo>,
         backend_metadata: Box<[u8]>,
         compiled_code: Memory,
+        target: Target,
+        backend_version: &str,
     ) -> Self {
         Self {
             inner: ArtifactInner {
                 info,
                 backend_metadata,
                 compiled_code,
+                target,
             },
+            backend_version: backend_version.to_string(),
         }
     }
 
+    /// Deserialize an artifact, checking that this version of the
+    /// runtime wrote it, that it's intact, and, if a signer is set with
+    /// [`set_artifact_signer`], that it was signed.
+    ///
+    /// [`set_artifact_signer`]: fn.set_artifact_signer.html
     pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
-        let (_, body_slice) = ArtifactHeader::read_from_slice(bytes)?;
+        let signer = artifact_signer();
+        let (header, body_slice) =
+            ArtifactHeader::read_from_slice(bytes, signer.as_ref().map(|signer| &**signer))?;
 
         let inner = serde_bench::deserialize(body_slice)
             .map_err(|e| Error::DeserializeError(format!("{:#?}", e)))?;
 
-        Ok(Artifact { inner })
+        Ok(Artifact {
+            inner,
+            backend_version: unpad_version(&header.backend_version).to_string(),
+        })
     }
 
     pub fn info(&self) -> &ModuleInfo {
         &self.inner.info
     }
 
+    /// The machine that the code was compiled for.
+    pub fn target(&self) -> &Target {
+        &self.inner.target
+    }
+
+    /// The version of the backend that compiled the code.
+    pub fn backend_version(&self) -> &str {
+        &self.backend_version
+    }
+
     #[doc(hidden)]
     pub fn consume(self) -> (ModuleInfo, Box<[u8]>, Memory) {
         (
@@ -176,24 +500,21 @@ impl Artifact {
         )
     }
 
+    /// Serialize the artifact, signed if a signer is set with
+    /// [`set_artifact_signer`].
+    ///
+    /// [`set_artifact_signer`]: fn.set_artifact_signer.html
     pub fn serialize(&self) -> Result<Vec<u8>, Error> {
-        let cache_header = ArtifactHeader {
-            magic: WASMER_CACHE_MAGIC,
-            version: CURRENT_CACHE_VERSION,
-            data_len: 0,
-        };
-
-        let mut buffer = cache_header.as_slice().to_vec();
-
-        serde_bench::serialize(&mut buffer, &self.inner)
+        let mut body = Vec::new();
+        serde_bench::serialize(&mut body, &self.inner)
             .map_err(|e| Error::SerializeError(e.to_string()))?;
 
-        let data_len = (buffer.len() - mem::size_of::<ArtifactHeader>()) as u64;
-
-        let (header, _) = ArtifactHeader::read_from_slice_mut(&mut buffer)?;
-        header.data_len = data_len;
-
-        Ok(buffer)
+        let signer = artifact_signer();
+        Ok(ArtifactHeader::write(
+            &body,
+            &self.backend_version,
+            signer.as_ref().map(|signer| &**signer),
+        ))
     }
 }
 
@@ -208,6 +529,211 @@ pub trait Cache {
     fn store(&mut self, key: WasmHash, module: Module) -> Result<(), Self::StoreError>;
 }
 
+/// Serialize `map` with its entries in the order of their keys, rather
+/// than the order that the map happens to hold them in, so the maps in
+/// artifacts don't make them differ. It deserializes as any map.
+#[doc(hidden)]
+pub fn serialize_sorted<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Hash + Eq + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    let sorted: BTreeMap<&K, &V> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
 /// A unique ID generated from the version of Wasmer for use with cache versioning
 pub const WASMER_VERSION_HASH: &'static str =
     include_str!(concat!(env!("OUT_DIR"), "/wasmer_version_hash.txt"));
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    fn artifact_with_body(body: &[u8], data_len: u64) -> Vec<u8> {
+        let mut buffer = ArtifactHeader::write(body, "0.0.0", None);
+        let (header, _) = ArtifactHeader::read_from_slice(&buffer, None).unwrap();
+        let header = ArtifactHeader {
+            data_len,
+            ..*header
+        };
+        buffer[..mem::size_of::<ArtifactHeader>()].copy_from_slice(header.as_slice());
+        buffer
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let buffer = artifact_with_body(&[1, 2, 3, 4], 4);
+        let (header, body) = ArtifactHeader::read_from_slice(&buffer, None).unwrap();
+        assert_eq!(body, &[1, 2, 3, 4]);
+        assert_eq!(unpad_version(&header.wasmer_version), crate::VERSION);
+        assert_eq!(unpad_version(&header.backend_version), "0.0.0");
+    }
+
+    #[test]
+    fn test_header_rejects_truncated_body() {
+        let buffer = artifact_with_body(&[1, 2], 4);
+        match ArtifactHeader::read_from_slice(&buffer, None) {
+            Err(Error::InvalidFile(InvalidFileType::InvalidSize)) => {}
+            _ => panic!("truncated artifact was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_header_rejects_changed_body() {
+        let mut buffer = artifact_with_body(&[1, 2, 3, 4], 4);
+        *buffer.last_mut().unwrap() = 5;
+        match ArtifactHeader::read_from_slice(&buffer, None) {
+            Err(Error::InvalidFile(InvalidFileType::InvalidChecksum)) => {}
+            _ => panic!("changed artifact was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_header_rejects_other_version() {
+        let buffer = artifact_with_body(&[1, 2, 3, 4], 4);
+        let (header, _) = ArtifactHeader::read_from_slice(&buffer, None).unwrap();
+        let header = ArtifactHeader {
+            wasmer_version: pad_version("0.0.1"),
+            ..*header
+        };
+        let mut buffer = buffer.clone();
+        buffer[..mem::size_of::<ArtifactHeader>()].copy_from_slice(header.as_slice());
+        match ArtifactHeader::read_from_slice(&buffer, None) {
+            Err(Error::IncompatibleVersion { wasmer, backend }) => {
+                assert_eq!(wasmer, "0.0.1");
+                assert_eq!(backend, "0.0.0");
+            }
+            _ => panic!("artifact of another version was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_signed_artifacts() {
+        let signer = KeyedHash::new(b"a secret key");
+        let other = KeyedHash::new(b"another key");
+        let body = [1, 2, 3, 4];
+
+        let signed = ArtifactHeader::write(&body, "0.0.0", Some(&signer));
+        let (_, read) = ArtifactHeader::read_from_slice(&signed, Some(&signer)).unwrap();
+        assert_eq!(read, &body);
+        // Artifacts are still read without a signer.
+        assert!(ArtifactHeader::read_from_slice(&signed, None).is_ok());
+
+        let unsigned = ArtifactHeader::write(&body, "0.0.0", None);
+        for (buffer, signer) in &[(&signed, &other), (&unsigned, &signer)] {
+            match ArtifactHeader::read_from_slice(buffer, Some(*signer)) {
+                Err(Error::InvalidFile(InvalidFileType::InvalidSignature)) => {}
+                _ => panic!("artifact that the signer didn't sign was accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_targets_that_run_on_host() {
+        let host = Target::host();
+        assert!(host.runs_on_host());
+
+        let generic = Target {
+            features: Vec::new(),
+            ..host.clone()
+        };
+        assert!(generic.runs_on_host());
+
+        let other = Target {
+            arch: "not-an-arch".to_string(),
+            ..host.clone()
+        };
+        assert!(!other.runs_on_host());
+
+        let target = Target::from_triple("aarch64-unknown-linux-gnu", &["avx2", "neon"]);
+        assert_eq!(target.arch, "aarch64");
+        assert_eq!(target.os, "linux");
+        assert_eq!(target.features, vec!["avx2".to_string()]);
+        assert_eq!(
+            Target::from_triple("x86_64-apple-darwin", &[]).to_string(),
+            "x86_64-macos:"
+        );
+        assert_eq!(
+            Target::from_triple("x86_64-apple-darwin18.2.0", &[]).os,
+            "macos"
+        );
+    }
+
+    #[test]
+    fn test_target_arch_names() {
+        for &(triple, arch) in &[
+            ("x86_64-unknown-linux-gnu", "x86_64"),
+            ("amd64-unknown-freebsd", "x86_64"),
+            ("i686-pc-windows-msvc", "x86"),
+            ("i586-unknown-linux-gnu", "x86"),
+            ("arm64-apple-darwin", "aarch64"),
+            ("aarch64_be-unknown-linux-gnu", "aarch64"),
+            ("armv7-unknown-linux-gnueabihf", "arm"),
+            ("thumbv7neon-unknown-linux-gnueabihf", "arm"),
+            ("powerpc64le-unknown-linux-gnu", "powerpc64"),
+            ("mips64el-unknown-linux-gnuabi64", "mips64"),
+            ("riscv64gc-unknown-linux-gnu", "riscv64"),
+        ] {
+            assert_eq!(Target::from_triple(triple, &[]).arch, arch, "{}", triple);
+        }
+
+        let host_triple = format!("{}-unknown-linux-gnu", std::env::consts::ARCH);
+        assert_eq!(
+            Target::from_triple(&host_triple, &[]).arch,
+            std::env::consts::ARCH
+        );
+    }
+
+    #[test]
+    fn test_host_features_are_detected_features() {
+        assert!(Target::host()
+            .features
+            .iter()
+            .all(|feature| DETECTED_FEATURES.contains(&feature.as_str())));
+    }
+
+    #[test]
+    fn test_baseline_runs_on_host() {
+        let baseline = Target::baseline();
+        assert!(baseline.runs_on_host());
+        assert!(baseline
+            .features
+            .iter()
+            .all(|feature| Target::host().features.contains(feature)));
+    }
+
+    #[test]
+    fn test_serialize_sorted() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde(serialize_with = "serialize_sorted")] HashMap<u32, u32>);
+
+        let mut forward = HashMap::new();
+        for i in 0..100 {
+            forward.insert(i, i * 2);
+        }
+        let mut backward = HashMap::with_capacity(1000);
+        for i in (0..100).rev() {
+            backward.insert(i, i * 2);
+        }
+
+        let mut forward_bytes = Vec::new();
+        serde_bench::serialize(&mut forward_bytes, &Wrapper(forward.clone())).unwrap();
+        let mut backward_bytes = Vec::new();
+        serde_bench::serialize(&mut backward_bytes, &Wrapper(backward)).unwrap();
+        assert_eq!(forward_bytes, backward_bytes);
+
+        let Wrapper(map) = serde_bench::deserialize(&forward_bytes).unwrap();
+        assert_eq!(map, forward);
+    }
+
+    #[test]
+    fn test_target_hash_differs_per_backend() {
+        let hash = WasmHash::generate(&[0, 1, 2, 3]);
+        assert_ne!(
+            hash.for_target(Backend::Cranelift).encode(),
+            hash.for_target(Backend::LLVM).encode()
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Writing a dump when the process faults where it can't recover.
+//!
+//! A fault in compiled code is returned as a trap by the protected
+//! call that ran it, but a fault with no protected call to unwind to,
+//! in the host or in the runtime itself, aborts the process. If a dump
+//! directory has been set with [`set_dump_dir`], a dump is written to
+//! `wasmer-crash-<pid>.txt` in it before the process aborts, with the
+//! fault, the WebAssembly functions that the thread was calling, the
+//! backend and memories of their instances, and the configuration of
+//! the runtime, so that the crash can be reported.
+//!
+//! The dump is written from the signal handler, which isn't safe in
+//! general, but the process is about to abort anyway. Only unix has
+//! signal handlers, so only unix writes dumps.
+//!
+//! [`set_dump_dir`]: fn.set_dump_dir.html
+
+use crate::{
+    deterministic,
+    module::ModuleInfo,
+    platform::{Fault, FaultKind},
+    stack,
+    structures::TypedIndex,
+    types::{FuncIndex, LocalFuncIndex},
+    vm,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    cell::RefCell,
+    fmt::Write as FmtWrite,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+lazy_static! {
+    static ref DUMP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+thread_local! {
+    /// The calls into instances that the thread is running, outermost
+    /// first.
+    static CALLS: RefCell<Vec<Call>> = RefCell::new(vec![]);
+}
+
+/// A call into an instance.
+#[derive(Debug, Clone, Copy)]
+struct Call {
+    /// The context of the instance.
+    vmctx: *const vm::Ctx,
+    callee: Callee,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Callee {
+    Index(FuncIndex),
+    /// A function called through a typed `Func`, which is found among
+    /// the local functions of the instance when the dump is written.
+    Ptr(*const vm::Func),
+}
+
+/// Write a dump to `dir` when the process faults where it can't
+/// recover, or stop writing them with `None`.
+pub fn set_dump_dir(dir: Option<PathBuf>) {
+    *DUMP_DIR.lock() = dir;
+}
+
+/// The file that a dump is written to, if dumps are written.
+pub fn dump_path() -> Option<PathBuf> {
+    DUMP_DIR.lock().as_ref().map(|dir| dir.join(file_name()))
+}
+
+fn file_name() -> String {
+    format!("wasmer-crash-{}.txt", process::id())
+}
+
+/// Keeps a call on the thread's list of calls into instances until
+/// it's dropped.
+pub(crate) struct CallGuard(());
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        CALLS.with(|calls| calls.borrow_mut().pop());
+    }
+}
+
+/// Note that the thread calls the function `func_index` of the
+/// instance whose context is `vmctx`.
+pub(crate) fn enter(vmctx: *const vm::Ctx, func_index: FuncIndex) -> CallGuard {
+    push(Call {
+        vmctx,
+        callee: Callee::Index(func_index),
+    })
+}
+
+/// Note that the thread calls `func` with `vmctx`, as a typed `Func`
+/// does, without knowing its index.
+pub(crate) fn enter_ptr(vmctx: *const vm::Ctx, func: *const vm::Func) -> CallGuard {
+    push(Call {
+        vmctx,
+        callee: Callee::Ptr(func),
+    })
+}
+
+fn push(call: Call) -> CallGuard {
+    CALLS.with(|calls| calls.borrow_mut().push(call));
+    CallGuard(())
+}
+
+/// Write a dump for `fault`, which can't be recovered from, if dumps
+/// are written, and abort.
+pub(crate) fn abort_on_fault(fault: &Fault) -> ! {
+    let kind = match fault.kind {
+        FaultKind::MemoryAccess => "memory access fault",
+        FaultKind::IllegalInstruction => "illegal instruction",
+        FaultKind::Arithmetic => "arithmetic fault",
+        FaultKind::StackOverflow => "stack overflow",
+    };
+    abort(&format!(
+        "{} at {:#x}, accessing {:#x}, outside of any protected call",
+        kind, fault.instruction, fault.address
+    ))
+}
+
+/// Write a dump for `reason` if dumps are written, and abort.
+pub(crate) fn abort(reason: &str) -> ! {
+    // The list of calls is only borrowed while it's being changed, so
+    // it's left out if the fault happened then.
+    let calls = CALLS
+        .with(|calls| calls.try_borrow().map(|calls| calls.clone()))
+        .unwrap_or_default();
+    let dir = DUMP_DIR.try_lock().and_then(|dir| dir.clone());
+    if let Some(path) = dir.map(|dir| dir.join(file_name())) {
+        let dump = unsafe { dump(reason, &calls) };
+        if let Ok(mut file) = File::create(&path) {
+            let _ = file.write_all(dump.as_bytes());
+            eprintln!("wasmer: wrote a crash dump to {}", path.display());
+        }
+    }
+    process::abort()
+}
+
+/// The text of a dump for `reason`, with the calls that the thread is
+/// running, outermost first.
+unsafe fn dump(reason: &str, calls: &[Call]) -> String {
+    let mut out = String::new();
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    let _ = writeln!(out, "wasmer-runtime-core {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        out,
+        "process {}, at {}s since the unix epoch",
+        process::id(),
+        seconds
+    );
+    let _ = writeln!(out, "reason: {}", reason);
+
+    let _ = writeln!(out, "\nconfig:");
+    let _ = writeln!(
+        out,
+        "  deterministic: {}",
+        deterministic::is_deterministic()
+    );
+    let _ = writeln!(out, "  max stack size: {} bytes", stack::max_stack_size());
+
+    let _ = writeln!(out, "\nwasm backtrace, innermost first:");
+    if calls.is_empty() {
+        let _ = writeln!(out, "  (no calls into instances)");
+    }
+    for (depth, call) in calls.iter().rev().enumerate() {
+        if call.vmctx.is_null() || (*call.vmctx).module.is_null() {
+            let _ = writeln!(out, "  #{}: a host function", depth);
+            continue;
+        }
+        let info = &(*(*call.vmctx).module).info;
+        let func = match call.callee {
+            Callee::Index(func_index) => describe_func(info, func_index),
+            Callee::Ptr(func) => find_local_func(&*call.vmctx, info, func)
+                .map(|func_index| describe_func(info, func_index))
+                .unwrap_or_else(|| format!("a function at {:p}", func)),
+        };
+        let _ = writeln!(out, "  #{}: {}", depth, func);
+        let _ = writeln!(
+            out,
+            "      backend: {}, memory style: {:?}, metering: {}",
+            info.backend.name(),
+            info.memory_style,
+            match info.initial_points {
+                Some(points) => format!("{} initial points", points),
+                None => "off".to_string(),
+            }
+        );
+        write_memories(&mut out, &*call.vmctx, info);
+    }
+
+    out
+}
+
+fn describe_func(info: &ModuleInfo, func_index: FuncIndex) -> String {
+    match info.func_names.get(&func_index) {
+        Some(name) => format!("function {} ({})", func_index.index(), name),
+        None => format!("function {}", func_index.index()),
+    }
+}
+
+/// The index of the local function of the instance that `func` points
+/// to, if it's one of them.
+unsafe fn find_local_func(
+    vmctx: &vm::Ctx,
+    info: &ModuleInfo,
+    func: *const vm::Func,
+) -> Option<FuncIndex> {
+    let import_count = info.imported_functions.len();
+    (0..info.func_assoc.len() - import_count)
+        .find(|&index| vmctx.local_function(LocalFuncIndex::new(index)) == func)
+        .map(|index| FuncIndex::new(import_count + index))
+}
+
+unsafe fn write_memories(out: &mut String, vmctx: &vm::Ctx, info: &ModuleInfo) {
+    let imported = (0..info.imported_memories.len())
+        .map(|index| (*vmctx.internal.imported_memories.add(index), "imported "));
+    let local = (0..info.memories.len()).map(|index| (*vmctx.internal.memories.add(index), ""));
+    for (index, (memory, imported)) in imported.chain(local).enumerate() {
+        let _ = writeln!(
+            out,
+            "      {}memory {}: {} bytes at {:p}",
+            imported,
+            index,
+            (*memory).bound,
+            (*memory).base
+        );
+    }
+}
+
+#[cfg(test)]
+mod crash_tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_path() {
+        set_dump_dir(Some(PathBuf::from("/tmp/dumps")));
+        let path = dump_path().unwrap();
+        set_dump_dir(None);
+
+        assert_eq!(
+            path,
+            PathBuf::from(format!("/tmp/dumps/wasmer-crash-{}.txt", process::id()))
+        );
+        assert_eq!(dump_path(), None);
+    }
+
+    #[test]
+    fn test_dump() {
+        let text = unsafe { dump("a test", &[]) };
+        assert!(text.contains("reason: a test"));
+        assert!(text.contains("deterministic: "));
+        assert!(text.contains("(no calls into instances)"));
+
+        let host = Call {
+            vmctx: std::ptr::null(),
+            callee: Callee::Index(FuncIndex::new(0)),
+        };
+        let guard = push(host);
+        let calls = CALLS.with(|calls| calls.borrow().clone());
+        drop(guard);
+        assert_eq!(CALLS.with(|calls| calls.borrow().len()), 0);
+
+        let text = unsafe { dump("a test", &calls) };
+        assert!(text.contains("#0: a host function"));
+    }
+}
@@ -0,0 +1,950 @@
+//! Debugging of WebAssembly code with breakpoints.
+//!
+//! Once [`set_debugging`] has been called, the modules that are compiled
+//! from then on are instrumented by a debugging middleware, which makes
+//! their code check for breakpoints before every operator. When an
+//! instance of such a module reaches one of its breakpoints, or is asked
+//! to pause with a [`PauseHandle`], execution pauses and the instance's
+//! [`DebugHandler`] is called with the state of the code. It can look at
+//! the locals and the call stack, and at memory through the `vm::Ctx`,
+//! and then resume, either until the next breakpoint or for a single
+//! step, to the next operator.
+//!
+//! Breakpoints are set with [`Instance::add_breakpoint`], at the entry
+//! of a function or at the offset of one of its operators from the
+//! start of its body.
+//!
+//! Instrumented code is a lot slower and bigger than the original, and
+//! the functions of an instrumented module are preceded by the imports
+//! of the debugger's hooks, which the runtime provides itself.
+//!
+//! [`set_debugging`]: fn.set_debugging.html
+//! [`PauseHandle`]: struct.PauseHandle.html
+//! [`DebugHandler`]: trait.DebugHandler.html
+//! [`Instance::add_breakpoint`]: ../struct.Instance.html#method.add_breakpoint
+
+use crate::{
+    error::{CompileError, CompileResult},
+    export::Export,
+    import::IsExport,
+    middleware::{
+        self, Event, EventSink, FunctionMiddleware, Instruction, Operator, Section, API_VERSION,
+        CODE_SECTION, CUSTOM_SECTION,
+    },
+    refs::{ExternRef, FuncRef},
+    structures::TypedIndex,
+    types::{FuncIndex, LocalFuncIndex, Value},
+    vm::Ctx,
+    Func,
+};
+use hashbrown::HashSet;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// The namespace of the imports of the debugger's hooks.
+pub(crate) const NAMESPACE: &str = "__wasmer_debug";
+
+static DEBUGGING: AtomicBool = AtomicBool::new(false);
+
+/// Instrument the modules that are compiled from now on for debugging,
+/// or stop doing so.
+///
+/// Modules that have already been compiled, including those in a cache,
+/// keep the instrumentation that they were compiled with.
+pub fn set_debugging(debugging: bool) {
+    DEBUGGING.store(debugging, Ordering::SeqCst);
+}
+
+/// Whether modules are instrumented for debugging.
+pub fn is_debugging() -> bool {
+    DEBUGGING.load(Ordering::SeqCst)
+}
+
+/// A place in the code to pause at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Breakpoint {
+    /// The function, indexed as it was before the module was
+    /// instrumented.
+    pub function: FuncIndex,
+    /// The offset of an operator from the start of the body of the
+    /// function, or `None` for the entry of the function.
+    pub offset: Option<usize>,
+}
+
+impl Breakpoint {
+    /// A breakpoint at the entry of `function`.
+    pub fn entry(function: FuncIndex) -> Self {
+        Breakpoint {
+            function,
+            offset: None,
+        }
+    }
+
+    /// A breakpoint at the operator at `offset` in `function`.
+    pub fn at(function: FuncIndex, offset: usize) -> Self {
+        Breakpoint {
+            function,
+            offset: Some(offset),
+        }
+    }
+}
+
+/// How to go on from a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Run the next operator and pause again.
+    Step,
+}
+
+/// Called when the code running in an instance pauses.
+pub trait DebugHandler {
+    fn paused(&mut self, paused: &mut Paused) -> Resume;
+}
+
+/// The state of paused code.
+pub struct Paused<'a> {
+    ctx: &'a mut Ctx,
+    location: Breakpoint,
+    locals: &'a [Value],
+    call_stack: &'a [FuncIndex],
+}
+
+impl<'a> Paused<'a> {
+    /// Where the code is paused, before the operator at the offset.
+    pub fn location(&self) -> Breakpoint {
+        self.location
+    }
+
+    /// The parameters and locals of the function, in order.
+    pub fn locals(&self) -> &[Value] {
+        self.locals
+    }
+
+    /// The functions that are being called, from the outermost to the
+    /// paused one.
+    pub fn call_stack(&self) -> &[FuncIndex] {
+        self.call_stack
+    }
+
+    /// The context of the instance, through which its memories can be
+    /// inspected.
+    pub fn ctx(&mut self) -> &mut Ctx {
+        self.ctx
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        state(self.ctx).breakpoints.insert(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        state(self.ctx).breakpoints.remove(&breakpoint)
+    }
+}
+
+/// A handle to an instance that can be sent to other threads to pause
+/// the code running in it, as returned by [`Instance::pause_handle`].
+///
+/// [`Instance::pause_handle`]: ../struct.Instance.html#method.pause_handle
+#[derive(Debug, Clone)]
+pub struct PauseHandle {
+    requested: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    /// Pause the code running in the instance before its next operator,
+    /// if the instance has a debug handler.
+    pub fn pause(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The debugging state of an instance.
+pub(crate) struct DebugState {
+    pub handler: Option<Box<dyn DebugHandler>>,
+    pub breakpoints: HashSet<Breakpoint>,
+    stepping: bool,
+    pause_requested: Arc<AtomicBool>,
+    location: Option<Breakpoint>,
+    locals: Vec<Value>,
+    call_stack: Vec<FuncIndex>,
+}
+
+impl fmt::Debug for DebugState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DebugState")
+            .field("has_handler", &self.handler.is_some())
+            .field("breakpoints", &self.breakpoints)
+            .field("stepping", &self.stepping)
+            .field("call_stack", &self.call_stack)
+            .finish()
+    }
+}
+
+impl DebugState {
+    pub fn new() -> Self {
+        DebugState {
+            handler: None,
+            breakpoints: HashSet::new(),
+            stepping: false,
+            pause_requested: Arc::new(AtomicBool::new(false)),
+            location: None,
+            locals: vec![],
+            call_stack: vec![],
+        }
+    }
+
+    pub fn pause_handle(&self) -> PauseHandle {
+        PauseHandle {
+            requested: Arc::clone(&self.pause_requested),
+        }
+    }
+
+    /// Forget the calls that a trap unwound.
+    pub fn unwind(&mut self) {
+        self.call_stack.clear();
+        self.locals.clear();
+        self.location = None;
+    }
+}
+
+fn state(ctx: &mut Ctx) -> &mut DebugState {
+    unsafe { &mut (*ctx.local_backing).debug }
+}
+
+/// The hook of the debugger called `name`.
+pub(crate) fn hook(name: &str) -> Option<Export> {
+    Some(match name {
+        "check" => Func::new(check).to_export(),
+        "pause" => Func::new(pause).to_export(),
+        "enter" => Func::new(enter).to_export(),
+        "leave" => Func::new(leave).to_export(),
+        "local_i32" => Func::new(local_i32).to_export(),
+        "local_i64" => Func::new(local_i64).to_export(),
+        "local_f32" => Func::new(local_f32).to_export(),
+        "local_f64" => Func::new(local_f64).to_export(),
+        "local_externref" => Func::new(local_externref).to_export(),
+        "local_funcref" => Func::new(local_funcref).to_export(),
+        _ => return None,
+    })
+}
+
+/// Whether to pause before the operator at `offset` in `function`, or at
+/// its entry if `offset` is negative. If so, the locals are reported and
+/// then `pause` is called.
+fn check(ctx: &mut Ctx, function: i32, offset: i32) -> i32 {
+    let state = state(ctx);
+    if state.handler.is_none() {
+        return 0;
+    }
+
+    let location = Breakpoint {
+        function: FuncIndex::new(function as u32 as usize),
+        offset: if offset < 0 {
+            None
+        } else {
+            Some(offset as usize)
+        },
+    };
+    let pause = (state.stepping && location.offset.is_some())
+        || state.pause_requested.swap(false, Ordering::SeqCst)
+        || state.breakpoints.contains(&location);
+    if pause {
+        state.location = Some(location);
+        state.locals.clear();
+    }
+    pause as i32
+}
+
+fn pause(ctx: &mut Ctx) {
+    let state = state(ctx);
+    let (mut handler, location) = match (state.handler.take(), state.location.take()) {
+        (Some(handler), Some(location)) => (handler, location),
+        (handler, _) => {
+            state.handler = handler;
+            return;
+        }
+    };
+    let locals = state.locals.clone();
+    let call_stack = state.call_stack.clone();
+
+    let resume = handler.paused(&mut Paused {
+        ctx,
+        location,
+        locals: &locals,
+        call_stack: &call_stack,
+    });
+
+    let state = self::state(ctx);
+    state.stepping = resume == Resume::Step;
+    state.handler = Some(handler);
+}
+
+fn enter(ctx: &mut Ctx, function: i32) {
+    state(ctx)
+        .call_stack
+        .push(FuncIndex::new(function as u32 as usize));
+}
+
+fn leave(ctx: &mut Ctx) {
+    state(ctx).call_stack.pop();
+}
+
+fn local_i32(ctx: &mut Ctx, value: i32) {
+    state(ctx).locals.push(Value::I32(value));
+}
+
+fn local_i64(ctx: &mut Ctx, value: i64) {
+    state(ctx).locals.push(Value::I64(value));
+}
+
+fn local_f32(ctx: &mut Ctx, value: f32) {
+    state(ctx).locals.push(Value::F32(value));
+}
+
+fn local_f64(ctx: &mut Ctx, value: f64) {
+    state(ctx).locals.push(Value::F64(value));
+}
+
+fn local_externref(ctx: &mut Ctx, value: ExternRef) {
+    state(ctx).locals.push(Value::ExternRef(value));
+}
+
+fn local_funcref(ctx: &mut Ctx, value: FuncRef) {
+    state(ctx).locals.push(Value::FuncRef(value));
+}
+
+const TYPE_SECTION: u8 = 1;
+const IMPORT_SECTION: u8 = 2;
+const FUNCTION_SECTION: u8 = 3;
+const EXPORT_SECTION: u8 = 7;
+const START_SECTION: u8 = 8;
+const ELEMENT_SECTION: u8 = 9;
+
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+const F32: u8 = 0x7d;
+const F64: u8 = 0x7c;
+const FUNCREF: u8 = 0x70;
+const EXTERNREF: u8 = 0x6f;
+
+/// The hooks, with the types of their parameters and results, in the
+/// order that they're imported in. The ones for references are only
+/// imported by modules with locals of those types.
+const HOOKS: &[(&str, &[u8], &[u8])] = &[
+    ("check", &[I32, I32], &[I32]),
+    ("pause", &[], &[]),
+    ("enter", &[I32], &[]),
+    ("leave", &[], &[]),
+    ("local_i32", &[I32], &[]),
+    ("local_i64", &[I64], &[]),
+    ("local_f32", &[F32], &[]),
+    ("local_f64", &[F64], &[]),
+    ("local_externref", &[EXTERNREF], &[]),
+    ("local_funcref", &[FUNCREF], &[]),
+];
+const NUMERIC_HOOKS: u32 = 8;
+
+const CHECK: u32 = 0;
+const PAUSE: u32 = 1;
+const ENTER: u32 = 2;
+const LEAVE: u32 = 3;
+
+fn local_hook(ty: u8) -> Option<u32> {
+    Some(match ty {
+        I32 => 4,
+        I64 => 5,
+        F32 => 6,
+        F64 => 7,
+        EXTERNREF => 8,
+        FUNCREF => 9,
+        _ => return None,
+    })
+}
+
+/// Instrument the module in `wasm` for debugging.
+pub(crate) fn instrument(wasm: &[u8]) -> CompileResult<Vec<u8>> {
+    let invalid = || CompileError::ValidationError {
+        msg: "malformed module".to_string(),
+    };
+    let sections = middleware::sections(wasm)?;
+    let layout = Layout::read(wasm, &sections).ok_or_else(invalid)?;
+
+    let uses_references = layout.functions.iter().any(|function| {
+        function
+            .locals
+            .iter()
+            .any(|&ty| ty == EXTERNREF || ty == FUNCREF)
+    });
+    let hooks = if uses_references {
+        HOOKS.len() as u32
+    } else {
+        NUMERIC_HOOKS
+    };
+    let shift = Shift {
+        imported_functions: layout.imported_functions,
+        hooks,
+    };
+
+    let mut out = wasm[..8].to_vec();
+    let mut wrote_types = false;
+    let mut wrote_imports = false;
+    for section in &sections {
+        let payload = &wasm[section.payload.clone()];
+        if section.id != CUSTOM_SECTION {
+            if section.id > TYPE_SECTION && !wrote_types {
+                let types = layout.hook_types(&[], hooks);
+                middleware::write_section(&mut out, TYPE_SECTION, &types);
+                wrote_types = true;
+            }
+            if section.id > IMPORT_SECTION && !wrote_imports {
+                let imports = layout.hook_imports(&[], hooks);
+                middleware::write_section(&mut out, IMPORT_SECTION, &imports);
+                wrote_imports = true;
+            }
+        }
+
+        let rewritten = match section.id {
+            TYPE_SECTION => {
+                wrote_types = true;
+                Some(layout.hook_types(payload, hooks))
+            }
+            IMPORT_SECTION => {
+                wrote_imports = true;
+                Some(layout.hook_imports(payload, hooks))
+            }
+            EXPORT_SECTION => Some(shift.exports(payload).ok_or_else(invalid)?),
+            START_SECTION => Some(shift.start(payload).ok_or_else(invalid)?),
+            ELEMENT_SECTION => Some(shift.elements(payload).ok_or_else(invalid)?),
+            CODE_SECTION => {
+                let mut middlewares: Vec<Box<dyn FunctionMiddleware>> =
+                    vec![Box::new(DebugMiddleware::new(&layout, shift)?)];
+                Some(middleware::transform_code(
+                    wasm,
+                    section.payload.start,
+                    section.payload.end,
+                    &mut middlewares,
+                    &mut BTreeMap::new(),
+                )?)
+            }
+            CUSTOM_SECTION => shift.names(payload),
+            _ => None,
+        };
+        match rewritten {
+            Some(payload) => middleware::write_section(&mut out, section.id, &payload),
+            None => out.extend_from_slice(&wasm[section.range()]),
+        }
+    }
+    if !wrote_types {
+        let types = layout.hook_types(&[], hooks);
+        middleware::write_section(&mut out, TYPE_SECTION, &types);
+    }
+    if !wrote_imports {
+        let imports = layout.hook_imports(&[], hooks);
+        middleware::write_section(&mut out, IMPORT_SECTION, &imports);
+    }
+
+    Ok(out)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.position)?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        middleware::read_u32(self.bytes, &mut self.position)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn name(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn skip_signed(&mut self) -> Option<()> {
+        while self.u8()? & 0x80 != 0 {}
+        Some(())
+    }
+
+    fn skip_limits(&mut self) -> Option<()> {
+        let flags = self.u8()?;
+        self.u32()?;
+        if flags & 1 != 0 {
+            self.u32()?;
+        }
+        Some(())
+    }
+
+    fn skip_init_expr(&mut self) -> Option<()> {
+        loop {
+            match self.u8()? {
+                0x0b => return Some(()),
+                0x41 | 0x42 => self.skip_signed()?,
+                0x43 => {
+                    self.bytes(4)?;
+                }
+                0x44 => {
+                    self.bytes(8)?;
+                }
+                0x23 => {
+                    self.u32()?;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// The bytes read since `start`.
+    fn since(&self, start: usize) -> &'a [u8] {
+        &self.bytes[start..self.position]
+    }
+}
+
+/// What instrumenting a function needs to know about it.
+#[derive(Clone)]
+struct FunctionLayout {
+    index: FuncIndex,
+    /// The types of the parameters and locals.
+    locals: Vec<u8>,
+    result: Option<u8>,
+    /// The length of the declarations of the locals, which the offsets
+    /// of operators start from.
+    locals_len: usize,
+}
+
+struct Layout {
+    types: u32,
+    imports: u32,
+    imported_functions: u32,
+    functions: Vec<FunctionLayout>,
+}
+
+impl Layout {
+    fn read(wasm: &[u8], sections: &[Section]) -> Option<Self> {
+        let mut types = vec![];
+        let mut imports = 0;
+        let mut imported_functions = 0;
+        let mut signatures = vec![];
+        let mut functions = vec![];
+
+        for section in sections {
+            let mut reader = Reader::new(&wasm[section.payload.clone()]);
+            match section.id {
+                TYPE_SECTION => {
+                    for _ in 0..reader.u32()? {
+                        if reader.u8()? != 0x60 {
+                            return None;
+                        }
+                        let params = reader.name()?.to_vec();
+                        let results = reader.name()?.to_vec();
+                        types.push((params, results));
+                    }
+                }
+                IMPORT_SECTION => {
+                    imports = reader.u32()?;
+                    for _ in 0..imports {
+                        reader.name()?;
+                        reader.name()?;
+                        match reader.u8()? {
+                            0 => {
+                                reader.u32()?;
+                                imported_functions += 1;
+                            }
+                            1 => {
+                                reader.u8()?;
+                                reader.skip_limits()?;
+                            }
+                            2 => reader.skip_limits()?,
+                            3 => {
+                                reader.bytes(2)?;
+                            }
+                            _ => return None,
+                        }
+                    }
+                }
+                FUNCTION_SECTION => {
+                    for _ in 0..reader.u32()? {
+                        signatures.push(reader.u32()? as usize);
+                    }
+                }
+                CODE_SECTION => {
+                    for (i, _) in (0..reader.u32()?).enumerate() {
+                        let size = reader.u32()? as usize;
+                        let start = reader.position;
+                        let (params, results) = types.get(*signatures.get(i)?)?;
+                        let mut locals = params.clone();
+                        for _ in 0..reader.u32()? {
+                            let count = reader.u32()? as usize;
+                            let ty = reader.u8()?;
+                            locals.resize(locals.len() + count, ty);
+                        }
+                        functions.push(FunctionLayout {
+                            index: FuncIndex::new(imported_functions as usize + i),
+                            locals,
+                            result: results.first().cloned(),
+                            locals_len: reader.position - start,
+                        });
+                        reader.position = start.checked_add(size)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Some(Layout {
+            types: types.len() as u32,
+            imports,
+            imported_functions,
+            functions,
+        })
+    }
+
+    /// The type section, with the types of the hooks after those in
+    /// `payload`.
+    fn hook_types(&self, payload: &[u8], hooks: u32) -> Vec<u8> {
+        let mut out = vec![];
+        middleware::write_u32(&mut out, self.types + hooks);
+        out.extend_from_slice(skip_count(payload));
+        for &(_, params, results) in &HOOKS[..hooks as usize] {
+            out.push(0x60);
+            middleware::write_u32(&mut out, params.len() as u32);
+            out.extend_from_slice(params);
+            middleware::write_u32(&mut out, results.len() as u32);
+            out.extend_from_slice(results);
+        }
+        out
+    }
+
+    /// The import section, with the hooks after the imports in `payload`.
+    fn hook_imports(&self, payload: &[u8], hooks: u32) -> Vec<u8> {
+        let mut out = vec![];
+        middleware::write_u32(&mut out, self.imports + hooks);
+        out.extend_from_slice(skip_count(payload));
+        for (i, &(name, _, _)) in HOOKS[..hooks as usize].iter().enumerate() {
+            middleware::write_name(&mut out, NAMESPACE);
+            middleware::write_name(&mut out, name);
+            out.push(0);
+            middleware::write_u32(&mut out, self.types + i as u32);
+        }
+        out
+    }
+}
+
+/// The entries of a section, after their count.
+fn skip_count(payload: &[u8]) -> &[u8] {
+    let mut position = 0;
+    match middleware::read_u32(payload, &mut position) {
+        Some(_) => &payload[position..],
+        None => &[],
+    }
+}
+
+/// Shifts the indices of the functions defined by a module past the
+/// hooks, which are imported after its own imports.
+#[derive(Clone, Copy)]
+struct Shift {
+    imported_functions: u32,
+    hooks: u32,
+}
+
+impl Shift {
+    fn function(&self, index: u32) -> u32 {
+        if index < self.imported_functions {
+            index
+        } else {
+            index + self.hooks
+        }
+    }
+
+    fn exports(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader::new(payload);
+        let mut out = vec![];
+        let count = reader.u32()?;
+        middleware::write_u32(&mut out, count);
+        for _ in 0..count {
+            let start = reader.position;
+            reader.name()?;
+            let kind = reader.u8()?;
+            out.extend_from_slice(reader.since(start));
+            let index = reader.u32()?;
+            let index = if kind == 0 {
+                self.function(index)
+            } else {
+                index
+            };
+            middleware::write_u32(&mut out, index);
+        }
+        Some(out)
+    }
+
+    fn start(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut out = vec![];
+        middleware::write_u32(&mut out, self.function(Reader::new(payload).u32()?));
+        Some(out)
+    }
+
+    fn elements(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader::new(payload);
+        let mut out = vec![];
+        let count = reader.u32()?;
+        middleware::write_u32(&mut out, count);
+        for _ in 0..count {
+            let start = reader.position;
+            match reader.u32()? {
+                0 => reader.skip_init_expr()?,
+                1 => {
+                    reader.u8()?;
+                }
+                2 => {
+                    reader.u32()?;
+                    reader.skip_init_expr()?;
+                }
+                _ => return None,
+            }
+            out.extend_from_slice(reader.since(start));
+            let len = reader.u32()?;
+            middleware::write_u32(&mut out, len);
+            for _ in 0..len {
+                let index = self.function(reader.u32()?);
+                middleware::write_u32(&mut out, index);
+            }
+        }
+        Some(out)
+    }
+
+    /// The `name` section, with the names of the functions moved along
+    /// with them, or `None` for any other custom section.
+    fn names(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader::new(payload);
+        if reader.name()? != b"name" {
+            return None;
+        }
+        let mut out = reader.since(0).to_vec();
+        while !reader.eof() {
+            let id = reader.u8()?;
+            let len = reader.u32()? as usize;
+            let mut subsection = Reader::new(reader.bytes(len)?);
+            let rewritten = match id {
+                // The names of functions, and of their locals.
+                1 | 2 => {
+                    let mut rewritten = vec![];
+                    let count = subsection.u32()?;
+                    middleware::write_u32(&mut rewritten, count);
+                    for _ in 0..count {
+                        let index = self.function(subsection.u32()?);
+                        middleware::write_u32(&mut rewritten, index);
+                        let start = subsection.position;
+                        if id == 1 {
+                            subsection.name()?;
+                        } else {
+                            for _ in 0..subsection.u32()? {
+                                subsection.u32()?;
+                                subsection.name()?;
+                            }
+                        }
+                        rewritten.extend_from_slice(subsection.since(start));
+                    }
+                    rewritten
+                }
+                _ => subsection.bytes.to_vec(),
+            };
+            middleware::write_section(&mut out, id, &rewritten);
+        }
+        Some(out)
+    }
+}
+
+/// Makes functions check for breakpoints before every operator, and
+/// keep track of the call stack.
+struct DebugMiddleware {
+    functions: Vec<FunctionLayout>,
+    shift: Shift,
+    /// The local index of the function being instrumented.
+    current: usize,
+    /// The offset of the next operator.
+    offset: usize,
+    /// The depth of the blocks that the next operator is in.
+    depth: usize,
+}
+
+impl DebugMiddleware {
+    fn new(layout: &Layout, shift: Shift) -> CompileResult<Self> {
+        for function in &layout.functions {
+            if function.locals.iter().any(|&ty| local_hook(ty).is_none()) {
+                return Err(CompileError::ValidationError {
+                    msg: "locals of this type can't be debugged".to_string(),
+                });
+            }
+        }
+        Ok(DebugMiddleware {
+            functions: layout.functions.clone(),
+            shift,
+            current: 0,
+            offset: 0,
+            depth: 0,
+        })
+    }
+
+    fn hook(&self, hook: u32) -> Instruction {
+        Instruction::Call(self.shift.imported_functions + hook)
+    }
+
+    /// Check whether to pause at `offset`, or at the entry of the
+    /// function if it's negative.
+    fn push_check(&self, offset: i32, sink: &mut EventSink) {
+        let function = &self.functions[self.current];
+        let instructions = vec![
+            Instruction::I32Const(function.index.index() as i32),
+            Instruction::I32Const(offset),
+            self.hook(CHECK),
+            Instruction::If,
+        ];
+        for instruction in instructions {
+            sink.push(Event::Insert(instruction));
+        }
+        for (index, &ty) in function.locals.iter().enumerate() {
+            sink.push(Event::Insert(Instruction::GetLocal(index as u32)));
+            // The types of the locals were checked in `new`.
+            sink.push(Event::Insert(self.hook(local_hook(ty).unwrap())));
+        }
+        sink.push(Event::Insert(self.hook(PAUSE)));
+        sink.push(Event::Insert(Instruction::End));
+    }
+}
+
+impl FunctionMiddleware for DebugMiddleware {
+    fn api_version(&self) -> u32 {
+        API_VERSION
+    }
+
+    fn begin_function(
+        &mut self,
+        index: LocalFuncIndex,
+        sink: &mut EventSink,
+    ) -> Result<(), String> {
+        self.current = index.index();
+        self.offset = self.functions[self.current].locals_len;
+        self.depth = 0;
+
+        let function = &self.functions[self.current];
+        sink.push(Event::Insert(Instruction::I32Const(
+            function.index.index() as i32
+        )));
+        sink.push(Event::Insert(self.hook(ENTER)));
+        self.push_check(-1, sink);
+
+        // The body is wrapped in a block, so that branches out of it go
+        // past the call that leaves the function.
+        let block = match function.result {
+            Some(ty) => Instruction::Raw(vec![0x02, ty]),
+            None => Instruction::Block,
+        };
+        sink.push(Event::Insert(block));
+        Ok(())
+    }
+
+    fn feed_event<'a>(&mut self, event: Event<'a>, sink: &mut EventSink<'a>) -> Result<(), String> {
+        let len = match event {
+            Event::Wasm(_, bytes) => bytes.len(),
+            Event::Insert(_) => {
+                sink.push(event);
+                return Ok(());
+            }
+        };
+        let offset = self.offset;
+        self.offset += len;
+        self.push_check(offset as i32, sink);
+
+        match event {
+            Event::Wasm(Operator::Block { .. }, _)
+            | Event::Wasm(Operator::Loop { .. }, _)
+            | Event::Wasm(Operator::If { .. }, _) => {
+                self.depth += 1;
+                sink.push(event);
+            }
+            Event::Wasm(Operator::End, _) if self.depth == 0 => {
+                sink.push(Event::Insert(Instruction::End));
+                sink.push(Event::Insert(self.hook(LEAVE)));
+                sink.push(event);
+            }
+            Event::Wasm(Operator::End, _) => {
+                self.depth -= 1;
+                sink.push(event);
+            }
+            Event::Wasm(Operator::Return, _) => {
+                sink.push(Event::Insert(self.hook(LEAVE)));
+                sink.push(event);
+            }
+            Event::Wasm(Operator::Call { function_index }, _) => {
+                let index = self.shift.function(function_index);
+                sink.push(Event::Insert(Instruction::Call(index)));
+            }
+            event => sink.push(event),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod debugger_tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const WASM: [u8; 36] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // (type (func (param i32)))
+        0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00,
+        // (func (type 0))
+        0x03, 0x02, 0x01, 0x00,
+        // (export "f" (func 0))
+        0x07, 0x05, 0x01, 0x01, b'f', 0x00, 0x00,
+        0x0a, 0x08, 0x01, 0x06, 0x00,
+        // get_local 0, call 0, end
+        0x20, 0x00, 0x10, 0x00, 0x0b,
+    ];
+
+    #[test]
+    fn test_instrument() {
+        let wasm = instrument(&WASM).unwrap();
+        assert!(crate::validate(&wasm));
+
+        let sections = middleware::sections(&wasm).unwrap();
+        let ids: Vec<_> = sections.iter().map(|section| section.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 7, 10]);
+
+        // The function is now the first one after the hooks.
+        let export = &sections[3];
+        assert_eq!(
+            &wasm[export.payload.clone()],
+            &[0x01, 0x01, b'f', 0x00, NUMERIC_HOOKS as u8]
+        );
+    }
+}
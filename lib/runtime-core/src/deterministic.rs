@@ -0,0 +1,97 @@
+//! Deterministic execution of WebAssembly code.
+//!
+//! WebAssembly leaves a few things up to the platform, so the same
+//! module can compute different results on different hosts. Once
+//! [`set_deterministic`] has been called, the modules that are compiled
+//! from then on run the same everywhere:
+//!
+//! - Backends replace the NaNs that float operations produce, whose
+//!   bit patterns vary between platforms, with the canonical NaN.
+//! - Modules with shared memories fail to compile, since the results
+//!   of the atomic operations on them depend on how threads are
+//!   scheduled.
+//!
+//! Host functions that would otherwise read the clock or other state
+//! of the host can check [`is_deterministic`] and return fixed values
+//! instead, as the Emscripten ones do.
+//!
+//! [`set_deterministic`]: fn.set_deterministic.html
+//! [`is_deterministic`]: fn.is_deterministic.html
+
+use crate::error::{CompileError, CompileResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use wasmparser::{BinaryReaderError, ImportSectionEntryType, ModuleReader, SectionCode};
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// Make the modules that are compiled from now on run
+/// deterministically, or stop doing so.
+///
+/// This affects which code backends generate, so modules that have
+/// already been compiled, including those in a cache, keep running
+/// the way that they were compiled to.
+pub fn set_deterministic(deterministic: bool) {
+    DETERMINISTIC.store(deterministic, Ordering::SeqCst);
+}
+
+/// Whether modules are compiled to run deterministically.
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::SeqCst)
+}
+
+/// Check that the module in `wasm` can run deterministically.
+pub fn check(wasm: &[u8]) -> CompileResult<()> {
+    match find_shared_memory(wasm) {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(CompileError::ValidationError {
+            msg: "shared memories are not supported in deterministic mode".to_string(),
+        }),
+        Err(e) => Err(CompileError::ValidationError {
+            msg: e.message.to_string(),
+        }),
+    }
+}
+
+fn find_shared_memory(wasm: &[u8]) -> Result<bool, BinaryReaderError> {
+    let mut reader = ModuleReader::new(wasm)?;
+    while !reader.eof() {
+        let section = reader.read()?;
+        match section.code {
+            SectionCode::Import => {
+                for import in section.get_import_section_reader()? {
+                    if let ImportSectionEntryType::Memory(ty) = import?.ty {
+                        if ty.shared {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+            SectionCode::Memory => {
+                for ty in section.get_memory_section_reader()? {
+                    if ty?.shared {
+                        return Ok(true);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod deterministic_tests {
+    use super::*;
+
+    #[test]
+    fn test_check_rejects_shared_memories() {
+        let header = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        // (memory 1 2)
+        let memory = [0x05, 0x04, 0x01, 0x01, 0x01, 0x02];
+        // (memory 1 2 shared)
+        let shared_memory = [0x05, 0x04, 0x01, 0x03, 0x01, 0x02];
+
+        assert!(check(&[&header[..], &memory[..]].concat()).is_ok());
+        assert!(check(&[&header[..], &shared_memory[..]].concat()).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! Preemption of WebAssembly code by epochs.
+//!
+//! There is a single epoch counter for the process, which an embedder
+//! increments, typically from a timer thread, with [`increment`]. Every
+//! instance has a deadline, an epoch at which the code running in it
+//! traps, that is set with [`Instance::set_epoch_deadline`]. Compiled
+//! code compares the two at the start of every function and loop
+//! iteration, so bounding the time that a call takes costs a load and
+//! a compare per iteration, rather than the metering of every
+//! instruction.
+//!
+//! The trap is reported as [`RuntimeError::DeadlineExceeded`].
+//!
+//! [`increment`]: fn.increment.html
+//! [`Instance::set_epoch_deadline`]: ../struct.Instance.html#method.set_epoch_deadline
+//! [`RuntimeError::DeadlineExceeded`]: ../error/enum.RuntimeError.html#variant.DeadlineExceeded
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub(crate) static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Advance the epoch by one, making the code running in instances
+/// whose deadline has now been reached trap.
+pub fn increment() {
+    EPOCH.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The current epoch.
+pub fn current() -> u64 {
+    EPOCH.load(Ordering::SeqCst)
+}
+
+/// The epoch `ticks` epochs from now, or the last one if that's
+/// too far away.
+pub(crate) fn deadline_after(ticks: u64) -> u64 {
+    current().saturating_add(ticks)
+}
+
+#[cfg(test)]
+mod epoch_tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_after() {
+        let before = current();
+        increment();
+        assert!(current() > before);
+        assert!(deadline_after(1) > current());
+        assert_eq!(deadline_after(u64::max_value()), u64::max_value());
+    }
+}
@@ -1,4 +1,10 @@
-use crate::types::{FuncSig, GlobalDescriptor, MemoryDescriptor, TableDescriptor, Type, Value};
+use crate::backend::Backend;
+use crate::replace::Error as ReplaceError;
+use crate::snapshot::Error as SnapshotError;
+use crate::structures::TypedIndex;
+use crate::types::{
+    FuncIndex, FuncSig, GlobalDescriptor, MemoryDescriptor, TableDescriptor, Type, Value,
+};
 use core::borrow::Borrow;
 use std::any::Any;
 
@@ -9,6 +15,75 @@ pub type RuntimeResult<T> = std::result::Result<T, RuntimeError>;
 pub type CallResult<T> = std::result::Result<T, CallError>;
 pub type ResolveResult<T> = std::result::Result<T, ResolveError>;
 
+/// A machine-readable code for every kind of error, so that embedders
+/// can tell failures apart without matching on messages.
+///
+/// The numeric values are stable: new codes are only ever added, and
+/// existing ones are never renumbered. The hundreds tell the stage that
+/// the error comes from.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Compilation.
+    Validation = 100,
+    CompilerInternal = 101,
+    UnavailableBackend = 102,
+
+    // Linking.
+    ImportNotFound = 200,
+    IncorrectImportType = 201,
+    IncorrectImportSignature = 202,
+    IncorrectMemoryDescriptor = 203,
+    IncorrectTableDescriptor = 204,
+    IncorrectGlobalDescriptor = 205,
+    UnknownModule = 206,
+    ImportCycle = 207,
+    NonNativeImport = 208,
+
+    // Resolving exports.
+    ExportNotFound = 300,
+    ExportWrongType = 301,
+    SignatureMismatch = 302,
+
+    // Running WebAssembly code.
+    TrapUnreachable = 400,
+    TrapMemoryOutOfBounds = 401,
+    TrapTableOutOfBounds = 402,
+    TrapIndirectCallToNull = 403,
+    TrapBadSignature = 404,
+    TrapIntegerOverflow = 405,
+    TrapIntegerDivisionByZero = 406,
+    TrapBadConversionToInteger = 407,
+    TrapIllegalArithmetic = 408,
+    TrapStackOverflow = 409,
+    TrapUnknown = 410,
+    Exception = 420,
+    Panic = 421,
+    Interrupted = 422,
+    DeadlineExceeded = 423,
+    OutOfGas = 424,
+
+    // Creating memories, tables and instances.
+    UnableToCreateMemory = 500,
+    UnableToCreateTable = 501,
+    InvalidDescriptor = 502,
+    InstanceLimitReached = 503,
+    ResourceLimitExceeded = 504,
+    InvalidBuffer = 505,
+
+    // Snapshots.
+    SnapshotIncompatibleModule = 600,
+    SnapshotUnknownFunction = 601,
+    SnapshotCouldNotGrow = 602,
+    SnapshotDeserialize = 603,
+    SnapshotSerialize = 604,
+
+    // Replacing functions.
+    ReplaceNotLocalFunction = 700,
+    ReplaceIncompatibleModule = 701,
+    ReplaceUnsupported = 702,
+}
+
 /// This is returned when the chosen compiler is unable to
 /// successfully compile the provided webassembly module into
 /// a `Module`.
@@ -16,8 +91,16 @@ pub type ResolveResult<T> = std::result::Result<T, ResolveError>;
 /// Comparing two `CompileError`s always evaluates to false.
 #[derive(Debug, Clone)]
 pub enum CompileError {
-    ValidationError { msg: String },
-    InternalError { msg: String },
+    ValidationError {
+        msg: String,
+    },
+    InternalError {
+        msg: String,
+    },
+    /// No compiler is registered for the backend.
+    UnavailableBackend {
+        backend: Backend,
+    },
 }
 
 impl PartialEq for CompileError {
@@ -33,6 +116,19 @@ impl std::fmt::Display for CompileError {
                 write!(f, "Internal compiler error: \"{}\"", msg)
             }
             CompileError::ValidationError { msg } => write!(f, "Validation error \"{}\"", msg),
+            CompileError::UnavailableBackend { backend } => {
+                write!(f, "The {} backend isn't available", backend.name())
+            }
+        }
+    }
+}
+
+impl CompileError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CompileError::ValidationError { .. } => ErrorCode::Validation,
+            CompileError::InternalError { .. } => ErrorCode::CompilerInternal,
+            CompileError::UnavailableBackend { .. } => ErrorCode::UnavailableBackend,
         }
     }
 }
@@ -79,6 +175,22 @@ pub enum LinkError {
         expected: GlobalDescriptor,
         found: GlobalDescriptor,
     },
+    /// A linker was asked for a module that it doesn't have.
+    UnknownModule {
+        name: String,
+    },
+    /// Modules that a linker has import from each other in a cycle,
+    /// so none of them can be instantiated first.
+    ImportCycle {
+        modules: Vec<String>,
+    },
+    /// A module with native code imports a function, or a table of
+    /// functions, of a backend without native code, which its code
+    /// can't call directly.
+    NonNativeImport {
+        namespace: String,
+        name: String,
+    },
 }
 
 impl PartialEq for LinkError {
@@ -87,25 +199,81 @@ impl PartialEq for LinkError {
     }
 }
 
+impl LinkError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            LinkError::ImportNotFound { .. } => ErrorCode::ImportNotFound,
+            LinkError::IncorrectImportType { .. } => ErrorCode::IncorrectImportType,
+            LinkError::IncorrectImportSignature { .. } => ErrorCode::IncorrectImportSignature,
+            LinkError::IncorrectMemoryDescriptor { .. } => ErrorCode::IncorrectMemoryDescriptor,
+            LinkError::IncorrectTableDescriptor { .. } => ErrorCode::IncorrectTableDescriptor,
+            LinkError::IncorrectGlobalDescriptor { .. } => ErrorCode::IncorrectGlobalDescriptor,
+            LinkError::UnknownModule { .. } => ErrorCode::UnknownModule,
+            LinkError::ImportCycle { .. } => ErrorCode::ImportCycle,
+            LinkError::NonNativeImport { .. } => ErrorCode::NonNativeImport,
+        }
+    }
+}
+
 impl std::fmt::Display for LinkError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            LinkError::ImportNotFound {namespace, name} => write!(f, "Import not found, namespace: {}, name: {}", namespace, name),
-            LinkError::IncorrectGlobalDescriptor {namespace, name,expected,found} => {
+            LinkError::ImportNotFound { namespace, name } => write!(
+                f,
+                "Import not found, namespace: {}, name: {}",
+                namespace, name
+            ),
+            LinkError::IncorrectGlobalDescriptor {
+                namespace,
+                name,
+                expected,
+                found,
+            } => {
                 write!(f, "Incorrect global descriptor, namespace: {}, name: {}, expected global descriptor: {:?}, found global descriptor: {:?}", namespace, name, expected, found)
-            },
-            LinkError::IncorrectImportSignature{namespace, name,expected,found} => {
+            }
+            LinkError::IncorrectImportSignature {
+                namespace,
+                name,
+                expected,
+                found,
+            } => {
                 write!(f, "Incorrect import signature, namespace: {}, name: {}, expected signature: {}, found signature: {}", namespace, name, expected, found)
             }
-            LinkError::IncorrectImportType{namespace, name,expected,found} => {
+            LinkError::IncorrectImportType {
+                namespace,
+                name,
+                expected,
+                found,
+            } => {
                 write!(f, "Incorrect import type, namespace: {}, name: {}, expected type: {}, found type: {}", namespace, name, expected, found)
             }
-            LinkError::IncorrectMemoryDescriptor{namespace, name,expected,found} => {
+            LinkError::IncorrectMemoryDescriptor {
+                namespace,
+                name,
+                expected,
+                found,
+            } => {
                 write!(f, "Incorrect memory descriptor, namespace: {}, name: {}, expected memory descriptor: {:?}, found memory descriptor: {:?}", namespace, name, expected, found)
-            },
-            LinkError::IncorrectTableDescriptor{namespace, name,expected,found} => {
+            }
+            LinkError::IncorrectTableDescriptor {
+                namespace,
+                name,
+                expected,
+                found,
+            } => {
                 write!(f, "Incorrect table descriptor, namespace: {}, name: {}, expected table descriptor: {:?}, found table descriptor: {:?}", namespace, name, expected, found)
-            },
+            }
+            LinkError::UnknownModule { name } => write!(f, "Unknown module: {}", name),
+            LinkError::ImportCycle { modules } => write!(
+                f,
+                "Modules import each other in a cycle: {}",
+                modules.join(" -> ")
+            ),
+            LinkError::NonNativeImport { namespace, name } => write!(
+                f,
+                "Import without native code, namespace: {}, name: {}",
+                namespace, name
+            ),
         }
     }
 }
@@ -120,9 +288,165 @@ impl std::error::Error for LinkError {}
 /// Comparing two `RuntimeError`s always evaluates to false.
 #[derive(Debug)]
 pub enum RuntimeError {
-    Trap { msg: Box<str> },
-    Exception { data: Box<[Value]> },
-    Panic { data: Box<dyn Any> },
+    /// WebAssembly code trapped. The backtrace holds the WebAssembly
+    /// frames that were on the stack, innermost first. It's always
+    /// empty for backends without [`Capabilities::backtraces`].
+    ///
+    /// [`Capabilities::backtraces`]: ../backend/struct.Capabilities.html#structfield.backtraces
+    Trap {
+        code: TrapCode,
+        msg: Box<str>,
+        backtrace: Vec<WasmFrame>,
+    },
+    Exception {
+        data: Box<[Value]>,
+    },
+    Panic {
+        data: Box<dyn Any>,
+    },
+    /// The instance was interrupted through an [`InterruptHandle`].
+    ///
+    /// [`InterruptHandle`]: ../instance/struct.InterruptHandle.html
+    Interrupted,
+    /// The epoch deadline of the instance was reached.
+    ///
+    /// See the [`epoch`] module.
+    ///
+    /// [`epoch`]: ../epoch/index.html
+    DeadlineExceeded,
+    /// The instance ran out of points to run metered code with.
+    ///
+    /// See the [`metering`] module.
+    ///
+    /// [`metering`]: ../metering/index.html
+    OutOfGas,
+}
+
+impl RuntimeError {
+    /// Create a trap error with the default message for `code`.
+    pub fn trap(code: TrapCode) -> Self {
+        RuntimeError::Trap {
+            code,
+            msg: code.to_string().into_boxed_str(),
+            backtrace: Vec::new(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RuntimeError::Trap { code, .. } => match code {
+                TrapCode::Unreachable => ErrorCode::TrapUnreachable,
+                TrapCode::MemoryOutOfBounds => ErrorCode::TrapMemoryOutOfBounds,
+                TrapCode::TableOutOfBounds => ErrorCode::TrapTableOutOfBounds,
+                TrapCode::IndirectCallToNull => ErrorCode::TrapIndirectCallToNull,
+                TrapCode::BadSignature => ErrorCode::TrapBadSignature,
+                TrapCode::IntegerOverflow => ErrorCode::TrapIntegerOverflow,
+                TrapCode::IntegerDivisionByZero => ErrorCode::TrapIntegerDivisionByZero,
+                TrapCode::BadConversionToInteger => ErrorCode::TrapBadConversionToInteger,
+                TrapCode::IllegalArithmetic => ErrorCode::TrapIllegalArithmetic,
+                TrapCode::StackOverflow => ErrorCode::TrapStackOverflow,
+                TrapCode::Interrupted => ErrorCode::Interrupted,
+                TrapCode::DeadlineExceeded => ErrorCode::DeadlineExceeded,
+                TrapCode::Unknown => ErrorCode::TrapUnknown,
+            },
+            RuntimeError::Exception { .. } => ErrorCode::Exception,
+            RuntimeError::Panic { .. } => ErrorCode::Panic,
+            RuntimeError::Interrupted => ErrorCode::Interrupted,
+            RuntimeError::DeadlineExceeded => ErrorCode::DeadlineExceeded,
+            RuntimeError::OutOfGas => ErrorCode::OutOfGas,
+        }
+    }
+
+    /// Attach a backtrace to this error, if it's a trap.
+    pub fn with_backtrace(mut self, frames: Vec<WasmFrame>) -> Self {
+        if let RuntimeError::Trap {
+            ref mut backtrace, ..
+        } = self
+        {
+            *backtrace = frames;
+        }
+        self
+    }
+}
+
+/// A WebAssembly function that was on the stack when a trap occured.
+#[derive(Debug, Clone)]
+pub struct WasmFrame {
+    pub func_index: FuncIndex,
+    /// The name of the function from the `name` section.
+    pub func_name: Option<String>,
+    /// The offset of the instruction being executed, in bytes from
+    /// the start of the module, if the backend knows it.
+    pub offset: Option<u32>,
+}
+
+impl std::fmt::Display for WasmFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.func_name {
+            Some(ref name) => write!(f, "{}", name)?,
+            None => write!(f, "<function {}>", self.func_index.index())?,
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " @ {:#x}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// The reason that WebAssembly code trapped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrapCode {
+    /// An `unreachable` instruction was executed.
+    Unreachable,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    IndirectCallToNull,
+    /// The signature of the function called by `call_indirect`
+    /// doesn't match the one that was expected.
+    BadSignature,
+    IntegerOverflow,
+    IntegerDivisionByZero,
+    /// A float that is NaN or out of range was truncated to an integer.
+    BadConversionToInteger,
+    /// An integer division trapped, but the backend
+    /// can't tell whether it was by zero or overflowed.
+    IllegalArithmetic,
+    StackOverflow,
+    /// The code saw that the instance was interrupted through an
+    /// [`InterruptHandle`], which the call reports as
+    /// [`RuntimeError::Interrupted`].
+    ///
+    /// [`InterruptHandle`]: ../instance/struct.InterruptHandle.html
+    /// [`RuntimeError::Interrupted`]: enum.RuntimeError.html#variant.Interrupted
+    Interrupted,
+    /// The code saw that the epoch deadline of the instance had been
+    /// reached, which the call reports as
+    /// [`RuntimeError::DeadlineExceeded`].
+    ///
+    /// [`RuntimeError::DeadlineExceeded`]: enum.RuntimeError.html#variant.DeadlineExceeded
+    DeadlineExceeded,
+    Unknown,
+}
+
+impl std::fmt::Display for TrapCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let msg = match self {
+            TrapCode::Unreachable => "unreachable",
+            TrapCode::MemoryOutOfBounds => "memory out-of-bounds access",
+            TrapCode::TableOutOfBounds => "table out-of-bounds access",
+            TrapCode::IndirectCallToNull => "indirect call to null",
+            TrapCode::BadSignature => "incorrect call_indirect signature",
+            TrapCode::IntegerOverflow => "integer overflow",
+            TrapCode::IntegerDivisionByZero => "integer divide by zero",
+            TrapCode::BadConversionToInteger => "invalid conversion to integer",
+            TrapCode::IllegalArithmetic => "illegal arithmetic operation",
+            TrapCode::StackOverflow => "stack overflow",
+            TrapCode::Interrupted => "interrupted",
+            TrapCode::DeadlineExceeded => "epoch deadline exceeded",
+            TrapCode::Unknown => "unknown trap",
+        };
+        write!(f, "{}", msg)
+    }
 }
 
 impl PartialEq for RuntimeError {
@@ -134,13 +458,26 @@ impl PartialEq for RuntimeError {
 impl std::fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            RuntimeError::Trap { ref msg } => {
-                write!(f, "WebAssembly trap occured during runtime: {}", msg)
+            RuntimeError::Trap {
+                ref msg,
+                ref backtrace,
+                ..
+            } => {
+                write!(f, "WebAssembly trap occured during runtime: {}", msg)?;
+                for (i, frame) in backtrace.iter().enumerate() {
+                    write!(f, "\n  {}: {}", i, frame)?;
+                }
+                Ok(())
             }
             RuntimeError::Exception { ref data } => {
                 write!(f, "Uncaught WebAssembly exception: {:?}", data)
             }
             RuntimeError::Panic { data: _ } => write!(f, "User-defined \"panic\""),
+            RuntimeError::Interrupted => write!(f, "WebAssembly execution was interrupted"),
+            RuntimeError::DeadlineExceeded => {
+                write!(f, "WebAssembly execution reached its epoch deadline")
+            }
+            RuntimeError::OutOfGas => write!(f, "WebAssembly execution ran out of gas"),
         }
     }
 }
@@ -164,6 +501,16 @@ impl PartialEq for ResolveError {
     }
 }
 
+impl ResolveError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ResolveError::Signature { .. } => ErrorCode::SignatureMismatch,
+            ResolveError::ExportNotFound { .. } => ErrorCode::ExportNotFound,
+            ResolveError::ExportWrongType { .. } => ErrorCode::ExportWrongType,
+        }
+    }
+}
+
 impl std::fmt::Display for ResolveError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -208,6 +555,15 @@ impl PartialEq for CallError {
     }
 }
 
+impl CallError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CallError::Resolve(resolve_error) => resolve_error.code(),
+            CallError::Runtime(runtime_error) => runtime_error.code(),
+        }
+    }
+}
+
 impl std::fmt::Display for CallError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -217,7 +573,14 @@ impl std::fmt::Display for CallError {
     }
 }
 
-impl std::error::Error for CallError {}
+impl std::error::Error for CallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CallError::Resolve(resolve_error) => Some(resolve_error),
+            CallError::Runtime(runtime_error) => Some(runtime_error),
+        }
+    }
+}
 
 /// This error type is produced when creating something,
 /// like a `Memory` or a `Table`.
@@ -226,6 +589,9 @@ pub enum CreationError {
     UnableToCreateMemory,
     UnableToCreateTable,
     InvalidDescriptor(String),
+    InstanceLimitReached,
+    ResourceLimitExceeded,
+    InvalidBuffer(String),
 }
 
 impl PartialEq for CreationError {
@@ -234,16 +600,41 @@ impl PartialEq for CreationError {
     }
 }
 
+impl CreationError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CreationError::UnableToCreateMemory => ErrorCode::UnableToCreateMemory,
+            CreationError::UnableToCreateTable => ErrorCode::UnableToCreateTable,
+            CreationError::InvalidDescriptor(_) => ErrorCode::InvalidDescriptor,
+            CreationError::InstanceLimitReached => ErrorCode::InstanceLimitReached,
+            CreationError::ResourceLimitExceeded => ErrorCode::ResourceLimitExceeded,
+            CreationError::InvalidBuffer(_) => ErrorCode::InvalidBuffer,
+        }
+    }
+}
+
 impl std::fmt::Display for CreationError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             CreationError::UnableToCreateMemory => write!(f, "Unable to Create Memory"),
             CreationError::UnableToCreateTable => write!(f, "Unable to Create Table"),
+            CreationError::InstanceLimitReached => {
+                write!(f, "Unable to Create Instance, the limit has been reached")
+            }
+            CreationError::ResourceLimitExceeded => write!(
+                f,
+                "Unable to Create Instance, the resource limiter refused its memories or tables"
+            ),
             CreationError::InvalidDescriptor(msg) => write!(
                 f,
                 "Unable to create because the supplied descriptor is invalid: \"{}\"",
                 msg
             ),
+            CreationError::InvalidBuffer(msg) => write!(
+                f,
+                "Unable to create because the supplied buffer is invalid: \"{}\"",
+                msg
+            ),
         }
     }
 }
@@ -263,6 +654,8 @@ pub enum Error {
     ResolveError(ResolveError),
     CallError(CallError),
     CreationError(CreationError),
+    SnapshotError(SnapshotError),
+    ReplaceError(ReplaceError),
 }
 
 impl PartialEq for Error {
@@ -271,6 +664,25 @@ impl PartialEq for Error {
     }
 }
 
+impl Error {
+    /// The code of the error, or of the first one for link errors.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::CompileError(err) => err.code(),
+            Error::LinkError(errs) => errs
+                .first()
+                .map(LinkError::code)
+                .unwrap_or(ErrorCode::ImportNotFound),
+            Error::RuntimeError(err) => err.code(),
+            Error::ResolveError(err) => err.code(),
+            Error::CallError(err) => err.code(),
+            Error::CreationError(err) => err.code(),
+            Error::SnapshotError(err) => err.code(),
+            Error::ReplaceError(err) => err.code(),
+        }
+    }
+}
+
 impl From<CompileError> for Error {
     fn from(compile_err: CompileError) -> Self {
         Error::CompileError(compile_err)
@@ -301,6 +713,18 @@ impl From<CreationError> for Error {
     }
 }
 
+impl From<SnapshotError> for Error {
+    fn from(snapshot_err: SnapshotError) -> Self {
+        Error::SnapshotError(snapshot_err)
+    }
+}
+
+impl From<ReplaceError> for Error {
+    fn from(replace_err: ReplaceError) -> Self {
+        Error::ReplaceError(replace_err)
+    }
+}
+
 impl From<Vec<LinkError>> for Error {
     fn from(link_errs: Vec<LinkError>) -> Self {
         Error::LinkError(link_errs)
@@ -338,11 +762,26 @@ impl std::fmt::Display for Error {
             Error::ResolveError(err) => write!(f, "resolve error: {}", err),
             Error::CallError(err) => write!(f, "call error: {}", err),
             Error::CreationError(err) => write!(f, "creation error: {}", err),
+            Error::SnapshotError(err) => write!(f, "snapshot error: {}", err),
+            Error::ReplaceError(err) => write!(f, "replace error: {}", err),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CompileError(err) => Some(err),
+            Error::LinkError(errs) => errs.first().map(|err| err as _),
+            Error::RuntimeError(err) => Some(err),
+            Error::ResolveError(err) => Some(err),
+            Error::CallError(err) => Some(err),
+            Error::CreationError(err) => Some(err),
+            Error::SnapshotError(err) => Some(err),
+            Error::ReplaceError(err) => Some(err),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum GrowError {
@@ -352,6 +791,7 @@ pub enum GrowError {
     ExceededMaxPagesForMemory(usize, usize),
     CouldNotProtectMemory(MemoryProtectionError),
     CouldNotCreateMemory(MemoryCreationError),
+    SharedMemoryGrowCallback,
 }
 
 impl std::fmt::Display for GrowError {
@@ -363,11 +803,23 @@ impl std::fmt::Display for GrowError {
             GrowError::ExceededMaxPagesForMemory(left, added) => write!(f, "Failed to add pages because would exceed maximum number of pages for the memory. Left: {}, Added: {}", left, added),
             GrowError::CouldNotCreateMemory(e) => write!(f, "Grow Error: {}", e),
             GrowError::CouldNotProtectMemory(e) => write!(f, "Grow Error: {}", e),
+            GrowError::SharedMemoryGrowCallback => {
+                write!(f, "Shared memories don't support grow callbacks")
+            }
         }
     }
 }
 
-impl std::error::Error for GrowError {}
+impl std::error::Error for GrowError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrowError::ExceededMaxPages(e) => Some(e),
+            GrowError::CouldNotProtectMemory(e) => Some(e),
+            GrowError::CouldNotCreateMemory(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum PageError {
@@ -408,7 +860,14 @@ impl std::fmt::Display for MemoryCreationError {
         }
     }
 }
-impl std::error::Error for MemoryCreationError {}
+impl std::error::Error for MemoryCreationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MemoryCreationError::CouldNotCreateMemoryFromFile(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl Into<GrowError> for MemoryCreationError {
     fn into(self) -> GrowError {
@@ -445,3 +904,31 @@ impl Into<GrowError> for MemoryProtectionError {
         GrowError::CouldNotProtectMemory(self)
     }
 }
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_codes_and_sources() {
+        let error: Error = CallError::Runtime(RuntimeError::trap(TrapCode::StackOverflow)).into();
+        assert_eq!(error.code(), ErrorCode::TrapStackOverflow);
+        assert_eq!(error.code() as u32, 409);
+
+        // The call error, then the trap.
+        let call_error = error.source().unwrap();
+        assert!(call_error.to_string().starts_with("Call error"));
+        let trap = call_error.source().unwrap();
+        assert!(trap.to_string().ends_with("stack overflow"));
+        assert!(trap.source().is_none());
+
+        let error: Error = vec![LinkError::ImportNotFound {
+            namespace: "env".to_string(),
+            name: "print".to_string(),
+        }]
+        .into();
+        assert_eq!(error.code(), ErrorCode::ImportNotFound);
+        assert!(error.to_string().contains("env"));
+    }
+}
@@ -3,12 +3,20 @@ use crate::{
     module::ModuleInner, table::Table, types::FuncSig, vm,
 };
 use hashbrown::hash_map;
-use std::sync::Arc;
+use std::{rc::Rc, sync::Arc};
 
-#[derive(Debug, Copy, Clone)]
+pub use crate::typed_func::FuncEnv;
+
+#[derive(Debug, Clone)]
 pub enum Context {
     External(*mut vm::Ctx),
     Internal,
+    /// Like `Internal`, but the function also receives the captured
+    /// environment of a host closure. The function is called with a
+    /// [`vm::FuncCtx`] in place of the `vm::Ctx`.
+    ///
+    /// [`vm::FuncCtx`]: ../vm/struct.FuncCtx.html
+    InternalWithEnv(Rc<FuncEnv>),
 }
 
 #[derive(Debug, Clone)]
@@ -34,7 +42,8 @@ impl FuncPointer {
         FuncPointer(f)
     }
 
-    pub(crate) fn inner(&self) -> *const vm::Func {
+    /// The function that this points to.
+    pub fn inner(&self) -> *const vm::Func {
         self.0
     }
 }
@@ -1,10 +1,11 @@
 use crate::{
     export::Export,
     import::IsExport,
-    types::{GlobalDescriptor, Type, Value},
+    refs::{ExternRef, FuncRef},
+    types::{GlobalDescriptor, Type, Value, WasmExternType},
     vm,
 };
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{cell::RefCell, fmt, mem, ptr, rc::Rc};
 
 pub struct Global {
     desc: GlobalDescriptor,
@@ -50,6 +51,8 @@ impl Global {
                 Value::I64(x) => x as u64,
                 Value::F32(x) => x.to_bits() as u64,
                 Value::F64(x) => x.to_bits(),
+                Value::ExternRef(r) => r.to_bits(),
+                Value::FuncRef(r) => r.to_bits(),
             },
         };
 
@@ -79,6 +82,8 @@ impl Global {
                         Value::I64(x) => x as u64,
                         Value::F32(x) => x.to_bits() as u64,
                         Value::F64(x) => x.to_bits(),
+                        Value::ExternRef(r) => r.to_bits(),
+                        Value::FuncRef(r) => r.to_bits(),
                     },
                 };
                 *self.storage.borrow_mut() = local_global;
@@ -99,9 +104,70 @@ impl Global {
             Type::I64 => Value::I64(data as i64),
             Type::F32 => Value::F32(f32::from_bits(data as u32)),
             Type::F64 => Value::F64(f64::from_bits(data)),
+            Type::ExternRef => Value::ExternRef(ExternRef::from_bits(data)),
+            Type::FuncRef => Value::FuncRef(FuncRef::from_bits(data)),
         }
     }
 
+    /// Get the value held by this global as a `T`, or `None`
+    /// if `T` isn't the type of this global.
+    ///
+    /// Usage:
+    ///
+    /// ```
+    /// # use wasmer_runtime_core::global::Global;
+    /// # use wasmer_runtime_core::types::Value;
+    /// let global = Global::new_mutable(Value::I32(42));
+    /// assert_eq!(global.get_typed::<i32>(), Some(42));
+    /// assert_eq!(global.get_typed::<f64>(), None);
+    ///
+    /// global.set_typed(7u32);
+    /// assert_eq!(global.get(), Value::I32(7));
+    /// ```
+    pub fn get_typed<T: WasmExternType>(&self) -> Option<T> {
+        if T::TYPE != self.desc.ty {
+            return None;
+        }
+
+        let data = self.storage.borrow().data;
+        // Every `WasmExternType` is at most 8 bytes, and is stored in
+        // the low bytes of the global, which come first on the
+        // little-endian targets that are supported.
+        debug_assert!(mem::size_of::<T>() <= mem::size_of::<u64>());
+        Some(unsafe { ptr::read(&data as *const u64 as *const T) })
+    }
+
+    /// Set the value held by this global from a `T`.
+    ///
+    /// Like [`set`], this method will panic if the global
+    /// is immutable or `T` is the wrong type.
+    ///
+    /// [`set`]: #method.set
+    pub fn set_typed<T: WasmExternType>(&self, value: T) {
+        if !self.desc.mutable {
+            panic!("Cannot modify global immutable by default")
+        }
+        if T::TYPE != self.desc.ty {
+            panic!("Wrong type for setting this global")
+        }
+
+        debug_assert!(mem::size_of::<T>() <= mem::size_of::<u64>());
+        let mut data = 0u64;
+        unsafe { ptr::write(&mut data as *mut u64 as *mut T, value) };
+        self.storage.borrow_mut().data = data;
+    }
+
+    /// The value held by this global, as it's stored.
+    pub(crate) fn raw_data(&self) -> u64 {
+        self.storage.borrow().data
+    }
+
+    /// Set the value held by this global, regardless of
+    /// whether it's mutable.
+    pub(crate) fn set_raw_data(&self, data: u64) {
+        self.storage.borrow_mut().data = data;
+    }
+
     pub(crate) fn vm_local_global(&mut self) -> *mut vm::LocalGlobal {
         &mut *self.storage.borrow_mut()
     }
@@ -130,3 +196,30 @@ impl fmt::Debug for Global {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod global_tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_access_is_shared_between_clones() {
+        let global = Global::new_mutable(Value::I64(-1));
+        let clone = global.clone();
+
+        assert_eq!(clone.get_typed::<i64>(), Some(-1));
+        assert_eq!(clone.get_typed::<u64>(), Some(u64::max_value()));
+        assert_eq!(clone.get_typed::<i32>(), None);
+
+        global.set_typed(1i64 << 40);
+        assert_eq!(clone.get(), Value::I64(1 << 40));
+
+        clone.set(Value::I64(3));
+        assert_eq!(global.get_typed::<i64>(), Some(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "immutable")]
+    fn test_set_typed_immutable() {
+        Global::new(Value::F32(1.0)).set_typed(2.0f32);
+    }
+}
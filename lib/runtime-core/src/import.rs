@@ -1,4 +1,7 @@
-use crate::export::Export;
+use crate::{
+    export::Export,
+    limits::{LimiterCreator, ResourceLimiter},
+};
 use hashbrown::{hash_map::Entry, HashMap};
 use std::collections::VecDeque;
 use std::{
@@ -45,6 +48,8 @@ impl IsExport for Export {
 /// ```
 pub struct ImportObject {
     map: Rc<RefCell<HashMap<String, Box<dyn LikeNamespace>>>>,
+    fallback: Option<Rc<dyn Fn(&str, &str) -> Option<Export>>>,
+    limiter: Option<Rc<LimiterCreator>>,
 }
 
 impl ImportObject {
@@ -52,6 +57,8 @@ impl ImportObject {
     pub fn new() -> Self {
         Self {
             map: Rc::new(RefCell::new(HashMap::new())),
+            fallback: None,
+            limiter: None,
         }
     }
 
@@ -95,9 +102,176 @@ impl ImportObject {
         }
     }
 
+    /// Look up a single import, asking the fallback resolver if
+    /// none of the registered namespaces provide it.
+    pub fn get_export(&self, namespace: &str, name: &str) -> Option<Export> {
+        self.get_namespace(namespace)
+            .and_then(|like_ns| like_ns.get_export(name))
+            .or_else(|| {
+                self.fallback
+                    .as_ref()
+                    .and_then(|fallback| fallback(namespace, name))
+            })
+    }
+
+    /// Set a resolver that is called, at instantiation time, for each
+    /// import that none of the registered namespaces provide.
+    ///
+    /// This is useful for supplying stubs for whatever a module
+    /// imports that the host doesn't implement.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{export::Export, global::Global, import::ImportObject, types::Value};
+    /// let mut import_object = ImportObject::new();
+    ///
+    /// import_object.set_fallback(|namespace, name| {
+    ///     if namespace == "env" && name.starts_with("unused_") {
+    ///         Some(Export::Global(Global::new(Value::I32(0))))
+    ///     } else {
+    ///         None
+    ///     }
+    /// });
+    ///
+    /// assert!(import_object.get_export("env", "unused_global").is_some());
+    /// assert!(import_object.get_export("env", "other").is_none());
+    /// ```
+    pub fn set_fallback<F>(&mut self, fallback: F)
+    where
+        F: Fn(&str, &str) -> Option<Export> + 'static,
+    {
+        self.fallback = Some(Rc::new(fallback));
+    }
+
+    /// Give each instance created with this `ImportObject` the
+    /// resource limiter that `create` returns, which the instance
+    /// consults whenever its memories and tables grow. See the
+    /// [`limits`] module.
+    ///
+    /// [`limits`]: ../limits/index.html
+    pub fn set_resource_limiter<F>(&mut self, create: F)
+    where
+        F: Fn() -> Box<dyn ResourceLimiter> + 'static,
+    {
+        self.limiter = Some(Rc::new(create));
+    }
+
+    /// An empty `ImportObject` that gets whatever its own namespaces
+    /// don't provide from `base`, and has the resource limiter of
+    /// `base`.
+    pub(crate) fn layered_over(base: &ImportObject) -> Self {
+        let base_ref = base.clone_ref();
+        Self {
+            map: Rc::new(RefCell::new(HashMap::new())),
+            fallback: Some(Rc::new(move |namespace, name| {
+                base_ref.get_export(namespace, name)
+            })),
+            limiter: base.limiter.clone(),
+        }
+    }
+
+    /// A resource limiter for a new instance, if one has been set.
+    pub(crate) fn create_resource_limiter(&self) -> Option<Box<dyn ResourceLimiter>> {
+        self.limiter.as_ref().map(|create| create())
+    }
+
+    /// The names of all registered namespaces.
+    pub fn namespace_names(&self) -> Vec<String> {
+        self.map.borrow().keys().cloned().collect()
+    }
+
+    /// Iterate over every `(namespace, name, export)` that
+    /// the registered namespaces provide.
+    pub fn iter(&self) -> ImportObjectIterator {
+        ImportObjectIterator {
+            elements: self.get_objects(),
+        }
+    }
+
+    /// Merge `other` into this `ImportObject`.
+    ///
+    /// Namespaces that only `other` has are moved over as they are.
+    /// Namespaces that both have are combined, with the exports of
+    /// `other` taking precedence. If this `ImportObject` has no
+    /// fallback resolver or resource limiter, it takes the one from
+    /// `other`.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{global::Global, imports, types::Value};
+    /// let mut host = imports! {
+    ///     "env" => {
+    ///         "a" => Global::new(Value::I32(0)),
+    ///     },
+    /// };
+    ///
+    /// host.merge(imports! {
+    ///     "env" => {
+    ///         "b" => Global::new(Value::I32(1)),
+    ///     },
+    ///     "stubs" => {
+    ///         "c" => Global::new(Value::I32(2)),
+    ///     },
+    /// });
+    ///
+    /// assert!(host.get_export("env", "a").is_some());
+    /// assert!(host.get_export("env", "b").is_some());
+    /// assert!(host.get_export("stubs", "c").is_some());
+    /// ```
+    pub fn merge(&mut self, other: ImportObject) {
+        if self.fallback.is_none() {
+            self.fallback = other.fallback.clone();
+        }
+        if self.limiter.is_none() {
+            self.limiter = other.limiter.clone();
+        }
+
+        let other_map = match Rc::try_unwrap(other.map) {
+            Ok(map) => map.into_inner(),
+            // Other references to `other` are still around,
+            // so leave its namespaces alone and copy the exports.
+            Err(map) => {
+                self.extend(ImportObject {
+                    map,
+                    fallback: None,
+                    limiter: None,
+                });
+                return;
+            }
+        };
+
+        for (ns_name, other_ns) in other_map {
+            let existing = self.map.borrow_mut().remove(&ns_name);
+            let merged: Box<dyn LikeNamespace> = match existing {
+                None => other_ns,
+                Some(mut existing) => {
+                    let exports = other_ns.get_exports();
+                    let insertable = exports.iter().all(|(name, export)| {
+                        existing.maybe_insert(name, export.clone()).is_some()
+                    });
+
+                    if insertable {
+                        existing
+                    } else {
+                        // The existing namespace can't be added to,
+                        // e.g. because it's an instance.
+                        let mut namespace = Namespace::new();
+                        for (name, export) in existing.get_exports().into_iter().chain(exports) {
+                            namespace.insert(name, export);
+                        }
+                        Box::new(namespace)
+                    }
+                }
+            };
+            self.map.borrow_mut().insert(ns_name, merged);
+        }
+    }
+
     pub fn clone_ref(&self) -> Self {
         Self {
             map: Rc::clone(&self.map),
+            fallback: self.fallback.clone(),
+            limiter: self.limiter.clone(),
         }
     }
 
@@ -245,4 +419,52 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn merging_shared_import_object_copies_exports() {
+        let mut imports1 = imports! {
+            "dog" => {
+                "happy" => Global::new(Value::I32(0)),
+            },
+        };
+
+        let imports2 = imports! {
+            "dog" => {
+                "small" => Global::new(Value::I32(2)),
+            },
+        };
+        let imports2_ref = imports2.clone_ref();
+
+        imports1.merge(imports2);
+
+        assert!(imports1.get_export("dog", "happy").is_some());
+        assert!(imports1.get_export("dog", "small").is_some());
+        assert!(imports2_ref.get_export("dog", "small").is_some());
+    }
+
+    #[test]
+    fn fallback_is_used_for_missing_imports() {
+        let mut imports = imports! {
+            "dog" => {
+                "happy" => Global::new(Value::I32(0)),
+            },
+        };
+        imports.set_fallback(|namespace, _name| {
+            if namespace == "dog" {
+                Some(Export::Global(Global::new(Value::I32(7))))
+            } else {
+                None
+            }
+        });
+
+        assert!(if let Export::Global(happy_dog_global) =
+            imports.get_export("dog", "happy").unwrap()
+        {
+            happy_dog_global.get() == Value::I32(0)
+        } else {
+            false
+        });
+        assert!(imports.get_export("dog", "sad").is_some());
+        assert!(imports.get_export("cat", "sad").is_none());
+    }
 }
@@ -1,29 +1,63 @@
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use crate::async_call::CallFuture;
 use crate::{
     backend::Token,
     backing::{ImportBacking, LocalBacking},
-    error::{CallError, CallResult, ResolveError, ResolveResult, Result},
+    crash,
+    debugger::{Breakpoint, DebugHandler, PauseHandle},
+    epoch,
+    error::{
+        CallError, CallResult, ResolveError, ResolveResult, Result, RuntimeError, RuntimeResult,
+        TrapCode,
+    },
     export::{Context, Export, ExportIter, FuncPointer},
     global::Global,
     import::{ImportObject, LikeNamespace},
     memory::Memory,
+    metering,
     module::{ExportIndex, Module, ModuleInner},
+    pool::InstanceSlot,
+    refs, replace,
     sig_registry::SigRegistry,
+    snapshot::{self, Snapshot},
+    sys,
     table::Table,
-    typed_func::{Func, Safe, WasmTypeList},
+    termination::{self, Reason},
+    typed_func::{Func, Safe, WasmTypeList, EARLY_TRAPPER},
     types::{FuncIndex, FuncSig, GlobalIndex, LocalOrImport, MemoryIndex, TableIndex, Value},
     vm,
 };
-use std::{mem, sync::Arc};
+use std::{
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 pub(crate) struct InstanceInner {
     #[allow(dead_code)]
     pub(crate) backing: LocalBacking,
-    import_backing: ImportBacking,
+    pub(crate) import_backing: ImportBacking,
     pub(crate) vmctx: *mut vm::Ctx,
+    interrupted: Arc<AtomicBool>,
+    _slot: InstanceSlot,
 }
 
 impl Drop for InstanceInner {
     fn drop(&mut self) {
+        // References to the functions of this instance, and to the
+        // host closures it imported, would otherwise outlive them.
+        let mut ctxs = vec![self.vmctx];
+        ctxs.extend(
+            self.import_backing
+                .func_ctxs
+                .iter()
+                .map(|(func_ctx, _)| &**func_ctx as *const vm::FuncCtx as *mut vm::Ctx),
+        );
+        refs::forget_funcs(&ctxs);
+
         // Drop the vmctx.
         unsafe { Box::from_raw(self.vmctx) };
     }
@@ -38,45 +72,107 @@ impl Drop for InstanceInner {
 /// [`ImportObject`]: struct.ImportObject.html
 pub struct Instance {
     module: Arc<ModuleInner>,
+    /// The memory images of the module, which `module` hands back.
+    images: Arc<Vec<Option<sys::MemoryImage>>>,
     inner: Box<InstanceInner>,
     #[allow(dead_code)]
     import_object: ImportObject,
+    /// The start function hasn't been run yet.
+    start_pending: bool,
+    /// The modules whose code has replaced functions of this one.
+    replacements: Vec<Arc<ModuleInner>>,
+    /// The points that this instance was last given.
+    points_limit: u64,
 }
 
 impl Instance {
-    pub(crate) fn new(module: Arc<ModuleInner>, imports: &ImportObject) -> Result<Instance> {
+    /// Instantiate `module`, mapping the memories that have an image
+    /// in `images` rather than copying their data segments into them.
+    pub(crate) fn new(
+        module: Arc<ModuleInner>,
+        images: Arc<Vec<Option<sys::MemoryImage>>>,
+        imports: &ImportObject,
+    ) -> Result<Instance> {
+        let mut instance = Self::new_deferred(module, images, imports)?;
+        instance.start()?;
+        Ok(instance)
+    }
+
+    /// Instantiate `module` like `new`, but leave its start function
+    /// for [`start`] to run.
+    ///
+    /// [`start`]: #method.start
+    pub(crate) fn new_deferred(
+        module: Arc<ModuleInner>,
+        images: Arc<Vec<Option<sys::MemoryImage>>>,
+        imports: &ImportObject,
+    ) -> Result<Instance> {
+        let mut instance = Self::new_uninitialized(module, imports, true, images)?;
+        instance.start_pending = instance.module.info.start_func.is_some();
+        Ok(instance)
+    }
+
+    /// Instantiate `module` without running its start function, and
+    /// without running its data and element segments unless
+    /// `run_initializers` is set. `images` are only mapped if it is.
+    pub(crate) fn new_uninitialized(
+        module: Arc<ModuleInner>,
+        imports: &ImportObject,
+        run_initializers: bool,
+        images: Arc<Vec<Option<sys::MemoryImage>>>,
+    ) -> Result<Instance> {
+        let slot = InstanceSlot::take()?;
+
+        // Errors from host functions are reported through the trapper
+        // of the thread that runs the instance, which is this one.
+        unsafe {
+            EARLY_TRAPPER
+                .with(|ucell| *ucell.get() = Some(module.protected_caller.get_early_trapper()));
+        }
+
         // We need the backing and import_backing to create a vm::Ctx, but we need
         // a vm::Ctx to create a backing and an import_backing. The solution is to create an
         // uninitialized vm::Ctx and then initialize it in-place.
         let mut vmctx = unsafe { Box::new(mem::uninitialized()) };
 
         let import_backing = ImportBacking::new(&module, &imports, &mut *vmctx)?;
-        let backing = LocalBacking::new(&module, &import_backing, &mut *vmctx);
+        let backing = LocalBacking::new(
+            &module,
+            &import_backing,
+            &mut *vmctx,
+            run_initializers,
+            &images,
+            imports.create_resource_limiter(),
+        )?;
 
         // When Pin is stablized, this will use `Box::pinned` instead of `Box::new`.
         let mut inner = Box::new(InstanceInner {
             backing,
             import_backing,
             vmctx: Box::leak(vmctx),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            _slot: slot,
         });
 
         // Initialize the vm::Ctx in-place after the backing
         // has been boxed.
+        let initial_points = metering::initial_points(module.info.initial_points);
         unsafe {
-            *inner.vmctx = vm::Ctx::new(&mut inner.backing, &mut inner.import_backing, &module)
+            *inner.vmctx = vm::Ctx::new(&mut inner.backing, &mut inner.import_backing, &module);
+            (*inner.vmctx).internal.interrupted = &*inner.interrupted;
+            (*inner.vmctx).internal.remaining_points = initial_points;
         };
+        let points_limit = initial_points as u64;
 
-        let instance = Instance {
+        Ok(Instance {
             module,
+            images,
             inner,
             import_object: imports.clone_ref(),
-        };
-
-        if let Some(start_index) = instance.module.info.start_func {
-            instance.call_with_index(start_index, &[])?;
-        }
-
-        Ok(instance)
+            start_pending: false,
+            replacements: Vec::new(),
+            points_limit,
+        })
     }
 
     /// Through generic magic and the awe-inspiring power of traits, we bring you...
@@ -137,19 +233,23 @@ impl Instance {
             };
 
             let func_ptr = match func_index.local_or_import(&self.module.info) {
-                LocalOrImport::Local(local_func_index) => self
-                    .module
-                    .func_resolver
-                    .get(&self.module, local_func_index)
-                    .unwrap()
-                    .as_ptr(),
+                LocalOrImport::Local(local_func_index) => {
+                    self.inner.backing.local_functions[local_func_index]
+                }
                 LocalOrImport::Import(import_func_index) => {
                     self.inner.import_backing.vm_functions[import_func_index].func
                 }
             };
 
             let typed_func: Func<Args, Rets, Safe> =
-                unsafe { Func::new_from_ptr(func_ptr as _, ctx) };
+                unsafe {
+                    Func::new_from_ptr(
+                        func_ptr as _,
+                        ctx,
+                        self.inner.vmctx,
+                        &*self.module.protected_caller,
+                    )
+                };
 
             Ok(typed_func)
         } else {
@@ -208,6 +308,76 @@ impl Instance {
         }
     }
 
+    /// This returns the exported global with the given name. It
+    /// shares its value with the instance, so setting it is
+    /// visible to webassembly code and vice versa.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Instance;
+    /// # use wasmer_runtime_core::error::ResolveResult;
+    /// # fn move_stack(instance: &mut Instance) -> ResolveResult<()> {
+    /// let stack_pointer = instance.global("stack_pointer")?;
+    /// let top = stack_pointer.get_typed::<u32>().unwrap();
+    /// stack_pointer.set_typed(top - 16);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn global(&self, name: &str) -> ResolveResult<Global> {
+        let export_index =
+            self.module
+                .info
+                .exports
+                .get(name)
+                .ok_or_else(|| ResolveError::ExportNotFound {
+                    name: name.to_string(),
+                })?;
+
+        if let ExportIndex::Global(global_index) = export_index {
+            Ok(self
+                .inner
+                .get_global_from_index(&self.module, *global_index))
+        } else {
+            Err(ResolveError::ExportWrongType {
+                name: name.to_string(),
+            })
+        }
+    }
+
+    /// This returns the exported table with the given name. Like
+    /// [`global`], it's shared with the instance rather than copied.
+    ///
+    /// [`global`]: #method.global
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Instance;
+    /// # use wasmer_runtime_core::error::ResolveResult;
+    /// # fn reserve_slots(instance: &mut Instance) -> ResolveResult<()> {
+    /// let table = instance.table("__indirect_function_table")?;
+    /// let first_free_slot = table.grow(4).expect("the table is full");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn table(&self, name: &str) -> ResolveResult<Table> {
+        let export_index =
+            self.module
+                .info
+                .exports
+                .get(name)
+                .ok_or_else(|| ResolveError::ExportNotFound {
+                    name: name.to_string(),
+                })?;
+
+        if let ExportIndex::Table(table_index) = export_index {
+            Ok(self.inner.get_table_from_index(&self.module, *table_index))
+        } else {
+            Err(ResolveError::ExportWrongType {
+                name: name.to_string(),
+            })
+        }
+    }
+
     /// Call an exported webassembly function given the export name.
     /// Pass arguments by wrapping each one in the [`Value`] enum.
     /// The returned values are also each wrapped in a [`Value`].
@@ -215,8 +385,8 @@ impl Instance {
     /// [`Value`]: enum.Value.html
     ///
     /// # Note:
-    /// This returns `CallResult<Vec<Value>>` in order to support
-    /// the future multi-value returns webassembly feature.
+    /// This returns `CallResult<Vec<Value>>` because a function
+    /// can return more than one value with the multi-value proposal.
     ///
     /// # Usage:
     /// ```
@@ -252,6 +422,73 @@ impl Instance {
         self.call_with_index(func_index, args)
     }
 
+    /// Call an exported webassembly function like [`call`], as a
+    /// future that host functions returning futures can suspend
+    /// without blocking the thread. See the [`async_call`] module.
+    ///
+    /// Nothing runs until the future is polled, and the instance stays
+    /// borrowed until the call has returned, so that calls into it
+    /// can't interleave. Only available on x86_64 Linux, the only
+    /// platform that calls can be suspended on.
+    ///
+    /// [`call`]: #method.call
+    /// [`async_call`]: async_call/index.html
+    ///
+    /// # Usage:
+    /// ```
+    /// # use futures::Future;
+    /// # use wasmer_runtime_core::types::Value;
+    /// # use wasmer_runtime_core::error::CallResult;
+    /// # use wasmer_runtime_core::Instance;
+    /// # fn call_foo(instance: &mut Instance) -> CallResult<()> {
+    /// let results = instance.call_async("foo", &[Value::I32(42)]).wait()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    pub fn call_async<'a>(&'a mut self, name: &'a str, args: &'a [Value]) -> CallFuture<'a> {
+        CallFuture::new(move || self.call(name, args))
+    }
+
+    /// Run the start function of the module, if this instance was
+    /// created with [`Module::instantiate_deferred`] and it hasn't
+    /// been run yet.
+    ///
+    /// This is a call like any other, so the metering points and epoch
+    /// deadline set on the instance beforehand apply to it. The start
+    /// function is only ever run once, even if it traps.
+    ///
+    /// [`Module::instantiate_deferred`]: struct.Module.html#method.instantiate_deferred
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::error::Result;
+    /// # use wasmer_runtime_core::{imports, Module};
+    /// # fn start_with_limit(module: &Module) -> Result<()> {
+    /// let mut instance = module.instantiate_deferred(&imports! {})?;
+    /// instance.set_remaining_points(1_000_000);
+    /// instance.start()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start(&mut self) -> CallResult<()> {
+        if !mem::replace(&mut self.start_pending, false) {
+            return Ok(());
+        }
+        let start_index = self
+            .module
+            .info
+            .start_func
+            .expect("broken invariant, pending start without a start function");
+        self.call_with_index(start_index, &[])?;
+        Ok(())
+    }
+
+    /// Whether the start function, if the module has one, has been run.
+    pub fn is_started(&self) -> bool {
+        !self.start_pending
+    }
+
     /// Returns an immutable reference to the
     /// [`Ctx`] used by this Instance.
     ///
@@ -268,15 +505,187 @@ impl Instance {
         unsafe { &mut *self.inner.vmctx }
     }
 
+    /// Returns a handle that can stop the WebAssembly code running
+    /// in this instance from another thread.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{Instance, error::CallResult};
+    /// # use std::{thread, time::Duration};
+    /// # fn call_with_timeout(instance: &Instance) -> CallResult<()> {
+    /// let handle = instance.interrupt_handle();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(1));
+    ///     handle.interrupt();
+    /// });
+    /// instance.call("run", &[])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupted: Arc::clone(&self.inner.interrupted),
+        }
+    }
+
+    /// Make the WebAssembly code running in this instance trap once
+    /// the epoch has been incremented `ticks` times from now.
+    ///
+    /// The deadline stays in place until it's set again, so calls
+    /// made after it has been reached trap as soon as they start.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{Instance, epoch, error::CallResult};
+    /// # use std::{thread, time::Duration};
+    /// # fn call_with_timeout(instance: &mut Instance) -> CallResult<()> {
+    /// thread::spawn(|| loop {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     epoch::increment();
+    /// });
+    ///
+    /// // Stop `run` after about 100 milliseconds.
+    /// instance.set_epoch_deadline(10);
+    /// instance.call("run", &[])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_epoch_deadline(&mut self, ticks: u64) {
+        self.context_mut().internal.epoch_deadline = epoch::deadline_after(ticks);
+    }
+
+    /// The points that this instance has left to run metered code
+    /// with. See the [`metering`] module.
+    ///
+    /// [`metering`]: metering/index.html
+    pub fn get_remaining_points(&self) -> u64 {
+        self.context().internal.remaining_points.max(0) as u64
+    }
+
+    /// Set the points that this instance has left to run metered
+    /// code with, up to `i64::MAX`.
+    pub fn set_remaining_points(&mut self, points: u64) {
+        let points = metering::clamp_points(points);
+        self.context_mut().internal.remaining_points = points;
+        self.points_limit = points as u64;
+    }
+
+    /// The points that this instance has used up since it was
+    /// created, or since its points were last set.
+    pub fn get_points_used(&self) -> u64 {
+        self.points_limit
+            .saturating_sub(self.get_remaining_points())
+    }
+
+    /// Set the handler that is called when the code running in this
+    /// instance pauses, or stop pausing it with `None`.
+    ///
+    /// Only modules compiled for debugging can pause. See the
+    /// [`debugger`] module.
+    ///
+    /// [`debugger`]: debugger/index.html
+    pub fn set_debug_handler(&mut self, handler: Option<Box<dyn DebugHandler>>) {
+        self.inner.backing.debug.handler = handler;
+    }
+
+    /// Pause at `breakpoint` whenever it's reached.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.inner.backing.debug.breakpoints.insert(breakpoint);
+    }
+
+    /// Stop pausing at `breakpoint`, returning whether it was set.
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.inner.backing.debug.breakpoints.remove(&breakpoint)
+    }
+
+    /// Get a handle that can be used to pause this instance from
+    /// another thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.inner.backing.debug.pause_handle()
+    }
+
     /// Returns an iterator over all of the items
     /// exported from this instance.
     pub fn exports(&mut self) -> ExportIter {
         ExportIter::new(&self.module, &mut self.inner)
     }
 
+    /// Capture the contents of the memories, globals and tables
+    /// that this instance defines.
+    ///
+    /// This fails if a table holds a function that belongs to
+    /// neither this instance nor its imports.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{Instance, Module, imports};
+    /// # fn fork(module: &Module, initialized: &Instance) -> wasmer_runtime_core::error::Result<()> {
+    /// let snapshot = initialized.snapshot().expect("couldn't take a snapshot");
+    ///
+    /// let mut instance = module.instantiate(&imports! {})?;
+    /// instance.restore(&snapshot).expect("couldn't restore the snapshot");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&self) -> std::result::Result<Snapshot, snapshot::Error> {
+        Snapshot::take(&self.module, &self.inner)
+    }
+
+    /// Restore the state captured by [`snapshot`], which must have
+    /// been taken from an instance of the same module.
+    ///
+    /// If the snapshot doesn't fit this instance, an error is
+    /// returned before anything is changed.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    pub fn restore(&mut self, snapshot: &Snapshot) -> std::result::Result<(), snapshot::Error> {
+        self.restore_with_images(snapshot, &[])
+    }
+
+    pub(crate) fn restore_with_images(
+        &mut self,
+        snapshot: &Snapshot,
+        images: &[Option<sys::MemoryImage>],
+    ) -> std::result::Result<(), snapshot::Error> {
+        snapshot.restore(&self.module, &self.inner, images)
+    }
+
+    /// Replace the code of the exported function `name` with its code
+    /// in `module`, which must be a compile of the same module that
+    /// differs only in the bodies of its functions. Calls that start
+    /// from now on run the new code. See [`replace`] for what is and
+    /// isn't replaced.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{Instance, Module};
+    /// # fn reload(instance: &mut Instance, edited: &Module) {
+    /// instance
+    ///     .replace_func("update", edited)
+    ///     .expect("couldn't replace update");
+    /// # }
+    /// ```
+    ///
+    /// [`replace`]: replace/index.html
+    pub fn replace_func(
+        &mut self,
+        name: &str,
+        module: &Module,
+    ) -> std::result::Result<(), replace::Error> {
+        replace::replace_func(&self.module, &mut self.inner, name, module.inner())?;
+        if !self
+            .replacements
+            .iter()
+            .any(|replacement| Arc::ptr_eq(replacement, module.inner()))
+        {
+            self.replacements.push(Arc::clone(module.inner()));
+        }
+        Ok(())
+    }
+
     /// The module used to instantiate this Instance.
     pub fn module(&self) -> Module {
-        Module::new(Arc::clone(&self.module))
+        Module::with_images(Arc::clone(&self.module), Arc::clone(&self.images))
     }
 }
 
@@ -305,6 +714,7 @@ impl Instance {
         };
 
         let token = Token::generate();
+        let _call = crash::enter(self.inner.vmctx, func_index);
 
         let returns = self.module.protected_caller.call(
             &self.module,
@@ -313,7 +723,8 @@ impl Instance {
             &self.inner.import_backing,
             vmctx,
             token,
-        )?;
+        );
+        let returns = unsafe { finish_call(self.inner.vmctx, returns) }?;
 
         Ok(returns)
     }
@@ -333,7 +744,7 @@ impl InstanceInner {
                     func,
                     ctx: match ctx {
                         Context::Internal => Context::External(self.vmctx),
-                        ctx @ Context::External(_) => ctx,
+                        ctx => ctx,
                     },
                     signature,
                 }
@@ -366,12 +777,7 @@ impl InstanceInner {
 
         let (func_ptr, ctx) = match func_index.local_or_import(&module.info) {
             LocalOrImport::Local(local_func_index) => (
-                module
-                    .func_resolver
-                    .get(&module, local_func_index)
-                    .expect("broken invariant, func resolver not synced with module.exports")
-                    .cast()
-                    .as_ptr() as *const _,
+                self.backing.local_functions[local_func_index] as *const _,
                 Context::Internal,
             ),
             LocalOrImport::Import(imported_func_index) => {
@@ -437,6 +843,32 @@ impl LikeNamespace for Instance {
     }
 }
 
+/// An instance that other instances share, such as one that a
+/// [`Linker`] made, can be imported from too.
+///
+/// [`Linker`]: ../link/struct.Linker.html
+impl LikeNamespace for Rc<Instance> {
+    fn get_export(&self, name: &str) -> Option<Export> {
+        (**self).get_export(name)
+    }
+
+    fn get_exports(&self) -> Vec<(String, Export)> {
+        self.module
+            .info
+            .exports
+            .iter()
+            .map(|(name, export_index)| {
+                let export = self.inner.get_export_from_index(&self.module, export_index);
+                (name.clone(), export)
+            })
+            .collect()
+    }
+
+    fn maybe_insert(&mut self, _name: &str, _export: Export) -> Option<()> {
+        None
+    }
+}
+
 /// A representation of an exported WebAssembly function.
 pub struct DynFunc<'a> {
     pub(crate) signature: Arc<FuncSig>,
@@ -454,8 +886,8 @@ impl<'a> DynFunc<'a> {
     /// [`Value`]: enum.Value.html
     ///
     /// # Note:
-    /// This returns `CallResult<Vec<Value>>` in order to support
-    /// the future multi-value returns webassembly feature.
+    /// This returns `CallResult<Vec<Value>>` because a function
+    /// can return more than one value with the multi-value proposal.
     ///
     /// # Usage:
     /// ```
@@ -484,6 +916,7 @@ impl<'a> DynFunc<'a> {
         };
 
         let token = Token::generate();
+        let _call = crash::enter(self.instance_inner.vmctx, self.func_index);
 
         let returns = self.module.protected_caller.call(
             &self.module,
@@ -492,7 +925,8 @@ impl<'a> DynFunc<'a> {
             &self.instance_inner.import_backing,
             vmctx,
             token,
-        )?;
+        );
+        let returns = unsafe { finish_call(self.instance_inner.vmctx, returns) }?;
 
         Ok(returns)
     }
@@ -503,12 +937,9 @@ impl<'a> DynFunc<'a> {
 
     pub fn raw(&self) -> *const vm::Func {
         match self.func_index.local_or_import(&self.module.info) {
-            LocalOrImport::Local(local_func_index) => self
-                .module
-                .func_resolver
-                .get(self.module, local_func_index)
-                .unwrap()
-                .as_ptr(),
+            LocalOrImport::Local(local_func_index) => {
+                self.instance_inner.backing.local_functions[local_func_index]
+            }
             LocalOrImport::Import(import_func_index) => {
                 self.instance_inner.import_backing.vm_functions[import_func_index].func
             }
@@ -522,3 +953,84 @@ impl Instance {
         unimplemented!()
     }
 }
+
+/// A handle to an [`Instance`] that can be sent to other threads to
+/// interrupt the WebAssembly code running in it, as returned by
+/// [`Instance::interrupt_handle`].
+///
+/// [`Instance`]: struct.Instance.html
+/// [`Instance::interrupt_handle`]: struct.Instance.html#method.interrupt_handle
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    /// Make the WebAssembly code running in the instance trap at the
+    /// start of its next function call or loop iteration, which the
+    /// call into the instance reports as [`RuntimeError::Interrupted`].
+    ///
+    /// The interrupt is cleared once the call into the instance
+    /// returns or fails, so it doesn't carry over to the next call.
+    /// If no code is running, it's the next call that is interrupted.
+    ///
+    /// [`RuntimeError::Interrupted`]: ../error/enum.RuntimeError.html#variant.Interrupted
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Finish an outermost call into the instance of `vmctx`, which
+/// returned `result`.
+///
+/// `vmctx` is the context of the instance that was called into, even
+/// if the function called is an import of it, whose own context may
+/// not be a `vm::Ctx` at all.
+///
+/// This clears an interrupt of the instance, whether the call saw it
+/// or not, and reports an error with [`preempted_error`].
+pub(crate) unsafe fn finish_call<T>(
+    vmctx: *mut vm::Ctx,
+    result: RuntimeResult<T>,
+) -> RuntimeResult<T> {
+    (*(*vmctx).internal.interrupted).store(false, Ordering::SeqCst);
+    result.map_err(|e| preempted_error(vmctx, e))
+}
+
+/// Report `error`, which a call into the instance of `vmctx` failed
+/// with, as an interrupt or an exceeded deadline if the code trapped
+/// with [`TrapCode::Interrupted`] or [`TrapCode::DeadlineExceeded`],
+/// or as running out of gas if the instance has no points left.
+///
+/// Other traps on `unreachable` are reported to the termination hook.
+pub(crate) unsafe fn preempted_error(vmctx: *mut vm::Ctx, error: RuntimeError) -> RuntimeError {
+    // The calls that the debugger was tracking have been unwound.
+    (*(*vmctx).local_backing).debug.unwind();
+
+    let internal = &mut (*vmctx).internal;
+    let error = match error {
+        RuntimeError::Trap {
+            code: TrapCode::Interrupted,
+            ..
+        } => RuntimeError::Interrupted,
+        RuntimeError::Trap {
+            code: TrapCode::DeadlineExceeded,
+            ..
+        } => RuntimeError::DeadlineExceeded,
+        RuntimeError::Trap { .. } if internal.remaining_points < 0 => {
+            internal.remaining_points = 0;
+            RuntimeError::OutOfGas
+        }
+        error => error,
+    };
+
+    if let RuntimeError::Trap {
+        code: TrapCode::Unreachable,
+        backtrace,
+        ..
+    } = &error
+    {
+        termination::report(Reason::Unreachable, backtrace.clone());
+    }
+    error
+}
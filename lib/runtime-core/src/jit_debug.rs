@@ -0,0 +1,209 @@
+//! Registration of compiled code with debuggers, through the
+//! [GDB JIT interface], which LLDB supports as well.
+//!
+//! A backend that can describe its compiled code with an object file,
+//! whose sections have the addresses that the code was loaded at,
+//! hands that object file to [`register`]. A debugger attached to the
+//! process then loads its symbols and debug info as if the code came
+//! from a shared library, so breakpoints can be set on WebAssembly
+//! functions and their frames show up in backtraces.
+//!
+//! Since the object files are kept alive for as long as their code is,
+//! backends only register them once [`set_enabled`] has been called.
+//!
+//! Only backends with [`Capabilities::jit_debug`] register their code,
+//! which for now is the LLVM backend. Its object files have symbols and
+//! unwind info, but no DWARF line tables that map the code back to the
+//! guest's source, so debuggers step through it instruction by
+//! instruction.
+//!
+//! [GDB JIT interface]: https://sourceware.org/gdb/onlinedocs/gdb/JIT-Interface.html
+//! [`register`]: fn.register.html
+//! [`set_enabled`]: fn.set_enabled.html
+//! [`Capabilities::jit_debug`]: ../backend/struct.Capabilities.html#structfield.jit_debug
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[repr(u32)]
+#[allow(dead_code)]
+enum JitAction {
+    NoAction = 0,
+    Register = 1,
+    Unregister = 2,
+}
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[doc(hidden)]
+#[repr(C)]
+pub struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// The debugger sets a breakpoint on this function to find out when
+/// the list of entries in `__jit_debug_descriptor` changes.
+#[doc(hidden)]
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn __jit_debug_register_code() {
+    // Keep the call from being optimized away.
+    unsafe { ptr::read_volatile(&0u8) };
+}
+
+/// The list of registered object files that the debugger reads.
+#[doc(hidden)]
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JitAction::NoAction as u32,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+lazy_static! {
+    /// Guards `__jit_debug_descriptor`, which every thread shares.
+    static ref DESCRIPTOR_LOCK: Mutex<()> = Mutex::new(());
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set whether backends register the code that they compile from now
+/// on with debuggers.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether backends register the code that they compile with debuggers.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// An object file registered with debuggers, which is unregistered
+/// when this is dropped.
+pub struct JitDebugRegistration {
+    entry: *mut JitCodeEntry,
+    _object: Box<[u8]>,
+}
+
+// The entry is only ever accessed while holding `DESCRIPTOR_LOCK`.
+unsafe impl Send for JitDebugRegistration {}
+unsafe impl Sync for JitDebugRegistration {}
+
+/// Register `object`, an in-memory object file describing code that
+/// has been loaded into this process, with debuggers.
+pub fn register(object: Vec<u8>) -> JitDebugRegistration {
+    let object = object.into_boxed_slice();
+    let entry = Box::into_raw(Box::new(JitCodeEntry {
+        next_entry: ptr::null_mut(),
+        prev_entry: ptr::null_mut(),
+        symfile_addr: object.as_ptr(),
+        symfile_size: object.len() as u64,
+    }));
+
+    let _guard = DESCRIPTOR_LOCK.lock();
+    unsafe {
+        let first_entry = __jit_debug_descriptor.first_entry;
+        (*entry).next_entry = first_entry;
+        if !first_entry.is_null() {
+            (*first_entry).prev_entry = entry;
+        }
+        __jit_debug_descriptor.first_entry = entry;
+
+        notify_debugger(JitAction::Register, entry);
+    }
+
+    JitDebugRegistration {
+        entry,
+        _object: object,
+    }
+}
+
+impl Drop for JitDebugRegistration {
+    fn drop(&mut self) {
+        let _guard = DESCRIPTOR_LOCK.lock();
+        unsafe {
+            let JitCodeEntry {
+                next_entry,
+                prev_entry,
+                ..
+            } = *self.entry;
+            if prev_entry.is_null() {
+                __jit_debug_descriptor.first_entry = next_entry;
+            } else {
+                (*prev_entry).next_entry = next_entry;
+            }
+            if !next_entry.is_null() {
+                (*next_entry).prev_entry = prev_entry;
+            }
+
+            notify_debugger(JitAction::Unregister, self.entry);
+            drop(Box::from_raw(self.entry));
+        }
+    }
+}
+
+/// Must be called with `DESCRIPTOR_LOCK` held.
+unsafe fn notify_debugger(action: JitAction, entry: *mut JitCodeEntry) {
+    __jit_debug_descriptor.relevant_entry = entry;
+    __jit_debug_descriptor.action_flag = action as u32;
+    __jit_debug_register_code();
+    __jit_debug_descriptor.relevant_entry = ptr::null_mut();
+    __jit_debug_descriptor.action_flag = JitAction::NoAction as u32;
+}
+
+#[cfg(test)]
+mod jit_debug_tests {
+    use super::*;
+
+    fn registered_objects() -> Vec<Vec<u8>> {
+        let _guard = DESCRIPTOR_LOCK.lock();
+        let mut objects = Vec::new();
+        unsafe {
+            let mut entry = __jit_debug_descriptor.first_entry;
+            while !entry.is_null() {
+                let object =
+                    std::slice::from_raw_parts((*entry).symfile_addr, (*entry).symfile_size as _);
+                objects.push(object.to_vec());
+                entry = (*entry).next_entry;
+            }
+        }
+        objects
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let first = register(b"first".to_vec());
+        let second = register(b"second".to_vec());
+        let third = register(b"third".to_vec());
+
+        assert_eq!(
+            registered_objects(),
+            vec![b"third".to_vec(), b"second".to_vec(), b"first".to_vec()],
+        );
+
+        drop(second);
+        assert_eq!(
+            registered_objects(),
+            vec![b"third".to_vec(), b"first".to_vec()],
+        );
+
+        drop(third);
+        drop(first);
+        assert!(registered_objects().is_empty());
+    }
+}
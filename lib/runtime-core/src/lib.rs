@@ -8,27 +8,49 @@ extern crate serde_derive;
 #[macro_use]
 mod macros;
 #[doc(hidden)]
+pub mod async_call;
 pub mod backend;
 mod backing;
 
 pub mod cache;
+pub mod crash;
+pub mod debugger;
+pub mod deterministic;
+pub mod epoch;
 pub mod error;
 pub mod export;
 pub mod global;
 pub mod import;
 pub mod instance;
+pub mod jit_debug;
+pub mod limits;
+pub mod link;
 pub mod memory;
+pub mod metering;
+pub mod middleware;
 pub mod module;
+pub mod perf;
+pub mod platform;
+pub mod pool;
+pub mod refs;
+pub mod replace;
 mod sig_registry;
+pub mod snapshot;
+pub mod stack;
+pub mod stackmap;
 pub mod structures;
 mod sys;
 pub mod table;
+pub mod termination;
+pub mod trap;
 mod typed_func;
 pub mod types;
 pub mod units;
 pub mod vm;
 #[doc(hidden)]
 pub mod vmcalls;
+#[cfg(feature = "vtune")]
+mod vtune;
 
 use self::error::CompileResult;
 #[doc(inline)]
@@ -41,7 +63,7 @@ pub use self::instance::Instance;
 pub use self::module::Module;
 #[doc(inline)]
 pub use self::typed_func::Func;
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
 use self::cache::{Artifact, Error as CacheError};
 
@@ -61,15 +83,53 @@ pub mod prelude {
 /// is necessary to a compile a module before it can be instantiated
 /// and must be used if you wish to use a different backend from the default.
 ///
+/// In deterministic mode, modules that can't run deterministically
+/// fail to compile. The code of the module is fed through the
+/// middleware chain, if there is one, before it's compiled.
+///
 /// [`Module`]: struct.Module.html
 pub fn compile_with(
     wasm: &[u8],
     compiler: &dyn backend::Compiler,
 ) -> CompileResult<module::Module> {
+    compile_with_config(wasm, compiler, backend::CompilerConfig::default())
+}
+
+/// Compile a [`Module`] like [`compile_with`] does, as
+/// `compiler_config` says.
+///
+/// [`Module`]: struct.Module.html
+/// [`compile_with`]: fn.compile_with.html
+pub fn compile_with_config(
+    wasm: &[u8],
+    compiler: &dyn backend::Compiler,
+    compiler_config: backend::CompilerConfig,
+) -> CompileResult<module::Module> {
+    let wasm = prepare_wasm(wasm)?;
     let token = backend::Token::generate();
-    compiler
-        .compile(wasm, token)
-        .map(|inner| module::Module::new(Arc::new(inner)))
+    let mut inner = compiler.compile(&wasm, compiler_config, token)?;
+    inner.info.initial_points = compiler_config
+        .metering
+        .map(|metering| metering.initial_points);
+    Ok(module::Module::new(Arc::new(inner)))
+}
+
+/// The code that `compile_with` gives the compiler for `wasm`, once
+/// it's been checked, fed through the middleware chain and
+/// instrumented for debugging, as configured.
+#[doc(hidden)]
+pub fn prepare_wasm(wasm: &[u8]) -> CompileResult<Cow<[u8]>> {
+    if deterministic::is_deterministic() {
+        deterministic::check(wasm)?;
+    }
+    let mut wasm = match middleware::transform(wasm)? {
+        Some(transformed) => Cow::Owned(transformed),
+        None => Cow::Borrowed(wasm),
+    };
+    if debugger::is_debugging() {
+        wasm = Cow::Owned(debugger::instrument(&wasm)?);
+    }
+    Ok(wasm)
 }
 
 /// Perform validation as defined by the
@@ -88,10 +148,92 @@ pub fn validate(wasm: &[u8]) -> bool {
     }
 }
 
+/// The WebAssembly proposals, beyond the MVP, that a module may use.
+///
+/// The default is the MVP alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    /// Shared memories and atomic instructions.
+    pub threads: bool,
+    /// `externref`, and the tables and instructions that use it.
+    pub reference_types: bool,
+    /// 128-bit SIMD instructions.
+    pub simd: bool,
+    /// Bulk memory instructions and passive segments.
+    pub bulk_memory: bool,
+    /// Imports of mutable globals.
+    pub mutable_global: bool,
+}
+
+/// Perform validation as defined by the WebAssembly specification,
+/// accepting only the proposals in `features`, without compiling the
+/// module.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::{validate_with_features, Features};
+/// // A shared memory and a function that atomically loads from it,
+/// // which need threads.
+/// let wasm = [
+///     &b"\0asm\x01\0\0\0"[..],
+///     &[1, 5, 1, 0x60, 0, 1, 0x7f],
+///     &[3, 2, 1, 0],
+///     &[5, 4, 1, 3, 1, 1],
+///     &[10, 10, 1, 8, 0, 0x41, 0, 0xfe, 0x10, 2, 0, 0x0b],
+/// ]
+/// .concat();
+/// assert!(validate_with_features(&wasm, Features::default()).is_err());
+///
+/// let features = Features {
+///     threads: true,
+///     ..Features::default()
+/// };
+/// assert!(validate_with_features(&wasm, features).is_ok());
+/// ```
+pub fn validate_with_features(wasm: &[u8], features: Features) -> CompileResult<()> {
+    use wasmparser::WasmDecoder;
+    let mut parser = wasmparser::ValidatingParser::new(
+        wasm,
+        Some(wasmparser::ValidatingParserConfig {
+            operator_config: wasmparser::OperatorValidatorConfig {
+                enable_threads: features.threads,
+                enable_reference_types: features.reference_types,
+                enable_simd: features.simd,
+                enable_bulk_memory: features.bulk_memory,
+            },
+            mutable_global_imports: features.mutable_global,
+        }),
+    );
+    loop {
+        let state = parser.read();
+        match *state {
+            wasmparser::ParserState::EndWasm => break Ok(()),
+            wasmparser::ParserState::Error(err) => Err(error::CompileError::ValidationError {
+                msg: err.message.to_string(),
+            })?,
+            _ => {}
+        }
+    }
+}
+
+/// Load a module from an artifact that `compiler` produced.
+///
+/// Fails with `IncompatibleTarget` if the artifact was compiled for a
+/// machine that the host isn't, and with `IncompatibleVersion` if
+/// another version of the backend compiled it.
 pub unsafe fn load_cache_with(
     cache: Artifact,
     compiler: &dyn backend::Compiler,
 ) -> std::result::Result<module::Module, CacheError> {
+    if !cache.target().runs_on_host() {
+        return Err(CacheError::IncompatibleTarget(cache.target().clone()));
+    }
+    if cache.backend_version() != compiler.capabilities().version {
+        return Err(CacheError::IncompatibleVersion {
+            wasmer: VERSION.to_string(),
+            backend: cache.backend_version().to_string(),
+        });
+    }
     let token = backend::Token::generate();
     compiler
         .from_cache(cache, token)
@@ -0,0 +1,140 @@
+//! Limits on how large the memories and tables of an instance may get,
+//! beyond the maximums that its module declares.
+//!
+//! Each instance gets a [`ResourceLimiter`] of its own from the
+//! function set with [`ImportObject::set_resource_limiter`]. The
+//! limiter is consulted when the instance creates its memories and
+//! tables, and whenever the guest grows one of them, so it can cap
+//! what each guest uses as it sees fit. Growing memories and tables
+//! from the host isn't limited.
+//!
+//! # Usage:
+//!
+//! ```
+//! # use wasmer_runtime_core::{imports, limits::ResourceLimiter, units::Pages};
+//! /// Lets each instance have 16 pages of memory at most.
+//! struct MemoryCap;
+//!
+//! impl ResourceLimiter for MemoryCap {
+//!     fn memory_growing(&mut self, _: Pages, desired: Pages, _: Option<Pages>) -> bool {
+//!         desired <= Pages(16)
+//!     }
+//!
+//!     fn table_growing(&mut self, _: u32, _: u32, _: Option<u32>) -> bool {
+//!         true
+//!     }
+//! }
+//!
+//! let mut import_object = imports! {};
+//! import_object.set_resource_limiter(|| Box::new(MemoryCap));
+//! ```
+//!
+//! [`ResourceLimiter`]: trait.ResourceLimiter.html
+//! [`ImportObject::set_resource_limiter`]: ../import/struct.ImportObject.html#method.set_resource_limiter
+
+use crate::{module::ModuleInfo, units::Pages};
+use std::fmt;
+
+/// Decides whether the memories and tables of an instance may grow.
+pub trait ResourceLimiter {
+    /// Whether a memory may grow from `current` to `desired` pages.
+    /// `maximum` is the maximum that the module declares for it.
+    ///
+    /// A memory that the instance creates is grown from zero pages to
+    /// its minimum, and instantiation fails if that's refused.
+    fn memory_growing(&mut self, current: Pages, desired: Pages, maximum: Option<Pages>) -> bool;
+
+    /// Whether a table may grow from `current` to `desired` elements.
+    /// `maximum` is the maximum that the module declares for it.
+    ///
+    /// A table that the instance creates is grown from zero elements
+    /// to its minimum, and instantiation fails if that's refused.
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+}
+
+/// Creates the resource limiter of each new instance.
+pub type LimiterCreator = dyn Fn() -> Box<dyn ResourceLimiter>;
+
+/// The resource limiter of an instance, if it has one.
+pub(crate) struct InstanceLimiter(Option<Box<dyn ResourceLimiter>>);
+
+impl InstanceLimiter {
+    pub fn new(limiter: Option<Box<dyn ResourceLimiter>>) -> Self {
+        InstanceLimiter(limiter)
+    }
+
+    /// Whether a memory may grow by `delta` pages from `current`.
+    pub fn memory_growing(&mut self, current: Pages, delta: Pages, maximum: Option<Pages>) -> bool {
+        match &mut self.0 {
+            Some(limiter) if delta != Pages(0) => {
+                let desired = Pages(current.0.saturating_add(delta.0));
+                limiter.memory_growing(current, desired, maximum)
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether a table may grow by `delta` elements from `current`.
+    pub fn table_growing(&mut self, current: u32, delta: u32, maximum: Option<u32>) -> bool {
+        match &mut self.0 {
+            Some(limiter) if delta != 0 => {
+                limiter.table_growing(current, current.saturating_add(delta), maximum)
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the local memories and tables of an instance of the
+    /// module described by `info` may be created.
+    pub fn allows_instance(&mut self, info: &ModuleInfo) -> bool {
+        info.memories
+            .iter()
+            .all(|(_, desc)| self.memory_growing(Pages(0), desc.minimum, desc.maximum))
+            && info
+                .tables
+                .iter()
+                .all(|(_, desc)| self.table_growing(0, desc.minimum, desc.maximum))
+    }
+}
+
+impl fmt::Debug for InstanceLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstanceLimiter")
+            .field("limited", &self.0.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+
+    /// Caps memories at two pages and refuses to grow tables.
+    struct Cap;
+
+    impl ResourceLimiter for Cap {
+        fn memory_growing(&mut self, _: Pages, desired: Pages, _: Option<Pages>) -> bool {
+            desired <= Pages(2)
+        }
+
+        fn table_growing(&mut self, _: u32, _: u32, _: Option<u32>) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_instance_limiter() {
+        let mut limiter = InstanceLimiter::new(Some(Box::new(Cap)));
+        assert!(limiter.memory_growing(Pages(1), Pages(1), None));
+        assert!(!limiter.memory_growing(Pages(1), Pages(2), Some(Pages(4))));
+        assert!(!limiter.table_growing(1, 1, None));
+
+        // Not growing at all is always allowed.
+        assert!(limiter.memory_growing(Pages(4), Pages(0), None));
+        assert!(limiter.table_growing(1, 0, None));
+
+        let mut unlimited = InstanceLimiter::new(None);
+        assert!(unlimited.memory_growing(Pages(1), Pages(100), None));
+        assert!(unlimited.table_growing(1, 100, None));
+    }
+}
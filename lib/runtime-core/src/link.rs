@@ -0,0 +1,152 @@
+//! Assembling an application out of several modules, whose instances
+//! import each other's exports.
+//!
+//! A [`Linker`] is given modules, and instances that already exist,
+//! under names. When a module is instantiated, each namespace that it
+//! imports from that names another of the linker's modules or
+//! instances is resolved to that instance's exports, whether they're
+//! functions, memories, globals or tables. Modules that it depends on
+//! are instantiated first, once each. Everything else comes from the
+//! host's [`ImportObject`].
+//!
+//! An instance keeps the instances that it imports from alive, so they
+//! can be dropped in any order. Modules that import each other in a
+//! cycle can't be instantiated, and fail with
+//! [`LinkError::ImportCycle`].
+//!
+//! # Usage:
+//!
+//! ```
+//! # use wasmer_runtime_core::{error::Result, imports, link::Linker, Module};
+//! # fn link(allocator: Module, app: Module) -> Result<()> {
+//! let mut linker = Linker::new(imports! {});
+//! // `app` imports `malloc`, `free` and the memory from "allocator".
+//! linker.define_module("allocator", allocator);
+//! linker.define_module("app", app);
+//!
+//! let app = linker.instantiate("app")?;
+//! app.call("main", &[])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Linker`]: struct.Linker.html
+//! [`ImportObject`]: ../import/struct.ImportObject.html
+//! [`LinkError::ImportCycle`]: ../error/enum.LinkError.html#variant.ImportCycle
+
+use crate::{
+    error::{LinkError, Result},
+    import::ImportObject,
+    instance::Instance,
+    module::Module,
+};
+use hashbrown::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Instantiates modules with each other's exports as their imports.
+pub struct Linker {
+    imports: ImportObject,
+    modules: HashMap<String, Module>,
+    instances: HashMap<String, Rc<Instance>>,
+}
+
+impl Linker {
+    /// A linker that takes the imports that none of its modules and
+    /// instances provide from `imports`.
+    pub fn new(imports: ImportObject) -> Self {
+        Self {
+            imports,
+            modules: HashMap::new(),
+            instances: HashMap::new(),
+        }
+    }
+
+    /// Let modules import the exports of an instance of `module`, as
+    /// the namespace `name`. It's instantiated when it's first needed.
+    ///
+    /// This replaces any module or instance called `name`, but the
+    /// instances that already import from it keep it.
+    pub fn define_module<S: Into<String>>(&mut self, name: S, module: Module) {
+        let name = name.into();
+        self.instances.remove(&name);
+        self.modules.insert(name, module);
+    }
+
+    /// Let modules import the exports of `instance`, as the namespace
+    /// `name`.
+    ///
+    /// This replaces any module or instance called `name`, but the
+    /// instances that already import from it keep it.
+    pub fn define_instance<S: Into<String>>(
+        &mut self,
+        name: S,
+        instance: Instance,
+    ) -> Rc<Instance> {
+        let name = name.into();
+        let instance = Rc::new(instance);
+        self.modules.remove(&name);
+        self.instances.insert(name, Rc::clone(&instance));
+        instance
+    }
+
+    /// The instance called `name`, if it has been defined or
+    /// instantiated.
+    pub fn get(&self, name: &str) -> Option<Rc<Instance>> {
+        self.instances.get(name).cloned()
+    }
+
+    /// The instance of the module called `name`, after instantiating
+    /// it and the modules that it imports from, if they haven't been
+    /// already.
+    pub fn instantiate(&mut self, name: &str) -> Result<Rc<Instance>> {
+        self.instantiate_in(name, &mut Vec::new())
+    }
+
+    /// Instantiate `name` for the modules in `chain`, each of which
+    /// imports from the next, and the last from `name`.
+    fn instantiate_in(&mut self, name: &str, chain: &mut Vec<String>) -> Result<Rc<Instance>> {
+        if let Some(instance) = self.instances.get(name) {
+            return Ok(Rc::clone(instance));
+        }
+
+        if let Some(start) = chain.iter().position(|link| link == name) {
+            let mut modules = chain[start..].to_vec();
+            modules.push(name.to_string());
+            return Err(vec![LinkError::ImportCycle { modules }].into());
+        }
+
+        let module = match self.modules.get(name) {
+            Some(module) => module.clone(),
+            None => {
+                return Err(vec![LinkError::UnknownModule {
+                    name: name.to_string(),
+                }]
+                .into())
+            }
+        };
+
+        let namespaces: HashSet<String> = module
+            .imports()
+            .into_iter()
+            .map(|import| import.namespace)
+            .filter(|namespace| {
+                self.modules.contains_key(namespace) || self.instances.contains_key(namespace)
+            })
+            .collect();
+
+        // The import object is kept by the new instance, and so are
+        // the instances registered with it.
+        let mut imports = ImportObject::layered_over(&self.imports);
+        chain.push(name.to_string());
+        for namespace in namespaces {
+            let dependency = self.instantiate_in(&namespace, chain)?;
+            imports.register(namespace, dependency);
+        }
+        chain.pop();
+
+        let instance = Rc::new(module.instantiate(&imports)?);
+        self.instances
+            .insert(name.to_string(), Rc::clone(&instance));
+        Ok(instance)
+    }
+}
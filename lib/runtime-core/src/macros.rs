@@ -17,6 +17,9 @@ macro_rules! func {
     ($func:path) => {{
         $crate::Func::new($func)
     }};
+    ($func:expr) => {{
+        $crate::Func::new($func)
+    }};
 }
 
 /// Generate an [`ImportObject`] safely.
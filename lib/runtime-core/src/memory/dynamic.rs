@@ -1,11 +1,13 @@
 use crate::error::GrowError;
 use crate::{
     error::CreationError,
+    memory::GrowCallback,
     sys,
     types::MemoryDescriptor,
     units::{Bytes, Pages},
     vm,
 };
+use std::rc::Rc;
 
 pub const DYNAMIC_GUARD_SIZE: usize = 4096;
 
@@ -26,6 +28,7 @@ pub struct DynamicMemory {
     memory: sys::Memory,
     current: Pages,
     max: Option<Pages>,
+    grow_callback: Option<Rc<GrowCallback>>,
 }
 
 impl DynamicMemory {
@@ -34,24 +37,35 @@ impl DynamicMemory {
         local: &mut vm::LocalMemory,
     ) -> Result<Box<Self>, CreationError> {
         let min_bytes: Bytes = desc.minimum.into();
-        let memory = {
-            let mut memory = sys::Memory::with_size(min_bytes.0 + DYNAMIC_GUARD_SIZE)
-                .map_err(|_| CreationError::UnableToCreateMemory)?;
-            if desc.minimum != Pages(0) {
-                unsafe {
-                    memory
-                        .protect(0..min_bytes.0, sys::Protect::ReadWrite)
-                        .map_err(|_| CreationError::UnableToCreateMemory)?;
-                }
-            }
+        let memory = sys::Memory::with_size(min_bytes.0 + DYNAMIC_GUARD_SIZE)
+            .map_err(|_| CreationError::UnableToCreateMemory)?;
 
-            memory
-        };
+        Self::with_memory(desc, memory, local)
+    }
+
+    /// Create a dynamic memory in `memory`, which must be large enough
+    /// for the minimum size and the guard page, and be inaccessible
+    /// past the minimum size. If there's room, it grows within
+    /// `memory` instead of being moved.
+    pub(super) fn with_memory(
+        desc: MemoryDescriptor,
+        mut memory: sys::Memory,
+        local: &mut vm::LocalMemory,
+    ) -> Result<Box<Self>, CreationError> {
+        let min_bytes: Bytes = desc.minimum.into();
+        if desc.minimum != Pages(0) {
+            unsafe {
+                memory
+                    .protect(0..min_bytes.0, sys::Protect::ReadWrite)
+                    .map_err(|_| CreationError::UnableToCreateMemory)?;
+            }
+        }
 
         let mut storage = Box::new(DynamicMemory {
             memory,
             current: desc.minimum,
             max: desc.maximum,
+            grow_callback: None,
         });
         let storage_ptr: *mut DynamicMemory = &mut *storage;
 
@@ -82,19 +96,27 @@ impl DynamicMemory {
             }
         }
 
-        let mut new_memory = sys::Memory::with_size(new_pages.bytes().0 + DYNAMIC_GUARD_SIZE)
-            .map_err(|e| e.into())?;
-
-        unsafe {
-            new_memory
-                .protect(0..new_pages.bytes().0, sys::Protect::ReadWrite)
+        if new_pages.bytes().0 + DYNAMIC_GUARD_SIZE <= self.memory.size() {
+            unsafe {
+                self.memory
+                    .protect(0..new_pages.bytes().0, sys::Protect::ReadWrite)
+                    .map_err(|e| e.into())?;
+            }
+        } else {
+            let mut new_memory = sys::Memory::with_size(new_pages.bytes().0 + DYNAMIC_GUARD_SIZE)
                 .map_err(|e| e.into())?;
 
-            new_memory.as_slice_mut()[..self.current.bytes().0]
-                .copy_from_slice(&self.memory.as_slice()[..self.current.bytes().0]);
-        }
+            unsafe {
+                new_memory
+                    .protect(0..new_pages.bytes().0, sys::Protect::ReadWrite)
+                    .map_err(|e| e.into())?;
 
-        self.memory = new_memory; //The old memory gets dropped.
+                new_memory.as_slice_mut()[..self.current.bytes().0]
+                    .copy_from_slice(&self.memory.as_slice()[..self.current.bytes().0]);
+            }
+
+            self.memory = new_memory; //The old memory gets dropped.
+        }
 
         local.base = self.memory.as_ptr();
         local.bound = new_pages.bytes().0;
@@ -104,6 +126,21 @@ impl DynamicMemory {
         Ok(old_pages)
     }
 
+    /// Map `image` copy-on-write over the start of this memory, which
+    /// must already be at least as large as the image.
+    pub(crate) fn map_image(&mut self, image: &sys::MemoryImage) -> Result<(), GrowError> {
+        assert!(image.size() <= self.current.bytes().0);
+        unsafe { self.memory.map_image(image) }.map_err(|e| e.into())
+    }
+
+    pub(crate) fn grow_callback(&self) -> Option<Rc<GrowCallback>> {
+        self.grow_callback.clone()
+    }
+
+    pub(crate) fn set_grow_callback(&mut self, callback: Option<Rc<GrowCallback>>) {
+        self.grow_callback = callback;
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { &self.memory.as_slice()[0..self.current.bytes().0] }
     }
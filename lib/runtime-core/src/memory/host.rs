@@ -0,0 +1,52 @@
+use crate::{platform::PageAllocator, sys};
+use parking_lot::Mutex;
+use std::ptr;
+
+/// The "allocator" of a memory made of a buffer that the embedder
+/// provides. It never allocates anything: it only changes the
+/// protection of the buffer, and hands the buffer back to the
+/// embedder when the memory is dropped.
+pub(super) struct HostBuffer {
+    release: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+}
+
+impl HostBuffer {
+    pub fn new(release: Box<dyn FnOnce() + Send>) -> Self {
+        HostBuffer {
+            release: Mutex::new(Some(release)),
+        }
+    }
+}
+
+unsafe impl PageAllocator for HostBuffer {
+    fn page_size(&self) -> usize {
+        page_size::get()
+    }
+
+    fn reserve(&self, _size: usize) -> Result<*mut u8, String> {
+        Err("a host buffer cannot be reallocated".to_string())
+    }
+
+    unsafe fn protect(
+        &self,
+        ptr: *mut u8,
+        size: usize,
+        protection: sys::Protect,
+    ) -> Result<(), String> {
+        sys::protect_pages(ptr, size, protection)
+    }
+
+    unsafe fn reset(&self, ptr: *mut u8, size: usize) -> Result<(), String> {
+        sys::protect_pages(ptr, size, sys::Protect::ReadWrite)?;
+        ptr::write_bytes(ptr, 0, size);
+        sys::protect_pages(ptr, size, sys::Protect::None)
+    }
+
+    unsafe fn release(&self, ptr: *mut u8, size: usize) {
+        // The embedder gets the buffer back as accessible as it gave it.
+        let _ = sys::protect_pages(ptr, size, sys::Protect::ReadWrite);
+        if let Some(release) = self.release.lock().take() {
+            release();
+        }
+    }
+}
@@ -2,27 +2,50 @@ use crate::{
     error::{CreationError, GrowError},
     export::Export,
     import::IsExport,
-    memory::dynamic::DYNAMIC_GUARD_SIZE,
-    memory::static_::{SAFE_STATIC_GUARD_SIZE, SAFE_STATIC_HEAP_SIZE},
+    memory::{dynamic::DYNAMIC_GUARD_SIZE, host::HostBuffer},
+    platform::Allocator,
+    sys,
     types::{MemoryDescriptor, ValueType},
-    units::Pages,
+    units::{Bytes, Pages},
     vm,
 };
 use std::{
-    cell::{Cell, RefCell},
-    fmt, mem, ptr,
+    cell::{Cell, RefCell, UnsafeCell},
+    cmp, fmt, mem,
     rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 pub use self::atomic::Atomic;
 pub use self::dynamic::DynamicMemory;
 pub use self::static_::{SharedStaticMemory, StaticMemory};
+pub(crate) use self::static_::{SAFE_STATIC_GUARD_SIZE, SAFE_STATIC_HEAP_SIZE};
 pub use self::view::{Atomically, MemoryView};
 
 mod atomic;
 mod dynamic;
+mod host;
+pub mod ptr;
 mod static_;
 mod view;
+pub(crate) mod waiter;
+
+/// A callback that is called with the old and the new size of
+/// a memory whenever it grows.
+pub type GrowCallback = dyn Fn(Pages, Pages);
+
+/// Let the grow callback of a memory, if it has one, know
+/// that the memory just grew by `delta` pages from `old`.
+pub(crate) fn notify_grow(callback: Option<Rc<GrowCallback>>, old: Pages, delta: Pages) {
+    if let Some(callback) = callback {
+        if delta != Pages(0) {
+            callback(old, Pages(old.0 + delta.0));
+        }
+    }
+}
 
 #[derive(Clone)]
 enum MemoryVariant {
@@ -63,6 +86,15 @@ impl Memory {
     /// # }
     /// ```
     pub fn new(desc: MemoryDescriptor) -> Result<Self, CreationError> {
+        Self::with_style(desc, memory_style())
+    }
+
+    /// Create a memory of the type that `desc` has with `style`,
+    /// whatever the current memory style is.
+    pub(crate) fn with_style(
+        desc: MemoryDescriptor,
+        style: MemoryStyle,
+    ) -> Result<Self, CreationError> {
         if let Some(max) = desc.maximum {
             if max < desc.minimum {
                 return Err(CreationError::InvalidDescriptor(
@@ -72,8 +104,23 @@ impl Memory {
             }
         }
 
+        if desc.minimum > Pages(65_536) || desc.maximum.map_or(false, |max| max > Pages(65_536)) {
+            return Err(CreationError::InvalidDescriptor(
+                "Memories cannot be larger than 65536 pages".to_string(),
+            ));
+        }
+
+        if desc.shared && desc.maximum.is_none() {
+            return Err(CreationError::InvalidDescriptor(
+                "Shared memories must have a maximum number of pages".to_string(),
+            ));
+        }
+
         let variant = if !desc.shared {
-            MemoryVariant::Unshared(UnsharedMemory::new(desc)?)
+            MemoryVariant::Unshared(UnsharedMemory::with_type(
+                desc,
+                desc.memory_type_with_style(style),
+            )?)
         } else {
             MemoryVariant::Shared(SharedMemory::new(desc)?)
         };
@@ -81,6 +128,100 @@ impl Memory {
         Ok(Memory { desc, variant })
     }
 
+    /// Create an unshared memory in the `len` bytes at `base`, a buffer
+    /// (or a shared mapping) that the embedder already has, so that the
+    /// host and the guests that import the memory can share data
+    /// without copying it.
+    ///
+    /// The first `desc.minimum` pages of the buffer become the memory,
+    /// with their contents kept. The memory grows within the buffer,
+    /// and never moves, so its maximum is lowered to the number of
+    /// pages that fit in the buffer before the guard page at its end.
+    ///
+    /// The memory is dynamic, so only modules whose import of it has no
+    /// maximum, or that were compiled with [`MemoryStyle::Dynamic`], can
+    /// import it.
+    ///
+    /// The memory owns the buffer until `release` is called, which is
+    /// when the memory and every instance that imports it have been
+    /// dropped. It makes the part of the buffer past its current size
+    /// inaccessible, and makes all of it readable and writable again
+    /// before calling `release`.
+    ///
+    /// # Safety
+    ///
+    /// - `base` must be aligned to the page size of the operating
+    ///   system, and `len` must be a multiple of it.
+    /// - The buffer must be readable and writable, and stay valid until
+    ///   `release` is called.
+    /// - Until then, the host may only access the part of the buffer
+    ///   that is within the current size of the memory. Since the guest
+    ///   can write to it whenever it runs, the host shouldn't hold
+    ///   references into it across calls into the guest.
+    ///
+    /// [`MemoryStyle::Dynamic`]: enum.MemoryStyle.html#variant.Dynamic
+    pub unsafe fn from_raw_parts<F>(
+        desc: MemoryDescriptor,
+        base: *mut u8,
+        len: usize,
+        release: F,
+    ) -> Result<Self, CreationError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if desc.shared {
+            return Err(CreationError::InvalidDescriptor(
+                "Memories in host buffers cannot be shared".to_string(),
+            ));
+        }
+
+        let page_size = page_size::get();
+        if base as usize % page_size != 0 || len % page_size != 0 {
+            return Err(CreationError::InvalidBuffer(format!(
+                "The buffer must be aligned to, and a multiple of, {} bytes",
+                page_size
+            )));
+        }
+
+        let limit = desc.maximum.unwrap_or(Pages(65_536));
+        let capacity: Pages = Bytes(len.saturating_sub(DYNAMIC_GUARD_SIZE)).into();
+        let maximum = cmp::min(limit, cmp::min(capacity, Pages(65_536)));
+        if maximum < desc.minimum {
+            return Err(CreationError::InvalidBuffer(format!(
+                "The buffer only has room for {} of the {} pages needed",
+                capacity.0, desc.minimum.0
+            )));
+        }
+        let desc = MemoryDescriptor {
+            maximum: Some(maximum),
+            ..desc
+        };
+
+        let allocator = Allocator(Arc::new(HostBuffer::new(Box::new(release))));
+        let mut memory = sys::Memory::from_raw_parts(base, len, sys::Protect::ReadWrite, allocator);
+        memory
+            .protect(desc.minimum.bytes().0.., sys::Protect::None)
+            .map_err(|_| CreationError::UnableToCreateMemory)?;
+
+        let mut local = vm::LocalMemory {
+            base: std::ptr::null_mut(),
+            bound: 0,
+            memory: std::ptr::null_mut(),
+        };
+        let storage =
+            UnsharedMemoryStorage::Dynamic(DynamicMemory::with_memory(desc, memory, &mut local)?);
+
+        Ok(Memory {
+            desc,
+            variant: MemoryVariant::Unshared(UnsharedMemory {
+                internal: Rc::new(UnsharedMemoryInternal {
+                    storage: RefCell::new(storage),
+                    local: Cell::new(local),
+                }),
+            }),
+        })
+    }
+
     /// Return the [`MemoryDescriptor`] that this memory
     /// was created with.
     ///
@@ -89,7 +230,11 @@ impl Memory {
         self.desc
     }
 
-    /// Grow this memory by the specified number of pages.
+    /// Grow this memory by the specified number of pages,
+    /// returning the previous size on success.
+    ///
+    /// Growing a memory may move it, so pointers into it
+    /// obtained before calling this are no longer valid.
     pub fn grow(&self, delta: Pages) -> Result<Pages, GrowError> {
         match &self.variant {
             MemoryVariant::Unshared(unshared_mem) => unshared_mem.grow(delta),
@@ -97,6 +242,57 @@ impl Memory {
         }
     }
 
+    /// Set a callback that is called with the old and the new
+    /// size of this memory whenever it grows, either because
+    /// of [`grow`] or because the guest used `memory.grow`.
+    ///
+    /// This replaces any previously set callback. Since it can
+    /// be called from WebAssembly, the callback must not panic.
+    ///
+    /// Returns an error for shared memories, which can be grown
+    /// from any thread and so don't support grow callbacks yet.
+    ///
+    /// [`grow`]: #method.grow
+    ///
+    /// # Usage:
+    ///
+    /// ```
+    /// # use wasmer_runtime_core::memory::Memory;
+    /// # use wasmer_runtime_core::error::GrowError;
+    /// # use wasmer_runtime_core::units::Pages;
+    /// # fn track_memory(memory: &Memory) -> Result<(), GrowError> {
+    /// memory.set_grow_callback(|old, new| {
+    ///     println!("memory grew from {:?} to {:?}", old, new);
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_grow_callback<F>(&self, callback: F) -> Result<(), GrowError>
+    where
+        F: Fn(Pages, Pages) + 'static,
+    {
+        match &self.variant {
+            MemoryVariant::Unshared(unshared_mem) => {
+                unshared_mem.set_grow_callback(Some(Rc::new(callback)));
+                Ok(())
+            }
+            MemoryVariant::Shared(_) => Err(GrowError::SharedMemoryGrowCallback),
+        }
+    }
+
+    /// Remove the callback set with [`set_grow_callback`].
+    ///
+    /// Since shared memories never have one, this does nothing
+    /// for them.
+    ///
+    /// [`set_grow_callback`]: #method.set_grow_callback
+    pub fn clear_grow_callback(&self) {
+        match &self.variant {
+            MemoryVariant::Unshared(unshared_mem) => unshared_mem.set_grow_callback(None),
+            MemoryVariant::Shared(_) => {}
+        }
+    }
+
     /// The size, in wasm pages, of this memory.
     pub fn size(&self) -> Pages {
         match &self.variant {
@@ -136,6 +332,11 @@ impl Memory {
     /// for byte in atomic_view[0x1000 .. 0x1010].iter().map(|atom| atom.load(Ordering::SeqCst)) {
     ///     println!("byte: {}", byte);
     /// }
+    ///
+    /// // Copying in and out of a part of the memory.
+    /// let mut bytes = [0u8; 16];
+    /// view.subarray(0x1000, 0x1010).copy_to(&mut bytes);
+    /// view.subarray(0x2000, 0x2010).copy_from(&bytes);
     /// # }
     /// ```
     pub fn view<T: ValueType>(&self) -> MemoryView<T> {
@@ -143,23 +344,60 @@ impl Memory {
 
         let length = self.size().bytes().0 / mem::size_of::<T>();
 
-        unsafe { MemoryView::new(base as _, length as u32) }
+        unsafe { MemoryView::new(base as _, length) }
     }
 
     /// Convert this memory to a shared memory if the shared flag
     /// is present in the description used to create it.
+    ///
+    /// Unlike a `Memory`, a [`SharedMemory`] can be sent to other
+    /// threads, where it can be turned back into a `Memory` and
+    /// imported into instances running there.
+    ///
+    /// [`SharedMemory`]: struct.SharedMemory.html
     pub fn shared(self) -> Option<SharedMemory> {
-        if self.desc.shared {
-            Some(SharedMemory { desc: self.desc })
-        } else {
-            None
+        match self.variant {
+            MemoryVariant::Shared(shared_mem) => Some(shared_mem),
+            MemoryVariant::Unshared(_) => None,
+        }
+    }
+
+    /// The type of this memory, which compiled code accessing it
+    /// must have been generated for.
+    pub(crate) fn memory_type(&self) -> MemoryType {
+        match &self.variant {
+            MemoryVariant::Unshared(unshared_mem) => {
+                match &*unshared_mem.internal.storage.borrow() {
+                    UnsharedMemoryStorage::Dynamic(_) => MemoryType::Dynamic,
+                    UnsharedMemoryStorage::Static(_) => MemoryType::Static,
+                }
+            }
+            MemoryVariant::Shared(_) => MemoryType::SharedStatic,
+        }
+    }
+
+    /// Map `image` copy-on-write over the start of this memory, if
+    /// it's unshared. Returns whether the image was mapped.
+    pub(crate) fn map_image(&self, image: &sys::MemoryImage) -> Result<bool, GrowError> {
+        match &self.variant {
+            MemoryVariant::Unshared(unshared_mem) => unshared_mem.map_image(image).map(|()| true),
+            MemoryVariant::Shared(_) => Ok(false),
         }
     }
 
     pub(crate) fn vm_local_memory(&self) -> *mut vm::LocalMemory {
         match &self.variant {
             MemoryVariant::Unshared(unshared_mem) => unshared_mem.vm_local_memory(),
-            MemoryVariant::Shared(_) => unimplemented!(),
+            MemoryVariant::Shared(shared_mem) => shared_mem.vm_local_memory(),
+        }
+    }
+}
+
+impl From<SharedMemory> for Memory {
+    fn from(shared_mem: SharedMemory) -> Self {
+        Memory {
+            desc: shared_mem.desc,
+            variant: MemoryVariant::Shared(shared_mem),
         }
     }
 }
@@ -179,6 +417,57 @@ impl fmt::Debug for Memory {
     }
 }
 
+/// How unshared memories with a maximum size are laid out.
+///
+/// Memories without a maximum size are always dynamic, and shared
+/// memories are always static.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryStyle {
+    /// Reserve enough address space, followed by a guard region, that
+    /// no access can reach past the end of it. Compiled code then
+    /// needs no bounds checks, since out of bounds accesses fault and
+    /// are reported as traps, but every memory reserves 6GiB of
+    /// virtual address space.
+    Static,
+    /// Only map as much as the memory's current size, and have
+    /// compiled code check the bounds of every access.
+    ///
+    /// The LLVM and singlepass backends check the whole of each
+    /// access. Cranelift only checks where an access starts, and
+    /// relies on a page that's kept inaccessible after the memory to
+    /// fault on the rest.
+    Dynamic,
+}
+
+static DYNAMIC_MEMORIES: AtomicBool = AtomicBool::new(cfg!(not(target_pointer_width = "64")));
+
+/// Set the style of the memories that are created, and that modules
+/// are compiled for, from now on.
+///
+/// Modules keep the style that they were compiled with, so their own
+/// memories aren't affected by changing it later. Memories imported by
+/// a module must have been created with the same style that it was
+/// compiled with.
+///
+/// The default is [`MemoryStyle::Static`] on 64-bit platforms and
+/// [`MemoryStyle::Dynamic`] elsewhere, where there isn't enough
+/// address space to reserve.
+///
+/// [`MemoryStyle::Static`]: enum.MemoryStyle.html#variant.Static
+/// [`MemoryStyle::Dynamic`]: enum.MemoryStyle.html#variant.Dynamic
+pub fn set_memory_style(style: MemoryStyle) {
+    DYNAMIC_MEMORIES.store(style == MemoryStyle::Dynamic, Ordering::SeqCst);
+}
+
+/// The style of the memories that are created from now on.
+pub fn memory_style() -> MemoryStyle {
+    if DYNAMIC_MEMORIES.load(Ordering::SeqCst) {
+        MemoryStyle::Dynamic
+    } else {
+        MemoryStyle::Static
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryType {
     Dynamic,
@@ -220,13 +509,17 @@ struct UnsharedMemoryInternal {
 
 impl UnsharedMemory {
     pub fn new(desc: MemoryDescriptor) -> Result<Self, CreationError> {
+        Self::with_type(desc, desc.memory_type())
+    }
+
+    fn with_type(desc: MemoryDescriptor, memory_type: MemoryType) -> Result<Self, CreationError> {
         let mut local = vm::LocalMemory {
-            base: ptr::null_mut(),
+            base: std::ptr::null_mut(),
             bound: 0,
-            memory: ptr::null_mut(),
+            memory: std::ptr::null_mut(),
         };
 
-        let storage = match desc.memory_type() {
+        let storage = match memory_type {
             MemoryType::Dynamic => {
                 UnsharedMemoryStorage::Dynamic(DynamicMemory::new(desc, &mut local)?)
             }
@@ -249,18 +542,42 @@ impl UnsharedMemory {
 
         let mut local = self.internal.local.get();
 
-        let pages = match &mut *storage {
-            UnsharedMemoryStorage::Dynamic(dynamic_memory) => {
-                dynamic_memory.grow(delta, &mut local)
-            }
-            UnsharedMemoryStorage::Static(static_memory) => static_memory.grow(delta, &mut local),
+        let (pages, callback) = match &mut *storage {
+            UnsharedMemoryStorage::Dynamic(dynamic_memory) => (
+                dynamic_memory.grow(delta, &mut local),
+                dynamic_memory.grow_callback(),
+            ),
+            UnsharedMemoryStorage::Static(static_memory) => (
+                static_memory.grow(delta, &mut local),
+                static_memory.grow_callback(),
+            ),
         };
 
         self.internal.local.set(local);
 
+        // The callback may well look at this memory,
+        // so it can't be called while it's borrowed.
+        drop(storage);
+        if let Ok(old) = pages {
+            notify_grow(callback, old, delta);
+        }
+
         pages
     }
 
+    fn set_grow_callback(&self, callback: Option<Rc<GrowCallback>>) {
+        let mut storage = self.internal.storage.borrow_mut();
+
+        match &mut *storage {
+            UnsharedMemoryStorage::Dynamic(dynamic_memory) => {
+                dynamic_memory.set_grow_callback(callback)
+            }
+            UnsharedMemoryStorage::Static(static_memory) => {
+                static_memory.set_grow_callback(callback)
+            }
+        }
+    }
+
     pub fn size(&self) -> Pages {
         let storage = self.internal.storage.borrow();
 
@@ -270,6 +587,15 @@ impl UnsharedMemory {
         }
     }
 
+    fn map_image(&self, image: &sys::MemoryImage) -> Result<(), GrowError> {
+        let mut storage = self.internal.storage.borrow_mut();
+
+        match &mut *storage {
+            UnsharedMemoryStorage::Dynamic(dynamic_memory) => dynamic_memory.map_image(image),
+            UnsharedMemoryStorage::Static(static_memory) => static_memory.map_image(image),
+        }
+    }
+
     pub(crate) fn vm_local_memory(&self) -> *mut vm::LocalMemory {
         self.internal.local.as_ptr()
     }
@@ -283,35 +609,75 @@ impl Clone for UnsharedMemory {
     }
 }
 
+/// A wasm linear memory that can be shared between threads.
+///
+/// Every clone refers to the same memory, and every instance
+/// that imports it sees the same contents at the same address.
 pub struct SharedMemory {
-    #[allow(dead_code)]
     desc: MemoryDescriptor,
+    internal: Arc<SharedMemoryInternal>,
 }
 
+struct SharedMemoryInternal {
+    memory: Box<SharedStaticMemory>,
+    local: UnsafeCell<vm::LocalMemory>,
+}
+
+// The `vm::LocalMemory` is only written to while
+// the lock in `SharedStaticMemory` is held.
+unsafe impl Send for SharedMemoryInternal {}
+unsafe impl Sync for SharedMemoryInternal {}
+
 impl SharedMemory {
     fn new(desc: MemoryDescriptor) -> Result<Self, CreationError> {
-        Ok(Self { desc })
+        let mut local = vm::LocalMemory {
+            base: std::ptr::null_mut(),
+            bound: 0,
+            memory: std::ptr::null_mut(),
+        };
+
+        let memory = SharedStaticMemory::new(desc, &mut local)?;
+
+        Ok(SharedMemory {
+            desc,
+            internal: Arc::new(SharedMemoryInternal {
+                memory,
+                local: UnsafeCell::new(local),
+            }),
+        })
     }
 
-    pub fn grow(&self, _delta: Pages) -> Result<Pages, GrowError> {
-        unimplemented!()
+    pub fn grow(&self, delta: Pages) -> Result<Pages, GrowError> {
+        unsafe {
+            self.internal
+                .memory
+                .grow(delta, &mut *self.internal.local.get())
+        }
     }
 
     pub fn size(&self) -> Pages {
-        unimplemented!()
+        self.internal.memory.size()
+    }
+
+    pub(crate) fn vm_local_memory(&self) -> *mut vm::LocalMemory {
+        self.internal.local.get()
     }
 }
 
 impl Clone for SharedMemory {
     fn clone(&self) -> Self {
-        unimplemented!()
+        SharedMemory {
+            desc: self.desc,
+            internal: Arc::clone(&self.internal),
+        }
     }
 }
 
 #[cfg(test)]
 mod memory_tests {
 
-    use super::{Memory, MemoryDescriptor, Pages};
+    use super::{Memory, MemoryDescriptor, MemoryStyle, MemoryType, Pages};
+    use std::{cell::RefCell, rc::Rc, sync::atomic::Ordering, thread};
 
     #[test]
     fn test_initial_memory_size() {
@@ -324,4 +690,212 @@ mod memory_tests {
         assert_eq!(unshared_memory.size(), Pages(10));
     }
 
+    #[test]
+    fn test_dynamic_style() {
+        let desc = MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(2)),
+            shared: false,
+        };
+        assert_eq!(
+            desc.memory_type_with_style(MemoryStyle::Static),
+            MemoryType::Static
+        );
+
+        let memory = Memory::with_style(desc, MemoryStyle::Dynamic).unwrap();
+        assert_eq!(memory.memory_type(), MemoryType::Dynamic);
+        assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(1));
+        assert!(memory.grow(Pages(1)).is_err());
+
+        let shared = Memory::with_style(
+            MemoryDescriptor {
+                shared: true,
+                ..desc
+            },
+            MemoryStyle::Dynamic,
+        )
+        .unwrap();
+        assert_eq!(shared.memory_type(), MemoryType::SharedStatic);
+    }
+
+    #[test]
+    fn test_grow_callback() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(4)),
+            shared: false,
+        })
+        .unwrap();
+
+        let grown = Rc::new(RefCell::new(vec![]));
+        let grown_in_callback = Rc::clone(&grown);
+        let memory_in_callback = memory.clone();
+        memory
+            .set_grow_callback(move |old, new| {
+                assert_eq!(memory_in_callback.size(), new);
+                grown_in_callback.borrow_mut().push((old, new));
+            })
+            .unwrap();
+
+        assert_eq!(memory.grow(Pages(2)).unwrap(), Pages(1));
+        assert_eq!(memory.grow(Pages(0)).unwrap(), Pages(3));
+        assert!(memory.grow(Pages(2)).is_err());
+
+        memory.clear_grow_callback();
+        assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(3));
+
+        assert_eq!(*grown.borrow(), vec![(Pages(1), Pages(3))]);
+    }
+
+    #[test]
+    fn test_shared_memory_grow_callback() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(4)),
+            shared: true,
+        })
+        .unwrap();
+
+        assert!(memory.set_grow_callback(|_, _| {}).is_err());
+        memory.clear_grow_callback();
+        assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(1));
+    }
+
+    #[test]
+    fn test_view_subarray_and_copy() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+
+        let view = memory.view::<u32>();
+        view.subarray(4, 8).copy_from(&[1, 2, 3, 4, 5]);
+        assert_eq!(view[3].get(), 0);
+        assert_eq!(view[7].get(), 4);
+        assert_eq!(view[8].get(), 0);
+
+        let sub = view.subarray(6, 10);
+        assert_eq!(sub.len(), 4);
+        assert_eq!(sub.atomically()[0].load(Ordering::SeqCst), 3);
+
+        let mut buffer = [0; 6];
+        sub.copy_to(&mut buffer);
+        assert_eq!(buffer, [3, 4, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_view_subarray_out_of_bounds() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+
+        let len = memory.view::<u32>().len();
+        memory.view::<u32>().subarray(len - 1, len + 1);
+    }
+
+    #[test]
+    fn test_shared_memory_requires_maximum() {
+        assert!(Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: true,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_shared_memory_across_threads() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(4)),
+            shared: true,
+        })
+        .unwrap();
+        let shared = memory.clone().shared().unwrap();
+
+        thread::spawn(move || {
+            let memory = Memory::from(shared);
+            memory.view::<u8>()[16].set(42);
+            assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(1));
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(memory.view::<u8>()[16].get(), 42);
+        assert_eq!(memory.size(), Pages(2));
+        assert_eq!(memory.view::<u8>().len(), Pages(2).bytes().0);
+    }
+
+    #[test]
+    fn test_memory_in_host_buffer() {
+        use std::alloc::{self, Layout};
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        // Room for three pages and the guard page after them.
+        let page_size = page_size::get();
+        let len =
+            (Pages(3).bytes().0 + super::DYNAMIC_GUARD_SIZE + page_size - 1) & !(page_size - 1);
+        let layout = Layout::from_size_align(len, page_size).unwrap();
+        let base = unsafe { alloc::alloc_zeroed(layout) };
+        unsafe { *base.add(8) = 42 };
+
+        let released = Arc::new(AtomicBool::new(false));
+        let memory = {
+            let released = released.clone();
+            let address = base as usize;
+            unsafe {
+                Memory::from_raw_parts(
+                    MemoryDescriptor {
+                        minimum: Pages(1),
+                        maximum: None,
+                        shared: false,
+                    },
+                    base,
+                    len,
+                    move || {
+                        alloc::dealloc(address as *mut u8, layout);
+                        released.store(true, Ordering::SeqCst);
+                    },
+                )
+            }
+            .unwrap()
+        };
+        assert_eq!(memory.descriptor().maximum, Some(Pages(3)));
+        assert_eq!(memory.memory_type(), MemoryType::Dynamic);
+
+        // The guest and the host see the same bytes, wherever the
+        // memory grows to.
+        assert_eq!(memory.view::<u8>()[8].get(), 42);
+        memory.grow(Pages(2)).unwrap();
+        memory.view::<u8>()[Pages(2).bytes().0].set(7);
+        assert_eq!(unsafe { *base.add(Pages(2).bytes().0) }, 7);
+        assert!(memory.grow(Pages(1)).is_err());
+
+        drop(memory);
+        assert!(released.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_memory_in_unaligned_host_buffer() {
+        let mut buffer = vec![0u8; Pages(2).bytes().0];
+        let result = unsafe {
+            Memory::from_raw_parts(
+                MemoryDescriptor {
+                    minimum: Pages(1),
+                    maximum: None,
+                    shared: false,
+                },
+                buffer.as_mut_ptr().add(1),
+                Pages(1).bytes().0,
+                || {},
+            )
+        };
+        assert!(result.is_err());
+    }
 }
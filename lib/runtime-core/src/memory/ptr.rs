@@ -0,0 +1,345 @@
+//! A typed pointer into a linear memory.
+//!
+//! Host functions receive guest pointers as plain offsets. Wrapping one
+//! in a [`WasmPtr`] lets the host dereference it with bounds and
+//! alignment checks instead of casting raw pointers. A [`WasmStr`] or
+//! [`WasmSlice`] does the same for a pointer and a length, borrowing
+//! the string or values in place rather than copying them out.
+//!
+//! [`WasmPtr`]: struct.WasmPtr.html
+//! [`WasmStr`]: struct.WasmStr.html
+//! [`WasmSlice`]: struct.WasmSlice.html
+
+use crate::{
+    memory::Memory,
+    types::{Type, ValueType, WasmExternType},
+};
+use std::{cell::Cell, ffi::CStr, fmt, marker::PhantomData, mem, slice, str};
+
+/// The pointer points to a single value.
+pub struct Item;
+/// The pointer points to the start of an array of values.
+pub struct Array;
+
+/// A pointer to a `T`, or to an array of `T`s, in a linear memory.
+///
+/// Since it's an `i32` as far as WebAssembly is concerned, a `WasmPtr`
+/// can be used directly as a parameter or result of a host function.
+///
+/// # Usage:
+///
+/// ```
+/// # use wasmer_runtime_core::memory::{Memory, ptr::{Array, WasmPtr}};
+/// # use wasmer_runtime_core::types::MemoryDescriptor;
+/// # use wasmer_runtime_core::units::Pages;
+/// # let memory = Memory::new(MemoryDescriptor {
+/// #     minimum: Pages(1),
+/// #     maximum: None,
+/// #     shared: false,
+/// # }).unwrap();
+/// let ptr: WasmPtr<u32> = WasmPtr::new(8);
+/// ptr.deref(&memory).unwrap().set(42);
+/// assert_eq!(memory.view::<u32>()[2].get(), 42);
+///
+/// let array: WasmPtr<u32, Array> = WasmPtr::new(4);
+/// assert_eq!(array.deref(&memory, 1, 2).unwrap()[0].get(), 42);
+/// assert!(array.deref(&memory, 0, 1 << 20).is_none());
+/// ```
+#[repr(transparent)]
+pub struct WasmPtr<T: Copy, Ty = Item> {
+    offset: u32,
+    _phantom: PhantomData<(T, Ty)>,
+}
+
+impl<T: Copy, Ty> WasmPtr<T, Ty> {
+    pub fn new(offset: u32) -> Self {
+        Self {
+            offset,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The offset of the pointee in the linear memory.
+    pub fn offset(self) -> u32 {
+        self.offset
+    }
+}
+
+/// The `count` `T`s starting at `offset` in `memory`, if they are in
+/// bounds and correctly aligned.
+fn memory_cells<T: Copy>(memory: &Memory, offset: usize, count: usize) -> Option<&[Cell<T>]> {
+    let size = count.checked_mul(mem::size_of::<T>())?;
+    let end = offset.checked_add(size)?;
+    if end > memory.size().bytes().0 || offset % mem::align_of::<T>() != 0 {
+        return None;
+    }
+
+    unsafe {
+        let base = (*memory.vm_local_memory()).base;
+        Some(slice::from_raw_parts(
+            base.add(offset) as *const Cell<T>,
+            count,
+        ))
+    }
+}
+
+impl<T: Copy + ValueType> WasmPtr<T, Item> {
+    /// The value that this points to, or `None` if it's out of
+    /// bounds or misaligned.
+    pub fn deref<'a>(self, memory: &'a Memory) -> Option<&'a Cell<T>> {
+        memory_cells(memory, self.offset as usize, 1).map(|cells| &cells[0])
+    }
+}
+
+impl<T: Copy + ValueType> WasmPtr<T, Array> {
+    /// The `length` values that start `index` values into the array,
+    /// or `None` if any of them are out of bounds or misaligned.
+    pub fn deref<'a>(self, memory: &'a Memory, index: u32, length: u32) -> Option<&'a [Cell<T>]> {
+        let offset = (index as usize)
+            .checked_mul(mem::size_of::<T>())?
+            .checked_add(self.offset as usize)?;
+        memory_cells(memory, offset, length as usize)
+    }
+}
+
+impl WasmPtr<u8, Array> {
+    /// The `str_len` bytes that this points to, if they are in
+    /// bounds and valid UTF-8.
+    pub fn get_utf8_string<'a>(self, memory: &'a Memory, str_len: u32) -> Option<&'a str> {
+        let bytes = self.bytes(memory, str_len as usize)?;
+        str::from_utf8(bytes).ok()
+    }
+
+    /// The nul-terminated string that this points to, or `None`
+    /// if it isn't terminated before the end of the memory.
+    pub fn get_c_str<'a>(self, memory: &'a Memory) -> Option<&'a CStr> {
+        let memory_len = memory.size().bytes().0;
+        let offset = self.offset as usize;
+        if offset > memory_len {
+            return None;
+        }
+
+        let rest = self.bytes(memory, memory_len - offset)?;
+        let len = rest.iter().position(|&byte| byte == 0)?;
+        CStr::from_bytes_with_nul(&rest[..=len]).ok()
+    }
+
+    fn bytes<'a>(self, memory: &'a Memory, len: usize) -> Option<&'a [u8]> {
+        let cells = memory_cells::<u8>(memory, self.offset as usize, len)?;
+        // `Cell<u8>` has the same layout as `u8`.
+        Some(unsafe { slice::from_raw_parts(cells.as_ptr() as *const u8, cells.len()) })
+    }
+}
+
+/// A UTF-8 string in a linear memory, as the pointer and the length in
+/// bytes that a guest passes to a host function.
+///
+/// The string is borrowed from the memory for as long as the memory
+/// is, which for a host function is the rest of the call at most.
+///
+/// # Usage:
+///
+/// ```
+/// # use wasmer_runtime_core::memory::ptr::{Array, WasmPtr, WasmStr};
+/// # use wasmer_runtime_core::vm::Ctx;
+/// fn print(ctx: &mut Ctx, ptr: WasmPtr<u8, Array>, len: u32) -> i32 {
+///     match WasmStr::new(ptr, len).get(ctx.memory(0)) {
+///         Some(string) => {
+///             println!("{}", string);
+///             0
+///         }
+///         None => -1,
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WasmStr {
+    ptr: WasmPtr<u8, Array>,
+    len: u32,
+}
+
+impl WasmStr {
+    pub fn new(ptr: WasmPtr<u8, Array>, len: u32) -> Self {
+        Self { ptr, len }
+    }
+
+    /// The length of the string, in bytes.
+    pub fn len(self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+
+    /// The string, or `None` if it's out of bounds or isn't UTF-8.
+    pub fn get<'a>(self, memory: &'a Memory) -> Option<&'a str> {
+        self.ptr.get_utf8_string(memory, self.len)
+    }
+}
+
+/// A slice of `T`s in a linear memory, as the pointer and the number
+/// of values that a guest passes to a host function.
+///
+/// The values are borrowed from the memory for as long as the memory
+/// is, which for a host function is the rest of the call at most.
+///
+/// # Usage:
+///
+/// ```
+/// # use wasmer_runtime_core::memory::ptr::{Array, WasmPtr, WasmSlice};
+/// # use wasmer_runtime_core::vm::Ctx;
+/// fn sum(ctx: &mut Ctx, ptr: WasmPtr<u32, Array>, len: u32) -> u32 {
+///     let values = WasmSlice::new(ptr, len).get(ctx.memory(0)).unwrap_or(&[]);
+///     values.iter().map(|value| value.get()).sum()
+/// }
+/// ```
+pub struct WasmSlice<T: Copy> {
+    ptr: WasmPtr<T, Array>,
+    len: u32,
+}
+
+impl<T: Copy> WasmSlice<T> {
+    pub fn new(ptr: WasmPtr<T, Array>, len: u32) -> Self {
+        Self { ptr, len }
+    }
+
+    /// The number of values in the slice.
+    pub fn len(self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Copy + ValueType> WasmSlice<T> {
+    /// The values, or `None` if they're out of bounds or misaligned.
+    pub fn get<'a>(self, memory: &'a Memory) -> Option<&'a [Cell<T>]> {
+        self.ptr.deref(memory, 0, self.len)
+    }
+}
+
+impl<T: Copy> Clone for WasmSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Copy> Copy for WasmSlice<T> {}
+
+impl<T: Copy> PartialEq for WasmSlice<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr && self.len == other.len
+    }
+}
+
+impl<T: Copy> Eq for WasmSlice<T> {}
+
+impl<T: Copy> fmt::Debug for WasmSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WasmSlice({:#x}, {})", self.ptr.offset, self.len)
+    }
+}
+
+unsafe impl<T: Copy, Ty> WasmExternType for WasmPtr<T, Ty> {
+    const TYPE: Type = Type::I32;
+}
+
+impl<T: Copy, Ty> Clone for WasmPtr<T, Ty> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Copy, Ty> Copy for WasmPtr<T, Ty> {}
+
+impl<T: Copy, Ty> PartialEq for WasmPtr<T, Ty> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T: Copy, Ty> Eq for WasmPtr<T, Ty> {}
+
+impl<T: Copy, Ty> fmt::Debug for WasmPtr<T, Ty> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WasmPtr({:#x})", self.offset)
+    }
+}
+
+#[cfg(test)]
+mod ptr_tests {
+    use super::*;
+    use crate::{types::MemoryDescriptor, units::Pages};
+
+    fn memory() -> Memory {
+        Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_deref_checks_bounds_and_alignment() {
+        let memory = memory();
+        let last = Pages(1).bytes().0 as u32 - 4;
+
+        assert!(WasmPtr::<u32>::new(last).deref(&memory).is_some());
+        assert!(WasmPtr::<u32>::new(last + 4).deref(&memory).is_none());
+        assert!(WasmPtr::<u32>::new(2).deref(&memory).is_none());
+
+        let array: WasmPtr<u32, Array> = WasmPtr::new(last - 4);
+        assert_eq!(array.deref(&memory, 0, 2).unwrap().len(), 2);
+        assert!(array.deref(&memory, 1, 2).is_none());
+        assert!(array.deref(&memory, u32::max_value(), 1).is_none());
+    }
+
+    #[test]
+    fn test_strings() {
+        let memory = memory();
+        for (cell, &byte) in memory.view::<u8>()[16..].iter().zip(b"hello\0") {
+            cell.set(byte);
+        }
+
+        let ptr: WasmPtr<u8, Array> = WasmPtr::new(16);
+        assert_eq!(ptr.get_utf8_string(&memory, 5), Some("hello"));
+        assert_eq!(ptr.get_c_str(&memory).unwrap().to_bytes(), b"hello");
+
+        // There is no nul after the last byte of the memory.
+        let last = Pages(1).bytes().0 as u32 - 1;
+        memory.view::<u8>()[last as usize].set(b'x');
+        assert!(WasmPtr::<u8, Array>::new(last).get_c_str(&memory).is_none());
+    }
+
+    #[test]
+    fn test_str_and_slice() {
+        let memory = memory();
+        for (cell, &byte) in memory.view::<u8>()[16..].iter().zip(b"hi\xff") {
+            cell.set(byte);
+        }
+        memory.view::<u32>()[8].set(7);
+        memory.view::<u32>()[9].set(9);
+
+        let bytes: WasmPtr<u8, Array> = WasmPtr::new(16);
+        assert_eq!(WasmStr::new(bytes, 2).get(&memory), Some("hi"));
+        assert_eq!(WasmStr::new(bytes, 3).get(&memory), None);
+        assert_eq!(
+            WasmStr::new(WasmPtr::new(u32::max_value()), 2).get(&memory),
+            None
+        );
+
+        let values = WasmSlice::<u32>::new(WasmPtr::new(32), 2);
+        let cells = values.get(&memory).unwrap();
+        assert_eq!(cells.iter().map(Cell::get).collect::<Vec<_>>(), vec![7, 9]);
+        // The slice is the memory itself.
+        cells[0].set(8);
+        assert_eq!(memory.view::<u32>()[8].get(), 8);
+        assert!(WasmSlice::<u32>::new(WasmPtr::new(34), 2)
+            .get(&memory)
+            .is_none());
+    }
+}
@@ -1,12 +1,9 @@
 use crate::error::GrowError;
 use crate::{
-    error::CreationError,
-    memory::static_::{SAFE_STATIC_GUARD_SIZE, SAFE_STATIC_HEAP_SIZE},
-    sys,
-    types::MemoryDescriptor,
-    units::Pages,
+    error::CreationError, memory::GrowCallback, pool, sys, types::MemoryDescriptor, units::Pages,
     vm,
 };
+use std::{mem, rc::Rc};
 
 /// This is an internal-only api.
 ///
@@ -23,6 +20,7 @@ pub struct StaticMemory {
     memory: sys::Memory,
     current: Pages,
     max: Option<Pages>,
+    grow_callback: Option<Rc<GrowCallback>>,
 }
 
 impl StaticMemory {
@@ -31,8 +29,7 @@ impl StaticMemory {
         local: &mut vm::LocalMemory,
     ) -> Result<Box<Self>, CreationError> {
         let memory = {
-            let mut memory = sys::Memory::with_size(SAFE_STATIC_HEAP_SIZE + SAFE_STATIC_GUARD_SIZE)
-                .map_err(|_| CreationError::UnableToCreateMemory)?;
+            let mut memory = pool::take_static_memory()?;
             if desc.minimum != Pages(0) {
                 unsafe {
                     memory
@@ -48,6 +45,7 @@ impl StaticMemory {
             memory,
             current: desc.minimum,
             max: desc.maximum,
+            grow_callback: None,
         });
         let storage_ptr: *mut StaticMemory = &mut *storage;
 
@@ -96,6 +94,21 @@ impl StaticMemory {
         Ok(old_pages)
     }
 
+    /// Map `image` copy-on-write over the start of this memory, which
+    /// must already be at least as large as the image.
+    pub(crate) fn map_image(&mut self, image: &sys::MemoryImage) -> Result<(), GrowError> {
+        assert!(image.size() <= self.current.bytes().0);
+        unsafe { self.memory.map_image(image) }.map_err(|e| e.into())
+    }
+
+    pub(crate) fn grow_callback(&self) -> Option<Rc<GrowCallback>> {
+        self.grow_callback.clone()
+    }
+
+    pub(crate) fn set_grow_callback(&mut self, callback: Option<Rc<GrowCallback>>) {
+        self.grow_callback = callback;
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { &self.memory.as_slice()[0..self.current.bytes().0] }
     }
@@ -104,3 +117,13 @@ impl StaticMemory {
         unsafe { &mut self.memory.as_slice_mut()[0..self.current.bytes().0] }
     }
 }
+
+impl Drop for StaticMemory {
+    fn drop(&mut self) {
+        // Hand the reservation back to the pool rather than unmapping it.
+        if let Ok(empty) = sys::Memory::with_size(0) {
+            let memory = mem::replace(&mut self.memory, empty);
+            pool::return_static_memory(memory, self.current.bytes().0);
+        }
+    }
+}
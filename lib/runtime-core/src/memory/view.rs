@@ -1,7 +1,7 @@
 use super::atomic::{Atomic, IntCast};
 use crate::types::ValueType;
 
-use std::{cell::Cell, marker::PhantomData, ops::Deref, slice};
+use std::{cell::Cell, marker::PhantomData, ops::Deref, ptr, slice};
 
 pub trait Atomicity {}
 pub struct Atomically;
@@ -19,10 +19,48 @@ impl<'a, T> MemoryView<'a, T, NonAtomically>
 where
     T: ValueType,
 {
-    pub(super) unsafe fn new(ptr: *mut T, length: u32) -> Self {
+    pub(super) unsafe fn new(ptr: *mut T, length: usize) -> Self {
         Self {
             ptr,
-            length: length as usize,
+            length,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Copy the values in this view into `buffer`, stopping at
+    /// the end of whichever is shorter.
+    pub fn copy_to(&self, buffer: &mut [T]) {
+        let len = self.length.min(buffer.len());
+        unsafe { ptr::copy(self.ptr, buffer.as_mut_ptr(), len) };
+    }
+
+    /// Copy the values in `buffer` into this view, stopping at
+    /// the end of whichever is shorter.
+    pub fn copy_from(&self, buffer: &[T]) {
+        let len = self.length.min(buffer.len());
+        unsafe { ptr::copy(buffer.as_ptr(), self.ptr, len) };
+    }
+}
+
+impl<'a, T, A: Atomicity> MemoryView<'a, T, A> {
+    /// A view of the values from `start` up to, but not including,
+    /// `end`.
+    ///
+    /// # Panics:
+    ///
+    /// Panics if `start > end` or if `end` is past the end of this view.
+    pub fn subarray(&self, start: usize, end: usize) -> MemoryView<'a, T, A> {
+        assert!(
+            start <= end && end <= self.length,
+            "subarray {}..{} is out of bounds of a view of length {}",
+            start,
+            end,
+            self.length,
+        );
+
+        MemoryView {
+            ptr: unsafe { self.ptr.add(start) },
+            length: end - start,
             _phantom: PhantomData,
         }
     }
@@ -0,0 +1,158 @@
+//! The futex-like wait queues behind the `wait` and `notify`
+//! operators of the threads proposal.
+//!
+//! Waiters are queued on the address that they wait on, in the
+//! order that they started waiting, and are woken up in that order.
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    static ref WAITERS: Mutex<HashMap<usize, VecDeque<Arc<Waiter>>>> = Mutex::new(HashMap::new());
+}
+
+struct Waiter {
+    notified: Mutex<bool>,
+    condvar: Condvar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The waiter was woken up by a notify.
+    Ok = 0,
+    /// The value at the address wasn't the expected value.
+    NotEqual = 1,
+    /// The timeout expired before the waiter was woken up.
+    TimedOut = 2,
+}
+
+/// Wait on `addr` until it's notified or `timeout` expires.
+///
+/// `is_expected` is called while no notify on any address can
+/// happen, so a notify that follows a change to the value at
+/// `addr` can't be missed.
+pub fn wait(
+    addr: usize,
+    is_expected: impl FnOnce() -> bool,
+    timeout: Option<Duration>,
+) -> WaitResult {
+    let waiter = {
+        let mut waiters = WAITERS.lock();
+
+        if !is_expected() {
+            return WaitResult::NotEqual;
+        }
+
+        let waiter = Arc::new(Waiter {
+            notified: Mutex::new(false),
+            condvar: Condvar::new(),
+        });
+        waiters
+            .entry(addr)
+            .or_insert_with(VecDeque::new)
+            .push_back(Arc::clone(&waiter));
+        waiter
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut notified = waiter.notified.lock();
+    while !*notified {
+        match deadline {
+            Some(deadline) => {
+                if waiter
+                    .condvar
+                    .wait_until(&mut notified, deadline)
+                    .timed_out()
+                {
+                    break;
+                }
+            }
+            None => waiter.condvar.wait(&mut notified),
+        }
+    }
+
+    if *notified {
+        return WaitResult::Ok;
+    }
+    drop(notified);
+
+    // Timed out, but a notify may have dequeued us in the meantime.
+    let mut waiters = WAITERS.lock();
+    if *waiter.notified.lock() {
+        return WaitResult::Ok;
+    }
+    if let Some(queue) = waiters.get_mut(&addr) {
+        queue.retain(|other| !Arc::ptr_eq(other, &waiter));
+        if queue.is_empty() {
+            waiters.remove(&addr);
+        }
+    }
+    WaitResult::TimedOut
+}
+
+/// Wake up at most `count` of the waiters on `addr`,
+/// returning how many were woken up.
+pub fn notify(addr: usize, count: u32) -> u32 {
+    let mut waiters = WAITERS.lock();
+
+    let queue = match waiters.get_mut(&addr) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+
+    let mut woken = 0;
+    while woken < count {
+        let waiter = match queue.pop_front() {
+            Some(waiter) => waiter,
+            None => break,
+        };
+        *waiter.notified.lock() = true;
+        waiter.condvar.notify_one();
+        woken += 1;
+    }
+
+    if queue.is_empty() {
+        waiters.remove(&addr);
+    }
+
+    woken
+}
+
+#[cfg(test)]
+mod waiter_tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_wait_not_equal_and_timeout() {
+        let addr = 0x1000;
+        assert_eq!(wait(addr, || false, None), WaitResult::NotEqual);
+        assert_eq!(
+            wait(addr, || true, Some(Duration::from_millis(1))),
+            WaitResult::TimedOut
+        );
+        assert_eq!(notify(addr, 1), 0);
+    }
+
+    #[test]
+    fn test_notify_wakes_waiter() {
+        let addr = 0x2000;
+        let waiting = thread::spawn(move || wait(addr, || true, None));
+
+        let mut woken = 0;
+        while woken == 0 {
+            thread::yield_now();
+            woken = notify(addr, u32::max_value());
+        }
+
+        assert_eq!(woken, 1);
+        assert_eq!(waiting.join().unwrap(), WaitResult::Ok);
+    }
+}
@@ -0,0 +1,297 @@
+//! Metering of the WebAssembly code that instances run, with gas.
+//!
+//! Backends instrument the modules that they compile with a
+//! [`CompilerConfig`] whose `metering` is set, so that running them
+//! uses up the points of the instance running them. Every operator
+//! costs the number of points that the configured [`CostFunction`]
+//! says it does, and the instances of a module start out with the
+//! initial points that it was compiled with. When they run out, the
+//! call traps with [`RuntimeError::OutOfGas`].
+//!
+//! Checking the points after every operator would be expensive, so
+//! they're charged a region at a time instead: the operators of a
+//! function outside of its loops when it's called, and those of a loop
+//! outside of the loops nested in it at the start of every iteration.
+//! A region is charged for in full even when a branch leaves it early,
+//! which makes the points used the same no matter which backend
+//! compiled the module.
+//!
+//! The points of an instance are read and changed with
+//! [`Instance::get_remaining_points`] and
+//! [`Instance::set_remaining_points`], and those that it has used up
+//! are read with [`Instance::get_points_used`].
+//!
+//! [`CompilerConfig`]: ../backend/struct.CompilerConfig.html
+//! [`CostFunction`]: type.CostFunction.html
+//! [`RuntimeError::OutOfGas`]: ../error/enum.RuntimeError.html#variant.OutOfGas
+//! [`Instance::get_remaining_points`]: ../struct.Instance.html#method.get_remaining_points
+//! [`Instance::set_remaining_points`]: ../struct.Instance.html#method.set_remaining_points
+//! [`Instance::get_points_used`]: ../struct.Instance.html#method.get_points_used
+
+use crate::{
+    error::{CompileError, CompileResult},
+    structures::Map,
+    types::LocalFuncIndex,
+};
+use wasmparser::{BinaryReaderError, FunctionBody, ModuleReader, SectionCode};
+
+pub use wasmparser::Operator;
+
+/// The number of points that running an operator costs.
+pub type CostFunction = fn(&Operator) -> u64;
+
+/// Charge a point for every operator.
+pub fn unit_cost(_: &Operator) -> u64 {
+    1
+}
+
+/// Charge operators roughly by how long they take to run: a point for
+/// most of them, more for multiplications, divisions, memory accesses
+/// and calls, and the most for growing a memory.
+pub fn weighted_cost(op: &Operator) -> u64 {
+    match op {
+        Operator::I32Mul | Operator::I64Mul | Operator::F32Mul | Operator::F64Mul => 3,
+        Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::F32Div
+        | Operator::F64Div
+        | Operator::F32Sqrt
+        | Operator::F64Sqrt => 10,
+        Operator::I32Load { .. }
+        | Operator::I64Load { .. }
+        | Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::I32Load8S { .. }
+        | Operator::I32Load8U { .. }
+        | Operator::I32Load16S { .. }
+        | Operator::I32Load16U { .. }
+        | Operator::I64Load8S { .. }
+        | Operator::I64Load8U { .. }
+        | Operator::I64Load16S { .. }
+        | Operator::I64Load16U { .. }
+        | Operator::I64Load32S { .. }
+        | Operator::I64Load32U { .. }
+        | Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. } => 3,
+        Operator::Call { .. } => 10,
+        Operator::CallIndirect { .. } => 15,
+        Operator::MemoryGrow { .. } => 1000,
+        _ => 1,
+    }
+}
+
+/// How a module is metered.
+#[derive(Clone, Copy)]
+pub struct MeteringConfig {
+    /// The points that instances start out with, which the module
+    /// records as [`ModuleInfo::initial_points`].
+    ///
+    /// [`ModuleInfo::initial_points`]: ../module/struct.ModuleInfo.html#structfield.initial_points
+    pub initial_points: u64,
+    pub cost_function: CostFunction,
+}
+
+/// The points that a new instance of a module compiled with
+/// `initial_points` starts out with.
+pub(crate) fn initial_points(initial_points: Option<u64>) -> i64 {
+    match initial_points {
+        Some(points) => clamp_points(points),
+        None => i64::max_value(),
+    }
+}
+
+/// Points are kept as an `i64` in the `vm::Ctx`, which goes negative
+/// when they run out.
+pub(crate) fn clamp_points(points: u64) -> i64 {
+    points.min(i64::max_value() as u64) as i64
+}
+
+/// The points charged for the regions of a function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionCosts {
+    /// Charged when the function is called.
+    pub entry: u64,
+    /// The loops of the function, in the order that they appear in.
+    pub loops: Vec<LoopCost>,
+}
+
+/// The points charged for a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopCost {
+    /// The offset of the `loop` operator from the start of the
+    /// function body.
+    pub offset: usize,
+    /// Charged at the start of every iteration.
+    pub cost: u64,
+}
+
+impl FunctionCosts {
+    fn new(body: FunctionBody, cost_function: CostFunction) -> Result<Self, BinaryReaderError> {
+        let start = body.get_binary_reader().original_position();
+        let mut entry = 0;
+        let mut loops: Vec<LoopCost> = vec![];
+        // The region that each of the open blocks is in, where `None`
+        // is the entry region and `Some` the index of a loop.
+        let mut blocks = vec![];
+        let mut region: Option<usize> = None;
+
+        let mut operators = body.get_operators_reader()?;
+        while !operators.eof() {
+            let offset = operators.original_position() - start;
+            let op = operators.read()?;
+            let cost = match region {
+                None => &mut entry,
+                Some(index) => &mut loops[index].cost,
+            };
+            // Never more than the most points an instance can have.
+            *cost = cost
+                .saturating_add(cost_function(&op))
+                .min(i64::max_value() as u64);
+
+            match op {
+                Operator::Block { .. } | Operator::If { .. } => blocks.push(region),
+                Operator::Loop { .. } => {
+                    blocks.push(region);
+                    loops.push(LoopCost { offset, cost: 0 });
+                    region = Some(loops.len() - 1);
+                }
+                Operator::End => {
+                    if let Some(outer) = blocks.pop() {
+                        region = outer;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(FunctionCosts { entry, loops })
+    }
+
+    /// The points charged for the loop with the given index, counting
+    /// from zero in the order that the loops of the function appear in.
+    pub fn loop_cost(&self, index: usize) -> u64 {
+        self.loops.get(index).map(|l| l.cost).unwrap_or(0)
+    }
+
+    /// The points charged for the loop whose `loop` operator is at
+    /// `offset` from the start of the function body.
+    pub fn loop_cost_at(&self, offset: usize) -> u64 {
+        self.loops
+            .iter()
+            .find(|l| l.offset == offset)
+            .map(|l| l.cost)
+            .unwrap_or(0)
+    }
+}
+
+/// The costs of every local function of the module in `wasm`.
+pub fn function_costs(
+    wasm: &[u8],
+    cost_function: CostFunction,
+) -> CompileResult<Map<LocalFuncIndex, FunctionCosts>> {
+    read_function_costs(wasm, cost_function).map_err(|e| CompileError::ValidationError {
+        msg: e.message.to_string(),
+    })
+}
+
+fn read_function_costs(
+    wasm: &[u8],
+    cost_function: CostFunction,
+) -> Result<Map<LocalFuncIndex, FunctionCosts>, BinaryReaderError> {
+    let mut costs = Map::new();
+    let mut reader = ModuleReader::new(wasm)?;
+    while !reader.eof() {
+        let section = reader.read()?;
+        if let SectionCode::Code = section.code {
+            let mut code_reader = section.get_code_section_reader()?;
+            for _ in 0..code_reader.get_count() {
+                let body = code_reader.read()?;
+                costs.push(FunctionCosts::new(body, cost_function)?);
+            }
+        }
+    }
+    Ok(costs)
+}
+
+#[cfg(test)]
+mod metering_tests {
+    use super::*;
+    use crate::structures::TypedIndex;
+
+    #[test]
+    fn test_function_costs() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // (type (func))
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // (func (type 0))
+            0x03, 0x02, 0x01, 0x00,
+            0x0a, 0x0f, 0x01, 0x0d, 0x00,
+            // i32.const 1, drop
+            0x41, 0x01, 0x1a,
+            // loop, nop, block, nop, end, end
+            0x03, 0x40, 0x01, 0x02, 0x40, 0x01, 0x0b, 0x0b,
+            // end
+            0x0b,
+        ];
+
+        let costs = function_costs(&wasm, unit_cost).unwrap();
+        assert_eq!(costs.len(), 1);
+        let costs = &costs[LocalFuncIndex::new(0)];
+        assert_eq!(costs.entry, 4);
+        assert_eq!(costs.loops, vec![LoopCost { offset: 4, cost: 5 }]);
+        assert_eq!(costs.loop_cost(0), 5);
+        assert_eq!(costs.loop_cost_at(4), 5);
+        assert_eq!(costs.loop_cost(1), 0);
+    }
+
+    #[test]
+    fn test_initial_points() {
+        assert_eq!(initial_points(None), i64::max_value());
+        assert_eq!(initial_points(Some(1000)), 1000);
+        assert_eq!(initial_points(Some(u64::max_value())), i64::max_value());
+    }
+
+    #[test]
+    fn test_weighted_cost() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // (type (func (param i32) (result i32)))
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f,
+            // (func (type 0))
+            0x03, 0x02, 0x01, 0x00,
+            // (memory 1)
+            0x05, 0x03, 0x01, 0x00, 0x01,
+            0x0a, 0x0f, 0x01, 0x0d, 0x00,
+            // get_local 0, i32.load, get_local 0, i32.div_u,
+            0x20, 0x00, 0x28, 0x02, 0x00, 0x20, 0x00, 0x6e,
+            // i32.const 1, i32.add
+            0x41, 0x01, 0x6a,
+            // end
+            0x0b,
+        ];
+
+        let costs = function_costs(&wasm, unit_cost).unwrap();
+        assert_eq!(costs[LocalFuncIndex::new(0)].entry, 7);
+        let costs = function_costs(&wasm, weighted_cost).unwrap();
+        assert_eq!(
+            costs[LocalFuncIndex::new(0)].entry,
+            1 + 3 + 1 + 10 + 1 + 1 + 1
+        );
+    }
+}
@@ -0,0 +1,640 @@
+//! Middleware that transform the code of functions before it's compiled.
+//!
+//! Once [`set_middleware_chain`] has been called, the operators of every
+//! function of the modules that are compiled from then on are fed
+//! through a chain of [`FunctionMiddleware`], each of which pushes the
+//! events that it's fed, or others in their place, to the next one. A
+//! middleware can insert instructions, rewrite or drop operators, and
+//! attach metadata to functions, which can be read back from the
+//! compiled module with [`read_metadata`].
+//!
+//! The transformation happens before a backend sees the module, so a
+//! middleware works the same with every backend.
+//!
+//! The API is versioned by [`API_VERSION`]. It only changes in ways that
+//! break middleware, such as adding variants to [`Event`] or
+//! [`Instruction`], along with that version, and a middleware written
+//! for another version fails to compile modules instead of
+//! misbehaving.
+//!
+//! Middleware can only refer to the functions, globals and locals that
+//! a module already has.
+//!
+//! # Usage:
+//!
+//! ```
+//! use wasmer_runtime_core::middleware::{
+//!     self, Event, EventSink, FunctionMiddleware, Instruction, Operator, API_VERSION,
+//! };
+//!
+//! /// Puts a `nop` before every call.
+//! struct NopBeforeCalls;
+//!
+//! impl FunctionMiddleware for NopBeforeCalls {
+//!     fn api_version(&self) -> u32 {
+//!         API_VERSION
+//!     }
+//!
+//!     fn feed_event<'a>(
+//!         &mut self,
+//!         event: Event<'a>,
+//!         sink: &mut EventSink<'a>,
+//!     ) -> Result<(), String> {
+//!         if let Event::Wasm(Operator::Call { .. }, _) = event {
+//!             sink.push(Event::Insert(Instruction::Nop));
+//!         }
+//!         sink.push(event);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! middleware::set_middleware_chain(vec![Box::new(|| {
+//!     Box::new(NopBeforeCalls) as Box<dyn FunctionMiddleware>
+//! })]);
+//! ```
+//!
+//! [`set_middleware_chain`]: fn.set_middleware_chain.html
+//! [`FunctionMiddleware`]: trait.FunctionMiddleware.html
+//! [`read_metadata`]: fn.read_metadata.html
+//! [`API_VERSION`]: constant.API_VERSION.html
+//! [`Event`]: enum.Event.html
+//! [`Instruction`]: enum.Instruction.html
+
+use crate::{
+    error::{CompileError, CompileResult},
+    module::Module,
+    structures::TypedIndex,
+    types::LocalFuncIndex,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, ops::Range};
+use wasmparser::{BinaryReaderError, CodeSectionReader};
+
+pub use wasmparser::Operator;
+
+/// The version of the middleware API.
+pub const API_VERSION: u32 = 1;
+
+pub(crate) const CUSTOM_SECTION: u8 = 0;
+pub(crate) const CODE_SECTION: u8 = 10;
+
+/// Something that happens in the code of a function.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// An operator of the function, as it was read, and its encoding.
+    Wasm(Operator<'a>, &'a [u8]),
+    /// An instruction that a middleware inserted.
+    Insert(Instruction),
+}
+
+/// An instruction that a middleware can insert into a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Unreachable,
+    Nop,
+    /// Starts a block without results.
+    Block,
+    /// Starts a loop without results.
+    Loop,
+    /// Starts an `if` without results.
+    If,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    GetLocal(u32),
+    SetLocal(u32),
+    TeeLocal(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    I32Const(i32),
+    I64Const(i64),
+    I32Eqz,
+    I32LtS,
+    I32Add,
+    I32Sub,
+    I64Eqz,
+    I64LtS,
+    I64Add,
+    I64Sub,
+    /// Any other instruction, already encoded.
+    Raw(Vec<u8>),
+}
+
+impl Instruction {
+    fn encode(&self, out: &mut Vec<u8>) {
+        use self::Instruction::*;
+
+        match *self {
+            Unreachable => out.push(0x00),
+            Nop => out.push(0x01),
+            Block => out.extend_from_slice(&[0x02, 0x40]),
+            Loop => out.extend_from_slice(&[0x03, 0x40]),
+            If => out.extend_from_slice(&[0x04, 0x40]),
+            Else => out.push(0x05),
+            End => out.push(0x0b),
+            Br(depth) => encode_with_u32(out, 0x0c, depth),
+            BrIf(depth) => encode_with_u32(out, 0x0d, depth),
+            Return => out.push(0x0f),
+            Call(index) => encode_with_u32(out, 0x10, index),
+            Drop => out.push(0x1a),
+            GetLocal(index) => encode_with_u32(out, 0x20, index),
+            SetLocal(index) => encode_with_u32(out, 0x21, index),
+            TeeLocal(index) => encode_with_u32(out, 0x22, index),
+            GetGlobal(index) => encode_with_u32(out, 0x23, index),
+            SetGlobal(index) => encode_with_u32(out, 0x24, index),
+            I32Const(value) => {
+                out.push(0x41);
+                write_signed(out, i64::from(value));
+            }
+            I64Const(value) => {
+                out.push(0x42);
+                write_signed(out, value);
+            }
+            I32Eqz => out.push(0x45),
+            I32LtS => out.push(0x48),
+            I64Eqz => out.push(0x50),
+            I64LtS => out.push(0x53),
+            I32Add => out.push(0x6a),
+            I32Sub => out.push(0x6b),
+            I64Add => out.push(0x7c),
+            I64Sub => out.push(0x7d),
+            Raw(ref bytes) => out.extend_from_slice(bytes),
+        }
+    }
+}
+
+fn encode_with_u32(out: &mut Vec<u8>, opcode: u8, immediate: u32) {
+    out.push(opcode);
+    write_u32(out, immediate);
+}
+
+/// Where a middleware pushes the events that it produces.
+pub struct EventSink<'a> {
+    events: Vec<Event<'a>>,
+    metadata: Vec<(String, Vec<u8>)>,
+}
+
+impl<'a> EventSink<'a> {
+    fn new() -> Self {
+        EventSink {
+            events: vec![],
+            metadata: vec![],
+        }
+    }
+
+    /// Pass an event on to the next middleware.
+    pub fn push(&mut self, event: Event<'a>) {
+        self.events.push(event);
+    }
+
+    /// Attach `data` to the function under `name`. Attaching more than
+    /// once keeps every piece of data.
+    pub fn attach_metadata(&mut self, name: &str, data: &[u8]) {
+        self.metadata.push((name.to_string(), data.to_vec()));
+    }
+}
+
+/// A transformer of the code of functions.
+///
+/// Every module is compiled with new middleware, so they can keep
+/// state about the module that they're transforming.
+pub trait FunctionMiddleware {
+    /// The version of the API that the middleware was written for,
+    /// which is [`API_VERSION`] at the time.
+    ///
+    /// [`API_VERSION`]: constant.API_VERSION.html
+    fn api_version(&self) -> u32;
+
+    /// Called before the first event of every function, to insert the
+    /// instructions that it should start with.
+    fn begin_function(
+        &mut self,
+        _index: LocalFuncIndex,
+        _sink: &mut EventSink,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Transform an event. Pushing it to `sink` unchanged leaves it as
+    /// it is, and not pushing it drops it.
+    fn feed_event<'a>(&mut self, event: Event<'a>, sink: &mut EventSink<'a>) -> Result<(), String>;
+}
+
+/// Creates the middleware that a module is compiled with.
+pub type MiddlewareFactory = Box<dyn Fn() -> Box<dyn FunctionMiddleware> + Send + Sync>;
+
+lazy_static! {
+    static ref CHAIN: Mutex<Vec<MiddlewareFactory>> = Mutex::new(vec![]);
+}
+
+/// Set the middleware that the modules compiled from now on are fed
+/// through, in order, or stop transforming them with an empty chain.
+///
+/// Modules that have already been compiled, including those in a cache,
+/// keep the transformations that they were compiled with.
+pub fn set_middleware_chain(chain: Vec<MiddlewareFactory>) {
+    *CHAIN.lock() = chain;
+}
+
+/// The metadata that middleware attached to the functions of `module`
+/// under `name`, in the order that it was attached in.
+pub fn read_metadata<'m>(module: &'m Module, name: &str) -> Vec<(LocalFuncIndex, &'m [u8])> {
+    module
+        .custom_sections(name)
+        .unwrap_or(&[])
+        .iter()
+        .flat_map(|section| parse_metadata(section))
+        .collect()
+}
+
+/// Feed the module in `wasm` through the middleware chain, if there is
+/// one.
+pub(crate) fn transform(wasm: &[u8]) -> CompileResult<Option<Vec<u8>>> {
+    let chain = CHAIN.lock();
+    if chain.is_empty() {
+        return Ok(None);
+    }
+    let mut middlewares: Vec<_> = chain.iter().map(|factory| factory()).collect();
+    drop(chain);
+    transform_with(wasm, &mut middlewares).map(Some)
+}
+
+fn transform_with(
+    wasm: &[u8],
+    middlewares: &mut [Box<dyn FunctionMiddleware>],
+) -> CompileResult<Vec<u8>> {
+    for middleware in middlewares.iter() {
+        if middleware.api_version() != API_VERSION {
+            return Err(CompileError::InternalError {
+                msg: format!(
+                    "middleware written for API version {} can't be used with version {}",
+                    middleware.api_version(),
+                    API_VERSION
+                ),
+            });
+        }
+    }
+
+    let mut out = wasm[..8].to_vec();
+    let mut metadata = BTreeMap::new();
+    for section in sections(wasm)? {
+        if section.id == CODE_SECTION {
+            let code = transform_code(
+                wasm,
+                section.payload.start,
+                section.payload.end,
+                middlewares,
+                &mut metadata,
+            )?;
+            write_section(&mut out, CODE_SECTION, &code);
+        } else {
+            out.extend_from_slice(&wasm[section.range()]);
+        }
+    }
+
+    for (name, entries) in metadata {
+        let mut payload = vec![];
+        write_name(&mut payload, &name);
+        payload.extend_from_slice(&entries);
+        write_section(&mut out, CUSTOM_SECTION, &payload);
+    }
+
+    Ok(out)
+}
+
+/// A section of a module.
+pub(crate) struct Section {
+    pub id: u8,
+    /// Where the section starts, at its id.
+    pub start: usize,
+    pub payload: Range<usize>,
+}
+
+impl Section {
+    /// The whole section, from its id to the end of its payload.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.payload.end
+    }
+}
+
+/// The sections of the module in `wasm`, in order.
+pub(crate) fn sections(wasm: &[u8]) -> CompileResult<Vec<Section>> {
+    let invalid = || CompileError::ValidationError {
+        msg: "malformed module".to_string(),
+    };
+    if wasm.len() < 8 {
+        return Err(invalid());
+    }
+
+    let mut sections = vec![];
+    let mut position = 8;
+    while position < wasm.len() {
+        let start = position;
+        let id = wasm[position];
+        position += 1;
+        let size = read_u32(wasm, &mut position).ok_or_else(invalid)? as usize;
+        let payload = position;
+        position = payload.checked_add(size).ok_or_else(invalid)?;
+        if position > wasm.len() {
+            return Err(invalid());
+        }
+        sections.push(Section {
+            id,
+            start,
+            payload: payload..position,
+        });
+    }
+    Ok(sections)
+}
+
+pub(crate) fn write_section(out: &mut Vec<u8>, id: u8, payload: &[u8]) {
+    out.push(id);
+    write_u32(out, payload.len() as u32);
+    out.extend_from_slice(payload);
+}
+
+pub(crate) fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// A function body, as the declarations of its locals and its
+/// operators, along with their encodings.
+struct Body<'a> {
+    locals: &'a [u8],
+    operators: Vec<(Operator<'a>, &'a [u8])>,
+}
+
+fn read_bodies(wasm: &[u8], start: usize, end: usize) -> Result<Vec<Body>, BinaryReaderError> {
+    let mut bodies = vec![];
+    let mut reader = CodeSectionReader::new(&wasm[start..end], start)?;
+    for _ in 0..reader.get_count() {
+        let body = reader.read()?;
+        let body_start = body.get_binary_reader().original_position();
+        let mut reader = body.get_operators_reader()?;
+        let locals = &wasm[body_start..reader.original_position()];
+
+        let mut operators = vec![];
+        while !reader.eof() {
+            let op_start = reader.original_position();
+            let op = reader.read()?;
+            operators.push((op, &wasm[op_start..reader.original_position()]));
+        }
+        bodies.push(Body { locals, operators });
+    }
+    Ok(bodies)
+}
+
+pub(crate) fn transform_code(
+    wasm: &[u8],
+    start: usize,
+    end: usize,
+    middlewares: &mut [Box<dyn FunctionMiddleware>],
+    metadata: &mut BTreeMap<String, Vec<u8>>,
+) -> CompileResult<Vec<u8>> {
+    let bodies = read_bodies(wasm, start, end).map_err(|e| CompileError::ValidationError {
+        msg: e.message.to_string(),
+    })?;
+
+    let mut code = vec![];
+    write_u32(&mut code, bodies.len() as u32);
+    for (index, body) in bodies.into_iter().enumerate() {
+        let index = LocalFuncIndex::new(index);
+        // The declarations of the locals are kept as they are.
+        let mut encoded = body.locals.to_vec();
+
+        for i in 0..middlewares.len() {
+            let mut sink = EventSink::new();
+            middlewares[i]
+                .begin_function(index, &mut sink)
+                .map_err(middleware_error)?;
+            collect_metadata(index, &mut sink, metadata);
+            let events = feed(&mut middlewares[i + 1..], sink.events, index, metadata)?;
+            encode_events(events, &mut encoded);
+        }
+
+        for (op, bytes) in body.operators {
+            let events = feed(middlewares, vec![Event::Wasm(op, bytes)], index, metadata)?;
+            encode_events(events, &mut encoded);
+        }
+
+        write_u32(&mut code, encoded.len() as u32);
+        code.extend_from_slice(&encoded);
+    }
+
+    Ok(code)
+}
+
+/// Feed `events` through each of `middlewares` in turn.
+fn feed<'a>(
+    middlewares: &mut [Box<dyn FunctionMiddleware>],
+    mut events: Vec<Event<'a>>,
+    index: LocalFuncIndex,
+    metadata: &mut BTreeMap<String, Vec<u8>>,
+) -> CompileResult<Vec<Event<'a>>> {
+    for middleware in middlewares.iter_mut() {
+        let mut sink = EventSink::new();
+        for event in events {
+            middleware
+                .feed_event(event, &mut sink)
+                .map_err(middleware_error)?;
+        }
+        collect_metadata(index, &mut sink, metadata);
+        events = sink.events;
+    }
+    Ok(events)
+}
+
+fn encode_events(events: Vec<Event>, out: &mut Vec<u8>) {
+    for event in events {
+        match event {
+            Event::Wasm(_, bytes) => out.extend_from_slice(bytes),
+            Event::Insert(instruction) => instruction.encode(out),
+        }
+    }
+}
+
+/// Metadata is kept in a custom section per name, as a sequence of
+/// entries that are each the index of the local function, the length of
+/// the data and the data.
+fn collect_metadata(
+    index: LocalFuncIndex,
+    sink: &mut EventSink,
+    metadata: &mut BTreeMap<String, Vec<u8>>,
+) {
+    for (name, data) in sink.metadata.drain(..) {
+        let entries = metadata.entry(name).or_default();
+        write_u32(entries, index.index() as u32);
+        write_u32(entries, data.len() as u32);
+        entries.extend_from_slice(&data);
+    }
+}
+
+fn parse_metadata(section: &[u8]) -> Vec<(LocalFuncIndex, &[u8])> {
+    let mut entries = vec![];
+    let mut position = 0;
+    while position < section.len() {
+        let entry = read_u32(section, &mut position).and_then(|index| {
+            let len = read_u32(section, &mut position)? as usize;
+            let data = section.get(position..position.checked_add(len)?)?;
+            position += len;
+            Some((LocalFuncIndex::new(index as usize), data))
+        });
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => break,
+        }
+    }
+    entries
+}
+
+fn middleware_error(msg: String) -> CompileError {
+    CompileError::InternalError {
+        msg: format!("middleware failed: {}", msg),
+    }
+}
+
+pub(crate) fn read_u32(bytes: &[u8], position: &mut usize) -> Option<u32> {
+    let mut result = 0u32;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*position)?;
+        *position += 1;
+        if shift >= 32 {
+            return None;
+        }
+        result |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+        shift += 7;
+    }
+}
+
+pub(crate) fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod middleware_tests {
+    use super::*;
+
+    /// Puts a `nop` before every loop and counts them in metadata.
+    struct NopBeforeLoops {
+        loops: u8,
+    }
+
+    impl FunctionMiddleware for NopBeforeLoops {
+        fn api_version(&self) -> u32 {
+            API_VERSION
+        }
+
+        fn feed_event<'a>(
+            &mut self,
+            event: Event<'a>,
+            sink: &mut EventSink<'a>,
+        ) -> Result<(), String> {
+            if let Event::Wasm(Operator::Loop { .. }, _) = event {
+                self.loops += 1;
+                sink.push(Event::Insert(Instruction::Nop));
+                sink.attach_metadata("loops", &[self.loops]);
+            }
+            sink.push(event);
+            Ok(())
+        }
+    }
+
+    #[rustfmt::skip]
+    const WASM: [u8; 30] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // (type (func))
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        // (func (type 0))
+        0x03, 0x02, 0x01, 0x00,
+        0x0a, 0x0a, 0x01, 0x08, 0x00,
+        // i32.const 1, drop, loop, end, end
+        0x41, 0x01, 0x1a, 0x03, 0x40, 0x0b, 0x0b,
+    ];
+
+    #[test]
+    fn test_transform() {
+        let mut middlewares: Vec<Box<dyn FunctionMiddleware>> =
+            vec![Box::new(NopBeforeLoops { loops: 0 })];
+        let wasm = transform_with(&WASM, &mut middlewares).unwrap();
+        assert!(crate::validate(&wasm));
+
+        #[rustfmt::skip]
+        let code = [
+            0x0a, 0x0b, 0x01, 0x09, 0x00,
+            0x41, 0x01, 0x1a, 0x01, 0x03, 0x40, 0x0b, 0x0b,
+        ];
+        assert_eq!(&wasm[18..31], &code[..]);
+
+        // (custom "loops" (func 0) 1)
+        assert_eq!(
+            &wasm[31..],
+            &[0x00, 0x09, 0x05, b'l', b'o', b'o', b'p', b's', 0x00, 0x01, 0x01][..]
+        );
+        assert_eq!(
+            parse_metadata(&[0x00, 0x01, 0x01]),
+            vec![(LocalFuncIndex::new(0), &[1][..])]
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_api_versions() {
+        struct FromTheFuture;
+
+        impl FunctionMiddleware for FromTheFuture {
+            fn api_version(&self) -> u32 {
+                API_VERSION + 1
+            }
+
+            fn feed_event<'a>(
+                &mut self,
+                event: Event<'a>,
+                sink: &mut EventSink<'a>,
+            ) -> Result<(), String> {
+                sink.push(event);
+                Ok(())
+            }
+        }
+
+        let mut middlewares: Vec<Box<dyn FunctionMiddleware>> = vec![Box::new(FromTheFuture)];
+        assert!(transform_with(&WASM, &mut middlewares).is_err());
+    }
+
+    #[test]
+    fn test_signed_leb() {
+        let mut out = vec![];
+        write_signed(&mut out, -1);
+        write_signed(&mut out, 64);
+        assert_eq!(out, vec![0x7f, 0xc0, 0x00]);
+    }
+}
@@ -1,15 +1,19 @@
 use crate::{
-    backend::{Backend, FuncResolver, ProtectedCaller},
-    cache::{Artifact, Error as CacheError},
+    backend::{Backend, FuncCode, FuncResolver, ProtectedCaller},
+    backing::LocalBacking,
+    cache::{serialize_sorted, Artifact, Error as CacheError},
     error,
     import::ImportObject,
+    memory::MemoryStyle,
+    snapshot::InstanceTemplate,
+    stackmap::StackMap,
     structures::{Map, TypedIndex},
-    typed_func::EARLY_TRAPPER,
+    sys,
     types::{
         FuncIndex, FuncSig, GlobalDescriptor, GlobalIndex, GlobalInit, ImportedFuncIndex,
-        ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex, Initializer,
-        LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryDescriptor, MemoryIndex,
-        SigIndex, TableDescriptor, TableIndex,
+        ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex, Initializer, LocalFuncIndex,
+        LocalGlobalIndex, LocalMemoryIndex, LocalOrImport, LocalTableIndex, MemoryDescriptor,
+        MemoryIndex, SigIndex, TableDescriptor, TableIndex,
     },
     Instance,
 };
@@ -43,19 +47,44 @@ pub struct ModuleInfo {
     pub imported_tables: Map<ImportedTableIndex, (ImportName, TableDescriptor)>,
     pub imported_globals: Map<ImportedGlobalIndex, (ImportName, GlobalDescriptor)>,
 
+    #[serde(serialize_with = "serialize_sorted")]
     pub exports: HashMap<String, ExportIndex>,
 
     pub data_initializers: Vec<DataInitializer>,
     pub elem_initializers: Vec<TableInitializer>,
 
+    /// The passive data segments, by data segment index. They are
+    /// only copied into memory by `memory.init`.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub passive_data: HashMap<u32, Vec<u8>>,
+    /// The passive element segments, by element segment index. They
+    /// are only copied into a table by `table.init`.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub passive_elements: HashMap<u32, Vec<FuncIndex>>,
+
     pub start_func: Option<FuncIndex>,
 
     pub func_assoc: Map<FuncIndex, SigIndex>,
     pub signatures: Map<SigIndex, FuncSig>,
     pub backend: Backend,
+    /// The style of the memories that the code was compiled for.
+    pub memory_style: MemoryStyle,
+    /// The points that instances start out with, if the code was
+    /// compiled to be metered. See the [`metering`] module.
+    ///
+    /// [`metering`]: ../metering/index.html
+    pub initial_points: Option<u64>,
 
     pub namespace_table: StringTable<NamespaceIndex>,
     pub name_table: StringTable<NameIndex>,
+
+    /// The contents of the custom sections, by section name, in the
+    /// order that they appear in the module.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub custom_sections: HashMap<String, Vec<Vec<u8>>>,
+    /// The function names from the `name` section, if there is one.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub func_names: HashMap<FuncIndex, String>,
 }
 
 /// A compiled WebAssembly module.
@@ -63,19 +92,35 @@ pub struct ModuleInfo {
 /// `Module` is returned by the [`compile`] and
 /// [`compile_with`] functions.
 ///
+/// A module is `Send + Sync` and cheap to clone, so it can be compiled
+/// once and instantiated from many threads at the same time. The
+/// instances made from it stay on the thread that made them.
+///
 /// [`compile`]: fn.compile.html
 /// [`compile_with`]: fn.compile_with.html
 pub struct Module {
     inner: Arc<ModuleInner>,
+    /// The initial contents of each local memory, if they can be
+    /// worked out without the imports.
+    images: Arc<Vec<Option<sys::MemoryImage>>>,
 }
 
 impl Module {
     pub(crate) fn new(inner: Arc<ModuleInner>) -> Self {
-        unsafe {
-            EARLY_TRAPPER
-                .with(|ucell| *ucell.get() = Some(inner.protected_caller.get_early_trapper()));
-        }
-        Module { inner }
+        let images = Arc::new(LocalBacking::memory_images(&inner.info));
+        Self::with_images(inner, images)
+    }
+
+    /// A module whose memory images have already been worked out.
+    pub(crate) fn with_images(
+        inner: Arc<ModuleInner>,
+        images: Arc<Vec<Option<sys::MemoryImage>>>,
+    ) -> Self {
+        Module { inner, images }
+    }
+
+    pub(crate) fn inner(&self) -> &Arc<ModuleInner> {
+        &self.inner
     }
 
     /// Instantiate a WebAssembly module with the provided [`ImportObject`].
@@ -101,29 +146,302 @@ impl Module {
     /// # }
     /// ```
     pub fn instantiate(&self, import_object: &ImportObject) -> error::Result<Instance> {
-        Instance::new(Arc::clone(&self.inner), import_object)
+        Instance::new(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.images),
+            import_object,
+        )
+    }
+
+    /// Instantiate a WebAssembly module with the provided [`ImportObject`]
+    /// like [`instantiate`], but without calling its `start` function.
+    ///
+    /// The embedder runs it later with [`Instance::start`], after
+    /// setting up metering or a deadline for it, for instance. Until
+    /// then, the instance's exports can be used, but the module may
+    /// not expect to be called before it has started.
+    ///
+    /// [`ImportObject`]: struct.ImportObject.html
+    /// [`instantiate`]: #method.instantiate
+    /// [`Instance::start`]: struct.Instance.html#method.start
+    pub fn instantiate_deferred(&self, import_object: &ImportObject) -> error::Result<Instance> {
+        Instance::new_deferred(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.images),
+            import_object,
+        )
+    }
+
+    /// Instantiate this module once with the provided [`ImportObject`],
+    /// and return a template that makes more instances starting out in
+    /// the same state, without running the start function again.
+    ///
+    /// [`ImportObject`]: struct.ImportObject.html
+    pub fn template(&self, import_object: &ImportObject) -> error::Result<InstanceTemplate> {
+        InstanceTemplate::new(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.images),
+            import_object,
+        )
     }
 
     pub fn cache(&self) -> Result<Artifact, CacheError> {
         let (info, backend_metadata, code) = self.inner.cache_gen.generate_cache(&self.inner)?;
-        Ok(Artifact::from_parts(info, backend_metadata, code))
+        Ok(Artifact::from_parts(
+            info,
+            backend_metadata,
+            code,
+            self.inner.cache_gen.target(),
+            self.inner.cache_gen.version(),
+        ))
     }
 
     pub fn info(&self) -> &ModuleInfo {
         &self.inner.info
     }
+
+    /// The imports that this module needs to be instantiated.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Module;
+    /// # use wasmer_runtime_core::module::ExternDescriptor;
+    /// # fn print_imports(module: &Module) {
+    /// for import in module.imports() {
+    ///     if let ExternDescriptor::Function(signature) = import.descriptor {
+    ///         println!("{}.{}: {}", import.namespace, import.name, signature);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn imports(&self) -> Vec<ImportDescriptor> {
+        self.inner.info.imports()
+    }
+
+    /// The exports of this module, sorted by name.
+    pub fn exports(&self) -> Vec<ExportDescriptor> {
+        self.inner.info.exports()
+    }
+
+    /// The contents of the custom sections called `name`, in the
+    /// order that they appear in the module, or `None` if there
+    /// are no such sections.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Module;
+    /// # fn print_producers(module: &Module) {
+    /// for section in module.custom_sections("producers").unwrap_or(&[]) {
+    ///     println!("producers section: {} bytes", section.len());
+    /// }
+    /// # }
+    /// ```
+    pub fn custom_sections(&self, name: &str) -> Option<&[Vec<u8>]> {
+        self.inner
+            .info
+            .custom_sections
+            .get(name)
+            .map(|sections| &sections[..])
+    }
+
+    /// The machine code that a local function was compiled to, with
+    /// how long it took to compile, or `None` if the backend doesn't
+    /// keep track of it.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Module;
+    /// # use wasmer_runtime_core::types::LocalOrImport;
+    /// # fn print_sizes(module: &Module) {
+    /// for (func_index, _) in module.info().func_assoc.iter() {
+    ///     if let LocalOrImport::Local(local_func_index) = func_index.local_or_import(module.info()) {
+    ///         if let Some(func_code) = module.func_code(local_func_index) {
+    ///             println!("{:?}: {} bytes", func_index, func_code.code.len());
+    ///         }
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn func_code(&self, local_func_index: LocalFuncIndex) -> Option<FuncCode> {
+        self.inner.func_resolver.code(&self.inner, local_func_index)
+    }
+
+    /// The disassembly of the machine code of a local function, or
+    /// `None` if the backend can't disassemble it.
+    pub fn disassemble(&self, local_func_index: LocalFuncIndex) -> Option<String> {
+        self.inner
+            .func_resolver
+            .disassemble(&self.inner, local_func_index)
+    }
+
+    /// Where the reference-typed values of a local function are at each
+    /// of its safepoints, or `None` if the backend doesn't make stack
+    /// maps.
+    pub fn stack_map(&self, local_func_index: LocalFuncIndex) -> Option<&StackMap> {
+        self.inner
+            .func_resolver
+            .stack_map(&self.inner, local_func_index)
+    }
 }
 
 impl Clone for Module {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            images: Arc::clone(&self.images),
         }
     }
 }
 
+/// Fails to compile if a module, or the artifact that it's cached as,
+/// can't be shared between threads.
+#[allow(dead_code)]
+fn assert_module_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Module>();
+    assert_send_sync::<ModuleInner>();
+    assert_send_sync::<Artifact>();
+}
+
 impl ModuleInner {}
 
+impl ModuleInfo {
+    /// The imports of this module: the functions, then the tables,
+    /// memories and globals, each in index order.
+    pub fn imports(&self) -> Vec<ImportDescriptor> {
+        let import = |import_name: &ImportName, descriptor| ImportDescriptor {
+            namespace: self
+                .namespace_table
+                .get(import_name.namespace_index)
+                .to_string(),
+            name: self.name_table.get(import_name.name_index).to_string(),
+            descriptor,
+        };
+
+        let functions = self.imported_functions.iter().map(|(index, import_name)| {
+            let descriptor = self.extern_descriptor(ExportIndex::Func(index.convert_up(self)));
+            import(import_name, descriptor)
+        });
+        let tables = self
+            .imported_tables
+            .iter()
+            .map(|(_, (import_name, desc))| import(import_name, ExternDescriptor::Table(*desc)));
+        let memories = self
+            .imported_memories
+            .iter()
+            .map(|(_, (import_name, desc))| import(import_name, ExternDescriptor::Memory(*desc)));
+        let globals = self
+            .imported_globals
+            .iter()
+            .map(|(_, (import_name, desc))| import(import_name, ExternDescriptor::Global(*desc)));
+
+        functions
+            .chain(tables)
+            .chain(memories)
+            .chain(globals)
+            .collect()
+    }
+
+    /// The exports of this module, sorted by name.
+    pub fn exports(&self) -> Vec<ExportDescriptor> {
+        let mut exports: Vec<_> = self
+            .exports
+            .iter()
+            .map(|(name, &export_index)| ExportDescriptor {
+                name: name.clone(),
+                descriptor: self.extern_descriptor(export_index),
+            })
+            .collect();
+        exports.sort_by(|a, b| a.name.cmp(&b.name));
+        exports
+    }
+
+    fn extern_descriptor(&self, index: ExportIndex) -> ExternDescriptor {
+        match index {
+            ExportIndex::Func(func_index) => {
+                let sig_index = self.func_assoc[func_index];
+                ExternDescriptor::Function(self.signatures[sig_index].clone())
+            }
+            ExportIndex::Memory(memory_index) => {
+                ExternDescriptor::Memory(match memory_index.local_or_import(self) {
+                    LocalOrImport::Local(local_index) => self.memories[local_index],
+                    LocalOrImport::Import(import_index) => self.imported_memories[import_index].1,
+                })
+            }
+            ExportIndex::Table(table_index) => {
+                ExternDescriptor::Table(match table_index.local_or_import(self) {
+                    LocalOrImport::Local(local_index) => self.tables[local_index],
+                    LocalOrImport::Import(import_index) => self.imported_tables[import_index].1,
+                })
+            }
+            ExportIndex::Global(global_index) => {
+                ExternDescriptor::Global(match global_index.local_or_import(self) {
+                    LocalOrImport::Local(local_index) => self.globals[local_index].desc,
+                    LocalOrImport::Import(import_index) => self.imported_globals[import_index].1,
+                })
+            }
+        }
+    }
+}
+
+/// Read the function names out of the contents of a `name` section.
+///
+/// The `name` section is only informative, so a malformed one is
+/// ignored rather than failing compilation.
+#[doc(hidden)]
+pub fn read_func_names(name_section: &[u8]) -> HashMap<FuncIndex, String> {
+    fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            result |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    fn read_bytes<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if bytes.len() < len {
+            return None;
+        }
+        let (read, rest) = bytes.split_at(len);
+        *bytes = rest;
+        Some(read)
+    }
+
+    fn read_function_names(mut bytes: &[u8], names: &mut HashMap<FuncIndex, String>) -> Option<()> {
+        let bytes = &mut bytes;
+        for _ in 0..read_u32(bytes)? {
+            let index = read_u32(bytes)?;
+            let len = read_u32(bytes)? as usize;
+            let name = String::from_utf8_lossy(read_bytes(bytes, len)?);
+            names.insert(FuncIndex::new(index as usize), name.into_owned());
+        }
+        Some(())
+    }
+
+    const FUNCTION_NAMES: u8 = 1;
+
+    let mut names = HashMap::new();
+    let mut bytes = name_section;
+    while let Some((&id, mut rest)) = bytes.split_first() {
+        let subsection =
+            match read_u32(&mut rest).and_then(|len| read_bytes(&mut rest, len as usize)) {
+                Some(subsection) => subsection,
+                None => break,
+            };
+        bytes = rest;
+
+        if id == FUNCTION_NAMES {
+            read_function_names(subsection, &mut names);
+        }
+    }
+    names
+}
+
 #[doc(hidden)]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ImportName {
@@ -139,6 +457,34 @@ pub enum ExportIndex {
     Table(TableIndex),
 }
 
+/// The type of an import or an export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternDescriptor {
+    Function(FuncSig),
+    Memory(MemoryDescriptor),
+    Table(TableDescriptor),
+    Global(GlobalDescriptor),
+}
+
+/// An import of a module, as returned by [`Module::imports`].
+///
+/// [`Module::imports`]: struct.Module.html#method.imports
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDescriptor {
+    pub namespace: String,
+    pub name: String,
+    pub descriptor: ExternDescriptor,
+}
+
+/// An export of a module, as returned by [`Module::exports`].
+///
+/// [`Module::exports`]: struct.Module.html#method.exports
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportDescriptor {
+    pub name: String,
+    pub descriptor: ExternDescriptor,
+}
+
 /// A data initializer for linear memory.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataInitializer {
@@ -265,3 +611,151 @@ impl TypedIndex for NameIndex {
         self.0 as usize
     }
 }
+
+#[cfg(test)]
+mod module_tests {
+    use super::*;
+    use crate::{
+        types::{ElementType, Initializer, Type, Value},
+        units::Pages,
+    };
+
+    #[test]
+    fn test_read_func_names() {
+        let name_section: Vec<u8> = [
+            // The module name, which is skipped.
+            &[0, 4, 3][..],
+            b"mod",
+            // Two function names. The second index takes two bytes.
+            &[1, 13, 2, 0, 4],
+            b"main",
+            &[0x83, 0x01, 3],
+            b"foo",
+            // The local names, which are skipped.
+            &[2, 1, 0],
+        ]
+        .concat();
+
+        let names = read_func_names(&name_section);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[&FuncIndex::new(0)], "main");
+        assert_eq!(names[&FuncIndex::new(131)], "foo");
+
+        // A truncated subsection is ignored.
+        let names = read_func_names(&name_section[..14]);
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn test_imports_and_exports() {
+        let mut namespace_table = StringTableBuilder::new();
+        let mut name_table = StringTableBuilder::new();
+        let mut import_name = |namespace: &str, name: &str| ImportName {
+            namespace_index: namespace_table.register(namespace),
+            name_index: name_table.register(name),
+        };
+
+        let mut signatures = Map::new();
+        let sig_index = signatures.push(FuncSig::new(vec![Type::I32], vec![]));
+        let mut func_assoc = Map::new();
+        func_assoc.push(sig_index);
+        func_assoc.push(sig_index);
+
+        let mut imported_functions = Map::new();
+        imported_functions.push(import_name("env", "print"));
+        let table = TableDescriptor {
+            element: ElementType::Anyfunc,
+            minimum: 1,
+            maximum: None,
+        };
+        let mut imported_tables = Map::new();
+        imported_tables.push((import_name("env", "table"), table));
+        let memory = MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(2)),
+            shared: false,
+        };
+        let mut memories = Map::new();
+        memories.push(memory);
+        let global = GlobalDescriptor {
+            mutable: true,
+            ty: Type::I64,
+        };
+        let mut globals = Map::new();
+        globals.push(GlobalInit {
+            desc: global,
+            init: Initializer::Const(Value::I64(0)),
+        });
+
+        let mut exports = HashMap::new();
+        exports.insert("run".to_string(), ExportIndex::Func(FuncIndex::new(1)));
+        exports.insert(
+            "memory".to_string(),
+            ExportIndex::Memory(MemoryIndex::new(0)),
+        );
+        exports.insert(
+            "counter".to_string(),
+            ExportIndex::Global(GlobalIndex::new(0)),
+        );
+
+        let info = ModuleInfo {
+            memories,
+            globals,
+            tables: Map::new(),
+
+            imported_functions,
+            imported_memories: Map::new(),
+            imported_tables,
+            imported_globals: Map::new(),
+
+            exports,
+
+            data_initializers: Vec::new(),
+            elem_initializers: Vec::new(),
+
+            passive_data: HashMap::new(),
+            passive_elements: HashMap::new(),
+
+            start_func: None,
+
+            func_assoc,
+            signatures,
+            backend: Backend::Cranelift,
+            memory_style: MemoryStyle::Static,
+            initial_points: None,
+
+            namespace_table: namespace_table.finish(),
+            name_table: name_table.finish(),
+
+            custom_sections: HashMap::new(),
+            func_names: HashMap::new(),
+        };
+
+        let signature = FuncSig::new(vec![Type::I32], vec![]);
+        let import = |name: &str, descriptor| ImportDescriptor {
+            namespace: "env".to_string(),
+            name: name.to_string(),
+            descriptor,
+        };
+        assert_eq!(
+            info.imports(),
+            vec![
+                import("print", ExternDescriptor::Function(signature.clone())),
+                import("table", ExternDescriptor::Table(table)),
+            ],
+        );
+
+        let export = |name: &str, descriptor| ExportDescriptor {
+            name: name.to_string(),
+            descriptor,
+        };
+        assert_eq!(
+            info.exports(),
+            vec![
+                export("counter", ExternDescriptor::Global(global)),
+                export("memory", ExternDescriptor::Memory(memory)),
+                export("run", ExternDescriptor::Function(signature)),
+            ],
+        );
+    }
+}
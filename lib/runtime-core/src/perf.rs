@@ -0,0 +1,316 @@
+//! Naming compiled code for the Linux `perf` profiler, so samples that
+//! land in it are attributed to WebAssembly functions rather than to
+//! anonymous addresses.
+//!
+//! Two formats are supported, which backends write to once they have
+//! loaded their code, if they've been enabled:
+//!
+//! - A [perf map], `/tmp/perf-<pid>.map`, with the address, size and
+//!   name of each function. `perf report` reads it by itself.
+//! - A [jitdump] file, `jit-<pid>.dump` in the working directory, which
+//!   holds the code of each function as well, so `perf annotate` can
+//!   show its instructions. Record with `perf record -k mono`, then run
+//!   `perf inject --jit` on the recording before reporting.
+//!
+//! Entries can't be removed from either, so the names of code that has
+//! been unloaded stay until the process exits.
+//!
+//! With the `vtune` feature, the code is also named for Intel VTune
+//! whenever the process is run under it.
+//!
+//! [perf map]: https://github.com/torvalds/linux/blob/master/tools/perf/Documentation/jit-interface.txt
+//! [jitdump]: https://github.com/torvalds/linux/blob/master/tools/perf/Documentation/jitdump-specification.txt
+
+use crate::{
+    module::ModuleInfo,
+    structures::TypedIndex,
+    types::{FuncIndex, LocalFuncIndex},
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    process, slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static MAP_ENABLED: AtomicBool = AtomicBool::new(false);
+static JITDUMP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref PERF_MAP: Mutex<Option<File>> = Mutex::new(None);
+    static ref JITDUMP: Mutex<Option<JitDump>> = Mutex::new(None);
+}
+
+/// Set whether backends write the code that they load from now on to
+/// the perf map of the process.
+pub fn set_map_enabled(enabled: bool) {
+    MAP_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Set whether backends write the code that they load from now on to
+/// the jitdump file of the process. Only Linux has jitdump files.
+pub fn set_jitdump_enabled(enabled: bool) {
+    JITDUMP_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether backends write the code that they load for a profiler, in
+/// any format.
+pub fn is_enabled() -> bool {
+    MAP_ENABLED.load(Ordering::SeqCst)
+        || JITDUMP_ENABLED.load(Ordering::SeqCst)
+        || vtune_is_active()
+}
+
+#[cfg(feature = "vtune")]
+fn vtune_is_active() -> bool {
+    crate::vtune::is_active()
+}
+
+#[cfg(not(feature = "vtune"))]
+fn vtune_is_active() -> bool {
+    false
+}
+
+/// The name that a function is profiled under: its name in the `name`
+/// section of the module, if it has one, or else its index.
+pub fn function_name(info: &ModuleInfo, func_index: FuncIndex) -> String {
+    match info.func_names.get(&func_index) {
+        Some(name) => name.clone(),
+        None => format!("wasm-function[{}]", func_index.index()),
+    }
+}
+
+/// Write the local functions of a module, given as their index, the
+/// address that their code was loaded at and its size in bytes, to the
+/// outputs that are enabled.
+///
+/// Failing to write doesn't fail the caller, which is only profiled
+/// less precisely.
+///
+/// # Safety
+///
+/// The code of each function must be readable for as many bytes as its
+/// size.
+pub unsafe fn register(info: &ModuleInfo, functions: &[(LocalFuncIndex, *const u8, usize)]) {
+    let names: Vec<String> = functions
+        .iter()
+        .map(|&(local_func_index, _, _)| function_name(info, local_func_index.convert_up(info)))
+        .collect();
+
+    if MAP_ENABLED.load(Ordering::SeqCst) {
+        let mut perf_map = PERF_MAP.lock();
+        if perf_map.is_none() {
+            *perf_map = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(format!("/tmp/perf-{}.map", process::id()))
+                .ok();
+        }
+        if let Some(file) = perf_map.as_mut() {
+            let mut lines = String::new();
+            for (&(_, address, size), name) in functions.iter().zip(&names) {
+                lines.push_str(&format!("{:x} {:x} {}\n", address as usize, size, name));
+            }
+            let _ = file.write_all(lines.as_bytes());
+        }
+    }
+
+    if JITDUMP_ENABLED.load(Ordering::SeqCst) {
+        let mut jitdump = JITDUMP.lock();
+        if jitdump.is_none() {
+            *jitdump = JitDump::open();
+        }
+        if let Some(jitdump) = jitdump.as_mut() {
+            for (&(_, address, size), name) in functions.iter().zip(&names) {
+                let code = slice::from_raw_parts(address, size);
+                jitdump.write_code_load(name, code);
+            }
+        }
+    }
+
+    #[cfg(feature = "vtune")]
+    {
+        if crate::vtune::is_active() {
+            for (&(_, address, size), name) in functions.iter().zip(&names) {
+                crate::vtune::register(name, address, size);
+            }
+        }
+    }
+}
+
+const JITDUMP_MAGIC: u32 = 0x4A69_5444;
+const JITDUMP_VERSION: u32 = 1;
+const JIT_CODE_LOAD: u32 = 0;
+
+#[cfg(target_arch = "x86_64")]
+const ELF_MACHINE: u32 = 62;
+#[cfg(target_arch = "aarch64")]
+const ELF_MACHINE: u32 = 183;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ELF_MACHINE: u32 = 0;
+
+struct JitDump {
+    file: File,
+    /// The number of functions written so far, which numbers the next.
+    code_index: u64,
+}
+
+impl JitDump {
+    #[cfg(target_os = "linux")]
+    fn open() -> Option<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let pid = process::id();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(format!("jit-{}.dump", pid))
+            .ok()?;
+
+        // `perf record` finds the file through this mapping of it,
+        // which has to be executable and is kept for good.
+        let marker = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page_size::get(),
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return None;
+        }
+
+        file.write_all(&jitdump_header(pid, timestamp())).ok()?;
+        Some(Self {
+            file,
+            code_index: 0,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open() -> Option<Self> {
+        None
+    }
+
+    fn write_code_load(&mut self, name: &str, code: &[u8]) {
+        let record = code_load_record(
+            process::id(),
+            thread_id(),
+            timestamp(),
+            self.code_index,
+            name,
+            code,
+        );
+        self.code_index += 1;
+        let _ = self.file.write_all(&record);
+    }
+}
+
+fn jitdump_header(pid: u32, timestamp: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(40);
+    header.extend_from_slice(&JITDUMP_MAGIC.to_ne_bytes());
+    header.extend_from_slice(&JITDUMP_VERSION.to_ne_bytes());
+    header.extend_from_slice(&40u32.to_ne_bytes());
+    header.extend_from_slice(&ELF_MACHINE.to_ne_bytes());
+    header.extend_from_slice(&0u32.to_ne_bytes());
+    header.extend_from_slice(&pid.to_ne_bytes());
+    header.extend_from_slice(&timestamp.to_ne_bytes());
+    // No flags.
+    header.extend_from_slice(&0u64.to_ne_bytes());
+    header
+}
+
+/// The record that tells `perf` that `code`, which is where it is in
+/// memory, has been loaded.
+fn code_load_record(
+    pid: u32,
+    tid: u32,
+    timestamp: u64,
+    code_index: u64,
+    name: &str,
+    code: &[u8],
+) -> Vec<u8> {
+    let address = code.as_ptr() as u64;
+    let size = 16 + 40 + name.len() + 1 + code.len();
+
+    let mut record = Vec::with_capacity(size);
+    record.extend_from_slice(&JIT_CODE_LOAD.to_ne_bytes());
+    record.extend_from_slice(&(size as u32).to_ne_bytes());
+    record.extend_from_slice(&timestamp.to_ne_bytes());
+    record.extend_from_slice(&pid.to_ne_bytes());
+    record.extend_from_slice(&tid.to_ne_bytes());
+    // The address that the code runs at, then where it is: the same.
+    record.extend_from_slice(&address.to_ne_bytes());
+    record.extend_from_slice(&address.to_ne_bytes());
+    record.extend_from_slice(&(code.len() as u64).to_ne_bytes());
+    record.extend_from_slice(&code_index.to_ne_bytes());
+    record.extend_from_slice(name.as_bytes());
+    record.push(0);
+    record.extend_from_slice(code);
+    record
+}
+
+/// The time in nanoseconds on the clock that `perf record -k mono`
+/// stamps samples with.
+#[cfg(target_os = "linux")]
+fn timestamp() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+    }
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
+
+#[cfg(not(target_os = "linux"))]
+fn timestamp() -> u64 {
+    0
+}
+
+#[cfg(target_os = "linux")]
+fn thread_id() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_id() -> u32 {
+    process::id()
+}
+
+#[cfg(test)]
+mod perf_tests {
+    use super::*;
+
+    #[test]
+    fn test_code_load_record() {
+        let code = [0x90u8, 0xc3];
+        let record = code_load_record(7, 8, 9, 3, "add", &code);
+
+        assert_eq!(record.len(), 16 + 40 + 4 + 2);
+        assert_eq!(record[..4], JIT_CODE_LOAD.to_ne_bytes());
+        assert_eq!(record[4..8], (record.len() as u32).to_ne_bytes());
+        assert_eq!(record[16..20], 7u32.to_ne_bytes());
+        assert_eq!(record[20..24], 8u32.to_ne_bytes());
+        assert_eq!(record[24..32], (code.as_ptr() as u64).to_ne_bytes());
+        assert_eq!(record[40..48], 2u64.to_ne_bytes());
+        assert_eq!(record[48..56], 3u64.to_ne_bytes());
+        assert_eq!(&record[56..], b"add\0\x90\xc3");
+    }
+
+    #[test]
+    fn test_jitdump_header() {
+        let header = jitdump_header(7, 9);
+        assert_eq!(header.len(), 40);
+        assert_eq!(header[..4], JITDUMP_MAGIC.to_ne_bytes());
+        assert_eq!(header[8..12], 40u32.to_ne_bytes());
+    }
+}
@@ -0,0 +1,251 @@
+//! Hooks for providing what the runtime otherwise gets from the
+//! operating system.
+//!
+//! By default, the memory that the runtime allocates for linear
+//! memories, tables, stacks and compiled code comes from `mmap` or
+//! `VirtualAlloc`, and faults in WebAssembly code are caught with
+//! signal handlers. Environments that don't have those, such as
+//! kernels and embedded systems, can provide them instead:
+//!
+//! - A [`PageAllocator`], set with [`set_page_allocator`], provides
+//!   the pages that memories are made of.
+//! - A [`TrapHandler`], set with [`set_trap_handler`], arranges for
+//!   faults in WebAssembly code to be reported to the runtime, which
+//!   then unwinds the call that faulted.
+//!
+//! Both must be set before the runtime first needs them: memories keep
+//! the allocator that they were allocated with, and the [`trap`] module
+//! installs the trap handler on the first call into WebAssembly.
+//!
+//! Memory images, which map the initial contents of memories from
+//! in-memory files, are only used with the default allocator.
+//!
+//! Compiled code is never writable and executable at once. On macOS,
+//! code can be mapped with `MAP_JIT` instead, for processes with the
+//! hardened runtime, with [`set_map_jit`].
+//!
+//! [`PageAllocator`]: trait.PageAllocator.html
+//! [`set_page_allocator`]: fn.set_page_allocator.html
+//! [`TrapHandler`]: trait.TrapHandler.html
+//! [`set_trap_handler`]: fn.set_trap_handler.html
+//! [`trap`]: ../trap/index.html
+//! [`set_map_jit`]: fn.set_map_jit.html
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+pub use crate::sys::Protect;
+
+/// Provides pages of memory.
+///
+/// Memory is reserved a region at a time and then made accessible a
+/// range of pages at a time. A region may be released, or reset, in
+/// several page-aligned parts.
+///
+/// # Safety
+///
+/// The regions that `reserve` returns must be page aligned, not
+/// overlap any other memory in use, and stay valid until they're
+/// released.
+pub unsafe trait PageAllocator: Send + Sync {
+    /// The size of a page, which is a power of two.
+    fn page_size(&self) -> usize;
+
+    /// Reserve `size` bytes, a multiple of the page size, of
+    /// inaccessible memory.
+    fn reserve(&self, size: usize) -> Result<*mut u8, String>;
+
+    /// Make the `size` bytes at `ptr` accessible with `protection`.
+    unsafe fn protect(&self, ptr: *mut u8, size: usize, protection: Protect) -> Result<(), String>;
+
+    /// Throw away the contents of the `size` bytes at `ptr` and make
+    /// them inaccessible again, keeping them reserved. They must read
+    /// as zeros when they're made accessible again.
+    unsafe fn reset(&self, ptr: *mut u8, size: usize) -> Result<(), String>;
+
+    /// Release the `size` bytes at `ptr`.
+    unsafe fn release(&self, ptr: *mut u8, size: usize);
+}
+
+/// The allocator that a memory came from.
+#[derive(Clone)]
+pub(crate) struct Allocator(pub Arc<dyn PageAllocator>);
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PageAllocator")
+            .field("page_size", &self.0.page_size())
+            .finish()
+    }
+}
+
+lazy_static! {
+    static ref PAGE_ALLOCATOR: Mutex<Option<Allocator>> = Mutex::new(None);
+    static ref TRAP_HANDLER: Mutex<Option<Arc<dyn TrapHandler>>> = Mutex::new(None);
+}
+
+/// Allocate the memories that are created from now on with
+/// `allocator`, or with the operating system again with `None`.
+pub fn set_page_allocator(allocator: Option<Box<dyn PageAllocator>>) {
+    *PAGE_ALLOCATOR.lock() = allocator.map(|allocator| Allocator(Arc::from(allocator)));
+}
+
+/// The allocator that new memories come from, if it isn't the
+/// operating system.
+pub(crate) fn page_allocator() -> Option<Allocator> {
+    PAGE_ALLOCATOR.lock().clone()
+}
+
+/// What kind of fault WebAssembly code caused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// An access to memory that isn't accessible, such as a guard
+    /// page.
+    MemoryAccess,
+    /// An illegal instruction, which compiled code uses for traps.
+    IllegalInstruction,
+    /// A division by zero or an overflowing division.
+    Arithmetic,
+    /// The stack overflowed, for handlers that can tell.
+    StackOverflow,
+}
+
+/// A fault in WebAssembly code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// The address that was accessed, for memory access faults.
+    pub address: usize,
+    /// The address of the faulting instruction.
+    pub instruction: usize,
+}
+
+/// Reports faults in WebAssembly code to the runtime.
+pub trait TrapHandler: Send + Sync {
+    /// Arrange for `unwind` to be called, on the thread that faulted
+    /// and with the fault, when WebAssembly code faults. `unwind` never
+    /// returns to the code that faulted.
+    fn install(&self, unwind: unsafe fn(Fault) -> !);
+}
+
+/// Report faults in WebAssembly code with `handler` instead of signal
+/// handlers, or with signal handlers again with `None`.
+pub fn set_trap_handler(handler: Option<Box<dyn TrapHandler>>) {
+    *TRAP_HANDLER.lock() = handler.map(Arc::from);
+}
+
+/// The trap handler to install, if it isn't signal handlers.
+pub(crate) fn trap_handler() -> Option<Arc<dyn TrapHandler>> {
+    TRAP_HANDLER.lock().clone()
+}
+
+static MAP_JIT: AtomicBool = AtomicBool::new(false);
+
+/// Map compiled code with `MAP_JIT`, which macOS requires of
+/// executable memory in processes with the hardened runtime and the
+/// `com.apple.security.cs.allow-jit` entitlement. On Apple silicon,
+/// such code is then made writable or executable for one thread at a
+/// time with `pthread_jit_write_protect_np`. This does nothing on
+/// other operating systems, or with a page allocator.
+pub fn set_map_jit(enabled: bool) {
+    MAP_JIT.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether compiled code is mapped with `MAP_JIT`.
+pub fn map_jit() -> bool {
+    MAP_JIT.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod platform_tests {
+    use super::*;
+    use crate::sys::Memory;
+    use std::{
+        alloc::{self, Layout},
+        ptr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Hands out heap memory, which is always accessible.
+    struct HeapAllocator {
+        reserved: AtomicUsize,
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 4096).unwrap()
+    }
+
+    unsafe impl PageAllocator for HeapAllocator {
+        fn page_size(&self) -> usize {
+            4096
+        }
+
+        fn reserve(&self, size: usize) -> Result<*mut u8, String> {
+            self.reserved.fetch_add(size, Ordering::SeqCst);
+            Ok(unsafe { alloc::alloc_zeroed(layout(size)) })
+        }
+
+        unsafe fn protect(&self, _: *mut u8, _: usize, _: Protect) -> Result<(), String> {
+            Ok(())
+        }
+
+        unsafe fn reset(&self, ptr: *mut u8, size: usize) -> Result<(), String> {
+            ptr::write_bytes(ptr, 0, size);
+            Ok(())
+        }
+
+        unsafe fn release(&self, ptr: *mut u8, size: usize) {
+            self.reserved.fetch_sub(size, Ordering::SeqCst);
+            alloc::dealloc(ptr, layout(size));
+        }
+    }
+
+    #[test]
+    fn test_memory_from_page_allocator() {
+        let allocator = Arc::new(HeapAllocator {
+            reserved: AtomicUsize::new(0),
+        });
+        let mut memory =
+            Memory::allocate_with(Allocator(allocator.clone()), 5000, Protect::ReadWrite).unwrap();
+        assert_eq!(memory.size(), 8192);
+        assert_eq!(allocator.reserved.load(Ordering::SeqCst), 8192);
+
+        unsafe {
+            memory.as_slice_mut()[0] = 1;
+            memory.reset(1).unwrap();
+            assert_eq!(memory.as_slice()[0], 0);
+        }
+        assert_eq!(memory.protection(), Protect::None);
+
+        drop(memory);
+        assert_eq!(allocator.reserved.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn test_code_memory() {
+        #[cfg(target_arch = "x86_64")]
+        let ret: &[u8] = &[0xc3];
+        #[cfg(target_arch = "aarch64")]
+        let ret: &[u8] = &0xd65f03c0u32.to_le_bytes();
+
+        let mut memory = Memory::with_size_for_code(ret.len()).unwrap();
+        unsafe {
+            memory.protect(.., Protect::ReadWrite).unwrap();
+            memory.as_slice_mut()[..ret.len()].copy_from_slice(ret);
+            memory.protect(.., Protect::ReadExec).unwrap();
+
+            let func: extern "C" fn() = std::mem::transmute(memory.as_ptr());
+            func();
+        }
+        assert_eq!(memory.protection(), Protect::ReadExec);
+        assert!(!memory.protection().is_writable());
+    }
+}
@@ -0,0 +1,256 @@
+//! Pooling of the memories, tables and instances that instantiation
+//! allocates.
+//!
+//! Reserving the address space of a static memory and releasing it
+//! again are relatively expensive, and at high instantiation rates
+//! that churn dominates. Once [`configure`] has been called, static
+//! memories are taken from a pool that's reserved up front, and are
+//! reset and returned to it when they're dropped. The storage of
+//! `anyfunc` tables is reused the same way.
+//!
+//! The pool can also put a ceiling on the number of static memories
+//! and instances that exist at once, so that the memory used by a
+//! host running many tenants is predictable.
+//!
+//! Dynamic memories, which are the ones without a maximum size, and
+//! shared memories aren't pooled.
+//!
+//! [`configure`]: fn.configure.html
+
+use crate::{
+    error::CreationError,
+    memory::{SAFE_STATIC_GUARD_SIZE, SAFE_STATIC_HEAP_SIZE},
+    sys, vm,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// How many of each resource to pool and how many may exist at once.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolConfig {
+    /// The number of static memories to reserve up front.
+    pub memories: usize,
+    /// The number of table storages to keep around for reuse.
+    pub tables: usize,
+    /// If set, creating a static memory fails once all of the pooled
+    /// ones are in use, rather than reserving a new one.
+    pub fixed_memories: bool,
+    /// The most instances that may exist at once, if limited.
+    pub max_instances: Option<usize>,
+}
+
+/// A snapshot of the use of the pool, as returned by [`stats`].
+///
+/// [`stats`]: fn.stats.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Pooled static memories that are ready to be used.
+    pub idle_memories: usize,
+    /// Static memories that are in use, pooled or not.
+    pub memories_in_use: usize,
+    /// Pooled table storages that are ready to be used.
+    pub idle_tables: usize,
+    /// Instances that exist.
+    pub instances: usize,
+}
+
+struct Pool {
+    config: PoolConfig,
+    memories: Vec<sys::Memory>,
+    tables: Vec<Vec<vm::Anyfunc>>,
+    memories_in_use: usize,
+    instances: usize,
+}
+
+// The pooled table storages are always empty, so no pointers to
+// functions or contexts are shared between threads.
+unsafe impl Send for Pool {}
+
+impl Pool {
+    fn new() -> Self {
+        Pool {
+            config: PoolConfig::default(),
+            memories: Vec::new(),
+            tables: Vec::new(),
+            memories_in_use: 0,
+            instances: 0,
+        }
+    }
+
+    fn configure(&mut self, config: PoolConfig) -> Result<(), CreationError> {
+        self.memories.truncate(config.memories);
+        self.tables.truncate(config.tables);
+        while self.memories.len() < config.memories {
+            let memory = reserve_static_memory()?;
+            self.memories.push(memory);
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            idle_memories: self.memories.len(),
+            memories_in_use: self.memories_in_use,
+            idle_tables: self.tables.len(),
+            instances: self.instances,
+        }
+    }
+
+    fn take_static_memory(&mut self) -> Result<sys::Memory, CreationError> {
+        let memory = match self.memories.pop() {
+            Some(memory) => memory,
+            None if self.config.fixed_memories => return Err(CreationError::UnableToCreateMemory),
+            None => reserve_static_memory()?,
+        };
+        self.memories_in_use += 1;
+        Ok(memory)
+    }
+
+    fn return_static_memory(&mut self, mut memory: sys::Memory, used: usize) {
+        self.memories_in_use -= 1;
+        if self.memories.len() < self.config.memories && unsafe { memory.reset(used) }.is_ok() {
+            self.memories.push(memory);
+        }
+    }
+
+    fn take_table(&mut self, len: usize) -> Vec<vm::Anyfunc> {
+        let mut backing = self.tables.pop().unwrap_or_default();
+        backing.resize(len, vm::Anyfunc::null());
+        backing
+    }
+
+    fn return_table(&mut self, mut backing: Vec<vm::Anyfunc>) {
+        if self.tables.len() < self.config.tables {
+            backing.clear();
+            self.tables.push(backing);
+        }
+    }
+
+    fn take_instance(&mut self) -> Result<(), CreationError> {
+        match self.config.max_instances {
+            Some(max) if self.instances >= max => Err(CreationError::InstanceLimitReached),
+            _ => {
+                self.instances += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn return_instance(&mut self) {
+        self.instances -= 1;
+    }
+}
+
+fn reserve_static_memory() -> Result<sys::Memory, CreationError> {
+    sys::Memory::with_size(SAFE_STATIC_HEAP_SIZE + SAFE_STATIC_GUARD_SIZE)
+        .map_err(|_| CreationError::UnableToCreateMemory)
+}
+
+lazy_static! {
+    static ref POOL: Mutex<Pool> = Mutex::new(Pool::new());
+}
+
+/// Configure the pool, reserving any static memories that it's short of.
+///
+/// Memories and tables in use are unaffected, but the limits apply to
+/// everything created from now on.
+pub fn configure(config: PoolConfig) -> Result<(), CreationError> {
+    POOL.lock().configure(config)
+}
+
+/// How much of the pool is in use right now.
+pub fn stats() -> PoolStats {
+    POOL.lock().stats()
+}
+
+/// The reservation for a new static memory, which is inaccessible.
+pub(crate) fn take_static_memory() -> Result<sys::Memory, CreationError> {
+    POOL.lock().take_static_memory()
+}
+
+/// Give back a static memory, of which the first `used` bytes may
+/// have been accessible.
+pub(crate) fn return_static_memory(memory: sys::Memory, used: usize) {
+    POOL.lock().return_static_memory(memory, used)
+}
+
+/// Storage for a table of `len` null elements.
+pub(crate) fn take_table(len: usize) -> Vec<vm::Anyfunc> {
+    POOL.lock().take_table(len)
+}
+
+pub(crate) fn return_table(backing: Vec<vm::Anyfunc>) {
+    POOL.lock().return_table(backing)
+}
+
+/// Held by every instance, so that their number can be limited.
+pub(crate) struct InstanceSlot(());
+
+impl InstanceSlot {
+    pub(crate) fn take() -> Result<Self, CreationError> {
+        POOL.lock().take_instance()?;
+        Ok(InstanceSlot(()))
+    }
+}
+
+impl Drop for InstanceSlot {
+    fn drop(&mut self) {
+        POOL.lock().return_instance();
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use super::*;
+
+    // These use a pool of their own, since other tests create memories
+    // and instances from the global one concurrently.
+
+    #[test]
+    fn test_memories_are_reused_and_reset() {
+        let mut pool = Pool::new();
+        pool.configure(PoolConfig {
+            memories: 1,
+            fixed_memories: true,
+            ..PoolConfig::default()
+        })
+        .unwrap();
+
+        let mut memory = pool.take_static_memory().unwrap();
+        let ptr = memory.as_ptr();
+        assert!(pool.take_static_memory().is_err());
+        assert_eq!(pool.stats().memories_in_use, 1);
+
+        unsafe {
+            memory.protect(0..4096, sys::Protect::ReadWrite).unwrap();
+            memory.as_slice_mut()[0] = 42;
+        }
+        pool.return_static_memory(memory, 4096);
+        assert_eq!(pool.stats().idle_memories, 1);
+
+        let mut memory = pool.take_static_memory().unwrap();
+        assert_eq!(memory.as_ptr(), ptr);
+        unsafe {
+            memory.protect(0..4096, sys::Protect::ReadWrite).unwrap();
+            assert_eq!(memory.as_slice()[0], 0);
+        }
+    }
+
+    #[test]
+    fn test_instance_limit() {
+        let mut pool = Pool::new();
+        pool.configure(PoolConfig {
+            max_instances: Some(2),
+            ..PoolConfig::default()
+        })
+        .unwrap();
+
+        pool.take_instance().unwrap();
+        pool.take_instance().unwrap();
+        assert!(pool.take_instance().is_err());
+        pool.return_instance();
+        pool.take_instance().unwrap();
+        assert_eq!(pool.stats().instances, 2);
+    }
+}
@@ -0,0 +1,284 @@
+//! The reference types of the reference types proposal.
+//!
+//! WebAssembly holds references as integer handles into
+//! process-wide registries, with zero being the null reference.
+//! A handle is only ever created by the runtime, so WebAssembly
+//! can't forge one, and handles are never reused, so a handle that
+//! outlives what it referred to is simply unknown to the registry.
+//!
+//! The registries don't keep what they refer to alive. The host
+//! owns the value of an [`ExternRef`], and the function of a
+//! [`FuncRef`] is forgotten once the instance or table that owns
+//! its context is dropped.
+//!
+//! [`ExternRef`]: struct.ExternRef.html
+//! [`FuncRef`]: struct.FuncRef.html
+
+use crate::vm;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    sync::{Arc, Weak},
+};
+
+lazy_static! {
+    static ref EXTERN_VALUES: Mutex<ExternValues> = Mutex::new(ExternValues {
+        values: HashMap::new(),
+        last_handle: 0,
+        prune_at: MIN_PRUNE_AT,
+    });
+    static ref FUNC_REFS: Mutex<FuncRefs> = Mutex::new(FuncRefs {
+        anyfuncs: HashMap::new(),
+        handles: HashMap::new(),
+        last_handle: 0,
+    });
+}
+
+/// The fewest values that the registry of extern values holds
+/// before the ones that have been dropped are removed from it.
+const MIN_PRUNE_AT: usize = 64;
+
+struct ExternValues {
+    values: HashMap<u64, Weak<dyn Any + Send + Sync>>,
+    last_handle: u64,
+    /// The number of values at which the dropped ones are removed.
+    prune_at: usize,
+}
+
+struct FuncRefs {
+    anyfuncs: HashMap<u64, vm::Anyfunc>,
+    handles: HashMap<(usize, usize, u32), u64>,
+    last_handle: u64,
+}
+
+// The anyfuncs are only ever dereferenced by the instances they
+// are called from, never by the registry.
+unsafe impl Send for FuncRefs {}
+
+/// An opaque reference to a host value, the `externref` type.
+///
+/// WebAssembly can store and pass around an `ExternRef`, but only
+/// the host can look at the value that it refers to.
+///
+/// Since the runtime can't tell when WebAssembly stops using a
+/// reference, the value isn't kept alive by it. The host keeps the
+/// value alive for as long as it holds an `Arc` of it; after that,
+/// the reference can still be passed around, but not downcast.
+///
+/// # Usage:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use wasmer_runtime_core::refs::ExternRef;
+/// let value = Arc::new(String::from("host value"));
+/// let reference = ExternRef::new(&value);
+/// assert_eq!(*reference.downcast::<String>().unwrap(), "host value");
+/// assert!(reference.downcast::<u32>().is_none());
+/// assert!(ExternRef::null().downcast::<String>().is_none());
+///
+/// drop(value);
+/// assert!(reference.downcast::<String>().is_none());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct ExternRef(u64);
+
+impl ExternRef {
+    /// A new reference to `value`.
+    pub fn new<T: Any + Send + Sync>(value: &Arc<T>) -> Self {
+        let value: Arc<dyn Any + Send + Sync> = value.clone();
+
+        let mut registry = EXTERN_VALUES.lock();
+        if registry.values.len() >= registry.prune_at {
+            registry.values.retain(|_, value| value.upgrade().is_some());
+            registry.prune_at = MIN_PRUNE_AT.max(registry.values.len() * 2);
+        }
+
+        registry.last_handle += 1;
+        let handle = registry.last_handle;
+        registry.values.insert(handle, Arc::downgrade(&value));
+        ExternRef(handle)
+    }
+
+    /// The null reference.
+    pub fn null() -> Self {
+        ExternRef(0)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The value that this refers to, if it isn't null, is still
+    /// alive and is a `T`.
+    pub fn downcast<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        if self.is_null() {
+            return None;
+        }
+
+        let value = EXTERN_VALUES.lock().values.get(&self.0)?.upgrade()?;
+        value.downcast().ok()
+    }
+
+    #[doc(hidden)]
+    pub fn from_bits(bits: u64) -> Self {
+        ExternRef(bits)
+    }
+
+    #[doc(hidden)]
+    pub fn to_bits(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A reference to a function, the `funcref` type.
+///
+/// Referencing the same function twice gives equal references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct FuncRef(u64);
+
+impl FuncRef {
+    /// The null reference.
+    pub fn null() -> Self {
+        FuncRef(0)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The reference to the function in a table slot.
+    pub(crate) fn from_anyfunc(anyfunc: vm::Anyfunc) -> Self {
+        if anyfunc.func.is_null() {
+            return FuncRef::null();
+        }
+
+        let mut func_refs = FUNC_REFS.lock();
+        let key = (
+            anyfunc.func as usize,
+            anyfunc.ctx as usize,
+            anyfunc.sig_id.0,
+        );
+        if let Some(&handle) = func_refs.handles.get(&key) {
+            return FuncRef(handle);
+        }
+
+        func_refs.last_handle += 1;
+        let handle = func_refs.last_handle;
+        func_refs.anyfuncs.insert(handle, anyfunc);
+        func_refs.handles.insert(key, handle);
+        FuncRef(handle)
+    }
+
+    /// The table slot for the function that this refers to, or `None`
+    /// if the function has been forgotten or was never referenced.
+    pub(crate) fn anyfunc(&self) -> Option<vm::Anyfunc> {
+        if self.is_null() {
+            return Some(vm::Anyfunc::null());
+        }
+
+        FUNC_REFS.lock().anyfuncs.get(&self.0).cloned()
+    }
+
+    #[doc(hidden)]
+    pub fn from_bits(bits: u64) -> Self {
+        FuncRef(bits)
+    }
+
+    #[doc(hidden)]
+    pub fn to_bits(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Forget the references to the functions that are called with one
+/// of `ctxs`, which are about to be freed.
+pub(crate) fn forget_funcs(ctxs: &[*mut vm::Ctx]) {
+    let mut func_refs = FUNC_REFS.lock();
+    if func_refs.handles.is_empty() {
+        return;
+    }
+
+    let mut forgotten = Vec::new();
+    func_refs.handles.retain(|&(_, ctx, _), &mut handle| {
+        let keep = !ctxs.contains(&(ctx as *mut vm::Ctx));
+        if !keep {
+            forgotten.push(handle);
+        }
+        keep
+    });
+    for handle in forgotten {
+        func_refs.anyfuncs.remove(&handle);
+    }
+}
+
+#[cfg(test)]
+mod refs_tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_func_refs_are_interned() {
+        let func = 0x1000 as *const vm::Func;
+        let anyfunc = |ctx| vm::Anyfunc {
+            func,
+            ctx,
+            sig_id: vm::SigId(0),
+        };
+
+        let first = FuncRef::from_anyfunc(anyfunc(ptr::null_mut()));
+        assert!(!first.is_null());
+        assert_eq!(FuncRef::from_anyfunc(anyfunc(ptr::null_mut())), first);
+        assert_ne!(FuncRef::from_anyfunc(anyfunc(0x2000 as *mut _)), first);
+        assert_eq!(first.anyfunc().unwrap().func, func);
+
+        assert!(FuncRef::from_anyfunc(vm::Anyfunc::null()).is_null());
+        assert!(FuncRef::null().anyfunc().unwrap().func.is_null());
+    }
+
+    #[test]
+    fn test_forgotten_func_refs() {
+        let ctx = 0x3000 as *mut vm::Ctx;
+        let anyfunc = vm::Anyfunc {
+            func: 0x1000 as *const vm::Func,
+            ctx,
+            sig_id: vm::SigId(0),
+        };
+
+        let reference = FuncRef::from_anyfunc(anyfunc);
+        assert!(reference.anyfunc().is_some());
+
+        forget_funcs(&[ctx]);
+        assert!(reference.anyfunc().is_none());
+        // Referencing the function again doesn't revive the old handle.
+        assert_ne!(FuncRef::from_anyfunc(anyfunc), reference);
+        assert!(reference.anyfunc().is_none());
+    }
+
+    #[test]
+    fn test_unknown_handles() {
+        assert!(FuncRef::from_bits(u64::max_value()).anyfunc().is_none());
+        assert!(ExternRef::from_bits(u64::max_value())
+            .downcast::<u32>()
+            .is_none());
+    }
+
+    #[test]
+    fn test_extern_values_are_not_kept_alive() {
+        let value = Arc::new(42u32);
+        let reference = ExternRef::new(&value);
+        assert_eq!(*reference.downcast::<u32>().unwrap(), 42);
+
+        drop(value);
+        assert!(reference.downcast::<u32>().is_none());
+
+        // Dropped values are removed from the registry as it grows.
+        for i in 0..MIN_PRUNE_AT * 16 {
+            ExternRef::new(&Arc::new(i));
+        }
+        assert!(EXTERN_VALUES.lock().values.len() < MIN_PRUNE_AT * 16);
+    }
+}
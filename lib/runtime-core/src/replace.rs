@@ -0,0 +1,254 @@
+//! Replacing the code of a function of an instance while it runs.
+//!
+//! [`Instance::replace_func`] swaps the code of one of the functions
+//! that an instance defines for the code of the same function in
+//! another compile of its module, such as one with that function
+//! edited, so a change can be tried out without losing the state of
+//! the instance. From then on, the function's calls from the other
+//! functions of the instance, through its tables, and through
+//! [`Instance::call`] and its exports go to the new code. Calls that
+//! are running when the function is replaced finish in the old code,
+//! as do calls through a [`Func`] that was resolved before.
+//!
+//! The new code runs against the memories, tables and globals of the
+//! instance, so the two modules have to declare the same signatures,
+//! functions, imports, memories, tables and globals, and only the
+//! bodies of their functions can differ. The code of functions can
+//! only be replaced with backends that call them through the table in
+//! [`vm::Ctx`], which the Cranelift and LLVM backends do and the
+//! singlepass backend doesn't.
+//!
+//! [`Instance::replace_func`]: ../struct.Instance.html#method.replace_func
+//! [`Instance::call`]: ../struct.Instance.html#method.call
+//! [`Func`]: ../struct.Func.html
+//! [`vm::Ctx`]: ../vm/struct.Ctx.html
+
+use crate::{
+    error::ErrorCode,
+    instance::InstanceInner,
+    module::{ExportIndex, ModuleInfo, ModuleInner},
+    structures::{Map, TypedIndex},
+    table::Table,
+    types::{ElementType, LocalOrImport},
+    vm,
+};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The export isn't a function that the instance defines.
+    NotLocalFunction(String),
+    /// The replacement isn't a compile of the same module.
+    IncompatibleModule(String),
+    /// The backend calls functions directly, so they can't be replaced.
+    Unsupported,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotLocalFunction(name) => {
+                write!(f, "{} is not a function defined by the instance", name)
+            }
+            Error::IncompatibleModule(msg) => write!(f, "Incompatible module: {}", msg),
+            Error::Unsupported => write!(f, "The backend cannot replace functions"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::NotLocalFunction(_) => ErrorCode::ReplaceNotLocalFunction,
+            Error::IncompatibleModule(_) => ErrorCode::ReplaceIncompatibleModule,
+            Error::Unsupported => ErrorCode::ReplaceUnsupported,
+        }
+    }
+}
+
+/// Replace the code of the exported function `name` of an instance of
+/// `module` with the code of the same function in `replacement`, which
+/// the instance has to keep alive.
+pub(crate) fn replace_func(
+    module: &ModuleInner,
+    inner: &mut InstanceInner,
+    name: &str,
+    replacement: &ModuleInner,
+) -> Result<(), Error> {
+    let local_func_index = match module.info.exports.get(name) {
+        Some(ExportIndex::Func(func_index)) => match func_index.local_or_import(&module.info) {
+            LocalOrImport::Local(local_func_index) => local_func_index,
+            LocalOrImport::Import(_) => return Err(Error::NotLocalFunction(name.to_string())),
+        },
+        _ => return Err(Error::NotLocalFunction(name.to_string())),
+    };
+
+    if !module.func_resolver.calls_through_ctx() || !replacement.func_resolver.calls_through_ctx() {
+        return Err(Error::Unsupported);
+    }
+    check_compatible(&module.info, &replacement.info)?;
+
+    let new = replacement
+        .func_resolver
+        .get(replacement, local_func_index)
+        .ok_or_else(|| Error::IncompatibleModule("the function has no code".to_string()))?
+        .as_ptr() as *const vm::Func;
+    let old = inner.backing.local_functions[local_func_index];
+    inner.backing.local_functions[local_func_index] = new;
+
+    // Tables hold the code of the functions that are in them too,
+    // including tables of other instances that this one imports.
+    let vmctx = inner.vmctx;
+    let tables = inner
+        .backing
+        .tables
+        .iter()
+        .map(|(_, table)| table)
+        .chain(inner.import_backing.tables.iter().map(|(_, table)| table));
+    for table in tables {
+        replace_in_table(table, vmctx, old, new);
+    }
+
+    Ok(())
+}
+
+fn replace_in_table(
+    table: &Table,
+    vmctx: *mut vm::Ctx,
+    old: *const vm::Func,
+    new: *const vm::Func,
+) {
+    if table.descriptor().element != ElementType::Anyfunc {
+        return;
+    }
+    table.anyfunc_direct_access_mut(|anyfuncs| {
+        for anyfunc in anyfuncs
+            .iter_mut()
+            .filter(|anyfunc| anyfunc.func == old && anyfunc.ctx == vmctx)
+        {
+            anyfunc.func = new;
+        }
+    });
+}
+
+/// Check that the code compiled for `other` can run in an instance
+/// of `info`.
+fn check_compatible(info: &ModuleInfo, other: &ModuleInfo) -> Result<(), Error> {
+    let incompatible = |msg: &str| Err(Error::IncompatibleModule(msg.to_string()));
+
+    if info.backend != other.backend || info.memory_style != other.memory_style {
+        return incompatible("it was compiled by another backend or for other memories");
+    }
+    if !same(&info.signatures, &other.signatures, |a, b| a == b)
+        || !same(&info.func_assoc, &other.func_assoc, |a, b| a == b)
+        || info.imported_functions.len() != other.imported_functions.len()
+    {
+        return incompatible("the signatures or the functions differ");
+    }
+    if !same(&info.memories, &other.memories, |a, b| a == b)
+        || !same(&info.imported_memories, &other.imported_memories, |a, b| {
+            a.1 == b.1
+        })
+    {
+        return incompatible("the memories differ");
+    }
+    if !same(&info.tables, &other.tables, |a, b| a == b)
+        || !same(&info.imported_tables, &other.imported_tables, |a, b| {
+            a.1 == b.1
+        })
+    {
+        return incompatible("the tables differ");
+    }
+    if !same(&info.globals, &other.globals, |a, b| a.desc == b.desc)
+        || !same(&info.imported_globals, &other.imported_globals, |a, b| {
+            a.1 == b.1
+        })
+    {
+        return incompatible("the globals differ");
+    }
+    Ok(())
+}
+
+fn same<K: TypedIndex, V>(a: &Map<K, V>, b: &Map<K, V>, eq: impl Fn(&V, &V) -> bool) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|((_, a), (_, b))| eq(a, b))
+}
+
+#[cfg(test)]
+mod replace_tests {
+    use super::*;
+    use crate::{
+        backend::Backend,
+        memory::MemoryStyle,
+        module::StringTable,
+        types::{FuncSig, MemoryDescriptor, SigIndex, Type},
+        units::Pages,
+    };
+    use hashbrown::HashMap;
+
+    /// A module with one function and a memory of `minimum` pages.
+    fn info(minimum: u32) -> ModuleInfo {
+        let mut signatures = Map::new();
+        signatures.push(FuncSig::new(vec![Type::I32], vec![Type::I32]));
+        let mut func_assoc = Map::new();
+        func_assoc.push(SigIndex::new(0));
+        let mut memories = Map::new();
+        memories.push(MemoryDescriptor {
+            minimum: Pages(minimum),
+            maximum: None,
+            shared: false,
+        });
+
+        ModuleInfo {
+            memories,
+            globals: Map::new(),
+            tables: Map::new(),
+
+            imported_functions: Map::new(),
+            imported_memories: Map::new(),
+            imported_tables: Map::new(),
+            imported_globals: Map::new(),
+
+            exports: HashMap::new(),
+
+            data_initializers: Vec::new(),
+            elem_initializers: Vec::new(),
+
+            passive_data: HashMap::new(),
+            passive_elements: HashMap::new(),
+
+            start_func: None,
+
+            func_assoc,
+            signatures,
+            backend: Backend::Cranelift,
+            memory_style: MemoryStyle::Static,
+            initial_points: None,
+
+            namespace_table: StringTable::new(),
+            name_table: StringTable::new(),
+
+            custom_sections: HashMap::new(),
+            func_names: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_compatible() {
+        assert!(check_compatible(&info(1), &info(1)).is_ok());
+
+        match check_compatible(&info(1), &info(2)) {
+            Err(Error::IncompatibleModule(msg)) => assert!(msg.contains("memories")),
+            other => panic!("expected the memories to differ, got {:?}", other),
+        }
+
+        let mut other = info(1);
+        other.backend = Backend::LLVM;
+        assert!(check_compatible(&info(1), &other).is_err());
+
+        let mut other = info(1);
+        other.func_assoc.push(SigIndex::new(0));
+        assert!(check_compatible(&info(1), &other).is_err());
+    }
+}
@@ -1,6 +1,19 @@
+//! The registry of every function signature that the process has seen.
+//!
+//! Each distinct signature gets one index, whichever module it comes
+//! from, and that index, as a [`vm::SigId`], is what anyfuncs and the
+//! compiled code of `call_indirect` compare, so checking the signature
+//! of an indirect call is a single integer comparison.
+//!
+//! Almost every lookup is of a signature that has been seen before, so
+//! lookups only take the registry's write lock to add a new one.
+//!
+//! [`vm::SigId`]: ../vm/struct.SigId.html
+
 use crate::{
-    structures::Map,
+    structures::{Map, TypedIndex},
     types::{FuncSig, SigIndex},
+    vm,
 };
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
@@ -23,6 +36,16 @@ struct GlobalSigRegistry {
     sig_assoc: Map<SigIndex, Arc<FuncSig>>,
 }
 
+impl GlobalSigRegistry {
+    fn register(&mut self, func_sig: Arc<FuncSig>) -> SigIndex {
+        let sig_assoc = &mut self.sig_assoc;
+        *self
+            .func_table
+            .entry(Arc::clone(&func_sig))
+            .or_insert_with(|| sig_assoc.push(func_sig))
+    }
+}
+
 #[derive(Debug)]
 pub struct SigRegistry;
 
@@ -32,37 +55,70 @@ impl SigRegistry {
         Sig: Into<Arc<FuncSig>>,
     {
         let func_sig = func_sig.into();
-        let mut global = (*GLOBAL_SIG_REGISTRY).write();
-        let global = &mut *global;
-
-        let func_table = &mut global.func_table;
-        let sig_assoc = &mut global.sig_assoc;
+        let known = GLOBAL_SIG_REGISTRY
+            .read()
+            .func_table
+            .get(&*func_sig)
+            .cloned();
+        match known {
+            Some(sig_index) => sig_index,
+            None => GLOBAL_SIG_REGISTRY.write().register(func_sig),
+        }
+    }
 
-        let sig_index = *func_table
-            .entry(Arc::clone(&func_sig))
-            .or_insert_with(|| sig_assoc.push(func_sig));
+    /// The id that anyfuncs of functions with `func_sig` carry, and
+    /// that indirect calls expecting `func_sig` check them against.
+    pub fn lookup_sig_id(&self, func_sig: &FuncSig) -> vm::SigId {
+        let known = GLOBAL_SIG_REGISTRY.read().func_table.get(func_sig).cloned();
+        let sig_index = match known {
+            Some(sig_index) => sig_index,
+            None => GLOBAL_SIG_REGISTRY
+                .write()
+                .register(Arc::new(func_sig.clone())),
+        };
 
-        sig_index
+        vm::SigId(sig_index.index() as u32)
     }
 
     pub fn lookup_signature(&self, sig_index: SigIndex) -> Arc<FuncSig> {
-        let global = (*GLOBAL_SIG_REGISTRY).read();
+        let global = GLOBAL_SIG_REGISTRY.read();
         Arc::clone(&global.sig_assoc[sig_index])
     }
 
     pub fn lookup_signature_ref(&self, func_sig: &FuncSig) -> Arc<FuncSig> {
-        let mut global = (*GLOBAL_SIG_REGISTRY).write();
-        let global = &mut *global;
-
-        let func_table = &mut global.func_table;
-        let sig_assoc = &mut global.sig_assoc;
-
-        if func_table.contains_key(func_sig) {
-            Arc::clone(&sig_assoc[func_table[func_sig]])
-        } else {
-            let arc = Arc::new(func_sig.clone());
-            func_table.insert(Arc::clone(&arc), sig_assoc.push(Arc::clone(&arc)));
-            arc
+        {
+            let global = GLOBAL_SIG_REGISTRY.read();
+            if let Some(&sig_index) = global.func_table.get(func_sig) {
+                return Arc::clone(&global.sig_assoc[sig_index]);
+            }
         }
+
+        let mut global = GLOBAL_SIG_REGISTRY.write();
+        let sig_index = global.register(Arc::new(func_sig.clone()));
+        Arc::clone(&global.sig_assoc[sig_index])
+    }
+}
+
+#[cfg(test)]
+mod sig_registry_tests {
+    use super::*;
+    use crate::types::Type;
+
+    #[test]
+    fn test_sig_ids_are_canonical() {
+        let sig = FuncSig::new(vec![Type::I32, Type::F64], vec![Type::I64]);
+        let other = FuncSig::new(vec![Type::F64, Type::I32], vec![Type::I64]);
+
+        let sig_id = SigRegistry.lookup_sig_id(&sig);
+        assert_eq!(SigRegistry.lookup_sig_id(&sig.clone()).0, sig_id.0);
+        assert_ne!(SigRegistry.lookup_sig_id(&other).0, sig_id.0);
+
+        let sig_index = SigRegistry.lookup_sig_index(sig.clone());
+        assert_eq!(sig_index.index() as u32, sig_id.0);
+        assert_eq!(*SigRegistry.lookup_signature(sig_index), sig);
+        assert!(Arc::ptr_eq(
+            &SigRegistry.lookup_signature_ref(&sig),
+            &SigRegistry.lookup_signature(sig_index)
+        ));
     }
 }
@@ -0,0 +1,660 @@
+//! Snapshots of the state of an instance.
+//!
+//! A [`Snapshot`] holds the contents of the memories, globals and
+//! tables that an instance defines itself. It can be restored into
+//! any instance of the same module, which makes it possible to
+//! checkpoint an instance, or to run the start function and other
+//! initialization once and then start many instances from there.
+//!
+//! Imported memories, globals and tables belong to the host and
+//! aren't part of a snapshot. Tables refer to functions by their
+//! index, but `externref`s are only meaningful in the process that
+//! took the snapshot.
+//!
+//! An [`InstanceTemplate`] uses a snapshot to make new instances of a
+//! module cheaply, without running its initializers again.
+//!
+//! [`Snapshot`]: struct.Snapshot.html
+//! [`InstanceTemplate`]: struct.InstanceTemplate.html
+
+use crate::{
+    backing::LocalBacking,
+    error::{self, ErrorCode, GrowError},
+    import::ImportObject,
+    instance::Instance,
+    instance::InstanceInner,
+    module::ModuleInner,
+    refs::ExternRef,
+    structures::TypedIndex,
+    sys,
+    types::{ElementType, FuncIndex, GlobalDescriptor},
+    units::Pages,
+    vm,
+};
+use hashbrown::HashMap;
+use std::{fmt, sync::Arc};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The snapshot was taken from an instance of a different module.
+    IncompatibleModule(String),
+    /// A table holds a function that isn't one of the instance's
+    /// own functions or imports, so it can't be referred to by index.
+    UnknownFunction {
+        table: usize,
+        element: usize,
+    },
+    CouldNotGrow(GrowError),
+    DeserializeError(String),
+    SerializeError(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IncompatibleModule(msg) => write!(f, "Incompatible module: {}", msg),
+            Error::UnknownFunction { table, element } => write!(
+                f,
+                "Element {} of table {} is not a function of the instance",
+                element, table
+            ),
+            Error::CouldNotGrow(err) => write!(f, "Could not grow: {}", err),
+            Error::DeserializeError(msg) => write!(f, "Could not deserialize snapshot: {}", msg),
+            Error::SerializeError(msg) => write!(f, "Could not serialize snapshot: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CouldNotGrow(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::IncompatibleModule(_) => ErrorCode::SnapshotIncompatibleModule,
+            Error::UnknownFunction { .. } => ErrorCode::SnapshotUnknownFunction,
+            Error::CouldNotGrow(_) => ErrorCode::SnapshotCouldNotGrow,
+            Error::DeserializeError(_) => ErrorCode::SnapshotDeserialize,
+            Error::SerializeError(_) => ErrorCode::SnapshotSerialize,
+        }
+    }
+}
+
+/// The state of an instance, as returned by [`Instance::snapshot`].
+///
+/// [`Instance::snapshot`]: ../struct.Instance.html#method.snapshot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    func_count: usize,
+    memories: Vec<MemorySnapshot>,
+    globals: Vec<GlobalSnapshot>,
+    tables: Vec<TableSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MemorySnapshot {
+    pages: u32,
+    #[serde(with = "serde_bytes")]
+    contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct GlobalSnapshot {
+    desc: GlobalDescriptor,
+    data: u64,
+}
+
+/// Extern references are saved as their handles, which are only
+/// meaningful in the process that took the snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum TableSnapshot {
+    Anyfunc(Vec<Option<FuncIndex>>),
+    ExternRef(Vec<u64>),
+}
+
+impl Snapshot {
+    pub(crate) fn take(module: &ModuleInner, inner: &InstanceInner) -> Result<Self, Error> {
+        let backing = &inner.backing;
+
+        let memories = backing
+            .memories
+            .iter()
+            .map(|(_, memory)| {
+                let mut contents = vec![0; memory.size().bytes().0];
+                memory.view::<u8>().copy_to(&mut contents);
+                MemorySnapshot {
+                    pages: memory.size().0,
+                    contents,
+                }
+            })
+            .collect();
+
+        let globals = backing
+            .globals
+            .iter()
+            .map(|(_, global)| GlobalSnapshot {
+                desc: global.descriptor(),
+                data: global.raw_data(),
+            })
+            .collect();
+
+        // Table slots hold raw function pointers, which are different
+        // in every instance, so they are saved as function indices.
+        let func_indices: HashMap<(usize, usize), FuncIndex> = (0..module.info.func_assoc.len())
+            .map(|index| {
+                let func_index = FuncIndex::new(index);
+                let anyfunc = Self::anyfunc(module, inner, func_index);
+                ((anyfunc.func as usize, anyfunc.ctx as usize), func_index)
+            })
+            .collect();
+
+        let mut tables = Vec::with_capacity(backing.tables.len());
+        for (table_index, table) in backing.tables.iter() {
+            let table = match table.descriptor().element {
+                ElementType::Anyfunc => {
+                    TableSnapshot::Anyfunc(table.anyfunc_direct_access_mut(|anyfuncs| {
+                        anyfuncs
+                            .iter()
+                            .enumerate()
+                            .map(|(element, anyfunc)| {
+                                if anyfunc.func.is_null() {
+                                    return Ok(None);
+                                }
+                                func_indices
+                                    .get(&(anyfunc.func as usize, anyfunc.ctx as usize))
+                                    .map(|&func_index| Some(func_index))
+                                    .ok_or(Error::UnknownFunction {
+                                        table: table_index.index(),
+                                        element,
+                                    })
+                            })
+                            .collect::<Result<_, _>>()
+                    })?)
+                }
+                ElementType::ExternRef => {
+                    TableSnapshot::ExternRef(table.externref_direct_access_mut(|externrefs| {
+                        externrefs.iter().map(ExternRef::to_bits).collect()
+                    }))
+                }
+            };
+            tables.push(table);
+        }
+
+        Ok(Snapshot {
+            func_count: module.info.func_assoc.len(),
+            memories,
+            globals,
+            tables,
+        })
+    }
+
+    /// Restore this snapshot into an instance. The memories that
+    /// have an image in `images` map it rather than being copied into.
+    pub(crate) fn restore(
+        &self,
+        module: &ModuleInner,
+        inner: &InstanceInner,
+        images: &[Option<sys::MemoryImage>],
+    ) -> Result<(), Error> {
+        self.check_compatible(module, inner)?;
+        let backing = &inner.backing;
+
+        for (index, ((_, memory), snapshot)) in
+            backing.memories.iter().zip(&self.memories).enumerate()
+        {
+            let pages = Pages(snapshot.pages);
+            if memory.size() < pages {
+                memory
+                    .grow(Pages(pages.0 - memory.size().0))
+                    .map_err(Error::CouldNotGrow)?;
+            }
+
+            let mapped = match images.get(index) {
+                Some(Some(image)) => memory.map_image(image).unwrap_or(false),
+                _ => false,
+            };
+            if !mapped {
+                memory.view::<u8>().copy_from(&snapshot.contents);
+            }
+        }
+
+        // Immutable globals can't have changed since instantiation, and
+        // may have been initialized from an import that differs now.
+        for ((_, global), snapshot) in backing.globals.iter().zip(&self.globals) {
+            if snapshot.desc.mutable {
+                global.set_raw_data(snapshot.data);
+            }
+        }
+
+        for ((_, table), snapshot) in backing.tables.iter().zip(&self.tables) {
+            let len = match snapshot {
+                TableSnapshot::Anyfunc(elements) => elements.len(),
+                TableSnapshot::ExternRef(elements) => elements.len(),
+            };
+            if (table.size() as usize) < len {
+                table
+                    .grow((len - table.size() as usize) as u32)
+                    .map_err(Error::CouldNotGrow)?;
+            }
+
+            match snapshot {
+                TableSnapshot::Anyfunc(elements) => table.anyfunc_direct_access_mut(|anyfuncs| {
+                    for (anyfunc, element) in anyfuncs.iter_mut().zip(elements) {
+                        *anyfunc = match *element {
+                            Some(func_index) => Self::anyfunc(module, inner, func_index),
+                            None => vm::Anyfunc::null(),
+                        };
+                    }
+                }),
+                TableSnapshot::ExternRef(elements) => {
+                    table.externref_direct_access_mut(|externrefs| {
+                        for (externref, &bits) in externrefs.iter_mut().zip(elements) {
+                            *externref = ExternRef::from_bits(bits);
+                        }
+                    })
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check everything that could fail other than growing, so that
+    /// the instance isn't left half restored.
+    fn check_compatible(&self, module: &ModuleInner, inner: &InstanceInner) -> Result<(), Error> {
+        let backing = &inner.backing;
+        let incompatible = |msg: &str| Err(Error::IncompatibleModule(msg.to_string()));
+
+        if self.func_count != module.info.func_assoc.len()
+            || self.memories.len() != backing.memories.len()
+            || self.globals.len() != backing.globals.len()
+            || self.tables.len() != backing.tables.len()
+        {
+            return incompatible("the number of functions, memories, globals or tables differs");
+        }
+
+        for ((_, memory), snapshot) in backing.memories.iter().zip(&self.memories) {
+            if memory.size() > Pages(snapshot.pages) {
+                return incompatible("a memory is larger than in the snapshot");
+            }
+        }
+
+        for ((_, global), snapshot) in backing.globals.iter().zip(&self.globals) {
+            if global.descriptor() != snapshot.desc {
+                return incompatible("a global has a different type");
+            }
+        }
+
+        for ((_, table), snapshot) in backing.tables.iter().zip(&self.tables) {
+            let (element_type, len) = match snapshot {
+                TableSnapshot::Anyfunc(elements) => (ElementType::Anyfunc, elements.len()),
+                TableSnapshot::ExternRef(elements) => (ElementType::ExternRef, elements.len()),
+            };
+            if table.descriptor().element != element_type {
+                return incompatible("a table has a different element type");
+            }
+            if table.size() as usize > len {
+                return incompatible("a table is larger than in the snapshot");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn anyfunc(module: &ModuleInner, inner: &InstanceInner, func_index: FuncIndex) -> vm::Anyfunc {
+        LocalBacking::anyfunc(
+            module,
+            &inner.import_backing,
+            &inner.backing.dynamic_sigindices,
+            &inner.backing.local_functions,
+            inner.vmctx,
+            func_index,
+        )
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        serde_bench::serialize(&mut buffer, self)
+            .map_err(|e| Error::SerializeError(e.to_string()))?;
+        Ok(buffer)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        serde_bench::deserialize(bytes).map_err(|e| Error::DeserializeError(format!("{:#?}", e)))
+    }
+}
+
+/// Instances of a module that start out in the state that another
+/// instance was in after it was instantiated.
+///
+/// The template instantiates the module once, which runs its data
+/// and element segments and its start function, and takes a snapshot
+/// of the result. Each instance made from the template restores that
+/// snapshot instead of running them again.
+///
+/// On Linux, the contents of the memories are kept in in-memory files
+/// that the new instances map copy-on-write, so the pages an instance
+/// never writes to are shared with the template rather than copied.
+///
+/// The imports are shared by the template and all of its instances,
+/// and so is anything that the start function did to them.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::{Module, imports, error::Result};
+/// # fn serve(module: &Module) -> Result<()> {
+/// let template = module.template(&imports! {})?;
+/// for _request in 0..3 {
+///     let instance = template.instantiate()?;
+///     // ...
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct InstanceTemplate {
+    module: Arc<ModuleInner>,
+    import_object: ImportObject,
+    snapshot: Snapshot,
+    images: Vec<Option<sys::MemoryImage>>,
+    /// The memory images of the module, which instances hand back.
+    module_images: Arc<Vec<Option<sys::MemoryImage>>>,
+}
+
+impl InstanceTemplate {
+    pub(crate) fn new(
+        module: Arc<ModuleInner>,
+        module_images: Arc<Vec<Option<sys::MemoryImage>>>,
+        import_object: &ImportObject,
+    ) -> error::Result<Self> {
+        let instance = Instance::new(
+            Arc::clone(&module),
+            Arc::clone(&module_images),
+            import_object,
+        )?;
+        let snapshot = instance.snapshot()?;
+
+        // Memories that can't be mapped are copied into instead.
+        let images = snapshot
+            .memories
+            .iter()
+            .map(|memory| sys::MemoryImage::new(&memory.contents).ok())
+            .collect();
+
+        Ok(InstanceTemplate {
+            module,
+            import_object: import_object.clone_ref(),
+            snapshot,
+            images,
+            module_images,
+        })
+    }
+
+    /// Make a new instance in the state that the template was made in.
+    pub fn instantiate(&self) -> error::Result<Instance> {
+        let mut instance = Instance::new_uninitialized(
+            Arc::clone(&self.module),
+            &self.import_object,
+            false,
+            Arc::clone(&self.module_images),
+        )?;
+        instance.restore_with_images(&self.snapshot, &self.images)?;
+        Ok(instance)
+    }
+
+    /// The snapshot that instances of this template start from.
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+    use crate::{
+        backend::{
+            sys::Memory as SysMemory, Backend, CacheGen, FuncResolver, ProtectedCaller, Token,
+            UserTrapper,
+        },
+        backing::{ImportBacking, LocalBacking},
+        cache::Error as CacheError,
+        error::RuntimeResult,
+        export::Export,
+        import::{ImportObject, LikeNamespace},
+        instance::Instance,
+        memory::MemoryStyle,
+        module::{DataInitializer, ExportIndex, ModuleInfo, StringTable},
+        structures::Map,
+        table::Element,
+        types::{
+            GlobalIndex, GlobalInit, Initializer, LocalFuncIndex, MemoryDescriptor, MemoryIndex,
+            TableDescriptor, TableIndex, Type, Value,
+        },
+    };
+    use std::{any::Any, ptr::NonNull, sync::Arc};
+
+    struct Placeholder;
+    impl FuncResolver for Placeholder {
+        fn get(&self, _: &ModuleInner, _: LocalFuncIndex) -> Option<NonNull<vm::Func>> {
+            None
+        }
+    }
+    impl ProtectedCaller for Placeholder {
+        fn call(
+            &self,
+            _: &ModuleInner,
+            _: FuncIndex,
+            _: &[Value],
+            _: &ImportBacking,
+            _: *mut vm::Ctx,
+            _: Token,
+        ) -> RuntimeResult<Vec<Value>> {
+            Ok(vec![])
+        }
+        fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+            f();
+            Ok(())
+        }
+        fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
+            Box::new(Placeholder)
+        }
+    }
+    impl UserTrapper for Placeholder {
+        unsafe fn do_early_trap(&self, _: Box<dyn Any>) -> ! {
+            unimplemented!()
+        }
+    }
+    impl CacheGen for Placeholder {
+        fn generate_cache(
+            &self,
+            _: &ModuleInner,
+        ) -> Result<(Box<ModuleInfo>, Box<[u8]>, SysMemory), CacheError> {
+            unimplemented!()
+        }
+    }
+
+    /// A module with an initialized memory, a mutable and an immutable
+    /// global, and an `externref` table, but no functions.
+    fn module() -> Arc<ModuleInner> {
+        let mut memories = Map::new();
+        memories.push(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(2)),
+            shared: false,
+        });
+        let mut globals = Map::new();
+        for &mutable in &[true, false] {
+            globals.push(GlobalInit {
+                desc: GlobalDescriptor {
+                    mutable,
+                    ty: Type::I32,
+                },
+                init: Initializer::Const(Value::I32(1)),
+            });
+        }
+        let mut tables = Map::new();
+        tables.push(TableDescriptor {
+            element: ElementType::ExternRef,
+            minimum: 1,
+            maximum: None,
+        });
+
+        let mut exports = HashMap::new();
+        exports.insert(
+            "memory".to_string(),
+            ExportIndex::Memory(MemoryIndex::new(0)),
+        );
+        exports.insert(
+            "counter".to_string(),
+            ExportIndex::Global(GlobalIndex::new(0)),
+        );
+        exports.insert("table".to_string(), ExportIndex::Table(TableIndex::new(0)));
+
+        Arc::new(ModuleInner {
+            func_resolver: Box::new(Placeholder),
+            protected_caller: Box::new(Placeholder),
+            cache_gen: Box::new(Placeholder),
+            info: ModuleInfo {
+                memories,
+                globals,
+                tables,
+
+                imported_functions: Map::new(),
+                imported_memories: Map::new(),
+                imported_tables: Map::new(),
+                imported_globals: Map::new(),
+
+                exports,
+
+                data_initializers: vec![DataInitializer {
+                    memory_index: MemoryIndex::new(0),
+                    base: Initializer::Const(Value::I32(16)),
+                    data: b"initialized".to_vec(),
+                }],
+                elem_initializers: Vec::new(),
+
+                passive_data: HashMap::new(),
+                passive_elements: HashMap::new(),
+
+                start_func: None,
+
+                func_assoc: Map::new(),
+                signatures: Map::new(),
+                backend: Backend::Cranelift,
+                memory_style: MemoryStyle::Static,
+                initial_points: None,
+
+                namespace_table: StringTable::new(),
+                name_table: StringTable::new(),
+
+                custom_sections: HashMap::new(),
+                func_names: HashMap::new(),
+            },
+        })
+    }
+
+    fn memory(instance: &Instance) -> crate::memory::Memory {
+        match instance.get_export("memory") {
+            Some(Export::Memory(memory)) => memory,
+            _ => panic!("expected a memory"),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let module = module();
+        let mut first =
+            Instance::new(Arc::clone(&module), Arc::default(), &ImportObject::new()).unwrap();
+        let mut second =
+            Instance::new(Arc::clone(&module), Arc::default(), &ImportObject::new()).unwrap();
+        let value = Arc::new("host value");
+        let reference = ExternRef::new(&value);
+        let offset = Pages(1).bytes().0 + 7;
+
+        memory(&first).grow(Pages(1)).unwrap();
+        memory(&first).view::<u8>()[offset].set(42);
+        first.global("counter").unwrap().set(Value::I32(5));
+        let table = first.table("table").unwrap();
+        table.grow(1).unwrap();
+        table.set(1, Element::ExternRef(reference)).unwrap();
+
+        let bytes = first.snapshot().unwrap().serialize().unwrap();
+        let snapshot = Snapshot::deserialize(&bytes).unwrap();
+        second.restore(&snapshot).unwrap();
+
+        assert_eq!(memory(&second).size(), Pages(2));
+        assert_eq!(memory(&second).view::<u8>()[offset].get(), 42);
+        assert_eq!(second.global("counter").unwrap().get(), Value::I32(5));
+        assert_eq!(
+            second.table("table").unwrap().get(1),
+            Some(Value::ExternRef(reference))
+        );
+
+        // An instance that has grown past the snapshot can't go back.
+        table.grow(1).unwrap();
+        assert!(first.restore(&snapshot).is_err());
+        assert!(Snapshot::deserialize(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_template() {
+        let template =
+            InstanceTemplate::new(module(), Arc::default(), &ImportObject::new()).unwrap();
+        if cfg!(target_os = "linux") {
+            assert!(template.images.iter().all(Option::is_some));
+        }
+        let first = template.instantiate().unwrap();
+        let second = template.instantiate().unwrap();
+
+        let read = |instance: &Instance| {
+            let mut bytes = [0; 11];
+            memory(instance).view().subarray(16, 27).copy_to(&mut bytes);
+            bytes
+        };
+        assert_eq!(&read(&first), b"initialized");
+
+        // Writing to one instance's memory doesn't affect the others.
+        memory(&first)
+            .view()
+            .subarray(16, 27)
+            .copy_from(b"overwritten");
+        first.global("counter").unwrap().set(Value::I32(2));
+        assert_eq!(&read(&first), b"overwritten");
+        assert_eq!(&read(&second), b"initialized");
+        assert_eq!(&read(&template.instantiate().unwrap()), b"initialized");
+        assert_eq!(second.global("counter").unwrap().get(), Value::I32(1));
+    }
+
+    #[test]
+    fn test_instantiate_from_memory_images() {
+        let module = module();
+        let images = Arc::new(LocalBacking::memory_images(&module.info));
+        if cfg!(target_os = "linux") {
+            assert_eq!(images[0].as_ref().unwrap().size(), page_size::get());
+        }
+
+        let first = Instance::new(
+            Arc::clone(&module),
+            Arc::clone(&images),
+            &ImportObject::new(),
+        )
+        .unwrap();
+        let second = Instance::new(
+            Arc::clone(&module),
+            Arc::clone(&images),
+            &ImportObject::new(),
+        )
+        .unwrap();
+        memory(&first).view()[16].set(b'I');
+
+        let mut bytes = [0; 11];
+        memory(&second).view().subarray(16, 27).copy_to(&mut bytes);
+        assert_eq!(&bytes, b"initialized");
+        assert_eq!(memory(&first).view::<u8>()[16].get(), b'I');
+    }
+}
@@ -0,0 +1,586 @@
+//! The native stack that WebAssembly code runs on.
+//!
+//! Backends run guest code through [`run_on_wasm_stack`], which, on
+//! supported platforms, switches to a separately allocated stack with
+//! a guard region below it. Deep recursion in the guest then faults
+//! on the guard region, which the trap handler reports as a
+//! [`TrapCode::StackOverflow`] instead of overflowing the stack of the
+//! host.
+//!
+//! The size of that stack can be changed with [`set_max_stack_size`].
+//!
+//! Calls made with `Instance::call_async` run on a fiber instead, which
+//! has a stack of its own that stays around while the call is
+//! suspended. Backends keep the state of the call that's running in
+//! thread-locals, which they save and restore around switches between
+//! fibers with the hooks they pass to [`register_switch_hook`].
+//!
+//! [`run_on_wasm_stack`]: fn.run_on_wasm_stack.html
+//! [`TrapCode::StackOverflow`]: ../error/enum.TrapCode.html
+//! [`set_max_stack_size`]: fn.set_max_stack_size.html
+//! [`register_switch_hook`]: fn.register_switch_hook.html
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default size of the stack that WebAssembly code runs on.
+pub const DEFAULT_MAX_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// The size of the inaccessible region below the stack. It is larger
+/// than a page, since compiled code doesn't probe the stack and a
+/// single large frame could otherwise skip over it.
+const GUARD_SIZE: usize = 64 * 1024;
+
+/// The size of the stack that signal handlers run on.
+const SIGNAL_STACK_SIZE: usize = 64 * 1024;
+
+static MAX_STACK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_STACK_SIZE);
+
+/// Set the size, in bytes, of the stack that WebAssembly code runs on.
+///
+/// This takes effect for calls into WebAssembly made after it returns,
+/// on every thread. Platforms that don't support running guest code
+/// on a separate stack ignore it.
+pub fn set_max_stack_size(bytes: usize) {
+    MAX_STACK_SIZE.store(bytes, Ordering::SeqCst);
+}
+
+/// The size, in bytes, of the stack that WebAssembly code runs on.
+pub fn max_stack_size() -> usize {
+    MAX_STACK_SIZE.load(Ordering::SeqCst)
+}
+
+/// Run `f`, which calls into WebAssembly, on the stack set aside for it.
+///
+/// If the calling thread is already running on that stack, e.g. because
+/// WebAssembly called a host function that calls back into WebAssembly,
+/// `f` is run directly.
+///
+/// This must be called after the backend has set up its trap handling,
+/// so that a trap unwinds back to the caller.
+#[doc(hidden)]
+pub fn run_on_wasm_stack<T>(f: impl FnOnce() -> T) -> T {
+    imp::ensure_signal_stack();
+
+    let mut f = Some(f);
+    let mut ret = None;
+    imp::run_on_wasm_stack(&mut || ret = Some((f.take().unwrap())()));
+    ret.unwrap()
+}
+
+/// Whether a fault at `addr` was caused by WebAssembly code
+/// running out of the stack of the current thread.
+#[doc(hidden)]
+pub fn is_stack_overflow(addr: *const u8) -> bool {
+    imp::is_guard_page(addr as usize)
+}
+
+/// Saves the state that a backend keeps in thread-locals for the call
+/// into WebAssembly that's running, and returns a function that puts
+/// it back.
+#[doc(hidden)]
+pub type SwitchHook = fn() -> Box<dyn FnOnce()>;
+
+lazy_static! {
+    static ref SWITCH_HOOKS: Mutex<Vec<SwitchHook>> = Mutex::new(vec![]);
+}
+
+/// Have the state that `hook` saves follow fibers as they're suspended
+/// and resumed. Registering the same hook again does nothing.
+#[doc(hidden)]
+pub fn register_switch_hook(hook: SwitchHook) {
+    let mut hooks = SWITCH_HOOKS.lock();
+    if !hooks
+        .iter()
+        .any(|registered| *registered as usize == hook as usize)
+    {
+        hooks.push(hook);
+    }
+}
+
+/// Run `switch`, which switches to another fiber and eventually back,
+/// with the state of the backends put back as it was afterwards.
+fn switch_with_hooks(switch: impl FnOnce()) {
+    let restores: Vec<_> = SWITCH_HOOKS.lock().iter().map(|hook| hook()).collect();
+    switch();
+    for restore in restores {
+        restore();
+    }
+}
+
+/// A call that runs on a stack of its own, so that it can [`suspend`]
+/// partway through and be resumed later. Fibers are only supported on
+/// x86_64 Linux.
+///
+/// A fiber that is dropped while it's suspended never runs the rest of
+/// its call. Its stack is freed without unwinding it, so what the
+/// frames on it own, including what the call captured, is leaked.
+///
+/// [`suspend`]: fn.suspend.html
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub(crate) struct Fiber<'a> {
+    inner: imp::Fiber<'a>,
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl<'a> Fiber<'a> {
+    pub fn new(call: Box<dyn FnOnce() + 'a>) -> Self {
+        Fiber {
+            inner: imp::Fiber::new(call),
+        }
+    }
+
+    /// Run the call until it returns or suspends, returning whether
+    /// it has returned. A panic in the call is resumed here.
+    pub fn resume(&mut self) -> bool {
+        self.inner.resume()
+    }
+}
+
+/// Whether the current thread is running a fiber.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub(crate) fn in_fiber() -> bool {
+    imp::in_fiber()
+}
+
+/// Suspend the fiber that is running on the current thread, returning
+/// once it's resumed. Does nothing if there isn't one.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub(crate) fn suspend() {
+    imp::suspend()
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod imp {
+    use super::{max_stack_size, switch_with_hooks, GUARD_SIZE, SIGNAL_STACK_SIZE};
+    use crate::sys::{Memory, Protect};
+    use libc::{c_int, c_void, ucontext_t};
+    use std::{any::Any, cell::Cell, cell::RefCell, mem, panic, ptr};
+
+    extern "C" {
+        fn getcontext(ucp: *mut ucontext_t) -> c_int;
+        fn makecontext(ucp: *mut ucontext_t, func: extern "C" fn(), argc: c_int, ...);
+        fn swapcontext(oucp: *mut ucontext_t, ucp: *const ucontext_t) -> c_int;
+    }
+
+    struct WasmStack {
+        memory: Memory,
+        requested_size: usize,
+    }
+
+    impl WasmStack {
+        fn new(size: usize) -> Option<Self> {
+            let mut memory = Memory::with_size(GUARD_SIZE + size).ok()?;
+            unsafe {
+                memory.protect(GUARD_SIZE.., Protect::ReadWrite).ok()?;
+            }
+            Some(WasmStack {
+                memory,
+                requested_size: size,
+            })
+        }
+
+        fn contains(&self, addr: usize) -> bool {
+            let start = self.memory.as_ptr() as usize;
+            addr >= start && addr < start + self.memory.size()
+        }
+
+        fn guard_contains(&self, addr: usize) -> bool {
+            let start = self.memory.as_ptr() as usize;
+            addr >= start && addr < start + GUARD_SIZE
+        }
+
+        fn usable_size(&self) -> usize {
+            self.memory.size() - GUARD_SIZE
+        }
+    }
+
+    thread_local! {
+        static WASM_STACK: RefCell<Option<WasmStack>> = RefCell::new(None);
+        static SIGNAL_STACK: RefCell<Option<Memory>> = RefCell::new(None);
+        static ENTRY: Cell<Option<*mut dyn FnMut()>> = Cell::new(None);
+        static PANIC: Cell<Option<Box<dyn Any + Send>>> = Cell::new(None);
+        static FIBER: Cell<Option<*mut FiberState>> = Cell::new(None);
+        /// The call of the fiber that is being started, as an
+        /// `Option<F>` for the `F` that `fiber_entry` is run with.
+        static FIBER_CALL: Cell<*mut u8> = Cell::new(ptr::null_mut());
+    }
+
+    pub fn ensure_signal_stack() {
+        SIGNAL_STACK.with(|signal_stack| {
+            let mut signal_stack = signal_stack.borrow_mut();
+            if signal_stack.is_some() {
+                return;
+            }
+
+            let memory = match Memory::with_size_protect(SIGNAL_STACK_SIZE, Protect::ReadWrite) {
+                Ok(memory) => memory,
+                Err(_) => return,
+            };
+
+            let stack = libc::stack_t {
+                ss_sp: memory.as_ptr() as *mut c_void,
+                ss_flags: 0,
+                ss_size: memory.size(),
+            };
+
+            if unsafe { libc::sigaltstack(&stack, ptr::null_mut()) } == 0 {
+                *signal_stack = Some(memory);
+            }
+        });
+    }
+
+    pub fn is_guard_page(addr: usize) -> bool {
+        if let Some(fiber) = FIBER.with(|fiber| fiber.get()) {
+            if unsafe { (*fiber).stack.guard_contains(addr) } {
+                return true;
+            }
+        }
+        WASM_STACK.with(|stack| match &*stack.borrow() {
+            Some(stack) => stack.guard_contains(addr),
+            None => false,
+        })
+    }
+
+    extern "C" fn entry() {
+        let f = ENTRY.with(|entry| entry.take()).unwrap();
+
+        // Unwinding can't cross the context switch, so carry
+        // a panic over to the other side and resume it there.
+        if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe { (*f)() })) {
+            PANIC.with(|cell| cell.set(Some(payload)));
+        }
+    }
+
+    pub fn run_on_wasm_stack(f: &mut dyn FnMut()) {
+        let sp = &f as *const _ as usize;
+        let size = max_stack_size();
+
+        // A fiber's stack has a guard region of its own.
+        if let Some(fiber) = FIBER.with(|fiber| fiber.get()) {
+            if unsafe { (*fiber).stack.contains(sp) } {
+                return f();
+            }
+        }
+
+        let stack_top = WASM_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+
+            if let Some(stack) = &*stack {
+                if stack.contains(sp) {
+                    return None;
+                }
+            }
+
+            let reusable = match &*stack {
+                Some(stack) => stack.requested_size == size,
+                None => false,
+            };
+            if !reusable {
+                *stack = WasmStack::new(size);
+            }
+
+            stack.as_ref().map(|stack| {
+                (
+                    stack.memory.as_ptr() as usize + GUARD_SIZE,
+                    stack.usable_size(),
+                )
+            })
+        });
+
+        let (stack_bottom, stack_size) = match stack_top {
+            Some(stack) => stack,
+            // Either we're on the wasm stack already, or it
+            // couldn't be allocated. Either way, stay put.
+            None => return f(),
+        };
+
+        unsafe {
+            let mut caller: ucontext_t = mem::zeroed();
+            let mut callee: ucontext_t = mem::zeroed();
+
+            if getcontext(&mut callee) != 0 {
+                return f();
+            }
+            callee.uc_stack.ss_sp = stack_bottom as *mut c_void;
+            callee.uc_stack.ss_size = stack_size;
+            callee.uc_link = &mut caller;
+
+            let f: *mut (dyn FnMut() + '_) = f;
+            ENTRY.with(|entry| entry.set(Some(mem::transmute(f))));
+
+            makecontext(&mut callee, entry, 0);
+            swapcontext(&mut caller, &callee);
+        }
+
+        if let Some(payload) = PANIC.with(|cell| cell.take()) {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    pub struct FiberState {
+        stack: WasmStack,
+        context: ucontext_t,
+        /// Where the fiber returns to when it suspends or its call
+        /// returns.
+        caller: ucontext_t,
+        started: bool,
+        finished: bool,
+    }
+
+    /// A fiber whose stack couldn't be allocated runs its call in place.
+    pub enum Fiber<'a> {
+        Switching {
+            state: Box<FiberState>,
+            /// Taken by `fiber_entry` when the fiber is first resumed.
+            call: Option<Box<dyn FnOnce() + 'a>>,
+        },
+        InPlace(Option<Box<dyn FnOnce() + 'a>>),
+    }
+
+    impl<'a> Fiber<'a> {
+        pub fn new(call: Box<dyn FnOnce() + 'a>) -> Self {
+            match WasmStack::new(max_stack_size()) {
+                Some(stack) => Fiber::Switching {
+                    state: Box::new(FiberState {
+                        stack,
+                        context: unsafe { mem::zeroed() },
+                        caller: unsafe { mem::zeroed() },
+                        started: false,
+                        finished: false,
+                    }),
+                    call: Some(call),
+                },
+                None => Fiber::InPlace(Some(call)),
+            }
+        }
+
+        pub fn resume(&mut self) -> bool {
+            let (state, call): (*mut FiberState, _) = match self {
+                Fiber::Switching { state, call } => (&mut **state, call),
+                Fiber::InPlace(call) => {
+                    if let Some(call) = call.take() {
+                        call();
+                    }
+                    return true;
+                }
+            };
+
+            unsafe {
+                if (*state).finished {
+                    return true;
+                }
+                if !(*state).started {
+                    if getcontext(&mut (*state).context) != 0 {
+                        let call = call.take();
+                        *self = Fiber::InPlace(call);
+                        return self.resume();
+                    }
+                    (*state).context.uc_stack.ss_sp =
+                        (*state).stack.memory.as_ptr().add(GUARD_SIZE) as *mut c_void;
+                    (*state).context.uc_stack.ss_size = (*state).stack.usable_size();
+                    (*state).context.uc_link = &mut (*state).caller;
+                    makecontext(
+                        &mut (*state).context,
+                        fiber_entry::<Box<dyn FnOnce() + 'a>>,
+                        0,
+                    );
+                    (*state).started = true;
+                    // Only read before the first switch back, while
+                    // `call` is still borrowed.
+                    FIBER_CALL.with(|cell| cell.set(call as *mut _ as *mut u8));
+                }
+
+                let previous = FIBER.with(|fiber| fiber.replace(Some(state)));
+                switch_with_hooks(|| {
+                    swapcontext(&mut (*state).caller, &(*state).context);
+                });
+                FIBER.with(|fiber| fiber.set(previous));
+            }
+
+            if let Some(payload) = PANIC.with(|cell| cell.take()) {
+                panic::resume_unwind(payload);
+            }
+            unsafe { (*state).finished }
+        }
+    }
+
+    extern "C" fn fiber_entry<F: FnOnce()>() {
+        let state = FIBER.with(|fiber| fiber.get()).unwrap();
+        let call = FIBER_CALL.with(|cell| cell.replace(ptr::null_mut())) as *mut Option<F>;
+        let call = unsafe { (*call).take().unwrap() };
+        if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(call)) {
+            PANIC.with(|cell| cell.set(Some(payload)));
+        }
+        unsafe { (*state).finished = true };
+    }
+
+    pub fn in_fiber() -> bool {
+        FIBER.with(|fiber| fiber.get()).is_some()
+    }
+
+    pub fn suspend() {
+        if let Some(state) = FIBER.with(|fiber| fiber.get()) {
+            switch_with_hooks(|| unsafe {
+                swapcontext(&mut (*state).context, &(*state).caller);
+            });
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+mod imp {
+    pub fn ensure_signal_stack() {}
+
+    pub fn is_guard_page(_addr: usize) -> bool {
+        false
+    }
+
+    pub fn run_on_wasm_stack(f: &mut dyn FnMut()) {
+        f()
+    }
+}
+
+#[cfg(test)]
+mod stack_tests {
+    use super::*;
+
+    #[test]
+    fn test_run_on_wasm_stack() {
+        let outer = 1;
+        let ret = run_on_wasm_stack(|| {
+            let inner = 2;
+            assert!(!is_stack_overflow(&inner as *const i32 as *const u8));
+            // Calling in again while on the wasm stack runs in place.
+            run_on_wasm_stack(|| outer + inner)
+        });
+        assert_eq!(ret, 3);
+    }
+
+    #[test]
+    fn test_panic_crosses_stack_switch() {
+        let result = std::panic::catch_unwind(|| run_on_wasm_stack(|| panic!("from wasm stack")));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn test_fiber_borrows_from_caller() {
+        use std::cell::Cell;
+
+        let steps = Cell::new(0);
+        let mut fiber = Fiber::new(Box::new(|| {
+            steps.set(1);
+            suspend();
+            steps.set(2);
+        }));
+        assert!(!fiber.resume());
+        assert_eq!(steps.get(), 1);
+        assert!(fiber.resume());
+        assert_eq!(steps.get(), 2);
+
+        // Dropping a suspended fiber leaves the rest of its call unrun.
+        let mut fiber = Fiber::new(Box::new(|| {
+            suspend();
+            steps.set(3);
+        }));
+        assert!(!fiber.resume());
+        drop(fiber);
+        assert_eq!(steps.get(), 2);
+    }
+
+    /// Set in the process that `test_overflow_hits_guard_page` runs
+    /// the overflow in, since it installs a handler for `SIGSEGV`.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    const GUARD_PAGE_CHILD: &str = "WASMER_TEST_GUARD_PAGE_CHILD";
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn test_overflow_hits_guard_page() {
+        use std::{env, process::Command};
+
+        if env::var_os(GUARD_PAGE_CHILD).is_some() {
+            overflow_into_guard_page();
+            return;
+        }
+
+        let status = Command::new(env::current_exe().unwrap())
+            .args(&[
+                "--exact",
+                "stack::stack_tests::test_overflow_hits_guard_page",
+                "--test-threads=1",
+            ])
+            .env(GUARD_PAGE_CHILD, "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// Overflow the wasm stack and check that the fault is on its
+    /// guard page.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn overflow_into_guard_page() {
+        use libc::{
+            c_int, c_void, sigaction, sigemptyset, siginfo_t, SA_ONSTACK, SA_SIGINFO, SIGSEGV,
+        };
+        use std::{
+            mem, ptr,
+            sync::atomic::{AtomicBool, Ordering},
+        };
+
+        extern "C" {
+            fn setjmp(env: *mut c_void) -> c_int;
+            fn longjmp(env: *mut c_void, val: c_int) -> !;
+        }
+
+        const FRAME_SIZE: usize = 256;
+
+        static mut JMP_BUF: [u64; 27] = [0; 27];
+        static HIT_GUARD: AtomicBool = AtomicBool::new(false);
+
+        extern "C" fn handler(_signum: c_int, siginfo: *mut siginfo_t, _: *mut c_void) {
+            #[repr(C)]
+            struct SigInfo {
+                si_signo: i32,
+                si_errno: i32,
+                si_code: i32,
+                si_addr: u64,
+            }
+
+            unsafe {
+                let addr = (*(siginfo as *const SigInfo)).si_addr;
+                HIT_GUARD.store(is_stack_overflow(addr as *const u8), Ordering::SeqCst);
+                longjmp(JMP_BUF.as_mut_ptr() as *mut c_void, 1);
+            }
+        }
+
+        // Deep enough that the frames can't all fit on the stack.
+        fn recurse(depth: usize) -> u64 {
+            let mut frame = [0u8; FRAME_SIZE];
+            unsafe { ptr::write_volatile(&mut frame[0], depth as u8) };
+            if depth == 0 {
+                return 0;
+            }
+            recurse(depth - 1) + unsafe { ptr::read_volatile(&frame[0]) } as u64
+        }
+
+        unsafe {
+            let mut action: sigaction = mem::zeroed();
+            action.sa_sigaction = handler as usize;
+            action.sa_flags = SA_ONSTACK | SA_SIGINFO;
+            sigemptyset(&mut action.sa_mask);
+            let mut previous: sigaction = mem::zeroed();
+            sigaction(SIGSEGV, &action, &mut previous);
+
+            if setjmp(JMP_BUF.as_mut_ptr() as *mut c_void) == 0 {
+                run_on_wasm_stack(|| recurse(max_stack_size() / FRAME_SIZE));
+                unreachable!();
+            }
+
+            sigaction(SIGSEGV, &previous, ptr::null_mut());
+        }
+
+        assert!(HIT_GUARD.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,112 @@
+//! Stack maps, which tell the runtime where the reference-typed values
+//! of a function are at each of its safepoints, so that the references
+//! that are held by the frames on the stack can be found while the code
+//! is stopped at one, for a collector or to snapshot an instance in the
+//! middle of a call.
+//!
+//! The safepoints of a function are its calls to other WebAssembly
+//! functions, each identified by the offset of its return address in the
+//! code of the function, which is what a walk of the stack finds in the
+//! frames of the callers.
+//!
+//! Backends make a stack map for each local function, which
+//! [`Module::stack_map`] returns. The dynasm backend records the
+//! parameters and locals of reference type, which it keeps in the frame
+//! for the whole call. The references that are only on the value stack
+//! at a safepoint aren't recorded yet, and the other backends don't make
+//! stack maps.
+//!
+//! [`Module::stack_map`]: ../struct.Module.html#method.stack_map
+
+/// Where a reference-typed value is while the code is stopped at a
+/// safepoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefLocation {
+    /// In the frame of the function, at this offset in bytes from its
+    /// frame pointer, `rbp` on x86-64.
+    Frame(i32),
+}
+
+/// The reference-typed values that are live at a safepoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Safepoint {
+    /// The offset of the return address of the call in the code of the
+    /// function.
+    pub offset: usize,
+    pub refs: Vec<RefLocation>,
+}
+
+/// The safepoints of a function, in the order of their offsets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackMap {
+    safepoints: Vec<Safepoint>,
+}
+
+impl StackMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a safepoint, which has to come after the ones added so far.
+    pub fn push(&mut self, safepoint: Safepoint) {
+        if let Some(last) = self.safepoints.last() {
+            assert!(
+                last.offset < safepoint.offset,
+                "safepoints must be added in order"
+            );
+        }
+        self.safepoints.push(safepoint);
+    }
+
+    pub fn safepoints(&self) -> &[Safepoint] {
+        &self.safepoints
+    }
+
+    /// The safepoint whose return address is at `offset` in the code of
+    /// the function, if there is one.
+    pub fn lookup(&self, offset: usize) -> Option<&Safepoint> {
+        let index = self
+            .safepoints
+            .binary_search_by_key(&offset, |safepoint| safepoint.offset)
+            .ok()?;
+        Some(&self.safepoints[index])
+    }
+}
+
+#[cfg(test)]
+mod stackmap_tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut stack_map = StackMap::new();
+        for &offset in &[8, 24, 40] {
+            stack_map.push(Safepoint {
+                offset,
+                refs: vec![RefLocation::Frame(-(offset as i32))],
+            });
+        }
+
+        assert_eq!(stack_map.safepoints().len(), 3);
+        assert_eq!(
+            stack_map.lookup(24).map(|safepoint| &safepoint.refs[..]),
+            Some(&[RefLocation::Frame(-24)][..])
+        );
+        assert!(stack_map.lookup(25).is_none());
+        assert!(stack_map.lookup(0).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_push_out_of_order() {
+        let mut stack_map = StackMap::new();
+        stack_map.push(Safepoint {
+            offset: 16,
+            refs: Vec::new(),
+        });
+        stack_map.push(Safepoint {
+            offset: 8,
+            refs: Vec::new(),
+        });
+    }
+}
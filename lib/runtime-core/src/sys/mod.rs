@@ -60,8 +60,16 @@ impl<'de> Deserialize<'de> for Memory {
                     .next_element()?
                     .ok_or_else(|| de::Error::invalid_length(1, &self))?;
 
-                let mut memory = Memory::with_size_protect(bytes.len(), Protect::ReadWrite)
-                    .expect("Could not create a memory");
+                let mut memory = if original_protection == Protect::ReadExec {
+                    let mut memory =
+                        Memory::with_size_for_code(bytes.len()).expect("Could not create a memory");
+                    unsafe { memory.protect(.., Protect::ReadWrite) }
+                        .expect("Could not make the memory writable");
+                    memory
+                } else {
+                    Memory::with_size_protect(bytes.len(), Protect::ReadWrite)
+                        .expect("Could not create a memory")
+                };
 
                 unsafe {
                     memory.as_slice_mut().copy_from_slice(&*bytes);
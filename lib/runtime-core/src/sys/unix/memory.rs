@@ -1,10 +1,11 @@
 use crate::error::MemoryCreationError;
 use crate::error::MemoryProtectionError;
+use crate::platform::{self, Allocator};
 use errno;
 use nix::libc;
 use page_size;
 use std::ops::{Bound, RangeBounds};
-use std::{fs::File, os::unix::io::IntoRawFd, path::Path, ptr, rc::Rc, slice};
+use std::{fs::File, io, os::unix::io::IntoRawFd, path::Path, ptr, rc::Rc, slice};
 
 unsafe impl Send for Memory {}
 unsafe impl Sync for Memory {}
@@ -15,6 +16,11 @@ pub struct Memory {
     size: usize,
     protection: Protect,
     fd: Option<Rc<RawFd>>,
+    /// The allocator that the memory came from, if it isn't the
+    /// operating system.
+    allocator: Option<Allocator>,
+    /// Whether the memory was mapped with `MAP_JIT`, for code.
+    jit: bool,
 }
 
 impl Memory {
@@ -50,6 +56,8 @@ impl Memory {
                 size: file_len as usize,
                 protection,
                 fd: Some(Rc::new(raw_fd)),
+                allocator: None,
+                jit: false,
             })
         }
     }
@@ -61,9 +69,15 @@ impl Memory {
                 size: 0,
                 protection,
                 fd: None,
+                allocator: None,
+                jit: false,
             });
         }
 
+        if let Some(allocator) = platform::page_allocator() {
+            return Self::allocate_with(allocator, size, protection);
+        }
+
         let size = round_up_to_page_size(size, page_size::get());
 
         let ptr = unsafe {
@@ -85,6 +99,8 @@ impl Memory {
                 size,
                 protection,
                 fd: None,
+                allocator: None,
+                jit: false,
             })
         }
     }
@@ -96,9 +112,16 @@ impl Memory {
                 size: 0,
                 protection: Protect::None,
                 fd: None,
+                allocator: None,
+                jit: false,
             });
         }
 
+        if let Some(allocator) = platform::page_allocator() {
+            return Self::allocate_with(allocator, size, Protect::None)
+                .map_err(|e| MemoryCreationError::VirtualMemoryAllocationFailed(size, e));
+        }
+
         let size = round_up_to_page_size(size, page_size::get());
 
         let ptr = unsafe {
@@ -123,10 +146,106 @@ impl Memory {
                 size,
                 protection: Protect::None,
                 fd: None,
+                allocator: None,
+                jit: false,
             })
         }
     }
 
+    /// Reserve `size` bytes of inaccessible memory for compiled code,
+    /// which is made `ReadWrite` to write the code and then `ReadExec`
+    /// to run it.
+    ///
+    /// On macOS, if `platform::set_map_jit` has been called, the memory
+    /// is mapped with `MAP_JIT`. On Apple silicon such memory has to be
+    /// mapped readable, writable and executable, so it starts out
+    /// `ReadExec` and making it writable or executable only changes
+    /// which of the two it is for the calling thread.
+    pub fn with_size_for_code(size: usize) -> Result<Self, MemoryCreationError> {
+        if size == 0 || !cfg!(target_os = "macos") || !platform::map_jit() {
+            return Self::with_size(size);
+        }
+        if platform::page_allocator().is_some() {
+            return Self::with_size(size);
+        }
+
+        let size = round_up_to_page_size(size, page_size::get());
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                JIT_PROTECTION.to_protect_const() as i32,
+                libc::MAP_PRIVATE | libc::MAP_ANON | MAP_JIT,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == -1 as _ {
+            Err(MemoryCreationError::VirtualMemoryAllocationFailed(
+                size,
+                errno::errno().to_string(),
+            ))
+        } else {
+            Ok(Self {
+                ptr: ptr as *mut u8,
+                size,
+                protection: JIT_PROTECTION,
+                fd: None,
+                allocator: None,
+                jit: true,
+            })
+        }
+    }
+
+    /// Allocate with an allocator set through the `platform` module.
+    pub(crate) fn allocate_with(
+        allocator: Allocator,
+        size: usize,
+        protection: Protect,
+    ) -> Result<Self, String> {
+        let size = round_up_to_page_size(size, allocator.0.page_size());
+        let ptr = allocator.0.reserve(size)?;
+        let mut memory = Self {
+            ptr,
+            size,
+            protection: Protect::None,
+            fd: None,
+            allocator: Some(allocator),
+            jit: false,
+        };
+        if protection != Protect::None {
+            unsafe { memory.protect(.., protection) }.map_err(|e| e.to_string())?;
+        }
+        Ok(memory)
+    }
+
+    /// Take over the `size` bytes at `ptr`, which already have
+    /// `protection`, to be protected and released by `allocator`.
+    pub(crate) unsafe fn from_raw_parts(
+        ptr: *mut u8,
+        size: usize,
+        protection: Protect,
+        allocator: Allocator,
+    ) -> Self {
+        Self {
+            ptr,
+            size,
+            protection,
+            fd: None,
+            allocator: Some(allocator),
+            jit: false,
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        match &self.allocator {
+            Some(allocator) => allocator.0.page_size(),
+            None => page_size::get(),
+        }
+    }
+
     pub unsafe fn protect(
         &mut self,
         range: impl RangeBounds<usize>,
@@ -146,13 +265,34 @@ impl Memory {
             Bound::Unbounded => self.size(),
         };
 
-        let page_size = page_size::get();
+        let page_size = self.page_size();
         let start = self
             .ptr
             .add(round_down_to_page_size(range_start, page_size));
         let size = round_up_to_page_size(range_end - range_start, page_size);
         assert!(size <= self.size);
 
+        if self.jit && cfg!(target_arch = "aarch64") {
+            return self.protect_jit(start, size, protection);
+        }
+
+        if let Some(allocator) = &self.allocator {
+            return match allocator.0.protect(start, size, protection) {
+                Ok(()) => {
+                    self.protection = protection;
+                    if protection == Protect::ReadExec {
+                        flush_icache(start, size);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(MemoryProtectionError::ProtectionFailed(
+                    start as usize,
+                    size,
+                    e,
+                )),
+            };
+        }
+
         let success = libc::mprotect(start as _, size, protect as i32);
         if success == -1 {
             Err(MemoryProtectionError::ProtectionFailed(
@@ -162,12 +302,42 @@ impl Memory {
             ))
         } else {
             self.protection = protection;
+            if protection == Protect::ReadExec {
+                flush_icache(start, size);
+            }
             Ok(())
         }
     }
 
+    /// Protect memory mapped with `MAP_JIT` on Apple silicon, which
+    /// stays readable, writable and executable, by switching whether
+    /// the calling thread sees it as writable or as executable.
+    unsafe fn protect_jit(
+        &mut self,
+        start: *mut u8,
+        size: usize,
+        protection: Protect,
+    ) -> Result<(), MemoryProtectionError> {
+        match protection {
+            Protect::ReadWrite => jit_write_protect(false),
+            Protect::ReadExec => {
+                jit_write_protect(true);
+                flush_icache(start, size);
+            }
+            Protect::None | Protect::Read => {
+                return Err(MemoryProtectionError::ProtectionFailed(
+                    start as usize,
+                    size,
+                    "code mapped with MAP_JIT can only be made writable or executable".to_string(),
+                ));
+            }
+        }
+        self.protection = protection;
+        Ok(())
+    }
+
     pub fn split_at(mut self, offset: usize) -> (Memory, Memory) {
-        let page_size = page_size::get();
+        let page_size = self.page_size();
         if offset % page_size == 0 {
             let second_ptr = unsafe { self.ptr.add(offset) };
             let second_size = self.size - offset;
@@ -179,6 +349,8 @@ impl Memory {
                 size: second_size,
                 protection: self.protection,
                 fd: self.fd.clone(),
+                allocator: self.allocator.clone(),
+                jit: self.jit,
             };
 
             (self, second)
@@ -206,11 +378,133 @@ impl Memory {
     pub fn as_ptr(&self) -> *mut u8 {
         self.ptr
     }
+
+    /// Throw away the contents of the first `len` bytes of this
+    /// memory and make them inaccessible again, keeping the address
+    /// space reserved.
+    pub unsafe fn reset(&mut self, len: usize) -> Result<(), MemoryProtectionError> {
+        let size = round_up_to_page_size(len, self.page_size());
+        assert!(size <= self.size);
+        if size == 0 {
+            return Ok(());
+        }
+
+        if let Some(allocator) = &self.allocator {
+            allocator
+                .0
+                .reset(self.ptr, size)
+                .map_err(|e| MemoryProtectionError::ProtectionFailed(self.ptr as usize, size, e))?;
+            self.protection = Protect::None;
+            return Ok(());
+        }
+
+        // Mapping fresh pages over the range also replaces a mapped image.
+        let ptr = libc::mmap(
+            self.ptr as _,
+            size,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_FIXED,
+            -1,
+            0,
+        );
+
+        if ptr == -1 as _ {
+            Err(MemoryProtectionError::ProtectionFailed(
+                self.ptr as usize,
+                size,
+                errno::errno().to_string(),
+            ))
+        } else {
+            self.protection = Protect::None;
+            Ok(())
+        }
+    }
+
+    /// Map `image` copy-on-write over the start of this memory, and
+    /// make that part readable and writable.
+    pub unsafe fn map_image(&mut self, image: &MemoryImage) -> Result<(), MemoryProtectionError> {
+        assert!(image.size <= self.size);
+        assert!(self.allocator.is_none());
+        assert_eq!(image.size % page_size::get(), 0);
+        if image.size == 0 {
+            return Ok(());
+        }
+
+        let ptr = libc::mmap(
+            self.ptr as _,
+            image.size,
+            Protect::ReadWrite.to_protect_const() as i32,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            image.fd.0,
+            0,
+        );
+
+        if ptr == -1 as _ {
+            Err(MemoryProtectionError::ProtectionFailed(
+                self.ptr as usize,
+                image.size,
+                errno::errno().to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The contents of a memory, held in an in-memory file that memories
+/// can map copy-on-write instead of copying the contents into them.
+#[derive(Debug)]
+pub struct MemoryImage {
+    fd: RawFd,
+    size: usize,
+}
+
+impl MemoryImage {
+    /// `contents` must be a multiple of the page size long.
+    #[cfg(target_os = "linux")]
+    pub fn new(contents: &[u8]) -> Result<Self, MemoryCreationError> {
+        use std::{io::Write, os::unix::io::FromRawFd};
+        const MFD_CLOEXEC: libc::c_uint = 1;
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_memfd_create,
+                b"wasmer-memory-image\0".as_ptr(),
+                MFD_CLOEXEC,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut file = unsafe { File::from_raw_fd(fd as i32) };
+        file.write_all(contents)?;
+
+        Ok(Self {
+            fd: RawFd::from_file(file),
+            size: contents.len(),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_contents: &[u8]) -> Result<Self, MemoryCreationError> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "memory images are only supported on linux",
+        )
+        .into())
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
 }
 
 impl Drop for Memory {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
+        if let Some(allocator) = &self.allocator {
+            unsafe { allocator.0.release(self.ptr, self.size) };
+        } else if !self.ptr.is_null() {
             let success = unsafe { libc::munmap(self.ptr as _, self.size) };
             assert_eq!(success, 0, "failed to unmap memory: {}", errno::errno());
         }
@@ -225,7 +519,13 @@ impl Clone for Memory {
             Protect::ReadWrite
         };
 
-        let mut new = Memory::with_size_protect(self.size, temp_protection).unwrap();
+        let mut new = if self.jit {
+            let mut new = Memory::with_size_for_code(self.size).unwrap();
+            unsafe { new.protect(.., temp_protection).unwrap() };
+            new
+        } else {
+            Memory::with_size_protect(self.size, temp_protection).unwrap()
+        };
         unsafe {
             new.as_slice_mut().copy_from_slice(self.as_slice());
 
@@ -238,14 +538,15 @@ impl Clone for Memory {
     }
 }
 
+/// How memory can be accessed. No memory is ever writable and
+/// executable at once: code is written while it's `ReadWrite`, and
+/// then made `ReadExec` to run it.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum Protect {
     None,
     Read,
     ReadWrite,
     ReadExec,
-    ReadWriteExec,
 }
 
 impl Protect {
@@ -255,7 +556,6 @@ impl Protect {
             Protect::Read => 1,
             Protect::ReadWrite => 1 | 2,
             Protect::ReadExec => 1 | 4,
-            Protect::ReadWriteExec => 1 | 2 | 4,
         }
     }
 
@@ -295,6 +595,69 @@ impl Drop for RawFd {
     }
 }
 
+/// Make the `size` bytes at `ptr`, which must be page aligned,
+/// accessible with `protection`, whoever they were allocated by.
+pub(crate) unsafe fn protect_pages(
+    ptr: *mut u8,
+    size: usize,
+    protection: Protect,
+) -> Result<(), String> {
+    if libc::mprotect(ptr as _, size, protection.to_protect_const() as i32) == -1 {
+        Err(errno::errno().to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// `MAP_JIT`, which isn't in every version of `libc`.
+const MAP_JIT: libc::c_int = 0x800;
+
+/// What memory mapped with `MAP_JIT` starts out as. Apple silicon
+/// requires it to be mapped readable, writable and executable, and
+/// new threads see it as executable.
+#[cfg(target_arch = "aarch64")]
+const JIT_PROTECTION: Protect = Protect::ReadExec;
+#[cfg(not(target_arch = "aarch64"))]
+const JIT_PROTECTION: Protect = Protect::None;
+
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+unsafe fn jit_write_protect(enabled: bool) {
+    extern "C" {
+        fn pthread_jit_write_protect_np(enabled: libc::c_int);
+    }
+    pthread_jit_write_protect_np(enabled as libc::c_int);
+}
+
+#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+unsafe fn jit_write_protect(_enabled: bool) {
+    unreachable!("only Apple silicon protects code mapped with MAP_JIT per thread")
+}
+
+/// Make sure that the instruction cache holds no stale copies of the
+/// `size` bytes at `ptr`, which have just been written, before they're
+/// run. x86 keeps its instruction cache coherent by itself.
+#[cfg(target_os = "macos")]
+pub fn flush_icache(ptr: *const u8, size: usize) {
+    extern "C" {
+        fn sys_icache_invalidate(start: *mut libc::c_void, len: libc::size_t);
+    }
+    unsafe { sys_icache_invalidate(ptr as _, size) }
+}
+
+#[cfg(all(
+    not(target_os = "macos"),
+    any(target_arch = "arm", target_arch = "aarch64")
+))]
+pub fn flush_icache(ptr: *const u8, size: usize) {
+    extern "C" {
+        fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+    }
+    unsafe { __clear_cache(ptr as _, ptr.add(size) as _) }
+}
+
+#[cfg(not(any(target_os = "macos", target_arch = "arm", target_arch = "aarch64")))]
+pub fn flush_icache(_ptr: *const u8, _size: usize) {}
+
 /// Round `size` up to the nearest multiple of `page_size`.
 fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
     (size + (page_size - 1)) & !(page_size - 1)
@@ -1,3 +1,4 @@
 mod memory;
 
-pub use self::memory::{Memory, Protect};
+pub(crate) use self::memory::protect_pages;
+pub use self::memory::{flush_icache, Memory, MemoryImage, Protect};
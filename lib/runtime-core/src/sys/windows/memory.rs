@@ -1,9 +1,11 @@
 use crate::error::MemoryCreationError;
 use crate::error::MemoryProtectionError;
+use crate::platform::{self, Allocator};
 use page_size;
 use std::ops::{Bound, RangeBounds};
-use std::{ptr, slice};
-use winapi::um::memoryapi::{VirtualAlloc, VirtualFree};
+use std::{io, ptr, slice};
+use winapi::um::memoryapi::{VirtualAlloc, VirtualFree, VirtualProtect};
+use winapi::um::processthreadsapi::{FlushInstructionCache, GetCurrentProcess};
 use winapi::um::winnt::{
     MEM_COMMIT, MEM_DECOMMIT, MEM_RESERVE, PAGE_EXECUTE_READ, PAGE_NOACCESS, PAGE_READONLY,
     PAGE_READWRITE,
@@ -17,6 +19,9 @@ pub struct Memory {
     ptr: *mut u8,
     size: usize,
     protection: Protect,
+    /// The allocator that the memory came from, if it isn't the
+    /// operating system.
+    allocator: Option<Allocator>,
 }
 
 impl Memory {
@@ -26,9 +31,14 @@ impl Memory {
                 ptr: ptr::null_mut(),
                 size: 0,
                 protection,
+                allocator: None,
             });
         }
 
+        if let Some(allocator) = platform::page_allocator() {
+            return Self::allocate_with(allocator, size, protection);
+        }
+
         let size = round_up_to_page_size(size, page_size::get());
 
         let protect = protection.to_protect_const();
@@ -42,6 +52,7 @@ impl Memory {
                 ptr: ptr as *mut u8,
                 size,
                 protection,
+                allocator: None,
             })
         }
     }
@@ -52,9 +63,15 @@ impl Memory {
                 ptr: ptr::null_mut(),
                 size: 0,
                 protection: Protect::None,
+                allocator: None,
             });
         }
 
+        if let Some(allocator) = platform::page_allocator() {
+            return Self::allocate_with(allocator, size, Protect::None)
+                .map_err(|e| MemoryCreationError::VirtualMemoryAllocationFailed(size, e));
+        }
+
         let size = round_up_to_page_size(size, page_size::get());
 
         let ptr = unsafe { VirtualAlloc(ptr::null_mut(), size, MEM_RESERVE, PAGE_NOACCESS) };
@@ -69,10 +86,61 @@ impl Memory {
                 ptr: ptr as *mut u8,
                 size,
                 protection: Protect::None,
+                allocator: None,
             })
         }
     }
 
+    /// Reserve `size` bytes of inaccessible memory for compiled code,
+    /// which is made `ReadWrite` to write the code and then `ReadExec`
+    /// to run it.
+    pub fn with_size_for_code(size: usize) -> Result<Self, MemoryCreationError> {
+        Self::with_size(size)
+    }
+
+    /// Allocate with an allocator set through the `platform` module.
+    pub(crate) fn allocate_with(
+        allocator: Allocator,
+        size: usize,
+        protection: Protect,
+    ) -> Result<Self, String> {
+        let size = round_up_to_page_size(size, allocator.0.page_size());
+        let ptr = allocator.0.reserve(size)?;
+        let mut memory = Self {
+            ptr,
+            size,
+            protection: Protect::None,
+            allocator: Some(allocator),
+        };
+        if protection != Protect::None {
+            unsafe { memory.protect(.., protection) }.map_err(|e| e.to_string())?;
+        }
+        Ok(memory)
+    }
+
+    /// Take over the `size` bytes at `ptr`, which already have
+    /// `protection`, to be protected and released by `allocator`.
+    pub(crate) unsafe fn from_raw_parts(
+        ptr: *mut u8,
+        size: usize,
+        protection: Protect,
+        allocator: Allocator,
+    ) -> Self {
+        Self {
+            ptr,
+            size,
+            protection,
+            allocator: Some(allocator),
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        match &self.allocator {
+            Some(allocator) => allocator.0.page_size(),
+            None => page_size::get(),
+        }
+    }
+
     pub unsafe fn protect(
         &mut self,
         range: impl RangeBounds<usize>,
@@ -92,13 +160,30 @@ impl Memory {
             Bound::Unbounded => self.size(),
         };
 
-        let page_size = page_size::get();
+        let page_size = self.page_size();
         let start = self
             .ptr
             .add(round_down_to_page_size(range_start, page_size));
         let size = round_up_to_page_size(range_end - range_start, page_size);
         assert!(size <= self.size);
 
+        if let Some(allocator) = &self.allocator {
+            return match allocator.0.protect(start, size, protect) {
+                Ok(()) => {
+                    self.protection = protect;
+                    if protect == Protect::ReadExec {
+                        flush_icache(start, size);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(MemoryProtectionError::ProtectionFailed(
+                    start as usize,
+                    size,
+                    e,
+                )),
+            };
+        }
+
         // Commit the virtual memory.
         let ptr = VirtualAlloc(start as _, size, MEM_COMMIT, protect_const);
 
@@ -110,12 +195,15 @@ impl Memory {
             ))
         } else {
             self.protection = protect;
+            if protect == Protect::ReadExec {
+                flush_icache(start, size);
+            }
             Ok(())
         }
     }
 
     pub fn split_at(mut self, offset: usize) -> (Memory, Memory) {
-        let page_size = page_size::get();
+        let page_size = self.page_size();
         if offset % page_size == 0 {
             let second_ptr = unsafe { self.ptr.add(offset) };
             let second_size = self.size - offset;
@@ -126,6 +214,7 @@ impl Memory {
                 ptr: second_ptr,
                 size: second_size,
                 protection: self.protection,
+                allocator: self.allocator.clone(),
             };
 
             (self, second)
@@ -153,11 +242,71 @@ impl Memory {
     pub fn as_ptr(&self) -> *mut u8 {
         self.ptr
     }
+
+    /// Throw away the contents of the first `len` bytes of this
+    /// memory and make them inaccessible again, keeping the address
+    /// space reserved.
+    pub unsafe fn reset(&mut self, len: usize) -> Result<(), MemoryProtectionError> {
+        let size = round_up_to_page_size(len, self.page_size());
+        assert!(size <= self.size);
+        if size == 0 {
+            return Ok(());
+        }
+
+        if let Some(allocator) = &self.allocator {
+            allocator
+                .0
+                .reset(self.ptr, size)
+                .map_err(|e| MemoryProtectionError::ProtectionFailed(self.ptr as usize, size, e))?;
+            self.protection = Protect::None;
+            return Ok(());
+        }
+
+        // Decommitted pages are zeroed when they're committed again.
+        let success = VirtualFree(self.ptr as _, size, MEM_DECOMMIT);
+        if success == 0 {
+            Err(MemoryProtectionError::ProtectionFailed(
+                self.ptr as usize,
+                size,
+                "unable to decommit memory".to_string(),
+            ))
+        } else {
+            self.protection = Protect::None;
+            Ok(())
+        }
+    }
+
+    pub unsafe fn map_image(&mut self, _image: &MemoryImage) -> Result<(), MemoryProtectionError> {
+        unreachable!("memory images can't be created on windows")
+    }
+}
+
+/// Memory images aren't supported on Windows yet, so the contents
+/// are always copied into memories instead.
+#[derive(Debug)]
+pub struct MemoryImage {
+    _private: (),
+}
+
+impl MemoryImage {
+    pub fn new(_contents: &[u8]) -> Result<Self, MemoryCreationError> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "memory images are not supported on windows",
+        )
+        .into())
+    }
+
+    pub fn size(&self) -> usize {
+        0
+    }
 }
 
 impl Drop for Memory {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
+        if let Some(allocator) = &self.allocator {
+            unsafe { allocator.0.release(self.ptr, self.size) };
+        } else if !self.ptr.is_null() {
             let success = unsafe { VirtualFree(self.ptr as _, self.size, MEM_DECOMMIT) };
             // If the function succeeds, the return value is nonzero.
             assert_eq!(success, 1, "failed to unmap memory: {}", errno::errno());
@@ -186,6 +335,9 @@ impl Clone for Memory {
     }
 }
 
+/// How memory can be accessed. No memory is ever writable and
+/// executable at once: code is written while it's `ReadWrite`, and
+/// then made `ReadExec` to run it.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum Protect {
@@ -220,6 +372,37 @@ impl Protect {
     }
 }
 
+/// Make the `size` bytes at `ptr`, which must be page aligned,
+/// accessible with `protection`, whoever they were allocated by.
+pub(crate) unsafe fn protect_pages(
+    ptr: *mut u8,
+    size: usize,
+    protection: Protect,
+) -> Result<(), String> {
+    let mut old_protect = 0;
+    let success = VirtualProtect(
+        ptr as _,
+        size,
+        protection.to_protect_const(),
+        &mut old_protect,
+    );
+    // If the function succeeds, the return value is nonzero.
+    if success == 0 {
+        Err(errno::errno().to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Make sure that the instruction cache holds no stale copies of the
+/// `size` bytes at `ptr`, which have just been written, before they're
+/// run.
+pub fn flush_icache(ptr: *const u8, size: usize) {
+    unsafe {
+        FlushInstructionCache(GetCurrentProcess(), ptr as _, size);
+    }
+}
+
 /// Round `size` up to the nearest multiple of `page_size`.
 fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
     (size + (page_size - 1)) & !(page_size - 1)
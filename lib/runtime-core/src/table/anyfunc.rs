@@ -1,19 +1,28 @@
 use crate::{
     error::CreationError,
+    export::{Context, Export},
+    import::IsExport,
     instance::DynFunc,
+    pool, refs,
     sig_registry::SigRegistry,
-    structures::TypedIndex,
+    typed_func::{Func, FuncEnv, Unsafe, WasmTypeList},
     types::{FuncSig, TableDescriptor},
     vm,
 };
 
-use std::{ptr, sync::Arc};
+use std::{mem, ptr, rc::Rc, sync::Arc};
 
 enum AnyfuncInner<'a> {
     Host {
         ptr: *const vm::Func,
         signature: Arc<FuncSig>,
     },
+    HostWithCtx {
+        ptr: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        env: Option<Rc<FuncEnv>>,
+        signature: Arc<FuncSig>,
+    },
     Managed(DynFunc<'a>),
 }
 
@@ -33,6 +42,62 @@ impl<'a> Anyfunc<'a> {
             },
         }
     }
+
+    /// A host function that's passed `vmctx` when webassembly calls it
+    /// through the table, rather than a null context, or a
+    /// [`vm::FuncCtx`] with `vmctx` and `env` if it has an environment.
+    ///
+    /// [`vm::FuncCtx`]: ../vm/struct.FuncCtx.html
+    pub unsafe fn with_ctx<Sig>(
+        func: *const vm::Func,
+        vmctx: *mut vm::Ctx,
+        env: Option<Rc<FuncEnv>>,
+        signature: Sig,
+    ) -> Self
+    where
+        Sig: Into<Arc<FuncSig>>,
+    {
+        Self {
+            inner: AnyfuncInner::HostWithCtx {
+                ptr: func,
+                vmctx,
+                env,
+                signature: signature.into(),
+            },
+        }
+    }
+}
+
+impl<'a> Anyfunc<'a> {
+    /// Wrap a host function so it can be stored in a table.
+    ///
+    /// When webassembly calls it through the table, the function
+    /// receives `ctx`, usually the context of the instance that
+    /// the table is used from.
+    pub fn from_host<Args, Rets>(func: &Func<'a, Args, Rets, Unsafe>, ctx: &'a mut vm::Ctx) -> Self
+    where
+        Args: WasmTypeList,
+        Rets: WasmTypeList,
+    {
+        match func.to_export() {
+            Export::Function {
+                func,
+                ctx: func_ctx,
+                signature,
+            } => Self {
+                inner: AnyfuncInner::HostWithCtx {
+                    ptr: func.inner(),
+                    vmctx: ctx,
+                    env: match func_ctx {
+                        Context::InternalWithEnv(env) => Some(env),
+                        _ => None,
+                    },
+                    signature,
+                },
+            },
+            _ => unreachable!("a host function is always exported as a function"),
+        }
+    }
 }
 
 impl<'a> From<DynFunc<'a>> for Anyfunc<'a> {
@@ -46,6 +111,11 @@ impl<'a> From<DynFunc<'a>> for Anyfunc<'a> {
 pub struct AnyfuncTable {
     backing: Vec<vm::Anyfunc>,
     max: Option<u32>,
+    /// The contexts of the host closures that have been stored in
+    /// this table. They're kept until the table is dropped, since
+    /// webassembly may still hold a reference to a closure after
+    /// its slot has been overwritten.
+    func_ctxs: Vec<(Box<vm::FuncCtx>, Rc<FuncEnv>)>,
 }
 
 impl AnyfuncTable {
@@ -56,8 +126,9 @@ impl AnyfuncTable {
         let initial_table_backing_len = desc.minimum as usize;
 
         let mut storage = Box::new(AnyfuncTable {
-            backing: vec![vm::Anyfunc::null(); initial_table_backing_len],
+            backing: pool::take_table(initial_table_backing_len),
             max: desc.maximum,
+            func_ctxs: Vec::new(),
         });
 
         let storage_ptr: *mut AnyfuncTable = &mut *storage;
@@ -100,8 +171,7 @@ impl AnyfuncTable {
         if let Some(slot) = self.backing.get_mut(index as usize) {
             let anyfunc = match element.inner {
                 AnyfuncInner::Host { ptr, signature } => {
-                    let sig_index = SigRegistry.lookup_sig_index(signature);
-                    let sig_id = vm::SigId(sig_index.index() as u32);
+                    let sig_id = SigRegistry.lookup_sig_id(&signature);
 
                     vm::Anyfunc {
                         func: ptr,
@@ -109,9 +179,35 @@ impl AnyfuncTable {
                         sig_id,
                     }
                 }
+                AnyfuncInner::HostWithCtx {
+                    ptr,
+                    vmctx,
+                    env,
+                    signature,
+                } => {
+                    let sig_id = SigRegistry.lookup_sig_id(&signature);
+
+                    let ctx = match env {
+                        Some(env) => {
+                            let mut func_ctx = Box::new(vm::FuncCtx {
+                                vmctx,
+                                env: env.as_ptr(),
+                            });
+                            let func_ctx_ptr = &mut *func_ctx as *mut vm::FuncCtx;
+                            self.func_ctxs.push((func_ctx, env));
+                            func_ctx_ptr as *mut vm::Ctx
+                        }
+                        None => vmctx,
+                    };
+
+                    vm::Anyfunc {
+                        func: ptr,
+                        ctx,
+                        sig_id,
+                    }
+                }
                 AnyfuncInner::Managed(ref func) => {
-                    let sig_index = SigRegistry.lookup_sig_index(Arc::clone(&func.signature));
-                    let sig_id = vm::SigId(sig_index.index() as u32);
+                    let sig_id = SigRegistry.lookup_sig_id(&func.signature);
 
                     vm::Anyfunc {
                         func: func.raw(),
@@ -129,3 +225,15 @@ impl AnyfuncTable {
         }
     }
 }
+
+impl Drop for AnyfuncTable {
+    fn drop(&mut self) {
+        let ctxs: Vec<_> = self
+            .func_ctxs
+            .iter()
+            .map(|(func_ctx, _)| &**func_ctx as *const vm::FuncCtx as *mut vm::Ctx)
+            .collect();
+        refs::forget_funcs(&ctxs);
+        pool::return_table(mem::replace(&mut self.backing, Vec::new()));
+    }
+}
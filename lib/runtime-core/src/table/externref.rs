@@ -0,0 +1,67 @@
+use crate::{error::CreationError, refs::ExternRef, types::TableDescriptor, vm};
+
+pub struct ExternRefTable {
+    backing: Vec<ExternRef>,
+    max: Option<u32>,
+}
+
+impl ExternRefTable {
+    pub fn new(
+        desc: TableDescriptor,
+        local: &mut vm::LocalTable,
+    ) -> Result<Box<Self>, CreationError> {
+        let mut storage = Box::new(ExternRefTable {
+            backing: vec![ExternRef::null(); desc.minimum as usize],
+            max: desc.maximum,
+        });
+
+        let storage_ptr: *mut ExternRefTable = &mut *storage;
+
+        local.base = storage.backing.as_mut_ptr() as *mut u8;
+        local.count = storage.backing.len();
+        local.table = storage_ptr as *mut ();
+
+        Ok(storage)
+    }
+
+    pub fn current_size(&self) -> u32 {
+        self.backing.len() as u32
+    }
+
+    pub fn internal_buffer(&mut self) -> &mut [ExternRef] {
+        &mut self.backing
+    }
+
+    pub fn grow(&mut self, delta: u32, local: &mut vm::LocalTable) -> Option<u32> {
+        let starting_len = self.backing.len() as u32;
+
+        let new_len = starting_len.checked_add(delta)?;
+
+        if let Some(max) = self.max {
+            if new_len > max {
+                return None;
+            }
+        }
+
+        self.backing.resize(new_len as usize, ExternRef::null());
+
+        local.base = self.backing.as_mut_ptr() as *mut u8;
+        local.count = self.backing.len();
+
+        Some(starting_len)
+    }
+
+    pub fn get(&self, index: u32) -> Option<ExternRef> {
+        self.backing.get(index as usize).cloned()
+    }
+
+    pub fn set(&mut self, index: u32, element: ExternRef) -> Result<(), ()> {
+        match self.backing.get_mut(index as usize) {
+            Some(slot) => {
+                *slot = element;
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+}
@@ -2,25 +2,31 @@ use crate::{
     error::CreationError,
     export::Export,
     import::IsExport,
-    types::{ElementType, TableDescriptor},
+    refs::{ExternRef, FuncRef},
+    types::{ElementType, TableDescriptor, Value},
     vm,
 };
 use std::{cell::RefCell, fmt, ptr, rc::Rc};
 
 mod anyfunc;
+mod externref;
 
 pub use self::anyfunc::Anyfunc;
 use self::anyfunc::AnyfuncTable;
+use self::externref::ExternRefTable;
 use crate::error::GrowError;
 
 pub enum Element<'a> {
     Anyfunc(Anyfunc<'a>),
+    FuncRef(FuncRef),
+    ExternRef(ExternRef),
 }
 
 // #[derive(Debug)]
 pub enum TableStorage {
     /// This is intended to be a caller-checked Anyfunc.
     Anyfunc(Box<AnyfuncTable>),
+    ExternRef(Box<ExternRefTable>),
 }
 
 pub struct Table {
@@ -67,6 +73,9 @@ impl Table {
 
         let storage = match desc.element {
             ElementType::Anyfunc => TableStorage::Anyfunc(AnyfuncTable::new(desc, &mut local)?),
+            ElementType::ExternRef => {
+                TableStorage::ExternRef(ExternRefTable::new(desc, &mut local)?)
+            }
         };
 
         Ok(Self {
@@ -80,15 +89,43 @@ impl Table {
         self.desc
     }
 
+    /// Get the element at index, as a reference.
+    pub fn get(&self, index: u32) -> Option<Value> {
+        match &mut *self.storage.borrow_mut() {
+            (TableStorage::Anyfunc(ref mut anyfunc_table), _) => anyfunc_table
+                .internal_buffer()
+                .get(index as usize)
+                .map(|&anyfunc| Value::FuncRef(FuncRef::from_anyfunc(anyfunc))),
+            (TableStorage::ExternRef(ref externref_table), _) => {
+                externref_table.get(index).map(Value::ExternRef)
+            }
+        }
+    }
+
     /// Set the element at index.
+    ///
+    /// Fails if the index is out of bounds, the element has the
+    /// wrong type for this table or it refers to a function that
+    /// has been forgotten.
     pub fn set(&self, index: u32, element: Element) -> Result<(), ()> {
         match &mut *self.storage.borrow_mut() {
-            (TableStorage::Anyfunc(ref mut anyfunc_table), _) => {
-                match element {
-                    Element::Anyfunc(anyfunc) => anyfunc_table.set(index, anyfunc),
-                    // _ => panic!("wrong element type for anyfunc table"),
+            (TableStorage::Anyfunc(ref mut anyfunc_table), _) => match element {
+                Element::Anyfunc(anyfunc) => anyfunc_table.set(index, anyfunc),
+                Element::FuncRef(funcref) => {
+                    match anyfunc_table.internal_buffer().get_mut(index as usize) {
+                        Some(slot) => {
+                            *slot = funcref.anyfunc().ok_or(())?;
+                            Ok(())
+                        }
+                        None => Err(()),
+                    }
                 }
-            }
+                Element::ExternRef(_) => Err(()),
+            },
+            (TableStorage::ExternRef(ref mut externref_table), _) => match element {
+                Element::ExternRef(externref) => externref_table.set(index, externref),
+                Element::Anyfunc(_) | Element::FuncRef(_) => Err(()),
+            },
         }
     }
 
@@ -98,6 +135,19 @@ impl Table {
     {
         match &mut *self.storage.borrow_mut() {
             (TableStorage::Anyfunc(ref mut anyfunc_table), _) => f(anyfunc_table.internal_buffer()),
+            (TableStorage::ExternRef(_), _) => panic!("not an anyfunc table"),
+        }
+    }
+
+    pub(crate) fn externref_direct_access_mut<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [ExternRef]) -> R,
+    {
+        match &mut *self.storage.borrow_mut() {
+            (TableStorage::ExternRef(ref mut externref_table), _) => {
+                f(externref_table.internal_buffer())
+            }
+            (TableStorage::Anyfunc(_), _) => panic!("not an externref table"),
         }
     }
 
@@ -105,6 +155,7 @@ impl Table {
     pub fn size(&self) -> u32 {
         match &*self.storage.borrow() {
             (TableStorage::Anyfunc(ref anyfunc_table), _) => anyfunc_table.current_size(),
+            (TableStorage::ExternRef(ref externref_table), _) => externref_table.current_size(),
         }
     }
 
@@ -118,6 +169,9 @@ impl Table {
             (TableStorage::Anyfunc(ref mut anyfunc_table), ref mut local) => anyfunc_table
                 .grow(delta, local)
                 .ok_or(GrowError::TableGrowError),
+            (TableStorage::ExternRef(ref mut externref_table), ref mut local) => externref_table
+                .grow(delta, local)
+                .ok_or(GrowError::TableGrowError),
         }
     }
 
@@ -153,7 +207,9 @@ impl fmt::Debug for Table {
 #[cfg(test)]
 mod table_tests {
 
-    use super::{ElementType, Table, TableDescriptor};
+    use super::{Anyfunc, Element, ElementType, ExternRef, FuncRef, Table, TableDescriptor, Value};
+    use crate::{typed_func::Func, vm};
+    use std::{mem, sync::Arc};
 
     #[test]
     fn test_initial_table_size() {
@@ -166,4 +222,73 @@ mod table_tests {
         assert_eq!(table.size(), 10);
     }
 
+    #[test]
+    fn test_externref_table() {
+        let table = Table::new(TableDescriptor {
+            element: ElementType::ExternRef,
+            minimum: 1,
+            maximum: None,
+        })
+        .unwrap();
+        let value = Arc::new(42u32);
+        let reference = ExternRef::new(&value);
+
+        assert_eq!(table.get(0), Some(Value::ExternRef(ExternRef::null())));
+        assert!(table.set(0, Element::ExternRef(reference)).is_ok());
+        assert!(table.set(1, Element::ExternRef(reference)).is_err());
+        assert!(table.set(0, Element::FuncRef(FuncRef::null())).is_err());
+
+        match table.get(0) {
+            Some(Value::ExternRef(r)) => assert_eq!(*r.downcast::<u32>().unwrap(), 42),
+            _ => panic!("expected an externref"),
+        }
+    }
+
+    #[test]
+    fn test_set_host_func() {
+        let table = Table::new(TableDescriptor {
+            element: ElementType::Anyfunc,
+            minimum: 2,
+            maximum: Some(3),
+        })
+        .unwrap();
+        // The functions are never called, so the context is never read.
+        let mut ctx: vm::Ctx = unsafe { mem::zeroed() };
+        let ctx_ptr = &mut ctx as *mut vm::Ctx;
+
+        let double = Func::new(|_: &mut vm::Ctx, x: i32| x * 2);
+        let offset = 10;
+        let add_offset = Func::new(move |_: &mut vm::Ctx, x: i32| x + offset);
+
+        table
+            .set(0, Element::Anyfunc(Anyfunc::from_host(&double, &mut ctx)))
+            .unwrap();
+        table
+            .set(
+                1,
+                Element::Anyfunc(Anyfunc::from_host(&add_offset, &mut ctx)),
+            )
+            .unwrap();
+        assert!(table
+            .set(2, Element::Anyfunc(Anyfunc::from_host(&double, &mut ctx)))
+            .is_err());
+
+        table.anyfunc_direct_access_mut(|anyfuncs| {
+            // Plain functions get the context directly, closures get it
+            // along with their environment.
+            assert_eq!(anyfuncs[0].ctx, ctx_ptr);
+            let func_ctx = anyfuncs[1].ctx as *const vm::FuncCtx;
+            assert_eq!(unsafe { (*func_ctx).vmctx }, ctx_ptr);
+            assert_eq!(anyfuncs[0].sig_id.0, anyfuncs[1].sig_id.0);
+        });
+
+        match table.get(0) {
+            Some(Value::FuncRef(funcref)) => assert!(!funcref.is_null()),
+            _ => panic!("expected a funcref"),
+        }
+
+        assert_eq!(table.grow(1).unwrap(), 2);
+        assert_eq!(table.get(2), Some(Value::FuncRef(FuncRef::null())));
+        assert!(table.grow(1).is_err());
+    }
 }
@@ -0,0 +1,94 @@
+//! A hook that hears about every guest that ends early, so that hosts
+//! can log and classify guest failures in one place.
+//!
+//! The hook is called when a guest executes an `unreachable`
+//! instruction, and by host functions that end the guest, such as
+//! emscripten's `abort` and `exit`, before the guest is unwound or the
+//! process exits.
+//!
+//! # Usage:
+//!
+//! ```
+//! use wasmer_runtime_core::termination::{set_termination_hook, Reason};
+//!
+//! set_termination_hook(Some(Box::new(|termination| {
+//!     if let Reason::Exit { code: 0 } = termination.reason {
+//!         return;
+//!     }
+//!     eprintln!("guest failed: {:?}", termination.reason);
+//!     for frame in &termination.backtrace {
+//!         eprintln!("    at {}", frame);
+//!     }
+//! })));
+//! ```
+
+use crate::error::WasmFrame;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Why a guest ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The guest aborted, with a message if it gave one.
+    Abort { message: Option<String> },
+    /// The guest exited with `code`.
+    Exit { code: i32 },
+    /// The guest executed an `unreachable` instruction.
+    Unreachable,
+}
+
+/// A guest that ended early.
+#[derive(Debug, Clone)]
+pub struct Termination {
+    pub reason: Reason,
+    /// The WebAssembly frames that were on the stack, innermost first.
+    /// It's empty when the backend can't find them, and when a host
+    /// function ended the guest.
+    pub backtrace: Vec<WasmFrame>,
+}
+
+/// A hook that is called with every guest that ends early.
+pub type TerminationHook = dyn Fn(&Termination) + Send + Sync;
+
+lazy_static! {
+    static ref TERMINATION_HOOK: Mutex<Option<Arc<TerminationHook>>> = Mutex::new(None);
+}
+
+/// Call `hook` with every guest that ends early from now on, or stop
+/// calling one with `None`.
+pub fn set_termination_hook(hook: Option<Box<TerminationHook>>) {
+    *TERMINATION_HOOK.lock() = hook.map(Arc::from);
+}
+
+/// Let the termination hook, if there is one, know that a guest ended
+/// for `reason`. Host functions that end the guest call this.
+pub fn report(reason: Reason, backtrace: Vec<WasmFrame>) {
+    // Don't hold the lock while the hook runs, in case it reports too.
+    let hook = TERMINATION_HOOK.lock().clone();
+    if let Some(hook) = hook {
+        hook(&Termination { reason, backtrace });
+    }
+}
+
+#[cfg(test)]
+mod termination_tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        set_termination_hook(Some(Box::new(move |termination| {
+            sender.lock().send(termination.reason.clone()).unwrap();
+        })));
+        report(Reason::Exit { code: 3 }, Vec::new());
+        set_termination_hook(None);
+        report(Reason::Unreachable, Vec::new());
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![Reason::Exit { code: 3 }]
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Catching the traps of WebAssembly code, for every backend.
+//!
+//! Compiled code traps in one of two ways: it faults, by accessing a
+//! guard page, executing an illegal instruction or dividing by zero,
+//! or it calls into the runtime, which raises the trap with [`raise`].
+//! Backends run compiled code through [`call_protected`], which
+//! installs the trap handler the first time it's called and returns
+//! the trap as an error when the code traps, having unwound the
+//! frames of the code that trapped.
+//!
+//! On unix, faults are caught with signal handlers, or with the trap
+//! handler set with [`platform::set_trap_handler`]. On Windows, the
+//! exception handler that backends call compiled code through catches
+//! them, and [`exception_fault`] turns what it caught into a [`Fault`].
+//!
+//! A backend describes its code with a [`TrapTable`]: where the code
+//! is, which instructions trap on purpose with which trap code, and
+//! where each function starts, for backtraces. Faults of backends that
+//! don't make one are told apart by their kind alone.
+//!
+//! [`raise`]: fn.raise.html
+//! [`call_protected`]: fn.call_protected.html
+//! [`platform::set_trap_handler`]: ../platform/fn.set_trap_handler.html
+//! [`exception_fault`]: fn.exception_fault.html
+//! [`Fault`]: ../platform/struct.Fault.html
+//! [`TrapTable`]: struct.TrapTable.html
+
+use crate::{
+    error::{RuntimeError, TrapCode, WasmFrame},
+    module::ModuleInfo,
+    platform::{Fault, FaultKind},
+    stack,
+    structures::TypedIndex,
+    types::FuncIndex,
+};
+use hashbrown::HashMap;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use self::unix::*;
+#[cfg(windows)]
+pub use self::windows::*;
+
+/// An instruction that traps on purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapSite {
+    /// The offset of the instruction from the start of the code.
+    pub offset: usize,
+    pub code: TrapCode,
+    /// The offset of the WebAssembly instruction that it was compiled
+    /// from, in bytes from the start of the module, if it's known.
+    pub wasm_offset: Option<u32>,
+}
+
+/// What the trap handler knows about the code of a module.
+#[derive(Debug)]
+pub struct TrapTable {
+    code_start: usize,
+    code_size: usize,
+    /// Sorted by offset.
+    sites: Vec<TrapSite>,
+    /// The offset of each local function in the code, in order.
+    func_offsets: Vec<usize>,
+    import_count: usize,
+    func_names: HashMap<FuncIndex, String>,
+}
+
+impl TrapTable {
+    /// The table of the `code_size` bytes of code at `code_start`, in
+    /// which the local functions of the module described by `info`
+    /// start at `func_offsets`.
+    pub fn new(
+        code_start: *const u8,
+        code_size: usize,
+        mut sites: Vec<TrapSite>,
+        func_offsets: Vec<usize>,
+        info: &ModuleInfo,
+    ) -> Self {
+        sites.sort_by_key(|site| site.offset);
+        Self {
+            code_start: code_start as usize,
+            code_size,
+            sites,
+            func_offsets,
+            import_count: info.imported_functions.len(),
+            func_names: info.func_names.clone(),
+        }
+    }
+
+    /// The start and end addresses of the code.
+    pub fn code_range(&self) -> (usize, usize) {
+        (self.code_start, self.code_start + self.code_size)
+    }
+
+    /// The trap site of the instruction at `address`, if it is one.
+    pub fn lookup(&self, address: usize) -> Option<&TrapSite> {
+        let offset = address.checked_sub(self.code_start)?;
+        if offset >= self.code_size {
+            return None;
+        }
+        let index = self
+            .sites
+            .binary_search_by_key(&offset, |site| site.offset)
+            .ok()?;
+        Some(&self.sites[index])
+    }
+
+    /// The WebAssembly frames for the addresses of the frames that
+    /// were on the stack when a trap occured, innermost first. The
+    /// innermost address is the trapping instruction and the rest
+    /// are return addresses.
+    pub fn backtrace(&self, addrs: &[usize]) -> Vec<WasmFrame> {
+        addrs
+            .iter()
+            .enumerate()
+            .filter_map(|(depth, &addr)| {
+                // A return address is just past the call instruction,
+                // which might be the last one in its function.
+                let ip = if depth == 0 { addr } else { addr - 1 };
+                let code_offset = ip.checked_sub(self.code_start)?;
+                if code_offset >= self.code_size {
+                    return None;
+                }
+                let local_index = match self.func_offsets.binary_search(&code_offset) {
+                    Ok(index) => index,
+                    Err(0) => return None,
+                    Err(index) => index - 1,
+                };
+                let func_index = FuncIndex::new(self.import_count + local_index);
+
+                // Only trapping instructions have a known offset in the module.
+                let offset = if depth == 0 {
+                    self.lookup(addr).and_then(|site| site.wasm_offset)
+                } else {
+                    None
+                };
+
+                Some(WasmFrame {
+                    func_index,
+                    func_name: self.func_names.get(&func_index).cloned(),
+                    offset,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The error for `fault`, in code described by `table`, with the
+/// addresses of the frames that were on the stack, innermost first.
+pub fn fault_error(fault: &Fault, table: Option<&TrapTable>, frames: &[usize]) -> RuntimeError {
+    let stack_overflow = fault.kind == FaultKind::StackOverflow
+        || (fault.kind == FaultKind::MemoryAccess
+            && stack::is_stack_overflow(fault.address as *const u8));
+    let site = table.and_then(|table| table.lookup(fault.instruction));
+
+    let code = match (site, table) {
+        _ if stack_overflow => TrapCode::StackOverflow,
+        // The code of the site is what the instruction traps for, but
+        // the kind of fault tells apart what shares an instruction.
+        (Some(site), _) => match fault.kind {
+            FaultKind::IllegalInstruction => site.code,
+            FaultKind::MemoryAccess => TrapCode::MemoryOutOfBounds,
+            FaultKind::Arithmetic => match site.code {
+                TrapCode::IntegerDivisionByZero | TrapCode::IntegerOverflow => site.code,
+                _ => TrapCode::IllegalArithmetic,
+            },
+            FaultKind::StackOverflow => TrapCode::StackOverflow,
+        },
+        (None, None) => match fault.kind {
+            FaultKind::MemoryAccess => TrapCode::MemoryOutOfBounds,
+            FaultKind::Arithmetic => TrapCode::IllegalArithmetic,
+            FaultKind::IllegalInstruction | FaultKind::StackOverflow => TrapCode::Unknown,
+        },
+        // The table has every instruction that traps on purpose, so
+        // this is a fault that the code didn't expect.
+        (None, Some(_)) => TrapCode::Unknown,
+    };
+    let kind = match fault.kind {
+        FaultKind::MemoryAccess => "memory access fault",
+        FaultKind::IllegalInstruction => "illegal instruction",
+        FaultKind::Arithmetic => "arithmetic fault",
+        FaultKind::StackOverflow => "stack overflow",
+    };
+
+    RuntimeError::Trap {
+        code,
+        msg: format!("{} at {:#x} - {}", code, fault.address, kind).into(),
+        backtrace: table
+            .map(|table| table.backtrace(frames))
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod trap_tests {
+    use super::*;
+    use crate::{backend::Backend, memory::MemoryStyle, module::StringTable, structures::Map};
+
+    fn table(code: &[u8]) -> TrapTable {
+        let info = ModuleInfo {
+            memories: Map::new(),
+            globals: Map::new(),
+            tables: Map::new(),
+
+            imported_functions: Map::new(),
+            imported_memories: Map::new(),
+            imported_tables: Map::new(),
+            imported_globals: Map::new(),
+
+            exports: HashMap::new(),
+
+            data_initializers: Vec::new(),
+            elem_initializers: Vec::new(),
+
+            passive_data: HashMap::new(),
+            passive_elements: HashMap::new(),
+
+            start_func: None,
+
+            func_assoc: Map::new(),
+            signatures: Map::new(),
+            backend: Backend::Cranelift,
+            memory_style: MemoryStyle::Static,
+            initial_points: None,
+
+            namespace_table: StringTable::new(),
+            name_table: StringTable::new(),
+
+            custom_sections: HashMap::new(),
+            func_names: HashMap::new(),
+        };
+        let sites = vec![
+            TrapSite {
+                offset: 24,
+                code: TrapCode::IntegerDivisionByZero,
+                wasm_offset: Some(0x40),
+            },
+            TrapSite {
+                offset: 8,
+                code: TrapCode::Unreachable,
+                wasm_offset: None,
+            },
+        ];
+        TrapTable::new(code.as_ptr(), code.len(), sites, vec![0, 16], &info)
+    }
+
+    fn fault(kind: FaultKind, instruction: usize) -> Fault {
+        Fault {
+            kind,
+            address: 0,
+            instruction,
+        }
+    }
+
+    fn code(error: &RuntimeError) -> TrapCode {
+        match error {
+            RuntimeError::Trap { code, .. } => *code,
+            _ => panic!("expected a trap"),
+        }
+    }
+
+    #[test]
+    fn test_fault_error() {
+        let code_buffer = [0u8; 32];
+        let table = table(&code_buffer);
+        let start = code_buffer.as_ptr() as usize;
+
+        let error = fault_error(
+            &fault(FaultKind::IllegalInstruction, start + 8),
+            Some(&table),
+            &[start + 8],
+        );
+        assert_eq!(code(&error), TrapCode::Unreachable);
+
+        let error = fault_error(
+            &fault(FaultKind::Arithmetic, start + 24),
+            Some(&table),
+            &[start + 24, start + 9],
+        );
+        assert_eq!(code(&error), TrapCode::IntegerDivisionByZero);
+        match error {
+            RuntimeError::Trap { backtrace, .. } => {
+                let frames: Vec<_> = backtrace
+                    .iter()
+                    .map(|frame| (frame.func_index.index(), frame.offset))
+                    .collect();
+                assert_eq!(frames, vec![(1, Some(0x40)), (0, None)]);
+            }
+            _ => unreachable!(),
+        }
+
+        let error = fault_error(
+            &fault(FaultKind::IllegalInstruction, start + 12),
+            Some(&table),
+            &[],
+        );
+        assert_eq!(code(&error), TrapCode::Unknown);
+
+        let error = fault_error(&fault(FaultKind::MemoryAccess, start + 12), None, &[]);
+        assert_eq!(code(&error), TrapCode::MemoryOutOfBounds);
+        let error = fault_error(&fault(FaultKind::StackOverflow, start), None, &[]);
+        assert_eq!(code(&error), TrapCode::StackOverflow);
+    }
+}
@@ -0,0 +1,332 @@
+//! Catching faults with signal handlers, and unwinding to the last
+//! protected call with `longjmp`.
+//!
+//! This code is inspired by: https://github.com/pepyakin/wasmtime/commit/625a2b6c0815b21996e111da51b9664feb174622
+//!
+//! The recovery information is kept in thread-locals. Since the four
+//! signals that are handled are very special, the async signal unsafety
+//! of Rust's TLS implementation generally does not affect the
+//! correctness here unless you have memory unsafety elsewhere in your
+//! code.
+use super::{fault_error, TrapTable};
+use crate::{
+    backend::UserTrapper,
+    crash,
+    error::{RuntimeError, RuntimeResult},
+    platform::{self, Fault, FaultKind},
+    stack,
+};
+use libc::{c_int, c_void, siginfo_t};
+use nix::sys::signal::{
+    sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal, SIGBUS, SIGFPE, SIGILL, SIGSEGV,
+};
+use std::any::Any;
+use std::cell::{Cell, UnsafeCell};
+use std::sync::Once;
+use std::{mem, ptr};
+
+extern "C" fn signal_trap_handler(
+    signum: ::nix::libc::c_int,
+    siginfo: *mut siginfo_t,
+    ucontext: *mut c_void,
+) {
+    unsafe {
+        do_unwind(signum, siginfo as _, ucontext);
+    }
+}
+
+extern "C" {
+    fn setjmp(env: *mut c_void) -> c_int;
+    fn longjmp(env: *mut c_void, val: c_int) -> !;
+}
+
+unsafe fn install_sighandler() {
+    let sa = SigAction::new(
+        SigHandler::SigAction(signal_trap_handler),
+        SaFlags::SA_ONSTACK | SaFlags::SA_SIGINFO,
+        SigSet::empty(),
+    );
+    sigaction(SIGFPE, &sa).unwrap();
+    sigaction(SIGILL, &sa).unwrap();
+    sigaction(SIGSEGV, &sa).unwrap();
+    sigaction(SIGBUS, &sa).unwrap();
+}
+
+const SETJMP_BUFFER_LEN: usize = 27;
+static SIGHANDLER_INIT: Once = Once::new();
+
+/// The most frames that a backtrace collects.
+const MAX_FRAMES: usize = 32;
+
+thread_local! {
+    static SETJMP_BUFFER: UnsafeCell<[c_int; SETJMP_BUFFER_LEN]> = UnsafeCell::new([0; SETJMP_BUFFER_LEN]);
+    /// The table of the code that the protected call runs, if it has one.
+    static CURRENT_TABLE: Cell<*const TrapTable> = Cell::new(ptr::null());
+    static CAUGHT_FAULT: Cell<Option<Fault>> = Cell::new(None);
+    static CAUGHT_FRAMES: Cell<([usize; MAX_FRAMES], usize)> = Cell::new(([0; MAX_FRAMES], 0));
+    /// The error of a trap that was raised rather than caught.
+    static RAISED_ERROR: Cell<Option<RuntimeError>> = Cell::new(None);
+}
+
+/// Run `f`, which calls compiled code that `table` describes, if the
+/// backend makes one. If the code traps, the frames down to here are
+/// unwound and the trap is returned as an error.
+pub fn call_protected<T>(table: Option<&TrapTable>, f: impl FnOnce() -> T) -> RuntimeResult<T> {
+    unsafe {
+        let jmp_buf = SETJMP_BUFFER.with(|buf| buf.get());
+        let prev_jmp_buf = *jmp_buf;
+
+        SIGHANDLER_INIT.call_once(|| {
+            match platform::trap_handler() {
+                Some(handler) => handler.install(unwind_fault),
+                None => install_sighandler(),
+            }
+            stack::register_switch_hook(save_trap_state);
+        });
+
+        let table = table.map_or(ptr::null(), |table| table as *const TrapTable);
+        let prev_table = CURRENT_TABLE.with(|cell| cell.replace(table));
+
+        if setjmp(jmp_buf as *mut _) != 0 {
+            *jmp_buf = prev_jmp_buf;
+            CURRENT_TABLE.with(|cell| cell.set(prev_table));
+
+            if let Some(error) = RAISED_ERROR.with(|cell| cell.replace(None)) {
+                return Err(error);
+            }
+            let fault = CAUGHT_FAULT
+                .with(|cell| cell.replace(None))
+                .expect("unwound without a fault");
+            let (frames, frame_count) = CAUGHT_FRAMES.with(|cell| cell.get());
+            Err(fault_error(&fault, table.as_ref(), &frames[..frame_count]))
+        } else {
+            let ret = stack::run_on_wasm_stack(f);
+            *jmp_buf = prev_jmp_buf;
+            CURRENT_TABLE.with(|cell| cell.set(prev_table));
+            Ok(ret)
+        }
+    }
+}
+
+/// Trap with `error`, unwinding to the last protected call. This may
+/// only be called from code that runs in a protected call and holds no
+/// resources, such as a function that compiled code calls.
+pub unsafe fn raise(error: RuntimeError) -> ! {
+    let jmp_buf = SETJMP_BUFFER.with(|buf| buf.get());
+    if *jmp_buf == [0; SETJMP_BUFFER_LEN] {
+        crash::abort(&format!("{}, raised outside of any protected call", error));
+    }
+
+    RAISED_ERROR.with(|cell| cell.set(Some(error)));
+    longjmp(jmp_buf as *mut c_void, 1)
+}
+
+/// Traps with the errors of host functions, for backends to return
+/// from `ProtectedCaller::get_early_trapper`.
+pub struct Trapper;
+
+impl UserTrapper for Trapper {
+    unsafe fn do_early_trap(&self, data: Box<dyn Any>) -> ! {
+        raise(RuntimeError::Panic { data })
+    }
+}
+
+/// Save the recovery information of the call that's running on this
+/// thread, for when it's suspended on a fiber.
+fn save_trap_state() -> Box<dyn FnOnce()> {
+    let jmp_buf = SETJMP_BUFFER.with(|buf| unsafe { *buf.get() });
+    let table = CURRENT_TABLE.with(|cell| cell.get());
+    Box::new(move || {
+        SETJMP_BUFFER.with(|buf| unsafe { *buf.get() = jmp_buf });
+        CURRENT_TABLE.with(|cell| cell.set(table));
+    })
+}
+
+/// Unwinds to last protected_call.
+unsafe fn do_unwind(signum: i32, siginfo: *const c_void, ucontext: *const c_void) -> ! {
+    // Since do_unwind is only expected to get called from WebAssembly code which doesn't hold any host resources (locks etc.)
+    // itself, accessing TLS here is safe. In case any other code calls this, it often indicates a memory safety bug and you should
+    // temporarily disable the signal handlers to debug it.
+
+    let kind = match Signal::from_c_int(signum) {
+        Ok(SIGILL) => FaultKind::IllegalInstruction,
+        Ok(SIGFPE) => FaultKind::Arithmetic,
+        _ => FaultKind::MemoryAccess,
+    };
+    let (address, instruction, fp, sp) = get_fault_info(siginfo, ucontext);
+    let fault = Fault {
+        kind,
+        address,
+        instruction,
+    };
+
+    let jmp_buf = SETJMP_BUFFER.with(|buf| buf.get());
+    if *jmp_buf == [0; SETJMP_BUFFER_LEN] {
+        crash::abort_on_fault(&fault);
+    }
+
+    CAUGHT_FAULT.with(|cell| cell.set(Some(fault)));
+    CAUGHT_FRAMES.with(|cell| cell.set(collect_frames(instruction, fp, sp)));
+
+    longjmp(jmp_buf as *mut c_void, 1)
+}
+
+/// Unwinds to last protected_call from a fault that the trap handler
+/// set through the `platform` module reported.
+unsafe fn unwind_fault(fault: Fault) -> ! {
+    let jmp_buf = SETJMP_BUFFER.with(|buf| buf.get());
+    if *jmp_buf == [0; SETJMP_BUFFER_LEN] {
+        crash::abort_on_fault(&fault);
+    }
+
+    CAUGHT_FAULT.with(|cell| cell.set(Some(fault)));
+    CAUGHT_FRAMES.with(|cell| cell.set(([0; MAX_FRAMES], 0)));
+
+    longjmp(jmp_buf as *mut c_void, 1)
+}
+
+/// Collect the addresses of the WebAssembly frames on the stack,
+/// starting at `ip`, by following the frame pointers that compiled
+/// code always keeps.
+///
+/// Only frames whose address is in the code being run are followed,
+/// and a frame pointer is only read if it's further up the stack
+/// than the previous one, so this doesn't wander off into frames of
+/// the host that might not keep a frame pointer.
+unsafe fn collect_frames(ip: usize, mut fp: usize, sp: usize) -> ([usize; MAX_FRAMES], usize) {
+    let mut frames = [0; MAX_FRAMES];
+    let table = CURRENT_TABLE.with(|cell| cell.get());
+    if table.is_null() {
+        return (frames, 0);
+    }
+    let (code_start, code_end) = (*table).code_range();
+    let stack_end = sp.saturating_add(stack::max_stack_size());
+
+    let mut count = 0;
+    let mut addr = ip;
+    let mut lowest_fp = sp;
+    while count < MAX_FRAMES && code_start <= addr && addr < code_end {
+        frames[count] = addr;
+        count += 1;
+
+        // A frame holds the caller's frame pointer followed by the return address.
+        if fp < lowest_fp || fp % mem::align_of::<usize>() != 0 || fp + 16 > stack_end {
+            break;
+        }
+        let frame = fp as *const usize;
+        addr = *frame.add(1);
+        lowest_fp = fp + 16;
+        fp = *frame;
+    }
+
+    (frames, count)
+}
+
+/// The address that was accessed, the address of the faulting
+/// instruction, and the frame and stack pointers.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn get_fault_info(
+    siginfo: *const c_void,
+    ucontext: *const c_void,
+) -> (usize, usize, usize, usize) {
+    use libc::{ucontext_t, REG_RBP, REG_RIP, REG_RSP};
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct siginfo_t {
+        si_signo: i32,
+        si_errno: i32,
+        si_code: i32,
+        si_addr: u64,
+        // ...
+    }
+
+    let siginfo = siginfo as *const siginfo_t;
+    let si_addr = (*siginfo).si_addr;
+
+    let ucontext = ucontext as *const ucontext_t;
+    let gregs = &(*ucontext).uc_mcontext.gregs;
+
+    (
+        si_addr as _,
+        gregs[REG_RIP as usize] as _,
+        gregs[REG_RBP as usize] as _,
+        gregs[REG_RSP as usize] as _,
+    )
+}
+
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+unsafe fn get_fault_info(
+    siginfo: *const c_void,
+    ucontext: *const c_void,
+) -> (usize, usize, usize, usize) {
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct ucontext_t {
+        uc_onstack: u32,
+        uc_sigmask: u32,
+        uc_stack: libc::stack_t,
+        uc_link: *const ucontext_t,
+        uc_mcsize: u64,
+        uc_mcontext: *const mcontext_t,
+    }
+    #[repr(C)]
+    struct exception_state {
+        trapno: u16,
+        cpu: u16,
+        err: u32,
+        faultvaddr: u64,
+    }
+    #[repr(C)]
+    struct regs {
+        rax: u64,
+        rbx: u64,
+        rcx: u64,
+        rdx: u64,
+        rdi: u64,
+        rsi: u64,
+        rbp: u64,
+        rsp: u64,
+        r8: u64,
+        r9: u64,
+        r10: u64,
+        r11: u64,
+        r12: u64,
+        r13: u64,
+        r14: u64,
+        r15: u64,
+        rip: u64,
+        rflags: u64,
+        cs: u64,
+        fs: u64,
+        gs: u64,
+    }
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct mcontext_t {
+        es: exception_state,
+        ss: regs,
+        // ...
+    }
+
+    let siginfo = siginfo as *const siginfo_t;
+    let si_addr = (*siginfo).si_addr;
+
+    let ucontext = ucontext as *const ucontext_t;
+    let regs = &(*(*ucontext).uc_mcontext).ss;
+
+    (si_addr as _, regs.rip as _, regs.rbp as _, regs.rsp as _)
+}
+
+/// Elsewhere the registers aren't read, so faults are told apart by
+/// their kind alone and have no backtraces.
+#[cfg(not(any(
+    all(target_os = "macos", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "x86_64"),
+)))]
+unsafe fn get_fault_info(
+    _siginfo: *const c_void,
+    _ucontext: *const c_void,
+) -> (usize, usize, usize, usize) {
+    (0, 0, 0, 0)
+}
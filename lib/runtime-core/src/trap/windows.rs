@@ -0,0 +1,34 @@
+use crate::platform::{Fault, FaultKind};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::minwinbase::{
+    EXCEPTION_ACCESS_VIOLATION, EXCEPTION_FLT_DENORMAL_OPERAND, EXCEPTION_FLT_DIVIDE_BY_ZERO,
+    EXCEPTION_FLT_INEXACT_RESULT, EXCEPTION_FLT_INVALID_OPERATION, EXCEPTION_FLT_OVERFLOW,
+    EXCEPTION_FLT_STACK_CHECK, EXCEPTION_FLT_UNDERFLOW, EXCEPTION_ILLEGAL_INSTRUCTION,
+    EXCEPTION_INT_DIVIDE_BY_ZERO, EXCEPTION_INT_OVERFLOW, EXCEPTION_STACK_OVERFLOW,
+};
+
+/// The fault for an exception with `code` that the exception handler
+/// caught at `instruction`, accessing `address`.
+pub fn exception_fault(code: DWORD, address: usize, instruction: usize) -> Fault {
+    let kind = match code {
+        EXCEPTION_STACK_OVERFLOW => FaultKind::StackOverflow,
+        EXCEPTION_ILLEGAL_INSTRUCTION => FaultKind::IllegalInstruction,
+        EXCEPTION_INT_DIVIDE_BY_ZERO
+        | EXCEPTION_INT_OVERFLOW
+        | EXCEPTION_FLT_DENORMAL_OPERAND
+        | EXCEPTION_FLT_DIVIDE_BY_ZERO
+        | EXCEPTION_FLT_INEXACT_RESULT
+        | EXCEPTION_FLT_INVALID_OPERATION
+        | EXCEPTION_FLT_OVERFLOW
+        | EXCEPTION_FLT_STACK_CHECK
+        | EXCEPTION_FLT_UNDERFLOW => FaultKind::Arithmetic,
+        EXCEPTION_ACCESS_VIOLATION => FaultKind::MemoryAccess,
+        // Compiled code doesn't raise other exceptions on purpose.
+        _ => FaultKind::IllegalInstruction,
+    };
+    Fault {
+        kind,
+        address,
+        instruction,
+    }
+}
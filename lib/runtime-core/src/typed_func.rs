@@ -1,15 +1,39 @@
 use crate::{
-    backend::UserTrapper,
+    backend::{ProtectedCaller, Token, UserTrapper},
+    crash,
     error::RuntimeError,
     export::{Context, Export, FuncPointer},
     import::IsExport,
-    types::{FuncSig, Type, WasmExternType},
-    vm::Ctx,
+    instance::finish_call,
+    stack,
+    types::{FuncSig, Type, Value, WasmExternType},
+    vm::{self, Ctx, FuncCtx},
+};
+use std::{
+    any::Any,
+    cell::{Cell, UnsafeCell},
+    ffi::c_void,
+    fmt,
+    marker::PhantomData,
+    mem, panic, ptr,
+    rc::Rc,
+    sync::{Arc, Once},
 };
-use std::{any::Any, cell::UnsafeCell, marker::PhantomData, mem, panic, ptr, sync::Arc};
 
 thread_local! {
     pub static EARLY_TRAPPER: UnsafeCell<Option<Box<dyn UserTrapper>>> = UnsafeCell::new(None);
+    /// The protected caller of the outermost call into WebAssembly
+    /// that's running on this thread, if any.
+    static PROTECTED_CALLER: Cell<*const ()> = Cell::new(ptr::null());
+}
+
+static SWITCH_HOOK_INIT: Once = Once::new();
+
+/// Save the protected caller of the running call, for when it's
+/// suspended on a fiber.
+fn save_protected_caller() -> Box<dyn FnOnce()> {
+    let caller = PROTECTED_CALLER.with(|cell| cell.get());
+    Box::new(move || PROTECTED_CALLER.with(|cell| cell.set(caller)))
 }
 
 pub trait Safeness {}
@@ -23,6 +47,8 @@ pub trait WasmTypeList {
     fn from_c_struct(c_struct: Self::CStruct) -> Self;
     fn into_c_struct(self) -> Self::CStruct;
     fn types() -> &'static [Type];
+    fn into_values(self) -> Vec<Value>;
+    fn from_values(values: &[Value]) -> Self;
     unsafe fn call<Rets>(self, f: *const (), ctx: *mut Ctx) -> Rets
     where
         Rets: WasmTypeList;
@@ -33,7 +59,91 @@ where
     Args: WasmTypeList,
     Rets: WasmTypeList,
 {
-    fn to_raw(&self) -> *const ();
+    /// Returns a pointer to a function with the native signature
+    /// that calls `self`, and the environment that `self` captured,
+    /// if any.
+    fn to_raw(self) -> (*const (), Option<FuncEnv>);
+}
+
+/// The environment captured by a host closure.
+///
+/// It is shared between the [`Func`] that wraps the closure, the
+/// exports made from it and the instances it's imported into, and
+/// freed once none of them are left.
+///
+/// [`Func`]: struct.Func.html
+pub struct FuncEnv {
+    ptr: *mut c_void,
+    drop: unsafe extern "C" fn(*mut c_void),
+}
+
+impl FuncEnv {
+    fn new<F: 'static>(f: F) -> Self {
+        unsafe extern "C" fn drop_env<F>(ptr: *mut c_void) {
+            drop(Box::from_raw(ptr as *mut F));
+        }
+
+        FuncEnv {
+            ptr: Box::into_raw(Box::new(f)) as *mut c_void,
+            drop: drop_env::<F>,
+        }
+    }
+
+    /// An environment that isn't a Rust closure, such as the data of a
+    /// host function defined in C, which is passed as `ptr` and freed
+    /// by calling `drop` with it.
+    pub unsafe fn from_raw(ptr: *mut c_void, drop: unsafe extern "C" fn(*mut c_void)) -> Self {
+        FuncEnv { ptr, drop }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.ptr
+    }
+}
+
+impl fmt::Debug for FuncEnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FuncEnv({:p})", self.ptr)
+    }
+}
+
+impl Drop for FuncEnv {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.ptr) }
+    }
+}
+
+/// Whether native functions that return `Rets` return them through a
+/// pointer to a `Rets::CStruct` that they take after their parameters,
+/// rather than as the `Rets::CStruct` itself. See
+/// [`FuncSig::returns_through_pointer`].
+///
+/// [`FuncSig::returns_through_pointer`]: ../types/struct.FuncSig.html#method.returns_through_pointer
+fn returns_through_pointer<Rets: WasmTypeList>() -> bool {
+    Rets::types().len() > 1
+}
+
+/// Call a host function, turning an error or a panic into
+/// an early trap back into the wasm code that called it.
+fn call_host<Rets, Trap>(f: impl FnOnce() -> Trap) -> Rets::CStruct
+where
+    Rets: WasmTypeList,
+    Trap: TrapEarly<Rets>,
+{
+    let err = match panic::catch_unwind(panic::AssertUnwindSafe(|| f().report())) {
+        Ok(Ok(returns)) => return returns.into_c_struct(),
+        Ok(Err(err)) => err,
+        Err(err) => err,
+    };
+
+    unsafe {
+        if let Some(early_trapper) = &*EARLY_TRAPPER.with(|ucell| ucell.get()) {
+            early_trapper.do_early_trap(err)
+        } else {
+            eprintln!("panic handling not setup");
+            std::process::exit(1)
+        }
+    }
 }
 
 pub trait TrapEarly<Rets>
@@ -74,6 +184,12 @@ where
 pub struct Func<'a, Args = (), Rets = (), Safety: Safeness = Safe> {
     f: *const (),
     ctx: *mut Ctx,
+    /// The context of the instance that the function was taken from,
+    /// which keeps track of interrupts and points for the call. It's
+    /// `ctx` unless the function is an import.
+    instance_ctx: *mut Ctx,
+    env: Option<Rc<FuncEnv>>,
+    protected_caller: Option<&'a dyn ProtectedCaller>,
     _phantom: PhantomData<(&'a (), Safety, Args, Rets)>,
 }
 
@@ -82,13 +198,72 @@ where
     Args: WasmTypeList,
     Rets: WasmTypeList,
 {
-    pub(crate) unsafe fn new_from_ptr(f: *const (), ctx: *mut Ctx) -> Func<'a, Args, Rets, Safe> {
+    pub(crate) unsafe fn new_from_ptr(
+        f: *const (),
+        ctx: *mut Ctx,
+        instance_ctx: *mut Ctx,
+        protected_caller: &'a dyn ProtectedCaller,
+    ) -> Func<'a, Args, Rets, Safe> {
         Func {
             f,
             ctx,
+            instance_ctx,
+            env: None,
+            protected_caller: Some(protected_caller),
             _phantom: PhantomData,
         }
     }
+
+    /// Call the function with the trap handlers of the
+    /// backend that compiled it installed.
+    ///
+    /// Only the outermost call into code of a backend goes through
+    /// `run_protected`. A host function that calls back into the same
+    /// code calls it directly, and a trap unwinds to the outermost
+    /// call, which returns it.
+    fn call_protected(&self, args: Args) -> Result<Rets, RuntimeError> {
+        let protected_caller = self
+            .protected_caller
+            .expect("a safe `Func` always has a protected caller");
+
+        if !protected_caller.has_native_code() {
+            let _call = crash::enter_ptr(self.instance_ctx, self.f as *const vm::Func);
+            let signature = FuncSig::new(Args::types(), Rets::types());
+            let rets = protected_caller.call_func(
+                self.f as *const vm::Func,
+                self.ctx,
+                &signature,
+                &args.into_values(),
+                Token::generate(),
+            );
+            let rets = unsafe { finish_call(self.instance_ctx, rets) }?;
+            return Ok(Rets::from_values(&rets));
+        }
+
+        let caller = protected_caller as *const dyn ProtectedCaller as *const ();
+        let prev_caller = PROTECTED_CALLER.with(|cell| cell.get());
+        if prev_caller == caller {
+            return Ok(unsafe { args.call::<Rets>(self.f, self.ctx) });
+        }
+
+        SWITCH_HOOK_INIT.call_once(|| stack::register_switch_hook(save_protected_caller));
+        let _call = crash::enter_ptr(self.instance_ctx, self.f as *const vm::Func);
+        PROTECTED_CALLER.with(|cell| cell.set(caller));
+
+        let mut args = Some(args);
+        let mut rets = None;
+        let result = protected_caller.run_protected(
+            &mut || {
+                let args = args.take().unwrap();
+                rets = Some(unsafe { args.call::<Rets>(self.f, self.ctx) });
+            },
+            Token::generate(),
+        );
+        PROTECTED_CALLER.with(|cell| cell.set(prev_caller));
+        unsafe { finish_call(self.instance_ctx, result) }?;
+
+        Ok(rets.expect("the function returned without a value"))
+    }
 }
 
 impl<'a, Args, Rets> Func<'a, Args, Rets, Unsafe>
@@ -96,13 +271,44 @@ where
     Args: WasmTypeList,
     Rets: WasmTypeList,
 {
+    /// Wrap a host function so it can be imported into an instance.
+    ///
+    /// Besides plain functions, `f` can be a closure that captures
+    /// state. Since the closure may be called from several instances,
+    /// it only gets shared access to that state; use a `Cell` or
+    /// `RefCell` to mutate it. It can't borrow anything, since the
+    /// exports made from it keep it alive for as long as they're
+    /// around.
+    ///
+    /// A closure can own its state:
+    ///
+    /// ```
+    /// # use wasmer_runtime_core::{vm::Ctx, Func};
+    /// # use std::{cell::Cell, rc::Rc};
+    /// let counter = Rc::new(Cell::new(0));
+    /// let f = Func::new(move |_ctx: &mut Ctx| counter.set(counter.get() + 1));
+    /// ```
+    ///
+    /// But it can't borrow it:
+    ///
+    /// ```compile_fail
+    /// # use wasmer_runtime_core::{vm::Ctx, Func};
+    /// # use std::cell::Cell;
+    /// let counter = Cell::new(0);
+    /// let f = Func::new(|_ctx: &mut Ctx| counter.set(counter.get() + 1));
+    /// ```
     pub fn new<F>(f: F) -> Func<'a, Args, Rets, Unsafe>
     where
-        F: ExternalFunction<Args, Rets>,
+        F: ExternalFunction<Args, Rets> + 'static,
     {
+        let (f, env) = f.to_raw();
+
         Func {
-            f: f.to_raw(),
+            f,
             ctx: ptr::null_mut(),
+            instance_ctx: ptr::null_mut(),
+            env: env.map(Rc::new),
+            protected_caller: None,
             _phantom: PhantomData,
         }
     }
@@ -136,11 +342,23 @@ impl<A: WasmExternType> WasmTypeList for (A,) {
     fn types() -> &'static [Type] {
         &[A::TYPE]
     }
+    fn into_values(self) -> Vec<Value> {
+        vec![self.0.to_value()]
+    }
+    fn from_values(values: &[Value]) -> Self {
+        (A::from_value(values[0].clone()),)
+    }
     #[allow(non_snake_case)]
     unsafe fn call<Rets: WasmTypeList>(self, f: *const (), ctx: *mut Ctx) -> Rets {
-        let f: extern "C" fn(*mut Ctx, A) -> Rets = mem::transmute(f);
         let (a,) = self;
-        f(ctx, a)
+        if returns_through_pointer::<Rets>() {
+            let f: extern "C" fn(*mut Ctx, A, *mut Rets::CStruct) = mem::transmute(f);
+            let mut c_struct = mem::uninitialized();
+            f(ctx, a, &mut c_struct);
+            return Rets::from_c_struct(c_struct);
+        }
+        let f: extern "C" fn(*mut Ctx, A) -> Rets::CStruct = mem::transmute(f);
+        Rets::from_c_struct(f(ctx, a))
     }
 }
 
@@ -149,7 +367,7 @@ where
     Rets: WasmTypeList,
 {
     pub fn call(&self, a: A) -> Result<Rets, RuntimeError> {
-        Ok(unsafe { <A as WasmTypeList>::call(a, self.f, self.ctx) })
+        self.call_protected((a,))
     }
 }
 
@@ -174,42 +392,77 @@ macro_rules! impl_traits {
                 &[$( $x::TYPE, )*]
             }
             #[allow(non_snake_case)]
+            fn into_values(self) -> Vec<Value> {
+                #[allow(unused_parens)]
+                let ( $( $x ),* ) = self;
+                vec![$( $x.to_value() ),*]
+            }
+            #[allow(unused_mut, unused_variables)]
+            fn from_values(values: &[Value]) -> Self {
+                let mut values = values.iter().cloned();
+                ( $( $x::from_value(values.next().expect("too few values")) ),* )
+            }
+            #[allow(non_snake_case)]
             unsafe fn call<Rets: WasmTypeList>(self, f: *const (), ctx: *mut Ctx) -> Rets {
-                let f: extern fn(*mut Ctx $( ,$x )*) -> Rets::CStruct = mem::transmute(f);
                 #[allow(unused_parens)]
                 let ( $( $x ),* ) = self;
+                if returns_through_pointer::<Rets>() {
+                    let f: extern fn(*mut Ctx $( ,$x )*, *mut Rets::CStruct) = mem::transmute(f);
+                    let mut c_struct = mem::uninitialized();
+                    f(ctx $( ,$x )*, &mut c_struct);
+                    return Rets::from_c_struct(c_struct);
+                }
+                let f: extern fn(*mut Ctx $( ,$x )*) -> Rets::CStruct = mem::transmute(f);
                 let c_struct = f(ctx $( ,$x )*);
                 Rets::from_c_struct(c_struct)
             }
         }
 
-        impl< $( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap> ExternalFunction<($( $x ),*), Rets> for FN {
+        impl< $( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap + 'static> ExternalFunction<($( $x ),*), Rets> for FN {
             #[allow(non_snake_case)]
-            fn to_raw(&self) -> *const () {
-                assert_eq!(mem::size_of::<Self>(), 0, "you cannot use a closure that captures state for `Func`.");
-
+            fn to_raw(self) -> (*const (), Option<FuncEnv>) {
                 extern fn wrap<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( ctx: &mut Ctx $( ,$x: $x )* ) -> Rets::CStruct {
                     let f: FN = unsafe { mem::transmute_copy(&()) };
+                    call_host(|| f( ctx $( ,$x )* ))
+                }
 
-                    let err = match panic::catch_unwind(panic::AssertUnwindSafe(|| {
-                        f( ctx $( ,$x )* ).report()
-                    })) {
-                        Ok(Ok(returns)) => return returns.into_c_struct(),
-                        Ok(Err(err)) => err,
-                        Err(err) => err,
-                    };
+                extern fn wrap_env<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( func_ctx: &mut FuncCtx $( ,$x: $x )* ) -> Rets::CStruct {
+                    let f: &FN = unsafe { &*(func_ctx.env as *const FN) };
+                    let ctx = unsafe { &mut *func_ctx.vmctx };
+                    call_host(|| f( ctx $( ,$x )* ))
+                }
 
-                    unsafe {
-                        if let Some(early_trapper) = &*EARLY_TRAPPER.with(|ucell| ucell.get()) {
-                            early_trapper.do_early_trap(err)
-                        } else {
-                            eprintln!("panic handling not setup");
-                            std::process::exit(1)
-                        }
-                    }
+                // The same, for results that are returned through a pointer.
+                extern fn wrap_rets<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( ctx: &mut Ctx $( ,$x: $x )*, rets: *mut Rets::CStruct ) {
+                    let f: FN = unsafe { mem::transmute_copy(&()) };
+                    let c_struct = call_host(|| f( ctx $( ,$x )* ));
+                    unsafe { ptr::write(rets, c_struct) };
                 }
 
-                wrap::<$( $x, )* Rets, Trap, Self> as *const ()
+                extern fn wrap_env_rets<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( func_ctx: &mut FuncCtx $( ,$x: $x )*, rets: *mut Rets::CStruct ) {
+                    let f: &FN = unsafe { &*(func_ctx.env as *const FN) };
+                    let ctx = unsafe { &mut *func_ctx.vmctx };
+                    let c_struct = call_host(|| f( ctx $( ,$x )* ));
+                    unsafe { ptr::write(rets, c_struct) };
+                }
+
+                let through_pointer = returns_through_pointer::<Rets>();
+                if mem::size_of::<Self>() == 0 {
+                    mem::forget(self);
+                    let f = if through_pointer {
+                        wrap_rets::<$( $x, )* Rets, Trap, Self> as *const ()
+                    } else {
+                        wrap::<$( $x, )* Rets, Trap, Self> as *const ()
+                    };
+                    (f, None)
+                } else {
+                    let f = if through_pointer {
+                        wrap_env_rets::<$( $x, )* Rets, Trap, Self> as *const ()
+                    } else {
+                        wrap_env::<$( $x, )* Rets, Trap, Self> as *const ()
+                    };
+                    (f, Some(FuncEnv::new(self)))
+                }
             }
         }
 
@@ -220,7 +473,7 @@ macro_rules! impl_traits {
             #[allow(non_snake_case)]
             pub fn call(&self, $( $x: $x, )* ) -> Result<Rets, RuntimeError> {
                 #[allow(unused_parens)]
-                Ok(unsafe { <( $( $x ),* ) as WasmTypeList>::call(( $($x),* ), self.f, self.ctx) })
+                self.call_protected(( $( $x ),* ))
             }
         }
     };
@@ -248,7 +501,10 @@ where
 {
     fn to_export(&self) -> Export {
         let func = unsafe { FuncPointer::new(self.f as _) };
-        let ctx = Context::Internal;
+        let ctx = match &self.env {
+            Some(env) => Context::InternalWithEnv(Rc::clone(env)),
+            None => Context::Internal,
+        };
         let signature = Arc::new(FuncSig::new(Args::types(), Rets::types()));
 
         Export::Function {
@@ -285,4 +541,74 @@ mod tests {
             },
         };
     }
+
+    #[test]
+    fn test_closure_env() {
+        use std::{cell::Cell, rc::Rc};
+
+        let counter = Rc::new(Cell::new(0));
+        let captured = Rc::clone(&counter);
+        let f = Func::new(move |_ctx: &mut Ctx, a: i32| -> i32 {
+            captured.set(captured.get() + a);
+            captured.get()
+        });
+
+        match f.to_export() {
+            Export::Function {
+                ctx: Context::InternalWithEnv(_),
+                ..
+            } => {}
+            _ => panic!("a capturing closure must export its environment"),
+        }
+
+        assert_eq!(Rc::strong_count(&counter), 2);
+        drop(f);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_result_offsets() {
+        use crate::types::{FuncSig, Type};
+
+        let sig = FuncSig::new(vec![], vec![Type::I32, Type::F64, Type::F32]);
+        let c_struct = S3(0i32, 0f64, 0f32);
+        let base = &c_struct as *const _ as usize;
+        let offsets = vec![
+            &c_struct.0 as *const _ as usize - base,
+            &c_struct.1 as *const _ as usize - base,
+            &c_struct.2 as *const _ as usize - base,
+        ];
+        assert!(sig.returns_through_pointer());
+        assert_eq!(sig.result_offsets(), (offsets, mem::size_of_val(&c_struct)));
+    }
+
+    #[test]
+    fn test_multiple_returns() {
+        fn swap(_ctx: &mut Ctx, a: i32, b: i64) -> (i64, i32) {
+            (b, a)
+        }
+
+        let mut ctx: Ctx = unsafe { mem::zeroed() };
+        let (f, env) = swap.to_raw();
+        assert!(env.is_none());
+        let rets = unsafe { (1, 2i64).call::<(i64, i32)>(f, &mut ctx) };
+        assert_eq!(rets, (2, 1));
+    }
+
+    #[test]
+    fn test_closure_multiple_returns() {
+        let offset = 10;
+        let (f, env) =
+            (move |_ctx: &mut Ctx, a: i32| -> (i32, i32, i32) { (a, a + offset, a + offset * 2) })
+                .to_raw();
+        let env = env.expect("a capturing closure must have an environment");
+
+        let mut ctx: Ctx = unsafe { mem::zeroed() };
+        let mut func_ctx = vm::FuncCtx {
+            vmctx: &mut ctx,
+            env: env.as_ptr(),
+        };
+        let rets = unsafe { (1,).call::<(i32, i32, i32)>(f, &mut func_ctx as *mut _ as *mut Ctx) };
+        assert_eq!(rets, (1, 11, 21));
+    }
 }
@@ -1,4 +1,10 @@
-use crate::{memory::MemoryType, module::ModuleInfo, structures::TypedIndex, units::Pages};
+use crate::{
+    memory::{memory_style, MemoryStyle, MemoryType},
+    module::ModuleInfo,
+    refs::{ExternRef, FuncRef},
+    structures::TypedIndex,
+    units::Pages,
+};
 use std::{borrow::Cow, mem};
 
 /// Represents a WebAssembly type.
@@ -12,6 +18,10 @@ pub enum Type {
     F32,
     /// The `f64` type.
     F64,
+    /// The `externref` type.
+    ExternRef,
+    /// The `funcref` type.
+    FuncRef,
 }
 
 impl std::fmt::Display for Type {
@@ -34,6 +44,13 @@ pub enum Value {
     F32(f32),
     /// The `f64` type.
     F64(f64),
+    /// The `externref` type. References are handles that are only
+    /// meaningful in this process, so they aren't serialized.
+    #[serde(skip)]
+    ExternRef(ExternRef),
+    /// The `funcref` type.
+    #[serde(skip)]
+    FuncRef(FuncRef),
 }
 
 impl Value {
@@ -43,6 +60,8 @@ impl Value {
             Value::I64(_) => Type::I64,
             Value::F32(_) => Type::F32,
             Value::F64(_) => Type::F64,
+            Value::ExternRef(_) => Type::ExternRef,
+            Value::FuncRef(_) => Type::FuncRef,
         }
     }
 }
@@ -71,11 +90,56 @@ impl From<f64> for Value {
     }
 }
 
+impl From<ExternRef> for Value {
+    fn from(r: ExternRef) -> Self {
+        Value::ExternRef(r)
+    }
+}
+
+impl From<FuncRef> for Value {
+    fn from(r: FuncRef) -> Self {
+        Value::FuncRef(r)
+    }
+}
+
 pub unsafe trait WasmExternType: Copy + Clone
 where
     Self: Sized,
 {
     const TYPE: Type;
+
+    /// Wrap this in a `Value` of type `TYPE`.
+    fn to_value(self) -> Value {
+        // Implementing this trait promises that `Self` is represented
+        // like the values of `TYPE`.
+        unsafe {
+            match Self::TYPE {
+                Type::I32 => Value::I32(mem::transmute_copy(&self)),
+                Type::I64 => Value::I64(mem::transmute_copy(&self)),
+                Type::F32 => Value::F32(mem::transmute_copy(&self)),
+                Type::F64 => Value::F64(mem::transmute_copy(&self)),
+                Type::ExternRef => Value::ExternRef(mem::transmute_copy(&self)),
+                Type::FuncRef => Value::FuncRef(mem::transmute_copy(&self)),
+            }
+        }
+    }
+
+    /// Unwrap a `Value` of type `TYPE`.
+    ///
+    /// Panics if `value` has another type.
+    fn from_value(value: Value) -> Self {
+        assert_eq!(value.ty(), Self::TYPE, "the value has the wrong type");
+        unsafe {
+            match value {
+                Value::I32(x) => mem::transmute_copy(&x),
+                Value::I64(x) => mem::transmute_copy(&x),
+                Value::F32(x) => mem::transmute_copy(&x),
+                Value::F64(x) => mem::transmute_copy(&x),
+                Value::ExternRef(x) => mem::transmute_copy(&x),
+                Value::FuncRef(x) => mem::transmute_copy(&x),
+            }
+        }
+    }
 }
 unsafe impl WasmExternType for i32 {
     const TYPE: Type = Type::I32;
@@ -95,6 +159,12 @@ unsafe impl WasmExternType for f32 {
 unsafe impl WasmExternType for f64 {
     const TYPE: Type = Type::F64;
 }
+unsafe impl WasmExternType for ExternRef {
+    const TYPE: Type = Type::ExternRef;
+}
+unsafe impl WasmExternType for FuncRef {
+    const TYPE: Type = Type::FuncRef;
+}
 
 // pub trait IntegerAtomic
 // where
@@ -173,9 +243,11 @@ impl ValueType for f64 {
 pub enum ElementType {
     /// Any wasm function.
     Anyfunc,
+    /// Any host reference.
+    ExternRef,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TableDescriptor {
     /// Type of data stored in this table.
     pub element: ElementType,
@@ -232,9 +304,19 @@ pub struct MemoryDescriptor {
 }
 
 impl MemoryDescriptor {
+    /// The type of memory that this describes, with the current
+    /// [`memory_style`].
+    ///
+    /// [`memory_style`]: ../memory/fn.memory_style.html
     pub fn memory_type(self) -> MemoryType {
+        self.memory_type_with_style(memory_style())
+    }
+
+    /// The type of memory that this describes with `style`.
+    pub fn memory_type_with_style(self, style: MemoryStyle) -> MemoryType {
         match (self.maximum.is_some(), self.shared) {
             (true, true) => MemoryType::SharedStatic,
+            (true, false) if style == MemoryStyle::Dynamic => MemoryType::Dynamic,
             (true, false) => MemoryType::Static,
             (false, false) => MemoryType::Dynamic,
             (false, true) => panic!("shared memory without a max is not allowed"),
@@ -279,6 +361,41 @@ impl FuncSig {
         &self.returns
     }
 
+    /// Whether native functions of this signature return their results
+    /// through a pointer that they take after their parameters, which
+    /// they do if they return more than one value. The results are
+    /// written to it as the fields of a `#[repr(C)]` struct, at the
+    /// offsets that [`result_offsets`] returns, as Rust's typed
+    /// functions and host functions do with the struct of their
+    /// results.
+    ///
+    /// [`result_offsets`]: #method.result_offsets
+    pub fn returns_through_pointer(&self) -> bool {
+        self.returns.len() > 1
+    }
+
+    /// The offset of each result in the struct that native functions
+    /// returning more than one value write them to, and the size of
+    /// that struct.
+    pub fn result_offsets(&self) -> (Vec<usize>, usize) {
+        let mut offsets = Vec::with_capacity(self.returns.len());
+        let mut size = 0;
+        let mut struct_align = 1;
+        for ty in self.returns.iter() {
+            // Every value is as aligned as it is large.
+            let align = match ty {
+                Type::I32 | Type::F32 => 4,
+                Type::I64 | Type::F64 | Type::ExternRef | Type::FuncRef => 8,
+            };
+            size = (size + align - 1) / align * align;
+            offsets.push(size);
+            size += align;
+            struct_align = struct_align.max(align);
+        }
+        let size = (size + struct_align - 1) / struct_align * struct_align;
+        (offsets, size)
+    }
+
     pub fn check_param_value_types(&self, params: &[Value]) -> bool {
         self.params.len() == params.len()
             && self
@@ -316,7 +433,7 @@ pub trait LocalImport {
 macro_rules! define_map_index {
     ($ty:ident) => {
         #[derive(Serialize, Deserialize)]
-        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
         pub struct $ty (u32);
         impl TypedIndex for $ty {
             #[doc(hidden)]
@@ -1,11 +1,16 @@
 pub use crate::backing::{ImportBacking, LocalBacking};
 use crate::{
+    epoch::EPOCH,
     memory::Memory,
     module::ModuleInner,
     structures::TypedIndex,
-    types::{LocalOrImport, MemoryIndex},
+    types::{LocalFuncIndex, LocalOrImport, MemoryIndex},
+};
+use std::{
+    ffi::c_void,
+    mem, ptr,
+    sync::atomic::{AtomicBool, AtomicU64},
 };
-use std::{ffi::c_void, mem, ptr};
 
 /// The context of the currently running WebAssembly instance.
 ///
@@ -18,9 +23,9 @@ pub struct Ctx {
 
     pub(crate) local_functions: *const *const Func,
 
-    local_backing: *mut LocalBacking,
-    import_backing: *mut ImportBacking,
-    module: *const ModuleInner,
+    pub(crate) local_backing: *mut LocalBacking,
+    pub(crate) import_backing: *mut ImportBacking,
+    pub(crate) module: *const ModuleInner,
 
     pub data: *mut c_void,
     pub data_finalizer: Option<extern "C" fn(data: *mut c_void)>,
@@ -59,8 +64,28 @@ pub struct InternalCtx {
     /// signature id. This is used to allow call-indirect to other
     /// modules safely.
     pub dynamic_sigindices: *const SigId,
+
+    /// Set when the instance has been asked to stop. Compiled code
+    /// checks it at the start of every function and loop iteration.
+    pub interrupted: *const AtomicBool,
+
+    /// A pointer to the epoch of the process, which compiled code
+    /// compares with `epoch_deadline` at the start of every function
+    /// and loop iteration.
+    pub epoch: *const AtomicU64,
+
+    /// The epoch at which the instance traps.
+    pub epoch_deadline: u64,
+
+    /// The points that metered code has left to use. Compiled code
+    /// traps when they go negative.
+    pub remaining_points: i64,
 }
 
+/// What `InternalCtx::interrupted` points to until an instance
+/// replaces it with its own flag.
+static NEVER_INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 impl Ctx {
     #[doc(hidden)]
     pub unsafe fn new(
@@ -80,6 +105,13 @@ impl Ctx {
                 imported_funcs: import_backing.vm_functions.as_mut_ptr(),
 
                 dynamic_sigindices: local_backing.dynamic_sigindices.as_ptr(),
+
+                interrupted: &NEVER_INTERRUPTED,
+
+                epoch: &EPOCH,
+                epoch_deadline: u64::max_value(),
+
+                remaining_points: i64::max_value(),
             },
             local_functions: local_backing.local_functions.as_ptr(),
 
@@ -112,6 +144,13 @@ impl Ctx {
                 imported_funcs: import_backing.vm_functions.as_mut_ptr(),
 
                 dynamic_sigindices: local_backing.dynamic_sigindices.as_ptr(),
+
+                interrupted: &NEVER_INTERRUPTED,
+
+                epoch: &EPOCH,
+                epoch_deadline: u64::max_value(),
+
+                remaining_points: i64::max_value(),
             },
             local_functions: local_backing.local_functions.as_ptr(),
 
@@ -160,6 +199,13 @@ impl Ctx {
 
 #[doc(hidden)]
 impl Ctx {
+    /// The code that calls to the local function `index` of the
+    /// instance go to, which is the module's unless the function has
+    /// been replaced.
+    pub fn local_function(&self, index: LocalFuncIndex) -> *const Func {
+        unsafe { *self.local_functions.add(index.index()) }
+    }
+
     #[allow(clippy::erasing_op)] // TODO
     pub fn offset_memories() -> u8 {
         0 * (mem::size_of::<usize>() as u8)
@@ -193,9 +239,25 @@ impl Ctx {
         7 * (mem::size_of::<usize>() as u8)
     }
 
-    pub fn offset_local_functions() -> u8 {
+    pub fn offset_interrupted() -> u8 {
         8 * (mem::size_of::<usize>() as u8)
     }
+
+    pub fn offset_epoch() -> u8 {
+        9 * (mem::size_of::<usize>() as u8)
+    }
+
+    pub fn offset_epoch_deadline() -> u8 {
+        10 * (mem::size_of::<usize>() as u8)
+    }
+
+    pub fn offset_remaining_points() -> u8 {
+        11 * (mem::size_of::<usize>() as u8)
+    }
+
+    pub fn offset_local_functions() -> u8 {
+        12 * (mem::size_of::<usize>() as u8)
+    }
 }
 
 enum InnerFunc {}
@@ -205,6 +267,16 @@ enum InnerFunc {}
 #[repr(C)]
 pub struct Func(InnerFunc);
 
+/// Passed in place of the vmctx to host closures that are imported
+/// into an instance, so that they can get at both the `Ctx` of that
+/// instance and their captured environment.
+#[derive(Debug)]
+#[repr(C)]
+pub struct FuncCtx {
+    pub vmctx: *mut Ctx,
+    pub env: *mut c_void,
+}
+
 /// An imported function, which contains the vmctx that owns this function.
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -389,6 +461,26 @@ mod vm_offset_tests {
             offset_of!(InternalCtx => imported_funcs).get_byte_offset(),
         );
 
+        assert_eq!(
+            Ctx::offset_interrupted() as usize,
+            offset_of!(InternalCtx => interrupted).get_byte_offset(),
+        );
+
+        assert_eq!(
+            Ctx::offset_epoch() as usize,
+            offset_of!(InternalCtx => epoch).get_byte_offset(),
+        );
+
+        assert_eq!(
+            Ctx::offset_epoch_deadline() as usize,
+            offset_of!(InternalCtx => epoch_deadline).get_byte_offset(),
+        );
+
+        assert_eq!(
+            Ctx::offset_remaining_points() as usize,
+            offset_of!(InternalCtx => remaining_points).get_byte_offset(),
+        );
+
         assert_eq!(
             Ctx::offset_local_functions() as usize,
             offset_of!(Ctx => local_functions).get_byte_offset(),
@@ -464,8 +556,11 @@ mod vm_offset_tests {
 #[cfg(test)]
 mod vm_ctx_tests {
     use super::{Ctx, ImportBacking, LocalBacking};
+    use crate::debugger::DebugState;
+    use crate::limits::InstanceLimiter;
     use crate::module::{ModuleInfo, ModuleInner, StringTable};
     use crate::structures::Map;
+    use hashbrown::HashSet;
     use std::ffi::c_void;
 
     struct TestData {
@@ -501,6 +596,12 @@ mod vm_ctx_tests {
 
             dynamic_sigindices: Map::new().into_boxed_map(),
             local_functions: Map::new().into_boxed_map(),
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            debug: DebugState::new(),
+            limiter: InstanceLimiter::new(None),
         };
         let mut import_backing = ImportBacking {
             memories: Map::new().into_boxed_map(),
@@ -508,6 +609,7 @@ mod vm_ctx_tests {
             globals: Map::new().into_boxed_map(),
 
             vm_functions: Map::new().into_boxed_map(),
+            func_ctxs: vec![],
             vm_memories: Map::new().into_boxed_map(),
             vm_tables: Map::new().into_boxed_map(),
             vm_globals: Map::new().into_boxed_map(),
@@ -530,6 +632,167 @@ mod vm_ctx_tests {
         drop(ctx);
     }
 
+    #[test]
+    fn test_bulk_memory() {
+        use crate::memory::Memory;
+        use crate::structures::TypedIndex;
+        use crate::types::{MemoryDescriptor, MemoryIndex};
+        use crate::units::Pages;
+        use crate::vmcalls;
+
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+        let mut memories = Map::new();
+        let mut vm_memories = Map::new();
+        vm_memories.push(memory.vm_local_memory());
+        memories.push(memory);
+
+        let mut local_backing = LocalBacking {
+            memories: memories.into_boxed_map(),
+            tables: Map::new().into_boxed_map(),
+            globals: Map::new().into_boxed_map(),
+
+            vm_memories: vm_memories.into_boxed_map(),
+            vm_tables: Map::new().into_boxed_map(),
+            vm_globals: Map::new().into_boxed_map(),
+
+            dynamic_sigindices: Map::new().into_boxed_map(),
+            local_functions: Map::new().into_boxed_map(),
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            debug: DebugState::new(),
+            limiter: InstanceLimiter::new(None),
+        };
+        let mut import_backing = ImportBacking {
+            memories: Map::new().into_boxed_map(),
+            tables: Map::new().into_boxed_map(),
+            globals: Map::new().into_boxed_map(),
+
+            vm_functions: Map::new().into_boxed_map(),
+            func_ctxs: vec![],
+            vm_memories: Map::new().into_boxed_map(),
+            vm_tables: Map::new().into_boxed_map(),
+            vm_globals: Map::new().into_boxed_map(),
+        };
+        let mut module = generate_module();
+        module.info.memories.push(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        });
+        module.info.passive_data.insert(0, vec![1, 2, 3, 4]);
+        let mut ctx = unsafe { Ctx::new(&mut local_backing, &mut import_backing, &module) };
+        let memory_index = MemoryIndex::new(0);
+
+        unsafe {
+            assert_eq!(vmcalls::memory_init(&mut ctx, memory_index, 0, 8, 1, 3), 0);
+            assert_eq!(vmcalls::memory_fill(&mut ctx, memory_index, 0, 0xff, 2), 0);
+            assert_eq!(vmcalls::memory_copy(&mut ctx, memory_index, 1, 8, 3), 0);
+
+            // Out of bounds accesses fail without writing anything.
+            assert_eq!(vmcalls::memory_init(&mut ctx, memory_index, 0, 0, 2, 3), 1);
+            assert_eq!(vmcalls::memory_fill(&mut ctx, memory_index, 65535, 0, 2), 1);
+            assert_eq!(vmcalls::memory_copy(&mut ctx, memory_index, 0, 65535, 2), 1);
+
+            // A dropped segment is empty.
+            vmcalls::data_drop(&mut ctx, 0);
+            assert_eq!(vmcalls::memory_init(&mut ctx, memory_index, 0, 0, 0, 1), 1);
+            assert_eq!(vmcalls::memory_init(&mut ctx, memory_index, 0, 0, 0, 0), 0);
+        }
+
+        let bytes: Vec<u8> = ctx.memory(0).view()[..11]
+            .iter()
+            .map(|cell| cell.get())
+            .collect();
+        assert_eq!(bytes, [0xff, 2, 3, 4, 0, 0, 0, 0, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_closure_import_outlives_import_object() {
+        use crate::module::{ImportName, StringTableBuilder};
+        use crate::structures::TypedIndex;
+        use crate::types::{FuncSig, ImportedFuncIndex, SigIndex, Type};
+        use crate::{func, imports};
+        use std::{cell::Cell, rc::Rc};
+
+        let mut module = generate_module();
+        let mut namespaces = StringTableBuilder::new();
+        let mut names = StringTableBuilder::new();
+        module.info.imported_functions.push(ImportName {
+            namespace_index: namespaces.register("env"),
+            name_index: names.register("add"),
+        });
+        module.info.namespace_table = namespaces.finish();
+        module.info.name_table = names.finish();
+        let sig_index: SigIndex = module
+            .info
+            .signatures
+            .push(FuncSig::new(vec![Type::I32], vec![Type::I32]));
+        module.info.func_assoc.push(sig_index);
+
+        let counter = Rc::new(Cell::new(0));
+        let captured = Rc::clone(&counter);
+        let add = move |_ctx: &mut Ctx, a: i32| -> i32 {
+            captured.set(captured.get() + a);
+            captured.get()
+        };
+        let import_object = imports! {
+            "env" => {
+                "add" => func!(add),
+            },
+        };
+        let mut local_backing = LocalBacking {
+            memories: Map::new().into_boxed_map(),
+            tables: Map::new().into_boxed_map(),
+            globals: Map::new().into_boxed_map(),
+
+            vm_memories: Map::new().into_boxed_map(),
+            vm_tables: Map::new().into_boxed_map(),
+            vm_globals: Map::new().into_boxed_map(),
+
+            dynamic_sigindices: Map::new().into_boxed_map(),
+            local_functions: Map::new().into_boxed_map(),
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            debug: DebugState::new(),
+            limiter: InstanceLimiter::new(None),
+        };
+        let mut no_imports = ImportBacking {
+            memories: Map::new().into_boxed_map(),
+            tables: Map::new().into_boxed_map(),
+            globals: Map::new().into_boxed_map(),
+
+            vm_functions: Map::new().into_boxed_map(),
+            func_ctxs: vec![],
+            vm_memories: Map::new().into_boxed_map(),
+            vm_tables: Map::new().into_boxed_map(),
+            vm_globals: Map::new().into_boxed_map(),
+        };
+        let mut ctx = unsafe { Ctx::new(&mut local_backing, &mut no_imports, &module) };
+
+        let import_backing = ImportBacking::new(&module, &import_object, &mut ctx).unwrap();
+        drop(import_object);
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        // Call the import the way compiled code does.
+        let import = &import_backing.vm_functions[ImportedFuncIndex::new(0)];
+        let f: extern "C" fn(*mut Ctx, i32) -> i32 = unsafe { std::mem::transmute(import.func) };
+        assert_eq!(f(import.vmctx, 2), 2);
+        assert_eq!(f(import.vmctx, 3), 5);
+        assert_eq!(counter.get(), 5);
+
+        drop(import_backing);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
     fn cast_test_data(data: *mut c_void) -> &'static mut TestData {
         let test_data: &mut TestData = unsafe { &mut *(data as *mut TestData) };
         test_data
@@ -542,6 +805,7 @@ mod vm_ctx_tests {
         };
         use crate::cache::{Error as CacheError, WasmHash};
         use crate::error::RuntimeResult;
+        use crate::memory::MemoryStyle;
         use crate::types::{FuncIndex, LocalFuncIndex, Value};
         use hashbrown::HashMap;
         use std::ptr::NonNull;
@@ -567,6 +831,10 @@ mod vm_ctx_tests {
             ) -> RuntimeResult<Vec<Value>> {
                 Ok(vec![])
             }
+            fn run_protected(&self, f: &mut dyn FnMut(), _: Token) -> RuntimeResult<()> {
+                f();
+                Ok(())
+            }
             fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
                 unimplemented!()
             }
@@ -600,14 +868,22 @@ mod vm_ctx_tests {
                 data_initializers: Vec::new(),
                 elem_initializers: Vec::new(),
 
+                passive_data: HashMap::new(),
+                passive_elements: HashMap::new(),
+
                 start_func: None,
 
                 func_assoc: Map::new(),
                 signatures: Map::new(),
                 backend: Backend::Cranelift,
+                memory_style: MemoryStyle::Static,
+                initial_points: None,
 
                 namespace_table: StringTable::new(),
                 name_table: StringTable::new(),
+
+                custom_sections: HashMap::new(),
+                func_names: HashMap::new(),
             },
         }
     }
@@ -1,12 +1,22 @@
 #![allow(clippy::cast_ptr_alignment)]
 
 use crate::{
-    memory::{DynamicMemory, StaticMemory},
+    memory::{notify_grow, waiter, DynamicMemory, SharedStaticMemory, StaticMemory},
+    refs::{ExternRef, FuncRef},
     structures::TypedIndex,
-    types::{ImportedMemoryIndex, LocalMemoryIndex, LocalTableIndex},
+    table::Table,
+    types::{
+        ElementType, FuncIndex, ImportedMemoryIndex, LocalMemoryIndex, LocalOrImport,
+        LocalTableIndex, MemoryIndex, TableIndex,
+    },
     units::Pages,
     vm,
 };
+use std::{
+    ptr,
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering},
+    time::Duration,
+};
 
 // +*****************************+
 // |       LOCAL MEMORIES        |
@@ -19,9 +29,20 @@ pub unsafe extern "C" fn local_static_memory_grow(
 ) -> i32 {
     let local_memory = *ctx.internal.memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut StaticMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.memories[memory_index].maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
 
     match (*memory).grow(delta, &mut *local_memory) {
-        Ok(old) => old.0 as i32,
+        Ok(old) => {
+            notify_grow((*memory).grow_callback(), old, delta);
+            old.0 as i32
+        }
         Err(_) => -1,
     }
 }
@@ -43,9 +64,20 @@ pub unsafe extern "C" fn local_dynamic_memory_grow(
 ) -> i32 {
     let local_memory = *ctx.internal.memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut DynamicMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.memories[memory_index].maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
 
     match (*memory).grow(delta, &mut *local_memory) {
-        Ok(old) => old.0 as i32,
+        Ok(old) => {
+            notify_grow((*memory).grow_callback(), old, delta);
+            old.0 as i32
+        }
         Err(_) => -1,
     }
 }
@@ -60,6 +92,38 @@ pub unsafe extern "C" fn local_dynamic_memory_size(
     (*memory).size()
 }
 
+pub unsafe extern "C" fn local_shared_static_memory_grow(
+    ctx: &mut vm::Ctx,
+    memory_index: LocalMemoryIndex,
+    delta: Pages,
+) -> i32 {
+    let local_memory = *ctx.internal.memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.memories[memory_index].maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
+
+    match (*memory).grow(delta, &mut *local_memory) {
+        Ok(old) => old.0 as i32,
+        Err(_) => -1,
+    }
+}
+
+pub unsafe extern "C" fn local_shared_static_memory_size(
+    ctx: &vm::Ctx,
+    memory_index: LocalMemoryIndex,
+) -> Pages {
+    let local_memory = *ctx.internal.memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    (*memory).size()
+}
+
 // +*****************************+
 // |      IMPORTED MEMORIES      |
 // +****************************+
@@ -74,9 +138,20 @@ pub unsafe extern "C" fn imported_static_memory_grow(
         .imported_memories
         .add(import_memory_index.index());
     let memory = (*local_memory).memory as *mut StaticMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.imported_memories[import_memory_index].1.maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
 
     match (*memory).grow(delta, &mut *local_memory) {
-        Ok(old) => old.0 as i32,
+        Ok(old) => {
+            notify_grow((*memory).grow_callback(), old, delta);
+            old.0 as i32
+        }
         Err(_) => -1,
     }
 }
@@ -101,9 +176,20 @@ pub unsafe extern "C" fn imported_dynamic_memory_grow(
 ) -> i32 {
     let local_memory = *ctx.internal.imported_memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut DynamicMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.imported_memories[memory_index].1.maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
 
     match (*memory).grow(delta, &mut *local_memory) {
-        Ok(old) => old.0 as i32,
+        Ok(old) => {
+            notify_grow((*memory).grow_callback(), old, delta);
+            old.0 as i32
+        }
         Err(_) => -1,
     }
 }
@@ -118,6 +204,355 @@ pub unsafe extern "C" fn imported_dynamic_memory_size(
     (*memory).size()
 }
 
+pub unsafe extern "C" fn imported_shared_static_memory_grow(
+    ctx: &mut vm::Ctx,
+    memory_index: ImportedMemoryIndex,
+    delta: Pages,
+) -> i32 {
+    let local_memory = *ctx.internal.imported_memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+    let module = &*ctx.module;
+    let maximum = module.info.imported_memories[memory_index].1.maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .memory_growing((*memory).size(), delta, maximum)
+    {
+        return -1;
+    }
+
+    match (*memory).grow(delta, &mut *local_memory) {
+        Ok(old) => old.0 as i32,
+        Err(_) => -1,
+    }
+}
+
+pub unsafe extern "C" fn imported_shared_static_memory_size(
+    ctx: &vm::Ctx,
+    memory_index: ImportedMemoryIndex,
+) -> Pages {
+    let local_memory = *ctx.internal.imported_memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    (*memory).size()
+}
+
+// +*****************************+
+// |           ATOMICS           |
+// +****************************+
+
+/// The operations that [`atomic_rmw`] can perform.
+///
+/// [`atomic_rmw`]: fn.atomic_rmw.html
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtomicRmwOp {
+    Load,
+    Store,
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+    Cmpxchg,
+}
+
+/// Atomically perform `op` on the `width` byte integer at `ptr`,
+/// returning the previous value, zero-extended.
+///
+/// `Cmpxchg` stores `replacement` if the previous value was
+/// `operand`. The other operations ignore `replacement`.
+///
+/// `ptr` must be aligned to `width`, which is 1, 2, 4 or 8.
+pub unsafe extern "C" fn atomic_rmw(
+    op: AtomicRmwOp,
+    width: u32,
+    ptr: *mut u8,
+    operand: u64,
+    replacement: u64,
+) -> u64 {
+    macro_rules! rmw {
+        ($atomic:ty, $int:ty) => {{
+            let atomic = &*(ptr as *const $atomic);
+            let operand = operand as $int;
+            let previous = match op {
+                AtomicRmwOp::Load => atomic.load(Ordering::SeqCst),
+                AtomicRmwOp::Store => {
+                    atomic.store(operand, Ordering::SeqCst);
+                    0
+                }
+                AtomicRmwOp::Add => atomic.fetch_add(operand, Ordering::SeqCst),
+                AtomicRmwOp::Sub => atomic.fetch_sub(operand, Ordering::SeqCst),
+                AtomicRmwOp::And => atomic.fetch_and(operand, Ordering::SeqCst),
+                AtomicRmwOp::Or => atomic.fetch_or(operand, Ordering::SeqCst),
+                AtomicRmwOp::Xor => atomic.fetch_xor(operand, Ordering::SeqCst),
+                AtomicRmwOp::Xchg => atomic.swap(operand, Ordering::SeqCst),
+                AtomicRmwOp::Cmpxchg => match atomic.compare_exchange(
+                    operand,
+                    replacement as $int,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                ) {
+                    Ok(previous) | Err(previous) => previous,
+                },
+            };
+            previous as u64
+        }};
+    }
+
+    match width {
+        1 => rmw!(AtomicU8, u8),
+        2 => rmw!(AtomicU16, u16),
+        4 => rmw!(AtomicU32, u32),
+        8 => rmw!(AtomicU64, u64),
+        _ => unreachable!("invalid atomic width: {}", width),
+    }
+}
+
+/// Wait until the `width` byte integer at `ptr` is notified, as
+/// long as it's `expected` when the wait begins.
+///
+/// `timeout` is in nanoseconds, with a negative timeout meaning
+/// no timeout. Returns 0 if woken up, 1 if the integer wasn't
+/// `expected` and 2 if the timeout expired.
+pub unsafe extern "C" fn atomic_wait(
+    width: u32,
+    ptr: *const u8,
+    expected: u64,
+    timeout: i64,
+) -> u32 {
+    let is_expected = || match width {
+        4 => u64::from((*(ptr as *const AtomicU32)).load(Ordering::SeqCst)) == expected,
+        8 => (*(ptr as *const AtomicU64)).load(Ordering::SeqCst) == expected,
+        _ => unreachable!("invalid atomic width: {}", width),
+    };
+    let timeout = if timeout < 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(timeout as u64))
+    };
+
+    waiter::wait(ptr as usize, is_expected, timeout) as u32
+}
+
+/// Wake up at most `count` of the threads waiting on `ptr`,
+/// returning how many were woken up.
+pub unsafe extern "C" fn atomic_notify(ptr: *const u8, count: u32) -> u32 {
+    waiter::notify(ptr as usize, count)
+}
+
+// +*****************************+
+// |         BULK MEMORY         |
+// +****************************+
+//
+// These return 0 on success and 1, without having changed
+// anything, if any of the accessed ranges is out of bounds.
+// A dropped segment behaves like an empty one.
+
+/// Copy `len` bytes from `src` to `dst`, which may overlap.
+pub unsafe extern "C" fn memory_copy(
+    ctx: &mut vm::Ctx,
+    memory_index: MemoryIndex,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    let memory = vm_memory(ctx, memory_index);
+    if !in_bounds(src, len, memory.bound) || !in_bounds(dst, len, memory.bound) {
+        return 1;
+    }
+
+    ptr::copy(
+        memory.base.add(src as usize),
+        memory.base.add(dst as usize),
+        len as usize,
+    );
+    0
+}
+
+/// Set `len` bytes starting at `dst` to the low byte of `value`.
+pub unsafe extern "C" fn memory_fill(
+    ctx: &mut vm::Ctx,
+    memory_index: MemoryIndex,
+    dst: u32,
+    value: u32,
+    len: u32,
+) -> u32 {
+    let memory = vm_memory(ctx, memory_index);
+    if !in_bounds(dst, len, memory.bound) {
+        return 1;
+    }
+
+    ptr::write_bytes(memory.base.add(dst as usize), value as u8, len as usize);
+    0
+}
+
+/// Copy `len` bytes, starting at `src` in the passive data
+/// segment `segment`, to `dst`.
+pub unsafe extern "C" fn memory_init(
+    ctx: &mut vm::Ctx,
+    memory_index: MemoryIndex,
+    segment: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    let module = &*ctx.module;
+    let data: &[u8] = match module.info.passive_data.get(&segment) {
+        Some(data) if !(*ctx.local_backing).dropped_data.contains(&segment) => data,
+        _ => &[],
+    };
+
+    let memory = vm_memory(ctx, memory_index);
+    if !in_bounds(src, len, data.len()) || !in_bounds(dst, len, memory.bound) {
+        return 1;
+    }
+
+    ptr::copy_nonoverlapping(
+        data.as_ptr().add(src as usize),
+        memory.base.add(dst as usize),
+        len as usize,
+    );
+    0
+}
+
+/// Drop the passive data segment `segment`.
+pub unsafe extern "C" fn data_drop(ctx: &mut vm::Ctx, segment: u32) {
+    (*ctx.local_backing).dropped_data.insert(segment);
+}
+
+/// Copy `len` functions, starting at `src` in the passive element
+/// segment `segment`, to `dst` in the table `table_index`.
+pub unsafe extern "C" fn table_init(
+    ctx: &mut vm::Ctx,
+    table_index: TableIndex,
+    segment: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    let module = &*ctx.module;
+    let elements: &[FuncIndex] = match module.info.passive_elements.get(&segment) {
+        Some(elements) if !(*ctx.local_backing).dropped_elements.contains(&segment) => elements,
+        _ => &[],
+    };
+
+    let table = vm_table(ctx, table_index);
+    if !in_bounds(src, len, elements.len()) || !in_bounds(dst, len, table.size() as usize) {
+        return 1;
+    }
+
+    let imports = &*ctx.import_backing;
+    let sig_ids = &(*ctx.local_backing).dynamic_sigindices;
+    let local_functions = &(*ctx.local_backing).local_functions;
+    let vmctx: *mut vm::Ctx = ctx;
+    let elements = &elements[src as usize..src as usize + len as usize];
+    table.anyfunc_direct_access_mut(|table| {
+        for (i, &func_index) in elements.iter().enumerate() {
+            table[dst as usize + i] = vm::LocalBacking::anyfunc(
+                module,
+                imports,
+                sig_ids,
+                local_functions,
+                vmctx,
+                func_index,
+            );
+        }
+    });
+    0
+}
+
+/// Drop the passive element segment `segment`.
+pub unsafe extern "C" fn elem_drop(ctx: &mut vm::Ctx, segment: u32) {
+    (*ctx.local_backing).dropped_elements.insert(segment);
+}
+
+unsafe fn vm_memory(ctx: &vm::Ctx, memory_index: MemoryIndex) -> &vm::LocalMemory {
+    match memory_index.local_or_import(&(*ctx.module).info) {
+        LocalOrImport::Local(local_memory_index) => {
+            &**ctx.internal.memories.add(local_memory_index.index())
+        }
+        LocalOrImport::Import(imported_memory_index) => &**ctx
+            .internal
+            .imported_memories
+            .add(imported_memory_index.index()),
+    }
+}
+
+fn in_bounds(start: u32, len: u32, bound: usize) -> bool {
+    start as usize + len as usize <= bound
+}
+
+// +*****************************+
+// |       REFERENCE TYPES       |
+// +****************************+
+//
+// References are passed around as the handles
+// of an `ExternRef` or a `FuncRef`.
+
+/// The element at `index` in the table `table_index`.
+///
+/// `index` must be in bounds.
+pub unsafe extern "C" fn table_get(ctx: &mut vm::Ctx, table_index: TableIndex, index: u32) -> u64 {
+    let table = vm_table(ctx, table_index);
+    match table.descriptor().element {
+        ElementType::Anyfunc => table.anyfunc_direct_access_mut(|elements| {
+            FuncRef::from_anyfunc(elements[index as usize]).to_bits()
+        }),
+        ElementType::ExternRef => {
+            table.externref_direct_access_mut(|elements| elements[index as usize].to_bits())
+        }
+    }
+}
+
+/// Set the element at `index` in the table `table_index`. A reference
+/// to a function that has been forgotten is stored as null.
+///
+/// `index` must be in bounds.
+pub unsafe extern "C" fn table_set(
+    ctx: &mut vm::Ctx,
+    table_index: TableIndex,
+    index: u32,
+    value: u64,
+) {
+    let table = vm_table(ctx, table_index);
+    match table.descriptor().element {
+        ElementType::Anyfunc => table.anyfunc_direct_access_mut(|elements| {
+            elements[index as usize] = FuncRef::from_bits(value)
+                .anyfunc()
+                .unwrap_or_else(vm::Anyfunc::null);
+        }),
+        ElementType::ExternRef => table.externref_direct_access_mut(|elements| {
+            elements[index as usize] = ExternRef::from_bits(value);
+        }),
+    }
+}
+
+/// The reference to the function `func_index`.
+pub unsafe extern "C" fn ref_func(ctx: &mut vm::Ctx, func_index: FuncIndex) -> FuncRef {
+    let module = &*ctx.module;
+    let imports = &*ctx.import_backing;
+    let sig_ids = &(*ctx.local_backing).dynamic_sigindices;
+    let local_functions = &(*ctx.local_backing).local_functions;
+    FuncRef::from_anyfunc(vm::LocalBacking::anyfunc(
+        module,
+        imports,
+        sig_ids,
+        local_functions,
+        ctx,
+        func_index,
+    ))
+}
+
+unsafe fn vm_table<'a>(ctx: &vm::Ctx, table_index: TableIndex) -> &'a Table {
+    match table_index.local_or_import(&(*ctx.module).info) {
+        LocalOrImport::Local(local_table_index) => &(&*ctx.local_backing).tables[local_table_index],
+        LocalOrImport::Import(imported_table_index) => {
+            &(&*ctx.import_backing).tables[imported_table_index]
+        }
+    }
+}
+
 // +*****************************+
 // |        LOCAL TABLES         |
 // +****************************+
@@ -127,14 +562,22 @@ pub unsafe extern "C" fn local_table_grow(
     table_index: LocalTableIndex,
     delta: u32,
 ) -> i32 {
-    let _ = table_index;
-    let _ = delta;
-    let _ = ctx;
-    unimplemented!()
+    let table = &(&*ctx.local_backing).tables[table_index];
+    let module = &*ctx.module;
+    let maximum = module.info.tables[table_index].maximum;
+    if !(*ctx.local_backing)
+        .limiter
+        .table_growing(table.size(), delta, maximum)
+    {
+        return -1;
+    }
+
+    match table.grow(delta) {
+        Ok(old) => old as i32,
+        Err(_) => -1,
+    }
 }
 
 pub unsafe extern "C" fn local_table_size(ctx: &vm::Ctx, table_index: LocalTableIndex) -> u32 {
-    let _ = table_index;
-    let _ = ctx;
-    unimplemented!()
+    (&*ctx.local_backing).tables[table_index].size()
 }
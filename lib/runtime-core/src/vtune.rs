@@ -0,0 +1,70 @@
+//! Naming compiled code for Intel VTune, through its JIT profiling API.
+//!
+//! This links against `jitprofiling`, the static library that comes
+//! with VTune, whose directory has to be on the linker's search path,
+//! for instance with `RUSTFLAGS="-L $VTUNE_DIR/lib64"`. The library
+//! does nothing unless the process is run under VTune, which it finds
+//! out by itself.
+
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_int, c_uint, c_void},
+    ptr,
+};
+
+/// `iJVM_EVENT_TYPE_METHOD_LOAD_FINISHED`.
+const METHOD_LOAD_FINISHED: c_int = 13;
+/// `iJIT_SAMPLING_ON`.
+const SAMPLING_ON: c_int = 1;
+
+/// `iJIT_Method_Load`.
+#[repr(C)]
+struct MethodLoad {
+    method_id: c_uint,
+    method_name: *mut c_char,
+    method_load_address: *mut c_void,
+    method_size: c_uint,
+    line_number_size: c_uint,
+    line_number_table: *mut c_void,
+    class_id: c_uint,
+    class_file_name: *mut c_char,
+    source_file_name: *mut c_char,
+}
+
+#[link(name = "jitprofiling", kind = "static")]
+extern "C" {
+    fn iJIT_NotifyEvent(event_type: c_int, event_data: *mut c_void) -> c_int;
+    fn iJIT_IsProfilingActive() -> c_int;
+    fn iJIT_GetNewMethodID() -> c_uint;
+}
+
+/// Whether the process is being profiled by VTune.
+pub fn is_active() -> bool {
+    unsafe { iJIT_IsProfilingActive() == SAMPLING_ON }
+}
+
+/// Tell VTune that the function `name` has been loaded at `address`.
+pub fn register(name: &str, address: *const u8, size: usize) {
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    let mut method = MethodLoad {
+        method_id: unsafe { iJIT_GetNewMethodID() },
+        // VTune copies the name.
+        method_name: name.as_ptr() as *mut c_char,
+        method_load_address: address as *mut c_void,
+        method_size: size as c_uint,
+        line_number_size: 0,
+        line_number_table: ptr::null_mut(),
+        class_id: 0,
+        class_file_name: ptr::null_mut(),
+        source_file_name: ptr::null_mut(),
+    };
+    unsafe {
+        iJIT_NotifyEvent(
+            METHOD_LOAD_FINISHED,
+            &mut method as *mut MethodLoad as *mut c_void,
+        );
+    }
+}
@@ -0,0 +1,62 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+use std::{cell::Cell, ptr, rc::Rc};
+use wasmer_runtime::{func, imports, instantiate, Ctx, Func, Instance};
+
+static WAT: &'static str = r#"
+(module
+  (import "env" "reenter" (func $reenter (param i32) (result i32)))
+  (func (export "add_one") (param i32) (result i32)
+    get_local 0
+    i32.const 1
+    i32.add)
+  (func (export "reenter") (param i32) (result i32)
+    get_local 0
+    call $reenter))
+"#;
+
+/// How many times the host function calls back into the instance.
+const REENTRIES: i32 = 100;
+
+fn call_benchmark(c: &mut Criterion) {
+    c.bench_function("call export", |b| {
+        let wasm = wabt::wat2wasm(WAT).unwrap();
+        let reenter = |_ctx: &mut Ctx, n: i32| n;
+        let import_object = imports! {
+            "env" => {
+                "reenter" => func!(reenter),
+            },
+        };
+        let instance = instantiate(&wasm, &import_object).expect("unable to instantiate");
+        let add_one: Func<i32, i32> = instance.func("add_one").unwrap();
+
+        b.iter(|| add_one.call(42).unwrap())
+    });
+}
+
+fn reentrant_call_benchmark(c: &mut Criterion) {
+    c.bench_function("reentrant call export", |b| {
+        let wasm = wabt::wat2wasm(WAT).unwrap();
+        let instance_ptr: Rc<Cell<*const Instance>> = Rc::new(Cell::new(ptr::null()));
+        let reenter_instance = instance_ptr.clone();
+        let reenter = move |_ctx: &mut Ctx, n: i32| {
+            let instance = unsafe { &*reenter_instance.get() };
+            let add_one: Func<i32, i32> = instance.func("add_one").unwrap();
+            (0..REENTRIES).fold(n, |n, _| add_one.call(n).unwrap())
+        };
+        let import_object = imports! {
+            "env" => {
+                "reenter" => func!(reenter),
+            },
+        };
+        let instance = instantiate(&wasm, &import_object).expect("unable to instantiate");
+        instance_ptr.set(&instance);
+        let call_reenter: Func<i32, i32> = instance.func("reenter").unwrap();
+
+        b.iter(|| call_reenter.call(0).unwrap())
+    });
+}
+
+criterion_group!(benches, call_benchmark, reentrant_call_benchmark);
+criterion_main!(benches);
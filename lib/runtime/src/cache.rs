@@ -1,20 +1,37 @@
 use crate::Module;
 use memmap::Mmap;
 use std::{
-    fs::{create_dir_all, File},
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs::{self, create_dir_all, File},
     io::{self, Write},
     path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use wasmer_runtime_core::cache::Error as CacheError;
-pub use wasmer_runtime_core::cache::{Artifact, Cache, WasmHash, WASMER_VERSION_HASH};
+pub use wasmer_runtime_core::cache::{
+    set_artifact_signer, Artifact, ArtifactSigner, Cache, KeyedHash, Target, WasmHash,
+    WASMER_VERSION_HASH,
+};
 
 /// Representation of a directory that contains compiled wasm artifacts.
 ///
 /// The `FileSystemCache` type implements the [`Cache`] trait, which allows it to be used
 /// generically when some sort of cache is required.
 ///
+/// Entries are keyed by the supplied [`WasmHash`] combined with the
+/// default backend and the cpu features of the host, so an artifact
+/// is never loaded on a machine or backend that didn't produce it.
+/// Artifacts are written to a temporary file and then renamed into
+/// place, so a concurrent or interrupted `store` never leaves a
+/// partially written entry behind. An entry that was corrupted or
+/// written by another version of wasmer fails to load with an error,
+/// and entries can be signed with [`set_artifact_signer`].
+///
 /// [`Cache`]: trait.Cache.html
+/// [`WasmHash`]: struct.WasmHash.html
+/// [`set_artifact_signer`]: fn.set_artifact_signer.html
 ///
 /// # Usage:
 ///
@@ -87,27 +104,344 @@ impl Cache for FileSystemCache {
     type StoreError = CacheError;
 
     fn load(&self, key: WasmHash) -> Result<Module, CacheError> {
-        let filename = key.encode();
+        let backend = super::default_backend();
+        let filename = key.for_target(backend).encode();
         let mut new_path_buf = self.path.clone();
         new_path_buf.push(filename);
         let file = File::open(new_path_buf)?;
         let mmap = unsafe { Mmap::map(&file)? };
 
         let serialized_cache = Artifact::deserialize(&mmap[..])?;
+        if serialized_cache.info().backend != backend {
+            return Err(CacheError::InvalidatedCache);
+        }
+
         unsafe { wasmer_runtime_core::load_cache_with(serialized_cache, super::default_compiler()) }
     }
 
     fn store(&mut self, key: WasmHash, module: Module) -> Result<(), CacheError> {
-        let filename = key.encode();
+        let filename = key.for_target(super::default_backend()).encode();
         let mut new_path_buf = self.path.clone();
-        new_path_buf.push(filename);
+        new_path_buf.push(&filename);
 
         let serialized_cache = module.cache()?;
         let buffer = serialized_cache.serialize()?;
 
-        let mut file = File::create(new_path_buf)?;
-        file.write_all(&buffer)?;
+        // Write to a uniquely named file in the same directory and
+        // rename it over the final path, which is atomic on the
+        // platforms we support.
+        let mut tmp_path_buf = self.path.clone();
+        tmp_path_buf.push(tmp_file_name(&filename));
+
+        let result = File::create(&tmp_path_buf)
+            .and_then(|mut file| {
+                file.write_all(&buffer)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path_buf, &new_path_buf));
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp_path_buf);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A name for the temporary file that an artifact named `filename` is
+/// written to, which no other `store` uses at the same time, whether
+/// in this process or another one.
+fn tmp_file_name(filename: &str) -> String {
+    static STORES: AtomicUsize = AtomicUsize::new(0);
+    let store = STORES.fetch_add(1, Ordering::Relaxed);
+    format!("{}.{}.{}.tmp", filename, std::process::id(), store)
+}
+
+/// Hit and miss counters of a [`MemoryCache`].
+///
+/// [`MemoryCache`]: struct.MemoryCache.html
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl MemoryCacheStats {
+    /// The fraction of loads that were served from the cache,
+    /// or `0.0` if nothing has been loaded yet.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// A bounded, in-memory cache of compiled modules.
+///
+/// The cache holds modules up to a total size, measured as the size of
+/// their serialized artifacts. When storing a module would go over it,
+/// the modules that were least recently loaded or stored are evicted
+/// first. This is useful for hosts that repeatedly instantiate the same
+/// working set of modules and don't want to pay for deserialization on
+/// every request.
+///
+/// # Usage:
+///
+/// ```rust
+/// use wasmer_runtime::cache::{Cache, MemoryCache, WasmHash};
+///
+/// # use wasmer_runtime::{Module, error::CacheError};
+/// fn cached_module(cache: &mut MemoryCache, wasm: &[u8], module: Module) -> Result<Module, CacheError> {
+///     let key = WasmHash::generate(wasm);
+///     if let Ok(module) = cache.load(key) {
+///         return Ok(module);
+///     }
+///     cache.store(key, module.clone())?;
+///     Ok(module)
+/// }
+/// ```
+pub struct MemoryCache {
+    entries: RefCell<Lru<Module>>,
+    stats: Cell<MemoryCacheStats>,
+}
+
+impl MemoryCache {
+    /// Construct a new `MemoryCache` that holds modules whose
+    /// serialized artifacts add up to at most `max_bytes`.
+    ///
+    /// # Panics:
+    /// Panics if `max_bytes` is zero.
+    pub fn new(max_bytes: usize) -> Self {
+        assert!(max_bytes > 0, "a memory cache must have room for a module");
+        Self {
+            entries: RefCell::new(Lru::new(max_bytes)),
+            stats: Cell::new(MemoryCacheStats::default()),
+        }
+    }
+
+    /// The most bytes that the modules in this cache add up to.
+    pub fn max_bytes(&self) -> usize {
+        self.entries.borrow().max_bytes
+    }
+
+    /// The bytes that the modules currently in the cache add up to.
+    pub fn size(&self) -> usize {
+        self.entries.borrow().bytes
+    }
+
+    /// The number of modules currently in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hit, miss, and eviction counts since this cache was created.
+    pub fn stats(&self) -> MemoryCacheStats {
+        self.stats.get()
+    }
+
+    /// Remove every module from the cache. The stats are kept.
+    pub fn clear(&mut self) {
+        self.entries.get_mut().clear();
+    }
+}
+
+impl Cache for MemoryCache {
+    type LoadError = CacheError;
+    type StoreError = CacheError;
+
+    fn load(&self, key: WasmHash) -> Result<Module, CacheError> {
+        let mut stats = self.stats.get();
+
+        let result = match self.entries.borrow_mut().get(key) {
+            Some(module) => {
+                stats.hits += 1;
+                Ok(module)
+            }
+            None => {
+                stats.misses += 1;
+                Err(CacheError::IoError(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "module is not in the memory cache",
+                )))
+            }
+        };
+
+        self.stats.set(stats);
+        result
+    }
+
+    fn store(&mut self, key: WasmHash, module: Module) -> Result<(), CacheError> {
+        let size = module.cache()?.serialize()?.len();
+        let max_bytes = self.max_bytes();
+        let evicted = self
+            .entries
+            .get_mut()
+            .insert(key, module, size)
+            .ok_or_else(|| {
+                CacheError::Unknown(format!(
+                    "a module of {} bytes doesn't fit in a memory cache of {} bytes",
+                    size, max_bytes
+                ))
+            })?;
+
+        let mut stats = self.stats.get();
+        stats.evictions += evicted;
+        self.stats.set(stats);
 
         Ok(())
     }
 }
+
+/// Values that take up some number of bytes, kept up to a total and
+/// evicted least recently used first.
+struct Lru<T> {
+    max_bytes: usize,
+    bytes: usize,
+    clock: u64,
+    entries: HashMap<WasmHash, LruEntry<T>>,
+}
+
+struct LruEntry<T> {
+    value: T,
+    size: usize,
+    last_used: u64,
+}
+
+impl<T: Clone> Lru<T> {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: WasmHash) -> Option<T> {
+        let now = self.tick();
+        self.entries.get_mut(&key).map(|entry| {
+            entry.last_used = now;
+            entry.value.clone()
+        })
+    }
+
+    /// Insert `value`, replacing the value of `key` if there is one,
+    /// and evict others until everything fits. Returns how many values
+    /// were evicted, or `None` if `value` alone is too big, in which
+    /// case nothing changes.
+    fn insert(&mut self, key: WasmHash, value: T, size: usize) -> Option<u64> {
+        if size > self.max_bytes {
+            return None;
+        }
+        let now = self.tick();
+
+        if let Some(old) = self.entries.remove(&key) {
+            self.bytes -= old.size;
+        }
+
+        let mut evicted = 0;
+        while self.bytes + size > self.max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)?;
+            let entry = self.entries.remove(&oldest)?;
+            self.bytes -= entry.size;
+            evicted += 1;
+        }
+
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            LruEntry {
+                value,
+                size,
+                last_used: now,
+            },
+        );
+        Some(evicted)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::{tmp_file_name, Lru, WasmHash};
+
+    fn key(n: u8) -> WasmHash {
+        WasmHash::generate(&[n])
+    }
+
+    #[test]
+    fn test_tmp_file_names_are_unique() {
+        let first = tmp_file_name("artifact");
+        let second = tmp_file_name("artifact");
+        assert_ne!(first, second);
+        assert!(first.starts_with("artifact."));
+        assert!(second.ends_with(".tmp"));
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(10);
+        assert_eq!(lru.insert(key(1), 1, 4), Some(0));
+        assert_eq!(lru.insert(key(2), 2, 4), Some(0));
+        assert_eq!(lru.bytes, 8);
+
+        // Using 1 makes 2 the least recently used.
+        assert_eq!(lru.get(key(1)), Some(1));
+        assert_eq!(lru.insert(key(3), 3, 4), Some(1));
+        assert_eq!(lru.get(key(2)), None);
+        assert_eq!(lru.get(key(1)), Some(1));
+        assert_eq!(lru.get(key(3)), Some(3));
+        assert_eq!(lru.bytes, 8);
+    }
+
+    #[test]
+    fn test_lru_evicts_until_it_fits() {
+        let mut lru = Lru::new(10);
+        for n in 0..5 {
+            assert_eq!(lru.insert(key(n), n, 2), Some(0));
+        }
+        assert_eq!(lru.insert(key(5), 5, 7), Some(4));
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.bytes, 9);
+        assert_eq!(lru.get(key(4)), Some(4));
+    }
+
+    #[test]
+    fn test_lru_replaces_and_rejects() {
+        let mut lru = Lru::new(10);
+        assert_eq!(lru.insert(key(1), 1, 6), Some(0));
+        assert_eq!(lru.insert(key(1), 10, 8), Some(0));
+        assert_eq!(lru.bytes, 8);
+        assert_eq!(lru.get(key(1)), Some(10));
+
+        assert_eq!(lru.insert(key(2), 2, 11), None);
+        assert_eq!(lru.bytes, 8);
+        assert_eq!(lru.get(key(1)), Some(10));
+
+        lru.clear();
+        assert_eq!(lru.bytes, 0);
+        assert_eq!(lru.get(key(1)), None);
+    }
+}
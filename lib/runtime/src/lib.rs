@@ -76,31 +76,109 @@
 
 pub use wasmer_runtime_core::global::Global;
 pub use wasmer_runtime_core::import::ImportObject;
-pub use wasmer_runtime_core::instance::{DynFunc, Instance};
+pub use wasmer_runtime_core::instance::{DynFunc, Instance, InterruptHandle};
 pub use wasmer_runtime_core::memory::Memory;
 pub use wasmer_runtime_core::module::Module;
+pub use wasmer_runtime_core::snapshot::{InstanceTemplate, Snapshot};
 pub use wasmer_runtime_core::table::Table;
 pub use wasmer_runtime_core::types::Value;
 pub use wasmer_runtime_core::vm::Ctx;
 
 pub use wasmer_runtime_core::Func;
-pub use wasmer_runtime_core::{compile_with, validate};
+pub use wasmer_runtime_core::{
+    compile_with, compile_with_config, validate, validate_with_features, Features,
+};
 pub use wasmer_runtime_core::{func, imports};
 
 pub mod memory {
-    pub use wasmer_runtime_core::memory::{Atomic, Atomically, Memory, MemoryView};
+    pub use wasmer_runtime_core::memory::ptr::{Array, Item, WasmPtr, WasmSlice, WasmStr};
+    pub use wasmer_runtime_core::memory::{
+        memory_style, set_memory_style, Atomic, Atomically, Memory, MemoryStyle, MemoryView,
+    };
 }
 
 pub mod wasm {
     //! Various types exposed by the Wasmer Runtime.
     pub use wasmer_runtime_core::global::Global;
-    pub use wasmer_runtime_core::table::Table;
-    pub use wasmer_runtime_core::types::{FuncSig, MemoryDescriptor, TableDescriptor, Type, Value};
+    pub use wasmer_runtime_core::module::{ExportDescriptor, ExternDescriptor, ImportDescriptor};
+    pub use wasmer_runtime_core::refs::{ExternRef, FuncRef};
+    pub use wasmer_runtime_core::table::{Anyfunc, Element, Table};
+    pub use wasmer_runtime_core::types::{
+        ElementType, FuncSig, GlobalDescriptor, MemoryDescriptor, TableDescriptor, Type, Value,
+    };
+}
+
+pub mod async_call {
+    //! Calls into WebAssembly that wait on host futures without blocking the thread.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    pub use wasmer_runtime_core::async_call::CallFuture;
+    pub use wasmer_runtime_core::async_call::{wait, Wait};
+}
+
+pub mod backend {
+    //! The compilers that modules can be compiled with.
+    pub use wasmer_runtime_core::backend::{
+        Backend, Capabilities, Compiler, CompilerConfig, FuncCode,
+    };
+}
+
+pub mod debugger {
+    //! Debugging of WebAssembly code with breakpoints.
+    pub use wasmer_runtime_core::debugger::{
+        is_debugging, set_debugging, Breakpoint, DebugHandler, PauseHandle, Paused, Resume,
+    };
+}
+
+pub mod deterministic {
+    //! Deterministic execution of WebAssembly code.
+    pub use wasmer_runtime_core::deterministic::{is_deterministic, set_deterministic};
+}
+
+pub mod epoch {
+    //! Preemption of WebAssembly code by epochs.
+    pub use wasmer_runtime_core::epoch::{current, increment};
 }
 
 pub mod error {
     pub use wasmer_runtime_core::cache::Error as CacheError;
     pub use wasmer_runtime_core::error::*;
+    pub use wasmer_runtime_core::replace::Error as ReplaceError;
+    pub use wasmer_runtime_core::snapshot::Error as SnapshotError;
+}
+
+pub mod limits {
+    //! Limits on how large the memories and tables of an instance may get.
+    pub use wasmer_runtime_core::limits::ResourceLimiter;
+}
+
+pub mod link {
+    //! Instances that import each other's exports.
+    pub use wasmer_runtime_core::link::Linker;
+}
+
+pub mod metering {
+    //! Metering of the WebAssembly code that instances run, with gas.
+    pub use wasmer_runtime_core::metering::{unit_cost, CostFunction, MeteringConfig, Operator};
+}
+
+pub mod middleware {
+    //! Middleware that transform the code of functions before it's compiled.
+    pub use wasmer_runtime_core::middleware::{
+        read_metadata, set_middleware_chain, Event, EventSink, FunctionMiddleware, Instruction,
+        MiddlewareFactory, Operator, API_VERSION,
+    };
+}
+
+pub mod pool {
+    //! Pooling of the memories, tables and instances that instantiation allocates.
+    pub use wasmer_runtime_core::pool::{configure, stats, PoolConfig, PoolStats};
+}
+
+pub mod termination {
+    //! A hook that hears about every guest that ends early.
+    pub use wasmer_runtime_core::termination::{
+        set_termination_hook, Reason, Termination, TerminationHook,
+    };
 }
 
 pub mod units {
@@ -110,7 +188,7 @@ pub mod units {
 
 pub mod cache;
 
-use wasmer_runtime_core::backend::Compiler;
+use wasmer_runtime_core::backend::{Backend, Compiler};
 
 /// Compile WebAssembly binary code into a [`Module`].
 /// This function is useful if it is necessary to
@@ -152,24 +230,114 @@ pub fn instantiate(wasm: &[u8], import_object: &ImportObject) -> error::Result<I
     module.instantiate(import_object)
 }
 
+/// Compile WebAssembly binary code into a [`Module`] with the
+/// backend `backend`, which must be one that this crate was built
+/// with.
+///
+/// [`Module`]: struct.Module.html
+///
+/// # Errors:
+/// If this crate wasn't built with `backend`, the function returns
+/// `Err(error::CompileError::UnavailableBackend { .. })`.
+pub fn compile_with_backend(wasm: &[u8], backend: Backend) -> error::CompileResult<Module> {
+    let compiler = compiler_for(backend)
+        .ok_or(error::CompileError::UnavailableBackend { backend })?;
+    wasmer_runtime_core::compile_with(&wasm[..], compiler)
+}
+
 /// Get a single instance of the default compiler to use.
 pub fn default_compiler() -> &'static dyn Compiler {
+    compiler_for(default_backend()).expect("the default backend is always built")
+}
+
+/// The compiler of `backend`, if this crate was built with it.
+pub fn compiler_for(backend: Backend) -> Option<&'static dyn Compiler> {
+    register_backends();
+    wasmer_runtime_core::backend::compiler_for(backend)
+        .map(|compiler| compiler as &'static dyn Compiler)
+}
+
+/// The backends that this crate was built with.
+pub fn available_backends() -> Vec<Backend> {
+    register_backends();
+    wasmer_runtime_core::backend::registered_backends()
+}
+
+/// Register a single instance of the compiler of each backend that
+/// this crate was built with.
+fn register_backends() {
     use lazy_static::lazy_static;
+    use std::sync::Once;
+
+    #[cfg(feature = "default-compiler")]
+    lazy_static! {
+        static ref CRANELIFT: wasmer_clif_backend::CraneliftCompiler =
+            { wasmer_clif_backend::CraneliftCompiler::new() };
+    }
 
     #[cfg(feature = "llvm")]
-    use wasmer_llvm_backend::LLVMCompiler as DefaultCompiler;
+    lazy_static! {
+        static ref LLVM: wasmer_llvm_backend::LLVMCompiler =
+            { wasmer_llvm_backend::LLVMCompiler::new() };
+    }
 
     #[cfg(feature = "dynasm")]
-    use wasmer_dynasm_backend::SinglePassCompiler as DefaultCompiler;
-
-    #[cfg(not(any(feature = "llvm", feature = "dynasm")))]
-    use wasmer_clif_backend::CraneliftCompiler as DefaultCompiler;
+    lazy_static! {
+        static ref DYNASM: wasmer_dynasm_backend::SinglePassCompiler =
+            { wasmer_dynasm_backend::SinglePassCompiler::new() };
+    }
 
+    #[cfg(feature = "interpreter")]
     lazy_static! {
-        static ref DEFAULT_COMPILER: DefaultCompiler = { DefaultCompiler::new() };
+        static ref INTERPRETER: wasmer_interpreter_backend::InterpreterCompiler =
+            { wasmer_interpreter_backend::InterpreterCompiler::new() };
     }
 
-    &*DEFAULT_COMPILER as &dyn Compiler
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        use wasmer_runtime_core::backend::register_backend;
+
+        #[cfg(feature = "default-compiler")]
+        register_backend(Backend::Cranelift, &*CRANELIFT);
+        #[cfg(feature = "llvm")]
+        register_backend(Backend::LLVM, &*LLVM);
+        #[cfg(feature = "dynasm")]
+        register_backend(Backend::Dynasm, &*DYNASM);
+        #[cfg(feature = "interpreter")]
+        register_backend(Backend::Interpreter, &*INTERPRETER);
+    });
+}
+
+/// Get the kind of backend that `default_compiler` returns.
+pub fn default_backend() -> Backend {
+    // Exactly one of these blocks is compiled in.
+    #[cfg(feature = "llvm")]
+    {
+        Backend::LLVM
+    }
+
+    #[cfg(all(feature = "dynasm", not(feature = "llvm")))]
+    {
+        Backend::Dynasm
+    }
+
+    // The interpreter is only the default when it's the one backend.
+    #[cfg(all(
+        feature = "interpreter",
+        not(any(feature = "default-compiler", feature = "llvm", feature = "dynasm"))
+    ))]
+    {
+        Backend::Interpreter
+    }
+
+    #[cfg(not(any(
+        feature = "llvm",
+        feature = "dynasm",
+        all(feature = "interpreter", not(feature = "default-compiler"))
+    )))]
+    {
+        Backend::Cranelift
+    }
 }
 
 /// The current version of this crate
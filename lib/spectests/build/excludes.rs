@@ -0,0 +1,94 @@
+//! The spec tests that a backend doesn't pass, which are read from
+//! `excludes/{BACKEND}.txt`. Each line excludes the command on a line
+//! of a `.wast` file, or all of the commands of a file:
+//!
+//! ```text
+//! # Skipped: the test isn't generated.
+//! skip float_exprs:1923
+//! # Expected to fail: the test has to fail, so it's noticed once it
+//! # passes, only on the platforms listed.
+//! fail traps:20 windows,macos
+//! skip elem:*
+//! ```
+//!
+//! The file is the name of the `.wast` file without its extension.
+//! Excluding the line of a module excludes the commands that use it,
+//! which can only be skipped.
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Exclusion {
+    Skip,
+    Fail,
+}
+
+/// The lines that are excluded in each file, with `None` for all of
+/// them.
+pub struct Excludes {
+    files: HashMap<String, HashMap<Option<u64>, Exclusion>>,
+}
+
+impl Excludes {
+    /// The excludes of `backend` on `platform`, a target OS as Rust
+    /// names it. There are none if the backend has no excludes file.
+    pub fn load(directory: &Path, backend: &str, platform: &str) -> Self {
+        let path = directory.join(format!("{}.txt", backend));
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let mut files: HashMap<String, HashMap<Option<u64>, Exclusion>> = HashMap::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| -> ! {
+                panic!("{}:{}: {}: {}", path.display(), number + 1, reason, line)
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let exclusion = match fields[0] {
+                "skip" => Exclusion::Skip,
+                "fail" => Exclusion::Fail,
+                _ => invalid("expected `skip` or `fail`"),
+            };
+            let (file, test_line) = match fields.get(1).and_then(|test| {
+                let mut parts = test.splitn(2, ':');
+                Some((parts.next()?, parts.next()?))
+            }) {
+                Some(test) => test,
+                None => invalid("expected a test as `file:line`"),
+            };
+            let test_line = match test_line {
+                "*" => None,
+                test_line => match test_line.parse() {
+                    Ok(test_line) => Some(test_line),
+                    Err(_) => invalid("the line of the test isn't a number"),
+                },
+            };
+            let applies = match fields.get(2) {
+                Some(platforms) => platforms.split(',').any(|p| p == platform),
+                None => true,
+            };
+            if fields.len() > 3 {
+                invalid("unexpected text after the platforms");
+            }
+
+            if applies {
+                files
+                    .entry(file.to_string())
+                    .or_default()
+                    .insert(test_line, exclusion);
+            }
+        }
+
+        Excludes { files }
+    }
+
+    /// How the command on `line` of `file` is excluded, if it is.
+    pub fn get(&self, file: &str, line: u64) -> Option<Exclusion> {
+        let lines = self.files.get(file)?;
+        lines.get(&Some(line)).or_else(|| lines.get(&None)).cloned()
+    }
+}
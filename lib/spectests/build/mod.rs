@@ -1,3 +1,4 @@
+mod excludes;
 mod spectests;
 
 fn main() -> std::io::Result<()> {
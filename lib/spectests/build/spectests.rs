@@ -1,10 +1,15 @@
 //! This file will run at build time to autogenerate Rust tests based on
 //! WebAssembly spec tests. It will convert the files indicated in TESTS
 //! from "/spectests/{MODULE}.wast" to "/src/spectests/{MODULE}.rs".
+//!
+//! The tests that the backend being tested is excluded from are left out
+//! or expected to fail, and a conformance report of what's run is
+//! written with the tests, which the `conformance_report` test prints.
+use crate::excludes::{Excludes, Exclusion};
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::PathBuf;
-use std::{env, fs, io::Write};
+use std::{env, fmt, fs, io::Write};
 use wabt::script::{Action, Command, CommandKind, ModuleBinary, ScriptParser, Value};
 use wabt::wasm2wat;
 
@@ -113,13 +118,35 @@ fn get_compiler() -> impl Compiler {
     SinglePassCompiler::new()
 }
 
-#[cfg(not(any(feature = "llvm", feature = "clif", feature = "dynasm")))]
+#[cfg(feature = "interpreter")]
+fn get_compiler() -> impl Compiler {
+    use wasmer_interpreter_backend::InterpreterCompiler;
+    InterpreterCompiler::new()
+}
+
+#[cfg(not(any(
+    feature = "llvm",
+    feature = "clif",
+    feature = "dynasm",
+    feature = "interpreter"
+)))]
 fn get_compiler() -> impl Compiler {
   panic!("compiler not specified, activate a compiler via features");
   use wasmer_clif_backend::CraneliftCompiler;
   CraneliftCompiler::new()
 }
 
+/// Run a command of a module that's expected to fail, which it does if
+/// it panics.
+fn expect_failure<F: FnOnce()>(command: &str, f: F) {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    assert!(
+        result.is_err(),
+        "{} passed but is expected to fail, remove it from the excludes",
+        command
+    );
+}
+
 pub fn generate_imports() -> ImportObject {
     let wasm_binary = wat2wasm(IMPORT_MODULE.as_bytes()).expect("WAST not valid or malformed");
     let module = wasmer_runtime_core::compile_with(&wasm_binary[..], &get_compiler())
@@ -278,36 +305,104 @@ fn wabt2rust_value(v: &Value) -> String {
     }
 }
 
+/// What was done with the commands of a `.wast` file.
+#[derive(Default)]
+struct FileReport {
+    commands: u32,
+    run: u32,
+    expected_failures: u32,
+    skipped: u32,
+    /// Commands that the generator doesn't make tests for.
+    unsupported: u32,
+}
+
+impl FileReport {
+    fn add(&mut self, other: &FileReport) {
+        self.commands += other.commands;
+        self.run += other.run;
+        self.expected_failures += other.expected_failures;
+        self.skipped += other.skipped;
+        self.unsupported += other.unsupported;
+    }
+}
+
+/// The conformance of a backend on a platform to the spec tests.
+struct Report {
+    backend: String,
+    platform: String,
+    files: Vec<(String, FileReport)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter, name: &str, report: &FileReport| {
+            writeln!(
+                f,
+                "| {} | {} | {} | {} | {} | {} |",
+                name,
+                report.commands,
+                report.run,
+                report.expected_failures,
+                report.skipped,
+                report.unsupported
+            )
+        };
+
+        writeln!(
+            f,
+            "Spec test conformance of {} on {}\n",
+            self.backend, self.platform
+        )?;
+        writeln!(
+            f,
+            "| Test | Commands | Run | Expected failures | Skipped | Unsupported |"
+        )?;
+        writeln!(
+            f,
+            "|------|---------:|----:|------------------:|--------:|------------:|"
+        )?;
+        let mut total = FileReport::default();
+        for (name, report) in &self.files {
+            row(f, name, report)?;
+            total.add(report);
+        }
+        row(f, "total", &total)
+    }
+}
+
 struct WastTestGenerator {
+    test_name: String,
     last_module: i32,
     last_line: u64,
     command_no: i32,
     script_parser: ScriptParser,
     module_calls: HashMap<i32, Vec<String>>,
+    /// How the command being visited is excluded.
+    exclusion: Option<Exclusion>,
+    report: FileReport,
     buffer: String,
 }
 
 impl WastTestGenerator {
-    fn new(path: &PathBuf) -> Self {
+    fn new(path: &PathBuf, test_name: &str) -> Self {
         let filename = path.file_name().unwrap().to_str().unwrap();
         let source = fs::read(&path).unwrap();
         let script: ScriptParser = ScriptParser::from_source_and_name(&source, filename).unwrap();
         let buffer = String::new();
         WastTestGenerator {
+            test_name: test_name.to_string(),
             last_module: 0,
             last_line: 0,
             command_no: 0,
             script_parser: script,
             buffer: buffer,
             module_calls: HashMap::new(),
+            exclusion: None,
+            report: FileReport::default(),
         }
     }
 
-    fn is_fat_test(&self) -> bool {
-        self.command_no > 200
-    }
-
-    fn consume(&mut self) {
+    fn consume(&mut self, excludes: &Excludes) {
         self.buffer.push_str(BANNER);
         //         self.buffer.push_str(&format!(
         //             "// Test based on spectests/{}
@@ -327,11 +422,35 @@ impl WastTestGenerator {
         // //}};\n\n",
         //             self.filename
         //         ));
+        // Whether the commands are of a module that's skipped.
+        let mut skipping_module = false;
         while let Some(Command { line, kind }) = &self.script_parser.next().unwrap() {
             self.last_line = line.clone();
-            self.buffer
-                .push_str(&format!("\n// Line {}\n", self.last_line));
-            self.visit_command(&kind);
+            self.report.commands += 1;
+            self.exclusion = excludes.get(&self.test_name, self.last_line);
+
+            if let CommandKind::Module { .. } = kind {
+                if self.exclusion == Some(Exclusion::Fail) {
+                    panic!(
+                        "the module on line {} of {} can only be skipped",
+                        self.last_line, self.test_name
+                    );
+                }
+                skipping_module = self.exclusion == Some(Exclusion::Skip);
+            }
+            if skipping_module || self.exclusion == Some(Exclusion::Skip) {
+                self.report.skipped += 1;
+            } else if !is_supported(kind) {
+                self.report.unsupported += 1;
+            } else {
+                self.report.run += 1;
+                if self.exclusion == Some(Exclusion::Fail) {
+                    self.report.expected_failures += 1;
+                }
+                self.buffer
+                    .push_str(&format!("\n// Line {}\n", self.last_line));
+                self.visit_command(&kind);
+            }
             self.command_no = self.command_no + 1;
         }
         for n in 1..self.last_module + 1 {
@@ -343,14 +462,35 @@ impl WastTestGenerator {
         format!("c{}_l{}", self.command_no, self.last_line)
     }
 
+    /// The attributes of a test for the command being visited.
+    fn test_attributes(&self) -> &'static str {
+        match self.exclusion {
+            Some(Exclusion::Fail) => "#[test]\n#[should_panic]",
+            _ => "#[test]",
+        }
+    }
+
+    /// Add a call of `func_name` to the test of the last module.
+    fn push_module_call(&mut self, func_name: String) {
+        let call = match self.exclusion {
+            Some(Exclusion::Fail) => format!(
+                "expect_failure(\"{}\", || {{ {}(&mut instance); }});",
+                func_name, func_name
+            ),
+            _ => format!("{}(&mut instance);", func_name),
+        };
+        self.module_calls
+            .entry(self.last_module)
+            .or_insert(Vec::new())
+            .push(call);
+    }
+
     fn flush_module_calls(&mut self, module: i32) {
         let calls: Vec<String> = self
             .module_calls
             .entry(module)
             .or_insert(Vec::new())
-            .iter()
-            .map(|call_str| format!("{}(&mut instance);", call_str))
-            .collect();
+            .clone();
         if calls.len() > 0 {
             self.buffer.push_str(
                 format!(
@@ -403,12 +543,13 @@ fn test_module_{}() {{
         let command_name = self.command_name();
         self.buffer.push_str(
             format!(
-                "#[test]
+                "{}
 fn {}_assert_invalid() {{
     let wasm_binary = {:?};
     let module = wasmer_runtime_core::compile_with(&wasm_binary, &get_compiler());
     assert!(module.is_err(), \"WASM should not compile as is invalid\");
 }}\n",
+                self.test_attributes(),
                 command_name,
                 wasm_binary,
                 // We do this to ident four spaces back
@@ -461,10 +602,7 @@ fn {}_assert_invalid() {{
                 //                field=field,
                 //                args_types=args_types.join(", "),
                 //                func_return=func_return,
-                self.module_calls
-                    .entry(self.last_module)
-                    .or_insert(Vec::new())
-                    .push(func_name);
+                self.push_module_call(func_name);
                 // let mut module_calls = self.module_calls.get(&self.last_module).unwrap();
                 // module_calls.push(func_name);
             }
@@ -517,10 +655,7 @@ fn {}_assert_invalid() {{
                     )
                     .as_str(),
                 );
-                self.module_calls
-                    .entry(self.last_module)
-                    .or_insert(Vec::new())
-                    .push(func_name);
+                self.push_module_call(func_name);
                 // let mut module_calls = self.module_calls.get(&self.last_module).unwrap();
                 // module_calls.push(func_name);
             }
@@ -534,12 +669,13 @@ fn {}_assert_invalid() {{
         // let wast_string = wasm2wat(wasm_binary).expect("Can't convert back to wasm");
         self.buffer.push_str(
             format!(
-                "#[test]
+                "{}
 fn {}_assert_malformed() {{
     let wasm_binary = {:?};
     let compilation = wasmer_runtime_core::compile_with(&wasm_binary, &get_compiler());
     assert!(compilation.is_err(), \"WASM should not compile as is malformed\");
 }}\n",
+                self.test_attributes(),
                 command_name,
                 wasm_binary,
                 // We do this to ident four spaces back
@@ -648,10 +784,7 @@ fn {}_assert_malformed() {{
         if action_fn_name.is_none() {
             return;
         }
-        self.module_calls
-            .entry(self.last_module)
-            .or_insert(Vec::new())
-            .push(action_fn_name.unwrap());
+        self.push_module_call(action_fn_name.unwrap());
     }
 
     fn visit_perform_action(&mut self, action: &Action) {
@@ -660,10 +793,7 @@ fn {}_assert_malformed() {{
         if action_fn_name.is_none() {
             return;
         }
-        self.module_calls
-            .entry(self.last_module)
-            .or_insert(Vec::new())
-            .push(action_fn_name.unwrap());
+        self.push_module_call(action_fn_name.unwrap());
     }
 
     fn visit_assert_trap(&mut self, action: &Action) {
@@ -676,12 +806,13 @@ fn {}_assert_malformed() {{
         self.buffer.push_str(
             format!(
                 "
-#[test]
+{}
 fn {}() {{
     let mut instance = create_module_{}();
     let result = {}(&mut instance);
     assert!(result.is_err());
 }}\n",
+                self.test_attributes(),
                 trap_func_name,
                 self.last_module,
                 action_fn_name.unwrap(),
@@ -751,34 +882,88 @@ fn {}() {{
     }
 }
 
-fn generate_spectest(out: &mut File, test_name: &str, wast: &PathBuf) -> std::io::Result<()> {
-    let mut generator = WastTestGenerator::new(wast);
-    generator.consume();
+/// Whether tests are generated for the command.
+fn is_supported(kind: &CommandKind) -> bool {
+    let is_invoke = |action: &Action| match action {
+        Action::Invoke { .. } => true,
+        _ => false,
+    };
+    match kind {
+        CommandKind::Module { .. }
+        | CommandKind::AssertInvalid { .. }
+        | CommandKind::AssertMalformed { .. } => true,
+        CommandKind::AssertReturn { action, .. }
+        | CommandKind::AssertReturnCanonicalNan { action }
+        | CommandKind::AssertReturnArithmeticNan { action }
+        | CommandKind::AssertTrap { action, .. }
+        | CommandKind::PerformAction(action) => is_invoke(action),
+        _ => false,
+    }
+}
+
+fn generate_spectest(
+    out: &mut File,
+    test_name: &str,
+    wast: &PathBuf,
+    excludes: &Excludes,
+) -> std::io::Result<FileReport> {
+    let mut generator = WastTestGenerator::new(wast, test_name);
+    generator.consume(excludes);
     let generated_script = generator.finalize();
 
-    if !generator.is_fat_test() {
-        out.write(format!("mod test_{} {{\nuse super::*;\n", test_name).as_bytes())?;
-        out.write(generated_script.as_bytes())?;
-        out.write("\n}\n".as_bytes())?;
-    }
+    out.write(format!("mod test_{} {{\nuse super::*;\n", test_name).as_bytes())?;
+    out.write(generated_script.as_bytes())?;
+    out.write("\n}\n".as_bytes())?;
 
-    Ok(())
+    Ok(generator.report)
+}
+
+/// The backend that the tests are built for, which is chosen with a
+/// feature.
+fn backend() -> &'static str {
+    ["clif", "llvm", "dynasm", "interpreter"]
+        .iter()
+        .find(|backend| env::var_os(format!("CARGO_FEATURE_{}", backend.to_uppercase())).is_some())
+        .cloned()
+        .unwrap_or("none")
 }
 
 pub fn build() -> std::io::Result<()> {
-    let mut out_file = File::create(format!("{}/spectests.rs", env::var("OUT_DIR").unwrap()))?;
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut out_file = File::create(format!("{}/spectests.rs", out_dir))?;
 
     out_file.write(COMMON.as_bytes())?;
 
+    let backend = backend();
+    let platform = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let excludes = Excludes::load(
+        &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("excludes"),
+        backend,
+        &platform,
+    );
+    let mut report = Report {
+        backend: backend.to_string(),
+        platform,
+        files: Vec::new(),
+    };
+
     for test in TESTS.iter() {
         let mut wast_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         wast_path.push(test);
-        generate_spectest(
-            &mut out_file,
-            test.split("/").last().unwrap().split(".").next().unwrap(),
-            &wast_path,
-        )?
+        let test_name = test.split("/").last().unwrap().split(".").next().unwrap();
+        let file_report = generate_spectest(&mut out_file, test_name, &wast_path, &excludes)?;
+        report.files.push((test_name.to_string(), file_report));
     }
 
+    fs::write(format!("{}/conformance.md", out_dir), report.to_string())?;
+    out_file.write(
+        b"
+#[test]
+fn conformance_report() {
+    println!(\"{}\", include_str!(concat!(env!(\"OUT_DIR\"), \"/conformance.md\")));
+}
+",
+    )?;
+
     Ok(())
 }
@@ -0,0 +1,19 @@
+(module
+  (func (export "i32_div") (param i32 i32) (result i32)
+    (i32.div_s (get_local 0) (get_local 1)))
+  (func (export "i32_rem") (param i32 i32) (result i32)
+    (i32.rem_u (get_local 0) (get_local 1)))
+  (func (export "i64_div") (param i64 i64) (result i64)
+    (i64.div_s (get_local 0) (get_local 1)))
+  (func (export "i64_shifts") (param i64 i64) (result i64)
+    (i64.xor (i64.rotl (get_local 0) (get_local 1)) (i64.shr_s (get_local 0) (get_local 1))))
+  (func (export "i32_bits") (param i32) (result i32)
+    (i32.add (i32.clz (get_local 0)) (i32.mul (i32.ctz (get_local 0)) (i32.popcnt (get_local 0)))))
+  (func (export "f32_trunc") (param f32) (result i32)
+    (i32.trunc_s/f32 (get_local 0)))
+  (func (export "f64_trunc") (param f64) (result i64)
+    (i64.trunc_u/f64 (get_local 0)))
+  (func (export "f64_minmax") (param f64 f64) (result f64)
+    (f64.sub (f64.min (get_local 0) (get_local 1)) (f64.max (get_local 0) (get_local 1))))
+  (func (export "f32_nearest") (param f32) (result f32)
+    (f32.nearest (get_local 0))))
@@ -0,0 +1,22 @@
+(module
+  (memory (export "memory") 1 2)
+  (global $sum (export "sum") (mut i64) (i64.const 0))
+  (data (i32.const 16) "\01\02\03\04\05\06\07\08")
+  (func (export "store") (param i32 i64)
+    (i64.store offset=4 (get_local 0) (get_local 1)))
+  (func (export "load8") (param i32) (result i32)
+    (i32.load8_s (get_local 0)))
+  (func (export "load16") (param i32) (result i64)
+    (i64.load16_u offset=65534 (get_local 0)))
+  (func (export "accumulate") (param i32) (result i64)
+    (local i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (get_local 1) (i32.and (get_local 0) (i32.const 255))))
+        (set_global $sum
+          (i64.add (get_global $sum) (i64.load8_u (i32.and (get_local 1) (i32.const 31)))))
+        (set_local 1 (i32.add (get_local 1) (i32.const 1)))
+        (br $next)))
+    (get_global $sum))
+  (func (export "grow") (param i32) (result i32)
+    (grow_memory (i32.and (get_local 0) (i32.const 3)))))
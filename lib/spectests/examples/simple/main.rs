@@ -28,7 +28,18 @@ fn get_compiler() -> impl Compiler {
     SinglePassCompiler::new()
 }
 
-#[cfg(not(any(feature = "llvm", feature = "clif", feature = "dynasm")))]
+#[cfg(feature = "interpreter")]
+fn get_compiler() -> impl Compiler {
+    use wasmer_interpreter_backend::InterpreterCompiler;
+    InterpreterCompiler::new()
+}
+
+#[cfg(not(any(
+    feature = "llvm",
+    feature = "clif",
+    feature = "dynasm",
+    feature = "interpreter"
+)))]
 fn get_compiler() -> impl Compiler {
     panic!("compiler not specified, activate a compiler via features");
     use wasmer_clif_backend::CraneliftCompiler;
@@ -0,0 +1,581 @@
+//! Differential testing of the backends.
+//!
+//! Each module is run through every backend that's enabled, which call
+//! its exported functions with the same arguments in the same order.
+//! What each call returns or traps with, and the contents of the
+//! exported memories and globals after it, have to be the same for all
+//! of them, or the test fails with every divergence that it found.
+//!
+//! The modules are the `.wat` and `.wasm` files in `differential/`, and
+//! in the directory that `WASMER_DIFFERENTIAL_CORPUS` names if it's set,
+//! along with modules generated from `WASMER_DIFFERENTIAL_SEEDS` seeds,
+//! 32 by default. Only the functions whose parameters are all numbers
+//! are called, and only modules that import nothing can be run.
+//!
+//! Cranelift is always enabled, and LLVM and singlepass are with the
+//! `llvm` and `dynasm` features:
+//!
+//! ```text
+//! cargo test -p wasmer-spectests --features llvm,dynasm --test differential
+//! ```
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fmt, fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+use wabt::wat2wasm;
+use wasmer_runtime_core::{
+    backend::Compiler,
+    error::{CallError, RuntimeError, TrapCode},
+    export::Export,
+    import::ImportObject,
+    types::{Type, Value},
+    Instance,
+};
+
+/// How many sets of arguments each function is called with.
+const CALLS_PER_FUNCTION: usize = 8;
+
+fn backends() -> Vec<(&'static str, Box<dyn Compiler>)> {
+    let mut backends: Vec<(&'static str, Box<dyn Compiler>)> = vec![(
+        "cranelift",
+        Box::new(wasmer_clif_backend::CraneliftCompiler::new()),
+    )];
+    #[cfg(feature = "llvm")]
+    backends.push(("llvm", Box::new(wasmer_llvm_backend::LLVMCompiler::new())));
+    #[cfg(feature = "dynasm")]
+    backends.push((
+        "singlepass",
+        Box::new(wasmer_dynasm_backend::SinglePassCompiler::new()),
+    ));
+    #[cfg(feature = "interpreter")]
+    backends.push((
+        "interpreter",
+        Box::new(wasmer_interpreter_backend::InterpreterCompiler::new()),
+    ));
+    backends
+}
+
+/// A small xorshift generator, so a seed always gives the same module
+/// and arguments.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Zero is the one state that xorshift can't leave.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Arguments of type `ty`, mostly the values at the edges of its range,
+/// where backends are the likeliest to differ.
+fn argument(rng: &mut Rng, ty: Type) -> Option<Value> {
+    let random = rng.next();
+    let edge = rng.below(2) == 0;
+    Some(match ty {
+        Type::I32 if edge => Value::I32(*rng.pick(&[0, 1, -1, i32::min_value(), i32::max_value()])),
+        Type::I32 => Value::I32(random as i32),
+        Type::I64 if edge => Value::I64(*rng.pick(&[0, 1, -1, i64::min_value(), i64::max_value()])),
+        Type::I64 => Value::I64(random as i64),
+        Type::F32 if edge => Value::F32(*rng.pick(&[
+            0.0,
+            -0.0,
+            1.5,
+            std::f32::NAN,
+            std::f32::INFINITY,
+            std::f32::MIN,
+        ])),
+        Type::F32 => Value::F32(f32::from_bits(random as u32)),
+        Type::F64 if edge => Value::F64(*rng.pick(&[
+            0.0,
+            -0.0,
+            1.5,
+            std::f64::NAN,
+            std::f64::INFINITY,
+            std::f64::MIN,
+        ])),
+        Type::F64 => Value::F64(f64::from_bits(random)),
+        Type::ExternRef | Type::FuncRef => return None,
+    })
+}
+
+/// What a call did.
+#[derive(Debug)]
+enum Outcome {
+    Returned(Vec<Value>),
+    Trapped(TrapCode),
+    /// Any other error, which is compared by its message.
+    Failed(String),
+}
+
+impl Outcome {
+    fn new(result: Result<Vec<Value>, CallError>) -> Self {
+        match result {
+            Ok(values) => Outcome::Returned(values),
+            Err(CallError::Runtime(RuntimeError::Trap { code, .. })) => Outcome::Trapped(code),
+            Err(err) => Outcome::Failed(err.to_string()),
+        }
+    }
+
+    fn matches(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Returned(a), Outcome::Returned(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+            }
+            (Outcome::Trapped(a), Outcome::Trapped(b)) => same_trap(*a, *b),
+            (Outcome::Failed(a), Outcome::Failed(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Values are the same if they have the same bits, except that any NaN
+/// is the same as any other, since WebAssembly doesn't specify which
+/// NaN an operation gives.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::F32(a), Value::F32(b)) => (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits(),
+        (Value::F64(a), Value::F64(b)) => (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits(),
+        (a, b) => a == b,
+    }
+}
+
+/// Backends that can't tell an integer division by zero from one that
+/// overflows report `IllegalArithmetic` for both.
+fn same_trap(a: TrapCode, b: TrapCode) -> bool {
+    let arithmetic = |code| match code {
+        TrapCode::IntegerOverflow | TrapCode::IntegerDivisionByZero => true,
+        _ => false,
+    };
+    match (a, b) {
+        (TrapCode::IllegalArithmetic, code) | (code, TrapCode::IllegalArithmetic) => {
+            code == TrapCode::IllegalArithmetic || arithmetic(code)
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// The exported memories and globals of an instance.
+#[derive(Debug)]
+struct State {
+    /// The name, size in bytes and a hash of the contents of each memory.
+    memories: Vec<(String, usize, u64)>,
+    globals: Vec<(String, Value)>,
+}
+
+impl State {
+    fn capture(instance: &mut Instance) -> Self {
+        let mut memories = Vec::new();
+        let mut globals = Vec::new();
+        for (name, export) in instance.exports() {
+            match export {
+                Export::Memory(memory) => {
+                    let view = memory.view::<u8>();
+                    let mut hasher = DefaultHasher::new();
+                    for byte in view.iter() {
+                        hasher.write_u8(byte.get());
+                    }
+                    memories.push((name, view.len(), hasher.finish()));
+                }
+                Export::Global(global) => globals.push((name, global.get())),
+                _ => {}
+            }
+        }
+        memories.sort_by(|a, b| a.0.cmp(&b.0));
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        State { memories, globals }
+    }
+
+    fn matches(&self, other: &State) -> bool {
+        self.memories == other.memories
+            && self.globals.len() == other.globals.len()
+            && self
+                .globals
+                .iter()
+                .zip(&other.globals)
+                .all(|(a, b)| a.0 == b.0 && same_value(&a.1, &b.1))
+    }
+}
+
+/// A call and what it did, with the state that it left.
+struct Step {
+    call: String,
+    outcome: Outcome,
+    state: State,
+}
+
+/// Run `wasm` with one backend: compile and instantiate it, then make
+/// the calls that `seed` chooses. If the module can't be run, the error
+/// is the only step.
+fn run(wasm: &[u8], compiler: &dyn Compiler, seed: u64) -> Vec<Step> {
+    let failed = |call: &str, err: String| {
+        vec![Step {
+            call: call.to_string(),
+            outcome: Outcome::Failed(err),
+            state: State {
+                memories: Vec::new(),
+                globals: Vec::new(),
+            },
+        }]
+    };
+    let module = match wasmer_runtime_core::compile_with(wasm, compiler) {
+        Ok(module) => module,
+        Err(err) => return failed("compile", err.to_string()),
+    };
+    let mut instance = match module.instantiate(&ImportObject::new()) {
+        Ok(instance) => instance,
+        Err(err) => return failed("instantiate", err.to_string()),
+    };
+
+    let mut functions: Vec<(String, Vec<Type>)> = instance
+        .exports()
+        .filter_map(|(name, export)| match export {
+            Export::Function { signature, .. } => Some((name, signature.params().to_vec())),
+            _ => None,
+        })
+        .collect();
+    functions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut rng = Rng::new(seed);
+    let mut steps = Vec::new();
+    for (name, params) in functions {
+        for _ in 0..CALLS_PER_FUNCTION {
+            let args: Option<Vec<Value>> =
+                params.iter().map(|&ty| argument(&mut rng, ty)).collect();
+            let args = match args {
+                Some(args) => args,
+                None => break,
+            };
+            let outcome = Outcome::new(instance.call(&name, &args));
+            steps.push(Step {
+                call: format!("{}{:?}", name, args),
+                outcome,
+                state: State::capture(&mut instance),
+            });
+        }
+    }
+    steps
+}
+
+/// Where the results of two backends first diverged for a module.
+struct Divergence {
+    module: String,
+    backends: (&'static str, &'static str),
+    call: String,
+    outcomes: (String, String),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {}", self.module, self.call)?;
+        writeln!(f, "  {}: {}", self.backends.0, self.outcomes.0)?;
+        write!(f, "  {}: {}", self.backends.1, self.outcomes.1)
+    }
+}
+
+/// Run `wasm` with every backend and compare each one with the first.
+fn check(name: &str, wasm: &[u8], seed: u64) -> Vec<Divergence> {
+    let backends = backends();
+    let runs: Vec<(&'static str, Vec<Step>)> = backends
+        .iter()
+        .map(|(backend, compiler)| (*backend, run(wasm, &**compiler, seed)))
+        .collect();
+
+    let (reference, expected) = &runs[0];
+    let mut divergences = Vec::new();
+    for (backend, steps) in &runs[1..] {
+        let diverged = expected
+            .iter()
+            .zip(steps)
+            .find(|(a, b)| !a.outcome.matches(&b.outcome) || !a.state.matches(&b.state));
+        let divergence = match diverged {
+            Some((a, b)) => Divergence {
+                module: name.to_string(),
+                backends: (*reference, *backend),
+                call: a.call.clone(),
+                outcomes: (
+                    format!("{:?}, {:?}", a.outcome, a.state),
+                    format!("{:?}, {:?}", b.outcome, b.state),
+                ),
+            },
+            None if expected.len() != steps.len() => Divergence {
+                module: name.to_string(),
+                backends: (*reference, *backend),
+                call: "all".to_string(),
+                outcomes: (
+                    format!("{} calls", expected.len()),
+                    format!("{} calls", steps.len()),
+                ),
+            },
+            None => continue,
+        };
+        divergences.push(divergence);
+    }
+    divergences
+}
+
+fn report(divergences: Vec<Divergence>) {
+    if divergences.is_empty() {
+        return;
+    }
+    let report: Vec<String> = divergences.iter().map(|d| d.to_string()).collect();
+    panic!(
+        "the backends diverged {} times:\n{}",
+        divergences.len(),
+        report.join("\n")
+    );
+}
+
+fn corpus_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("wat") | Some("wasm") => true,
+                    _ => false,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_corpus() {
+    let mut directories = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("differential")];
+    if let Some(directory) = env::var_os("WASMER_DIFFERENTIAL_CORPUS") {
+        directories.push(PathBuf::from(directory));
+    }
+
+    let mut divergences = Vec::new();
+    for path in directories
+        .iter()
+        .flat_map(|directory| corpus_files(directory))
+    {
+        let contents = fs::read(&path).expect("can't read corpus file");
+        let wasm = if path.extension().map_or(false, |ext| ext == "wat") {
+            wat2wasm(&contents).expect("WAST not valid or malformed")
+        } else {
+            contents
+        };
+        divergences.extend(check(&path.display().to_string(), &wasm, 0));
+    }
+    report(divergences);
+}
+
+#[test]
+fn test_generated() {
+    let seeds = env::var("WASMER_DIFFERENTIAL_SEEDS")
+        .ok()
+        .and_then(|seeds| seeds.parse().ok())
+        .unwrap_or(32);
+
+    let mut divergences = Vec::new();
+    for seed in 0..seeds {
+        let wat = generate::module(seed);
+        let wasm = wat2wasm(&wat).expect("generated an invalid module");
+        divergences.extend(check(&format!("generated module {}", seed), &wasm, seed));
+    }
+    report(divergences);
+}
+
+/// Random modules, which compute expressions of their parameters, and
+/// store to memory and globals along the way.
+mod generate {
+    use super::Rng;
+
+    #[derive(Copy, Clone, PartialEq)]
+    enum Ty {
+        I32,
+        I64,
+        F64,
+    }
+
+    impl Ty {
+        fn name(self) -> &'static str {
+            match self {
+                Ty::I32 => "i32",
+                Ty::I64 => "i64",
+                Ty::F64 => "f64",
+            }
+        }
+    }
+
+    const TYPES: &[Ty] = &[Ty::I32, Ty::I64, Ty::F64];
+
+    /// How deep expressions nest.
+    const MAX_DEPTH: u32 = 4;
+
+    /// The text of the module that `seed` makes. Its functions take an
+    /// `i32`, an `i64` and an `f64`, in locals 0 to 2, and loop with
+    /// local 3.
+    pub fn module(seed: u64) -> String {
+        let rng = &mut Rng::new(seed);
+        let mut wat = String::from(
+            "(module\n\
+             (memory (export \"memory\") 1)\n\
+             (global $g32 (export \"g32\") (mut i32) (i32.const 0))\n\
+             (global $g64 (export \"g64\") (mut i64) (i64.const 0))\n",
+        );
+        for index in 0..1 + rng.below(4) {
+            let result = *rng.pick(TYPES);
+            wat.push_str(&format!(
+                "(func (export \"f{}\") (param i32 i64 f64) (result {}) (local i32)\n",
+                index,
+                result.name()
+            ));
+            for _ in 0..rng.below(4) {
+                wat.push_str(&statement(rng));
+                wat.push('\n');
+            }
+            wat.push_str(&expr(rng, result, 0));
+            wat.push_str(")\n");
+        }
+        wat.push(')');
+        wat
+    }
+
+    fn statement(rng: &mut Rng) -> String {
+        match rng.below(4) {
+            0 => format!("(i32.store {} {})", address(rng), expr(rng, Ty::I32, 1)),
+            1 => format!("(i64.store {} {})", address(rng), expr(rng, Ty::I64, 1)),
+            2 => format!("(set_global $g32 {})", expr(rng, Ty::I32, 1)),
+            _ => format!(
+                "(set_local 3 (i32.const 0))\n\
+                 (loop $l\n\
+                 (set_global $g64 (i64.add (get_global $g64) {}))\n\
+                 (set_local 3 (i32.add (get_local 3) (i32.const 1)))\n\
+                 (br_if $l (i32.lt_u (get_local 3) (i32.const {}))))",
+                expr(rng, Ty::I64, 2),
+                1 + rng.below(8)
+            ),
+        }
+    }
+
+    /// An address that's usually in bounds of the one page of memory,
+    /// and sometimes whatever the first parameter is.
+    fn address(rng: &mut Rng) -> String {
+        if rng.below(8) == 0 {
+            "(get_local 0)".to_string()
+        } else {
+            format!(
+                "(i32.and {} (i32.const 0xfff8))",
+                expr(rng, Ty::I32, MAX_DEPTH - 1)
+            )
+        }
+    }
+
+    fn expr(rng: &mut Rng, ty: Ty, depth: u32) -> String {
+        if depth >= MAX_DEPTH || rng.below(4) == 0 {
+            return leaf(rng, ty);
+        }
+        let depth = depth + 1;
+        match (ty, rng.below(6)) {
+            (_, 0) => format!(
+                "(select {} {} {})",
+                expr(rng, ty, depth),
+                expr(rng, ty, depth),
+                expr(rng, Ty::I32, depth)
+            ),
+            (_, 1) => format!(
+                "(if (result {}) {} (then {}) (else {}))",
+                ty.name(),
+                expr(rng, Ty::I32, depth),
+                expr(rng, ty, depth),
+                expr(rng, ty, depth)
+            ),
+            (Ty::I32, 2) => {
+                let operand = *rng.pick(TYPES);
+                let op = match operand {
+                    Ty::F64 => *rng.pick(&["eq", "ne", "lt", "gt", "le", "ge"]),
+                    _ => *rng.pick(&["eq", "ne", "lt_s", "lt_u", "gt_s", "ge_u"]),
+                };
+                format!(
+                    "({}.{} {} {})",
+                    operand.name(),
+                    op,
+                    expr(rng, operand, depth),
+                    expr(rng, operand, depth)
+                )
+            }
+            (Ty::I32, 3) => match rng.below(3) {
+                0 => format!("(i32.wrap/i64 {})", expr(rng, Ty::I64, depth)),
+                1 => format!("(i32.trunc_s/f64 {})", expr(rng, Ty::F64, depth)),
+                _ => format!("(i32.load {})", address(rng)),
+            },
+            (Ty::I64, 3) => match rng.below(3) {
+                0 => format!("(i64.extend_s/i32 {})", expr(rng, Ty::I32, depth)),
+                1 => format!("(i64.trunc_u/f64 {})", expr(rng, Ty::F64, depth)),
+                _ => format!("(i64.load {})", address(rng)),
+            },
+            (Ty::F64, 3) => {
+                let operand = *rng.pick(&[Ty::I32, Ty::I64]);
+                format!(
+                    "(f64.convert_{}/{} {})",
+                    rng.pick(&["s", "u"]),
+                    operand.name(),
+                    expr(rng, operand, depth)
+                )
+            }
+            (Ty::F64, 4) => format!(
+                "(f64.{} {})",
+                rng.pick(&["neg", "abs", "sqrt", "ceil", "floor", "trunc", "nearest"]),
+                expr(rng, ty, depth)
+            ),
+            (Ty::F64, _) => format!(
+                "(f64.{} {} {})",
+                rng.pick(&["add", "sub", "mul", "div", "min", "max", "copysign"]),
+                expr(rng, ty, depth),
+                expr(rng, ty, depth)
+            ),
+            (_, 4) => format!(
+                "({}.{} {})",
+                ty.name(),
+                rng.pick(&["clz", "ctz", "popcnt"]),
+                expr(rng, ty, depth)
+            ),
+            (_, _) => format!(
+                "({}.{} {} {})",
+                ty.name(),
+                rng.pick(&[
+                    "add", "sub", "mul", "div_s", "div_u", "rem_s", "rem_u", "and", "or", "xor",
+                    "shl", "shr_s", "shr_u", "rotl", "rotr",
+                ]),
+                expr(rng, ty, depth),
+                expr(rng, ty, depth)
+            ),
+        }
+    }
+
+    fn leaf(rng: &mut Rng, ty: Ty) -> String {
+        match (ty, rng.below(3)) {
+            (Ty::I32, 0) => "(get_local 0)".to_string(),
+            (Ty::I32, 1) => "(get_global $g32)".to_string(),
+            (Ty::I32, _) => format!("(i32.const {})", rng.next() as i32),
+            (Ty::I64, 0) => "(get_local 1)".to_string(),
+            (Ty::I64, 1) => "(get_global $g64)".to_string(),
+            (Ty::I64, _) => format!("(i64.const {})", rng.next() as i64),
+            (Ty::F64, 0) => "(get_local 2)".to_string(),
+            (Ty::F64, _) => format!("(f64.const {:?})", (rng.next() as i64 as f64) / 1024.0),
+        }
+    }
+}
@@ -4,7 +4,11 @@ mod tests {
     use wasmer_clif_backend::CraneliftCompiler;
     use wasmer_runtime_core::{
         error::{CallError, RuntimeError},
+        func,
         import::ImportObject,
+        imports,
+        types::Value,
+        vm::Ctx,
     };
 
     // The semantics of stack overflow are documented at:
@@ -31,7 +35,7 @@ mod tests {
 
         match result {
             Err(err) => match err {
-                CallError::Runtime(RuntimeError::Trap { msg }) => {
+                CallError::Runtime(RuntimeError::Trap { msg, .. }) => {
                     assert!(!msg.contains("segmentation violation"));
                     assert!(!msg.contains("bus error"));
                 }
@@ -40,4 +44,99 @@ mod tests {
             Ok(_) => panic!("should fail with error due to stack overflow"),
         }
     }
+
+    // The clif backend rejects functions that return more than one
+    // value when it compiles the module, rather than when they're called.
+    #[test]
+    fn test_multiple_returns_rejected_by_clif() {
+        // (module (func (result i32 i32) i32.const 1 i32.const 2)), which
+        // wabt can't assemble without multi-value enabled.
+        let wasm_binary = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f, 0x7f, // type section
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x01, 0x41, 0x02, 0x0b, // code section
+        ];
+        let result = wasmer_runtime_core::compile_with(&wasm_binary[..], &CraneliftCompiler::new());
+        assert!(result.is_err());
+    }
+
+    // Modules with native code call the functions they import directly,
+    // so they can't import the interpreter's functions, or tables that
+    // hold them. Modules of the interpreter can.
+    #[cfg(feature = "interpreter")]
+    #[test]
+    fn test_interpreted_imports_rejected() {
+        use wasmer_interpreter_backend::InterpreterCompiler;
+        use wasmer_runtime_core::error::ErrorCode;
+
+        let module_str = r#"(module
+      (func $answer (result i32) i32.const 42)
+      (table (export "table") 1 anyfunc)
+      (elem (i32.const 0) $answer)
+      (export "answer" (func $answer)))
+    "#;
+        let wasm_binary = wat2wasm(module_str.as_bytes()).expect("WAST not valid or malformed");
+        let module =
+            wasmer_runtime_core::compile_with(&wasm_binary[..], &InterpreterCompiler::new())
+                .expect("WASM can't be compiled");
+        let instance = module
+            .instantiate(&ImportObject::new())
+            .expect("WASM can't be instantiated");
+        let mut import_object = ImportObject::new();
+        import_object.register("interpreted", instance);
+
+        for module_str in &[
+            r#"(module (import "interpreted" "answer" (func (result i32))))"#,
+            r#"(module (import "interpreted" "table" (table 1 anyfunc)))"#,
+        ] {
+            let wasm_binary = wat2wasm(module_str.as_bytes()).expect("WAST not valid or malformed");
+            let module =
+                wasmer_runtime_core::compile_with(&wasm_binary[..], &CraneliftCompiler::new())
+                    .expect("WASM can't be compiled");
+            match module.instantiate(&import_object) {
+                Err(err) => assert_eq!(err.code(), ErrorCode::NonNativeImport),
+                Ok(_) => panic!("interpreted functions must not be imported by native code"),
+            }
+
+            let module =
+                wasmer_runtime_core::compile_with(&wasm_binary[..], &InterpreterCompiler::new())
+                    .expect("WASM can't be compiled");
+            assert!(module.instantiate(&import_object).is_ok());
+        }
+    }
+
+    // A re-exported import is called with the context of the closure
+    // it wraps, which isn't an instance's, while the call itself is
+    // accounted to the instance that re-exports it.
+    #[test]
+    fn test_reexported_closure_import() {
+        let module_str = r#"(module
+      (import "env" "add" (func $add (param i32) (result i32)))
+      (export "add" (func $add)))
+    "#;
+        let wasm_binary = wat2wasm(module_str.as_bytes()).expect("WAST not valid or malformed");
+        let module = wasmer_runtime_core::compile_with(&wasm_binary[..], &CraneliftCompiler::new())
+            .expect("WASM can't be compiled");
+        let offset = 10;
+        let import_object = imports! {
+            "env" => {
+                "add" => func!(move |_ctx: &mut Ctx, x: i32| x + offset),
+            },
+        };
+        let instance = module
+            .instantiate(&import_object)
+            .expect("WASM can't be instantiated");
+
+        assert_eq!(
+            instance.call("add", &[Value::I32(1)]),
+            Ok(vec![Value::I32(11)])
+        );
+        assert_eq!(
+            instance.dyn_func("add").unwrap().call(&[Value::I32(2)]),
+            Ok(vec![Value::I32(12)])
+        );
+        let add = instance.func::<i32, i32>("add").unwrap();
+        assert_eq!(add.call(3).unwrap(), 13);
+    }
 }
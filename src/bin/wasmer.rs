@@ -12,7 +12,10 @@ use structopt::StructOpt;
 use wasmer::webassembly::InstanceABI;
 use wasmer::*;
 use wasmer_emscripten;
+use wasmer_runtime::backend::Backend;
 use wasmer_runtime::cache::{Cache as BaseCache, FileSystemCache, WasmHash, WASMER_VERSION_HASH};
+use wasmer_runtime_core::memory::MemoryStyle;
+use wasmer_runtime_core::types::LocalOrImport;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "wasmer", about = "Wasm execution runtime.")]
@@ -26,6 +29,10 @@ enum CLIOptions {
     #[structopt(name = "cache")]
     Cache(Cache),
 
+    /// Compile a WebAssembly file and report the size and compile time of each function
+    #[structopt(name = "inspect")]
+    Inspect(Inspect),
+
     /// Update wasmer to the latest version
     #[structopt(name = "self-update")]
     SelfUpdate,
@@ -37,15 +44,67 @@ struct Run {
     #[structopt(long = "disable-cache")]
     disable_cache: bool,
 
+    /// Register the compiled code with gdb and lldb (LLVM backend only)
+    #[structopt(long = "debug-jit")]
+    debug_jit: bool,
+
+    /// Write the names of compiled functions to /tmp/perf-<pid>.map for `perf report`
+    #[structopt(long = "perf-map")]
+    perf_map: bool,
+
+    /// Write compiled functions to jit-<pid>.dump for `perf inject --jit` (Linux only)
+    #[structopt(long = "jitdump")]
+    jitdump: bool,
+
+    /// Bounds-check memory accesses instead of reserving a large guarded region for each memory
+    #[structopt(long = "dynamic-memory")]
+    dynamic_memory: bool,
+
+    /// Write a crash dump to wasmer-crash-<pid>.txt in this directory if the process faults outside of WebAssembly code
+    #[structopt(long = "crash-dump-dir", parse(from_os_str))]
+    crash_dump_dir: Option<PathBuf>,
+
+    /// The backend to compile with: cranelift, dynasm (or singlepass), llvm or interpreter
+    #[structopt(long = "backend")]
+    backend: Option<String>,
+
+    /// Set an environment variable for the program, as KEY=VALUE (emscripten only)
+    #[structopt(
+        long = "env",
+        raw(number_of_values = "1"),
+        parse(try_from_str = "parse_env_var")
+    )]
+    env: Vec<(String, String)>,
+
+    /// Let the program see the environment variables of wasmer, under those set with --env (emscripten only)
+    #[structopt(long = "inherit-env")]
+    inherit_env: bool,
+
     /// Input file
     #[structopt(parse(from_os_str))]
     path: PathBuf,
 
-    /// Application arguments
+    /// Arguments to pass to the program, after `--`: the argv of an
+    /// emscripten module, or the parameters of the main function of others
     #[structopt(name = "--", raw(multiple = "true"))]
     args: Vec<String>,
 }
 
+#[derive(Debug, StructOpt)]
+struct Inspect {
+    /// The backend to compile with: cranelift, dynasm (or singlepass), llvm or interpreter
+    #[structopt(long = "backend")]
+    backend: Option<String>,
+
+    /// Print the disassembly of each function, if the backend can disassemble
+    #[structopt(long = "disassemble")]
+    disassemble: bool,
+
+    /// Input file
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+}
+
 #[derive(Debug, StructOpt)]
 enum Cache {
     /// Clear the cache
@@ -57,6 +116,24 @@ enum Cache {
     Dir,
 }
 
+/// Splits a KEY=VALUE environment variable given with --env.
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    if var.contains('\0') {
+        return Err(format!(
+            "Environment variable {:?} contains a nul byte",
+            var
+        ));
+    }
+    let mut parts = var.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!(
+            "Environment variable {:?} is not of the form KEY=VALUE",
+            var
+        )),
+    }
+}
+
 /// Read the contents of a file
 fn read_file_contents(path: &PathBuf) -> Result<Vec<u8>, io::Error> {
     let mut buffer: Vec<u8> = Vec::new();
@@ -85,8 +162,45 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
     // force disable caching on windows
     #[cfg(target_os = "windows")]
     let disable_cache = true;
+    // The cache only holds modules compiled with the default backend.
     #[cfg(not(target_os = "windows"))]
-    let disable_cache = options.disable_cache;
+    let disable_cache = options.disable_cache
+        || options.debug_jit
+        || options.dynamic_memory
+        || options.backend.is_some();
+
+    let backend = match &options.backend {
+        Some(name) => match Backend::from_name(name) {
+            Some(backend) => backend,
+            None => return Err(format!("Unknown backend: {}", name)),
+        },
+        None => wasmer_runtime::default_backend(),
+    };
+
+    if options.debug_jit {
+        let capabilities = wasmer_runtime::compiler_for(backend)
+            .map(|compiler| compiler.capabilities())
+            .unwrap_or_default();
+        if !capabilities.jit_debug {
+            return Err(format!(
+                "The {} backend can't register its code with debuggers",
+                backend.name()
+            ));
+        }
+        wasmer_runtime_core::jit_debug::set_enabled(true);
+    }
+    if options.perf_map {
+        wasmer_runtime_core::perf::set_map_enabled(true);
+    }
+    if options.jitdump {
+        wasmer_runtime_core::perf::set_jitdump_enabled(true);
+    }
+    if options.dynamic_memory {
+        wasmer_runtime_core::memory::set_memory_style(MemoryStyle::Dynamic);
+    }
+    if let Some(dir) = &options.crash_dump_dir {
+        wasmer_runtime_core::crash::set_dump_dir(Some(dir.clone()));
+    }
 
     let wasm_path = &options.path;
 
@@ -139,11 +253,16 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
         };
         module
     } else {
-        webassembly::compile(&wasm_binary[..])
+        wasmer_runtime::compile_with_backend(&wasm_binary[..], backend)
             .map_err(|e| format!("Can't compile module: {:?}", e))?
     };
 
-    let (_abi, import_object, _em_globals) = if wasmer_emscripten::is_emscripten_module(&module) {
+    let is_emscripten = wasmer_emscripten::is_emscripten_module(&module);
+    if !is_emscripten && (options.inherit_env || !options.env.is_empty()) {
+        return Err("--env and --inherit-env only work with emscripten modules".to_string());
+    }
+
+    let (_abi, import_object, _em_globals) = if is_emscripten {
         let mut emscripten_globals = wasmer_emscripten::EmscriptenGlobals::new(&module);
         (
             InstanceABI::Emscripten,
@@ -162,17 +281,101 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
         .instantiate(&import_object)
         .map_err(|e| format!("Can't instantiate module: {:?}", e))?;
 
+    let mut env: Vec<(String, String)> = if options.inherit_env {
+        env::vars().collect()
+    } else {
+        vec![]
+    };
+    for (key, value) in &options.env {
+        env.retain(|(k, _)| k != key);
+        env.push((key.clone(), value.clone()));
+    }
+
     webassembly::run_instance(
         &module,
         &mut instance,
         options.path.to_str().unwrap(),
         options.args.iter().map(|arg| arg.as_str()).collect(),
-    )
-    .map_err(|e| format!("{:?}", e))?;
+        env,
+    )?;
 
     Ok(())
 }
 
+/// Print the machine code size and compile time of each function of a
+/// wasm/wat file
+fn inspect_wasm(options: &Inspect) -> Result<(), String> {
+    let backend = match &options.backend {
+        Some(name) => match Backend::from_name(name) {
+            Some(backend) => backend,
+            None => return Err(format!("Unknown backend: {}", name)),
+        },
+        None => wasmer_runtime::default_backend(),
+    };
+
+    let mut wasm_binary: Vec<u8> = read_file_contents(&options.path).map_err(|err| {
+        format!(
+            "Can't read the file {}: {}",
+            options.path.as_os_str().to_string_lossy(),
+            err
+        )
+    })?;
+    if !utils::is_wasm_binary(&wasm_binary) {
+        wasm_binary = wabt::wat2wasm(wasm_binary)
+            .map_err(|e| format!("Can't convert from wast to wasm: {:?}", e))?;
+    }
+
+    let module = wasmer_runtime::compile_with_backend(&wasm_binary[..], backend)
+        .map_err(|e| format!("Can't compile module: {:?}", e))?;
+    let info = module.info();
+
+    let mut total_size = 0;
+    println!("{:>10} {:>12}  function", "size", "compile time");
+    for (func_index, _) in info.func_assoc.iter() {
+        let local_func_index = match func_index.local_or_import(info) {
+            LocalOrImport::Local(local_func_index) => local_func_index,
+            LocalOrImport::Import(_) => continue,
+        };
+        let name = wasmer_runtime_core::perf::function_name(info, func_index);
+        let func_code = match module.func_code(local_func_index) {
+            Some(func_code) => func_code,
+            None => {
+                return Err(format!(
+                    "The {} backend doesn't report code",
+                    backend.name()
+                ))
+            }
+        };
+        total_size += func_code.code.len();
+        let compile_time = match func_code.compile_time {
+            Some(time) => format!("{:?}", time),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:>10} {:>12}  {}",
+            func_code.code.len(),
+            compile_time,
+            name
+        );
+
+        if options.disassemble {
+            if let Some(text) = module.disassemble(local_func_index) {
+                println!("{}", text);
+            }
+        }
+    }
+    println!("{:>10} {:>12}  total", total_size, "");
+
+    Ok(())
+}
+
+fn inspect(options: Inspect) {
+    if let Err(message) = inspect_wasm(&options) {
+        eprintln!("{:?}", message);
+        exit(1);
+    }
+}
+
 fn run(options: Run) {
     match execute_wasm(&options) {
         Ok(()) => {}
@@ -187,6 +390,7 @@ fn main() {
     let options = CLIOptions::from_args();
     match options {
         CLIOptions::Run(options) => run(options),
+        CLIOptions::Inspect(options) => inspect(options),
         #[cfg(not(target_os = "windows"))]
         CLIOptions::SelfUpdate => update::self_update(),
         #[cfg(target_os = "windows")]
@@ -213,3 +417,23 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_var() {
+        assert_eq!(
+            parse_env_var("KEY=a=b"),
+            Ok(("KEY".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_env_var("KEY="),
+            Ok(("KEY".to_string(), "".to_string()))
+        );
+        assert!(parse_env_var("KEY").is_err());
+        assert!(parse_env_var("=value").is_err());
+        assert!(parse_env_var("KEY=a\0b").is_err());
+    }
+}
@@ -1,12 +1,8 @@
 use std::panic;
-use wasmer_runtime::{
-    self as runtime,
-    error::{CallResult, Result},
-    ImportObject, Instance, Module,
-};
-use wasmer_runtime_core::types::Value;
+use wasmer_runtime::{self as runtime, error::Result, ImportObject, Instance, Module};
+use wasmer_runtime_core::types::{Type, Value};
 
-use wasmer_emscripten::{is_emscripten_module, run_emscripten_instance};
+use wasmer_emscripten::{is_emscripten_module, run_emscripten_instance_with, RunOptions};
 
 pub struct ResultObject {
     /// A webassembly::Module object representing the compiled WebAssembly module.
@@ -78,21 +74,104 @@ pub fn compile(buffer_source: &[u8]) -> Result<Module> {
 
 /// Performs common instance operations needed when an instance is first run
 /// including data setup, handling arguments and calling a main function
+///
+/// An emscripten module gets `args` as its `argv`, after `path`. Any other
+/// module has its `main` function called with `args` as its parameters,
+/// which must be numbers of the types that it takes. There's no WASI
+/// support yet, so nothing can read `args` through `args_get`.
+///
+/// An emscripten module sees `env`, and only it, as its environment
+/// variables. Others have none.
 pub fn run_instance(
     module: &Module,
     instance: &mut Instance,
     path: &str,
     args: Vec<&str>,
-) -> CallResult<()> {
+    env: Vec<(String, String)>,
+) -> std::result::Result<(), String> {
     if is_emscripten_module(module) {
-        run_emscripten_instance(module, instance, path, args)?;
+        let options = RunOptions {
+            env: Some(env),
+            ..RunOptions::default()
+        };
+        run_emscripten_instance_with(module, instance, path, args, options)
+            .map_err(|e| format!("{:?}", e))?;
     } else {
-        let args: Vec<Value> = args
-            .into_iter()
-            .map(|x| Value::I32(x.parse().unwrap()))
-            .collect();
-        instance.call("main", &args)?;
+        let params = instance
+            .dyn_func("main")
+            .map_err(|e| format!("{:?}", e))?
+            .signature()
+            .params()
+            .to_vec();
+        let args = parse_main_args(&params, args)?;
+        instance
+            .call("main", &args)
+            .map_err(|e| format!("{:?}", e))?;
     };
 
     Ok(())
 }
+
+/// Parses command-line arguments as the parameters of a main function
+/// that takes `params`.
+fn parse_main_args(params: &[Type], args: Vec<&str>) -> std::result::Result<Vec<Value>, String> {
+    if params.len() != args.len() {
+        return Err(format!(
+            "main takes {} arguments, but {} were given",
+            params.len(),
+            args.len()
+        ));
+    }
+    params
+        .iter()
+        .zip(args)
+        .map(|(&ty, arg)| parse_arg(ty, arg))
+        .collect()
+}
+
+/// Parses a command-line argument as a parameter of type `ty`.
+fn parse_arg(ty: Type, arg: &str) -> std::result::Result<Value, String> {
+    let value = match ty {
+        Type::I32 => arg.parse().ok().map(Value::I32),
+        Type::I64 => arg.parse().ok().map(Value::I64),
+        Type::F32 => arg.parse().ok().map(Value::F32),
+        Type::F64 => arg.parse().ok().map(Value::F64),
+        Type::ExternRef | Type::FuncRef => None,
+    };
+    value.ok_or_else(|| format!("{:?} isn't a valid {:?} argument for main", arg, ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_arg() {
+        assert_eq!(parse_arg(Type::I32, "-7"), Ok(Value::I32(-7)));
+        assert_eq!(
+            parse_arg(Type::I64, "8589934592"),
+            Ok(Value::I64(8589934592))
+        );
+        assert_eq!(parse_arg(Type::F32, "1.5"), Ok(Value::F32(1.5)));
+        assert_eq!(parse_arg(Type::F64, "-0.25"), Ok(Value::F64(-0.25)));
+        assert!(parse_arg(Type::I32, "8589934592").is_err());
+        assert!(parse_arg(Type::I32, "one").is_err());
+        assert!(parse_arg(Type::ExternRef, "0").is_err());
+    }
+
+    #[test]
+    fn test_parse_main_args() {
+        assert_eq!(
+            parse_main_args(&[Type::I32, Type::F64], vec!["1", "2"]),
+            Ok(vec![Value::I32(1), Value::F64(2.0)])
+        );
+        assert_eq!(
+            parse_main_args(&[Type::I32], vec!["1", "2"]),
+            Err("main takes 1 arguments, but 2 were given".to_string())
+        );
+        assert_eq!(
+            parse_main_args(&[Type::I32, Type::I32], vec![]),
+            Err("main takes 2 arguments, but 0 were given".to_string())
+        );
+    }
+}